@@ -0,0 +1,13 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS kick_log (
+    id BIGSERIAL PRIMARY KEY,
+    chat_id BIGINT NOT NULL,
+    user_id BIGINT NOT NULL,
+    username TEXT,
+    name TEXT NOT NULL,
+    reason TEXT NOT NULL,
+    initiator TEXT NOT NULL,
+    kicked_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS kick_log_chat_id_idx ON kick_log (chat_id, kicked_at);
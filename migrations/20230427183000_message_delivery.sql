@@ -0,0 +1,10 @@
+-- Add migration script here
+alter table message_queue add column completed_at timestamptz;
+
+CREATE TABLE IF NOT EXISTS message_delivery (
+    message_id INT NOT NULL REFERENCES message_queue(id) ON DELETE CASCADE,
+    chat_id BIGINT NOT NULL,
+    error TEXT,
+    delivered_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY(message_id, chat_id)
+);
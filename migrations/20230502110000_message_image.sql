@@ -0,0 +1,7 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS message_image (
+    message_id INT NOT NULL REFERENCES message_queue(id) ON DELETE CASCADE,
+    position INT NOT NULL,
+    data BYTEA NOT NULL,
+    PRIMARY KEY(message_id, position)
+);
@@ -0,0 +1,4 @@
+-- Add migration script here
+alter table message_queue add column pin boolean not null default false;
+alter table message_queue add column pin_silently boolean not null default false;
+alter table message_delivery add column pin_error text;
@@ -0,0 +1,2 @@
+-- Add migration script here
+alter table tg_chat add column muted boolean not null default false;
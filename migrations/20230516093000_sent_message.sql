@@ -0,0 +1,13 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS sent_message (
+    id SERIAL PRIMARY KEY,
+    chat_id BIGINT NOT NULL,
+    queue_id INT REFERENCES message_queue(id) ON DELETE SET NULL,
+    telegram_message_id INT,
+    text TEXT NOT NULL,
+    media TEXT[] NOT NULL DEFAULT '{}',
+    error TEXT,
+    sent_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS sent_message_chat_id_idx ON sent_message (chat_id, sent_at DESC);
@@ -0,0 +1,4 @@
+-- Add migration script here
+alter table sent_message add column media_message_ids INT[] NOT NULL DEFAULT '{}';
+alter table sent_message add column edited_at timestamptz;
+alter table sent_message add column deleted_at timestamptz;
@@ -0,0 +1,19 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS message_poll (
+    message_id INT PRIMARY KEY REFERENCES message_queue(id) ON DELETE CASCADE,
+    options TEXT[] NOT NULL,
+    is_anonymous BOOLEAN NOT NULL DEFAULT true,
+    allows_multiple_answers BOOLEAN NOT NULL DEFAULT false
+);
+
+CREATE TABLE IF NOT EXISTS sent_poll (
+    poll_id TEXT PRIMARY KEY,
+    message_id INT NOT NULL REFERENCES message_queue(id) ON DELETE CASCADE,
+    chat_id BIGINT NOT NULL,
+    voter_counts INT[] NOT NULL,
+    total_voter_count INT NOT NULL DEFAULT 0,
+    is_closed BOOLEAN NOT NULL DEFAULT false,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS sent_poll_message_id_idx ON sent_poll (message_id);
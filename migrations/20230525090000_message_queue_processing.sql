@@ -0,0 +1,2 @@
+-- Add migration script here
+alter table message_queue add column processing_at timestamptz;
@@ -0,0 +1,11 @@
+-- Add migration script here
+CREATE OR REPLACE FUNCTION notify_message_queue() RETURNS trigger AS $$
+BEGIN
+    PERFORM pg_notify('message_queue', NEW.id::text);
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER message_queue_notify
+AFTER INSERT OR UPDATE OF datetime ON message_queue
+FOR EACH ROW EXECUTE FUNCTION notify_message_queue();
@@ -0,0 +1,3 @@
+-- Add migration script here
+alter table message_queue add column copy_from_chat_id BIGINT;
+alter table message_queue add column copy_message_id INT;
@@ -0,0 +1,9 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS cleanup_schedule (
+    chat_id BIGINT PRIMARY KEY REFERENCES tg_chat(id) ON DELETE CASCADE ON UPDATE CASCADE,
+    cron TEXT NOT NULL,
+    next_run_at TIMESTAMPTZ NOT NULL,
+    last_run_at TIMESTAMPTZ,
+    last_error TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
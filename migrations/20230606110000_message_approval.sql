@@ -0,0 +1,8 @@
+-- Add migration script here
+alter table message_queue add column awaiting_approval boolean not null default false;
+
+DROP TRIGGER IF EXISTS message_queue_notify ON message_queue;
+
+CREATE TRIGGER message_queue_notify
+AFTER INSERT OR UPDATE OF datetime, awaiting_approval ON message_queue
+FOR EACH ROW EXECUTE FUNCTION notify_message_queue();
@@ -0,0 +1,2 @@
+-- Add migration script here
+ALTER TABLE message_queue ADD COLUMN IF NOT EXISTS all_chats BOOLEAN NOT NULL DEFAULT false;
@@ -0,0 +1,6 @@
+-- Add migration script here
+ALTER TABLE tg_chat ADD COLUMN IF NOT EXISTS kind TEXT;
+ALTER TABLE tg_chat ADD COLUMN IF NOT EXISTS username TEXT;
+ALTER TABLE tg_chat ADD COLUMN IF NOT EXISTS member_count INT;
+ALTER TABLE tg_chat ADD COLUMN IF NOT EXISTS invite_link TEXT;
+ALTER TABLE tg_chat ADD COLUMN IF NOT EXISTS metadata_updated_at TIMESTAMPTZ;
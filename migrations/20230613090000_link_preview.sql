@@ -0,0 +1,2 @@
+-- Add migration script here
+ALTER TABLE message_queue ADD COLUMN IF NOT EXISTS disable_web_page_preview BOOLEAN NOT NULL DEFAULT false;
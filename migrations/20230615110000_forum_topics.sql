@@ -0,0 +1,15 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS message_thread (
+    message_id INT NOT NULL REFERENCES message_queue (id) ON DELETE CASCADE,
+    chat_id BIGINT NOT NULL,
+    thread_id INT NOT NULL,
+    PRIMARY KEY (message_id, chat_id)
+);
+
+CREATE TABLE IF NOT EXISTS chat_topic (
+    chat_id BIGINT NOT NULL REFERENCES tg_chat (id) ON DELETE CASCADE ON UPDATE CASCADE,
+    thread_id INT NOT NULL,
+    name TEXT,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (chat_id, thread_id)
+);
@@ -0,0 +1,12 @@
+-- Add migration script here
+-- users who started the bot in a private chat and receive broadcasts as DMs
+CREATE TABLE IF NOT EXISTS tg_subscriber (
+    bot_id TEXT NOT NULL,
+    user_id BIGINT NOT NULL,
+    username TEXT,
+    name TEXT NOT NULL,
+    subscribed_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (bot_id, user_id)
+);
+
+ALTER TABLE message_queue ADD COLUMN audience TEXT NOT NULL DEFAULT 'chats';
@@ -0,0 +1,8 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS chat_welcome (
+    chat_id BIGINT PRIMARY KEY REFERENCES tg_chat (id) ON DELETE CASCADE ON UPDATE CASCADE,
+    template TEXT NOT NULL,
+    -- the greeting is deleted again after this many minutes when set
+    delete_after_minutes INTEGER,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
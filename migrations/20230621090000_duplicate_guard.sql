@@ -0,0 +1,5 @@
+-- Add migration script here
+ALTER TABLE message_queue ADD COLUMN text_hash TEXT;
+ALTER TABLE message_queue ADD COLUMN created_at TIMESTAMPTZ NOT NULL DEFAULT now();
+
+CREATE INDEX message_queue_text_hash_idx ON message_queue (bot_id, text_hash);
@@ -0,0 +1,10 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS chat_name_history (
+    id SERIAL PRIMARY KEY,
+    chat_id BIGINT NOT NULL REFERENCES tg_chat (id) ON DELETE CASCADE ON UPDATE CASCADE,
+    old_name TEXT NOT NULL,
+    new_name TEXT NOT NULL,
+    renamed_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS chat_name_history_chat_id_idx ON chat_name_history (chat_id, renamed_at);
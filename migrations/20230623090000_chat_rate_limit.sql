@@ -0,0 +1,3 @@
+-- Add migration script here
+-- messages per minute sent to the chat, below the global throttle
+ALTER TABLE tg_chat ADD COLUMN IF NOT EXISTS rate_limit_override INTEGER;
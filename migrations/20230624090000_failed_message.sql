@@ -0,0 +1,10 @@
+-- Add migration script here
+ALTER TABLE message_queue ADD COLUMN IF NOT EXISTS attempts INTEGER NOT NULL DEFAULT 0;
+
+-- messages that failed too often, the queue skips them until they are retried
+CREATE TABLE IF NOT EXISTS failed_message (
+    queue_id INTEGER PRIMARY KEY REFERENCES message_queue (id) ON DELETE CASCADE,
+    error TEXT NOT NULL,
+    attempts INTEGER NOT NULL,
+    failed_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
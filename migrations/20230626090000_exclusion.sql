@@ -0,0 +1,12 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS exclusion (
+    id SERIAL PRIMARY KEY,
+    bot_id TEXT NOT NULL,
+    -- NULL protects the user in every chat of the bot
+    chat_id BIGINT REFERENCES tg_chat (id) ON DELETE CASCADE ON UPDATE CASCADE,
+    user_id BIGINT NOT NULL,
+    note TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS exclusion_user_idx ON exclusion (bot_id, COALESCE(chat_id, 0), user_id);
@@ -0,0 +1,3 @@
+-- Add migration script here
+-- chats the bot was removed from keep their members until restored
+ALTER TABLE tg_chat ADD COLUMN IF NOT EXISTS archived BOOLEAN NOT NULL DEFAULT false;
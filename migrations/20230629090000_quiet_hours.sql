@@ -0,0 +1,12 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS chat_quiet_hours (
+    chat_id BIGINT PRIMARY KEY REFERENCES tg_chat (id) ON DELETE CASCADE ON UPDATE CASCADE,
+    -- HH:MM in the chat's local time, the window wraps past midnight when end < start
+    start_time TEXT NOT NULL,
+    end_time TEXT NOT NULL,
+    utc_offset_minutes INTEGER NOT NULL DEFAULT 0,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+-- set while the chat is held back by its quiet hours, the row is then no delivery yet
+ALTER TABLE message_delivery ADD COLUMN IF NOT EXISTS deferred_until TIMESTAMPTZ;
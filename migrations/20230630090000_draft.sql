@@ -0,0 +1,11 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS draft (
+    id SERIAL PRIMARY KEY,
+    bot_id TEXT NOT NULL,
+    -- JSON of the send payload, incomplete until the draft is sent
+    content TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS draft_bot_id_idx ON draft (bot_id);
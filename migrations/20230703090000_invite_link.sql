@@ -0,0 +1,15 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS chat_invite_link (
+    id SERIAL PRIMARY KEY,
+    chat_id BIGINT NOT NULL REFERENCES tg_chat (id) ON DELETE CASCADE ON UPDATE CASCADE,
+    invite_link TEXT NOT NULL,
+    name TEXT,
+    expire_date TIMESTAMPTZ,
+    member_limit INTEGER,
+    creates_join_request BOOLEAN NOT NULL DEFAULT false,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    -- kept after revoking so joins through the link can still be told apart
+    revoked_at TIMESTAMPTZ
+);
+
+CREATE INDEX chat_invite_link_chat_id_idx ON chat_invite_link (chat_id);
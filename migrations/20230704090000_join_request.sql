@@ -0,0 +1,24 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS chat_join_policy (
+    chat_id BIGINT PRIMARY KEY REFERENCES tg_chat (id) ON DELETE CASCADE ON UPDATE CASCADE,
+    -- manual, approve or decline
+    policy TEXT NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE TABLE IF NOT EXISTS join_request (
+    id SERIAL PRIMARY KEY,
+    chat_id BIGINT NOT NULL REFERENCES tg_chat (id) ON DELETE CASCADE ON UPDATE CASCADE,
+    user_id BIGINT NOT NULL,
+    username TEXT,
+    name TEXT NOT NULL,
+    bio TEXT,
+    invite_link TEXT,
+    -- pending, approved or declined
+    status TEXT NOT NULL DEFAULT 'pending',
+    requested_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    decided_at TIMESTAMPTZ,
+    decided_by TEXT
+);
+
+CREATE INDEX join_request_chat_id_idx ON join_request (chat_id, status);
@@ -0,0 +1,3 @@
+-- Add migration script here
+-- null for members that haven't written since activity was tracked
+ALTER TABLE tg_user ADD COLUMN last_message_at TIMESTAMPTZ;
@@ -0,0 +1,20 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS chat_moderation (
+    chat_id BIGINT PRIMARY KEY REFERENCES tg_chat (id) ON DELETE CASCADE ON UPDATE CASCADE,
+    banned_words TEXT[] NOT NULL DEFAULT '{}',
+    banned_patterns TEXT[] NOT NULL DEFAULT '{}',
+    links TEXT NOT NULL DEFAULT 'allow',
+    restrict_after INTEGER,
+    restrict_minutes INTEGER NOT NULL DEFAULT 60,
+    kick_after INTEGER,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE TABLE IF NOT EXISTS chat_warning (
+    chat_id BIGINT NOT NULL REFERENCES tg_chat (id) ON DELETE CASCADE ON UPDATE CASCADE,
+    user_id BIGINT NOT NULL,
+    warnings INTEGER NOT NULL,
+    last_reason TEXT NOT NULL,
+    last_warned_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (chat_id, user_id)
+);
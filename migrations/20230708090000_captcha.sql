@@ -0,0 +1,18 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS chat_captcha (
+    chat_id BIGINT PRIMARY KEY REFERENCES tg_chat (id) ON DELETE CASCADE ON UPDATE CASCADE,
+    timeout_minutes INTEGER NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE TABLE IF NOT EXISTS captcha_challenge (
+    chat_id BIGINT NOT NULL REFERENCES tg_chat (id) ON DELETE CASCADE ON UPDATE CASCADE,
+    user_id BIGINT NOT NULL,
+    username TEXT,
+    name TEXT NOT NULL,
+    message_id INTEGER NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (chat_id, user_id)
+);
+
+CREATE INDEX captcha_challenge_expires_at_idx ON captcha_challenge (expires_at);
@@ -0,0 +1,18 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS message_variant (
+    message_id INT NOT NULL REFERENCES message_queue(id) ON DELETE CASCADE,
+    variant INT NOT NULL,
+    message TEXT NOT NULL,
+    images TEXT[] NOT NULL DEFAULT '{}',
+    weight INT NOT NULL DEFAULT 1,
+    PRIMARY KEY (message_id, variant)
+);
+
+-- the variant each chat received, kept to compare how the variants did
+CREATE TABLE IF NOT EXISTS variant_assignment (
+    message_id INT NOT NULL REFERENCES message_queue(id) ON DELETE CASCADE,
+    chat_id BIGINT NOT NULL,
+    variant INT NOT NULL,
+    assigned_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (message_id, chat_id)
+);
@@ -0,0 +1,12 @@
+-- Add migration script here
+-- latest reaction counts of the messages broadcasts landed as
+CREATE TABLE IF NOT EXISTS message_reaction (
+    chat_id BIGINT NOT NULL,
+    message_id INT NOT NULL,
+    reaction TEXT NOT NULL,
+    count INT NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (chat_id, message_id, reaction)
+);
+
+CREATE INDEX IF NOT EXISTS sent_message_telegram_message_id_idx ON sent_message (chat_id, telegram_message_id);
@@ -0,0 +1,6 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS message_excluded_chat (
+    message_id INT NOT NULL REFERENCES message_queue(id) ON DELETE CASCADE,
+    chat_id BIGINT NOT NULL,
+    PRIMARY KEY (message_id, chat_id)
+);
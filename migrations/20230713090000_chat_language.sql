@@ -0,0 +1,10 @@
+-- Add migration script here
+-- language code picking the translation of broadcasts sent to the chat
+ALTER TABLE tg_chat ADD COLUMN IF NOT EXISTS language TEXT;
+
+CREATE TABLE IF NOT EXISTS message_translation (
+    message_id INT NOT NULL REFERENCES message_queue(id) ON DELETE CASCADE,
+    language TEXT NOT NULL,
+    message TEXT NOT NULL,
+    PRIMARY KEY (message_id, language)
+);
@@ -0,0 +1,6 @@
+-- Add migration script here
+-- free-form labels to target broadcasts by and notes for the operators
+ALTER TABLE tg_chat ADD COLUMN IF NOT EXISTS labels TEXT[] NOT NULL DEFAULT '{}';
+ALTER TABLE tg_chat ADD COLUMN IF NOT EXISTS notes TEXT;
+
+CREATE INDEX IF NOT EXISTS tg_chat_labels_idx ON tg_chat USING GIN (labels);
@@ -0,0 +1,3 @@
+-- Add migration script here
+-- whether join and leave messages are deleted from the chat
+ALTER TABLE tg_chat ADD COLUMN IF NOT EXISTS delete_service_messages BOOLEAN NOT NULL DEFAULT TRUE;
@@ -0,0 +1,4 @@
+-- Add migration script here
+-- offset of the chat's local time, and the local time a message goes out at in every chat
+ALTER TABLE tg_chat ADD COLUMN IF NOT EXISTS utc_offset_minutes INT;
+ALTER TABLE message_queue ADD COLUMN IF NOT EXISTS datetime_local TIMESTAMP;
@@ -0,0 +1,11 @@
+-- Add migration script here
+-- members a staged cleanup restricted, kicked once the cleanup is confirmed
+CREATE TABLE IF NOT EXISTS cleanup_candidate (
+    chat_id BIGINT NOT NULL REFERENCES tg_chat (id) ON DELETE CASCADE ON UPDATE CASCADE,
+    user_id BIGINT NOT NULL,
+    username TEXT,
+    name TEXT NOT NULL,
+    initiator TEXT NOT NULL,
+    staged_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (chat_id, user_id)
+);
@@ -0,0 +1,10 @@
+-- Add migration script here
+-- a sticker and a map pin sent with a broadcast
+CREATE TABLE IF NOT EXISTS message_attachment (
+    message_id INT PRIMARY KEY REFERENCES message_queue (id) ON DELETE CASCADE,
+    sticker TEXT,
+    latitude DOUBLE PRECISION,
+    longitude DOUBLE PRECISION,
+    venue_title TEXT,
+    venue_address TEXT
+);
@@ -0,0 +1,7 @@
+-- Add migration script here
+-- images of queued messages, referenced as `media:<hash>` from their images
+CREATE TABLE IF NOT EXISTS media_blob (
+    hash TEXT PRIMARY KEY,
+    data BYTEA NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
@@ -0,0 +1,11 @@
+-- Add migration script here
+-- messages, their target chats and cleanups per API key and day
+CREATE TABLE IF NOT EXISTS api_usage (
+    bot_id TEXT NOT NULL,
+    actor TEXT NOT NULL,
+    day DATE NOT NULL,
+    messages BIGINT NOT NULL DEFAULT 0,
+    target_chats BIGINT NOT NULL DEFAULT 0,
+    cleanups BIGINT NOT NULL DEFAULT 0,
+    PRIMARY KEY (bot_id, actor, day)
+);
@@ -0,0 +1,4 @@
+-- Add migration script here
+-- refreshed with the rest of the chat metadata
+ALTER TABLE tg_chat ADD COLUMN IF NOT EXISTS description TEXT;
+ALTER TABLE tg_chat ADD COLUMN IF NOT EXISTS photo_file_id TEXT;
@@ -0,0 +1,19 @@
+-- Add migration script here
+-- replies to the broadcasts that asked for them, matched by their sent messages
+ALTER TABLE message_queue ADD COLUMN IF NOT EXISTS collect_replies BOOLEAN NOT NULL DEFAULT false;
+
+CREATE TABLE IF NOT EXISTS broadcast_reply (
+    id BIGSERIAL PRIMARY KEY,
+    queue_id INT NOT NULL REFERENCES message_queue(id) ON DELETE CASCADE,
+    chat_id BIGINT NOT NULL,
+    message_id INT NOT NULL,
+    user_id BIGINT,
+    username TEXT,
+    name TEXT NOT NULL,
+    text TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    edited_at TIMESTAMPTZ,
+    UNIQUE (chat_id, message_id)
+);
+
+CREATE INDEX IF NOT EXISTS broadcast_reply_queue_id_idx ON broadcast_reply (queue_id, id);
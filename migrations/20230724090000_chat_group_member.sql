@@ -0,0 +1,10 @@
+-- Add migration script here
+-- chats their admins put into a configured chat group with /setgroup
+CREATE TABLE IF NOT EXISTS chat_group_member (
+    bot_id TEXT NOT NULL,
+    chat_id BIGINT NOT NULL,
+    group_name TEXT NOT NULL,
+    assigned_by BIGINT NOT NULL,
+    assigned_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (bot_id, chat_id)
+);
@@ -0,0 +1,6 @@
+-- Add migration script here
+-- broadcasts deleted again from the chats some time after they were sent
+ALTER TABLE message_queue ADD COLUMN IF NOT EXISTS delete_after_seconds INT;
+ALTER TABLE sent_message ADD COLUMN IF NOT EXISTS delete_at TIMESTAMPTZ;
+
+CREATE INDEX IF NOT EXISTS sent_message_delete_at_idx ON sent_message (delete_at) WHERE delete_at IS NOT NULL;
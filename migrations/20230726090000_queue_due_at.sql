@@ -0,0 +1,11 @@
+-- Add migration script here
+-- lets the queue fetch only the messages due soon, NULL for unparsable datetimes
+CREATE OR REPLACE FUNCTION queue_due_at(datetime TEXT) RETURNS TIMESTAMPTZ AS $$
+BEGIN
+    RETURN datetime::timestamptz;
+EXCEPTION WHEN others THEN
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql IMMUTABLE;
+
+CREATE INDEX IF NOT EXISTS message_queue_due_idx ON message_queue (bot_id, queue_due_at(datetime)) WHERE completed_at IS NULL;
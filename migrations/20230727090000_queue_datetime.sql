@@ -0,0 +1,22 @@
+-- Add migration script here
+-- datetime becomes a timestamp, messages due at a time that can't be parsed are given up on
+INSERT INTO failed_message ( queue_id, error, attempts )
+SELECT id, 'bad datetime: ' || datetime, attempts FROM message_queue
+WHERE completed_at IS NULL AND queue_due_at(datetime) IS NULL
+ON CONFLICT (queue_id) DO NOTHING;
+
+UPDATE message_queue SET datetime = created_at::TEXT WHERE queue_due_at(datetime) IS NULL;
+
+-- the trigger and the index depend on the column
+DROP TRIGGER IF EXISTS message_queue_notify ON message_queue;
+DROP INDEX IF EXISTS message_queue_due_idx;
+
+ALTER TABLE message_queue ALTER COLUMN datetime TYPE TIMESTAMPTZ USING datetime::TIMESTAMPTZ;
+
+DROP FUNCTION IF EXISTS queue_due_at(TEXT);
+
+CREATE INDEX IF NOT EXISTS message_queue_due_idx ON message_queue (bot_id, datetime) WHERE completed_at IS NULL;
+
+CREATE TRIGGER message_queue_notify
+AFTER INSERT OR UPDATE OF datetime, awaiting_approval ON message_queue
+FOR EACH ROW EXECUTE FUNCTION notify_message_queue();
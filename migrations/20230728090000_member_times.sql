@@ -0,0 +1,4 @@
+-- Add migration script here
+-- null for members tracked before these were kept
+ALTER TABLE tg_user ADD COLUMN IF NOT EXISTS joined_at TIMESTAMPTZ;
+ALTER TABLE tg_user ADD COLUMN IF NOT EXISTS updated_at TIMESTAMPTZ;
@@ -0,0 +1,8 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS chat_branding (
+    chat_id BIGINT PRIMARY KEY REFERENCES tg_chat (id) ON DELETE CASCADE ON UPDATE CASCADE,
+    -- plain text, escaped for the parse mode of each broadcast
+    header TEXT,
+    footer TEXT,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
@@ -0,0 +1,13 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS delivered_file (
+    message_id INT NOT NULL REFERENCES message_queue(id) ON DELETE CASCADE,
+    chat_id BIGINT NOT NULL,
+    telegram_message_id INT NOT NULL,
+    kind TEXT NOT NULL,
+    file_id TEXT NOT NULL,
+    file_unique_id TEXT NOT NULL,
+    file_size BIGINT,
+    width INT NOT NULL,
+    height INT NOT NULL,
+    PRIMARY KEY(message_id, chat_id, telegram_message_id)
+);
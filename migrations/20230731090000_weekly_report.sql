@@ -0,0 +1,7 @@
+-- Add migration script here
+-- chats known before are neither counted as added nor as lost
+ALTER TABLE tg_chat ADD COLUMN IF NOT EXISTS created_at TIMESTAMPTZ;
+ALTER TABLE tg_chat ALTER COLUMN created_at SET DEFAULT now();
+ALTER TABLE tg_chat ADD COLUMN IF NOT EXISTS archived_at TIMESTAMPTZ;
+
+ALTER TABLE bot_settings ADD COLUMN IF NOT EXISTS weekly_report_at TIMESTAMPTZ;
@@ -0,0 +1,9 @@
+-- Add migration script here
+-- cleanups running right now, a row left behind at startup belongs to a
+-- cleanup the process died in
+CREATE TABLE IF NOT EXISTS cleanup_run (
+    chat_id BIGINT PRIMARY KEY REFERENCES tg_chat (id) ON DELETE CASCADE ON UPDATE CASCADE,
+    staged BOOLEAN NOT NULL,
+    initiator TEXT NOT NULL,
+    started_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
@@ -0,0 +1,5 @@
+-- Add migration script here
+-- channels only tell whether they sign their posts by posting, null until then
+ALTER TABLE tg_chat ADD COLUMN IF NOT EXISTS signs_messages BOOLEAN;
+ALTER TABLE message_queue ADD COLUMN IF NOT EXISTS channel_silent BOOLEAN;
+ALTER TABLE message_queue ADD COLUMN IF NOT EXISTS channel_protect_content BOOLEAN;
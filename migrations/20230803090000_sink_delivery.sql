@@ -0,0 +1,9 @@
+-- Add migration script here
+-- sinks are named after their chat group and place in it, like `ops/0`
+CREATE TABLE IF NOT EXISTS sink_delivery (
+    message_id INT NOT NULL REFERENCES message_queue(id) ON DELETE CASCADE,
+    sink TEXT NOT NULL,
+    error TEXT,
+    delivered_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY(message_id, sink)
+);
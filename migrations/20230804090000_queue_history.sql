@@ -0,0 +1,11 @@
+-- Add migration script here
+-- versions of queued messages replaced by edits, `edited_by` made the edit
+CREATE TABLE IF NOT EXISTS message_queue_history (
+    id SERIAL PRIMARY KEY,
+    message_id INT NOT NULL REFERENCES message_queue(id) ON DELETE CASCADE,
+    message TEXT NOT NULL,
+    datetime TIMESTAMPTZ NOT NULL,
+    edited_by TEXT NOT NULL,
+    edited_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+CREATE INDEX IF NOT EXISTS message_queue_history_message_id_idx ON message_queue_history (message_id);
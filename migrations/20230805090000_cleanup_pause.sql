@@ -0,0 +1,5 @@
+-- Add migration script here
+-- members are kicked in the order of their ids, a cleanup going on skips
+-- everyone up to `last_user_id`; `resume_at` is set while it paused itself
+ALTER TABLE cleanup_run ADD COLUMN IF NOT EXISTS last_user_id BIGINT;
+ALTER TABLE cleanup_run ADD COLUMN IF NOT EXISTS resume_at TIMESTAMPTZ;
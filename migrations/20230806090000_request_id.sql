@@ -0,0 +1,9 @@
+-- Add migration script here
+-- the id of the API request behind a broadcast or a kick, the same one its
+-- log lines carry
+ALTER TABLE message_queue ADD COLUMN IF NOT EXISTS request_id TEXT;
+ALTER TABLE message_delivery ADD COLUMN IF NOT EXISTS request_id TEXT;
+ALTER TABLE kick_log ADD COLUMN IF NOT EXISTS request_id TEXT;
+ALTER TABLE audit_log ADD COLUMN IF NOT EXISTS request_id TEXT;
+
+CREATE INDEX IF NOT EXISTS audit_log_request_id_idx ON audit_log (request_id);
@@ -0,0 +1,12 @@
+-- Add migration script here
+-- cleanup statuses shared between instances with STATUS_STORE=postgres;
+-- `seq` orders the writes of one instance, which are made in the background
+CREATE TABLE IF NOT EXISTS chat_status (
+    bot_id TEXT NOT NULL,
+    chat_id BIGINT NOT NULL,
+    status JSONB NOT NULL,
+    instance TEXT NOT NULL,
+    seq BIGINT NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY(bot_id, chat_id)
+);
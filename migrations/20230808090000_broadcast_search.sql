@@ -0,0 +1,7 @@
+-- Add migration script here
+-- the words of every broadcast, for searching the sent ones. 'simple' doesn't
+-- stem, so it doesn't matter which language a broadcast is written in
+ALTER TABLE message_queue ADD COLUMN IF NOT EXISTS message_tsv tsvector
+    GENERATED ALWAYS AS ( to_tsvector('simple', message) ) STORED;
+
+CREATE INDEX IF NOT EXISTS message_queue_message_tsv_idx ON message_queue USING GIN (message_tsv);
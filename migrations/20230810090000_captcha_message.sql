@@ -0,0 +1,3 @@
+-- Add migration script here
+-- the challenge is stored before the button is sent, so a restricted member always has one
+ALTER TABLE captcha_challenge ALTER COLUMN message_id DROP NOT NULL;
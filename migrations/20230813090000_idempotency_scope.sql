@@ -0,0 +1,6 @@
+-- Add migration script here
+ALTER TABLE idempotency_key ADD COLUMN IF NOT EXISTS bot_id TEXT NOT NULL DEFAULT '';
+ALTER TABLE idempotency_key ADD COLUMN IF NOT EXISTS actor TEXT NOT NULL DEFAULT '';
+
+ALTER TABLE idempotency_key DROP CONSTRAINT IF EXISTS idempotency_key_pkey;
+ALTER TABLE idempotency_key ADD PRIMARY KEY (bot_id, actor, key, endpoint);
@@ -0,0 +1,10 @@
+-- Add migration script here
+-- greetings waiting to be deleted again, kept so a restart doesn't forget them
+CREATE TABLE IF NOT EXISTS welcome_deletion (
+    chat_id BIGINT NOT NULL REFERENCES tg_chat (id) ON DELETE CASCADE ON UPDATE CASCADE,
+    message_id INTEGER NOT NULL,
+    delete_at TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (chat_id, message_id)
+);
+
+CREATE INDEX IF NOT EXISTS welcome_deletion_delete_at_idx ON welcome_deletion (delete_at);
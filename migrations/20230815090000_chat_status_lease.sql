@@ -0,0 +1,5 @@
+-- Add migration script here
+-- a NULL status marks a chat the instance dropped, so the others drop it too;
+-- instances refresh `updated_at` of the cleanups they run, older running
+-- statuses belong to an instance that stopped
+ALTER TABLE chat_status ALTER COLUMN status DROP NOT NULL;
@@ -0,0 +1,78 @@
+-- Add migration script here
+-- Schema for SQLite deployments, covering what the storage layer keeps:
+-- chats, their members, the kick log and the message queue.
+CREATE TABLE tg_chat (
+    id INTEGER PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL,
+    muted INTEGER NOT NULL DEFAULT 0,
+    approved INTEGER NOT NULL DEFAULT 1,
+    kind TEXT,
+    username TEXT,
+    member_count INTEGER,
+    invite_link TEXT,
+    metadata_updated_at TEXT
+);
+
+CREATE TABLE tg_user (
+    id INTEGER NOT NULL,
+    chat_id INTEGER NOT NULL REFERENCES tg_chat (id) ON UPDATE CASCADE ON DELETE CASCADE,
+    username TEXT,
+    name TEXT NOT NULL,
+    PRIMARY KEY (id, chat_id)
+);
+
+CREATE TABLE kick_log (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    chat_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    username TEXT,
+    name TEXT NOT NULL,
+    reason TEXT NOT NULL,
+    initiator TEXT NOT NULL,
+    kicked_at TEXT NOT NULL
+);
+
+CREATE INDEX kick_log_chat_id_idx ON kick_log (chat_id, kicked_at);
+
+-- `chats` and `images` hold JSON arrays
+CREATE TABLE message_queue (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    chats TEXT NOT NULL,
+    all_chats INTEGER NOT NULL DEFAULT 0,
+    message TEXT NOT NULL,
+    images TEXT NOT NULL,
+    datetime TEXT NOT NULL,
+    parse_mode TEXT NOT NULL DEFAULT 'markdownv2',
+    pin INTEGER NOT NULL DEFAULT 0,
+    pin_silently INTEGER NOT NULL DEFAULT 0,
+    silent INTEGER NOT NULL DEFAULT 0,
+    disable_web_page_preview INTEGER NOT NULL DEFAULT 0,
+    copy_from_chat_id INTEGER,
+    copy_message_id INTEGER,
+    awaiting_approval INTEGER NOT NULL DEFAULT 0,
+    processing_at TEXT,
+    completed_at TEXT
+);
+
+CREATE TABLE message_image (
+    message_id INTEGER NOT NULL REFERENCES message_queue (id) ON DELETE CASCADE,
+    position INTEGER NOT NULL,
+    data BLOB NOT NULL,
+    PRIMARY KEY (message_id, position)
+);
+
+CREATE TABLE message_thread (
+    message_id INTEGER NOT NULL REFERENCES message_queue (id) ON DELETE CASCADE,
+    chat_id INTEGER NOT NULL,
+    thread_id INTEGER NOT NULL,
+    PRIMARY KEY (message_id, chat_id)
+);
+
+CREATE TABLE message_delivery (
+    message_id INTEGER NOT NULL REFERENCES message_queue (id) ON DELETE CASCADE,
+    chat_id INTEGER NOT NULL,
+    error TEXT,
+    pin_error TEXT,
+    delivered_at TEXT NOT NULL,
+    PRIMARY KEY (message_id, chat_id)
+);
@@ -0,0 +1,8 @@
+-- Add migration script here
+-- rows from before multi-bot support belong to the bot configured through BOT_TOKEN
+ALTER TABLE tg_chat ADD COLUMN bot_id TEXT NOT NULL DEFAULT 'default';
+ALTER TABLE tg_user ADD COLUMN bot_id TEXT NOT NULL DEFAULT 'default';
+ALTER TABLE message_queue ADD COLUMN bot_id TEXT NOT NULL DEFAULT 'default';
+
+CREATE INDEX tg_chat_bot_id_idx ON tg_chat (bot_id);
+CREATE INDEX message_queue_bot_id_idx ON message_queue (bot_id) WHERE completed_at IS NULL;
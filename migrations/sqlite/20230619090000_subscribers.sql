@@ -0,0 +1,11 @@
+-- Add migration script here
+CREATE TABLE tg_subscriber (
+    bot_id TEXT NOT NULL,
+    user_id INTEGER NOT NULL,
+    username TEXT,
+    name TEXT NOT NULL,
+    subscribed_at TEXT NOT NULL,
+    PRIMARY KEY (bot_id, user_id)
+);
+
+ALTER TABLE message_queue ADD COLUMN audience TEXT NOT NULL DEFAULT 'chats';
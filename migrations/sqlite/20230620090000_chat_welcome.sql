@@ -0,0 +1,7 @@
+-- Add migration script here
+CREATE TABLE chat_welcome (
+    chat_id INTEGER PRIMARY KEY NOT NULL REFERENCES tg_chat (id) ON DELETE CASCADE ON UPDATE CASCADE,
+    template TEXT NOT NULL,
+    delete_after_minutes INTEGER,
+    updated_at TEXT NOT NULL
+);
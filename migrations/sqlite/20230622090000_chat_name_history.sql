@@ -0,0 +1,10 @@
+-- Add migration script here
+CREATE TABLE chat_name_history (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    chat_id INTEGER NOT NULL REFERENCES tg_chat (id) ON DELETE CASCADE ON UPDATE CASCADE,
+    old_name TEXT NOT NULL,
+    new_name TEXT NOT NULL,
+    renamed_at TEXT NOT NULL
+);
+
+CREATE INDEX chat_name_history_chat_id_idx ON chat_name_history (chat_id, renamed_at);
@@ -0,0 +1,2 @@
+-- Add migration script here
+ALTER TABLE tg_chat ADD COLUMN rate_limit_override INTEGER;
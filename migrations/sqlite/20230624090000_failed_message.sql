@@ -0,0 +1,9 @@
+-- Add migration script here
+ALTER TABLE message_queue ADD COLUMN attempts INTEGER NOT NULL DEFAULT 0;
+
+CREATE TABLE failed_message (
+    queue_id INTEGER PRIMARY KEY NOT NULL REFERENCES message_queue (id) ON DELETE CASCADE,
+    error TEXT NOT NULL,
+    attempts INTEGER NOT NULL,
+    failed_at TEXT NOT NULL
+);
@@ -0,0 +1,14 @@
+-- Add migration script here
+CREATE TABLE audit_log (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    bot_id TEXT NOT NULL,
+    actor TEXT NOT NULL,
+    method TEXT NOT NULL,
+    endpoint TEXT NOT NULL,
+    payload TEXT,
+    status INTEGER,
+    created_at TEXT NOT NULL
+);
+
+CREATE INDEX audit_log_created_at_idx ON audit_log (bot_id, created_at);
+CREATE INDEX audit_log_actor_idx ON audit_log (actor);
@@ -0,0 +1,11 @@
+-- Add migration script here
+CREATE TABLE exclusion (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    bot_id TEXT NOT NULL,
+    chat_id INTEGER REFERENCES tg_chat (id) ON DELETE CASCADE ON UPDATE CASCADE,
+    user_id INTEGER NOT NULL,
+    note TEXT,
+    created_at TEXT NOT NULL
+);
+
+CREATE UNIQUE INDEX exclusion_user_idx ON exclusion (bot_id, COALESCE(chat_id, 0), user_id);
@@ -0,0 +1,11 @@
+-- Add migration script here
+CREATE TABLE webhook (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    bot_id TEXT NOT NULL,
+    url TEXT NOT NULL,
+    events TEXT NOT NULL DEFAULT '[]',
+    secret TEXT NOT NULL,
+    created_at TEXT NOT NULL
+);
+
+CREATE INDEX webhook_bot_id_idx ON webhook (bot_id);
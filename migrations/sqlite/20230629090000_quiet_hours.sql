@@ -0,0 +1,10 @@
+-- Add migration script here
+CREATE TABLE chat_quiet_hours (
+    chat_id INTEGER PRIMARY KEY NOT NULL REFERENCES tg_chat (id) ON DELETE CASCADE ON UPDATE CASCADE,
+    start_time TEXT NOT NULL,
+    end_time TEXT NOT NULL,
+    utc_offset_minutes INTEGER NOT NULL DEFAULT 0,
+    updated_at TEXT NOT NULL
+);
+
+ALTER TABLE message_delivery ADD COLUMN deferred_until TEXT;
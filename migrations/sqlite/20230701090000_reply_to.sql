@@ -0,0 +1,3 @@
+-- Add migration script here
+ALTER TABLE message_queue
+    ADD COLUMN reply_to_queue_id INTEGER REFERENCES message_queue (id) ON DELETE SET NULL;
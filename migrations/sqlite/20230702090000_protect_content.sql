@@ -0,0 +1,2 @@
+-- Add migration script here
+ALTER TABLE message_queue ADD COLUMN protect_content INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,14 @@
+-- Add migration script here
+CREATE TABLE chat_invite_link (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    chat_id INTEGER NOT NULL REFERENCES tg_chat (id) ON DELETE CASCADE ON UPDATE CASCADE,
+    invite_link TEXT NOT NULL,
+    name TEXT,
+    expire_date TEXT,
+    member_limit INTEGER,
+    creates_join_request INTEGER NOT NULL DEFAULT 0,
+    created_at TEXT NOT NULL,
+    revoked_at TEXT
+);
+
+CREATE INDEX chat_invite_link_chat_id_idx ON chat_invite_link (chat_id);
@@ -0,0 +1,22 @@
+-- Add migration script here
+CREATE TABLE chat_join_policy (
+    chat_id INTEGER PRIMARY KEY NOT NULL REFERENCES tg_chat (id) ON DELETE CASCADE ON UPDATE CASCADE,
+    policy TEXT NOT NULL,
+    updated_at TEXT NOT NULL
+);
+
+CREATE TABLE join_request (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    chat_id INTEGER NOT NULL REFERENCES tg_chat (id) ON DELETE CASCADE ON UPDATE CASCADE,
+    user_id INTEGER NOT NULL,
+    username TEXT,
+    name TEXT NOT NULL,
+    bio TEXT,
+    invite_link TEXT,
+    status TEXT NOT NULL DEFAULT 'pending',
+    requested_at TEXT NOT NULL,
+    decided_at TEXT,
+    decided_by TEXT
+);
+
+CREATE INDEX join_request_chat_id_idx ON join_request (chat_id, status);
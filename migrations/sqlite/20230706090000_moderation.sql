@@ -0,0 +1,21 @@
+-- Add migration script here
+-- `banned_words` and `banned_patterns` hold JSON arrays
+CREATE TABLE chat_moderation (
+    chat_id INTEGER PRIMARY KEY NOT NULL REFERENCES tg_chat (id) ON DELETE CASCADE ON UPDATE CASCADE,
+    banned_words TEXT NOT NULL DEFAULT '[]',
+    banned_patterns TEXT NOT NULL DEFAULT '[]',
+    links TEXT NOT NULL DEFAULT 'allow',
+    restrict_after INTEGER,
+    restrict_minutes INTEGER NOT NULL DEFAULT 60,
+    kick_after INTEGER,
+    updated_at TEXT NOT NULL
+);
+
+CREATE TABLE chat_warning (
+    chat_id INTEGER NOT NULL REFERENCES tg_chat (id) ON DELETE CASCADE ON UPDATE CASCADE,
+    user_id INTEGER NOT NULL,
+    warnings INTEGER NOT NULL,
+    last_reason TEXT NOT NULL,
+    last_warned_at TEXT NOT NULL,
+    PRIMARY KEY (chat_id, user_id)
+);
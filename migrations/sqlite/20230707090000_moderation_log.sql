@@ -0,0 +1,12 @@
+-- Add migration script here
+CREATE TABLE moderation_log (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    chat_id INTEGER NOT NULL REFERENCES tg_chat (id) ON DELETE CASCADE ON UPDATE CASCADE,
+    user_id INTEGER NOT NULL,
+    action TEXT NOT NULL,
+    reason TEXT NOT NULL,
+    moderator TEXT NOT NULL,
+    created_at TEXT NOT NULL
+);
+
+CREATE INDEX moderation_log_chat_id_idx ON moderation_log (chat_id, created_at);
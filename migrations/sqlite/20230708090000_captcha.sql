@@ -0,0 +1,18 @@
+-- Add migration script here
+CREATE TABLE chat_captcha (
+    chat_id INTEGER PRIMARY KEY NOT NULL REFERENCES tg_chat (id) ON DELETE CASCADE ON UPDATE CASCADE,
+    timeout_minutes INTEGER NOT NULL,
+    updated_at TEXT NOT NULL
+);
+
+CREATE TABLE captcha_challenge (
+    chat_id INTEGER NOT NULL REFERENCES tg_chat (id) ON DELETE CASCADE ON UPDATE CASCADE,
+    user_id INTEGER NOT NULL,
+    username TEXT,
+    name TEXT NOT NULL,
+    message_id INTEGER NOT NULL,
+    expires_at TEXT NOT NULL,
+    PRIMARY KEY (chat_id, user_id)
+);
+
+CREATE INDEX captcha_challenge_expires_at_idx ON captcha_challenge (expires_at);
@@ -0,0 +1,18 @@
+-- Add migration script here
+CREATE TABLE message_variant (
+    message_id INTEGER NOT NULL REFERENCES message_queue (id) ON DELETE CASCADE,
+    variant INTEGER NOT NULL,
+    message TEXT NOT NULL,
+    images TEXT NOT NULL DEFAULT '[]',
+    weight INTEGER NOT NULL DEFAULT 1,
+    PRIMARY KEY (message_id, variant)
+);
+
+-- the variant each chat received, kept to compare how the variants did
+CREATE TABLE variant_assignment (
+    message_id INTEGER NOT NULL REFERENCES message_queue (id) ON DELETE CASCADE,
+    chat_id INTEGER NOT NULL,
+    variant INTEGER NOT NULL,
+    assigned_at TEXT NOT NULL,
+    PRIMARY KEY (message_id, chat_id)
+);
@@ -0,0 +1,6 @@
+-- Add migration script here
+CREATE TABLE bot_settings (
+    bot_id TEXT PRIMARY KEY NOT NULL,
+    maintenance BOOLEAN NOT NULL DEFAULT 0,
+    updated_at TEXT NOT NULL
+);
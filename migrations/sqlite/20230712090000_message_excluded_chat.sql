@@ -0,0 +1,6 @@
+-- Add migration script here
+CREATE TABLE message_excluded_chat (
+    message_id INTEGER NOT NULL REFERENCES message_queue (id) ON DELETE CASCADE,
+    chat_id INTEGER NOT NULL,
+    PRIMARY KEY (message_id, chat_id)
+);
@@ -0,0 +1,9 @@
+-- Add migration script here
+ALTER TABLE tg_chat ADD COLUMN language TEXT;
+
+CREATE TABLE message_translation (
+    message_id INTEGER NOT NULL REFERENCES message_queue (id) ON DELETE CASCADE,
+    language TEXT NOT NULL,
+    message TEXT NOT NULL,
+    PRIMARY KEY (message_id, language)
+);
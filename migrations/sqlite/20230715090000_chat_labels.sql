@@ -0,0 +1,4 @@
+-- Add migration script here
+-- labels are a JSON array of strings
+ALTER TABLE tg_chat ADD COLUMN labels TEXT NOT NULL DEFAULT '[]';
+ALTER TABLE tg_chat ADD COLUMN notes TEXT;
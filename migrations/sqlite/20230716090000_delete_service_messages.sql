@@ -0,0 +1,2 @@
+-- Add migration script here
+ALTER TABLE tg_chat ADD COLUMN delete_service_messages INTEGER NOT NULL DEFAULT 1;
@@ -0,0 +1,3 @@
+-- Add migration script here
+ALTER TABLE tg_chat ADD COLUMN utc_offset_minutes INTEGER;
+ALTER TABLE message_queue ADD COLUMN datetime_local TEXT;
@@ -0,0 +1,10 @@
+-- Add migration script here
+CREATE TABLE cleanup_candidate (
+    chat_id INTEGER NOT NULL REFERENCES tg_chat (id) ON DELETE CASCADE ON UPDATE CASCADE,
+    user_id INTEGER NOT NULL,
+    username TEXT,
+    name TEXT NOT NULL,
+    initiator TEXT NOT NULL,
+    staged_at TEXT NOT NULL,
+    PRIMARY KEY (chat_id, user_id)
+);
@@ -0,0 +1,9 @@
+-- Add migration script here
+CREATE TABLE message_attachment (
+    message_id INTEGER PRIMARY KEY REFERENCES message_queue (id) ON DELETE CASCADE,
+    sticker TEXT,
+    latitude REAL,
+    longitude REAL,
+    venue_title TEXT,
+    venue_address TEXT
+);
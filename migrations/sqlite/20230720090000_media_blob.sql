@@ -0,0 +1,5 @@
+-- Add migration script here
+CREATE TABLE media_blob (
+    hash TEXT PRIMARY KEY,
+    data BLOB NOT NULL
+);
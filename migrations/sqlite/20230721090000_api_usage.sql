@@ -0,0 +1,10 @@
+-- Add migration script here
+CREATE TABLE api_usage (
+    bot_id TEXT NOT NULL,
+    actor TEXT NOT NULL,
+    day TEXT NOT NULL,
+    messages INTEGER NOT NULL DEFAULT 0,
+    target_chats INTEGER NOT NULL DEFAULT 0,
+    cleanups INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (bot_id, actor, day)
+);
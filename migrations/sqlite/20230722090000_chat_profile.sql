@@ -0,0 +1,3 @@
+-- Add migration script here
+ALTER TABLE tg_chat ADD COLUMN description TEXT;
+ALTER TABLE tg_chat ADD COLUMN photo_file_id TEXT;
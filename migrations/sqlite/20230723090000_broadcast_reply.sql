@@ -0,0 +1,2 @@
+-- Add migration script here
+ALTER TABLE message_queue ADD COLUMN collect_replies BOOLEAN NOT NULL DEFAULT 0;
@@ -0,0 +1,9 @@
+-- Add migration script here
+CREATE TABLE chat_group_member (
+    bot_id TEXT NOT NULL,
+    chat_id INTEGER NOT NULL,
+    group_name TEXT NOT NULL,
+    assigned_by INTEGER NOT NULL,
+    assigned_at TEXT NOT NULL,
+    PRIMARY KEY (bot_id, chat_id)
+);
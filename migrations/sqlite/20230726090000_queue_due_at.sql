@@ -0,0 +1,2 @@
+-- Add migration script here
+CREATE INDEX message_queue_due_idx ON message_queue (bot_id, julianday(datetime)) WHERE completed_at IS NULL;
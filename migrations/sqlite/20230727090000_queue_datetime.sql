@@ -0,0 +1,12 @@
+-- Add migration script here
+INSERT OR IGNORE INTO failed_message ( queue_id, error, attempts, failed_at )
+SELECT id, 'bad datetime: ' || datetime, attempts, strftime('%Y-%m-%dT%H:%M:%f+00:00', 'now') FROM message_queue
+WHERE completed_at IS NULL AND julianday(datetime) IS NULL;
+
+UPDATE message_queue SET datetime = COALESCE(created_at, datetime('now')) WHERE julianday(datetime) IS NULL;
+
+-- in UTC like every other timestamp, so they compare as text
+UPDATE message_queue SET datetime = strftime('%Y-%m-%dT%H:%M:%f+00:00', datetime);
+
+DROP INDEX message_queue_due_idx;
+CREATE INDEX message_queue_due_idx ON message_queue (bot_id, datetime) WHERE completed_at IS NULL;
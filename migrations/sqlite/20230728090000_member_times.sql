@@ -0,0 +1,4 @@
+-- Add migration script here
+-- null for members tracked before these were kept
+ALTER TABLE tg_user ADD COLUMN joined_at TEXT;
+ALTER TABLE tg_user ADD COLUMN updated_at TEXT;
@@ -0,0 +1,7 @@
+-- Add migration script here
+CREATE TABLE chat_branding (
+    chat_id INTEGER PRIMARY KEY NOT NULL REFERENCES tg_chat (id) ON DELETE CASCADE ON UPDATE CASCADE,
+    header TEXT,
+    footer TEXT,
+    updated_at TEXT NOT NULL
+);
@@ -0,0 +1,13 @@
+-- Add migration script here
+CREATE TABLE delivered_file (
+    message_id INTEGER NOT NULL REFERENCES message_queue (id) ON DELETE CASCADE,
+    chat_id INTEGER NOT NULL,
+    telegram_message_id INTEGER NOT NULL,
+    kind TEXT NOT NULL,
+    file_id TEXT NOT NULL,
+    file_unique_id TEXT NOT NULL,
+    file_size INTEGER,
+    width INTEGER NOT NULL,
+    height INTEGER NOT NULL,
+    PRIMARY KEY (message_id, chat_id, telegram_message_id)
+);
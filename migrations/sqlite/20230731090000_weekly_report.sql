@@ -0,0 +1,6 @@
+-- Add migration script here
+-- chats known before are neither counted as added nor as lost
+ALTER TABLE tg_chat ADD COLUMN created_at TEXT;
+ALTER TABLE tg_chat ADD COLUMN archived_at TEXT;
+
+ALTER TABLE bot_settings ADD COLUMN weekly_report_at TEXT;
@@ -0,0 +1,7 @@
+-- Add migration script here
+CREATE TABLE cleanup_run (
+    chat_id INTEGER PRIMARY KEY NOT NULL REFERENCES tg_chat (id) ON DELETE CASCADE ON UPDATE CASCADE,
+    staged BOOLEAN NOT NULL,
+    initiator TEXT NOT NULL,
+    started_at TEXT NOT NULL
+);
@@ -0,0 +1,4 @@
+-- Add migration script here
+ALTER TABLE tg_chat ADD COLUMN signs_messages BOOLEAN;
+ALTER TABLE message_queue ADD COLUMN channel_silent BOOLEAN;
+ALTER TABLE message_queue ADD COLUMN channel_protect_content BOOLEAN;
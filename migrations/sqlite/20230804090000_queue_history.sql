@@ -0,0 +1,11 @@
+-- Add migration script here
+-- versions of queued messages replaced by edits, `edited_by` made the edit
+CREATE TABLE message_queue_history (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    message_id INTEGER NOT NULL REFERENCES message_queue (id) ON DELETE CASCADE,
+    message TEXT NOT NULL,
+    datetime TEXT NOT NULL,
+    edited_by TEXT NOT NULL,
+    edited_at TEXT NOT NULL
+);
+CREATE INDEX message_queue_history_message_id_idx ON message_queue_history (message_id);
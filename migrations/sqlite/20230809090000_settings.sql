@@ -0,0 +1,3 @@
+-- Add migration script here
+-- a JSON object of the settings changed through the API, on top of the config
+ALTER TABLE bot_settings ADD COLUMN overrides TEXT;
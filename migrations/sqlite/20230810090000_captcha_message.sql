@@ -0,0 +1,19 @@
+-- Add migration script here
+-- the challenge is stored before the button is sent, so a restricted member always has one
+CREATE TABLE captcha_challenge_new (
+    chat_id INTEGER NOT NULL REFERENCES tg_chat (id) ON DELETE CASCADE ON UPDATE CASCADE,
+    user_id INTEGER NOT NULL,
+    username TEXT,
+    name TEXT NOT NULL,
+    message_id INTEGER,
+    expires_at TEXT NOT NULL,
+    PRIMARY KEY (chat_id, user_id)
+);
+
+INSERT INTO captcha_challenge_new ( chat_id, user_id, username, name, message_id, expires_at )
+SELECT chat_id, user_id, username, name, message_id, expires_at FROM captcha_challenge;
+
+DROP TABLE captcha_challenge;
+ALTER TABLE captcha_challenge_new RENAME TO captcha_challenge;
+
+CREATE INDEX captcha_challenge_expires_at_idx ON captcha_challenge (expires_at);
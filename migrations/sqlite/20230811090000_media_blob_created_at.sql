@@ -0,0 +1,3 @@
+-- Add migration script here
+-- blobs stored before are old enough to be cleaned up
+ALTER TABLE media_blob ADD COLUMN created_at TEXT;
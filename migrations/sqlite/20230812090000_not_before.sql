@@ -0,0 +1,3 @@
+-- Add migration script here
+-- when a deferred broadcast is picked up again, its scheduled datetime stays as it was
+ALTER TABLE message_queue ADD COLUMN not_before TEXT;
@@ -0,0 +1,9 @@
+-- Add migration script here
+CREATE TABLE welcome_deletion (
+    chat_id INTEGER NOT NULL REFERENCES tg_chat (id) ON DELETE CASCADE ON UPDATE CASCADE,
+    message_id INTEGER NOT NULL,
+    delete_at TEXT NOT NULL,
+    PRIMARY KEY (chat_id, message_id)
+);
+
+CREATE INDEX welcome_deletion_delete_at_idx ON welcome_deletion (delete_at);
@@ -0,0 +1,208 @@
+use std::collections::HashSet;
+
+use async_trait::async_trait;
+use axum::{
+    extract::{FromRequest, RequestParts},
+    http::{HeaderMap, StatusCode},
+    Extension,
+};
+use serde::Deserialize;
+
+use crate::{
+    api::initiator, config::ApiKeyConfig, error::ApiError, state::AppState, subscriber::Audience,
+    topic::ChatTarget,
+};
+
+/// What an API key may change. Every key can read the chats it is limited
+/// to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Permission {
+    /// Queueing, approving, editing and deleting broadcasts.
+    Send,
+    /// Kicking members: clearing, purging and deleting chats and their
+    /// cleanup schedules.
+    Cleanup,
+    /// Chat settings, welcome messages, imports and throttle limits.
+    Manage,
+}
+
+impl Permission {
+    pub const ALL: [Permission; 3] = [Permission::Send, Permission::Cleanup, Permission::Manage];
+}
+
+/// What the caller of a request may do, extracted by every handler that
+/// reads or changes chats. Without configured API keys the API stays open.
+pub struct Access {
+    /// `None` for callers not limited to some chats.
+    chats: Option<HashSet<i64>>,
+    permissions: Vec<Permission>,
+    /// Who made the request, named like in the audit log.
+    actor: String,
+}
+
+impl Access {
+    fn full(actor: String) -> Self {
+        Self {
+            chats: None,
+            permissions: Permission::ALL.to_vec(),
+            actor,
+        }
+    }
+
+    async fn key(state: &AppState, key: &ApiKeyConfig, actor: String) -> Result<Self, ApiError> {
+        let chats = if key.chats.is_empty() && key.chat_groups.is_empty() {
+            None
+        } else {
+            let groups = if key.chat_groups.is_empty() {
+                Default::default()
+            } else {
+                state.get_chat_groups().await?
+            };
+            let members = key
+                .chat_groups
+                .iter()
+                .filter_map(|group| groups.get(group))
+                .flatten();
+            Some(key.chats.iter().chain(members).copied().collect())
+        };
+
+        Ok(Self {
+            chats,
+            permissions: key.permissions.clone(),
+            actor,
+        })
+    }
+
+    pub fn actor(&self) -> &str {
+        &self.actor
+    }
+
+    pub fn require(&self, permission: Permission) -> Result<(), ApiError> {
+        if !self.permissions.contains(&permission) {
+            return Err(forbidden(format!(
+                "the API key lacks the {permission:?} permission"
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// `None` for callers not limited to some chats.
+    pub fn allowed_chats(&self) -> Option<Vec<i64>> {
+        self.chats
+            .as_ref()
+            .map(|chats| chats.iter().copied().collect())
+    }
+
+    pub fn allows_chat(&self, chat_id: i64) -> bool {
+        self.chats
+            .as_ref()
+            .is_none_or(|chats| chats.contains(&chat_id))
+    }
+
+    pub fn chat(&self, chat_id: i64) -> Result<(), ApiError> {
+        self.chats([chat_id])
+    }
+
+    pub fn chats(&self, chats: impl IntoIterator<Item = i64>) -> Result<(), ApiError> {
+        match chats
+            .into_iter()
+            .find(|&chat_id| !self.allows_chat(chat_id))
+        {
+            Some(chat_id) => Err(forbidden(format!(
+                "the API key is not allowed to access chat {chat_id}"
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// For everything not scoped to some chats, like sending to every chat or
+    /// the subscribers.
+    pub fn all_chats(&self) -> Result<(), ApiError> {
+        if self.chats.is_some() {
+            return Err(forbidden("the API key is limited to some chats"));
+        }
+
+        Ok(())
+    }
+
+    /// Checks the audience of a message about to be queued, `None` targets
+    /// stand for the whole audience.
+    pub fn targets(
+        &self,
+        targets: Option<&[ChatTarget]>,
+        audience: Audience,
+    ) -> Result<(), ApiError> {
+        match (targets, audience) {
+            (Some(targets), Audience::Chats) => {
+                self.chats(targets.iter().map(|target| target.chat_id()))
+            }
+            _ => self.all_chats(),
+        }
+    }
+
+    /// Checks the audience of a queued message, answering 404 for unknown
+    /// ids like the handlers do.
+    pub async fn queued_message(&self, state: &AppState, id: i32) -> Result<(), ApiError> {
+        if self.chats.is_none() {
+            return Ok(());
+        }
+
+        let message = state
+            .get_queued_message(id)
+            .await?
+            .ok_or_else(|| ApiError::not_found(format!("queued message {id} not found")))?;
+        if message.all_chats || message.audience != Audience::Chats.to_string() {
+            return self.all_chats();
+        }
+
+        self.chats(message.chats)
+    }
+}
+
+fn forbidden(message: impl ToString) -> ApiError {
+    ApiError::new(StatusCode::FORBIDDEN, "forbidden", message)
+}
+
+impl AppState {
+    /// The API key of the request, named by its `X-Api-Key` header.
+    pub fn request_api_key(&self, headers: &HeaderMap) -> Option<&ApiKeyConfig> {
+        let key = headers.get("x-api-key")?.to_str().ok()?;
+        self.config
+            .api_keys
+            .iter()
+            .find(|api_key| api_key.key == key)
+    }
+
+    /// Logged in admins may do everything, other callers need one of the
+    /// configured API keys once there are any.
+    pub async fn access(&self, headers: &HeaderMap) -> Result<Access, ApiError> {
+        let actor = initiator(self, headers);
+        if self.config.api_keys.is_empty() || self.request_session(headers).is_some() {
+            return Ok(Access::full(actor));
+        }
+
+        match self.request_api_key(headers) {
+            Some(key) => Access::key(self, key, actor).await,
+            None => Err(ApiError::new(
+                StatusCode::UNAUTHORIZED,
+                "unauthorized",
+                "a valid X-Api-Key header or session is required",
+            )),
+        }
+    }
+}
+
+#[async_trait]
+impl<B: Send> FromRequest<B> for Access {
+    type Rejection = ApiError;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let Extension(state) = Extension::<AppState>::from_request(req)
+            .await
+            .map_err(anyhow::Error::from)?;
+
+        state.access(req.headers()).await
+    }
+}
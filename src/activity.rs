@@ -0,0 +1,48 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::state::AppState;
+
+/// How long members have to be silent to be listed as inactive by default.
+pub const DEFAULT_INACTIVE_DAYS: u32 = 90;
+/// The longest silence that can be asked for, about ten years.
+pub const MAX_INACTIVE_DAYS: u32 = 3650;
+
+/// A member that hasn't written in a chat for a while.
+#[derive(Clone, Debug, Serialize, ToSchema, sqlx::FromRow)]
+pub struct InactiveMember {
+    pub id: i64,
+    pub chat_id: i64,
+    pub username: Option<String>,
+    pub name: String,
+    /// Missing when the member never wrote since activity was tracked, they
+    /// are then counted as silent since they joined.
+    pub last_message_at: Option<DateTime<Utc>>,
+}
+
+impl AppState {
+    /// Remembers that a stored member wrote in the chat, unknown members
+    /// are ignored.
+    pub async fn record_activity(
+        &self,
+        chat_id: i64,
+        user_id: i64,
+        at: DateTime<Utc>,
+    ) -> anyhow::Result<()> {
+        self.storage.touch_member(chat_id, user_id, at).await
+    }
+
+    /// Members that didn't write for `days`, the longest silent first.
+    /// Members without a message or a recorded join aren't listed, nothing
+    /// is known about their activity.
+    pub async fn get_inactive_members(
+        &self,
+        chat_id: i64,
+        days: u32,
+    ) -> anyhow::Result<Vec<InactiveMember>> {
+        let before = Utc::now() - Duration::days(days.into());
+
+        self.storage.get_inactive_members(chat_id, before).await
+    }
+}
@@ -0,0 +1,61 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use teloxide::types::{ChatMember, ChatMemberKind};
+use tracing::info;
+use utoipa::ToSchema;
+
+use crate::state::AppState;
+
+/// An administrator of a chat as last seen by the admin sync or a
+/// membership update.
+#[derive(Clone, Debug, Serialize, ToSchema, sqlx::FromRow)]
+pub struct ChatAdmin {
+    pub user_id: i64,
+    pub username: Option<String>,
+    pub name: String,
+    /// `creator` or `administrator`, like telegram calls them.
+    pub status: String,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl ChatAdmin {
+    /// `None` for members that are not privileged.
+    pub fn from_member(member: &ChatMember) -> Option<Self> {
+        let status = match member.kind {
+            ChatMemberKind::Owner(_) => "creator",
+            ChatMemberKind::Administrator(_) => "administrator",
+            _ => return None,
+        };
+
+        Some(Self {
+            user_id: member.user.id.0 as i64,
+            username: member.user.username.clone(),
+            name: member.user.full_name(),
+            status: status.to_string(),
+            updated_at: Utc::now(),
+        })
+    }
+}
+
+impl AppState {
+    /// Replaces the stored administrators of the chat with those telegram
+    /// reported.
+    pub async fn set_chat_admins(&self, chat_id: i64, admins: &[ChatAdmin]) -> anyhow::Result<()> {
+        self.storage.set_chat_admins(chat_id, admins).await
+    }
+
+    pub async fn upsert_chat_admin(&self, chat_id: i64, admin: &ChatAdmin) -> anyhow::Result<()> {
+        info!("storing admin {} of chat:{chat_id}", admin.user_id);
+
+        self.storage.upsert_chat_admin(chat_id, admin).await
+    }
+
+    pub async fn remove_chat_admin(&self, chat_id: i64, user_id: i64) -> anyhow::Result<()> {
+        self.storage.remove_chat_admin(chat_id, user_id).await
+    }
+
+    /// Administrators of the chat, the creator first.
+    pub async fn get_chat_admins(&self, chat_id: i64) -> anyhow::Result<Vec<ChatAdmin>> {
+        self.storage.get_chat_admins(chat_id).await
+    }
+}
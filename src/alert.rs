@@ -0,0 +1,99 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use teloxide::{requests::Requester, types::ChatId};
+use tracing::{error, warn, Instrument, Span};
+
+use crate::state::AppState;
+
+const HOUR: Duration = Duration::from_secs(60 * 60);
+
+/// Which alerts went out recently, shared by every bot so a problem they
+/// all run into is reported once.
+#[derive(Clone, Default)]
+pub struct Alerts {
+    sent: Arc<Mutex<SentAlerts>>,
+}
+
+#[derive(Default)]
+struct SentAlerts {
+    /// When each alert was last sent, by its key.
+    last_sent: HashMap<String, Instant>,
+    /// Send times within the last hour, oldest first.
+    recent: VecDeque<Instant>,
+}
+
+impl Alerts {
+    /// Whether an alert with this key may be sent at `now`, recording it as
+    /// sent when it may. Alerts repeating within `dedup_window` and those
+    /// beyond `max_per_hour` are held back.
+    pub fn admit(
+        &self,
+        key: &str,
+        dedup_window: Duration,
+        max_per_hour: usize,
+        now: Instant,
+    ) -> bool {
+        let mut sent = self.sent.lock().unwrap();
+        if sent
+            .last_sent
+            .get(key)
+            .is_some_and(|&last| now.duration_since(last) < dedup_window)
+        {
+            return false;
+        }
+        while sent
+            .recent
+            .front()
+            .is_some_and(|&oldest| now.duration_since(oldest) >= HOUR)
+        {
+            sent.recent.pop_front();
+        }
+        if sent.recent.len() >= max_per_hour {
+            return false;
+        }
+
+        sent.recent.push_back(now);
+        sent.last_sent.insert(key.to_string(), now);
+        // keys of alerts that may repeat again aren't needed any more
+        sent.last_sent
+            .retain(|_, last| now.duration_since(*last) < dedup_window);
+
+        true
+    }
+}
+
+impl AppState {
+    /// Logs the alert and sends it to the alerts chat in the background,
+    /// unless an alert with the same key was just sent or too many were.
+    pub fn alert(&self, key: &str, text: &str) {
+        warn!("alert: {text}");
+
+        let config = &self.config.alerts;
+        let Some(chat_id) = config.chat_id else {
+            return;
+        };
+        if !self.alerts.admit(
+            key,
+            config.dedup_window(),
+            config.max_per_hour,
+            Instant::now(),
+        ) {
+            return;
+        }
+
+        let state = self.clone();
+        let text = format!("Alert from bot {}: {text}", self.bot_id);
+        tokio::spawn(
+            async move {
+                if let Err(err) = state.bot.send_message(ChatId(chat_id), text).await {
+                    error!("failed to send an alert to chat:{chat_id} {err}");
+                }
+            }
+            .instrument(Span::current()),
+        );
+    }
+}
@@ -1,111 +1,5083 @@
-use std::sync::Arc;
+use std::{collections::HashSet, net::SocketAddr};
 
 use axum::{
-    extract::Path,
-    http::{header::CONTENT_TYPE, Method, StatusCode},
-    routing::{get, post},
+    body::Bytes,
+    extract::{multipart::Field, ws::WebSocketUpgrade, Multipart, Path, Query},
+    http::{
+        header::{AUTHORIZATION, CONTENT_TYPE, SET_COOKIE},
+        HeaderMap, HeaderName, HeaderValue, Method, Request, StatusCode,
+    },
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{delete, get, patch, post, put},
     Extension, Json, Router,
 };
-use dashmap::{DashMap, DashSet};
-use serde::Deserialize;
-use tower_http::cors::{Any, CorsLayer};
-use tracing::{error, info};
+use axum_server::tls_rustls::RustlsConfig;
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use teloxide::{adaptors::throttle::Limits, requests::Requester};
+use tokio::net::UnixListener;
+use tower_http::{
+    cors::{Any, CorsLayer},
+    request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer},
+    trace::{DefaultOnResponse, TraceLayer},
+    LatencyUnit,
+};
+use tracing::{error, info, warn, Level};
+use utoipa::{IntoParams, ToSchema};
 
-use crate::state::{AppState, ChatCleaningStatus, Chats};
+use crate::access::{Access, Permission};
+use crate::activity::{InactiveMember, DEFAULT_INACTIVE_DAYS, MAX_INACTIVE_DAYS};
+use crate::admin::ChatAdmin;
+use crate::attachment::Attachments;
+use crate::audit::{audit, AuditEntry};
+use crate::backpressure::backpressure;
+use crate::branding::Branding;
+use crate::broadcast::{
+    requests_per_chat, BroadcastProgress, ChatResult, DeliveredFile, Delivery, MessageOptions,
+    SentMessage,
+};
+use crate::broadcast_search::{BroadcastSearch, FoundBroadcast};
+use crate::calendar::Occurrence;
+use crate::captcha::{Captcha, CaptchaChallenge};
+use crate::chat_list::{ChatQuery, ChatSort};
+use crate::config::Config;
+use crate::convert::{convert, SourceFormat};
+use crate::dead_letter::FailedMessage;
+use crate::draft::{Draft, DraftContent};
+use crate::duplicate::DuplicateBroadcast;
+use crate::error::ApiError;
+use crate::estimate::BroadcastEstimate;
+use crate::exclusion::{Exclusion, NewExclusion};
+use crate::format::MessageFormat;
+use crate::health::TaskHeartbeat;
+use crate::idempotency::idempotency;
+use crate::import::{Backup, ConflictMode, ImportReport};
+use crate::invite_link::{InviteLink, NewInviteLink};
+use crate::join_request::{ChatJoinPolicy, JoinRequest, JoinRequestStatus};
+use crate::label::{normalize_labels, MAX_NOTES_LENGTH};
+use crate::language::{validate_language, LocalizedMessage, Translations};
+use crate::maintenance::{maintenance, Maintenance};
+use crate::media::{decode_inline_image, is_remote_image};
+use crate::member_import::{MemberImportReport, SeededMember, MAX_SEEDED_MEMBERS};
+use crate::moderation::{ModerationAction, ModerationSettings, Warning};
+use crate::openapi;
+use crate::poll::{PollDefinition, PollResults};
+use crate::purge::PurgeFilter;
+use crate::queue::{QueueEntry, QueueFilter, QueueStatus};
+use crate::queue_history::{QueuedMessageEdit, QueuedMessageVersion};
+use crate::queue_transfer::{QueueExport, QueueImportReport};
+use crate::quiet_hours::{validate_utc_offset, QuietHours};
+use crate::ratelimit::rate_limit;
+use crate::reaction::BroadcastStats;
+use crate::reconcile::Interrupted;
+use crate::rename::ChatRename;
+use crate::reply::BroadcastReply;
+use crate::report::WeeklyReport;
+use crate::schedule::{parse_cron, CleanupSchedule};
+use crate::self_destruct::MAX_DELETE_AFTER_SECONDS;
+use crate::session::{Session, TelegramLogin, SESSION_COOKIE};
+use crate::settings::Settings;
+use crate::split::split_message;
+use crate::staged_cleanup::{CleanupCandidate, UnbanResult};
+use crate::state::{
+    AppState, ChatStatus, ChatStatusSummary, Chats, CleanupState, ClearChatsResult, Kicks,
+    QueueMode, User, Users,
+};
+use crate::stats::ChatStats;
+use crate::status_v1::ChatStatusV1;
+use crate::subscriber::Audience;
+use crate::telemetry::{make_request_span, scope_request_id, spawn_in_request, REQUEST_ID_HEADER};
+use crate::topic::{ChatTarget, ChatTopic, Targets};
+use crate::usage::{Usage, UsageEntry};
+use crate::validation::{FieldError, Validator, MAX_MESSAGE_PARTS};
+use crate::variant::{validate_variants, MessageVariant, VariantResult};
+use crate::webhook::{NewWebhook, Webhook};
+use crate::welcome::Welcome;
 
-pub async fn run(state: AppState) -> anyhow::Result<()> {
+/// Serves every bot under `/bots/:bot_id`, the first one also without the
+/// prefix.
+pub async fn run(states: Vec<AppState>) -> anyhow::Result<()> {
     info!("starting api server...");
 
+    let primary = states[0].clone();
+    let config = primary.config.clone();
+
     let cors = CorsLayer::new()
-        .allow_headers([CONTENT_TYPE])
-        // allow `GET` and `POST` when accessing the resource
-        .allow_methods([Method::GET, Method::POST])
+        .allow_headers([
+            AUTHORIZATION,
+            CONTENT_TYPE,
+            HeaderName::from_static("idempotency-key"),
+            REQUEST_ID_HEADER,
+        ])
+        .expose_headers([REQUEST_ID_HEADER, TOTAL_COUNT_HEADER])
+        // allow `GET`, `POST` and `DELETE` when accessing the resource
+        .allow_methods([
+            Method::GET,
+            Method::POST,
+            Method::PUT,
+            Method::PATCH,
+            Method::DELETE,
+        ])
         // allow requests from any origin
         .allow_origin(Any);
 
-    let app = Router::new()
+    let mut app = Router::new()
         .route("/", get(|| async { "Hello, World!" }))
-        .route("/chats", get(chats))
-        .route("/status", get(status))
-        .route("/deleteChat/:chat_id", get(delete_chat))
-        .route("/clearChat/:chat_id", get(clear_chat))
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .route("/metrics", get(metrics))
+        .route("/openapi.json", get(openapi::spec))
+        .route("/docs", get(openapi::swagger_ui))
+        .merge(routes(&config));
+    for state in states {
+        app = app.nest(
+            &format!("/bots/{}", state.bot_id),
+            routes(&config).layer(Extension(state)),
+        );
+    }
+
+    let app = app
+        .layer(Extension(primary))
+        .layer(middleware::from_fn(scope_request_id))
+        .layer(
+            TraceLayer::new_for_http()
+                .make_span_with(make_request_span)
+                .on_response(
+                    DefaultOnResponse::new()
+                        .level(Level::INFO)
+                        .latency_unit(LatencyUnit::Millis),
+                ),
+        )
+        .layer(PropagateRequestIdLayer::new(REQUEST_ID_HEADER))
+        .layer(SetRequestIdLayer::new(REQUEST_ID_HEADER, MakeRequestUuid))
+        .layer(cors);
+
+    if let Some(path) = &config.api_socket {
+        info!("listening on unix socket {}", path.display());
+        // a socket left over from a previous run would make binding fail
+        let _ = std::fs::remove_file(path);
+        let listener = UnixListener::bind(path)?;
+        let incoming = futures::stream::unfold(listener, |listener| async {
+            let stream = listener.accept().await.map(|(stream, _)| stream);
+            Some((stream, listener))
+        });
+
+        axum::Server::builder(hyper::server::accept::from_stream(incoming))
+            .serve(app.into_make_service())
+            .await?;
+    } else if let (Some(cert), Some(key)) = (&config.tls_cert, &config.tls_key) {
+        info!("listening on https://{}", config.api_bind);
+        let tls = RustlsConfig::from_pem_file(cert, key).await?;
+
+        axum_server::bind_rustls(config.api_bind, tls)
+            .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+            .await?;
+    } else {
+        info!("listening on http://{}", config.api_bind);
+
+        axum::Server::bind(&config.api_bind)
+            .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Routes that act on the chats and queue of a single bot.
+pub(crate) fn routes(config: &Config) -> Router {
+    // retried requests with the same idempotency key replay the first response
+    let idempotent = Router::new()
         .route("/clearChats/", post(clear_chats))
+        .route_layer(middleware::from_fn(idempotency));
+
+    // refused while the queue is full, before an idempotency key is taken
+    let queueing = Router::new()
         .route("/sendMessage/", post(send_message_to_chat))
-        .layer(Extension(state))
-        .layer(cors);
+        .route("/sendVariants/", post(send_variants))
+        .route_layer(middleware::from_fn(idempotency))
+        .route("/sendMessageMultipart/", post(send_message_multipart))
+        .route("/sendPoll/", post(send_poll))
+        .route("/copyMessage/", post(copy_message))
+        .route("/drafts/:id/send", post(send_draft))
+        .route("/broadcasts/:id/resend", post(resend_broadcast))
+        .route("/broadcasts/:id/retryFailed", post(retry_failed_chats))
+        .route_layer(middleware::from_fn(backpressure));
+
+    // destructive GETs a browser prefetching links or a crawler can trigger
+    let legacy = if config.legacy_get_routes {
+        Router::new()
+            .route("/deleteChat/:chat_id", get(legacy_delete_chat))
+            .route("/clearChat/:chat_id", get(legacy_clear_chat))
+            .route_layer(middleware::from_fn(deprecated))
+    } else {
+        Router::new()
+    };
+
+    // everything that changes state or talks to telegram is rate limited
+    // and, but for logging in and ending maintenance, refused in maintenance
+    let mutations = Router::new()
+        .route("/chats/:chat_id", patch(update_chat).delete(delete_chat))
+        .route("/chats/bulkMute", post(bulk_mute_chats))
+        .route("/chats/bulkDelete", post(bulk_delete_chats))
+        .route("/chats/:chat_id/mute", post(mute_chat))
+        .route("/chats/:chat_id/unmute", post(unmute_chat))
+        .route("/chats/:chat_id/approve", post(approve_chat))
+        .route("/chats/:chat_id/restore", post(restore_chat))
+        .route(
+            "/chats/:chat_id/cleanupSchedule",
+            post(set_cleanup_schedule).delete(delete_cleanup_schedule),
+        )
+        .route("/chats/:chat_id/clear", post(clear_chat))
+        .route("/chats/:chat_id/clear/token", post(clear_chat_token))
+        .route("/chats/:chat_id/clear/confirm", post(confirm_cleanup))
+        .route("/chats/:chat_id/clear/staged", delete(discard_cleanup))
+        .route("/chats/:chat_id/unbanAll", post(unban_all))
+        .route("/clearChats/:chat_id/cancel", post(cancel_cleanup))
+        .route("/chats/:chat_id/resume", post(resume_cleanup))
+        .route("/chats/:chat_id/purge", post(purge))
+        .route("/chats/:chat_id/purgeDeleted", post(purge_deleted))
+        .route("/chats/:chat_id/resync", post(resync_chat))
+        .route("/chats/:chat_id/members/import", post(import_members))
+        .route("/queue/:id", patch(edit_queued_message))
+        .route("/queue/:id/approve", post(approve_queued_message))
+        .route("/queue/:id/resume", post(resume_queued_message))
+        .route("/queue/failed/:id/retry", post(retry_failed_message))
+        .route("/queue/import", post(import_queue))
+        .route("/testSend", post(test_send))
+        .route("/exclusions", post(add_exclusion))
+        .route("/exclusions/:id", delete(delete_exclusion))
+        .route("/import", post(import))
+        .route("/webhooks", post(add_webhook))
+        .route("/webhooks/:id", delete(delete_webhook))
+        .route("/drafts", post(add_draft))
+        .route("/drafts/:id", put(update_draft).delete(delete_draft))
+        .route("/settings", patch(update_settings))
+        .route("/settings/throttle", patch(update_throttle))
+        .route("/broadcasts/:id/edit", post(edit_broadcast))
+        .route("/broadcasts/:id", delete(delete_broadcast))
+        .route(
+            "/chats/:chat_id/welcome",
+            put(set_welcome).delete(delete_welcome),
+        )
+        .route(
+            "/chats/:chat_id/quietHours",
+            put(set_quiet_hours).delete(delete_quiet_hours),
+        )
+        .route("/chats/:chat_id/branding", patch(update_branding))
+        .route(
+            "/chats/:chat_id/captcha",
+            put(set_captcha).delete(delete_captcha),
+        )
+        .route(
+            "/chats/:chat_id/spamFilter",
+            put(set_spam_filter).delete(delete_spam_filter),
+        )
+        .route("/chats/:chat_id/warnings/:user_id", delete(reset_warnings))
+        .route("/chats/:chat_id/inviteLinks", post(create_invite_link))
+        .route(
+            "/chats/:chat_id/inviteLinks/:id/revoke",
+            post(revoke_invite_link),
+        )
+        .route("/chats/:chat_id/joinPolicy", put(set_join_policy))
+        .route(
+            "/chats/:chat_id/joinRequests/:id/approve",
+            post(approve_join_request),
+        )
+        .route(
+            "/chats/:chat_id/joinRequests/:id/decline",
+            post(decline_join_request),
+        )
+        .merge(idempotent)
+        .merge(queueing)
+        .merge(legacy)
+        .route_layer(middleware::from_fn(maintenance))
+        .route("/auth/telegram", post(telegram_login))
+        .route("/settings/maintenance", post(set_maintenance))
+        .route_layer(middleware::from_fn(rate_limit))
+        .route_layer(middleware::from_fn(audit));
+
+    Router::new()
+        .route("/audit", get(audit_log))
+        .route("/interrupted", get(interrupted))
+        .route("/auth/session", get(current_session))
+        .route("/botinfo", get(botinfo))
+        .route("/chats", get(chats))
+        .route("/chats/:chat_id/members", get(chat_members))
+        .route("/chats/:chat_id/members/:user_id", get(chat_member))
+        .route("/chats/:chat_id/inactive", get(inactive_members))
+        .route("/chats/:chat_id/kicks", get(chat_kicks))
+        .route("/chats/:chat_id/clear/staged", get(cleanup_candidates))
+        .route("/chats/:chat_id/moderation", get(chat_moderation))
+        .route("/chats/:chat_id/history", get(chat_history))
+        .route("/chats/:chat_id/stats", get(chat_stats))
+        .route("/chats/:chat_id/topics", get(chat_topics))
+        .route("/chats/:chat_id/names", get(chat_names))
+        .route("/chats/:chat_id/admins", get(chat_admins))
+        .route("/chats/:chat_id/quietHours", get(quiet_hours))
+        .route("/chats/:chat_id/branding", get(branding))
+        .route("/chats/:chat_id/captcha", get(captcha))
+        .route("/chats/:chat_id/captcha/pending", get(captcha_challenges))
+        .route("/chats/:chat_id/spamFilter", get(spam_filter))
+        .route("/chats/:chat_id/warnings", get(warnings))
+        .route("/chats/:chat_id/inviteLinks", get(invite_links))
+        .route("/chats/:chat_id/joinPolicy", get(join_policy))
+        .route("/chats/:chat_id/joinRequests", get(join_requests))
+        .route("/exclusions", get(exclusions))
+        .route("/status", get(status))
+        .route("/schedule", get(schedule))
+        .route("/status/live", get(live_status))
+        .route("/status/:chat_id", get(chat_status))
+        .route("/v2/status/:chat_id", get(chat_status_v2))
+        .route("/usage", get(usage))
+        .route("/reports/weekly", get(weekly_report))
+        .route("/polls/:id/results", get(poll_results))
+        .route("/queue", get(queue))
+        .route("/queue/failed", get(failed_messages))
+        .route("/queue/export", get(export_queue))
+        .route("/webhooks", get(webhooks))
+        .route("/drafts", get(drafts))
+        .route("/queue/:id/history", get(queue_history))
+        .route("/queue/:id/progress", get(queue_progress))
+        .route("/queue/:id/deliveries", get(queue_deliveries))
+        .route("/queue/:id/files", get(queue_files))
+        .route("/broadcasts/search", get(search_broadcasts))
+        .route("/broadcasts/:id/variants", get(broadcast_variants))
+        .route("/broadcasts/:id/stats", get(broadcast_stats))
+        .route("/broadcasts/:id/replies", get(broadcast_replies))
+        .route("/render", post(render))
+        .route("/convert", post(convert_text))
+        .route("/estimate", get(estimate))
+        .route("/settings", get(get_settings))
+        .route("/settings/maintenance", get(get_maintenance))
+        .merge(mutations)
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct Liveness {
+    status: &'static str,
+    uptime_secs: u64,
+    /// Background loops of every bot, stale ones are restarted.
+    tasks: Vec<TaskHeartbeat>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/healthz",
+    responses(
+        (status = 200, description = "Service is alive", body = Liveness),
+    )
+)]
+async fn healthz(Extension(state): Extension<AppState>) -> Json<Liveness> {
+    Json(Liveness {
+        status: "ok",
+        uptime_secs: state.health.uptime().as_secs(),
+        tasks: state.health.heartbeats(),
+    })
+}
+
+/// Uptime, background task heartbeats and queue depths in the Prometheus
+/// text format.
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    responses(
+        (status = 200, description = "Metrics in the Prometheus text format", body = String, content_type = "text/plain"),
+    )
+)]
+async fn metrics(Extension(state): Extension<AppState>) -> impl IntoResponse {
+    let mut metrics = state.health.metrics();
+    // the rest is still worth scraping while the database is down
+    match state.queue_metrics().await {
+        Ok(queue) => metrics.push_str(&queue),
+        Err(err) => error!("failed to count the queued messages {err}"),
+    }
+
+    ([(CONTENT_TYPE, "text/plain; version=0.0.4")], metrics)
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct Readiness {
+    /// As of the last ping of the background health check.
+    database: bool,
+    telegram: bool,
+    stale_tasks: Vec<&'static str>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/readyz",
+    responses(
+        (status = 200, description = "Service is ready", body = Readiness),
+        (status = 503, description = "A dependency is unavailable", body = Readiness),
+    )
+)]
+async fn readyz(Extension(state): Extension<AppState>) -> (StatusCode, Json<Readiness>) {
+    let database = state.health.database_reachable();
+    let telegram = state.bot.get_me().await.is_ok();
+    if telegram {
+        state.telegram_activity.record_success();
+    }
+    let stale_tasks = state.health.stale_tasks();
+
+    let status = if database && telegram && stale_tasks.is_empty() {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    (
+        status,
+        Json(Readiness {
+            database,
+            telegram,
+            stale_tasks,
+        }),
+    )
+}
+
+/// How the bot receives its updates.
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum UpdateMode {
+    Polling,
+    /// Telegram has a webhook URL for the bot, polling fails until it is
+    /// removed.
+    Webhook,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct BotInfo {
+    bot_id: String,
+    /// Telegram user id of the bot, missing when telegram can't be reached.
+    id: Option<u64>,
+    username: Option<String>,
+    first_name: Option<String>,
+    can_join_groups: Option<bool>,
+    can_read_all_group_messages: Option<bool>,
+    mode: Option<UpdateMode>,
+    webhook_url: Option<String>,
+    /// Updates telegram holds that the bot hasn't fetched yet.
+    pending_update_count: Option<u32>,
+    /// Error of the requests made for this answer, like a revoked token.
+    telegram_error: Option<String>,
+    throttle: ThrottleSettings,
+    uptime_secs: u64,
+    /// When telegram last answered a send or bot info request successfully.
+    last_telegram_success_at: Option<DateTime<Utc>>,
+}
+
+/// Answers even when telegram can't be reached, so token and connection
+/// trouble can be diagnosed from `telegram_error`.
+#[utoipa::path(
+    get,
+    path = "/botinfo",
+    responses(
+        (status = 200, description = "The bot as telegram sees it and its connection", body = BotInfo),
+    )
+)]
+async fn botinfo(Extension(state): Extension<AppState>, _access: Access) -> Json<BotInfo> {
+    let mut info = BotInfo {
+        bot_id: state.bot_id.to_string(),
+        id: None,
+        username: None,
+        first_name: None,
+        can_join_groups: None,
+        can_read_all_group_messages: None,
+        mode: None,
+        webhook_url: None,
+        pending_update_count: None,
+        telegram_error: None,
+        throttle: state.settings().throttle.limits().into(),
+        uptime_secs: state.health.uptime().as_secs(),
+        last_telegram_success_at: None,
+    };
+
+    let telegram = async {
+        let me = state.bot.get_me().await?;
+        info.id = Some(me.user.id.0);
+        info.username = me.user.username.clone();
+        info.first_name = Some(me.user.first_name.clone());
+        info.can_join_groups = Some(me.can_join_groups);
+        info.can_read_all_group_messages = Some(me.can_read_all_group_messages);
+
+        let webhook = state.bot.get_webhook_info().await?;
+        info.mode = Some(match webhook.url {
+            Some(_) => UpdateMode::Webhook,
+            None => UpdateMode::Polling,
+        });
+        info.webhook_url = webhook.url.map(String::from);
+        info.pending_update_count = Some(webhook.pending_update_count);
+        state.telegram_activity.record_success();
+
+        Ok::<_, teloxide::RequestError>(())
+    };
+    if let Err(err) = telegram.await {
+        info.telegram_error = Some(err.to_string());
+    }
+    info.last_telegram_success_at = state.telegram_activity.last_success();
+
+    Json(info)
+}
+
+/// Number of items matching a paginated list request, across all pages.
+const TOTAL_COUNT_HEADER: HeaderName = HeaderName::from_static("x-total-count");
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ChatsQuery {
+    /// List the chats the bot was removed from instead.
+    #[serde(default)]
+    archived: bool,
+    /// Only list chats carrying this label.
+    label: Option<String>,
+    /// Only list chats whose name contains this, case-insensitive.
+    search: Option<String>,
+    /// Only list muted chats, or only unmuted ones.
+    muted: Option<bool>,
+    /// Only list chats of this kind: `group`, `supergroup`, `channel` or
+    /// `private`.
+    kind: Option<String>,
+    /// Only list chats in this cleanup state.
+    status: Option<CleanupState>,
+    #[serde(default)]
+    sort: ChatSort,
+    #[serde(default)]
+    descending: bool,
+    page: Option<i64>,
+    /// Lists every chat when left out.
+    per_page: Option<i64>,
+}
+
+/// The number of chats matching the query across all pages is in the
+/// `X-Total-Count` header.
+#[utoipa::path(
+    get,
+    path = "/chats",
+    params(ChatsQuery),
+    responses(
+        (status = 200, description = "Known chats matching the query", body = [Chat]),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn chats(
+    Extension(state): Extension<AppState>,
+    Query(query): Query<ChatsQuery>,
+    access: Access,
+) -> Result<(HeaderMap, Json<Chats>), ApiError> {
+    let mut chat_query = ChatQuery {
+        archived: query.archived,
+        search: query.search.filter(|search| !search.is_empty()),
+        label: query.label,
+        muted: query.muted,
+        kind: query.kind,
+        sort: query.sort,
+        descending: query.descending,
+        ..Default::default()
+    };
+    if let Some(chats) = access.allowed_chats() {
+        chat_query.restrict(chats);
+    }
+    if let Some(status) = query.status {
+        state.restrict_to_state(&mut chat_query, status);
+    }
+    let (limit, offset) = match query.per_page {
+        Some(per_page) => {
+            let per_page = per_page.clamp(1, 500);
+            let page = query.page.unwrap_or(1).max(1);
+            (per_page, (page - 1) * per_page)
+        }
+        None => (i64::MAX, 0),
+    };
+
+    let (chats, total) = state.query_chats(&chat_query, limit, offset).await?;
+    let mut headers = HeaderMap::new();
+    headers.insert(TOTAL_COUNT_HEADER, HeaderValue::from(total));
+
+    Ok((headers, Json(chats)))
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct MembersQuery {
+    page: Option<i64>,
+    per_page: Option<i64>,
+    search: Option<String>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/chats/{chat_id}/members",
+    params(("chat_id" = i64, Path, description = "Telegram chat id"), MembersQuery),
+    responses(
+        (status = 200, description = "Tracked members of the chat", body = [User]),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn chat_members(
+    Extension(state): Extension<AppState>,
+    Path(chat_id): Path<i64>,
+    access: Access,
+    Query(query): Query<MembersQuery>,
+) -> Result<Json<Users>, ApiError> {
+    access.chat(chat_id)?;
+    let per_page = query.per_page.unwrap_or(50).clamp(1, 500);
+    let page = query.page.unwrap_or(1).max(1);
+    let search = query.search.filter(|search| !search.is_empty());
+
+    let members = state
+        .get_members_page(chat_id, search, per_page, (page - 1) * per_page)
+        .await?;
 
-    axum::Server::bind(&"0.0.0.0:3030".parse().unwrap())
-        .serve(app.into_make_service())
+    Ok(Json(members))
+}
+
+#[utoipa::path(
+    get,
+    path = "/chats/{chat_id}/members/{user_id}",
+    params(
+        ("chat_id" = i64, Path, description = "Telegram chat id"),
+        ("user_id" = i64, Path, description = "Telegram user id"),
+    ),
+    responses(
+        (status = 200, description = "The tracked member", body = User),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn chat_member(
+    Extension(state): Extension<AppState>,
+    Path((chat_id, user_id)): Path<(i64, i64)>,
+    access: Access,
+) -> Result<Json<User>, ApiError> {
+    access.chat(chat_id)?;
+    let member = state
+        .get_member(chat_id, user_id)
+        .await?
+        .ok_or_else(|| ApiError::not_found("member not found"))?;
+
+    Ok(Json(member))
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct InactiveQuery {
+    /// Defaults to 90.
+    days: Option<u32>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/chats/{chat_id}/inactive",
+    params(("chat_id" = i64, Path, description = "Telegram chat id"), InactiveQuery),
+    responses(
+        (status = 200, description = "Members that didn't write for the given days, the longest silent first", body = [InactiveMember]),
+        (status = 400, description = "Invalid days", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn inactive_members(
+    Extension(state): Extension<AppState>,
+    Path(chat_id): Path<i64>,
+    access: Access,
+    Query(query): Query<InactiveQuery>,
+) -> Result<Json<Vec<InactiveMember>>, ApiError> {
+    access.chat(chat_id)?;
+    let days = query.days.unwrap_or(DEFAULT_INACTIVE_DAYS);
+    if !(1..=MAX_INACTIVE_DAYS).contains(&days) {
+        return Err(ApiError::bad_request(format!(
+            "days must be between 1 and {MAX_INACTIVE_DAYS}"
+        )));
+    }
+
+    Ok(Json(state.get_inactive_members(chat_id, days).await?))
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct KicksQuery {
+    page: Option<i64>,
+    per_page: Option<i64>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/chats/{chat_id}/kicks",
+    params(("chat_id" = i64, Path, description = "Telegram chat id"), KicksQuery),
+    responses(
+        (status = 200, description = "Members kicked from the chat", body = [Kick]),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn chat_kicks(
+    Extension(state): Extension<AppState>,
+    Path(chat_id): Path<i64>,
+    access: Access,
+    Query(query): Query<KicksQuery>,
+) -> Result<Json<Kicks>, ApiError> {
+    access.chat(chat_id)?;
+    let per_page = query.per_page.unwrap_or(50).clamp(1, 500);
+    let page = query.page.unwrap_or(1).max(1);
+
+    let kicks = state
+        .get_kicks(chat_id, per_page, (page - 1) * per_page)
+        .await?;
+
+    Ok(Json(kicks))
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ModerationQuery {
+    /// Only actions taken on this user.
+    user_id: Option<i64>,
+    page: Option<i64>,
+    per_page: Option<i64>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/chats/{chat_id}/moderation",
+    params(("chat_id" = i64, Path, description = "Telegram chat id"), ModerationQuery),
+    responses(
+        (status = 200, description = "Warnings, kicks and bans by chat admins and the spam filter, newest first", body = [ModerationAction]),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn chat_moderation(
+    Extension(state): Extension<AppState>,
+    Path(chat_id): Path<i64>,
+    access: Access,
+    Query(query): Query<ModerationQuery>,
+) -> Result<Json<Vec<ModerationAction>>, ApiError> {
+    access.chat(chat_id)?;
+    let per_page = query.per_page.unwrap_or(50).clamp(1, 500);
+    let page = query.page.unwrap_or(1).max(1);
+
+    let actions = state
+        .get_moderation_log(chat_id, query.user_id, per_page, (page - 1) * per_page)
+        .await?;
+
+    Ok(Json(actions))
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct HistoryQuery {
+    page: Option<i64>,
+    per_page: Option<i64>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/chats/{chat_id}/history",
+    params(("chat_id" = i64, Path, description = "Telegram chat id"), HistoryQuery),
+    responses(
+        (status = 200, description = "Messages sent to the chat, newest first", body = [SentMessage]),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn chat_history(
+    Extension(state): Extension<AppState>,
+    Path(chat_id): Path<i64>,
+    access: Access,
+    Query(query): Query<HistoryQuery>,
+) -> Result<Json<Vec<SentMessage>>, ApiError> {
+    access.chat(chat_id)?;
+    let per_page = query.per_page.unwrap_or(50).clamp(1, 500);
+    let page = query.page.unwrap_or(1).max(1);
+
+    let messages = state
+        .get_sent_messages(chat_id, per_page, (page - 1) * per_page)
         .await?;
+
+    Ok(Json(messages))
+}
+
+/// Refuses chats the bot is not in.
+fn known_chat(state: &AppState, chat_id: i64) -> Result<(), ApiError> {
+    if !state.chats_status.contains_key(&chat_id) {
+        return Err(ApiError::not_found(format!("chat {chat_id} not found")));
+    }
+
     Ok(())
 }
 
-async fn chats(Extension(state): Extension<AppState>) -> Json<Chats> {
-    Json(state.get_chats().await.unwrap())
+#[utoipa::path(
+    get,
+    path = "/chats/{chat_id}/stats",
+    params(("chat_id" = i64, Path, description = "Telegram chat id")),
+    responses(
+        (status = 200, description = "Membership, broadcast and cleanup statistics of the chat", body = ChatStats),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn chat_stats(
+    Extension(state): Extension<AppState>,
+    Path(chat_id): Path<i64>,
+    access: Access,
+) -> Result<Json<ChatStats>, ApiError> {
+    access.chat(chat_id)?;
+    known_chat(&state, chat_id)?;
+
+    Ok(Json(state.get_chat_stats(chat_id).await?))
 }
 
-async fn status(
+#[utoipa::path(
+    get,
+    path = "/chats/{chat_id}/topics",
+    params(("chat_id" = i64, Path, description = "Telegram chat id")),
+    responses(
+        (status = 200, description = "Forum topics seen in the chat", body = [ChatTopic]),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn chat_topics(
     Extension(state): Extension<AppState>,
-) -> Json<Arc<DashMap<i64, ChatCleaningStatus>>> {
-    Json(state.chats_status.clone())
+    Path(chat_id): Path<i64>,
+    access: Access,
+) -> Result<Json<Vec<ChatTopic>>, ApiError> {
+    access.chat(chat_id)?;
+    known_chat(&state, chat_id)?;
+
+    Ok(Json(state.get_topics(chat_id).await?))
 }
 
-async fn delete_chat(
+#[utoipa::path(
+    get,
+    path = "/chats/{chat_id}/names",
+    params(("chat_id" = i64, Path, description = "Telegram chat id")),
+    responses(
+        (status = 200, description = "Renames of the chat, newest first", body = [ChatRename]),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn chat_names(
     Extension(state): Extension<AppState>,
     Path(chat_id): Path<i64>,
-) -> Result<(), StatusCode> {
-    state.delete_chat(chat_id).await.map_err(|err| {
-        error!("{err}");
-        StatusCode::INTERNAL_SERVER_ERROR
-    })
+    access: Access,
+) -> Result<Json<Vec<ChatRename>>, ApiError> {
+    access.chat(chat_id)?;
+    known_chat(&state, chat_id)?;
+
+    Ok(Json(state.get_chat_renames(chat_id).await?))
 }
 
-async fn clear_chat(
+#[utoipa::path(
+    get,
+    path = "/chats/{chat_id}/admins",
+    params(("chat_id" = i64, Path, description = "Telegram chat id")),
+    responses(
+        (status = 200, description = "Administrators of the chat, the creator first", body = [ChatAdmin]),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn chat_admins(
     Extension(state): Extension<AppState>,
     Path(chat_id): Path<i64>,
-) -> Result<(), StatusCode> {
-    state.delete_all_members(chat_id).await.map_err(|err| {
-        error!("{err}");
-        StatusCode::INTERNAL_SERVER_ERROR
-    })
+    access: Access,
+) -> Result<Json<Vec<ChatAdmin>>, ApiError> {
+    access.chat(chat_id)?;
+    known_chat(&state, chat_id)?;
+
+    Ok(Json(state.get_chat_admins(chat_id).await?))
 }
 
-#[derive(Deserialize)]
-struct ClearChatsBody {
-    chats: Vec<i64>,
+/// Fields that are left out keep their current value.
+#[derive(Deserialize, ToSchema)]
+pub struct ChatPatch {
+    /// Messages per minute sent to the chat, `null` goes back to the global
+    /// throttle.
+    #[serde(default, deserialize_with = "present")]
+    #[schema(value_type = Option<i32>)]
+    rate_limit_override: Option<Option<i32>>,
+    /// Language code like `en` or `pt-br` picking the translation of
+    /// broadcasts, `null` sends the untranslated text.
+    #[serde(default, deserialize_with = "present")]
+    #[schema(value_type = Option<String>)]
+    language: Option<Option<String>>,
+    /// Replaces the labels of the chat, `[]` removes them all.
+    labels: Option<Vec<String>>,
+    /// Free-form notes for the operators, `null` clears them.
+    #[serde(default, deserialize_with = "present")]
+    #[schema(value_type = Option<String>)]
+    notes: Option<Option<String>>,
+    /// Delete the messages telegram posts when members join or leave.
+    delete_service_messages: Option<bool>,
+    /// Offset of the chat's local time from UTC that broadcasts with
+    /// `datetime_local` go out by, `null` puts the chat on UTC.
+    #[serde(default, deserialize_with = "present")]
+    #[schema(value_type = Option<i32>)]
+    utc_offset_minutes: Option<Option<i32>>,
 }
 
-async fn clear_chats(
+/// Tells a field set to `null` apart from a missing one.
+fn present<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+#[utoipa::path(
+    patch,
+    path = "/chats/{chat_id}",
+    params(("chat_id" = i64, Path, description = "Telegram chat id")),
+    request_body = ChatPatch,
+    responses(
+        (status = 200, description = "Chat settings were updated"),
+        (status = 400, description = "Invalid settings", body = ErrorBody),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn update_chat(
     Extension(state): Extension<AppState>,
-    Json(payload): Json<ClearChatsBody>,
-) -> Result<(), StatusCode> {
-    tokio::spawn(async move { state.clear_chats(payload.chats).await });
+    Path(chat_id): Path<i64>,
+    access: Access,
+    Json(payload): Json<ChatPatch>,
+) -> Result<(), ApiError> {
+    access.require(Permission::Manage)?;
+    access.chat(chat_id)?;
+    known_chat(&state, chat_id)?;
+
+    if payload
+        .rate_limit_override
+        .flatten()
+        .is_some_and(|per_minute| per_minute < 1)
+    {
+        return Err(ApiError::bad_request(
+            "rate_limit_override must be at least 1 message per minute",
+        ));
+    }
+    if let Some(Some(language)) = &payload.language {
+        validate_language(language).map_err(ApiError::bad_request)?;
+    }
+    if let Some(Some(minutes)) = payload.utc_offset_minutes {
+        validate_utc_offset(minutes).map_err(ApiError::bad_request)?;
+    }
+    let labels = payload
+        .labels
+        .map(normalize_labels)
+        .transpose()
+        .map_err(ApiError::bad_request)?;
+    if payload
+        .notes
+        .as_ref()
+        .and_then(Option::as_ref)
+        .is_some_and(|notes| notes.chars().count() > MAX_NOTES_LENGTH)
+    {
+        return Err(ApiError::bad_request(format!(
+            "notes can't be longer than {MAX_NOTES_LENGTH} characters"
+        )));
+    }
+
+    if let Some(per_minute) = payload.rate_limit_override {
+        state.set_chat_rate_limit(chat_id, per_minute).await?;
+    }
+    if let Some(language) = payload.language {
+        state
+            .set_chat_language(chat_id, language.as_deref())
+            .await?;
+    }
+    if let Some(labels) = labels {
+        state.set_chat_labels(chat_id, labels).await?;
+    }
+    if let Some(notes) = payload.notes {
+        state.set_chat_notes(chat_id, notes.as_deref()).await?;
+    }
+    if let Some(delete) = payload.delete_service_messages {
+        state.set_delete_service_messages(chat_id, delete).await?;
+    }
+    if let Some(minutes) = payload.utc_offset_minutes {
+        state.set_chat_utc_offset(chat_id, minutes).await?;
+    }
+
     Ok(())
 }
 
-#[derive(Deserialize)]
-struct SendMessageBody {
-    chats: Vec<i64>,
-    message: String,
-    images: Vec<String>,
-    datetime: String,
+#[utoipa::path(
+    post,
+    path = "/chats/{chat_id}/mute",
+    params(("chat_id" = i64, Path, description = "Telegram chat id")),
+    responses(
+        (status = 200, description = "Chat is excluded from broadcasts"),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn mute_chat(
+    Extension(state): Extension<AppState>,
+    Path(chat_id): Path<i64>,
+    access: Access,
+) -> Result<(), ApiError> {
+    access.require(Permission::Manage)?;
+    access.chat(chat_id)?;
+    set_chat_muted(state, chat_id, true).await
 }
 
-async fn send_message_to_chat(
+#[utoipa::path(
+    post,
+    path = "/chats/{chat_id}/unmute",
+    params(("chat_id" = i64, Path, description = "Telegram chat id")),
+    responses(
+        (status = 200, description = "Chat receives broadcasts again"),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn unmute_chat(
     Extension(state): Extension<AppState>,
-    Json(payload): Json<SendMessageBody>,
-) {
-    tokio::spawn(async move {
-        if let Err(err) = state
-            .queue_message_with_images(
-                payload.chats,
-                payload.message,
-                payload.images,
-                payload.datetime,
-            )
-            .await
-        {
-            error!("error when queuing message with images to chats {err}");
-        }
-    });
+    Path(chat_id): Path<i64>,
+    access: Access,
+) -> Result<(), ApiError> {
+    access.require(Permission::Manage)?;
+    access.chat(chat_id)?;
+    set_chat_muted(state, chat_id, false).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/chats/{chat_id}/approve",
+    params(("chat_id" = i64, Path, description = "Telegram chat id")),
+    responses(
+        (status = 200, description = "Bot is active in the chat"),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn approve_chat(
+    Extension(state): Extension<AppState>,
+    Path(chat_id): Path<i64>,
+    access: Access,
+) -> Result<(), ApiError> {
+    access.require(Permission::Manage)?;
+    access.chat(chat_id)?;
+    if !state.approve_chat(chat_id).await? {
+        return Err(ApiError::not_found(format!("chat {chat_id} not found")));
+    }
+
+    Ok(())
+}
+
+#[utoipa::path(
+    post,
+    path = "/chats/{chat_id}/restore",
+    params(("chat_id" = i64, Path, description = "Telegram chat id")),
+    responses(
+        (status = 200, description = "Archived chat is tracked again"),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn restore_chat(
+    Extension(state): Extension<AppState>,
+    Path(chat_id): Path<i64>,
+    access: Access,
+) -> Result<(), ApiError> {
+    access.require(Permission::Manage)?;
+    access.chat(chat_id)?;
+    if !state.restore_chat(chat_id).await? {
+        return Err(ApiError::not_found(format!(
+            "archived chat {chat_id} not found"
+        )));
+    }
+
+    Ok(())
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct CleanupScheduleBody {
+    /// Cron expression, either five fields or with leading seconds.
+    cron: String,
+}
+
+#[utoipa::path(
+    post,
+    path = "/chats/{chat_id}/cleanupSchedule",
+    params(("chat_id" = i64, Path, description = "Telegram chat id")),
+    request_body = CleanupScheduleBody,
+    responses(
+        (status = 200, description = "Chat will be cleared on the schedule", body = CleanupSchedule),
+        (status = 400, description = "Invalid cron expression", body = ErrorBody),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn set_cleanup_schedule(
+    Extension(state): Extension<AppState>,
+    Path(chat_id): Path<i64>,
+    access: Access,
+    Json(payload): Json<CleanupScheduleBody>,
+) -> Result<Json<CleanupSchedule>, ApiError> {
+    access.require(Permission::Cleanup)?;
+    access.chat(chat_id)?;
+    parse_cron(&payload.cron).map_err(ApiError::bad_request)?;
+    known_chat(&state, chat_id)?;
+
+    Ok(Json(
+        state.set_cleanup_schedule(chat_id, &payload.cron).await?,
+    ))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/chats/{chat_id}/cleanupSchedule",
+    params(("chat_id" = i64, Path, description = "Telegram chat id")),
+    responses(
+        (status = 200, description = "Schedule was removed"),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn delete_cleanup_schedule(
+    Extension(state): Extension<AppState>,
+    Path(chat_id): Path<i64>,
+    access: Access,
+) -> Result<(), ApiError> {
+    access.require(Permission::Cleanup)?;
+    access.chat(chat_id)?;
+    if !state.delete_cleanup_schedule(chat_id).await? {
+        return Err(ApiError::not_found(format!(
+            "chat {chat_id} has no cleanup schedule"
+        )));
+    }
+
+    Ok(())
+}
+
+#[utoipa::path(
+    put,
+    path = "/chats/{chat_id}/welcome",
+    params(("chat_id" = i64, Path, description = "Telegram chat id")),
+    request_body = Welcome,
+    responses(
+        (status = 200, description = "New members will be greeted", body = Welcome),
+        (status = 400, description = "Invalid template", body = ErrorBody),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn set_welcome(
+    Extension(state): Extension<AppState>,
+    Path(chat_id): Path<i64>,
+    access: Access,
+    Json(payload): Json<Welcome>,
+) -> Result<Json<Welcome>, ApiError> {
+    access.require(Permission::Manage)?;
+    access.chat(chat_id)?;
+    payload.validate().map_err(ApiError::bad_request)?;
+    known_chat(&state, chat_id)?;
+
+    state.set_welcome(chat_id, &payload).await?;
+
+    Ok(Json(payload))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/chats/{chat_id}/welcome",
+    params(("chat_id" = i64, Path, description = "Telegram chat id")),
+    responses(
+        (status = 200, description = "New members are no longer greeted"),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn delete_welcome(
+    Extension(state): Extension<AppState>,
+    Path(chat_id): Path<i64>,
+    access: Access,
+) -> Result<(), ApiError> {
+    access.require(Permission::Manage)?;
+    access.chat(chat_id)?;
+    if !state.chats_status.contains_key(&chat_id) || !state.delete_welcome(chat_id).await? {
+        return Err(ApiError::not_found(format!(
+            "chat {chat_id} has no welcome message"
+        )));
+    }
+
+    Ok(())
+}
+
+#[utoipa::path(
+    get,
+    path = "/chats/{chat_id}/quietHours",
+    params(("chat_id" = i64, Path, description = "Telegram chat id")),
+    responses(
+        (status = 200, description = "Quiet hours of the chat", body = QuietHours),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn quiet_hours(
+    Extension(state): Extension<AppState>,
+    Path(chat_id): Path<i64>,
+    access: Access,
+) -> Result<Json<QuietHours>, ApiError> {
+    access.chat(chat_id)?;
+
+    match state.get_quiet_hours(chat_id).await? {
+        Some(quiet_hours) if state.chats_status.contains_key(&chat_id) => Ok(Json(quiet_hours)),
+        _ => Err(ApiError::not_found(format!(
+            "chat {chat_id} has no quiet hours"
+        ))),
+    }
+}
+
+#[utoipa::path(
+    put,
+    path = "/chats/{chat_id}/quietHours",
+    params(("chat_id" = i64, Path, description = "Telegram chat id")),
+    request_body = QuietHours,
+    responses(
+        (status = 200, description = "Broadcasts to the chat are held back during the quiet hours", body = QuietHours),
+        (status = 400, description = "Invalid quiet hours", body = ErrorBody),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn set_quiet_hours(
+    Extension(state): Extension<AppState>,
+    Path(chat_id): Path<i64>,
+    access: Access,
+    Json(payload): Json<QuietHours>,
+) -> Result<Json<QuietHours>, ApiError> {
+    access.require(Permission::Manage)?;
+    access.chat(chat_id)?;
+    payload.validate().map_err(ApiError::bad_request)?;
+    known_chat(&state, chat_id)?;
+
+    state.set_quiet_hours(chat_id, &payload).await?;
+
+    Ok(Json(payload))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/chats/{chat_id}/quietHours",
+    params(("chat_id" = i64, Path, description = "Telegram chat id")),
+    responses(
+        (status = 200, description = "Broadcasts reach the chat at any time again"),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn delete_quiet_hours(
+    Extension(state): Extension<AppState>,
+    Path(chat_id): Path<i64>,
+    access: Access,
+) -> Result<(), ApiError> {
+    access.require(Permission::Manage)?;
+    access.chat(chat_id)?;
+    if !state.chats_status.contains_key(&chat_id) || !state.delete_quiet_hours(chat_id).await? {
+        return Err(ApiError::not_found(format!(
+            "chat {chat_id} has no quiet hours"
+        )));
+    }
+
+    Ok(())
+}
+
+/// The chat's own header and footer. Where they are missing, broadcasts get
+/// the ones configured for its chat group.
+#[utoipa::path(
+    get,
+    path = "/chats/{chat_id}/branding",
+    params(("chat_id" = i64, Path, description = "Telegram chat id")),
+    responses(
+        (status = 200, description = "Branding of the chat", body = Branding),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn branding(
+    Extension(state): Extension<AppState>,
+    Path(chat_id): Path<i64>,
+    access: Access,
+) -> Result<Json<Branding>, ApiError> {
+    access.chat(chat_id)?;
+    known_chat(&state, chat_id)?;
+
+    Ok(Json(state.get_branding(chat_id).await?.unwrap_or_default()))
+}
+
+/// Fields that are left out keep their current value. Both are plain text,
+/// escaped for the parse mode of each broadcast.
+#[derive(Deserialize, ToSchema)]
+pub struct BrandingPatch {
+    /// Put above the text of broadcasts, `null` removes it.
+    #[serde(default, deserialize_with = "present")]
+    #[schema(value_type = Option<String>)]
+    header: Option<Option<String>>,
+    /// Put below the text of broadcasts, `null` removes it.
+    #[serde(default, deserialize_with = "present")]
+    #[schema(value_type = Option<String>)]
+    footer: Option<Option<String>>,
+}
+
+#[utoipa::path(
+    patch,
+    path = "/chats/{chat_id}/branding",
+    params(("chat_id" = i64, Path, description = "Telegram chat id")),
+    request_body = BrandingPatch,
+    responses(
+        (status = 200, description = "Broadcasts to the chat get the new header and footer", body = Branding),
+        (status = 400, description = "Invalid branding", body = ErrorBody),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn update_branding(
+    Extension(state): Extension<AppState>,
+    Path(chat_id): Path<i64>,
+    access: Access,
+    Json(payload): Json<BrandingPatch>,
+) -> Result<Json<Branding>, ApiError> {
+    access.require(Permission::Manage)?;
+    access.chat(chat_id)?;
+    known_chat(&state, chat_id)?;
+
+    let mut branding = state.get_branding(chat_id).await?.unwrap_or_default();
+    if let Some(header) = payload.header {
+        branding.header = header;
+    }
+    if let Some(footer) = payload.footer {
+        branding.footer = footer;
+    }
+    branding.validate().map_err(ApiError::bad_request)?;
+    state.set_branding(chat_id, &branding).await?;
+
+    Ok(Json(branding))
+}
+
+#[utoipa::path(
+    get,
+    path = "/chats/{chat_id}/captcha",
+    params(("chat_id" = i64, Path, description = "Telegram chat id")),
+    responses(
+        (status = 200, description = "Captcha of the chat", body = Captcha),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn captcha(
+    Extension(state): Extension<AppState>,
+    Path(chat_id): Path<i64>,
+    access: Access,
+) -> Result<Json<Captcha>, ApiError> {
+    access.chat(chat_id)?;
+
+    match state.get_captcha(chat_id).await? {
+        Some(captcha) if state.chats_status.contains_key(&chat_id) => Ok(Json(captcha)),
+        _ => Err(ApiError::not_found(format!(
+            "chat {chat_id} has no captcha"
+        ))),
+    }
+}
+
+/// The bot has to be an admin allowed to restrict and ban members.
+#[utoipa::path(
+    put,
+    path = "/chats/{chat_id}/captcha",
+    params(("chat_id" = i64, Path, description = "Telegram chat id")),
+    request_body = Captcha,
+    responses(
+        (status = 200, description = "New members have to solve the captcha", body = Captcha),
+        (status = 400, description = "Invalid captcha", body = ErrorBody),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn set_captcha(
+    Extension(state): Extension<AppState>,
+    Path(chat_id): Path<i64>,
+    access: Access,
+    Json(payload): Json<Captcha>,
+) -> Result<Json<Captcha>, ApiError> {
+    access.require(Permission::Manage)?;
+    access.chat(chat_id)?;
+    payload.validate().map_err(ApiError::bad_request)?;
+    known_chat(&state, chat_id)?;
+
+    state.set_captcha(chat_id, &payload).await?;
+
+    Ok(Json(payload))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/chats/{chat_id}/captcha",
+    params(("chat_id" = i64, Path, description = "Telegram chat id")),
+    responses(
+        (status = 200, description = "New members can write right away again"),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn delete_captcha(
+    Extension(state): Extension<AppState>,
+    Path(chat_id): Path<i64>,
+    access: Access,
+) -> Result<(), ApiError> {
+    access.require(Permission::Manage)?;
+    access.chat(chat_id)?;
+    if !state.chats_status.contains_key(&chat_id) || !state.delete_captcha(chat_id).await? {
+        return Err(ApiError::not_found(format!(
+            "chat {chat_id} has no captcha"
+        )));
+    }
+
+    Ok(())
+}
+
+#[utoipa::path(
+    get,
+    path = "/chats/{chat_id}/captcha/pending",
+    params(("chat_id" = i64, Path, description = "Telegram chat id")),
+    responses(
+        (status = 200, description = "New members that have yet to solve the captcha, soonest to be kicked first", body = [CaptchaChallenge]),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn captcha_challenges(
+    Extension(state): Extension<AppState>,
+    Path(chat_id): Path<i64>,
+    access: Access,
+) -> Result<Json<Vec<CaptchaChallenge>>, ApiError> {
+    access.chat(chat_id)?;
+    known_chat(&state, chat_id)?;
+
+    Ok(Json(state.get_captcha_challenges(chat_id).await?))
+}
+
+#[utoipa::path(
+    get,
+    path = "/chats/{chat_id}/spamFilter",
+    params(("chat_id" = i64, Path, description = "Telegram chat id")),
+    responses(
+        (status = 200, description = "Spam filter of the chat", body = ModerationSettings),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn spam_filter(
+    Extension(state): Extension<AppState>,
+    Path(chat_id): Path<i64>,
+    access: Access,
+) -> Result<Json<ModerationSettings>, ApiError> {
+    access.chat(chat_id)?;
+
+    match state.get_moderation(chat_id).await? {
+        Some(settings) if state.chats_status.contains_key(&chat_id) => Ok(Json(settings)),
+        _ => Err(ApiError::not_found(format!(
+            "chat {chat_id} has no spam filter"
+        ))),
+    }
+}
+
+#[utoipa::path(
+    put,
+    path = "/chats/{chat_id}/spamFilter",
+    params(("chat_id" = i64, Path, description = "Telegram chat id")),
+    request_body = ModerationSettings,
+    responses(
+        (status = 200, description = "Matching messages of regular members are deleted", body = ModerationSettings),
+        (status = 400, description = "Invalid spam filter", body = ErrorBody),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn set_spam_filter(
+    Extension(state): Extension<AppState>,
+    Path(chat_id): Path<i64>,
+    access: Access,
+    Json(payload): Json<ModerationSettings>,
+) -> Result<Json<ModerationSettings>, ApiError> {
+    access.require(Permission::Manage)?;
+    access.chat(chat_id)?;
+    payload.validate().map_err(ApiError::bad_request)?;
+    known_chat(&state, chat_id)?;
+
+    state.set_moderation(chat_id, &payload).await?;
+
+    Ok(Json(payload))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/chats/{chat_id}/spamFilter",
+    params(("chat_id" = i64, Path, description = "Telegram chat id")),
+    responses(
+        (status = 200, description = "Messages of the chat are no longer filtered"),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn delete_spam_filter(
+    Extension(state): Extension<AppState>,
+    Path(chat_id): Path<i64>,
+    access: Access,
+) -> Result<(), ApiError> {
+    access.require(Permission::Manage)?;
+    access.chat(chat_id)?;
+    if !state.chats_status.contains_key(&chat_id) || !state.delete_moderation(chat_id).await? {
+        return Err(ApiError::not_found(format!(
+            "chat {chat_id} has no spam filter"
+        )));
+    }
+
+    Ok(())
+}
+
+#[utoipa::path(
+    get,
+    path = "/chats/{chat_id}/warnings",
+    params(("chat_id" = i64, Path, description = "Telegram chat id")),
+    responses(
+        (status = 200, description = "Members warned by the spam filter, most warned first", body = [Warning]),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn warnings(
+    Extension(state): Extension<AppState>,
+    Path(chat_id): Path<i64>,
+    access: Access,
+) -> Result<Json<Vec<Warning>>, ApiError> {
+    access.chat(chat_id)?;
+    known_chat(&state, chat_id)?;
+
+    Ok(Json(state.get_warnings(chat_id).await?))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/chats/{chat_id}/warnings/{user_id}",
+    params(
+        ("chat_id" = i64, Path, description = "Telegram chat id"),
+        ("user_id" = i64, Path, description = "Telegram user id"),
+    ),
+    responses(
+        (status = 200, description = "The member starts over without warnings"),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn reset_warnings(
+    Extension(state): Extension<AppState>,
+    Path((chat_id, user_id)): Path<(i64, i64)>,
+    access: Access,
+) -> Result<(), ApiError> {
+    access.require(Permission::Manage)?;
+    access.chat(chat_id)?;
+    if !state.chats_status.contains_key(&chat_id) || !state.reset_warnings(chat_id, user_id).await?
+    {
+        return Err(ApiError::not_found(format!(
+            "user {user_id} has no warnings in chat {chat_id}"
+        )));
+    }
+
+    Ok(())
+}
+
+#[utoipa::path(
+    get,
+    path = "/chats/{chat_id}/inviteLinks",
+    params(("chat_id" = i64, Path, description = "Telegram chat id")),
+    responses(
+        (status = 200, description = "Invite links the bot created for the chat, newest first", body = [InviteLink]),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn invite_links(
+    Extension(state): Extension<AppState>,
+    Path(chat_id): Path<i64>,
+    access: Access,
+) -> Result<Json<Vec<InviteLink>>, ApiError> {
+    access.chat(chat_id)?;
+    known_chat(&state, chat_id)?;
+
+    Ok(Json(state.get_invite_links(chat_id).await?))
+}
+
+/// Creates the link in telegram, which needs the bot to be an admin allowed
+/// to invite users.
+#[utoipa::path(
+    post,
+    path = "/chats/{chat_id}/inviteLinks",
+    params(("chat_id" = i64, Path, description = "Telegram chat id")),
+    request_body = NewInviteLink,
+    responses(
+        (status = 200, description = "The new invite link", body = InviteLink),
+        (status = 400, description = "Invalid options", body = ErrorBody),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 502, description = "Telegram refused to create the link", body = ErrorBody),
+    )
+)]
+async fn create_invite_link(
+    Extension(state): Extension<AppState>,
+    Path(chat_id): Path<i64>,
+    access: Access,
+    Json(payload): Json<NewInviteLink>,
+) -> Result<Json<InviteLink>, ApiError> {
+    access.require(Permission::Manage)?;
+    access.chat(chat_id)?;
+    payload.validate().map_err(ApiError::bad_request)?;
+    known_chat(&state, chat_id)?;
+
+    Ok(Json(state.create_invite_link(chat_id, &payload).await?))
+}
+
+#[utoipa::path(
+    post,
+    path = "/chats/{chat_id}/inviteLinks/{id}/revoke",
+    params(
+        ("chat_id" = i64, Path, description = "Telegram chat id"),
+        ("id" = i32, Path, description = "Invite link id"),
+    ),
+    responses(
+        (status = 200, description = "The link no longer works", body = InviteLink),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 502, description = "Telegram refused to revoke the link", body = ErrorBody),
+    )
+)]
+async fn revoke_invite_link(
+    Extension(state): Extension<AppState>,
+    Path((chat_id, id)): Path<(i64, i32)>,
+    access: Access,
+) -> Result<Json<InviteLink>, ApiError> {
+    access.require(Permission::Manage)?;
+    access.chat(chat_id)?;
+
+    state
+        .revoke_invite_link(chat_id, id)
+        .await?
+        .map(Json)
+        .ok_or_else(|| ApiError::not_found(format!("chat {chat_id} has no invite link {id}")))
+}
+
+#[utoipa::path(
+    get,
+    path = "/chats/{chat_id}/joinPolicy",
+    params(("chat_id" = i64, Path, description = "Telegram chat id")),
+    responses(
+        (status = 200, description = "What happens to requests to join the chat", body = ChatJoinPolicy),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn join_policy(
+    Extension(state): Extension<AppState>,
+    Path(chat_id): Path<i64>,
+    access: Access,
+) -> Result<Json<ChatJoinPolicy>, ApiError> {
+    access.chat(chat_id)?;
+    known_chat(&state, chat_id)?;
+
+    Ok(Json(ChatJoinPolicy {
+        policy: state.get_join_policy(chat_id).await?,
+    }))
+}
+
+#[utoipa::path(
+    put,
+    path = "/chats/{chat_id}/joinPolicy",
+    params(("chat_id" = i64, Path, description = "Telegram chat id")),
+    request_body = ChatJoinPolicy,
+    responses(
+        (status = 200, description = "New join requests follow the policy", body = ChatJoinPolicy),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn set_join_policy(
+    Extension(state): Extension<AppState>,
+    Path(chat_id): Path<i64>,
+    access: Access,
+    Json(payload): Json<ChatJoinPolicy>,
+) -> Result<Json<ChatJoinPolicy>, ApiError> {
+    access.require(Permission::Manage)?;
+    access.chat(chat_id)?;
+    known_chat(&state, chat_id)?;
+
+    state.set_join_policy(chat_id, payload.policy).await?;
+
+    Ok(Json(payload))
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct JoinRequestsQuery {
+    status: Option<JoinRequestStatus>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/chats/{chat_id}/joinRequests",
+    params(("chat_id" = i64, Path, description = "Telegram chat id"), JoinRequestsQuery),
+    responses(
+        (status = 200, description = "Requests to join the chat, newest first", body = [JoinRequest]),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn join_requests(
+    Extension(state): Extension<AppState>,
+    Path(chat_id): Path<i64>,
+    access: Access,
+    Query(query): Query<JoinRequestsQuery>,
+) -> Result<Json<Vec<JoinRequest>>, ApiError> {
+    access.chat(chat_id)?;
+    known_chat(&state, chat_id)?;
+
+    Ok(Json(state.get_join_requests(chat_id, query.status).await?))
+}
+
+async fn decide_join_request(
+    state: AppState,
+    chat_id: i64,
+    id: i32,
+    access: Access,
+    approve: bool,
+) -> Result<Json<JoinRequest>, ApiError> {
+    access.require(Permission::Manage)?;
+    access.chat(chat_id)?;
+    let request = state
+        .get_join_request(id)
+        .await?
+        .filter(|request| request.chat_id == chat_id)
+        .ok_or_else(|| ApiError::not_found(format!("chat {chat_id} has no join request {id}")))?;
+
+    match state.decide_join_request(&request, approve, "api").await? {
+        Some(decided) => Ok(Json(decided)),
+        None => Err(ApiError::new(
+            StatusCode::CONFLICT,
+            "already_decided",
+            format!("join request {id} was {} already", request.status),
+        )),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/chats/{chat_id}/joinRequests/{id}/approve",
+    params(
+        ("chat_id" = i64, Path, description = "Telegram chat id"),
+        ("id" = i32, Path, description = "Join request id"),
+    ),
+    responses(
+        (status = 200, description = "The user joined the chat", body = JoinRequest),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 409, description = "The request was decided already", body = ErrorBody),
+        (status = 502, description = "Telegram refused the decision", body = ErrorBody),
+    )
+)]
+async fn approve_join_request(
+    Extension(state): Extension<AppState>,
+    Path((chat_id, id)): Path<(i64, i32)>,
+    access: Access,
+) -> Result<Json<JoinRequest>, ApiError> {
+    decide_join_request(state, chat_id, id, access, true).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/chats/{chat_id}/joinRequests/{id}/decline",
+    params(
+        ("chat_id" = i64, Path, description = "Telegram chat id"),
+        ("id" = i32, Path, description = "Join request id"),
+    ),
+    responses(
+        (status = 200, description = "The request was declined", body = JoinRequest),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 409, description = "The request was decided already", body = ErrorBody),
+        (status = 502, description = "Telegram refused the decision", body = ErrorBody),
+    )
+)]
+async fn decline_join_request(
+    Extension(state): Extension<AppState>,
+    Path((chat_id, id)): Path<(i64, i32)>,
+    access: Access,
+) -> Result<Json<JoinRequest>, ApiError> {
+    decide_join_request(state, chat_id, id, access, false).await
+}
+
+async fn set_chat_muted(state: AppState, chat_id: i64, muted: bool) -> Result<(), ApiError> {
+    if !state.set_chat_muted(chat_id, muted).await? {
+        return Err(ApiError::not_found(format!("chat {chat_id} not found")));
+    }
+
+    Ok(())
+}
+
+#[utoipa::path(
+    get,
+    path = "/exclusions",
+    responses(
+        (status = 200, description = "Users cleanups never kick", body = [Exclusion]),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn exclusions(
+    Extension(state): Extension<AppState>,
+    access: Access,
+) -> Result<Json<Vec<Exclusion>>, ApiError> {
+    let mut exclusions = state.get_exclusions().await?;
+    exclusions.retain(|exclusion| {
+        exclusion
+            .chat_id
+            .is_none_or(|chat_id| access.allows_chat(chat_id))
+    });
+    Ok(Json(exclusions))
+}
+
+#[utoipa::path(
+    post,
+    path = "/exclusions",
+    request_body = NewExclusion,
+    responses(
+        (status = 200, description = "The user is no longer kicked by cleanups", body = Exclusion),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn add_exclusion(
+    Extension(state): Extension<AppState>,
+    access: Access,
+    Json(payload): Json<NewExclusion>,
+) -> Result<Json<Exclusion>, ApiError> {
+    access.require(Permission::Cleanup)?;
+    match payload.chat_id {
+        Some(chat_id) => {
+            access.chat(chat_id)?;
+            known_chat(&state, chat_id)?;
+        }
+        None => access.all_chats()?,
+    }
+
+    Ok(Json(state.add_exclusion(&payload).await?))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/exclusions/{id}",
+    params(("id" = i32, Path, description = "Exclusion id")),
+    responses(
+        (status = 200, description = "Cleanups may kick the user again"),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn delete_exclusion(
+    Extension(state): Extension<AppState>,
+    access: Access,
+    Path(id): Path<i32>,
+) -> Result<(), ApiError> {
+    access.require(Permission::Cleanup)?;
+    let exclusion = state
+        .get_exclusions()
+        .await?
+        .into_iter()
+        .find(|exclusion| exclusion.id == id)
+        .ok_or_else(|| ApiError::not_found(format!("exclusion {id} not found")))?;
+    match exclusion.chat_id {
+        Some(chat_id) => access.chat(chat_id)?,
+        None => access.all_chats()?,
+    }
+
+    state.delete_exclusion(id).await?;
+
+    Ok(())
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct StatusQuery {
+    /// Only chats in this state.
+    status: Option<CleanupState>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/status",
+    params(StatusQuery),
+    responses(
+        (status = 200, description = "Cleanup status, members and queued broadcasts of every chat", body = [ChatStatusSummary]),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn status(
+    Extension(state): Extension<AppState>,
+    access: Access,
+    Query(query): Query<StatusQuery>,
+) -> Result<Json<Vec<ChatStatusSummary>>, ApiError> {
+    let mut overview = state.get_status_overview(query.status).await?;
+    overview.retain(|summary| access.allows_chat(summary.chat_id));
+    Ok(Json(overview))
+}
+
+/// The status in its first, externally tagged shape, see
+/// `/v2/status/{chat_id}` for the current one.
+#[utoipa::path(
+    get,
+    path = "/status/{chat_id}",
+    params(("chat_id" = i64, Path, description = "Telegram chat id")),
+    responses(
+        (status = 200, description = "Cleaning status of the chat", body = ChatStatusV1),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn chat_status(
+    Extension(state): Extension<AppState>,
+    Path(chat_id): Path<i64>,
+    access: Access,
+) -> Result<Json<ChatStatusV1>, ApiError> {
+    access.chat(chat_id)?;
+    state
+        .get_chat_status(chat_id)
+        .await?
+        .map(|status| Json(status.into()))
+        .ok_or_else(|| ApiError::not_found(format!("chat {chat_id} not found")))
+}
+
+/// The status is `{"state": "error", "detail": "..."}`, with the states of
+/// the `/status` overview.
+#[utoipa::path(
+    get,
+    path = "/v2/status/{chat_id}",
+    params(("chat_id" = i64, Path, description = "Telegram chat id")),
+    responses(
+        (status = 200, description = "Cleaning status of the chat", body = ChatStatus),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn chat_status_v2(
+    Extension(state): Extension<AppState>,
+    Path(chat_id): Path<i64>,
+    access: Access,
+) -> Result<Json<ChatStatus>, ApiError> {
+    access.chat(chat_id)?;
+    state
+        .get_chat_status(chat_id)
+        .await?
+        .map(Json)
+        .ok_or_else(|| ApiError::not_found(format!("chat {chat_id} not found")))
+}
+
+/// Pushes status changes over a WebSocket. The client sends
+/// `{"action": "subscribe", "chats": [-100]}`, or `"chats": "all"`, and
+/// receives a `snapshot` of those chats followed by a `LiveEvent` for
+/// every change. `unsubscribe` takes the same chats.
+#[utoipa::path(
+    get,
+    path = "/status/live",
+    responses(
+        (status = 101, description = "Switching to the WebSocket protocol, changes are sent as LiveEvent"),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn live_status(
+    Extension(state): Extension<AppState>,
+    access: Access,
+    upgrade: WebSocketUpgrade,
+) -> Response {
+    upgrade.on_upgrade(move |socket| state.serve_live(access, socket))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/chats/{chat_id}",
+    params(("chat_id" = i64, Path, description = "Telegram chat id")),
+    responses(
+        (status = 200, description = "Chat was deleted"),
+        (status = 404, description = "The bot has no such chat", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn delete_chat(
+    Extension(state): Extension<AppState>,
+    Path(chat_id): Path<i64>,
+    access: Access,
+) -> Result<(), ApiError> {
+    access.require(Permission::Cleanup)?;
+    access.chat(chat_id)?;
+    if !state.delete_chat(chat_id).await? {
+        return Err(ApiError::not_found(format!("chat {chat_id} not found")));
+    }
+    Ok(())
+}
+
+/// Deprecated in favour of `DELETE /chats/{chat_id}`, only served while
+/// `legacy_get_routes` is on.
+#[utoipa::path(
+    get,
+    path = "/deleteChat/{chat_id}",
+    params(("chat_id" = i64, Path, description = "Telegram chat id")),
+    responses(
+        (status = 200, description = "Chat was deleted"),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn legacy_delete_chat(
+    state: Extension<AppState>,
+    chat_id: Path<i64>,
+    access: Access,
+) -> Result<(), ApiError> {
+    delete_chat(state, chat_id, access).await
+}
+
+/// Most chats a single bulk request can act on.
+const MAX_BULK_CHATS: usize = 1000;
+
+#[derive(Deserialize, ToSchema)]
+pub struct BulkChatsBody {
+    chats: Vec<i64>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct BulkMuteBody {
+    chats: Vec<i64>,
+    /// `false` unmutes the chats.
+    muted: bool,
+}
+
+/// Checks the chats of a bulk request, a key has to be allowed every one of
+/// them.
+fn check_bulk_chats(access: &Access, chats: &[i64]) -> Result<(), ApiError> {
+    if chats.is_empty() {
+        return Err(ApiError::bad_request("no chats given"));
+    }
+    if chats.len() > MAX_BULK_CHATS {
+        return Err(ApiError::bad_request(format!(
+            "at most {MAX_BULK_CHATS} chats can be given at once"
+        )));
+    }
+    for &chat_id in chats {
+        access.chat(chat_id)?;
+    }
+
+    Ok(())
+}
+
+/// Deletes several chats in one transaction.
+#[utoipa::path(
+    post,
+    path = "/chats/bulkDelete",
+    request_body = BulkChatsBody,
+    responses(
+        (status = 200, description = "Per-chat results, unknown chats are failed", body = [ChatResult]),
+        (status = 400, description = "Invalid payload", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn bulk_delete_chats(
+    Extension(state): Extension<AppState>,
+    access: Access,
+    Json(payload): Json<BulkChatsBody>,
+) -> Result<Json<Vec<ChatResult>>, ApiError> {
+    access.require(Permission::Cleanup)?;
+    check_bulk_chats(&access, &payload.chats)?;
+
+    Ok(Json(state.delete_chats(&payload.chats).await?))
+}
+
+/// Mutes or unmutes several chats in one transaction.
+#[utoipa::path(
+    post,
+    path = "/chats/bulkMute",
+    request_body = BulkMuteBody,
+    responses(
+        (status = 200, description = "Per-chat results, unknown chats are failed", body = [ChatResult]),
+        (status = 400, description = "Invalid payload", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn bulk_mute_chats(
+    Extension(state): Extension<AppState>,
+    access: Access,
+    Json(payload): Json<BulkMuteBody>,
+) -> Result<Json<Vec<ChatResult>>, ApiError> {
+    access.require(Permission::Manage)?;
+    check_bulk_chats(&access, &payload.chats)?;
+
+    Ok(Json(
+        state.set_chats_muted(&payload.chats, payload.muted).await?,
+    ))
+}
+
+/// A token that confirms clearing a chat, see [`clear_chat`].
+#[derive(Serialize, ToSchema)]
+pub struct ConfirmationToken {
+    token: String,
+    expires_at: DateTime<Utc>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct ClearChatBody {
+    /// Token from `POST /chats/{chat_id}/clear/token`.
+    confirmation: String,
+    /// Only restricts the members and records them as candidates,
+    /// `POST /chats/{chat_id}/clear/confirm` kicks them.
+    #[serde(default)]
+    stage: bool,
+}
+
+fn clear_action(chat_id: i64) -> String {
+    format!("clear:{chat_id}")
+}
+
+/// Hands out the token `POST /chats/{chat_id}/clear` has to be confirmed
+/// with. It is valid for five minutes and only for this chat.
+#[utoipa::path(
+    post,
+    path = "/chats/{chat_id}/clear/token",
+    params(("chat_id" = i64, Path, description = "Telegram chat id")),
+    responses(
+        (status = 200, description = "Token confirming the clear", body = ConfirmationToken),
+        (status = 404, description = "Not found", body = ErrorBody),
+    )
+)]
+async fn clear_chat_token(
+    Extension(state): Extension<AppState>,
+    Path(chat_id): Path<i64>,
+    access: Access,
+) -> Result<Json<ConfirmationToken>, ApiError> {
+    access.require(Permission::Cleanup)?;
+    access.chat(chat_id)?;
+    known_chat(&state, chat_id)?;
+
+    let (token, expires_at) = state.confirmation_token(&clear_action(chat_id));
+    Ok(Json(ConfirmationToken { token, expires_at }))
+}
+
+/// Kicks every member of the chat, or with `stage` restricts them until the
+/// cleanup is confirmed. Needs a fresh token from
+/// `POST /chats/{chat_id}/clear/token` so a replayed or mistaken request
+/// can't empty a chat.
+#[utoipa::path(
+    post,
+    path = "/chats/{chat_id}/clear",
+    params(("chat_id" = i64, Path, description = "Telegram chat id")),
+    request_body = ClearChatBody,
+    responses(
+        (status = 200, description = "All members were kicked or staged"),
+        (status = 400, description = "The confirmation token is invalid or expired", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn clear_chat(
+    Extension(state): Extension<AppState>,
+    Path(chat_id): Path<i64>,
+    access: Access,
+    headers: HeaderMap,
+    Json(payload): Json<ClearChatBody>,
+) -> Result<(), ApiError> {
+    access.require(Permission::Cleanup)?;
+    access.chat(chat_id)?;
+    if !state.verify_confirmation(&clear_action(chat_id), &payload.confirmation) {
+        return Err(ApiError::bad_request(
+            "the confirmation token is invalid or expired",
+        ));
+    }
+    let initiator = initiator(&state, &headers);
+    state.record_usage(access.actor(), Usage::cleanups(1)).await;
+    if payload.stage {
+        state.stage_all_members(chat_id, &initiator).await?;
+    } else {
+        state.delete_all_members(chat_id, &initiator).await?;
+    }
+    Ok(())
+}
+
+#[utoipa::path(
+    get,
+    path = "/chats/{chat_id}/clear/staged",
+    params(("chat_id" = i64, Path, description = "Telegram chat id")),
+    responses(
+        (status = 200, description = "Members a staged cleanup will kick", body = [CleanupCandidate]),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn cleanup_candidates(
+    Extension(state): Extension<AppState>,
+    Path(chat_id): Path<i64>,
+    access: Access,
+) -> Result<Json<Vec<CleanupCandidate>>, ApiError> {
+    access.chat(chat_id)?;
+    known_chat(&state, chat_id)?;
+
+    Ok(Json(state.get_cleanup_candidates(chat_id).await?))
+}
+
+fn nothing_staged(chat_id: i64) -> ApiError {
+    ApiError::new(
+        StatusCode::CONFLICT,
+        "nothing_staged",
+        format!("chat {chat_id} has no staged cleanup"),
+    )
+}
+
+/// Kicks the members staged by `POST /chats/{chat_id}/clear` with `stage`.
+/// Runs in the background like `/clearChats/`, its progress shows in the
+/// chat's status.
+#[utoipa::path(
+    post,
+    path = "/chats/{chat_id}/clear/confirm",
+    params(("chat_id" = i64, Path, description = "Telegram chat id")),
+    responses(
+        (status = 200, description = "The staged members are being kicked"),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 409, description = "Nothing is staged or a cleanup is already running", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn confirm_cleanup(
+    Extension(state): Extension<AppState>,
+    Path(chat_id): Path<i64>,
+    access: Access,
+    headers: HeaderMap,
+) -> Result<(), ApiError> {
+    access.require(Permission::Cleanup)?;
+    access.chat(chat_id)?;
+    known_chat(&state, chat_id)?;
+    if state.get_cleanup_candidates(chat_id).await?.is_empty() {
+        return Err(nothing_staged(chat_id));
+    }
+    if !state.queue_cleanup(chat_id)? {
+        return Err(ApiError::new(
+            StatusCode::CONFLICT,
+            "cleanup_running",
+            format!("chat {chat_id} already has a queued or running cleanup or resync"),
+        ));
+    }
+
+    state.record_usage(access.actor(), Usage::cleanups(1)).await;
+    let initiator = initiator(&state, &headers);
+    spawn_in_request(async move { state.confirm_staged_cleanup(chat_id, &initiator).await });
+
+    Ok(())
+}
+
+/// Drops a staged cleanup, the candidates get the chat's default rights
+/// back.
+#[utoipa::path(
+    delete,
+    path = "/chats/{chat_id}/clear/staged",
+    params(("chat_id" = i64, Path, description = "Telegram chat id")),
+    responses(
+        (status = 200, description = "The staged members were restored"),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 409, description = "Nothing is staged", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn discard_cleanup(
+    Extension(state): Extension<AppState>,
+    Path(chat_id): Path<i64>,
+    access: Access,
+) -> Result<(), ApiError> {
+    access.require(Permission::Cleanup)?;
+    access.chat(chat_id)?;
+    known_chat(&state, chat_id)?;
+    if state.discard_staged_cleanup(chat_id).await? == 0 {
+        return Err(nothing_staged(chat_id));
+    }
+
+    Ok(())
+}
+
+/// Unbans everyone the kick log has for the chat, to undo a cleanup that
+/// kicked the wrong people.
+#[utoipa::path(
+    post,
+    path = "/chats/{chat_id}/unbanAll",
+    params(("chat_id" = i64, Path, description = "Telegram chat id")),
+    responses(
+        (status = 200, description = "Kicked users were unbanned", body = UnbanResult),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn unban_all(
+    Extension(state): Extension<AppState>,
+    Path(chat_id): Path<i64>,
+    access: Access,
+) -> Result<Json<UnbanResult>, ApiError> {
+    access.require(Permission::Cleanup)?;
+    access.chat(chat_id)?;
+    known_chat(&state, chat_id)?;
+
+    Ok(Json(state.unban_all(chat_id).await?))
+}
+
+/// Deprecated in favour of `POST /chats/{chat_id}/clear`, only served while
+/// `legacy_get_routes` is on.
+#[utoipa::path(
+    get,
+    path = "/clearChat/{chat_id}",
+    params(("chat_id" = i64, Path, description = "Telegram chat id")),
+    responses(
+        (status = 200, description = "All members were kicked"),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn legacy_clear_chat(
+    Extension(state): Extension<AppState>,
+    Path(chat_id): Path<i64>,
+    access: Access,
+    headers: HeaderMap,
+) -> Result<(), ApiError> {
+    access.require(Permission::Cleanup)?;
+    access.chat(chat_id)?;
+    state.record_usage(access.actor(), Usage::cleanups(1)).await;
+    state
+        .delete_all_members(chat_id, &initiator(&state, &headers))
+        .await?;
+    Ok(())
+}
+
+/// Marks the responses of deprecated routes with a `Deprecation` header.
+async fn deprecated<B>(req: Request<B>, next: Next<B>) -> Response {
+    warn!("deprecated endpoint {} was called", req.uri().path());
+    let mut response = next.run(req).await;
+    response
+        .headers_mut()
+        .insert("deprecation", HeaderValue::from_static("true"));
+    response
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct ClearChatsBody {
+    chats: Vec<i64>,
+    /// Stages the cleanups like `POST /chats/{chat_id}/clear` does.
+    #[serde(default)]
+    stage: bool,
+}
+
+#[utoipa::path(
+    post,
+    path = "/clearChats/",
+    request_body = ClearChatsBody,
+    responses(
+        (status = 200, description = "Cleanups of the known chats were started", body = ClearChatsResult),
+    )
+)]
+async fn clear_chats(
+    Extension(state): Extension<AppState>,
+    access: Access,
+    headers: HeaderMap,
+    Json(payload): Json<ClearChatsBody>,
+) -> Result<Json<ClearChatsResult>, ApiError> {
+    access.require(Permission::Cleanup)?;
+    access.chats(payload.chats.iter().copied())?;
+    let initiator = initiator(&state, &headers);
+    let result = state.queue_cleanups(payload.chats);
+    let queued = result.queued.clone();
+    state
+        .record_usage(access.actor(), Usage::cleanups(queued.len()))
+        .await;
+    let stage = payload.stage;
+    // the cleanup outlives the request but keeps logging under its span
+    spawn_in_request(async move { state.clear_chats(queued, &initiator, stage).await });
+    Ok(Json(result))
+}
+
+#[utoipa::path(
+    post,
+    path = "/clearChats/{chat_id}/cancel",
+    params(("chat_id" = i64, Path, description = "Telegram chat id")),
+    responses(
+        (status = 200, description = "The cleanup will stop before the next member"),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 409, description = "No cleanup is running, or it runs on another instance", body = ErrorBody),
+    )
+)]
+async fn cancel_cleanup(
+    Extension(state): Extension<AppState>,
+    Path(chat_id): Path<i64>,
+    access: Access,
+) -> Result<(), ApiError> {
+    access.require(Permission::Cleanup)?;
+    access.chat(chat_id)?;
+    known_chat(&state, chat_id)?;
+
+    if !state.cancel_cleanup(chat_id)? {
+        return Err(ApiError::new(
+            StatusCode::CONFLICT,
+            "no_cleanup_running",
+            format!("chat {chat_id} has no queued or running cleanup"),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Runs a cleanup the process stopped in again, staged or not like it was.
+/// Members kicked before are not asked about again.
+#[utoipa::path(
+    post,
+    path = "/chats/{chat_id}/resume",
+    params(("chat_id" = i64, Path, description = "Telegram chat id")),
+    responses(
+        (status = 202, description = "The cleanup is queued again"),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 409, description = "The chat has no interrupted cleanup", body = ErrorBody),
+    )
+)]
+async fn resume_cleanup(
+    Extension(state): Extension<AppState>,
+    Path(chat_id): Path<i64>,
+    access: Access,
+    headers: HeaderMap,
+) -> Result<StatusCode, ApiError> {
+    access.require(Permission::Cleanup)?;
+    access.chat(chat_id)?;
+    known_chat(&state, chat_id)?;
+
+    if !state.resume_cleanup(chat_id, &initiator(&state, &headers))? {
+        return Err(ApiError::new(
+            StatusCode::CONFLICT,
+            "no_cleanup_interrupted",
+            format!("chat {chat_id} has no interrupted cleanup"),
+        ));
+    }
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct PurgeStarted {
+    /// Number of members matching the filter that will be kicked.
+    matched: usize,
+}
+
+#[utoipa::path(
+    post,
+    path = "/chats/{chat_id}/purge",
+    params(("chat_id" = i64, Path, description = "Telegram chat id")),
+    request_body = PurgeFilter,
+    responses(
+        (status = 200, description = "Purge was started", body = PurgeStarted),
+        (status = 400, description = "Invalid filter", body = ErrorBody),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 409, description = "A cleanup is already running", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn purge(
+    Extension(state): Extension<AppState>,
+    Path(chat_id): Path<i64>,
+    access: Access,
+    headers: HeaderMap,
+    Json(payload): Json<PurgeFilter>,
+) -> Result<Json<PurgeStarted>, ApiError> {
+    access.require(Permission::Cleanup)?;
+    access.chat(chat_id)?;
+    known_chat(&state, chat_id)?;
+    payload.validate().map_err(ApiError::bad_request)?;
+
+    let members = state.find_purge_targets(chat_id, &payload).await?;
+    if !state.queue_cleanup(chat_id)? {
+        return Err(ApiError::new(
+            StatusCode::CONFLICT,
+            "cleanup_running",
+            format!("chat {chat_id} already has a queued or running cleanup"),
+        ));
+    }
+
+    let matched = members.len();
+    let initiator = initiator(&state, &headers);
+    state.record_usage(access.actor(), Usage::cleanups(1)).await;
+    spawn_in_request(async move {
+        state
+            .purge_members(chat_id, members, "purge", &initiator)
+            .await
+    });
+
+    Ok(Json(PurgeStarted { matched }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/chats/{chat_id}/purgeDeleted",
+    params(("chat_id" = i64, Path, description = "Telegram chat id")),
+    responses(
+        (status = 200, description = "Deleted accounts are being looked up and kicked"),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 409, description = "A cleanup is already running", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn purge_deleted(
+    Extension(state): Extension<AppState>,
+    Path(chat_id): Path<i64>,
+    access: Access,
+    headers: HeaderMap,
+) -> Result<(), ApiError> {
+    access.require(Permission::Cleanup)?;
+    access.chat(chat_id)?;
+    known_chat(&state, chat_id)?;
+    if !state.queue_cleanup(chat_id)? {
+        return Err(ApiError::new(
+            StatusCode::CONFLICT,
+            "cleanup_running",
+            format!("chat {chat_id} already has a queued or running cleanup"),
+        ));
+    }
+
+    state.record_usage(access.actor(), Usage::cleanups(1)).await;
+    let initiator = initiator(&state, &headers);
+    spawn_in_request(async move { state.purge_deleted_accounts(chat_id, &initiator).await });
+
+    Ok(())
+}
+
+/// Checks every stored member against telegram in the background, the
+/// progress shows in the chat's status as `Resyncing`. Cancelled like a
+/// cleanup.
+#[utoipa::path(
+    post,
+    path = "/chats/{chat_id}/resync",
+    params(("chat_id" = i64, Path, description = "Telegram chat id")),
+    responses(
+        (status = 200, description = "Members are being checked"),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 409, description = "A cleanup or resync is already running", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn resync_chat(
+    Extension(state): Extension<AppState>,
+    Path(chat_id): Path<i64>,
+    access: Access,
+) -> Result<(), ApiError> {
+    access.require(Permission::Cleanup)?;
+    access.chat(chat_id)?;
+    known_chat(&state, chat_id)?;
+    if !state.start_resync(chat_id)? {
+        return Err(ApiError::new(
+            StatusCode::CONFLICT,
+            "cleanup_running",
+            format!("chat {chat_id} already has a queued or running cleanup or resync"),
+        ));
+    }
+
+    spawn_in_request(async move { state.resync_members(chat_id).await });
+
+    Ok(())
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct MemberImportQuery {
+    /// Look every member up in the chat and keep only those still in it.
+    #[serde(default)]
+    verify: bool,
+}
+
+/// Stores members the bot didn't see yet, so cleanups include them. Members
+/// already stored keep what the bot knows about them.
+#[utoipa::path(
+    post,
+    path = "/chats/{chat_id}/members/import",
+    params(("chat_id" = i64, Path, description = "Telegram chat id"), MemberImportQuery),
+    request_body = [SeededMember],
+    responses(
+        (status = 200, description = "What was imported", body = MemberImportReport),
+        (status = 400, description = "Too many members", body = ErrorBody),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn import_members(
+    Extension(state): Extension<AppState>,
+    Path(chat_id): Path<i64>,
+    access: Access,
+    Query(query): Query<MemberImportQuery>,
+    Json(members): Json<Vec<SeededMember>>,
+) -> Result<Json<MemberImportReport>, ApiError> {
+    access.require(Permission::Manage)?;
+    access.chat(chat_id)?;
+    known_chat(&state, chat_id)?;
+    if members.len() > MAX_SEEDED_MEMBERS {
+        return Err(ApiError::bad_request(format!(
+            "at most {MAX_SEEDED_MEMBERS} members can be imported at once"
+        )));
+    }
+
+    let report = state.import_members(chat_id, members, query.verify).await?;
+
+    Ok(Json(report))
+}
+
+/// The chats a message is sent to, shared by the send payloads.
+#[derive(Default, Deserialize, ToSchema)]
+pub struct SendTargets {
+    /// Chat ids, or `{chat_id, thread_id}` objects to post into a forum
+    /// topic.
+    #[serde(default)]
+    chats: Vec<ChatTarget>,
+    /// Sends to every chat that is not muted at send time instead of
+    /// `chats`, so chats added after queueing receive it too.
+    #[serde(default)]
+    all_chats: bool,
+    /// Named chat groups from the configuration, sent to besides `chats`.
+    #[serde(default)]
+    chat_groups: Vec<String>,
+    /// Chats carrying any of these labels when the message is queued, sent
+    /// to besides `chats`.
+    #[serde(default)]
+    labels: Vec<String>,
+    /// Chats left out, also of `all_chats`, the chat groups and the labels.
+    #[serde(default)]
+    exclude_chats: Vec<i64>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct SendMessageBody {
+    #[serde(flatten)]
+    targets: SendTargets,
+    /// Can be left empty when a sticker or a location is sent.
+    #[serde(default)]
+    message: String,
+    /// Texts for chats with these languages, keyed by language code like
+    /// `{"en": "...", "ru": "..."}`. Other chats receive `message`.
+    #[serde(default)]
+    translations: Translations,
+    #[serde(flatten)]
+    attachments: Attachments,
+    /// Base64 encoded images, `data:` URLs or https URLs to download the
+    /// image from when the message is sent.
+    images: Vec<String>,
+    /// Can be left out when `datetime_local` is given.
+    #[serde(default)]
+    datetime: String,
+    /// Queues the message even if the same text went to these chats within
+    /// the duplicate window.
+    #[serde(default)]
+    force: bool,
+    /// Converts `message` and the translations from HTML or CommonMark to
+    /// `parse_mode` first, like `/convert`.
+    convert_from: Option<SourceFormat>,
+    #[serde(flatten)]
+    options: MessageOptions,
+}
+
+impl SendMessageBody {
+    /// Rewrites the texts into `parse_mode` when `convert_from` is set, see
+    /// [`convert_texts`].
+    fn convert(&mut self) -> Result<(), ApiError> {
+        let texts = message_texts(&mut self.message, &mut self.translations);
+        convert_texts(self.convert_from, self.options.parse_mode, texts)
+    }
+}
+
+/// `message` and the translations of a payload with their field names.
+fn message_texts<'a>(
+    message: &'a mut String,
+    translations: &'a mut Translations,
+) -> Vec<(String, &'a mut String)> {
+    std::iter::once(("message".to_string(), message))
+        .chain(
+            translations
+                .iter_mut()
+                .map(|(language, text)| (format!("translations.{language}"), text)),
+        )
+        .collect()
+}
+
+/// Rewrites the texts of a payload from `from` into `to` when `from` is
+/// set, the texts that can't be converted are refused as invalid fields.
+fn convert_texts<'a>(
+    from: Option<SourceFormat>,
+    to: MessageFormat,
+    texts: impl IntoIterator<Item = (String, &'a mut String)>,
+) -> Result<(), ApiError> {
+    let Some(from) = from else {
+        return Ok(());
+    };
+    let mut validator = Validator::default();
+    for (field, text) in texts {
+        match convert(text, from, to) {
+            Ok(converted) => *text = converted,
+            Err(err) => validator.error(field, err.to_string()),
+        }
+    }
+
+    validator.finish()
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SendMessageQuery {
+    /// Only send to the configured preview chat and hold the message until
+    /// it is approved.
+    #[serde(default)]
+    preview: bool,
+    /// Preview in this chat instead of the configured one.
+    preview_chat: Option<i64>,
+}
+
+impl SendMessageQuery {
+    fn preview_chat(&self, state: &AppState) -> Result<Option<i64>, ApiError> {
+        match (self.preview_chat, self.preview) {
+            (Some(chat_id), _) => Ok(Some(chat_id)),
+            (None, true) => state
+                .config
+                .preview_chat
+                .map(Some)
+                .ok_or_else(|| ApiError::bad_request("no preview chat is configured")),
+            (None, false) => Ok(None),
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/sendMessage/",
+    params(SendMessageQuery),
+    request_body = SendMessageBody,
+    responses(
+        (status = 200, description = "Message was queued", body = QueuedMessageId),
+        (status = 400, description = "Invalid payload", body = ErrorBody),
+        (status = 409, description = "The same text was queued for these chats within the duplicate window", body = ErrorBody),
+        (status = 413, description = "The body is larger than the configured limit", body = ErrorBody),
+        (status = 422, description = "Invalid fields, listed in `errors`", body = ErrorBody),
+        (status = 429, description = "Too many messages are waiting in the queue", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn send_message_to_chat(
+    Extension(state): Extension<AppState>,
+    access: Access,
+    Query(query): Query<SendMessageQuery>,
+    Json(payload): Json<SendMessageBody>,
+) -> Result<Json<QueuedMessageId>, ApiError> {
+    let preview_chat = query.preview_chat(&state)?;
+    let id = queue_message(&state, &access, payload, preview_chat).await?;
+
+    if let Some(chat_id) = preview_chat {
+        send_preview(&state, id, chat_id).await?;
+    }
+
+    Ok(Json(QueuedMessageId { id }))
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct TestSent {
+    /// The configured sandbox chat the message went to.
+    chat_id: i64,
+}
+
+/// Sends a `sendMessage` payload right away to the configured sandbox chat
+/// only, whatever its targets are, to try the whole send path without
+/// reaching any real chat. Nothing is left in the queue.
+#[utoipa::path(
+    post,
+    path = "/testSend",
+    request_body = SendMessageBody,
+    responses(
+        (status = 200, description = "Message was sent to the sandbox chat", body = TestSent),
+        (status = 400, description = "Invalid payload or no sandbox chat is configured", body = ErrorBody),
+        (status = 422, description = "Invalid fields, listed in `errors`", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn test_send(
+    Extension(state): Extension<AppState>,
+    access: Access,
+    Json(mut payload): Json<SendMessageBody>,
+) -> Result<Json<TestSent>, ApiError> {
+    let chat_id = state
+        .config
+        .sandbox_chat
+        .ok_or_else(|| ApiError::bad_request("no sandbox chat is configured"))?;
+    payload.targets = SendTargets {
+        chats: vec![ChatTarget::Chat(chat_id)],
+        ..Default::default()
+    };
+    payload.datetime = Utc::now().to_rfc3339();
+    payload.options.audience = Audience::Chats;
+    payload.options.datetime_local = None;
+    // replies and self-destruction need a real broadcast to refer to
+    payload.options.reply_to = None;
+    payload.options.delete_after_seconds = None;
+
+    // held like a preview so the queue never picks it up
+    let id = queue_message(&state, &access, payload, Some(chat_id)).await?;
+    send_preview(&state, id, chat_id).await?;
+    state.delete_queued_message(id).await?;
+
+    Ok(Json(TestSent { chat_id }))
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct ConvertBody {
+    text: String,
+    #[serde(default)]
+    from: SourceFormat,
+    /// The parse mode the text is going to be sent with.
+    #[serde(default)]
+    to: MessageFormat,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ConvertedText {
+    text: String,
+    /// The format the text was read as, never `auto`.
+    from: SourceFormat,
+    to: MessageFormat,
+}
+
+/// Converts rich text pasted from an editor, HTML or CommonMark, to the
+/// markup of a parse mode. Constructs telegram has no formatting for are
+/// refused with what to do about them.
+#[utoipa::path(
+    post,
+    path = "/convert",
+    request_body = ConvertBody,
+    responses(
+        (status = 200, description = "The text in the markup of the parse mode", body = ConvertedText),
+        (status = 400, description = "The text can't be converted", body = ErrorBody),
+    )
+)]
+async fn convert_text(
+    access: Access,
+    Json(payload): Json<ConvertBody>,
+) -> Result<Json<ConvertedText>, ApiError> {
+    access.require(Permission::Send)?;
+    let from = payload.from.detect(&payload.text);
+    let text = convert(&payload.text, from, payload.to).map_err(ApiError::bad_request)?;
+
+    Ok(Json(ConvertedText {
+        text,
+        from,
+        to: payload.to,
+    }))
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct RenderedMessage {
+    /// The text exactly as telegram receives it, broadcasts have no
+    /// placeholders to fill in.
+    message: String,
+    /// The messages the text is sent as in order, texts too long for one
+    /// message are split.
+    parts: Vec<String>,
+    parse_mode: MessageFormat,
+    /// Chats that would receive the message if it was sent now. The whole
+    /// audience is only settled once the message is due.
+    chats: Vec<i64>,
+    /// Targets that would be left out, with the error recorded for them.
+    skipped: Vec<ChatResult>,
+    /// Everything `sendMessage` would refuse the message for, empty when it
+    /// can be queued.
+    errors: Vec<FieldError>,
+    /// Time sending takes once the message is due, broadcasts queued before
+    /// it are only accounted for by `/estimate`.
+    estimated_duration_secs: u64,
+}
+
+/// Shows what a `sendMessage` payload would be sent as and to which chats,
+/// without queueing anything.
+#[utoipa::path(
+    post,
+    path = "/render",
+    request_body = SendMessageBody,
+    responses(
+        (status = 200, description = "The message as it would be sent, with the problems found", body = RenderedMessage),
+        (status = 400, description = "Invalid payload", body = ErrorBody),
+        (status = 422, description = "A text can't be converted from `convert_from`", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn render(
+    Extension(state): Extension<AppState>,
+    access: Access,
+    Json(mut payload): Json<SendMessageBody>,
+) -> Result<Json<RenderedMessage>, ApiError> {
+    access.require(Permission::Send)?;
+    payload.convert()?;
+    let audience = payload.options.audience;
+    let targets = targets(&state, payload.targets, audience).await?;
+    let chats = targets.chats.as_deref();
+    access.targets(chats, audience)?;
+    let datetime = queue_datetime(&state, &targets, payload.datetime, &payload.options).await?;
+    let mut validator = validate_message(
+        chats,
+        &payload.message,
+        &payload.attachments,
+        payload.images.len(),
+        &datetime,
+        payload.options.parse_mode,
+    );
+    validator.translations(&payload.translations, payload.options.parse_mode);
+    validate_images(&mut validator, &payload.images, state.config.max_images);
+    if !payload.force {
+        if let Some(id) = state
+            .find_duplicate_broadcast(&payload.message, chats, audience)
+            .await?
+        {
+            validator.error("message", DuplicateBroadcast(id).to_string());
+        }
+    }
+
+    let recipients = state.get_recipients(&targets, audience).await?;
+    let parts = split_message(&payload.message, payload.options.parse_mode);
+    let duration = state
+        .estimate_broadcast_duration(
+            &recipients.chats,
+            requests_per_chat(
+                payload.images.len(),
+                parts.len() + payload.attachments.len(),
+                payload.options.pin,
+            ),
+        )
+        .await?;
+
+    Ok(Json(RenderedMessage {
+        message: payload.message,
+        parts,
+        parse_mode: payload.options.parse_mode,
+        chats: recipients.chats,
+        skipped: recipients.skipped,
+        errors: validator.into_errors(),
+        estimated_duration_secs: duration.as_secs_f64().ceil() as u64,
+    }))
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct EstimateQuery {
+    /// Comma separated chat ids like `-100,-200`, the whole audience when
+    /// left out.
+    chats: Option<String>,
+    #[serde(default)]
+    audience: Audience,
+    #[serde(default)]
+    images: usize,
+    /// Messages the text is sent as, `parts` of `/render`.
+    parts: Option<usize>,
+    #[serde(default)]
+    pin: bool,
+    /// When the broadcast would be due, now when left out.
+    datetime: Option<DateTime<Utc>>,
+}
+
+/// Estimates how long a broadcast takes under the throttle limits, the rate
+/// limit overrides of its chats and the broadcasts queued before it.
+#[utoipa::path(
+    get,
+    path = "/estimate",
+    params(EstimateQuery),
+    responses(
+        (status = 200, description = "Expected duration of the broadcast", body = BroadcastEstimate),
+        (status = 400, description = "Invalid chat ids", body = ErrorBody),
+        (status = 422, description = "Invalid fields, listed in `errors`", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn estimate(
+    Extension(state): Extension<AppState>,
+    access: Access,
+    Query(query): Query<EstimateQuery>,
+) -> Result<Json<BroadcastEstimate>, ApiError> {
+    let chats =
+        query
+            .chats
+            .as_deref()
+            .map(|chats| {
+                chats
+                    .split(',')
+                    .map(|chat_id| {
+                        chat_id.trim().parse().map(ChatTarget::Chat).map_err(|_| {
+                            ApiError::bad_request(format!("invalid chat id {chat_id:?}"))
+                        })
+                    })
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .transpose()?;
+    access.targets(chats.as_deref(), query.audience)?;
+    let parts = query.parts.unwrap_or(1);
+    let mut validator = Validator::default();
+    validator.image_count(query.images, state.config.max_images);
+    if !(1..=MAX_MESSAGE_PARTS).contains(&parts) {
+        validator.error(
+            "parts",
+            format!("a text is sent as 1-{MAX_MESSAGE_PARTS} parts"),
+        );
+    }
+    validator.finish()?;
+
+    let recipients = state
+        .get_recipients(&Targets::from(chats), query.audience)
+        .await?;
+    let estimate = state
+        .estimate_broadcast(
+            &recipients.chats,
+            requests_per_chat(query.images, parts, query.pin),
+            query.datetime.unwrap_or_else(Utc::now),
+        )
+        .await?;
+
+    Ok(Json(estimate))
+}
+
+/// Validates and queues a `sendMessage` payload, shared with sending
+/// drafts. Messages for a preview chat are held for approval.
+async fn queue_message(
+    state: &AppState,
+    access: &Access,
+    mut payload: SendMessageBody,
+    preview_chat: Option<i64>,
+) -> Result<i32, ApiError> {
+    access.require(Permission::Send)?;
+    payload.convert()?;
+    let targets = targets(state, payload.targets, payload.options.audience).await?;
+    access.targets(targets.chats.as_deref(), payload.options.audience)?;
+    let datetime = queue_datetime(state, &targets, payload.datetime, &payload.options).await?;
+    let mut validator = validate_message(
+        targets.chats.as_deref(),
+        &payload.message,
+        &payload.attachments,
+        payload.images.len(),
+        &datetime,
+        payload.options.parse_mode,
+    );
+    validator.translations(&payload.translations, payload.options.parse_mode);
+    validate_images(&mut validator, &payload.images, state.config.max_images);
+    validator.finish()?;
+    check_reply_to(state, access, &payload.options).await?;
+    check_delete_after(state, &payload.options)?;
+    access.chats(preview_chat)?;
+    let usage = state
+        .message_usage(&targets, payload.options.audience)
+        .await;
+    let id = state
+        .queue_message_with_images(
+            targets,
+            LocalizedMessage {
+                message: payload.message,
+                translations: payload.translations,
+                attachments: payload.attachments,
+            },
+            payload.images,
+            datetime,
+            payload.options,
+            queue_mode(preview_chat, payload.force),
+        )
+        .await?;
+    state.record_usage(access.actor(), usage).await;
+
+    Ok(id)
+}
+
+/// Checks images given as base64, `data:` URLs or https URLs.
+fn validate_images(validator: &mut Validator, images: &[String], max_images: usize) {
+    validator.image_count(images.len(), max_images);
+    for (i, image) in images.iter().enumerate() {
+        if is_remote_image(image) {
+            if let Err(err) = reqwest::Url::parse(image) {
+                validator.error(format!("images[{i}]"), format!("invalid URL: {err}"));
+            }
+            continue;
+        }
+        match decode_inline_image(image) {
+            Ok(data) => validator.image(i, &data),
+            Err(err) => validator.error(format!("images[{i}]"), err.to_string()),
+        }
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct SendVariantsBody {
+    #[serde(flatten)]
+    targets: SendTargets,
+    /// 2-10 versions of the message, each chat receives one of them.
+    variants: Vec<MessageVariant>,
+    /// Can be left out when `datetime_local` is given.
+    #[serde(default)]
+    datetime: String,
+    /// Converts the messages of the variants from HTML or CommonMark to
+    /// `parse_mode` first, like `/convert`.
+    convert_from: Option<SourceFormat>,
+    #[serde(flatten)]
+    options: MessageOptions,
+}
+
+/// Queues an A/B tested broadcast. Chats are split between the variants by
+/// their weights, the same chat always receives the same variant.
+#[utoipa::path(
+    post,
+    path = "/sendVariants/",
+    request_body = SendVariantsBody,
+    responses(
+        (status = 200, description = "Message was queued", body = QueuedMessageId),
+        (status = 400, description = "Invalid payload", body = ErrorBody),
+        (status = 413, description = "The body is larger than the configured limit", body = ErrorBody),
+        (status = 422, description = "Invalid fields, listed in `errors`", body = ErrorBody),
+        (status = 429, description = "Too many messages are waiting in the queue", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn send_variants(
+    Extension(state): Extension<AppState>,
+    access: Access,
+    Json(mut payload): Json<SendVariantsBody>,
+) -> Result<Json<QueuedMessageId>, ApiError> {
+    access.require(Permission::Send)?;
+    let texts = payload
+        .variants
+        .iter_mut()
+        .enumerate()
+        .map(|(i, variant)| (format!("variants[{i}].message"), &mut variant.message));
+    convert_texts(payload.convert_from, payload.options.parse_mode, texts)?;
+    let targets = targets(&state, payload.targets, payload.options.audience).await?;
+    access.targets(targets.chats.as_deref(), payload.options.audience)?;
+    let mut validator = Validator::default();
+    if targets.chats.as_ref().is_some_and(|chats| chats.is_empty()) {
+        validator.error(
+            "chats",
+            "no chats given, set all_chats to send to every chat",
+        );
+    }
+    if let Err(err) = validate_variants(&payload.variants) {
+        validator.error("variants", err.to_string());
+    }
+    for (i, variant) in payload.variants.iter().enumerate() {
+        let mut checks = Validator::default();
+        checks.content(
+            &variant.message,
+            &Attachments::default(),
+            variant.images.len(),
+            payload.options.parse_mode,
+        );
+        validate_images(&mut checks, &variant.images, state.config.max_images);
+        validator.nested(&format!("variants[{i}]"), checks);
+    }
+    let datetime = queue_datetime(&state, &targets, payload.datetime, &payload.options).await?;
+    validator.datetime(&datetime);
+    validator.finish()?;
+    check_reply_to(&state, &access, &payload.options).await?;
+    check_delete_after(&state, &payload.options)?;
+
+    let usage = state
+        .message_usage(&targets, payload.options.audience)
+        .await;
+    let id = state
+        .queue_variants(targets, payload.variants, datetime, payload.options)
+        .await?;
+    state.record_usage(access.actor(), usage).await;
+
+    Ok(Json(QueuedMessageId { id }))
+}
+
+/// A message scheduled in local time without a `datetime` is due when the
+/// first of its chats reaches that time.
+async fn queue_datetime(
+    state: &AppState,
+    targets: &Targets,
+    datetime: String,
+    options: &MessageOptions,
+) -> Result<String, ApiError> {
+    match options.datetime_local {
+        Some(local) if datetime.is_empty() => Ok(state
+            .earliest_local_due(local, targets.chats.as_deref())
+            .await?
+            .to_rfc3339()),
+        _ => Ok(datetime),
+    }
+}
+
+/// Resolves the chat groups and labels into chats and leaves out the
+/// excluded chats. `None` chats stand for the whole audience, every chat
+/// unless the message goes to the subscribers, which is filtered when the
+/// message is sent.
+async fn targets(
+    state: &AppState,
+    targets: SendTargets,
+    audience: Audience,
+) -> Result<Targets, ApiError> {
+    let SendTargets {
+        chats,
+        all_chats,
+        chat_groups,
+        labels,
+        exclude_chats,
+    } = targets;
+    if !chat_groups.is_empty() && (all_chats || audience == Audience::Subscribers) {
+        return Err(ApiError::bad_request(
+            "chat_groups can't be combined with all_chats or the subscribers audience",
+        ));
+    }
+    if !labels.is_empty() && (all_chats || audience == Audience::Subscribers) {
+        return Err(ApiError::bad_request(
+            "labels can't be combined with all_chats or the subscribers audience",
+        ));
+    }
+    let mut chats = chats;
+    let groups = if chat_groups.is_empty() {
+        Default::default()
+    } else {
+        state.get_chat_groups().await?
+    };
+    for group in &chat_groups {
+        let members = groups
+            .get(group)
+            .ok_or_else(|| ApiError::bad_request(format!("unknown chat group {group}")))?;
+        chats.extend(members.iter().copied().map(ChatTarget::Chat));
+    }
+    let labelled = state.get_labelled_chats(&labels).await?;
+    if !labels.is_empty() && labelled.is_empty() && chats.is_empty() {
+        return Err(ApiError::bad_request(format!(
+            "no chats carry the labels {}",
+            labels.join(", ")
+        )));
+    }
+    chats.extend(labelled.into_iter().map(ChatTarget::Chat));
+
+    let chats = match (all_chats, audience, chats.is_empty()) {
+        (_, Audience::Subscribers, false) => {
+            return Err(ApiError::bad_request(
+                "chats and the subscribers audience can't be combined",
+            ))
+        }
+        (true, _, false) => {
+            return Err(ApiError::bad_request(
+                "chats and all_chats can't be combined",
+            ))
+        }
+        (true, _, true) | (_, Audience::Subscribers, true) => None,
+        (false, Audience::Chats, _) => Some(chats),
+    };
+    let Some(mut chats) = chats else {
+        return Ok(Targets {
+            chats: None,
+            excluded: exclude_chats,
+        });
+    };
+
+    let mut seen = HashSet::new();
+    chats.retain(|chat| {
+        !exclude_chats.contains(&chat.chat_id()) && seen.insert((chat.chat_id(), chat.thread_id()))
+    });
+
+    Ok(Targets::from(Some(chats)))
+}
+
+/// Checks of a message to be queued shared by the JSON and multipart
+/// endpoints, the images are left to the caller.
+fn validate_message(
+    chats: Option<&[ChatTarget]>,
+    message: &str,
+    attachments: &Attachments,
+    images: usize,
+    datetime: &str,
+    parse_mode: MessageFormat,
+) -> Validator {
+    let mut validator = Validator::default();
+    if chats.is_some_and(|chats| chats.is_empty()) {
+        validator.error(
+            "chats",
+            "no chats given, set all_chats to send to every chat",
+        );
+    }
+    validator.content(message, attachments, images, parse_mode);
+    validator.attachments(attachments);
+    validator.datetime(datetime);
+
+    validator
+}
+
+/// Makes sure the broadcast a message replies to exists. It is looked up in
+/// the sent message history, which only postgres keeps.
+async fn check_reply_to(
+    state: &AppState,
+    access: &Access,
+    options: &MessageOptions,
+) -> Result<(), ApiError> {
+    let Some(id) = options.reply_to else {
+        return Ok(());
+    };
+    state.pg()?;
+    access.queued_message(state, id).await?;
+    if state.get_queued_message(id).await?.is_none() {
+        return Err(ApiError::not_found(format!(
+            "queued message {id} not found"
+        )));
+    }
+
+    Ok(())
+}
+
+/// Self-destructing broadcasts are deleted through the sent message
+/// history, which only postgres keeps.
+fn check_delete_after(state: &AppState, options: &MessageOptions) -> Result<(), ApiError> {
+    let Some(seconds) = options.delete_after_seconds else {
+        return Ok(());
+    };
+    if !(1..=MAX_DELETE_AFTER_SECONDS).contains(&seconds) {
+        return Err(ApiError::bad_request(format!(
+            "delete_after_seconds must be between 1 and {MAX_DELETE_AFTER_SECONDS}"
+        )));
+    }
+    state.pg()?;
+
+    Ok(())
+}
+
+/// Guards against double announcements from clients retrying or clicking
+/// twice, the caller can still insist with `force`.
+fn queue_mode(preview_chat: Option<i64>, force: bool) -> QueueMode {
+    match (preview_chat, force) {
+        (Some(_), _) => QueueMode::AwaitApproval,
+        (None, true) => QueueMode::Send,
+        (None, false) => QueueMode::SendOnce,
+    }
+}
+
+/// Sends the held message to the preview chat, dropping it again when that
+/// fails so no unapprovable message is left behind.
+async fn send_preview(state: &AppState, id: i32, chat_id: i64) -> Result<(), ApiError> {
+    let message = state
+        .get_queued_message(id)
+        .await?
+        .ok_or_else(|| ApiError::not_found(format!("queued message {id} not found")))?;
+
+    if let Err(err) = state.send_preview(&message, chat_id).await {
+        state.delete_queued_message(id).await?;
+        return Err(err.into());
+    }
+
+    Ok(())
+}
+
+#[utoipa::path(
+    post,
+    path = "/queue/{id}/approve",
+    params(("id" = i32, Path, description = "Queued message id")),
+    responses(
+        (status = 200, description = "Message is released to the queue"),
+        (status = 404, description = "No message with this id is waiting for approval", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn approve_queued_message(
+    Extension(state): Extension<AppState>,
+    Path(id): Path<i32>,
+    access: Access,
+) -> Result<(), ApiError> {
+    access.require(Permission::Send)?;
+    access.queued_message(&state, id).await?;
+    if !state.approve_queued_message(id).await? {
+        return Err(ApiError::not_found(format!(
+            "queued message {id} is not waiting for approval"
+        )));
+    }
+
+    Ok(())
+}
+
+/// Changes the text or time of a message before it goes out to any chat,
+/// the replaced version is kept in its history.
+#[utoipa::path(
+    patch,
+    path = "/queue/{id}",
+    params(("id" = i32, Path, description = "Queued message id")),
+    request_body = QueuedMessageEdit,
+    responses(
+        (status = 200, description = "Message was changed"),
+        (status = 400, description = "Invalid payload", body = ErrorBody),
+        (status = 403, description = "Forbidden", body = ErrorBody),
+        (status = 404, description = "No message with this id is still waiting to be sent", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn edit_queued_message(
+    Extension(state): Extension<AppState>,
+    Path(id): Path<i32>,
+    access: Access,
+    Json(payload): Json<QueuedMessageEdit>,
+) -> Result<(), ApiError> {
+    access.require(Permission::Send)?;
+    access.queued_message(&state, id).await?;
+    let message = state
+        .get_queued_message(id)
+        .await?
+        .ok_or_else(|| ApiError::not_found(format!("queued message {id} not found")))?;
+    if let Some(text) = &payload.message {
+        message
+            .options()?
+            .parse_mode
+            .validate(text)
+            .map_err(ApiError::bad_request)?;
+    }
+
+    if !state
+        .edit_queued_message(id, &payload, access.actor())
+        .await?
+    {
+        return Err(ApiError::not_found(format!(
+            "queued message {id} is not waiting to be sent any more"
+        )));
+    }
+
+    Ok(())
+}
+
+/// The versions of a queued message its edits replaced, with who edited it.
+#[utoipa::path(
+    get,
+    path = "/queue/{id}/history",
+    params(("id" = i32, Path, description = "Queued message id")),
+    responses(
+        (status = 200, description = "Replaced versions, the latest first", body = [QueuedMessageVersion]),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn queue_history(
+    Extension(state): Extension<AppState>,
+    Path(id): Path<i32>,
+    access: Access,
+) -> Result<Json<Vec<QueuedMessageVersion>>, ApiError> {
+    access.queued_message(&state, id).await?;
+    state
+        .get_queued_message_history(id)
+        .await?
+        .map(Json)
+        .ok_or_else(|| ApiError::not_found(format!("queued message {id} not found")))
+}
+
+/// Releases the claim of a broadcast the process stopped in, the queue sends
+/// it to the chats it didn't reach yet. Only for a single instance, a claim
+/// of another instance that is still sending looks the same.
+#[utoipa::path(
+    post,
+    path = "/queue/{id}/resume",
+    params(("id" = i32, Path, description = "Queued message id")),
+    responses(
+        (status = 200, description = "Message is released to the queue"),
+        (status = 404, description = "No message with this id was interrupted", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn resume_queued_message(
+    Extension(state): Extension<AppState>,
+    Path(id): Path<i32>,
+    access: Access,
+) -> Result<(), ApiError> {
+    access.require(Permission::Send)?;
+    access.queued_message(&state, id).await?;
+    if !state.resume_broadcast(id).await? {
+        return Err(ApiError::not_found(format!(
+            "queued message {id} was not interrupted"
+        )));
+    }
+
+    Ok(())
+}
+
+/// Cleanups and broadcasts the process stopped in and that weren't resumed
+/// yet.
+#[utoipa::path(
+    get,
+    path = "/interrupted",
+    responses(
+        (status = 200, description = "Interrupted cleanups and broadcasts", body = Interrupted),
+        (status = 403, description = "Forbidden", body = ErrorBody),
+    )
+)]
+async fn interrupted(
+    Extension(state): Extension<AppState>,
+    access: Access,
+) -> Result<Json<Interrupted>, ApiError> {
+    access.all_chats()?;
+
+    Ok(Json(state.get_interrupted()))
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AuditQuery {
+    /// Only calls made at or after this time.
+    since: Option<DateTime<Utc>>,
+    /// Only calls made by this actor, like `telegram:@admin`.
+    actor: Option<String>,
+    /// Only the call with this `x-request-id`, like one found on a delivery
+    /// or kick.
+    request_id: Option<String>,
+    limit: Option<i64>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/audit",
+    params(AuditQuery),
+    responses(
+        (status = 200, description = "Mutating API calls, newest first", body = [AuditEntry]),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn audit_log(
+    Extension(state): Extension<AppState>,
+    access: Access,
+    Query(query): Query<AuditQuery>,
+) -> Result<Json<Vec<AuditEntry>>, ApiError> {
+    access.all_chats()?;
+    let limit = query.limit.unwrap_or(100).clamp(1, 1000);
+
+    let entries = state
+        .get_audit_log(
+            query.since,
+            query.actor.as_deref(),
+            query.request_id.as_deref(),
+            limit,
+        )
+        .await?;
+
+    Ok(Json(entries))
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct UsageQuery {
+    /// First day, 30 days before `to` when omitted.
+    from: Option<NaiveDate>,
+    /// Last day, today (UTC) when omitted.
+    to: Option<NaiveDate>,
+}
+
+/// Messages, their target chats and cleanups per API key or logged in
+/// admin and day, for attributing the use of the bot.
+#[utoipa::path(
+    get,
+    path = "/usage",
+    params(UsageQuery),
+    responses(
+        (status = 200, description = "Usage per actor and day, oldest first", body = [UsageEntry]),
+        (status = 400, description = "`from` is after `to`", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn usage(
+    Extension(state): Extension<AppState>,
+    access: Access,
+    Query(query): Query<UsageQuery>,
+) -> Result<Json<Vec<UsageEntry>>, ApiError> {
+    access.all_chats()?;
+    let to = query.to.unwrap_or_else(|| Utc::now().date_naive());
+    let from = query.from.unwrap_or(to - chrono::Duration::days(30));
+    if from > to {
+        return Err(ApiError::bad_request("from is after to"));
+    }
+
+    Ok(Json(state.get_usage(from, to).await?))
+}
+
+/// The report the alerts chat gets each week, over the 7 days up to now.
+#[utoipa::path(
+    get,
+    path = "/reports/weekly",
+    responses(
+        (status = 200, description = "Counts of the past 7 days", body = WeeklyReport),
+        (status = 403, description = "Forbidden", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn weekly_report(
+    Extension(state): Extension<AppState>,
+    access: Access,
+) -> Result<Json<WeeklyReport>, ApiError> {
+    access.all_chats()?;
+
+    Ok(Json(state.weekly_report(Utc::now()).await?))
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct QueueQuery {
+    status: Option<QueueStatus>,
+    /// Only messages scheduled before this RFC 3339 time.
+    due_before: Option<DateTime<Utc>>,
+    /// Only messages targeting the chat, broadcasts to all chats included.
+    /// API keys limited to some chats have to pass one of theirs.
+    chat_id: Option<i64>,
+    page: Option<i64>,
+    per_page: Option<i64>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/queue",
+    params(QueueQuery),
+    responses(
+        (status = 200, description = "Queued messages ordered by when they are due", body = [QueueEntry]),
+        (status = 403, description = "Forbidden", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn queue(
+    Extension(state): Extension<AppState>,
+    access: Access,
+    Query(query): Query<QueueQuery>,
+) -> Result<Json<Vec<QueueEntry>>, ApiError> {
+    match query.chat_id {
+        Some(chat_id) => access.chat(chat_id)?,
+        None => access.all_chats()?,
+    }
+    let per_page = query.per_page.unwrap_or(50).clamp(1, 500);
+    let page = query.page.unwrap_or(1).max(1);
+    let filter = QueueFilter {
+        status: query.status,
+        due_before: query.due_before,
+        chat_id: query.chat_id,
+    };
+
+    let queue = state
+        .get_queue(&filter, per_page, (page - 1) * per_page)
+        .await?;
+
+    Ok(Json(queue))
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ScheduleQuery {
+    /// Start of the window, now when omitted.
+    from: Option<DateTime<Utc>>,
+    /// End of the window, 30 days after `from` when omitted.
+    to: Option<DateTime<Utc>>,
+}
+
+/// Queued messages placed at the times they go out, for rendering a
+/// calendar of upcoming announcements.
+#[utoipa::path(
+    get,
+    path = "/schedule",
+    params(ScheduleQuery),
+    responses(
+        (status = 200, description = "Occurrences within the window ordered by time", body = [Occurrence]),
+        (status = 400, description = "`from` is after `to`", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn schedule(
+    Extension(state): Extension<AppState>,
+    access: Access,
+    Query(query): Query<ScheduleQuery>,
+) -> Result<Json<Vec<Occurrence>>, ApiError> {
+    access.all_chats()?;
+    let from = query.from.unwrap_or_else(Utc::now);
+    let to = query.to.unwrap_or(from + chrono::Duration::days(30));
+    if from > to {
+        return Err(ApiError::bad_request("from is after to"));
+    }
+
+    Ok(Json(state.get_calendar(from, to).await?))
+}
+
+#[utoipa::path(
+    get,
+    path = "/queue/failed",
+    responses(
+        (status = 200, description = "Messages given up on after too many failed attempts, newest first", body = [FailedMessage]),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn failed_messages(
+    Extension(state): Extension<AppState>,
+    access: Access,
+) -> Result<Json<Vec<FailedMessage>>, ApiError> {
+    access.all_chats()?;
+    Ok(Json(state.get_failed_messages().await?))
+}
+
+#[utoipa::path(
+    post,
+    path = "/queue/failed/{id}/retry",
+    params(("id" = i32, Path, description = "Queued message id")),
+    responses(
+        (status = 200, description = "The message is back in the queue"),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn retry_failed_message(
+    Extension(state): Extension<AppState>,
+    Path(id): Path<i32>,
+    access: Access,
+) -> Result<(), ApiError> {
+    access.require(Permission::Send)?;
+    access.queued_message(&state, id).await?;
+    if !state.retry_failed_message(id).await? {
+        return Err(ApiError::not_found(format!(
+            "failed message {id} not found"
+        )));
+    }
+
+    Ok(())
+}
+
+/// Pending messages and messages waiting for approval with their images,
+/// to queue them again in another database with `POST /queue/import`.
+#[utoipa::path(
+    get,
+    path = "/queue/export",
+    responses(
+        (status = 200, description = "The pending messages ordered by when they are due", body = QueueExport),
+        (status = 403, description = "Forbidden", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn export_queue(
+    Extension(state): Extension<AppState>,
+    access: Access,
+) -> Result<Json<QueueExport>, ApiError> {
+    access.all_chats()?;
+
+    Ok(Json(state.export_queue().await?))
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct QueueImportQuery {
+    /// Report what would be queued without queueing anything.
+    #[serde(default)]
+    dry_run: bool,
+}
+
+/// Queues the messages of a `GET /queue/export` under new ids. Messages that
+/// conflict with the queue or the chats of the bot are left out and listed.
+#[utoipa::path(
+    post,
+    path = "/queue/import",
+    params(QueueImportQuery),
+    request_body = QueueExport,
+    responses(
+        (status = 200, description = "New ids of the queued messages and the conflicts", body = QueueImportReport),
+        (status = 400, description = "Invalid export", body = ErrorBody),
+        (status = 403, description = "Forbidden", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn import_queue(
+    Extension(state): Extension<AppState>,
+    access: Access,
+    Query(query): Query<QueueImportQuery>,
+    Json(export): Json<QueueExport>,
+) -> Result<Json<QueueImportReport>, ApiError> {
+    access.require(Permission::Send)?;
+    access.all_chats()?;
+
+    let report = state
+        .import_queue(export, query.dry_run)
+        .await
+        .map_err(|err| match err.downcast_ref::<sqlx::Error>() {
+            Some(_) => ApiError::from(err),
+            None => ApiError::bad_request(err),
+        })?;
+
+    Ok(Json(report))
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct SendMessageMetadata {
+    #[serde(flatten)]
+    targets: SendTargets,
+    /// Can be left empty when a sticker or a location is sent.
+    #[serde(default)]
+    message: String,
+    /// Texts for chats with these languages, keyed by language code like
+    /// `{"en": "...", "ru": "..."}`. Other chats receive `message`.
+    #[serde(default)]
+    translations: Translations,
+    #[serde(flatten)]
+    attachments: Attachments,
+    /// Can be left out when `datetime_local` is given.
+    #[serde(default)]
+    datetime: String,
+    /// Queues the message even if the same text went to these chats within
+    /// the duplicate window.
+    #[serde(default)]
+    force: bool,
+    /// Converts `message` and the translations from HTML or CommonMark to
+    /// `parse_mode` first, like `/convert`.
+    convert_from: Option<SourceFormat>,
+    #[serde(flatten)]
+    options: MessageOptions,
+}
+
+/// Largest `metadata` part of a `sendMessageMultipart` request.
+const MAX_METADATA_BYTES: usize = 1024 * 1024;
+
+/// Reads a part of a multipart request, refusing parts over `limit` and
+/// requests whose parts take more than `remaining` bytes in total.
+async fn read_part(
+    field: &mut Field<'_>,
+    limit: usize,
+    remaining: &mut usize,
+) -> Result<Vec<u8>, ApiError> {
+    let too_large = |message: String| {
+        ApiError::new(StatusCode::PAYLOAD_TOO_LARGE, "payload_too_large", message)
+    };
+
+    let mut bytes = Vec::new();
+    while let Some(chunk) = field.chunk().await.map_err(ApiError::bad_request)? {
+        if bytes.len() + chunk.len() > limit {
+            let name = field.name().unwrap_or("unnamed");
+            return Err(too_large(format!(
+                "the {name} part is larger than {limit} bytes"
+            )));
+        }
+        *remaining = remaining
+            .checked_sub(chunk.len())
+            .ok_or_else(|| too_large("the parts are larger than allowed in total".to_string()))?;
+        bytes.extend_from_slice(&chunk);
+    }
+
+    Ok(bytes)
+}
+
+/// Accepts a `metadata` part with the JSON message description, every other
+/// part is treated as a binary image in the order it was sent.
+#[utoipa::path(
+    post,
+    path = "/sendMessageMultipart/",
+    params(SendMessageQuery),
+    request_body(
+        content = String,
+        content_type = "multipart/form-data",
+        description = "A `metadata` JSON part shaped like `SendMessageMetadata` followed by binary image parts",
+    ),
+    responses(
+        (status = 200, description = "Message was queued", body = QueuedMessageId),
+        (status = 400, description = "Invalid payload", body = ErrorBody),
+        (status = 409, description = "The same text was queued for these chats within the duplicate window", body = ErrorBody),
+        (status = 413, description = "The body is larger than the configured limit", body = ErrorBody),
+        (status = 422, description = "Invalid fields, listed in `errors`", body = ErrorBody),
+        (status = 429, description = "Too many messages are waiting in the queue", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn send_message_multipart(
+    Extension(state): Extension<AppState>,
+    access: Access,
+    Query(query): Query<SendMessageQuery>,
+    mut multipart: Multipart,
+) -> Result<Json<QueuedMessageId>, ApiError> {
+    access.require(Permission::Send)?;
+    let mut metadata: Option<SendMessageMetadata> = None;
+    let mut images = Vec::new();
+    let image_limit = state.config.upload_image_max_bytes;
+    let mut remaining = MAX_METADATA_BYTES + state.config.max_images * image_limit;
+
+    while let Some(mut field) = multipart
+        .next_field()
+        .await
+        .map_err(ApiError::bad_request)?
+    {
+        if field.name() == Some("metadata") {
+            let bytes = read_part(&mut field, MAX_METADATA_BYTES, &mut remaining).await?;
+            metadata = Some(serde_json::from_slice(&bytes).map_err(ApiError::bad_request)?);
+        } else {
+            images.push(read_part(&mut field, image_limit, &mut remaining).await?);
+        }
+    }
+
+    let mut metadata = metadata.ok_or_else(|| ApiError::bad_request("missing metadata part"))?;
+    let texts = message_texts(&mut metadata.message, &mut metadata.translations);
+    convert_texts(metadata.convert_from, metadata.options.parse_mode, texts)?;
+    let targets = targets(&state, metadata.targets, metadata.options.audience).await?;
+    access.targets(targets.chats.as_deref(), metadata.options.audience)?;
+    let datetime = queue_datetime(&state, &targets, metadata.datetime, &metadata.options).await?;
+    let mut validator = validate_message(
+        targets.chats.as_deref(),
+        &metadata.message,
+        &metadata.attachments,
+        images.len(),
+        &datetime,
+        metadata.options.parse_mode,
+    );
+    validator.translations(&metadata.translations, metadata.options.parse_mode);
+    validator.image_count(images.len(), state.config.max_images);
+    for (i, image) in images.iter().enumerate() {
+        validator.image(i, image);
+    }
+    validator.finish()?;
+    check_reply_to(&state, &access, &metadata.options).await?;
+    check_delete_after(&state, &metadata.options)?;
+    let preview_chat = query.preview_chat(&state)?;
+    access.chats(preview_chat)?;
+    let usage = state
+        .message_usage(&targets, metadata.options.audience)
+        .await;
+    let id = state
+        .queue_message_with_image_files(
+            targets,
+            LocalizedMessage {
+                message: metadata.message,
+                translations: metadata.translations,
+                attachments: metadata.attachments,
+            },
+            images,
+            datetime,
+            metadata.options,
+            queue_mode(preview_chat, metadata.force),
+        )
+        .await?;
+    state.record_usage(access.actor(), usage).await;
+
+    if let Some(chat_id) = preview_chat {
+        send_preview(&state, id, chat_id).await?;
+    }
+
+    Ok(Json(QueuedMessageId { id }))
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct SendPollBody {
+    /// Left empty for the subscribers audience.
+    #[serde(default)]
+    chats: Vec<i64>,
+    question: String,
+    #[serde(flatten)]
+    poll: PollDefinition,
+    /// Sends right away when omitted.
+    datetime: Option<String>,
+    #[serde(flatten)]
+    options: MessageOptions,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct QueuedPoll {
+    id: i32,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct CopyMessageBody {
+    #[serde(default)]
+    chats: Vec<i64>,
+    /// Sends to every known chat instead of `chats`.
+    #[serde(default)]
+    all: bool,
+    from_chat_id: i64,
+    message_id: i32,
+    /// Sends right away when omitted.
+    datetime: Option<String>,
+    #[serde(flatten)]
+    options: MessageOptions,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct QueuedMessageId {
+    id: i32,
+}
+
+/// Broadcasts an existing telegram message with `copy_message`, so content
+/// authored in a drafts channel keeps its formatting, media and buttons.
+#[utoipa::path(
+    post,
+    path = "/copyMessage/",
+    request_body = CopyMessageBody,
+    responses(
+        (status = 200, description = "Copy was queued", body = QueuedMessageId),
+        (status = 400, description = "Invalid payload", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn copy_message(
+    Extension(state): Extension<AppState>,
+    access: Access,
+    Json(payload): Json<CopyMessageBody>,
+) -> Result<Json<QueuedMessageId>, ApiError> {
+    access.require(Permission::Send)?;
+    if payload.options.audience == Audience::Subscribers
+        && (payload.all || !payload.chats.is_empty())
+    {
+        return Err(ApiError::bad_request(
+            "chats and the subscribers audience can't be combined",
+        ));
+    }
+    let chats = if payload.all {
+        state
+            .get_chats()
+            .await?
+            .iter()
+            .map(|chat| chat.id)
+            .collect()
+    } else {
+        payload.chats
+    };
+    if chats.is_empty() && payload.options.audience == Audience::Chats {
+        return Err(ApiError::bad_request("no target chats"));
+    }
+    if payload.all || payload.options.audience == Audience::Subscribers {
+        access.all_chats()?;
+    }
+    access.chats(chats.iter().copied())?;
+    check_reply_to(&state, &access, &payload.options).await?;
+    check_delete_after(&state, &payload.options)?;
+    let datetime = match payload.datetime {
+        Some(datetime) => {
+            chrono::DateTime::parse_from_rfc3339(&datetime).map_err(ApiError::bad_request)?;
+            datetime
+        }
+        None => chrono::Utc::now().to_rfc3339(),
+    };
+
+    // empty for the subscribers audience
+    let usage_targets = Targets::from(
+        (!chats.is_empty()).then(|| chats.iter().copied().map(ChatTarget::Chat).collect()),
+    );
+    let usage = state
+        .message_usage(&usage_targets, payload.options.audience)
+        .await;
+    let id = state
+        .queue_copied_message(
+            chats,
+            payload.from_chat_id,
+            payload.message_id,
+            String::new(),
+            datetime,
+            payload.options,
+        )
+        .await?;
+    state.record_usage(access.actor(), usage).await;
+
+    Ok(Json(QueuedMessageId { id }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/sendPoll/",
+    request_body = SendPollBody,
+    responses(
+        (status = 200, description = "Poll was queued", body = QueuedPoll),
+        (status = 400, description = "Invalid payload", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn send_poll(
+    Extension(state): Extension<AppState>,
+    access: Access,
+    Json(payload): Json<SendPollBody>,
+) -> Result<Json<QueuedPoll>, ApiError> {
+    access.require(Permission::Send)?;
+    payload
+        .poll
+        .validate(&payload.question)
+        .map_err(ApiError::bad_request)?;
+    if payload.options.audience == Audience::Subscribers && !payload.chats.is_empty() {
+        return Err(ApiError::bad_request(
+            "chats and the subscribers audience can't be combined",
+        ));
+    }
+    if payload.options.audience == Audience::Subscribers {
+        access.all_chats()?;
+    }
+    access.chats(payload.chats.iter().copied())?;
+    check_reply_to(&state, &access, &payload.options).await?;
+    check_delete_after(&state, &payload.options)?;
+    let datetime = match payload.datetime {
+        Some(datetime) => {
+            chrono::DateTime::parse_from_rfc3339(&datetime).map_err(ApiError::bad_request)?;
+            datetime
+        }
+        None => chrono::Utc::now().to_rfc3339(),
+    };
+
+    let usage_targets = Targets::from((!payload.chats.is_empty()).then(|| {
+        payload
+            .chats
+            .iter()
+            .copied()
+            .map(ChatTarget::Chat)
+            .collect()
+    }));
+    let usage = state
+        .message_usage(&usage_targets, payload.options.audience)
+        .await;
+    let id = state
+        .queue_poll(
+            payload.chats,
+            payload.question,
+            payload.poll,
+            datetime,
+            payload.options,
+        )
+        .await?;
+    state.record_usage(access.actor(), usage).await;
+
+    Ok(Json(QueuedPoll { id }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/polls/{id}/results",
+    params(("id" = i32, Path, description = "Queued message id of the poll")),
+    responses(
+        (status = 200, description = "Votes summed over every chat the poll was sent to", body = PollResults),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn poll_results(
+    Extension(state): Extension<AppState>,
+    Path(id): Path<i32>,
+    access: Access,
+) -> Result<Json<PollResults>, ApiError> {
+    access.queued_message(&state, id).await?;
+    state
+        .get_poll_results(id)
+        .await?
+        .map(Json)
+        .ok_or_else(|| ApiError::not_found(format!("poll {id} not found")))
+}
+
+#[utoipa::path(
+    get,
+    path = "/queue/{id}/progress",
+    params(("id" = i32, Path, description = "Queued message id")),
+    responses(
+        (status = 200, description = "Delivery progress of the message", body = BroadcastProgress),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn queue_progress(
+    Extension(state): Extension<AppState>,
+    Path(id): Path<i32>,
+    access: Access,
+) -> Result<Json<BroadcastProgress>, ApiError> {
+    access.queued_message(&state, id).await?;
+    state
+        .get_broadcast_progress(id)
+        .await?
+        .map(Json)
+        .ok_or_else(|| ApiError::not_found(format!("queued message {id} not found")))
+}
+
+#[utoipa::path(
+    get,
+    path = "/queue/{id}/deliveries",
+    params(("id" = i32, Path, description = "Queued message id")),
+    responses(
+        (status = 200, description = "Per-chat delivery results", body = [Delivery]),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn queue_deliveries(
+    Extension(state): Extension<AppState>,
+    Path(id): Path<i32>,
+    access: Access,
+) -> Result<Json<Vec<Delivery>>, ApiError> {
+    access.queued_message(&state, id).await?;
+    Ok(Json(state.get_deliveries(id).await?))
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct FilesQuery {
+    /// Only the files sent to this chat.
+    chat_id: Option<i64>,
+}
+
+/// The photos and stickers of a broadcast as telegram stored them in each
+/// chat, with the ids other systems can send them again by.
+#[utoipa::path(
+    get,
+    path = "/queue/{id}/files",
+    params(("id" = i32, Path, description = "Queued message id"), FilesQuery),
+    responses(
+        (status = 200, description = "Files of the broadcast per chat", body = [DeliveredFile]),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn queue_files(
+    Extension(state): Extension<AppState>,
+    Path(id): Path<i32>,
+    access: Access,
+    Query(query): Query<FilesQuery>,
+) -> Result<Json<Vec<DeliveredFile>>, ApiError> {
+    access.queued_message(&state, id).await?;
+    if let Some(chat_id) = query.chat_id {
+        access.chat(chat_id)?;
+    }
+
+    state
+        .get_delivered_files(id, query.chat_id)
+        .await?
+        .map(Json)
+        .ok_or_else(|| ApiError::not_found(format!("queued message {id} not found")))
+}
+
+#[utoipa::path(
+    get,
+    path = "/broadcasts/{id}/variants",
+    params(("id" = i32, Path, description = "Queued message id")),
+    responses(
+        (status = 200, description = "Chats and deliveries per variant, empty for messages without variants", body = [VariantResult]),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn broadcast_variants(
+    Extension(state): Extension<AppState>,
+    Path(id): Path<i32>,
+    access: Access,
+) -> Result<Json<Vec<VariantResult>>, ApiError> {
+    access.queued_message(&state, id).await?;
+    state
+        .get_variant_results(id)
+        .await?
+        .map(Json)
+        .ok_or_else(|| ApiError::not_found(format!("queued message {id} not found")))
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct BroadcastSearchQuery {
+    /// Words to look for: quoted phrases, `or` and `-` to leave words out
+    /// work like in a search engine.
+    q: String,
+    /// Only broadcasts finished at or after this RFC 3339 time.
+    from: Option<DateTime<Utc>>,
+    /// Only broadcasts finished before this RFC 3339 time.
+    to: Option<DateTime<Utc>>,
+    /// Only broadcasts delivered to the chat. API keys limited to some chats
+    /// have to pass one of theirs.
+    chat_id: Option<i64>,
+    page: Option<i64>,
+    per_page: Option<i64>,
+}
+
+/// Searches the text of the broadcasts that went out. Only available with
+/// postgres.
+#[utoipa::path(
+    get,
+    path = "/broadcasts/search",
+    params(BroadcastSearchQuery),
+    responses(
+        (status = 200, description = "Sent broadcasts matching the search, best matches first", body = [FoundBroadcast]),
+        (status = 400, description = "No words given", body = ErrorBody),
+        (status = 403, description = "Forbidden", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn search_broadcasts(
+    Extension(state): Extension<AppState>,
+    access: Access,
+    Query(query): Query<BroadcastSearchQuery>,
+) -> Result<Json<Vec<FoundBroadcast>>, ApiError> {
+    match query.chat_id {
+        Some(chat_id) => access.chat(chat_id)?,
+        None => access.all_chats()?,
+    }
+    if query.q.trim().is_empty() {
+        return Err(ApiError::bad_request("q must not be empty"));
+    }
+    let per_page = query.per_page.unwrap_or(50).clamp(1, 500);
+    let page = query.page.unwrap_or(1).max(1);
+    let search = BroadcastSearch {
+        query: &query.q,
+        from: query.from,
+        to: query.to,
+        chat_id: query.chat_id,
+    };
+
+    let found = state
+        .search_broadcasts(&search, per_page, (page - 1) * per_page)
+        .await?;
+
+    Ok(Json(found))
+}
+
+#[utoipa::path(
+    get,
+    path = "/broadcasts/{id}/stats",
+    params(("id" = i32, Path, description = "Queued message id")),
+    responses(
+        (status = 200, description = "Reactions to the broadcast summed over the chats it landed in", body = BroadcastStats),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn broadcast_stats(
+    Extension(state): Extension<AppState>,
+    Path(id): Path<i32>,
+    access: Access,
+) -> Result<Json<BroadcastStats>, ApiError> {
+    access.queued_message(&state, id).await?;
+    state
+        .get_broadcast_stats(id)
+        .await?
+        .map(Json)
+        .ok_or_else(|| ApiError::not_found(format!("queued message {id} not found")))
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct RepliesQuery {
+    page: Option<i64>,
+    per_page: Option<i64>,
+}
+
+/// Replies to a broadcast sent with `collect_replies`, from every chat it
+/// landed in. Only available with postgres.
+#[utoipa::path(
+    get,
+    path = "/broadcasts/{id}/replies",
+    params(("id" = i32, Path, description = "Queued message id"), RepliesQuery),
+    responses(
+        (status = 200, description = "Replies to the broadcast, oldest first", body = [BroadcastReply]),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn broadcast_replies(
+    Extension(state): Extension<AppState>,
+    Path(id): Path<i32>,
+    access: Access,
+    Query(query): Query<RepliesQuery>,
+) -> Result<Json<Vec<BroadcastReply>>, ApiError> {
+    access.queued_message(&state, id).await?;
+    if state.get_queued_message(id).await?.is_none() {
+        return Err(ApiError::not_found(format!(
+            "queued message {id} not found"
+        )));
+    }
+    let per_page = query.per_page.unwrap_or(50).clamp(1, 500);
+    let page = query.page.unwrap_or(1).max(1);
+
+    let replies = state
+        .get_broadcast_replies(id, per_page, (page - 1) * per_page)
+        .await?;
+
+    Ok(Json(replies))
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct EditBroadcastBody {
+    message: String,
+    #[serde(default)]
+    parse_mode: MessageFormat,
+}
+
+#[utoipa::path(
+    post,
+    path = "/broadcasts/{id}/edit",
+    params(("id" = i32, Path, description = "Queued message id")),
+    request_body = EditBroadcastBody,
+    responses(
+        (status = 200, description = "Per-chat edit results", body = [ChatResult]),
+        (status = 400, description = "Invalid payload", body = ErrorBody),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn edit_broadcast(
+    Extension(state): Extension<AppState>,
+    Path(id): Path<i32>,
+    access: Access,
+    Json(payload): Json<EditBroadcastBody>,
+) -> Result<Json<Vec<ChatResult>>, ApiError> {
+    access.require(Permission::Send)?;
+    access.queued_message(&state, id).await?;
+    payload
+        .parse_mode
+        .validate(&payload.message)
+        .map_err(ApiError::bad_request)?;
+    if state.get_broadcast_progress(id).await?.is_none() {
+        return Err(ApiError::not_found(format!(
+            "queued message {id} not found"
+        )));
+    }
+
+    let results = state
+        .edit_broadcast(id, &payload.message, payload.parse_mode)
+        .await?;
+
+    Ok(Json(results))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/broadcasts/{id}",
+    params(("id" = i32, Path, description = "Queued message id")),
+    responses(
+        (status = 200, description = "Per-chat delete results", body = [ChatResult]),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn delete_broadcast(
+    Extension(state): Extension<AppState>,
+    Path(id): Path<i32>,
+    access: Access,
+) -> Result<Json<Vec<ChatResult>>, ApiError> {
+    access.require(Permission::Send)?;
+    access.queued_message(&state, id).await?;
+    if state.get_broadcast_progress(id).await?.is_none() {
+        return Err(ApiError::not_found(format!(
+            "queued message {id} not found"
+        )));
+    }
+
+    Ok(Json(state.delete_broadcast(id).await?))
+}
+
+/// Overrides for the copy of a broadcast, it keeps the targets of the
+/// original when neither `chats` nor `all_chats` is set.
+#[derive(Deserialize, ToSchema)]
+pub struct ResendBroadcastBody {
+    #[serde(default)]
+    chats: Vec<ChatTarget>,
+    #[serde(default)]
+    all_chats: bool,
+    /// When to send the copy, right away when it's missing.
+    datetime: Option<String>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/broadcasts/{id}/resend",
+    params(("id" = i32, Path, description = "Queued message id")),
+    request_body = ResendBroadcastBody,
+    responses(
+        (status = 200, description = "The copy was queued", body = QueuedMessageId),
+        (status = 400, description = "Invalid payload", body = ErrorBody),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn resend_broadcast(
+    Extension(state): Extension<AppState>,
+    Path(id): Path<i32>,
+    access: Access,
+    Json(payload): Json<ResendBroadcastBody>,
+) -> Result<Json<QueuedMessageId>, ApiError> {
+    access.require(Permission::Send)?;
+    access.queued_message(&state, id).await?;
+    let message = state
+        .get_queued_message(id)
+        .await?
+        .ok_or_else(|| ApiError::not_found(format!("queued message {id} not found")))?;
+    let audience: Audience = message.audience.parse()?;
+    let targets = if payload.all_chats || !payload.chats.is_empty() {
+        let chats = SendTargets {
+            chats: payload.chats,
+            all_chats: payload.all_chats,
+            ..Default::default()
+        };
+        targets(&state, chats, audience).await?.chats
+    } else {
+        state.get_queued_targets(&message).await?
+    };
+    access.targets(targets.as_deref(), audience)?;
+    let datetime = payload
+        .datetime
+        .unwrap_or_else(|| chrono::Utc::now().to_rfc3339());
+    let mut validator = Validator::default();
+    validator.datetime(&datetime);
+    validator.finish()?;
+
+    let id = state.resend_broadcast(&message, targets, datetime).await?;
+
+    Ok(Json(QueuedMessageId { id }))
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct RetriedChats {
+    /// Chats the broadcast failed in, it is queued for them again.
+    chats: Vec<i64>,
+}
+
+/// Sends a completed broadcast again to the chats its delivery failed in.
+/// The new results replace the failures in `/queue/{id}/deliveries`.
+#[utoipa::path(
+    post,
+    path = "/broadcasts/{id}/retryFailed",
+    params(("id" = i32, Path, description = "Queued message id")),
+    responses(
+        (status = 200, description = "The broadcast is queued again for the failed chats", body = RetriedChats),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 409, description = "The broadcast is still being sent", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn retry_failed_chats(
+    Extension(state): Extension<AppState>,
+    Path(id): Path<i32>,
+    access: Access,
+) -> Result<Json<RetriedChats>, ApiError> {
+    access.require(Permission::Send)?;
+    access.queued_message(&state, id).await?;
+    let progress = state
+        .get_broadcast_progress(id)
+        .await?
+        .ok_or_else(|| ApiError::not_found(format!("queued message {id} not found")))?;
+    if !progress.completed {
+        return Err(ApiError::new(
+            StatusCode::CONFLICT,
+            "not_completed",
+            format!("queued message {id} is still being sent"),
+        ));
+    }
+
+    let chats = state.retry_failed_chats(id).await?;
+
+    Ok(Json(RetriedChats { chats }))
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ImportQuery {
+    #[serde(default)]
+    mode: ConflictMode,
+    /// Report what would change without writing anything.
+    #[serde(default)]
+    dry_run: bool,
+}
+
+/// Accepts a JSON backup, or CSV with `chat_id,chat_name,user_id,username,name`
+/// columns when sent as `text/csv`.
+#[utoipa::path(
+    post,
+    path = "/import",
+    params(ImportQuery),
+    request_body(
+        content = Backup,
+        description = "JSON backup, or CSV rows when sent as `text/csv`",
+    ),
+    responses(
+        (status = 200, description = "What was (or would be) imported", body = ImportReport),
+        (status = 400, description = "Invalid backup", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn import(
+    Extension(state): Extension<AppState>,
+    access: Access,
+    Query(query): Query<ImportQuery>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<ImportReport>, ApiError> {
+    access.require(Permission::Manage)?;
+    access.all_chats()?;
+    let is_csv = headers
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/csv"));
+
+    let backup = if is_csv {
+        Backup::from_csv(&body).map_err(ApiError::bad_request)?
+    } else {
+        serde_json::from_slice(&body).map_err(ApiError::bad_request)?
+    };
+
+    let report = state
+        .import_backup(backup, query.mode, query.dry_run)
+        .await
+        .map_err(|err| match err.downcast_ref::<sqlx::Error>() {
+            Some(_) => ApiError::from(err),
+            None => ApiError::bad_request(err),
+        })?;
+
+    Ok(Json(report))
+}
+
+#[utoipa::path(
+    get,
+    path = "/webhooks",
+    responses(
+        (status = 200, description = "Registered webhooks, without their secrets", body = [Webhook]),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn webhooks(
+    Extension(state): Extension<AppState>,
+    access: Access,
+) -> Result<Json<Vec<Webhook>>, ApiError> {
+    access.require(Permission::Manage)?;
+    access.all_chats()?;
+    Ok(Json(state.get_webhooks().await?))
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct WebhookCreated {
+    #[serde(flatten)]
+    webhook: Webhook,
+    /// Key of the HMAC in the `X-Webhook-Signature` header, only shown once.
+    secret: String,
+}
+
+#[utoipa::path(
+    post,
+    path = "/webhooks",
+    request_body = NewWebhook,
+    responses(
+        (status = 200, description = "The webhook receives events from now on", body = WebhookCreated),
+        (status = 400, description = "Invalid URL", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn add_webhook(
+    Extension(state): Extension<AppState>,
+    access: Access,
+    Json(payload): Json<NewWebhook>,
+) -> Result<Json<WebhookCreated>, ApiError> {
+    access.require(Permission::Manage)?;
+    access.all_chats()?;
+    payload.validate().map_err(ApiError::bad_request)?;
+    state
+        .check_outbound_url(&payload.url)
+        .map_err(ApiError::bad_request)?;
+
+    let webhook = state.add_webhook(&payload).await?;
+    let secret = webhook.secret.clone();
+
+    Ok(Json(WebhookCreated { webhook, secret }))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/webhooks/{id}",
+    params(("id" = i32, Path, description = "Webhook id")),
+    responses(
+        (status = 200, description = "The webhook no longer receives events"),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn delete_webhook(
+    Extension(state): Extension<AppState>,
+    access: Access,
+    Path(id): Path<i32>,
+) -> Result<(), ApiError> {
+    access.require(Permission::Manage)?;
+    access.all_chats()?;
+    if !state.delete_webhook(id).await? {
+        return Err(ApiError::not_found(format!("webhook {id} not found")));
+    }
+
+    Ok(())
+}
+
+/// Drafts are visible to the API keys that could send them.
+fn draft_access(access: &Access, content: &DraftContent) -> Result<(), ApiError> {
+    if content.all_chats || content.options.audience != Audience::Chats {
+        return access.all_chats();
+    }
+
+    access.chats(content.chats.iter().map(|target| target.chat_id()))
+}
+
+async fn get_accessible_draft(
+    state: &AppState,
+    access: &Access,
+    id: i32,
+) -> Result<Draft, ApiError> {
+    state
+        .get_draft(id)
+        .await?
+        .filter(|draft| draft_access(access, &draft.content).is_ok())
+        .ok_or_else(|| ApiError::not_found(format!("draft {id} not found")))
+}
+
+#[utoipa::path(
+    get,
+    path = "/drafts",
+    responses(
+        (status = 200, description = "Drafts, the most recently updated first", body = [Draft]),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn drafts(
+    Extension(state): Extension<AppState>,
+    access: Access,
+) -> Result<Json<Vec<Draft>>, ApiError> {
+    let drafts = state
+        .get_drafts()
+        .await?
+        .into_iter()
+        .filter(|draft| draft_access(&access, &draft.content).is_ok())
+        .collect();
+
+    Ok(Json(drafts))
+}
+
+#[utoipa::path(
+    post,
+    path = "/drafts",
+    request_body = DraftContent,
+    responses(
+        (status = 200, description = "Draft was saved", body = Draft),
+        (status = 403, description = "Not allowed to send to these chats", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn add_draft(
+    Extension(state): Extension<AppState>,
+    access: Access,
+    Json(payload): Json<DraftContent>,
+) -> Result<Json<Draft>, ApiError> {
+    access.require(Permission::Send)?;
+    draft_access(&access, &payload)?;
+
+    Ok(Json(state.add_draft(&payload).await?))
+}
+
+#[utoipa::path(
+    put,
+    path = "/drafts/{id}",
+    params(("id" = i32, Path, description = "Draft id")),
+    request_body = DraftContent,
+    responses(
+        (status = 200, description = "Draft was replaced", body = Draft),
+        (status = 403, description = "Not allowed to send to these chats", body = ErrorBody),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn update_draft(
+    Extension(state): Extension<AppState>,
+    access: Access,
+    Path(id): Path<i32>,
+    Json(payload): Json<DraftContent>,
+) -> Result<Json<Draft>, ApiError> {
+    access.require(Permission::Send)?;
+    get_accessible_draft(&state, &access, id).await?;
+    draft_access(&access, &payload)?;
+
+    state
+        .update_draft(id, &payload)
+        .await?
+        .map(Json)
+        .ok_or_else(|| ApiError::not_found(format!("draft {id} not found")))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/drafts/{id}",
+    params(("id" = i32, Path, description = "Draft id")),
+    responses(
+        (status = 200, description = "Draft was discarded"),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn delete_draft(
+    Extension(state): Extension<AppState>,
+    access: Access,
+    Path(id): Path<i32>,
+) -> Result<(), ApiError> {
+    access.require(Permission::Send)?;
+    get_accessible_draft(&state, &access, id).await?;
+    if !state.delete_draft(id).await? {
+        return Err(ApiError::not_found(format!("draft {id} not found")));
+    }
+
+    Ok(())
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SendDraftQuery {
+    /// Queues the message even if the same text went to these chats within
+    /// the duplicate window.
+    #[serde(default)]
+    force: bool,
+}
+
+#[utoipa::path(
+    post,
+    path = "/drafts/{id}/send",
+    params(("id" = i32, Path, description = "Draft id"), SendDraftQuery),
+    responses(
+        (status = 200, description = "Message was queued and the draft discarded", body = QueuedMessageId),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 409, description = "The same text was queued for these chats within the duplicate window", body = ErrorBody),
+        (status = 422, description = "The draft is incomplete, the invalid fields are listed in `errors`", body = ErrorBody),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn send_draft(
+    Extension(state): Extension<AppState>,
+    access: Access,
+    Path(id): Path<i32>,
+    Query(query): Query<SendDraftQuery>,
+) -> Result<Json<QueuedMessageId>, ApiError> {
+    access.require(Permission::Send)?;
+    let mut draft = get_accessible_draft(&state, &access, id).await?.content;
+    draft.options.draft_id = Some(id);
+    let payload = SendMessageBody {
+        targets: SendTargets {
+            chats: draft.chats,
+            all_chats: draft.all_chats,
+            exclude_chats: draft.exclude_chats,
+            ..Default::default()
+        },
+        message: draft.message,
+        translations: draft.translations,
+        attachments: draft.attachments,
+        images: draft.images,
+        datetime: draft.datetime.unwrap_or_else(|| Utc::now().to_rfc3339()),
+        force: query.force,
+        convert_from: None,
+        options: draft.options,
+    };
+    let queued = queue_message(&state, &access, payload, None).await?;
+
+    Ok(Json(QueuedMessageId { id: queued }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/settings/maintenance",
+    responses(
+        (status = 200, description = "Whether the bot is in maintenance", body = Maintenance),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn get_maintenance(
+    Extension(state): Extension<AppState>,
+    _access: Access,
+) -> Result<Json<Maintenance>, ApiError> {
+    let enabled = state.in_maintenance().await?;
+
+    Ok(Json(Maintenance { enabled }))
+}
+
+/// Switches maintenance on or off. In maintenance the queue is paused,
+/// cleanups don't run and every other mutation is refused with `503`.
+#[utoipa::path(
+    post,
+    path = "/settings/maintenance",
+    request_body = Maintenance,
+    responses(
+        (status = 200, description = "Maintenance was switched", body = Maintenance),
+        (status = 500, description = "Internal error", body = ErrorBody),
+    )
+)]
+async fn set_maintenance(
+    Extension(state): Extension<AppState>,
+    access: Access,
+    Json(payload): Json<Maintenance>,
+) -> Result<Json<Maintenance>, ApiError> {
+    access.require(Permission::Manage)?;
+    access.all_chats()?;
+    state.set_maintenance(payload.enabled).await?;
+
+    Ok(Json(payload))
+}
+
+/// Telegram limits the bot adaptor queues outgoing requests by.
+#[derive(Serialize, ToSchema)]
+pub struct ThrottleSettings {
+    messages_per_sec_chat: u32,
+    messages_per_min_chat: u32,
+    messages_per_min_channel: u32,
+    messages_per_sec_overall: u32,
+}
+
+impl From<Limits> for ThrottleSettings {
+    fn from(limits: Limits) -> Self {
+        Self {
+            messages_per_sec_chat: limits.messages_per_sec_chat,
+            messages_per_min_chat: limits.messages_per_min_chat,
+            messages_per_min_channel: limits.messages_per_min_channel,
+            messages_per_sec_overall: limits.messages_per_sec_overall,
+        }
+    }
+}
+
+/// Fields that are left out keep their current value.
+#[derive(Deserialize, ToSchema)]
+pub struct ThrottlePatch {
+    messages_per_sec_chat: Option<u32>,
+    messages_per_min_chat: Option<u32>,
+    messages_per_min_channel: Option<u32>,
+    messages_per_sec_overall: Option<u32>,
+}
+
+/// Changes the throttle limits of the bot, stored and applied like a
+/// `PATCH /settings` of `throttle`.
+#[utoipa::path(
+    patch,
+    path = "/settings/throttle",
+    request_body = ThrottlePatch,
+    responses(
+        (status = 200, description = "The limits now in effect", body = ThrottleSettings),
+        (status = 400, description = "Invalid limits", body = ErrorBody),
+    )
+)]
+async fn update_throttle(
+    Extension(state): Extension<AppState>,
+    access: Access,
+    Json(payload): Json<ThrottlePatch>,
+) -> Result<Json<ThrottleSettings>, ApiError> {
+    access.require(Permission::Manage)?;
+    access.all_chats()?;
+    let fields = [
+        ("messages_per_sec_chat", payload.messages_per_sec_chat),
+        ("messages_per_min_chat", payload.messages_per_min_chat),
+        ("messages_per_min_channel", payload.messages_per_min_channel),
+        ("messages_per_sec_overall", payload.messages_per_sec_overall),
+    ];
+    let throttle: serde_json::Map<_, _> = fields
+        .into_iter()
+        .filter_map(|(name, value)| Some((name.to_string(), value?.into())))
+        .collect();
+
+    let settings = state
+        .update_settings(serde_json::json!({ "throttle": throttle }))
+        .await
+        .map_err(settings_error)?;
+
+    Ok(Json(settings.throttle.limits().into()))
+}
+
+/// The settings of the bot in effect, the configured ones with the changes
+/// made through `PATCH /settings` on top.
+#[utoipa::path(
+    get,
+    path = "/settings",
+    responses(
+        (status = 200, description = "The settings in effect", body = Settings),
+        (status = 403, description = "The API key is limited to some chats", body = ErrorBody),
+    )
+)]
+async fn get_settings(
+    Extension(state): Extension<AppState>,
+    access: Access,
+) -> Result<Json<Settings>, ApiError> {
+    access.all_chats()?;
+
+    Ok(Json(state.settings().as_ref().clone()))
+}
+
+/// Changes settings of the running bot without a restart. The body is a
+/// JSON merge patch of the settings: fields that are left out keep their
+/// value and `null` goes back to the configured one. The changes are stored
+/// and other instances pick them up within `intervals.settings_sync`.
+#[utoipa::path(
+    patch,
+    path = "/settings",
+    request_body = Object,
+    responses(
+        (status = 200, description = "The settings now in effect", body = Settings),
+        (status = 400, description = "Unknown or invalid settings", body = ErrorBody),
+    )
+)]
+async fn update_settings(
+    Extension(state): Extension<AppState>,
+    access: Access,
+    Json(payload): Json<serde_json::Value>,
+) -> Result<Json<Settings>, ApiError> {
+    access.require(Permission::Manage)?;
+    access.all_chats()?;
+    let settings = state
+        .update_settings(payload)
+        .await
+        .map_err(settings_error)?;
+
+    Ok(Json(settings.as_ref().clone()))
+}
+
+// anything but the storage failing is wrong with the patch
+fn settings_error(err: anyhow::Error) -> ApiError {
+    match err.downcast_ref::<sqlx::Error>() {
+        Some(_) => ApiError::from(err),
+        None => ApiError::bad_request(err),
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct LoggedIn {
+    /// Also set as the `session` cookie, send it as a bearer token when
+    /// cookies are not an option.
+    token: String,
+    session: Session,
+}
+
+/// Logs an admin in with the data of the Telegram Login Widget.
+#[utoipa::path(
+    post,
+    path = "/auth/telegram",
+    request_body = TelegramLogin,
+    responses(
+        (status = 200, description = "Logged in, the session cookie is set", body = LoggedIn),
+        (status = 401, description = "The login could not be verified", body = ErrorBody),
+        (status = 403, description = "The user is not an admin", body = ErrorBody),
+    )
+)]
+async fn telegram_login(
+    Extension(state): Extension<AppState>,
+    Json(payload): Json<TelegramLogin>,
+) -> Result<(HeaderMap, Json<LoggedIn>), ApiError> {
+    payload
+        .verify(state.bot.inner().token())
+        .map_err(|err| ApiError::new(StatusCode::UNAUTHORIZED, "invalid_login", err))?;
+    let (token, session) = state
+        .login(&payload)
+        .map_err(|err| ApiError::new(StatusCode::FORBIDDEN, "forbidden", err))?;
+    info!("{} logged in", session.actor());
+
+    let cookie = format!(
+        "{SESSION_COOKIE}={token}; Path=/; Max-Age={}; HttpOnly; Secure; SameSite=Strict",
+        state.config.session_ttl
+    );
+    let mut headers = HeaderMap::new();
+    headers.insert(SET_COOKIE, cookie.parse().map_err(anyhow::Error::from)?);
+
+    Ok((headers, Json(LoggedIn { token, session })))
+}
+
+#[utoipa::path(
+    get,
+    path = "/auth/session",
+    responses(
+        (status = 200, description = "The session of the request", body = Session),
+        (status = 401, description = "Not logged in", body = ErrorBody),
+    )
+)]
+async fn current_session(
+    Extension(state): Extension<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<Session>, ApiError> {
+    state
+        .request_session(&headers)
+        .map(Json)
+        .ok_or_else(|| ApiError::new(StatusCode::UNAUTHORIZED, "unauthorized", "not logged in"))
+}
+
+/// Identifies who triggered a request for the audit tables. Users logged in
+/// with telegram are named by their session, configured API keys by their
+/// name, other callers identify themselves with the `X-Api-Key` header of
+/// which only a short prefix is kept.
+pub(crate) fn initiator(state: &AppState, headers: &HeaderMap) -> String {
+    if let Some(session) = state.request_session(headers) {
+        return session.actor();
+    }
+    if let Some(api_key) = state.request_api_key(headers) {
+        return format!("api-key:{}", api_key.name);
+    }
+
+    match headers.get("x-api-key").and_then(|key| key.to_str().ok()) {
+        Some(key) => format!("api-key:{}", key.chars().take(8).collect::<String>()),
+        None => "api".to_string(),
+    }
 }
@@ -0,0 +1,161 @@
+use serde::{Deserialize, Serialize};
+use teloxide::{
+    payloads::{SendLocationSetters, SendStickerSetters, SendVenueSetters},
+    requests::Requester,
+    types::{ChatId, InputFile, Message},
+};
+use tracing::{error, info, instrument};
+use utoipa::ToSchema;
+
+use crate::{broadcast::MessageOptions, state::AppState};
+
+/// A sticker and a map pin sent after the text of a broadcast, or instead of
+/// it when the message is empty. The sticker goes first.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, ToSchema)]
+pub struct Attachments {
+    /// `file_id` of a sticker the bot has seen, like one it received.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sticker: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub location: Option<Location>,
+}
+
+/// A point on the map, sent as a venue when it has a title and an address.
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct Location {
+    pub latitude: f64,
+    pub longitude: f64,
+    pub title: Option<String>,
+    pub address: Option<String>,
+}
+
+impl Attachments {
+    /// Rebuilds the attachments from their `message_attachment` columns.
+    pub fn from_columns(
+        sticker: Option<String>,
+        latitude: Option<f64>,
+        longitude: Option<f64>,
+        title: Option<String>,
+        address: Option<String>,
+    ) -> Self {
+        let location = latitude
+            .zip(longitude)
+            .map(|(latitude, longitude)| Location {
+                latitude,
+                longitude,
+                title,
+                address,
+            });
+
+        Self { sticker, location }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sticker.is_none() && self.location.is_none()
+    }
+
+    /// Messages the attachments are sent as.
+    pub fn len(&self) -> usize {
+        usize::from(self.sticker.is_some()) + usize::from(self.location.is_some())
+    }
+}
+
+impl AppState {
+    #[instrument(skip_all, fields(chat_id))]
+    pub async fn send_sticker_to_chat(
+        &self,
+        chat_id: i64,
+        sticker: &str,
+        options: &MessageOptions,
+    ) -> anyhow::Result<Message> {
+        info!("sending sticker:{sticker} to chat:{chat_id}");
+
+        let mut request = self
+            .bot
+            .send_sticker(ChatId(chat_id), InputFile::file_id(sticker))
+            .disable_notification(options.silent)
+            .protect_content(options.protect_content);
+        if let Some(thread_id) = options.thread_id {
+            request = request.message_thread_id(thread_id);
+        }
+        options.reply_to(&mut request);
+        if let Some(markup) = self.unsubscribe_markup(options) {
+            request = request.reply_markup(markup);
+        }
+
+        self.record_attachment(chat_id, request.await)
+    }
+
+    /// Sends a venue when the location has a title and an address, a bare
+    /// pin otherwise.
+    #[instrument(skip_all, fields(chat_id))]
+    pub async fn send_location_to_chat(
+        &self,
+        chat_id: i64,
+        location: &Location,
+        options: &MessageOptions,
+    ) -> anyhow::Result<Message> {
+        info!(
+            "sending location {},{} to chat:{chat_id}",
+            location.latitude, location.longitude
+        );
+
+        let result = match (&location.title, &location.address) {
+            (Some(title), Some(address)) => {
+                let mut request = self
+                    .bot
+                    .send_venue(
+                        ChatId(chat_id),
+                        location.latitude,
+                        location.longitude,
+                        title,
+                        address,
+                    )
+                    .disable_notification(options.silent)
+                    .protect_content(options.protect_content);
+                if let Some(thread_id) = options.thread_id {
+                    request = request.message_thread_id(thread_id);
+                }
+                options.reply_to(&mut request);
+                if let Some(markup) = self.unsubscribe_markup(options) {
+                    request = request.reply_markup(markup);
+                }
+                request.await
+            }
+            _ => {
+                let mut request = self
+                    .bot
+                    .send_location(ChatId(chat_id), location.latitude, location.longitude)
+                    .disable_notification(options.silent)
+                    .protect_content(options.protect_content);
+                if let Some(thread_id) = options.thread_id {
+                    request = request.message_thread_id(thread_id);
+                }
+                options.reply_to(&mut request);
+                if let Some(markup) = self.unsubscribe_markup(options) {
+                    request = request.reply_markup(markup);
+                }
+                request.await
+            }
+        };
+
+        self.record_attachment(chat_id, result)
+    }
+
+    fn record_attachment(
+        &self,
+        chat_id: i64,
+        result: Result<Message, teloxide::RequestError>,
+    ) -> anyhow::Result<Message> {
+        match result {
+            Ok(sent) => {
+                self.telegram_activity.record_success();
+                Ok(sent)
+            }
+            Err(err) => {
+                error!("error sending an attachment to chat:{chat_id} {err}");
+                Err(err.into())
+            }
+        }
+    }
+}
@@ -0,0 +1,206 @@
+use std::time::Duration;
+
+use axum::{
+    body::{Body, Bytes},
+    extract::OriginalUri,
+    http::{
+        header::{CONTENT_LENGTH, CONTENT_TYPE},
+        Request,
+    },
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value;
+use tracing::{error, info};
+use utoipa::ToSchema;
+
+use crate::{api::initiator, error::ApiError, state::AppState, telemetry::current_request_id};
+
+pub const AUDIT_PRUNE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Longest string kept in a payload summary.
+const MAX_SUMMARY_STRING: usize = 200;
+const MAX_SUMMARY_ITEMS: usize = 20;
+const MAX_SUMMARY_LENGTH: usize = 2000;
+
+tokio::task_local! {
+    static AUDIT_ID: i64;
+}
+
+/// Id of the audit entry of the API request being handled, if any.
+pub fn current_audit_id() -> Option<i64> {
+    AUDIT_ID.try_with(|id| *id).ok()
+}
+
+#[derive(Serialize, ToSchema, sqlx::FromRow)]
+pub struct AuditEntry {
+    pub id: i64,
+    pub actor: String,
+    pub method: String,
+    pub endpoint: String,
+    pub payload: Option<String>,
+    /// HTTP status of the response, missing while the request is handled.
+    pub status: Option<i32>,
+    pub created_at: DateTime<Utc>,
+    /// The `x-request-id` of the call, also on the broadcasts and kicks it
+    /// started.
+    pub request_id: Option<String>,
+}
+
+/// What is recorded before a request is handled.
+pub struct NewAuditEntry<'a> {
+    pub bot_id: &'a str,
+    pub actor: &'a str,
+    pub method: &'a str,
+    pub endpoint: &'a str,
+    pub payload: Option<&'a str>,
+    pub request_id: Option<&'a str>,
+}
+
+/// Middleware recording every call of the routes it wraps in the audit log,
+/// with who made it, a summary of the payload and the resulting status.
+/// Error responses carry the id of their entry.
+pub async fn audit(req: Request<Body>, next: Next<Body>) -> Response {
+    let Some(state) = req.extensions().get::<AppState>().cloned() else {
+        return next.run(req).await;
+    };
+    // nested bot routes see their path without the `/bots/:bot_id` prefix
+    let endpoint = match req.extensions().get::<OriginalUri>() {
+        Some(OriginalUri(uri)) => uri.path().to_string(),
+        None => req.uri().path().to_string(),
+    };
+    let method = req.method().to_string();
+    let actor = initiator(&state, req.headers());
+    let request_id = current_request_id();
+
+    let content_type = req
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+    // only JSON bodies are read up front, uploads are recorded by their size
+    let (req, payload) = if content_type.starts_with("application/json") {
+        let (parts, body) = req.into_parts();
+        let body = match hyper::body::to_bytes(body).await {
+            Ok(body) => body,
+            Err(err) => return ApiError::bad_request(err).into_response(),
+        };
+        let payload = summarize_json(&body);
+        (Request::from_parts(parts, Body::from(body)), payload)
+    } else {
+        let length = req
+            .headers()
+            .get(CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok());
+        let payload = length.map(|length| format!("<{content_type} {length} bytes>"));
+        (req, payload)
+    };
+
+    let entry = NewAuditEntry {
+        bot_id: &state.bot_id,
+        actor: &actor,
+        method: &method,
+        endpoint: &endpoint,
+        payload: payload.as_deref(),
+        request_id: request_id.as_deref(),
+    };
+    let id = match state.storage.start_audit(entry).await {
+        Ok(id) => id,
+        Err(err) => {
+            // the call still goes through, only its entry is missing
+            error!("failed to record {method} {endpoint} in the audit log: {err}");
+            return next.run(req).await;
+        }
+    };
+
+    let response = AUDIT_ID.scope(id, next.run(req)).await;
+    if let Err(err) = state
+        .storage
+        .finish_audit(id, response.status().as_u16() as i32)
+        .await
+    {
+        error!("failed to record the result of audit entry {id}: {err}");
+    }
+
+    response
+}
+
+/// A short readable version of a JSON body, with long strings and arrays
+/// like base64 images replaced by their length.
+fn summarize_json(body: &Bytes) -> Option<String> {
+    if body.is_empty() {
+        return None;
+    }
+
+    let summary = match serde_json::from_slice::<Value>(body) {
+        Ok(value) => summarize(value).to_string(),
+        Err(_) => format!("<invalid json {} bytes>", body.len()),
+    };
+
+    Some(summary.chars().take(MAX_SUMMARY_LENGTH).collect())
+}
+
+fn summarize(value: Value) -> Value {
+    match value {
+        Value::String(text) if text.chars().count() > MAX_SUMMARY_STRING => {
+            Value::String(format!("<{} chars>", text.chars().count()))
+        }
+        Value::Array(items) if items.len() > MAX_SUMMARY_ITEMS => {
+            Value::String(format!("<{} items>", items.len()))
+        }
+        Value::Array(items) => Value::Array(items.into_iter().map(summarize).collect()),
+        Value::Object(fields) => Value::Object(
+            fields
+                .into_iter()
+                .map(|(key, value)| (key, summarize(value)))
+                .collect(),
+        ),
+        value => value,
+    }
+}
+
+impl AppState {
+    pub async fn prune_audit_log(state: Self) -> anyhow::Result<()> {
+        loop {
+            state.heartbeat("prune_audit_log");
+            if let Err(err) = state.delete_old_audit_entries(Utc::now()).await {
+                error!("failed to prune the audit log: {err}");
+            }
+            tokio::time::sleep(AUDIT_PRUNE_INTERVAL).await;
+        }
+    }
+
+    /// Deletes the entries older than `audit_retention`.
+    pub async fn delete_old_audit_entries(&self, now: DateTime<Utc>) -> anyhow::Result<()> {
+        if self.config.audit_retention == 0 {
+            return Ok(());
+        }
+
+        let before = now - chrono::Duration::seconds(self.config.audit_retention as i64);
+        let deleted = self
+            .storage
+            .delete_audit_entries(&self.bot_id, before)
+            .await?;
+        if deleted > 0 {
+            info!("deleted {deleted} audit log entries");
+        }
+
+        Ok(())
+    }
+
+    /// Newest first, at most `limit` entries.
+    pub async fn get_audit_log(
+        &self,
+        since: Option<DateTime<Utc>>,
+        actor: Option<&str>,
+        request_id: Option<&str>,
+        limit: i64,
+    ) -> anyhow::Result<Vec<AuditEntry>> {
+        self.storage
+            .get_audit_log(&self.bot_id, since, actor, request_id, limit)
+            .await
+    }
+}
@@ -0,0 +1,117 @@
+use std::fmt::Write;
+
+use axum::{
+    body::Body,
+    http::{header::CONTENT_LENGTH, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use hyper::body::HttpBody;
+
+use crate::{error::ApiError, state::AppState};
+
+/// Middleware of the endpoints queueing messages: bodies over
+/// `max_payload_bytes` are refused with 413 and, while the bot has
+/// `max_pending_messages` waiting, new messages are refused with 429 until
+/// the queue drains.
+pub async fn backpressure(req: Request<Body>, next: Next<Body>) -> Response {
+    let Some(state) = req.extensions().get::<AppState>().cloned() else {
+        return next.run(req).await;
+    };
+
+    let req = match limit_body(req, state.config.max_payload_bytes).await {
+        Ok(req) => req,
+        Err(err) => return err.into_response(),
+    };
+    if let Err(err) = check_queue_depth(&state).await {
+        return err.into_response();
+    }
+
+    next.run(req).await
+}
+
+/// Refuses bodies declaring a length over the limit up front, bodies of
+/// unknown length are read up to it.
+async fn limit_body(req: Request<Body>, limit: usize) -> Result<Request<Body>, ApiError> {
+    if limit == 0 {
+        return Ok(req);
+    }
+    let too_large = || {
+        ApiError::new(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            "payload_too_large",
+            format!("the request body is larger than {limit} bytes"),
+        )
+    };
+
+    let length = req
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|length| length.to_str().ok()?.parse::<usize>().ok());
+    match length {
+        Some(length) if length > limit => Err(too_large()),
+        Some(_) => Ok(req),
+        None => {
+            let (parts, mut body) = req.into_parts();
+            let mut buffered = Vec::new();
+            while let Some(chunk) = body.data().await {
+                let chunk = chunk.map_err(ApiError::bad_request)?;
+                if buffered.len() + chunk.len() > limit {
+                    return Err(too_large());
+                }
+                buffered.extend_from_slice(&chunk);
+            }
+
+            Ok(Request::from_parts(parts, Body::from(buffered)))
+        }
+    }
+}
+
+async fn check_queue_depth(state: &AppState) -> Result<(), ApiError> {
+    let limit = state.config.max_pending_messages;
+    if limit == 0 {
+        return Ok(());
+    }
+
+    let depth = state.queue_depth().await?;
+    if depth >= limit {
+        return Err(ApiError::new(
+            StatusCode::TOO_MANY_REQUESTS,
+            "queue_full",
+            format!("{depth} messages are waiting in the queue, the limit is {limit}"),
+        ));
+    }
+
+    Ok(())
+}
+
+impl AppState {
+    /// Messages of the bot waiting to be sent, see
+    /// [`Storage::get_queue_depths`](crate::storage::Storage::get_queue_depths).
+    pub async fn queue_depth(&self) -> anyhow::Result<usize> {
+        let depths = self.storage.get_queue_depths().await?;
+
+        Ok(depths
+            .into_iter()
+            .find(|(bot_id, _)| **bot_id == *self.bot_id)
+            .map_or(0, |(_, depth)| depth as usize))
+    }
+
+    /// The queue depth of every bot in the Prometheus text format.
+    pub async fn queue_metrics(&self) -> anyhow::Result<String> {
+        let mut metrics = String::new();
+        let _ = writeln!(
+            metrics,
+            "# HELP telegram_sender_queue_depth Queued messages not sent yet.\n\
+             # TYPE telegram_sender_queue_depth gauge"
+        );
+        for (bot_id, depth) in self.storage.get_queue_depths().await? {
+            let _ = writeln!(
+                metrics,
+                "telegram_sender_queue_depth{{bot=\"{bot_id}\"}} {depth}"
+            );
+        }
+
+        Ok(metrics)
+    }
+}
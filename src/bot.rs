@@ -1,16 +1,108 @@
+use anyhow::Context;
 use teloxide::{
-    dispatching::UpdateFilterExt,
+    dispatching::{HandlerExt, UpdateFilterExt},
     dptree,
+    error_handlers::LoggingErrorHandler,
+    payloads::{AnswerCallbackQuerySetters, EditMessageTextSetters},
     prelude::Dispatcher,
-    requests::Requester,
-    types::{Message, Update},
+    requests::{Requester, RequesterExt},
+    types::{
+        CallbackQuery, ChatId, ChatJoinRequest, ChatMemberUpdated, Message, Poll, Update, User,
+        UserId,
+    },
+    utils::command::BotCommands,
+    Bot,
 };
 use tracing::{error, info, warn};
 
-use crate::state::{AppState, WrappedBot};
+use crate::{
+    admin::ChatAdmin,
+    captcha::parse_captcha_callback,
+    config::Config,
+    join_request::parse_join_callback,
+    moderation::{display_name, LinkPolicy},
+    reaction::ReactionPolling,
+    state::{AppState, WrappedBot},
+    stats::{MEMBER_JOINED, MEMBER_LEFT},
+    subscriber::UNSUBSCRIBE_CALLBACK,
+    welcome::Welcome,
+};
+
+#[derive(BotCommands, Clone)]
+#[command(rename_rule = "lowercase", description = "Supported commands:")]
+pub(crate) enum Command {
+    #[command(description = "receive broadcasts as direct messages.")]
+    Start(String),
+    #[command(description = "stop receiving broadcasts as direct messages.")]
+    Stop,
+    #[command(description = "stop receiving broadcasts in this chat.")]
+    Mute,
+    #[command(description = "receive broadcasts in this chat again.")]
+    Unmute,
+    #[command(
+        description = "reply to a message to broadcast a copy of it to `all` chats or to the given chat ids."
+    )]
+    Broadcast(String),
+    #[command(
+        description = "greet new members with the given text, `{name}` and `{username}` are replaced. Without text the greeting is turned off."
+    )]
+    SetWelcome(String),
+    #[command(description = "delete messages containing the given word or phrase.")]
+    Filter(String),
+    #[command(description = "stop deleting messages containing the given word or phrase.")]
+    Unfilter(String),
+    #[command(description = "`allow` or `delete` messages with links.")]
+    Links(String),
+    #[command(description = "`keep` or `delete` the messages about members joining and leaving.")]
+    ServiceMessages(String),
+    #[command(description = "reply to a message to clear the warnings of its sender.")]
+    Unwarn,
+    #[command(
+        description = "reply to a message or give a user id to warn the member, followed by the reason."
+    )]
+    Warn(String),
+    #[command(
+        description = "reply to a message or give a user id to remove the member, followed by the reason."
+    )]
+    Kick(String),
+    #[command(
+        description = "reply to a message or give a user id to ban the member, followed by the reason."
+    )]
+    Ban(String),
+    #[command(description = "give a user id to let a banned user join again.")]
+    Unban(String),
+    #[command(
+        description = "put this chat into the given chat group to receive its broadcasts. Without a name the chat leaves its group."
+    )]
+    SetGroup(String),
+}
+
+/// Creates the bot client, pointed at `telegram_api_url` and going through
+/// `telegram_proxy_url` when they are set. The dispatcher and the sends share
+/// it.
+pub fn build(config: &Config, token: &str) -> anyhow::Result<WrappedBot> {
+    let mut client = teloxide::net::default_reqwest_settings()
+        .connect_timeout(config.telegram_connect_timeout())
+        .timeout(config.telegram_timeout());
+    if let Some(url) = &config.telegram_proxy_url {
+        // the url may carry credentials, so it is left out of the error
+        let proxy = reqwest::Proxy::all(url).context("bad TELEGRAM_PROXY_URL")?;
+        client = client.proxy(proxy);
+    }
+
+    let mut bot = Bot::with_client(token, client.build()?);
+    if let Some(url) = &config.telegram_api_url {
+        let url = url
+            .parse()
+            .with_context(|| format!("bad TELEGRAM_API_URL: {url}"))?;
+        bot = bot.set_api_url(url);
+    }
+
+    Ok(bot.throttle(config.throttle.limits()))
+}
 
 pub async fn run(state: AppState) -> anyhow::Result<()> {
-    info!("starting telegram bot...");
+    info!("starting telegram bot {}...", state.bot_id);
 
     let bot = state.bot.clone();
 
@@ -32,20 +124,417 @@ pub async fn run(state: AppState) -> anyhow::Result<()> {
     //     debug!("LOOP");
     // }
 
+    // the dispatcher derives `allowed_updates` from the branches below, so
+    // filtering `chat_member` here is what makes telegram deliver those updates
     let handler = dptree::entry()
+        .branch(
+            Update::filter_message()
+                .filter_command::<Command>()
+                .endpoint(handle_command),
+        )
         .branch(Update::filter_message().endpoint(handle_message))
-        .branch(Update::filter_edited_message().endpoint(handle_message));
+        .branch(Update::filter_edited_message().endpoint(handle_message))
+        .branch(Update::filter_channel_post().endpoint(handle_channel_post))
+        .branch(Update::filter_chat_member().endpoint(handle_chat_member))
+        .branch(Update::filter_my_chat_member().endpoint(handle_my_chat_member))
+        .branch(Update::filter_poll().endpoint(handle_poll))
+        .branch(Update::filter_chat_join_request().endpoint(handle_chat_join_request))
+        .branch(Update::filter_callback_query().endpoint(handle_callback_query));
 
+    // reactions are asked for and recorded by the listener, teloxide can't
+    // parse them yet
+    let listener = ReactionPolling::new(state.clone()).await;
     Dispatcher::builder(bot, handler)
         .dependencies(dptree::deps![state])
         .build()
-        .dispatch()
+        .dispatch_with_listener(
+            listener,
+            LoggingErrorHandler::with_custom_text("An error from the update listener"),
+        )
         .await;
 
     Ok(())
 }
 
-async fn handle_message(message: Message, bot: WrappedBot, state: AppState) -> anyhow::Result<()> {
+pub(crate) async fn handle_command(
+    message: Message,
+    bot: WrappedBot,
+    state: AppState,
+    command: Command,
+) -> anyhow::Result<()> {
+    info!("got a command! {message:?}");
+
+    let chat = &message.chat;
+    let Some(user) = message.from() else {
+        return Ok(());
+    };
+
+    if let Command::Broadcast(targets) = &command {
+        return handle_broadcast_command(&message, &bot, &state, user.id.0 as i64, targets).await;
+    }
+
+    if chat.is_private() {
+        return handle_subscription_command(&bot, &state, chat.id, user, command).await;
+    }
+    if matches!(command, Command::Start(_) | Command::Stop) {
+        return Ok(());
+    }
+
+    if !bot.get_chat_member(chat.id, user.id).await?.is_privileged() {
+        bot.send_message(chat.id, "Only chat admins can do that.")
+            .await?;
+        return Ok(());
+    }
+
+    if !state.new_chat(chat).await? {
+        return Ok(());
+    }
+
+    if let Command::Warn(_) | Command::Kick(_) | Command::Ban(_) | Command::Unban(_) = &command {
+        return handle_moderation_command(&message, &bot, &state, user, &command).await;
+    }
+    if let Command::SetGroup(group) = &command {
+        let reply = set_group_reply(&state, chat.id.0, user.id.0 as i64, group.trim()).await?;
+        bot.send_message(chat.id, reply).await?;
+        return Ok(());
+    }
+
+    let reply = match command {
+        Command::Mute => {
+            state.set_chat_muted(chat.id.0, true).await?;
+            "Broadcasts are muted in this chat."
+        }
+        Command::Unmute => {
+            state.set_chat_muted(chat.id.0, false).await?;
+            "Broadcasts are unmuted in this chat."
+        }
+        Command::SetWelcome(template) => match template.trim() {
+            "" => {
+                state.delete_welcome(chat.id.0).await?;
+                "New members are no longer greeted."
+            }
+            template => {
+                // keep the auto-delete configured through the API
+                let delete_after_minutes = state
+                    .get_welcome(chat.id.0)
+                    .await?
+                    .and_then(|welcome| welcome.delete_after_minutes);
+                let welcome = Welcome {
+                    template: template.to_string(),
+                    delete_after_minutes,
+                };
+                if let Err(err) = state.set_welcome(chat.id.0, &welcome).await {
+                    bot.send_message(chat.id, err.to_string()).await?;
+                    return Ok(());
+                }
+                "New members will be greeted."
+            }
+        },
+        Command::Filter(word) | Command::Unfilter(word) if word.trim().is_empty() => {
+            "Give the word or phrase to filter."
+        }
+        Command::Filter(word) => {
+            let mut settings = state.get_moderation(chat.id.0).await?.unwrap_or_default();
+            let word = word.trim().to_lowercase();
+            if !settings.banned_words.contains(&word) {
+                settings.banned_words.push(word);
+            }
+            if let Err(err) = state.set_moderation(chat.id.0, &settings).await {
+                bot.send_message(chat.id, err.to_string()).await?;
+                return Ok(());
+            }
+            "Messages containing it will be deleted."
+        }
+        Command::Unfilter(word) => {
+            let word = word.trim().to_lowercase();
+            match state.get_moderation(chat.id.0).await? {
+                Some(mut settings) if settings.banned_words.contains(&word) => {
+                    settings.banned_words.retain(|banned| *banned != word);
+                    state.set_moderation(chat.id.0, &settings).await?;
+                    "Messages containing it are no longer deleted."
+                }
+                _ => "It isn't filtered.",
+            }
+        }
+        Command::Links(policy) => match policy.trim().parse() {
+            Ok(links) => {
+                let mut settings = state.get_moderation(chat.id.0).await?.unwrap_or_default();
+                settings.links = links;
+                state.set_moderation(chat.id.0, &settings).await?;
+                match links {
+                    LinkPolicy::Allow => "Links are allowed.",
+                    LinkPolicy::Delete => "Messages with links will be deleted.",
+                }
+            }
+            Err(_) => "Usage: /links allow | delete",
+        },
+        Command::ServiceMessages(mode) => match mode.trim() {
+            "keep" => {
+                state.set_delete_service_messages(chat.id.0, false).await?;
+                "Join and leave messages are kept."
+            }
+            "delete" => {
+                state.set_delete_service_messages(chat.id.0, true).await?;
+                "Join and leave messages will be deleted."
+            }
+            _ => "Usage: /servicemessages keep | delete",
+        },
+        Command::Unwarn => match message.reply_to_message().and_then(|reply| reply.from()) {
+            Some(offender) => match state
+                .reset_warnings(chat.id.0, offender.id.0 as i64)
+                .await?
+            {
+                true => "Their warnings are cleared.",
+                false => "They have no warnings.",
+            },
+            None => "Reply to a message of the member whose warnings to clear.",
+        },
+        Command::Broadcast(_)
+        | Command::Start(_)
+        | Command::Stop
+        | Command::Warn(_)
+        | Command::Kick(_)
+        | Command::Ban(_)
+        | Command::Unban(_)
+        | Command::SetGroup(_) => return Ok(()),
+    };
+    bot.send_message(chat.id, reply).await?;
+
+    Ok(())
+}
+
+/// `/setgroup` puts the chat into one of the configured chat groups, the
+/// admin's choice replaces the group it was in.
+async fn set_group_reply(
+    state: &AppState,
+    chat_id: i64,
+    admin_id: i64,
+    group: &str,
+) -> anyhow::Result<String> {
+    if group.is_empty() {
+        state.set_chat_group(chat_id, None, admin_id).await?;
+        return Ok("This chat left its chat group.".to_string());
+    }
+    if state.set_chat_group(chat_id, Some(group), admin_id).await? {
+        return Ok(format!("This chat is now in the chat group {group}."));
+    }
+
+    let mut known: Vec<_> = state
+        .config
+        .chat_groups
+        .keys()
+        .map(String::as_str)
+        .collect();
+    known.sort_unstable();
+    Ok(match known.is_empty() {
+        true => "No chat groups are configured.".to_string(),
+        false => format!(
+            "There is no chat group {group}, pick one of: {}",
+            known.join(", ")
+        ),
+    })
+}
+
+/// `/warn`, `/kick`, `/ban` and `/unban` act on the sender of the message
+/// replied to or on the user id given first, the rest is the reason.
+async fn handle_moderation_command(
+    message: &Message,
+    bot: &WrappedBot,
+    state: &AppState,
+    admin: &User,
+    command: &Command,
+) -> anyhow::Result<()> {
+    let chat = &message.chat;
+    let (Command::Warn(args) | Command::Kick(args) | Command::Ban(args) | Command::Unban(args)) =
+        command
+    else {
+        return Ok(());
+    };
+
+    let args = args.trim();
+    let (target, reason) = match message.reply_to_message().and_then(|reply| reply.from()) {
+        Some(target) => (Some(target.id), args),
+        None => {
+            let (id, reason) = args.split_once(char::is_whitespace).unwrap_or((args, ""));
+            (id.parse().ok().map(UserId), reason.trim())
+        }
+    };
+    let Some(target) = target else {
+        bot.send_message(
+            chat.id,
+            "Reply to a message of the member or give their user id.",
+        )
+        .await?;
+        return Ok(());
+    };
+    let member = bot.get_chat_member(chat.id, target).await?;
+    if member.is_privileged() {
+        bot.send_message(chat.id, "Chat admins can't be moderated.")
+            .await?;
+        return Ok(());
+    }
+
+    let reason = if reason.is_empty() {
+        "no reason given"
+    } else {
+        reason
+    };
+    let moderator = format!("admin:{}", admin.id);
+    let name = display_name(&member.user);
+    let reply = match command {
+        Command::Warn(_) => {
+            state
+                .warn_member(chat, &member.user, reason, &moderator)
+                .await?
+        }
+        Command::Kick(_) => {
+            state
+                .kick_member(chat, &member.user, reason, &moderator)
+                .await?;
+            format!("{name} was removed: {reason}.")
+        }
+        Command::Ban(_) => {
+            state
+                .ban_member(chat, &member.user, reason, &moderator)
+                .await?;
+            format!("{name} is banned: {reason}.")
+        }
+        Command::Unban(_) => {
+            state
+                .unban_member(chat, &member.user, reason, &moderator)
+                .await?;
+            format!("{name} can join again.")
+        }
+        _ => return Ok(()),
+    };
+    bot.send_message(chat.id, reply).await?;
+
+    Ok(())
+}
+
+/// `/start` and `/stop` in a private chat manage the subscription to
+/// broadcasts sent to the `subscribers` audience.
+async fn handle_subscription_command(
+    bot: &WrappedBot,
+    state: &AppState,
+    chat_id: ChatId,
+    user: &User,
+    command: Command,
+) -> anyhow::Result<()> {
+    let reply = match command {
+        Command::Start(payload) => {
+            if !payload.is_empty() {
+                info!("user:{} started the bot from link {payload}", user.id);
+            }
+            match state.subscribe(user).await? {
+                true => "You are subscribed to broadcasts, send /stop to unsubscribe.",
+                false => "You are already subscribed, send /stop to unsubscribe.",
+            }
+        }
+        Command::Stop => match state.unsubscribe(user.id.0 as i64).await? {
+            true => "You are unsubscribed, send /start to subscribe again.",
+            false => "You are not subscribed, send /start to subscribe.",
+        },
+        Command::Mute
+        | Command::Unmute
+        | Command::Broadcast(_)
+        | Command::SetWelcome(_)
+        | Command::Filter(_)
+        | Command::Unfilter(_)
+        | Command::Links(_)
+        | Command::ServiceMessages(_)
+        | Command::Unwarn
+        | Command::Warn(_)
+        | Command::Kick(_)
+        | Command::Ban(_)
+        | Command::Unban(_)
+        | Command::SetGroup(_) => return Ok(()),
+    };
+    bot.send_message(chat_id, reply).await?;
+
+    Ok(())
+}
+
+/// Lets the configured admins broadcast a message they reply to, so content
+/// can be authored in a drafts channel and copied everywhere as is.
+async fn handle_broadcast_command(
+    message: &Message,
+    bot: &WrappedBot,
+    state: &AppState,
+    user_id: i64,
+    targets: &str,
+) -> anyhow::Result<()> {
+    let chat_id = message.chat.id;
+
+    if !state.settings().admin_ids.contains(&user_id) {
+        bot.send_message(chat_id, "Only bot admins can do that.")
+            .await?;
+        return Ok(());
+    }
+
+    let Some(source) = message.reply_to_message() else {
+        bot.send_message(chat_id, "Reply to the message you want to broadcast.")
+            .await?;
+        return Ok(());
+    };
+
+    let chats: Vec<i64> = match targets.trim() {
+        "all" => state
+            .get_chats()
+            .await?
+            .into_iter()
+            .map(|chat| chat.id)
+            .collect(),
+        targets => match targets.split_whitespace().map(str::parse).collect() {
+            Ok(chats) => chats,
+            Err(_) => {
+                bot.send_message(chat_id, "Usage: /broadcast all | <chat id>...")
+                    .await?;
+                return Ok(());
+            }
+        },
+    };
+    if chats.is_empty() {
+        bot.send_message(chat_id, "Usage: /broadcast all | <chat id>...")
+            .await?;
+        return Ok(());
+    }
+
+    let text = source
+        .text()
+        .or_else(|| source.caption())
+        .unwrap_or_default()
+        .to_string();
+    let count = chats.len();
+    let id = state
+        .queue_copied_message(
+            chats,
+            chat_id.0,
+            source.id.0,
+            text,
+            chrono::Utc::now().to_rfc3339(),
+            Default::default(),
+        )
+        .await?;
+
+    bot.send_message(chat_id, format!("Queued message {id} to {count} chats."))
+        .await?;
+
+    Ok(())
+}
+
+/// Channels send their posts as channel posts rather than messages.
+pub(crate) async fn handle_channel_post(message: Message, state: AppState) -> anyhow::Result<()> {
+    info!("got a channel post! {message:?}");
+
+    state.channel_post(&message).await
+}
+
+pub(crate) async fn handle_message(
+    message: Message,
+    bot: WrappedBot,
+    state: AppState,
+) -> anyhow::Result<()> {
     info!("got a new message! {message:?}");
 
     if let teloxide::types::MessageKind::Common(m) = &message.kind {
@@ -69,31 +558,322 @@ async fn handle_message(message: Message, bot: WrappedBot, state: AppState) -> a
         return Ok(());
     }
 
-    state.new_chat(&chat).await?;
+    // registering the chat overwrites its name, record the rename first
+    if let teloxide::types::MessageKind::NewChatTitle(m) = &message.kind {
+        state.rename_chat(chat_id, &m.new_chat_title).await?;
+    }
 
+    if !state.new_chat(chat).await? {
+        return Ok(());
+    }
+
+    let sender_id = message.from().map(|user| user.id.0 as i64);
     if let Some(user) = message.from() {
         state.new_chat_member(chat_id, user).await?;
     }
 
+    // outside forums `thread_id` is also set on replies, only topics count
+    if let (teloxide::types::MessageKind::Common(m), Some(thread_id)) =
+        (&message.kind, message.thread_id)
+    {
+        if m.is_topic_message {
+            state.record_topic(chat_id, thread_id, None).await?;
+        }
+    }
+
     match message.kind {
         teloxide::types::MessageKind::Common(_) => {
-            //handle a basic message
+            match state.moderate(&message).await {
+                Ok(Some(_)) => return Ok(()),
+                Ok(None) => {}
+                Err(err) => error!("failed to moderate a message in chat:{chat_id} {err}"),
+            }
+            // service messages like joins don't count as activity
+            if let Some(user_id) = sender_id {
+                state
+                    .record_activity(chat_id, user_id, message.date)
+                    .await?;
+            }
+            if let Err(err) = state.record_reply(&message).await {
+                error!("failed to record a reply in chat:{chat_id} {err}");
+            }
         }
         teloxide::types::MessageKind::NewChatMembers(m) => {
             // handle a new chat member!
-            state.new_chat_members(chat_id, m.new_chat_members).await?;
-            bot.delete_message(message.chat.id, message.id).await?;
+            for member in &m.new_chat_members {
+                state
+                    .log_member_event(chat_id, member.id.0 as i64, MEMBER_JOINED)
+                    .await?;
+            }
+            state
+                .new_chat_members(chat_id, m.new_chat_members.clone())
+                .await?;
+            if state.deletes_service_messages(chat_id).await? {
+                bot.delete_message(message.chat.id, message.id).await?;
+            }
+            if let Err(err) = state
+                .challenge_members(&message.chat, &m.new_chat_members)
+                .await
+            {
+                error!("failed to challenge new members of chat:{chat_id} {err}");
+            }
+            if let Err(err) = state.greet_members(chat_id, &m.new_chat_members).await {
+                error!("failed to greet new members of chat:{chat_id} {err}");
+            }
         }
         teloxide::types::MessageKind::LeftChatMember(m) => {
+            state
+                .log_member_event(chat_id, m.left_chat_member.id.0 as i64, MEMBER_LEFT)
+                .await?;
             state
                 .remove_chat_member(chat_id, &m.left_chat_member)
                 .await?;
-            bot.delete_message(message.chat.id, message.id).await?;
+            if state.deletes_service_messages(chat_id).await? {
+                bot.delete_message(message.chat.id, message.id).await?;
+            }
+        }
+        teloxide::types::MessageKind::ForumTopicCreated(m) => {
+            if let Some(thread_id) = message.thread_id {
+                state
+                    .record_topic(chat_id, thread_id, Some(&m.forum_topic_created.name))
+                    .await?;
+            }
+        }
+        teloxide::types::MessageKind::ForumTopicEdited(m) => {
+            if let (Some(thread_id), Some(name)) = (message.thread_id, &m.forum_topic_edited.name) {
+                state.record_topic(chat_id, thread_id, Some(name)).await?;
+            }
+        }
+        teloxide::types::MessageKind::NewChatTitle(_) => {
+            // renamed before the chat was registered
+        }
+        teloxide::types::MessageKind::GroupChatCreated(_) => {
+            state.new_chat(&message.chat).await?;
         }
-        teloxide::types::MessageKind::GroupChatCreated(_) => state.new_chat(&message.chat).await?,
         // teloxide::types::MessageKind::SupergroupChatCreated(_) => todo!(),
         _ => warn!("unhandled message type!"),
     }
 
     Ok(())
 }
+
+pub(crate) async fn handle_chat_member(
+    update: ChatMemberUpdated,
+    state: AppState,
+) -> anyhow::Result<()> {
+    info!("got a chat member update! {update:?}");
+
+    if update.chat.is_private() {
+        return Ok(());
+    }
+
+    let chat_id = update.chat.id.0;
+    let old = &update.old_chat_member;
+    let new = &update.new_chat_member;
+    let user_id = new.user.id.0 as i64;
+
+    if !state.new_chat(&update.chat).await? {
+        return Ok(());
+    }
+    state.cache_member_kind(chat_id, new);
+
+    if let Some(admin) = ChatAdmin::from_member(new) {
+        info!("member {} was promoted in chat:{chat_id}", new.user.id);
+        state.upsert_chat_admin(chat_id, &admin).await?;
+        state.remove_chat_member(chat_id, &new.user).await?;
+        return Ok(());
+    }
+    if old.is_privileged() {
+        state.remove_chat_admin(chat_id, user_id).await?;
+    }
+
+    if new.is_present() {
+        if old.is_privileged() {
+            info!("member {} was demoted in chat:{chat_id}", new.user.id);
+        } else if !old.is_present() {
+            info!("member {} joined chat:{chat_id}", new.user.id);
+            state
+                .log_member_event(chat_id, user_id, MEMBER_JOINED)
+                .await?;
+        }
+        state.upsert_chat_member(chat_id, &new.user).await?;
+    } else if new.is_banned() {
+        info!("member {} was banned from chat:{chat_id}", new.user.id);
+        state
+            .log_member_event(chat_id, user_id, MEMBER_LEFT)
+            .await?;
+        state.remove_chat_member(chat_id, &new.user).await?;
+    } else {
+        info!("member {} left chat:{chat_id}", new.user.id);
+        state
+            .log_member_event(chat_id, user_id, MEMBER_LEFT)
+            .await?;
+        state.remove_chat_member(chat_id, &new.user).await?;
+    }
+
+    Ok(())
+}
+
+/// Changes of the bot's own membership, so chats are registered as soon as
+/// the bot is added and archived as soon as it is removed instead of on the
+/// next message or deprecated chat check.
+pub(crate) async fn handle_my_chat_member(
+    update: ChatMemberUpdated,
+    state: AppState,
+) -> anyhow::Result<()> {
+    info!("got a bot membership update! {update:?}");
+
+    if update.chat.is_private() {
+        return Ok(());
+    }
+
+    let chat_id = update.chat.id.0;
+    if update.new_chat_member.is_present() {
+        if !update.old_chat_member.is_present() {
+            info!("bot was added to chat:{chat_id}");
+        }
+        state.new_chat(&update.chat).await?;
+    } else if state.chats_status.contains_key(&chat_id) {
+        // chats of other bots are left alone
+        info!("bot was removed from chat:{chat_id}");
+        state
+            .archive_kicked_chat(chat_id, update.chat.title().unwrap_or_default())
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Requests to join chats that require approval, decided by the chat's join
+/// policy.
+pub(crate) async fn handle_chat_join_request(
+    request: ChatJoinRequest,
+    state: AppState,
+) -> anyhow::Result<()> {
+    info!("got a join request! {request:?}");
+
+    if !state.new_chat(&request.chat).await? {
+        return Ok(());
+    }
+    state.handle_join_request(&request).await?;
+
+    Ok(())
+}
+
+/// Presses of the approve and decline buttons sent to the bot admins for
+/// join requests.
+pub(crate) async fn handle_callback_query(
+    query: CallbackQuery,
+    bot: WrappedBot,
+    state: AppState,
+) -> anyhow::Result<()> {
+    let Some(data) = query.data.as_deref() else {
+        return Ok(());
+    };
+    if data == UNSUBSCRIBE_CALLBACK {
+        return handle_unsubscribe_callback(query, bot, state).await;
+    }
+    if let Some(user_id) = parse_captcha_callback(data) {
+        return handle_captcha_callback(query, bot, state, user_id).await;
+    }
+    let Some((approve, id)) = parse_join_callback(data) else {
+        return Ok(());
+    };
+
+    let admin_id = query.from.id.0 as i64;
+    let answer = if !state.settings().admin_ids.contains(&admin_id) {
+        "Only bot admins can do that.".to_string()
+    } else {
+        match state.get_join_request(id).await? {
+            None => "The request is gone.".to_string(),
+            Some(request) => match state
+                .decide_join_request(&request, approve, &format!("admin:{admin_id}"))
+                .await
+            {
+                Ok(Some(decided)) => format!("The request was {}.", decided.status),
+                Ok(None) => format!("The request was {} already.", request.status),
+                Err(err) => {
+                    warn!("error deciding join request {id}: {err}");
+                    format!("Telegram refused: {err}")
+                }
+            },
+        }
+    };
+
+    // the buttons go away once the request is decided
+    if let Some(message) = &query.message {
+        let text = format!("{}\n\n{answer}", message.text().unwrap_or_default());
+        if let Err(err) = bot
+            .edit_message_text(message.chat.id, message.id, text)
+            .await
+        {
+            warn!("error updating join request message: {err}");
+        }
+    }
+    bot.answer_callback_query(query.id).text(answer).await?;
+
+    Ok(())
+}
+
+/// Only the member a captcha button was sent to can press it.
+async fn handle_captcha_callback(
+    query: CallbackQuery,
+    bot: WrappedBot,
+    state: AppState,
+    user_id: i64,
+) -> anyhow::Result<()> {
+    let answer = match &query.message {
+        Some(_) if query.from.id.0 as i64 != user_id => "This button is not for you.",
+        Some(message) => match state.solve_captcha(message.chat.id.0, user_id).await? {
+            true => "Welcome, you can write now.",
+            false => "There is nothing to verify.",
+        },
+        None => "There is nothing to verify.",
+    };
+    bot.answer_callback_query(query.id).text(answer).await?;
+
+    Ok(())
+}
+
+/// The button under broadcasts to the subscribers. The confirmation
+/// replaces the button, below the text of the broadcast when it has one.
+async fn handle_unsubscribe_callback(
+    query: CallbackQuery,
+    bot: WrappedBot,
+    state: AppState,
+) -> anyhow::Result<()> {
+    let user_id = query.from.id;
+    let answer = match state.unsubscribe(user_id.0 as i64).await? {
+        true => "You are unsubscribed, send /start to subscribe again.",
+        false => "You are not subscribed, send /start to subscribe.",
+    };
+
+    if let Some(message) = &query.message {
+        let result = match message.text() {
+            Some(text) => bot
+                .edit_message_text(message.chat.id, message.id, format!("{text}\n\n{answer}"))
+                .entities(message.entities().unwrap_or_default().to_vec())
+                .await
+                .map(|_| ()),
+            None => bot
+                .edit_message_reply_markup(message.chat.id, message.id)
+                .await
+                .map(|_| ()),
+        };
+        if let Err(err) = result {
+            warn!("error confirming the unsubscription of user:{user_id}: {err}");
+        }
+    }
+    bot.answer_callback_query(query.id).text(answer).await?;
+
+    Ok(())
+}
+
+/// Telegram sends updated counts for every poll the bot has sent.
+async fn handle_poll(poll: Poll, state: AppState) -> anyhow::Result<()> {
+    info!("got a poll update! {}", poll.id);
+
+    state.update_poll_results(&poll).await?;
+
+    Ok(())
+}
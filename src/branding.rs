@@ -0,0 +1,103 @@
+use std::collections::HashMap;
+
+use anyhow::bail;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+use utoipa::ToSchema;
+
+use crate::{format::MessageFormat, state::AppState};
+
+const MAX_LENGTH: usize = 512;
+
+/// Plain text put above and below every broadcast to a chat, escaped for the
+/// parse mode of each broadcast. Copied messages and polls go out unchanged.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema, sqlx::FromRow)]
+pub struct Branding {
+    #[serde(default)]
+    pub header: Option<String>,
+    #[serde(default)]
+    pub footer: Option<String>,
+}
+
+impl Branding {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        for (field, text) in [("header", &self.header), ("footer", &self.footer)] {
+            let Some(text) = text else {
+                continue;
+            };
+            if text.trim().is_empty() {
+                bail!("{field} is empty");
+            }
+            if text.chars().count() > MAX_LENGTH {
+                bail!("{field} is longer than {MAX_LENGTH} characters");
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.header.is_none() && self.footer.is_none()
+    }
+
+    /// The text of a broadcast with the header and footer around it, each
+    /// separated by an empty line.
+    pub fn apply(&self, text: &str, format: MessageFormat) -> String {
+        let header = self.header.iter().map(|header| format.escape(header));
+        let footer = self.footer.iter().map(|footer| format.escape(footer));
+
+        header
+            .chain([text.to_string()])
+            .chain(footer)
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+
+    /// Fields missing here taken from `other`.
+    fn or(self, other: &Branding) -> Branding {
+        Branding {
+            header: self.header.or_else(|| other.header.clone()),
+            footer: self.footer.or_else(|| other.footer.clone()),
+        }
+    }
+}
+
+impl AppState {
+    /// The chat's own branding, without what it gets from its chat group.
+    pub async fn get_branding(&self, chat_id: i64) -> anyhow::Result<Option<Branding>> {
+        self.storage.get_branding(chat_id).await
+    }
+
+    /// Branding broadcasts to each chat get. A chat without its own header
+    /// or footer takes the one of its chat group, of the first group by name
+    /// when it is in several.
+    pub async fn get_brandings(&self) -> anyhow::Result<HashMap<i64, Branding>> {
+        let mut brandings = self.storage.get_all_brandings(&self.bot_id).await?;
+        if self.config.chat_group_branding.is_empty() {
+            return Ok(brandings);
+        }
+
+        let mut groups: Vec<_> = self.get_chat_groups().await?.into_iter().collect();
+        groups.sort_by(|(a, _), (b, _)| a.cmp(b));
+        for (group, chats) in groups {
+            let Some(group_branding) = self.config.chat_group_branding.get(&group) else {
+                continue;
+            };
+            for chat_id in chats {
+                let branding = brandings.remove(&chat_id).unwrap_or_default();
+                brandings.insert(chat_id, branding.or(group_branding));
+            }
+        }
+
+        Ok(brandings)
+    }
+
+    /// An empty branding removes the chat's own, leaving the one of its
+    /// chat group.
+    pub async fn set_branding(&self, chat_id: i64, branding: &Branding) -> anyhow::Result<()> {
+        branding.validate()?;
+        info!("setting branding {branding:?} for chat:{chat_id}");
+
+        self.storage.set_branding(chat_id, branding).await
+    }
+}
@@ -0,0 +1,1324 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::bail;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use futures::{stream, TryStreamExt};
+use serde::{Deserialize, Serialize};
+use teloxide::{
+    payloads::{self, CopyMessageSetters, EditMessageTextSetters, PinChatMessageSetters},
+    requests::{HasPayload, Requester},
+    types::{ChatId, Message, MessageId, Poll},
+    RequestError,
+};
+use tokio::{
+    sync::Mutex,
+    time::{Instant, Interval, MissedTickBehavior},
+};
+use tracing::{error, info, instrument};
+use utoipa::ToSchema;
+
+use crate::{
+    attachment::Attachments,
+    format::MessageFormat,
+    language::pick_translation,
+    local_time::local_due,
+    media::Media,
+    poll::PollDefinition,
+    queue::parse_datetime,
+    sink::{off_telegram_chats, OFF_TELEGRAM},
+    split::split_message,
+    state::{AppState, QueuedMessage},
+    storage::NewQueuedMessage,
+    subscriber::Audience,
+    telegram_error::TelegramErrorClass,
+    telemetry::current_request_id,
+    topic::{ChatTarget, Targets},
+    variant::pick_variant,
+};
+
+/// Images sent per album, telegram's limit for media groups.
+const ALBUM_SIZE: usize = 10;
+
+/// Spaces out outgoing requests so a broadcast never exceeds the configured
+/// messages per second, independently of the throttle adaptor queueing.
+/// The chats a broadcast is sent to at once share it.
+pub struct Pacer(Mutex<Interval>);
+
+impl Pacer {
+    pub fn new(messages_per_sec: u32) -> Self {
+        let mut interval = tokio::time::interval(Duration::from_secs(1) / messages_per_sec.max(1));
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        Self(Mutex::new(interval))
+    }
+
+    pub async fn wait(&self) {
+        self.0.lock().await.tick().await;
+    }
+}
+
+/// Per-message sending options, accepted flattened into the send payloads
+/// and stored alongside the queued message.
+#[derive(Clone, Copy, Default, Serialize, Deserialize, ToSchema)]
+pub struct MessageOptions {
+    #[serde(default)]
+    pub parse_mode: MessageFormat,
+    #[serde(default)]
+    pub pin: bool,
+    #[serde(default)]
+    pub pin_silently: bool,
+    #[serde(default)]
+    pub silent: bool,
+    /// Keeps links in the text from expanding into a preview.
+    #[serde(default)]
+    pub disable_web_page_preview: bool,
+    /// Keeps the message from being forwarded and saved in the chats it is
+    /// sent to.
+    #[serde(default)]
+    pub protect_content: bool,
+    /// `silent` in channels instead, where every subscriber is notified of a
+    /// post.
+    #[serde(default)]
+    pub channel_silent: Option<bool>,
+    /// `protect_content` in channels instead.
+    #[serde(default)]
+    pub channel_protect_content: Option<bool>,
+    /// Who receives the message when no chats are given.
+    #[serde(default)]
+    pub audience: Audience,
+    /// Queued message whose broadcast this one answers, sent as a reply to
+    /// it in every chat it landed in.
+    #[serde(default)]
+    pub reply_to: Option<i32>,
+    /// Wall clock time like `2024-06-01T09:00` the message goes out at in
+    /// each chat's own time, chats without a UTC offset are on UTC.
+    /// `datetime` defaults to the first chat's turn.
+    #[serde(default, with = "crate::local_time::datetime_local")]
+    #[schema(value_type = Option<String>, example = "2024-06-01T09:00")]
+    pub datetime_local: Option<NaiveDateTime>,
+    /// Keeps the replies to the broadcast, listed under
+    /// `GET /broadcasts/{id}/replies`. Only with postgres, which keeps the
+    /// sent messages they are matched by.
+    #[serde(default)]
+    pub collect_replies: bool,
+    /// Deletes the broadcast from every chat this many seconds after it was
+    /// sent there, at most 48 hours as telegram refuses to delete older
+    /// messages. Only with postgres, which keeps the sent messages.
+    #[serde(default)]
+    pub delete_after_seconds: Option<i32>,
+    /// Forum topic of the chat currently sent to, set per chat while
+    /// broadcasting.
+    #[serde(skip)]
+    pub thread_id: Option<i32>,
+    /// Least time between two sends to the chat currently sent to, set for
+    /// chats with a rate limit override.
+    #[serde(skip)]
+    pub chat_interval: Option<Duration>,
+    /// Telegram message id of the replied to broadcast in the chat currently
+    /// sent to.
+    #[serde(skip)]
+    pub reply_to_message_id: Option<i32>,
+    /// Puts the configured unsubscribe button under the message, set for
+    /// the first message of broadcasts to the subscribers.
+    #[serde(skip)]
+    pub unsubscribe_button: bool,
+    /// Draft the message is sent from, deleted in the transaction queueing
+    /// the message so it is sent once.
+    #[serde(skip)]
+    pub draft_id: Option<i32>,
+}
+
+impl MessageOptions {
+    /// Makes the request answer the broadcast in `reply_to_message_id`. The
+    /// reply goes out anyway when that message was deleted.
+    pub fn reply_to<R>(&self, request: &mut R)
+    where
+        R: HasPayload,
+        R::Payload: ReplyPayload,
+    {
+        if let Some(message_id) = self.reply_to_message_id {
+            request.payload_mut().reply_to(MessageId(message_id));
+        }
+    }
+}
+
+/// Payloads of the requests a broadcast is sent with, see
+/// [`MessageOptions::reply_to`].
+pub trait ReplyPayload {
+    fn reply_to(&mut self, message_id: MessageId);
+}
+
+macro_rules! reply_payload {
+    ($($payload:ident),*) => {
+        $(
+            impl ReplyPayload for payloads::$payload {
+                fn reply_to(&mut self, message_id: MessageId) {
+                    self.reply_to_message_id = Some(message_id);
+                    self.allow_sending_without_reply = Some(true);
+                }
+            }
+        )*
+    };
+}
+
+reply_payload!(
+    SendMessage,
+    SendMediaGroup,
+    SendPoll,
+    SendLocation,
+    SendVenue,
+    CopyMessage
+);
+
+// the only one still taking the bare id
+impl ReplyPayload for payloads::SendSticker {
+    fn reply_to(&mut self, message_id: MessageId) {
+        self.reply_to_message_id = Some(message_id.0);
+        self.allow_sending_without_reply = Some(true);
+    }
+}
+
+#[derive(Serialize, ToSchema, sqlx::FromRow)]
+pub struct BroadcastProgress {
+    pub id: i32,
+    pub total: i64,
+    pub sent: i64,
+    pub failed: i64,
+    /// Chats held back by their quiet hours.
+    pub deferred: i64,
+    pub pin_failed: i64,
+    pub completed: bool,
+    /// When the first and the last chat were sent to, the time between them
+    /// is how long sending took. Missing until a chat was sent to.
+    pub first_delivered_at: Option<DateTime<Utc>>,
+    pub last_delivered_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, ToSchema, sqlx::FromRow)]
+pub struct Delivery {
+    pub chat_id: i64,
+    pub error: Option<String>,
+    pub pin_error: Option<String>,
+    pub delivered_at: DateTime<Utc>,
+    /// Set while the chat's quiet hours hold the message back.
+    pub deferred_until: Option<DateTime<Utc>>,
+    /// The API request that queued the message.
+    pub request_id: Option<String>,
+}
+
+/// A file telegram keeps for a broadcast in one chat. Its `file_id` sends the
+/// same file again without uploading it.
+#[derive(Clone, Debug, Serialize, ToSchema, sqlx::FromRow)]
+pub struct DeliveredFile {
+    pub chat_id: i64,
+    /// The telegram message carrying the file.
+    pub telegram_message_id: i32,
+    /// `photo` or `sticker`.
+    pub kind: String,
+    pub file_id: String,
+    /// The same for every copy of the file, whichever chat or bot it was
+    /// sent by.
+    pub file_unique_id: String,
+    /// Bytes, missing when telegram didn't tell.
+    pub file_size: Option<i64>,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl DeliveredFile {
+    /// The file a sent message carries, the largest size of a photo.
+    fn of(chat_id: i64, message: &Message) -> Option<DeliveredFile> {
+        let (kind, file, width, height) = match (message.photo(), message.sticker()) {
+            (Some(sizes), _) => {
+                let size = sizes.last()?;
+                ("photo", &size.file, size.width, size.height)
+            }
+            (None, Some(sticker)) => (
+                "sticker",
+                &sticker.file,
+                sticker.width.into(),
+                sticker.height.into(),
+            ),
+            (None, None) => return None,
+        };
+
+        Some(DeliveredFile {
+            chat_id,
+            telegram_message_id: message.id.0,
+            kind: kind.to_string(),
+            file_id: file.id.clone(),
+            file_unique_id: file.unique_id.clone(),
+            // teloxide fills in u32::MAX when telegram leaves the size out
+            file_size: (file.size != u32::MAX).then(|| file.size.into()),
+            width: width.try_into().unwrap_or(i32::MAX),
+            height: height.try_into().unwrap_or(i32::MAX),
+        })
+    }
+}
+
+/// A single outgoing message as it was actually sent to a chat.
+#[derive(Serialize, ToSchema)]
+pub struct SentMessage {
+    id: i32,
+    queue_id: Option<i32>,
+    telegram_message_id: Option<i32>,
+    text: String,
+    /// Hashes of the images sent as an album before the text.
+    media: Vec<String>,
+    error: Option<String>,
+    sent_at: DateTime<Utc>,
+}
+
+/// Outcome of an action in a single chat, like editing a broadcast or
+/// deleting the chat.
+#[derive(Serialize, ToSchema)]
+pub struct ChatResult {
+    pub chat_id: i64,
+    pub error: Option<String>,
+}
+
+/// Who a broadcast would reach if it was sent now.
+pub struct Recipients {
+    pub chats: Vec<i64>,
+    /// Targets that would be left out, with the error recorded for them.
+    pub skipped: Vec<ChatResult>,
+}
+
+/// Requests a broadcast makes per chat: one per album, one per part of the
+/// text and the pin.
+pub fn requests_per_chat(images: usize, parts: usize, pin: bool) -> u32 {
+    (images.div_ceil(ALBUM_SIZE) + parts.max(1) + usize::from(pin)) as u32
+}
+
+/// What a single chat received for a broadcast.
+struct SentBroadcast {
+    /// The first part of the text, which replies and pins refer to.
+    message_id: MessageId,
+    albums: Vec<i32>,
+    /// The further parts of a text too long for a single message and the
+    /// attachments sent after it.
+    parts: Vec<i32>,
+    poll: Option<Poll>,
+    /// Files telegram stored for the albums and attachments.
+    files: Vec<DeliveredFile>,
+}
+
+/// What a broadcast sends besides its text and images, the same in every
+/// chat.
+struct Extras<'a> {
+    poll: Option<&'a PollDefinition>,
+    attachments: &'a Attachments,
+}
+
+/// A variant of a broadcast ready to be sent, the queued message carries the
+/// variant's text.
+struct LoadedVariant {
+    message: QueuedMessage,
+    images: Vec<Media>,
+    weight: i32,
+}
+
+pub(crate) struct LandedMessage {
+    pub id: i32,
+    pub chat_id: i64,
+    pub telegram_message_id: i32,
+    pub media_message_ids: Vec<i32>,
+}
+
+/// The user blocked the bot or deleted their account, so nothing can reach
+/// their private chat any more.
+fn is_blocked<T>(result: &anyhow::Result<T>) -> bool {
+    result.as_ref().err().is_some_and(|err| {
+        matches!(
+            TelegramErrorClass::of_anyhow(err),
+            TelegramErrorClass::Kicked | TelegramErrorClass::UserGone
+        )
+    })
+}
+
+impl AppState {
+    /// Sends a queued message to every chat that has no delivery record yet,
+    /// so a broadcast interrupted by a restart continues where it stopped.
+    /// Chats in their quiet hours, or not at the local time the message is
+    /// scheduled for yet, are held back, the earliest time one of them can
+    /// be sent to is returned.
+    #[instrument(skip_all, fields(queue_id = message.id))]
+    pub async fn broadcast(
+        &self,
+        message: &QueuedMessage,
+    ) -> anyhow::Result<Option<DateTime<Utc>>> {
+        let options = message.options()?;
+        let images = self.load_images(message).await?;
+        let variants = self.load_variants(message).await?;
+        let weights: Vec<i32> = variants.iter().map(|variant| variant.weight).collect();
+        let translations = self.load_translations(message).await?;
+        let languages = if translations.is_empty() {
+            HashMap::new()
+        } else {
+            self.get_chat_languages().await?
+        };
+        let poll = self.get_queued_poll(message.id).await?;
+        let attachments = self.storage.get_message_attachments(message.id).await?;
+        let extras = Extras {
+            poll: poll.as_ref(),
+            attachments: &attachments,
+        };
+        let delivered = self.get_delivered_chats(message.id).await?;
+        let muted = self.get_muted_chats().await?;
+        let unapproved = self.get_unapproved_chats().await?;
+        let threads = self.get_message_threads(message.id).await?;
+        let intervals = self.get_chat_intervals().await?;
+        let quiet_hours = self.get_all_quiet_hours().await?;
+        let brandings = self.get_brandings().await?;
+        let utc_offsets = match options.datetime_local {
+            Some(_) => self.get_chat_utc_offsets().await?,
+            None => HashMap::new(),
+        };
+        let replies = match options.reply_to {
+            Some(queue_id) => self.get_reply_targets(queue_id).await?,
+            None => HashMap::new(),
+        };
+        let off_telegram = if self.config.chat_group_sinks.is_empty() {
+            HashSet::new()
+        } else {
+            let groups = self.get_chat_groups().await?;
+            self.deliver_to_sinks(message, &groups).await?;
+            off_telegram_chats(&self.config.chat_group_sinks, &groups)
+        };
+        let channels =
+            if options.channel_silent.is_some() || options.channel_protect_content.is_some() {
+                self.get_channels().await?
+            } else {
+                HashSet::new()
+            };
+
+        let now = Utc::now();
+        let mut deferred_until: Option<DateTime<Utc>> = None;
+        let mut pending = Vec::new();
+        for &chat_id in &message.chats {
+            if delivered.contains(&chat_id) {
+                continue;
+            }
+            if muted.contains(&chat_id) {
+                info!("skipping muted chat:{chat_id} for message {}", message.id);
+                self.record_delivery(message.id, chat_id, Some("chat is muted".into()), None)
+                    .await?;
+                continue;
+            }
+            if off_telegram.contains(&chat_id) {
+                self.record_delivery(message.id, chat_id, Some(OFF_TELEGRAM.into()), None)
+                    .await?;
+                continue;
+            }
+            if unapproved.contains(&chat_id) {
+                info!(
+                    "skipping unapproved chat:{chat_id} for message {}",
+                    message.id
+                );
+                self.record_delivery(
+                    message.id,
+                    chat_id,
+                    Some("chat is not approved".into()),
+                    None,
+                )
+                .await?;
+                continue;
+            }
+            let local_due = options
+                .datetime_local
+                .map(|local| local_due(local, utc_offsets.get(&chat_id).copied().unwrap_or(0)))
+                .filter(|due| *due > now);
+            let deferral = match local_due {
+                Some(until) => Some((until, "its local time comes")),
+                None => quiet_hours
+                    .get(&chat_id)
+                    .and_then(|quiet_hours| quiet_hours.ends_at(now))
+                    .map(|until| (until, "its quiet hours end")),
+            };
+            if let Some((until, reason)) = deferral {
+                info!(
+                    "deferring message {} for chat:{chat_id} until {reason} at {until}",
+                    message.id
+                );
+                self.storage
+                    .defer_delivery(message.id, chat_id, until)
+                    .await?;
+                deferred_until = Some(deferred_until.map_or(until, |due| due.min(until)));
+                continue;
+            }
+            pending.push(chat_id);
+        }
+
+        info!(
+            "broadcasting message {} to {} chats, {} already delivered",
+            message.id,
+            pending.len(),
+            delivered.len()
+        );
+
+        let chats = pending.len();
+        let started = Instant::now();
+        let pacer = &Pacer::new(self.config.broadcast_rate);
+        let (weights, variants, images, extras) = (&weights, &variants, &images, &extras);
+        let (languages, translations) = (&languages, &translations);
+        let (threads, intervals, replies) = (&threads, &intervals, &replies);
+        let channels = &channels;
+        let brandings = &brandings;
+        // chats share the pacer, sending to several at once only overlaps
+        // the time each request takes
+        stream::iter(pending.into_iter().map(Ok))
+            .try_for_each_concurrent(
+                self.config.queue.chat_concurrency,
+                move |mut chat_id| async move {
+                    // a long broadcast is progress, not a stuck queue
+                    self.heartbeat("message_queue");
+                    let variant = pick_variant(message.id, chat_id, weights);
+                    let (message, images) = match variant {
+                        Some(index) => (&variants[index].message, &variants[index].images),
+                        None => {
+                            let language = languages.get(&chat_id).map(String::as_str);
+                            let translated = pick_translation(translations, language);
+                            (translated.unwrap_or(message), images)
+                        }
+                    };
+                    let branded;
+                    let message = match brandings.get(&chat_id) {
+                        Some(branding)
+                            if message.copy_message_id.is_none()
+                                && extras.poll.is_none()
+                                && !message.message.trim().is_empty() =>
+                        {
+                            branded = QueuedMessage {
+                                message: branding.apply(&message.message, options.parse_mode),
+                                ..message.clone()
+                            };
+                            &branded
+                        }
+                        _ => message,
+                    };
+                    let options = match channels.contains(&chat_id) {
+                        true => options.for_channel(),
+                        false => options,
+                    };
+                    let options = MessageOptions {
+                        thread_id: threads.get(&chat_id).copied(),
+                        chat_interval: intervals.get(&chat_id).copied(),
+                        reply_to_message_id: replies.get(&chat_id).copied(),
+                        unsubscribe_button: options.audience == Audience::Subscribers,
+                        ..options
+                    };
+                    let mut result = self
+                        .send_broadcast_to_chat(pacer, chat_id, message, images, extras, &options)
+                        .await;
+
+                    // the group became a supergroup, move it over and send there instead
+                    if let Some(TelegramErrorClass::Migrated(new_id)) =
+                        result.as_ref().err().map(TelegramErrorClass::of_anyhow)
+                    {
+                        info!(
+                            "chat:{chat_id} migrated to chat:{new_id}, resending message {}",
+                            message.id
+                        );
+                        self.migrate_chat(chat_id, new_id).await?;
+                        chat_id = new_id;
+                        result = self
+                            .send_broadcast_to_chat(
+                                pacer, chat_id, message, images, extras, &options,
+                            )
+                            .await;
+                    }
+
+                    if let Ok(SentBroadcast {
+                        poll: Some(sent_poll),
+                        ..
+                    }) = &result
+                    {
+                        self.record_sent_poll(message.id, chat_id, sent_poll)
+                            .await?;
+                    }
+
+                    let pin_error = match &result {
+                        Ok(sent) if options.pin => {
+                            self.pace(pacer, chat_id, &options).await;
+                            self.pin_broadcast(chat_id, sent.message_id, options.pin_silently)
+                                .await
+                                .err()
+                                .map(|err| err.to_string())
+                        }
+                        _ => None,
+                    };
+
+                    if options.audience == Audience::Subscribers && is_blocked(&result) {
+                        self.unsubscribe(chat_id).await?;
+                    }
+
+                    self.record_sent_message(
+                        chat_id,
+                        message.id,
+                        &message.message,
+                        images,
+                        &result,
+                        &options,
+                    )
+                    .await?;
+                    if let Some(variant) = variant {
+                        self.assign_variant(message.id, chat_id, variant).await?;
+                    }
+                    if let Ok(SentBroadcast { files, .. }) = &result {
+                        if !files.is_empty() {
+                            self.storage
+                                .record_delivered_files(message.id, files)
+                                .await?;
+                        }
+                    }
+                    let error = result.err().map(|err| err.to_string());
+                    self.record_delivery(message.id, chat_id, error, pin_error)
+                        .await?;
+                    self.publish_progress(message.id, chat_id).await?;
+                    Ok::<_, anyhow::Error>(())
+                },
+            )
+            .await?;
+        info!(
+            "sent message {} to {chats} chats in {:.1?}",
+            message.id,
+            started.elapsed()
+        );
+
+        Ok(deferred_until)
+    }
+
+    /// Waits for the next slot of the broadcast and, for chats with a rate
+    /// limit override, until the chat's interval since the last send passed.
+    async fn pace(&self, pacer: &Pacer, chat_id: i64, options: &MessageOptions) {
+        pacer.wait().await;
+
+        let Some(interval) = options.chat_interval else {
+            return;
+        };
+        let last_send = self.last_chat_sends.get(&chat_id).map(|last| *last);
+        if let Some(last_send) = last_send {
+            tokio::time::sleep_until(last_send + interval).await;
+        }
+        self.last_chat_sends.insert(chat_id, Instant::now());
+    }
+
+    /// Lock that serializes broadcasts to a chat. It is held for all the
+    /// albums and the text of a broadcast so they arrive contiguously, other
+    /// broadcasts to the chat wait their turn in the order they asked.
+    fn chat_send_lock(&self, chat_id: i64) -> Arc<Mutex<()>> {
+        self.chat_send_locks.entry(chat_id).or_default().clone()
+    }
+
+    /// Least time between two sends per chat with a rate limit override.
+    pub async fn get_chat_intervals(&self) -> anyhow::Result<HashMap<i64, Duration>> {
+        let intervals = self
+            .get_chats()
+            .await?
+            .into_iter()
+            .filter_map(|chat| {
+                let per_minute = chat.rate_limit_override?.max(1) as u32;
+                Some((chat.id, Duration::from_secs(60) / per_minute))
+            })
+            .collect();
+
+        Ok(intervals)
+    }
+
+    /// The chats a message would go to, like [`broadcast`](Self::broadcast)
+    /// and the resolving of the whole audience pick them but without writing
+    /// anything. Chats in their quiet hours are included, they only receive
+    /// it later.
+    pub async fn get_recipients(
+        &self,
+        targets: &Targets,
+        audience: Audience,
+    ) -> anyhow::Result<Recipients> {
+        let mut chats: Vec<i64> = match (targets.chats.as_deref(), audience) {
+            (Some(targets), _) => targets.iter().map(|target| target.chat_id()).collect(),
+            (None, Audience::Chats) => self
+                .get_chats()
+                .await?
+                .into_iter()
+                .filter(|chat| !chat.muted && chat.approved)
+                .map(|chat| chat.id)
+                .collect(),
+            (None, Audience::Subscribers) => self.get_subscribers().await?,
+        };
+        chats.retain(|chat_id| !targets.excluded.contains(chat_id));
+        if audience == Audience::Subscribers {
+            return Ok(Recipients {
+                chats,
+                skipped: Vec::new(),
+            });
+        }
+
+        let muted = self.get_muted_chats().await?;
+        let unapproved = self.get_unapproved_chats().await?;
+        let mut recipients = Recipients {
+            chats: Vec::new(),
+            skipped: Vec::new(),
+        };
+        for chat_id in chats {
+            let error = if muted.contains(&chat_id) {
+                "chat is muted"
+            } else if unapproved.contains(&chat_id) {
+                "chat is not approved"
+            } else {
+                recipients.chats.push(chat_id);
+                continue;
+            };
+            recipients.skipped.push(ChatResult {
+                chat_id,
+                error: Some(error.to_string()),
+            });
+        }
+
+        Ok(recipients)
+    }
+
+    /// Sends a queued message to a single preview chat without recording any
+    /// delivery, so it can be checked before the real broadcast.
+    #[instrument(skip_all, fields(queue_id = message.id, chat_id))]
+    pub async fn send_preview(&self, message: &QueuedMessage, chat_id: i64) -> anyhow::Result<()> {
+        info!("previewing message {} in chat:{chat_id}", message.id);
+
+        let options = message.options()?;
+        let images = self.load_images(message).await?;
+        let poll = self.get_queued_poll(message.id).await?;
+        let attachments = self.storage.get_message_attachments(message.id).await?;
+        let extras = Extras {
+            poll: poll.as_ref(),
+            attachments: &attachments,
+        };
+
+        let pacer = Pacer::new(self.config.broadcast_rate);
+        self.send_broadcast_to_chat(&pacer, chat_id, message, &images, &extras, &options)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn load_images(&self, message: &QueuedMessage) -> anyhow::Result<Vec<Media>> {
+        let mut images = self.load_image_entries(&message.images).await?;
+        images.extend(
+            self.get_message_images(message.id)
+                .await?
+                .into_iter()
+                .map(Media::new),
+        );
+
+        Ok(images)
+    }
+
+    /// Empty for messages sent the same way to every chat.
+    async fn load_variants(&self, message: &QueuedMessage) -> anyhow::Result<Vec<LoadedVariant>> {
+        let mut loaded = Vec::new();
+        for variant in self.get_message_variants(message.id).await? {
+            let message = QueuedMessage {
+                message: variant.message,
+                images: variant.images,
+                ..message.clone()
+            };
+            loaded.push(LoadedVariant {
+                images: self.load_images(&message).await?,
+                message,
+                weight: variant.weight,
+            });
+        }
+
+        Ok(loaded)
+    }
+
+    /// The message as sent to each language it was translated to, it only
+    /// differs in its text.
+    async fn load_translations(
+        &self,
+        message: &QueuedMessage,
+    ) -> anyhow::Result<HashMap<String, QueuedMessage>> {
+        let translations = self
+            .get_message_translations(message.id)
+            .await?
+            .into_iter()
+            .map(|(language, text)| {
+                let translated = QueuedMessage {
+                    message: text,
+                    ..message.clone()
+                };
+                (language, translated)
+            })
+            .collect();
+
+        Ok(translations)
+    }
+
+    #[instrument(skip_all, fields(queue_id = message.id, chat_id))]
+    async fn send_broadcast_to_chat(
+        &self,
+        pacer: &Pacer,
+        chat_id: i64,
+        message: &QueuedMessage,
+        images: &[Media],
+        extras: &Extras<'_>,
+        options: &MessageOptions,
+    ) -> anyhow::Result<SentBroadcast> {
+        let lock = self.chat_send_lock(chat_id);
+        let _sending = lock.lock().await;
+
+        if let (Some(from_chat_id), Some(message_id)) =
+            (message.copy_from_chat_id, message.copy_message_id)
+        {
+            self.pace(pacer, chat_id, options).await;
+            let message_id = self
+                .copy_message_to_chat(chat_id, from_chat_id, MessageId(message_id), options)
+                .await?;
+
+            return Ok(SentBroadcast {
+                message_id,
+                albums: Vec::new(),
+                parts: Vec::new(),
+                poll: None,
+                files: Vec::new(),
+            });
+        }
+
+        let mut albums = Vec::new();
+        let mut files = Vec::new();
+        for chunk in images.chunks(ALBUM_SIZE) {
+            let mut album = Vec::with_capacity(chunk.len());
+            for media in chunk {
+                album.push(self.input_media(media).await?);
+            }
+
+            self.pace(pacer, chat_id, options).await;
+            let sent = self.send_media_group(chat_id, album, options).await?;
+            self.remember_media(chunk, &sent).await?;
+            albums.extend(sent.iter().map(|message| message.id.0));
+            files.extend(
+                sent.iter()
+                    .filter_map(|message| DeliveredFile::of(chat_id, message)),
+            );
+        }
+
+        self.pace(pacer, chat_id, options).await;
+        if let Some(poll) = extras.poll {
+            let sent = self
+                .send_poll_to_chat(chat_id, &message.message, poll, options)
+                .await?;
+            return Ok(SentBroadcast {
+                message_id: sent.id,
+                albums,
+                parts: Vec::new(),
+                poll: sent.poll().cloned(),
+                files,
+            });
+        }
+
+        let mut sent = Vec::new();
+        let mut options = *options;
+        // a message of images or attachments only has no text
+        let texts = split_message(&message.message, options.parse_mode)
+            .into_iter()
+            .filter(|text| {
+                (images.is_empty() && extras.attachments.is_empty()) || !text.trim().is_empty()
+            });
+        for text in texts {
+            if !sent.is_empty() {
+                self.pace(pacer, chat_id, &options).await;
+            }
+            sent.push(self.send_message_to_chat(chat_id, &text, &options).await?);
+            // only the first part answers the replied to broadcast and
+            // carries the button
+            options.reply_to_message_id = None;
+            options.unsubscribe_button = false;
+        }
+        if let Some(sticker) = &extras.attachments.sticker {
+            if !sent.is_empty() {
+                self.pace(pacer, chat_id, &options).await;
+            }
+            sent.push(
+                self.send_sticker_to_chat(chat_id, sticker, &options)
+                    .await?,
+            );
+            options.reply_to_message_id = None;
+            options.unsubscribe_button = false;
+        }
+        if let Some(location) = &extras.attachments.location {
+            if !sent.is_empty() {
+                self.pace(pacer, chat_id, &options).await;
+            }
+            sent.push(
+                self.send_location_to_chat(chat_id, location, &options)
+                    .await?,
+            );
+        }
+
+        files.extend(
+            sent.iter()
+                .filter_map(|message| DeliveredFile::of(chat_id, message)),
+        );
+        let mut sent = sent.into_iter().map(|message| message.id);
+        // without any text the first image stands for the message
+        let message_id = match sent.next() {
+            Some(message_id) => message_id,
+            None if !albums.is_empty() => MessageId(albums.remove(0)),
+            None => bail!("the message has nothing to send"),
+        };
+        Ok(SentBroadcast {
+            message_id,
+            albums,
+            parts: sent.map(|id| id.0).collect(),
+            poll: None,
+            files,
+        })
+    }
+
+    /// Copies a message authored elsewhere, keeping its formatting, media and
+    /// buttons but without the "forwarded from" header.
+    async fn copy_message_to_chat(
+        &self,
+        chat_id: i64,
+        from_chat_id: i64,
+        message_id: MessageId,
+        options: &MessageOptions,
+    ) -> anyhow::Result<MessageId> {
+        info!("copying message {message_id} from chat:{from_chat_id} to chat:{chat_id}");
+
+        let mut request = self
+            .bot
+            .copy_message(ChatId(chat_id), ChatId(from_chat_id), message_id)
+            .disable_notification(options.silent)
+            .protect_content(options.protect_content);
+        if let Some(thread_id) = options.thread_id {
+            request = request.message_thread_id(thread_id);
+        }
+        options.reply_to(&mut request);
+        let copied = request.await?;
+
+        Ok(copied)
+    }
+
+    /// Replaces the text of a broadcast in every chat it was delivered to.
+    #[instrument(skip_all, fields(queue_id))]
+    pub async fn edit_broadcast(
+        &self,
+        queue_id: i32,
+        text: &str,
+        parse_mode: MessageFormat,
+    ) -> anyhow::Result<Vec<ChatResult>> {
+        let landed = self.get_landed_messages(queue_id).await?;
+        info!("editing message {queue_id} in {} chats", landed.len());
+
+        // an edit brings the link preview back unless it is disabled again
+        let disable_web_page_preview = sqlx::query_scalar!(
+            r#"
+SELECT disable_web_page_preview FROM message_queue
+WHERE id = $1
+            "#,
+            queue_id
+        )
+        .fetch_optional(self.pg()?)
+        .await?
+        .unwrap_or_default();
+
+        let pacer = Pacer::new(self.config.broadcast_rate);
+        let mut results = Vec::with_capacity(landed.len());
+        for sent in landed {
+            pacer.wait().await;
+
+            let mut request = self
+                .bot
+                .edit_message_text(
+                    ChatId(sent.chat_id),
+                    MessageId(sent.telegram_message_id),
+                    text,
+                )
+                .disable_web_page_preview(disable_web_page_preview);
+            if let Some(parse_mode) = parse_mode.parse_mode() {
+                request = request.parse_mode(parse_mode);
+            }
+
+            let error = match request.await {
+                Ok(_) => {
+                    sqlx::query!(
+                        r#"
+UPDATE sent_message
+SET text = $2, edited_at = now()
+WHERE id = $1
+                        "#,
+                        sent.id,
+                        text
+                    )
+                    .execute(self.pg()?)
+                    .await?;
+                    None
+                }
+                Err(err) => {
+                    error!("error editing message in chat {}: {err}", sent.chat_id);
+                    Some(err.to_string())
+                }
+            };
+
+            results.push(ChatResult {
+                chat_id: sent.chat_id,
+                error,
+            });
+        }
+
+        Ok(results)
+    }
+
+    /// Deletes a broadcast, including its image albums and the further parts
+    /// of a long text, from every chat it was delivered to.
+    #[instrument(skip_all, fields(queue_id))]
+    pub async fn delete_broadcast(&self, queue_id: i32) -> anyhow::Result<Vec<ChatResult>> {
+        let landed = self.get_landed_messages(queue_id).await?;
+        info!("deleting message {queue_id} from {} chats", landed.len());
+
+        let pacer = Pacer::new(self.config.broadcast_rate);
+        let mut results = Vec::with_capacity(landed.len());
+        for sent in landed {
+            let error = self.delete_landed_message(&pacer, &sent).await?;
+            results.push(ChatResult {
+                chat_id: sent.chat_id,
+                error: error.map(|err| err.to_string()),
+            });
+        }
+
+        Ok(results)
+    }
+
+    /// Deletes the parts of a broadcast that landed in one chat and marks it
+    /// deleted. Returns the last error telegram answered with, the message
+    /// is only marked when every part is gone.
+    pub(crate) async fn delete_landed_message(
+        &self,
+        pacer: &Pacer,
+        sent: &LandedMessage,
+    ) -> anyhow::Result<Option<RequestError>> {
+        let mut error = None;
+        for message_id in sent
+            .media_message_ids
+            .iter()
+            .chain([&sent.telegram_message_id])
+        {
+            pacer.wait().await;
+            if let Err(err) = self
+                .bot
+                .delete_message(ChatId(sent.chat_id), MessageId(*message_id))
+                .await
+            {
+                error!("error deleting message in chat {}: {err}", sent.chat_id);
+                error = Some(err);
+            }
+        }
+
+        if error.is_none() {
+            sqlx::query!(
+                r#"
+UPDATE sent_message
+SET deleted_at = now(), delete_at = NULL
+WHERE id = $1
+                "#,
+                sent.id
+            )
+            .execute(self.pg()?)
+            .await?;
+        }
+
+        Ok(error)
+    }
+
+    /// The chats a message was queued for with their topics, `None` for the
+    /// whole audience.
+    pub async fn get_queued_targets(
+        &self,
+        message: &QueuedMessage,
+    ) -> anyhow::Result<Option<Vec<ChatTarget>>> {
+        if message.all_chats {
+            return Ok(None);
+        }
+
+        let threads = self.get_message_threads(message.id).await?;
+        let targets = message
+            .chats
+            .iter()
+            .map(|&chat_id| match threads.get(&chat_id) {
+                Some(&thread_id) => ChatTarget::Thread { chat_id, thread_id },
+                None => ChatTarget::Chat(chat_id),
+            })
+            .collect();
+
+        Ok(Some(targets))
+    }
+
+    /// Queues a copy of a broadcast with its text, translations, options and
+    /// images, poll, variants or copied message. `targets` of `None` is the
+    /// whole audience, less the chats the original left out.
+    #[instrument(skip_all, fields(queue_id = message.id))]
+    pub async fn resend_broadcast(
+        &self,
+        message: &QueuedMessage,
+        targets: Option<Vec<ChatTarget>>,
+        datetime: String,
+    ) -> anyhow::Result<i32> {
+        info!("resending message {} on datetime: {datetime}", message.id);
+        let options = message.options()?;
+
+        if let Some(poll) = self.get_queued_poll(message.id).await? {
+            let chats = match targets {
+                Some(targets) => targets.iter().map(|target| target.chat_id()).collect(),
+                None if options.audience == Audience::Chats => {
+                    self.get_chats().await?.iter().map(|chat| chat.id).collect()
+                }
+                None => Vec::new(),
+            };
+            return self
+                .queue_poll(chats, message.message.clone(), poll, datetime, options)
+                .await;
+        }
+
+        // images uploaded before they were kept in `media_blob` move there
+        let media: Vec<Media> = self
+            .get_message_images(message.id)
+            .await?
+            .into_iter()
+            .map(Media::new)
+            .collect();
+        let images: Vec<String> = message
+            .images
+            .iter()
+            .cloned()
+            .chain(media.iter().map(Media::reference))
+            .collect();
+        let variants = self.get_message_variants(message.id).await?;
+        let translations = self.get_message_translations(message.id).await?;
+        let attachments = self.storage.get_message_attachments(message.id).await?;
+        let excluded_chats = match targets {
+            Some(_) => Vec::new(),
+            None => self.storage.get_excluded_chats(message.id).await?,
+        };
+        let copy_from = message.copy_from_chat_id.zip(message.copy_message_id);
+        self.storage
+            .queue_message(NewQueuedMessage {
+                bot_id: &self.bot_id,
+                targets: targets.as_deref(),
+                excluded_chats: &excluded_chats,
+                message: &message.message,
+                images: &images,
+                media: &media,
+                datetime: parse_datetime(&datetime)?,
+                options: &options,
+                awaiting_approval: false,
+                copy_from,
+                variants: &variants,
+                translations: &translations,
+                attachments: &attachments,
+                request_id: current_request_id().as_deref(),
+                duplicates_since: None,
+            })
+            .await
+    }
+
+    /// Telegram message id of the broadcast per chat it landed in, chats
+    /// without one get the reply as a plain message. Empty on sqlite, which
+    /// keeps no sent message history.
+    async fn get_reply_targets(&self, queue_id: i32) -> anyhow::Result<HashMap<i64, i32>> {
+        if self.pool.is_none() {
+            return Ok(HashMap::new());
+        }
+
+        let targets = self
+            .get_landed_messages(queue_id)
+            .await?
+            .into_iter()
+            .map(|sent| (sent.chat_id, sent.telegram_message_id))
+            .collect();
+
+        Ok(targets)
+    }
+
+    async fn get_landed_messages(&self, queue_id: i32) -> anyhow::Result<Vec<LandedMessage>> {
+        let landed = sqlx::query_as!(
+            LandedMessage,
+            r#"
+SELECT id, chat_id, telegram_message_id AS "telegram_message_id!", media_message_ids
+FROM sent_message
+WHERE queue_id = $1 AND telegram_message_id IS NOT NULL AND deleted_at IS NULL
+ORDER BY id
+            "#,
+            queue_id
+        )
+        .fetch_all(self.pg()?)
+        .await?;
+
+        Ok(landed)
+    }
+
+    async fn pin_broadcast(
+        &self,
+        chat_id: i64,
+        message_id: MessageId,
+        silently: bool,
+    ) -> anyhow::Result<()> {
+        info!("pinning message {message_id} in chat:{chat_id}");
+
+        self.bot
+            .pin_chat_message(ChatId(chat_id), message_id)
+            .disable_notification(silently)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn get_delivered_chats(&self, message_id: i32) -> anyhow::Result<Vec<i64>> {
+        self.storage.get_delivered_chats(message_id).await
+    }
+
+    async fn record_delivery(
+        &self,
+        message_id: i32,
+        chat_id: i64,
+        error: Option<String>,
+        pin_error: Option<String>,
+    ) -> anyhow::Result<()> {
+        self.storage
+            .record_delivery(
+                message_id,
+                chat_id,
+                error,
+                pin_error,
+                current_request_id().as_deref(),
+            )
+            .await
+    }
+
+    /// `None` for unknown messages and those of other bots.
+    pub async fn get_broadcast_progress(
+        &self,
+        message_id: i32,
+    ) -> anyhow::Result<Option<BroadcastProgress>> {
+        if self.get_queued_message(message_id).await?.is_none() {
+            return Ok(None);
+        }
+
+        self.storage.get_broadcast_progress(message_id).await
+    }
+
+    /// Sends a completed broadcast again to the chats it failed in, through
+    /// the queue. Their new results replace the failures in the delivery
+    /// report, chats it reached are left alone.
+    pub async fn retry_failed_chats(&self, message_id: i32) -> anyhow::Result<Vec<i64>> {
+        let chats = self
+            .storage
+            .retry_failed_deliveries(message_id, Utc::now())
+            .await?;
+        info!(
+            "retrying message {message_id} for {} failed chats",
+            chats.len()
+        );
+
+        Ok(chats)
+    }
+
+    pub async fn get_deliveries(&self, message_id: i32) -> anyhow::Result<Vec<Delivery>> {
+        if self.get_queued_message(message_id).await?.is_none() {
+            return Ok(Vec::new());
+        }
+
+        self.storage.get_deliveries(message_id).await
+    }
+
+    /// Files of the broadcast as telegram stored them, of one chat when
+    /// given. `None` for unknown messages and those of other bots.
+    pub async fn get_delivered_files(
+        &self,
+        message_id: i32,
+        chat_id: Option<i64>,
+    ) -> anyhow::Result<Option<Vec<DeliveredFile>>> {
+        if self.get_queued_message(message_id).await?.is_none() {
+            return Ok(None);
+        }
+
+        let files = self
+            .storage
+            .get_delivered_files(message_id, chat_id)
+            .await?;
+
+        Ok(Some(files))
+    }
+
+    async fn record_sent_message(
+        &self,
+        chat_id: i64,
+        queue_id: i32,
+        text: &str,
+        images: &[Media],
+        result: &anyhow::Result<SentBroadcast>,
+        options: &MessageOptions,
+    ) -> anyhow::Result<()> {
+        // the sent message history is only kept in postgres
+        let Some(pool) = &self.pool else {
+            return Ok(());
+        };
+
+        let media: Vec<String> = images.iter().map(|media| media.hash.clone()).collect();
+        let (telegram_message_id, media_message_ids, error) = match result {
+            Ok(sent) => {
+                let media_message_ids = sent.albums.iter().chain(&sent.parts).copied().collect();
+                (Some(sent.message_id.0), media_message_ids, None)
+            }
+            Err(err) => (None, Vec::new(), Some(err.to_string())),
+        };
+        let delete_at = options
+            .delete_after_seconds
+            .filter(|_| telegram_message_id.is_some())
+            .map(|seconds| Utc::now() + chrono::Duration::seconds(seconds.into()));
+
+        sqlx::query!(
+            r#"
+INSERT INTO sent_message ( chat_id, queue_id, telegram_message_id, media_message_ids, text, media, error, delete_at )
+VALUES ( $1, $2, $3, $4, $5, $6, $7, $8 )
+            "#,
+            chat_id,
+            queue_id,
+            telegram_message_id,
+            &media_message_ids,
+            text,
+            &media,
+            error,
+            delete_at
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_sent_messages(
+        &self,
+        chat_id: i64,
+        limit: i64,
+        offset: i64,
+    ) -> anyhow::Result<Vec<SentMessage>> {
+        let messages = sqlx::query_as!(
+            SentMessage,
+            r#"
+SELECT id, queue_id, telegram_message_id, text, media, error, sent_at FROM sent_message
+WHERE chat_id = $1
+ORDER BY sent_at DESC, id DESC
+LIMIT $2 OFFSET $3
+            "#,
+            chat_id,
+            limit,
+            offset
+        )
+        .fetch_all(self.pg()?)
+        .await?;
+
+        Ok(messages)
+    }
+}
@@ -0,0 +1,76 @@
+//! Full-text search over the broadcasts that went out, to find when
+//! something was announced and which chats got it. Only available with
+//! postgres.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::state::AppState;
+
+/// Narrows a search down besides its words.
+pub struct BroadcastSearch<'a> {
+    /// Words as typed into a search engine: quoted phrases, `or` and `-`
+    /// to leave words out.
+    pub query: &'a str,
+    /// Only broadcasts finished at or after this time.
+    pub from: Option<DateTime<Utc>>,
+    /// Only broadcasts finished before this time.
+    pub to: Option<DateTime<Utc>>,
+    /// Only broadcasts that reached the chat.
+    pub chat_id: Option<i64>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct FoundBroadcast {
+    pub id: i32,
+    pub message: String,
+    pub datetime: DateTime<Utc>,
+    pub completed_at: DateTime<Utc>,
+    /// Chats it was delivered to.
+    pub chats: Vec<i64>,
+}
+
+impl AppState {
+    /// Sent broadcasts matching the search, the best matches first and the
+    /// latest of equally good ones.
+    pub async fn search_broadcasts(
+        &self,
+        search: &BroadcastSearch<'_>,
+        limit: i64,
+        offset: i64,
+    ) -> anyhow::Result<Vec<FoundBroadcast>> {
+        let found = sqlx::query_as!(
+            FoundBroadcast,
+            r#"
+SELECT q.id, q.message, q.datetime, q.completed_at AS "completed_at!",
+    array(
+        SELECT chat_id FROM message_delivery
+        WHERE message_id = q.id AND error IS NULL
+        ORDER BY chat_id
+    ) AS "chats!"
+FROM message_queue q, websearch_to_tsquery('simple', $2) query
+WHERE q.bot_id = $1 AND q.completed_at IS NOT NULL AND q.message_tsv @@ query
+AND ( $3::TIMESTAMPTZ IS NULL OR q.completed_at >= $3 )
+AND ( $4::TIMESTAMPTZ IS NULL OR q.completed_at < $4 )
+AND ( $5::BIGINT IS NULL OR EXISTS (
+    SELECT 1 FROM message_delivery
+    WHERE message_id = q.id AND chat_id = $5 AND error IS NULL
+) )
+ORDER BY ts_rank(q.message_tsv, query) DESC, q.completed_at DESC, q.id
+LIMIT $6 OFFSET $7
+            "#,
+            &*self.bot_id,
+            search.query,
+            search.from,
+            search.to,
+            search.chat_id,
+            limit,
+            offset
+        )
+        .fetch_all(self.pg()?)
+        .await?;
+
+        Ok(found)
+    }
+}
@@ -0,0 +1,104 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::{
+    local_time::local_due,
+    queue::{QueueEntry, QueueFilter, QueueStatus},
+    state::AppState,
+    topic::Targets,
+};
+
+/// Most queued messages of each status looked at for one calendar.
+const MAX_MESSAGES: i64 = 1000;
+/// Characters of the text shown on the calendar.
+const PREVIEW_LENGTH: usize = 100;
+
+/// When a queued message goes out, once for most messages and once per UTC
+/// offset of its chats for those sent in local time.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Occurrence {
+    /// Id of the queued message.
+    pub id: i32,
+    pub at: DateTime<Utc>,
+    pub status: QueueStatus,
+    /// Chats the message reaches at that time. Empty for broadcasts to the
+    /// whole audience, unless they are sent in local time, then they are the
+    /// chats the audience has now.
+    pub chats: Vec<i64>,
+    pub all_chats: bool,
+    pub audience: String,
+    /// The start of the text, empty for copied messages.
+    pub preview: String,
+}
+
+impl AppState {
+    /// Messages waiting to be sent or approved that go out between both
+    /// times, ordered by when they do. Messages already due but not picked
+    /// up yet are left out.
+    pub async fn get_calendar(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> anyhow::Result<Vec<Occurrence>> {
+        let mut entries = Vec::new();
+        for status in [QueueStatus::Pending, QueueStatus::AwaitingApproval] {
+            let filter = QueueFilter {
+                status: Some(status),
+                ..Default::default()
+            };
+            entries.extend(self.get_queue(&filter, MAX_MESSAGES, 0).await?);
+        }
+        let utc_offsets = if entries.iter().any(|entry| entry.datetime_local.is_some()) {
+            self.get_chat_utc_offsets().await?
+        } else {
+            Default::default()
+        };
+
+        let mut occurrences = Vec::new();
+        for entry in entries {
+            let Some(local) = entry.datetime_local else {
+                occurrences.push(occurrence(&entry, entry.datetime, entry.chats.clone()));
+                continue;
+            };
+
+            let chats = if entry.all_chats {
+                let targets = Targets {
+                    chats: None,
+                    excluded: self.storage.get_excluded_chats(entry.id).await?,
+                };
+                self.get_recipients(&targets, entry.audience.parse()?)
+                    .await?
+                    .chats
+            } else {
+                entry.chats.clone()
+            };
+            let mut by_offset: BTreeMap<i32, Vec<i64>> = BTreeMap::new();
+            for chat_id in chats {
+                let offset = utc_offsets.get(&chat_id).copied().unwrap_or(0);
+                by_offset.entry(offset).or_default().push(chat_id);
+            }
+            for (offset, chats) in by_offset {
+                occurrences.push(occurrence(&entry, local_due(local, offset), chats));
+            }
+        }
+        occurrences.retain(|occurrence| occurrence.at >= from && occurrence.at <= to);
+        occurrences.sort_by_key(|occurrence| (occurrence.at, occurrence.id));
+
+        Ok(occurrences)
+    }
+}
+
+fn occurrence(entry: &QueueEntry, at: DateTime<Utc>, chats: Vec<i64>) -> Occurrence {
+    Occurrence {
+        id: entry.id,
+        at,
+        status: entry.status,
+        chats,
+        all_chats: entry.all_chats,
+        audience: entry.audience.clone(),
+        preview: entry.message.chars().take(PREVIEW_LENGTH).collect(),
+    }
+}
@@ -0,0 +1,252 @@
+use anyhow::bail;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use teloxide::{
+    payloads::SendMessageSetters,
+    requests::Requester,
+    types::{
+        Chat, ChatId, ChatPermissions, InlineKeyboardButton, InlineKeyboardMarkup, MessageId,
+        UserId,
+    },
+};
+use tracing::{error, info};
+use utoipa::ToSchema;
+
+use crate::{
+    moderation::display_name,
+    state::{AppState, User},
+};
+
+/// Prefix of the callback data of the button new members have to press.
+pub const CAPTCHA_CALLBACK_PREFIX: &str = "captcha";
+const MAX_TIMEOUT_MINUTES: i32 = 24 * 60;
+/// How long a failed kick of an unverified member waits before the next try.
+const KICK_RETRY_MINUTES: i64 = 5;
+
+/// Join gate of a chat: new members can't write until they press the button
+/// sent to them and are kicked when they don't in time.
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema, sqlx::FromRow)]
+pub struct Captcha {
+    pub timeout_minutes: i32,
+}
+
+impl Captcha {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if !(1..=MAX_TIMEOUT_MINUTES).contains(&self.timeout_minutes) {
+            bail!("timeout_minutes must be between 1 and {MAX_TIMEOUT_MINUTES}");
+        }
+
+        Ok(())
+    }
+}
+
+/// A new member that has yet to press the button.
+#[derive(Clone, Debug, Serialize, ToSchema, sqlx::FromRow)]
+pub struct CaptchaChallenge {
+    pub chat_id: i64,
+    pub user_id: i64,
+    pub username: Option<String>,
+    pub name: String,
+    /// The message with the button, missing until it was sent.
+    pub message_id: Option<i32>,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// The user a captcha button was sent to.
+pub fn parse_captcha_callback(data: &str) -> Option<i64> {
+    let (prefix, user_id) = data.split_once(':')?;
+    if prefix != CAPTCHA_CALLBACK_PREFIX {
+        return None;
+    }
+
+    user_id.parse().ok()
+}
+
+impl AppState {
+    pub async fn get_captcha(&self, chat_id: i64) -> anyhow::Result<Option<Captcha>> {
+        self.storage.get_captcha(chat_id).await
+    }
+
+    pub async fn set_captcha(&self, chat_id: i64, captcha: &Captcha) -> anyhow::Result<()> {
+        captcha.validate()?;
+        info!("setting the captcha of chat:{chat_id} to {captcha:?}");
+
+        self.storage.set_captcha(chat_id, captcha).await
+    }
+
+    /// Returns `false` when the chat had no captcha. Members that are still
+    /// challenged have to press the button anyway.
+    pub async fn delete_captcha(&self, chat_id: i64) -> anyhow::Result<bool> {
+        info!("removing the captcha of chat:{chat_id}");
+
+        self.storage.delete_captcha(chat_id).await
+    }
+
+    pub async fn get_captcha_challenges(
+        &self,
+        chat_id: i64,
+    ) -> anyhow::Result<Vec<CaptchaChallenge>> {
+        self.storage.get_captcha_challenges(chat_id).await
+    }
+
+    /// Mutes the new members of a chat with a captcha and sends each of
+    /// them the button to press.
+    pub async fn challenge_members(
+        &self,
+        chat: &Chat,
+        members: &[teloxide::types::User],
+    ) -> anyhow::Result<()> {
+        let Some(captcha) = self.get_captcha(chat.id.0).await? else {
+            return Ok(());
+        };
+
+        for member in members.iter().filter(|member| !member.is_bot) {
+            info!("challenging user:{} in chat:{}", member.id, chat.id);
+            let mut challenge = CaptchaChallenge {
+                chat_id: chat.id.0,
+                user_id: member.id.0 as i64,
+                username: member.username.clone(),
+                name: member.full_name(),
+                message_id: None,
+                expires_at: Utc::now() + Duration::minutes(captcha.timeout_minutes.into()),
+            };
+            // stored before the restriction, so a muted member is always
+            // either let in or kicked in the end
+            self.storage.add_captcha_challenge(&challenge).await?;
+            if let Err(err) = self
+                .bot
+                .restrict_chat_member(chat.id, member.id, ChatPermissions::empty())
+                .await
+            {
+                self.storage
+                    .delete_captcha_challenge(challenge.chat_id, challenge.user_id)
+                    .await?;
+                return Err(err.into());
+            }
+            let button = InlineKeyboardMarkup::new([[InlineKeyboardButton::callback(
+                "I'm not a bot",
+                format!("{CAPTCHA_CALLBACK_PREFIX}:{}", member.id),
+            )]]);
+            let sent = self
+                .bot
+                .send_message(
+                    chat.id,
+                    format!(
+                        "{}, press the button within {} minutes to be able to write.",
+                        display_name(member),
+                        captcha.timeout_minutes
+                    ),
+                )
+                .reply_markup(button)
+                .await?;
+
+            challenge.message_id = Some(sent.id.0);
+            self.storage.add_captcha_challenge(&challenge).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Lets a challenged member write, returns `false` when they weren't
+    /// challenged.
+    pub async fn solve_captcha(&self, chat_id: i64, user_id: i64) -> anyhow::Result<bool> {
+        let Some(challenge) = self.storage.get_captcha_challenge(chat_id, user_id).await? else {
+            return Ok(false);
+        };
+        info!("user:{user_id} solved the captcha of chat:{chat_id}");
+
+        let chat = self.telegram_chat(chat_id).await?;
+        let permissions = chat.permissions().unwrap_or_else(ChatPermissions::all);
+        self.bot
+            .restrict_chat_member(ChatId(chat_id), UserId(user_id as u64), permissions)
+            .await?;
+        self.storage
+            .delete_captcha_challenge(chat_id, user_id)
+            .await?;
+        self.delete_captcha_message(&challenge).await;
+
+        Ok(true)
+    }
+
+    pub async fn captcha_timeouts(state: Self) -> anyhow::Result<()> {
+        loop {
+            state.heartbeat("captcha_timeouts");
+            if let Err(err) = state.kick_unverified_members().await {
+                error!("failed to kick unverified members: {err}");
+            }
+            state
+                .sleep_interval(state.settings().intervals.captcha_timeouts())
+                .await;
+        }
+    }
+
+    /// Kicks the members whose captcha expired, the ones that can't be
+    /// kicked now are tried again later.
+    pub async fn kick_unverified_members(&self) -> anyhow::Result<()> {
+        let now = Utc::now();
+        let expired = self
+            .storage
+            .claim_expired_captcha_challenges(
+                &self.bot_id,
+                now,
+                now + Duration::minutes(KICK_RETRY_MINUTES),
+            )
+            .await?;
+
+        for challenge in expired {
+            if let Err(err) = self.kick_unverified(&challenge).await {
+                error!(
+                    "failed to kick user:{} from chat:{}: {err}",
+                    challenge.user_id, challenge.chat_id
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn kick_unverified(&self, challenge: &CaptchaChallenge) -> anyhow::Result<()> {
+        info!(
+            "kicking user:{} from chat:{}, the captcha expired",
+            challenge.user_id, challenge.chat_id
+        );
+        let chat = self.telegram_chat(challenge.chat_id).await?;
+        self.remove_from_chat(&chat, UserId(challenge.user_id as u64))
+            .await?;
+        self.storage
+            .delete_captcha_challenge(challenge.chat_id, challenge.user_id)
+            .await?;
+
+        let member = User {
+            id: challenge.user_id,
+            chat_id: challenge.chat_id,
+            username: challenge.username.clone(),
+            name: challenge.name.clone(),
+            joined_at: None,
+            updated_at: None,
+        };
+        self.log_kick(challenge.chat_id, &member, "captcha", "bot")
+            .await?;
+        self.remove_chat_member_by_id(challenge.chat_id, challenge.user_id)
+            .await?;
+        self.delete_captcha_message(challenge).await;
+
+        Ok(())
+    }
+
+    async fn delete_captcha_message(&self, challenge: &CaptchaChallenge) {
+        let Some(message_id) = challenge.message_id else {
+            return;
+        };
+        if let Err(err) = self
+            .bot
+            .delete_message(ChatId(challenge.chat_id), MessageId(message_id))
+            .await
+        {
+            error!(
+                "failed to delete the captcha of user:{} in chat:{}: {err}",
+                challenge.user_id, challenge.chat_id
+            );
+        }
+    }
+}
@@ -0,0 +1,54 @@
+use std::collections::HashSet;
+
+use teloxide::types::Message;
+use tracing::info;
+
+use crate::{broadcast::MessageOptions, state::AppState};
+
+/// `kind` of the chats that are channels.
+pub const CHANNEL_KIND: &str = "channel";
+
+impl MessageOptions {
+    /// The options a channel is sent to with, its overrides applied.
+    pub fn for_channel(self) -> Self {
+        Self {
+            silent: self.channel_silent.unwrap_or(self.silent),
+            protect_content: self.channel_protect_content.unwrap_or(self.protect_content),
+            ..self
+        }
+    }
+}
+
+impl AppState {
+    /// Registers the channel a post was made in, like messages register
+    /// groups. Posts carry the author's signature only when the channel
+    /// signs them, which is remembered for the chat.
+    pub async fn channel_post(&self, message: &Message) -> anyhow::Result<()> {
+        let chat_id = message.chat.id.0;
+        if !self.new_chat(&message.chat).await? {
+            return Ok(());
+        }
+
+        let signs = message.author_signature().is_some();
+        if self
+            .storage
+            .set_chat_signs_messages(&self.bot_id, chat_id, signs)
+            .await?
+        {
+            info!("chat:{chat_id} signs its posts: {signs}");
+        }
+
+        Ok(())
+    }
+
+    /// The chats of the bot that are channels.
+    pub async fn get_channels(&self) -> anyhow::Result<HashSet<i64>> {
+        Ok(self
+            .get_chats()
+            .await?
+            .into_iter()
+            .filter(|chat| chat.kind.as_deref() == Some(CHANNEL_KIND))
+            .map(|chat| chat.id)
+            .collect())
+    }
+}
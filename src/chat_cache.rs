@@ -0,0 +1,48 @@
+use teloxide::{
+    requests::Requester,
+    types::{Chat, ChatId},
+};
+use tokio::time::Instant;
+
+use crate::state::AppState;
+
+/// A chat as telegram last returned it.
+#[derive(Clone)]
+pub struct CachedChat {
+    chat: Chat,
+    fetched_at: Instant,
+}
+
+impl AppState {
+    /// The chat from telegram, asked again once it is older than
+    /// `chat_cache_ttl`. Cleanups and captchas look at the same chat for
+    /// every member, this keeps them to one request.
+    pub async fn telegram_chat(&self, chat_id: i64) -> anyhow::Result<Chat> {
+        if let Some(cached) = self.chat_cache.get(&chat_id) {
+            if cached.fetched_at.elapsed() < self.config.chat_cache_ttl() {
+                return Ok(cached.chat.clone());
+            }
+        }
+
+        let chat = self.bot.get_chat(ChatId(chat_id)).await?;
+        self.cache_chat(chat.clone());
+
+        Ok(chat)
+    }
+
+    pub fn cache_chat(&self, chat: Chat) {
+        self.chat_cache.insert(
+            chat.id.0,
+            CachedChat {
+                chat,
+                fetched_at: Instant::now(),
+            },
+        );
+    }
+
+    /// Drops the cached chat after it changed, like when it was renamed or
+    /// migrated to a supergroup.
+    pub fn forget_chat(&self, chat_id: i64) {
+        self.chat_cache.remove(&chat_id);
+    }
+}
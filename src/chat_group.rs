@@ -0,0 +1,43 @@
+use std::collections::HashMap;
+
+use crate::state::AppState;
+
+impl AppState {
+    /// The configured chat groups with the chats their admins put into them
+    /// with `/setgroup`. Chats put into groups no longer configured are left
+    /// out.
+    pub async fn get_chat_groups(&self) -> anyhow::Result<HashMap<String, Vec<i64>>> {
+        let mut groups = self.config.chat_groups.clone();
+        for (chat_id, group) in self
+            .storage
+            .get_chat_group_assignments(&self.bot_id)
+            .await?
+        {
+            if let Some(chats) = groups.get_mut(&group) {
+                if !chats.contains(&chat_id) {
+                    chats.push(chat_id);
+                }
+            }
+        }
+
+        Ok(groups)
+    }
+
+    /// Puts the chat into a configured group, `None` takes it out of the one
+    /// it was put into. Returns false for groups that aren't configured.
+    pub async fn set_chat_group(
+        &self,
+        chat_id: i64,
+        group: Option<&str>,
+        assigned_by: i64,
+    ) -> anyhow::Result<bool> {
+        if group.is_some_and(|group| !self.config.chat_groups.contains_key(group)) {
+            return Ok(false);
+        }
+        self.storage
+            .set_chat_group(&self.bot_id, chat_id, group, assigned_by)
+            .await?;
+
+        Ok(true)
+    }
+}
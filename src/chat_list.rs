@@ -0,0 +1,90 @@
+use serde::Deserialize;
+use utoipa::ToSchema;
+
+use crate::state::{AppState, Chats, CleanupState};
+
+/// What `GET /chats` orders by, ties are broken by the chat id.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ChatSort {
+    /// Case-insensitive.
+    #[default]
+    Name,
+    /// Chats whose count wasn't synced yet come last.
+    MemberCount,
+    /// When a broadcast last reached the chat, chats never reached come
+    /// last.
+    LastBroadcast,
+}
+
+impl ChatSort {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ChatSort::Name => "name",
+            ChatSort::MemberCount => "member_count",
+            ChatSort::LastBroadcast => "last_broadcast",
+        }
+    }
+}
+
+/// Narrows down and orders the chats of a bot, resolved by the storage.
+#[derive(Clone, Debug, Default)]
+pub struct ChatQuery {
+    /// The chats the bot was removed from instead of the others.
+    pub archived: bool,
+    /// Part of the name, case-insensitive.
+    pub search: Option<String>,
+    pub label: Option<String>,
+    pub muted: Option<bool>,
+    /// `group`, `supergroup`, `channel` or `private`.
+    pub kind: Option<String>,
+    /// Only these chats.
+    pub only: Option<Vec<i64>>,
+    /// None of these chats.
+    pub except: Vec<i64>,
+    pub sort: ChatSort,
+    pub descending: bool,
+}
+
+impl ChatQuery {
+    /// Keeps only the chats also in `chats`.
+    pub fn restrict(&mut self, chats: Vec<i64>) {
+        self.only = Some(match self.only.take() {
+            Some(only) => only.into_iter().filter(|id| chats.contains(id)).collect(),
+            None => chats,
+        });
+    }
+}
+
+impl AppState {
+    /// One page of the chats matching the query, with the number of all of
+    /// them.
+    pub async fn query_chats(
+        &self,
+        query: &ChatQuery,
+        limit: i64,
+        offset: i64,
+    ) -> anyhow::Result<(Chats, i64)> {
+        self.storage
+            .query_chats(&self.bot_id, query, limit, offset)
+            .await
+    }
+
+    /// Limits the query to the chats in the cleanup state, which is only
+    /// kept in memory. Chats without a status are idle.
+    pub fn restrict_to_state(&self, query: &mut ChatQuery, state: CleanupState) {
+        if state == CleanupState::Idle {
+            let busy = self
+                .chats_status
+                .iter()
+                .filter(|status| status.state() != CleanupState::Idle);
+            query.except.extend(busy.map(|status| *status.key()));
+        } else {
+            let chats = self
+                .chats_status
+                .iter()
+                .filter(|status| status.state() == state);
+            query.restrict(chats.map(|status| *status.key()).collect());
+        }
+    }
+}
@@ -0,0 +1,98 @@
+//! Cleanups of large chats pause themselves instead of running for hours or
+//! sleeping through long flood waits. A paused cleanup keeps its record with
+//! the last member it got to and goes on from there by itself, also after a
+//! restart.
+
+use std::time::Duration;
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use tokio::time::Instant;
+use tracing::{error, info};
+
+use crate::{
+    state::{AppState, ChatCleaningStatus},
+    telemetry::spawn_in_request,
+};
+
+/// Bounds of one run of [`AppState::kick_members_within`].
+#[derive(Default)]
+pub struct CleanupWindow {
+    /// When the run pauses, `None` runs to the end.
+    pub deadline: Option<Instant>,
+    /// Longest flood wait slept through, `None` sleeps through any.
+    pub max_flood_wait: Option<Duration>,
+    /// Members up to this id were dealt with by an earlier run.
+    pub last_user_id: Option<i64>,
+    /// Whether the last member is written to the run record of the chat.
+    pub resumable: bool,
+}
+
+/// How far a run of [`AppState::kick_members_within`] got.
+#[derive(Debug, PartialEq, Eq)]
+pub enum KickRun {
+    Finished,
+    /// Stopped on request, the chat status is already `Cancelled`.
+    Cancelled,
+    /// Stopped for the time box or a long flood wait, to go on at this time.
+    Paused(DateTime<Utc>),
+}
+
+impl AppState {
+    /// The window of a cleanup starting now, going on after the last member
+    /// an earlier run of it got to.
+    pub(crate) async fn cleanup_window(&self, chat_id: i64) -> anyhow::Result<CleanupWindow> {
+        let run = self.storage.get_cleanup_run(chat_id).await?;
+
+        Ok(CleanupWindow {
+            deadline: self
+                .config
+                .cleanup_max_runtime()
+                .map(|runtime| Instant::now() + runtime),
+            max_flood_wait: Some(self.config.cleanup_max_flood_wait()),
+            last_user_id: run.and_then(|run| run.last_user_id),
+            resumable: true,
+        })
+    }
+
+    /// Leaves the cleanup `Resumable` with its record kept, and goes on with
+    /// it at `resume_at`.
+    pub(crate) async fn pause_cleanup(
+        &self,
+        chat_id: i64,
+        initiator: &str,
+        resume_at: DateTime<Utc>,
+    ) -> anyhow::Result<()> {
+        info!("pausing the cleanup of chat:{chat_id} until {resume_at}");
+        self.storage.pause_cleanup_run(chat_id, resume_at).await?;
+        let run = self
+            .storage
+            .get_cleanup_run(chat_id)
+            .await?
+            .context("the paused cleanup has no record")?;
+        self.set_chat_status(chat_id, ChatCleaningStatus::Resumable(run))?;
+        self.resume_cleanup_at(chat_id, initiator, resume_at);
+
+        Ok(())
+    }
+
+    /// Resumes the cleanup of the chat in the background once `resume_at`
+    /// comes, unless it was resumed otherwise by then.
+    pub(crate) fn resume_cleanup_at(
+        &self,
+        chat_id: i64,
+        initiator: &str,
+        resume_at: DateTime<Utc>,
+    ) {
+        let state = self.clone();
+        let initiator = initiator.to_string();
+        // kicks after the pause are logged under the request of the cleanup
+        spawn_in_request(async move {
+            let wait = (resume_at - Utc::now()).to_std().unwrap_or_default();
+            tokio::time::sleep(wait).await;
+            if let Err(err) = state.resume_cleanup(chat_id, &initiator) {
+                error!("failed to resume the paused cleanup of chat:{chat_id}: {err}");
+            }
+        });
+    }
+}
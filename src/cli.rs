@@ -0,0 +1,160 @@
+//! Administration from the command line. Subcommands work on the database
+//! and telegram directly, without the API and background loops, for cron
+//! jobs and for when the server is down.
+
+use std::io::Write;
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use clap::{Parser, Subcommand};
+
+use crate::{
+    bot,
+    broadcast::MessageOptions,
+    chat_list::ChatQuery,
+    config::Config,
+    format::MessageFormat,
+    preflight::Preflight,
+    state::{AppState, QueueMode},
+    topic::ChatTarget,
+};
+
+/// Runs the server when no subcommand is given.
+#[derive(Parser)]
+#[command(name = "telegram-sender")]
+pub struct Cli {
+    /// Bot the subcommand acts as, the first configured one by default.
+    #[arg(long, global = true)]
+    pub bot: Option<String>,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Queues a message and, unless it is scheduled for later, sends the due
+    /// messages right away.
+    Send {
+        /// Comma separated chat ids, every chat when omitted.
+        #[arg(long, value_delimiter = ',', allow_hyphen_values = true)]
+        chats: Vec<i64>,
+        #[arg(long)]
+        message: String,
+        /// `markdownv2`, `html` or `plain`.
+        #[arg(long, default_value = "markdownv2")]
+        parse_mode: MessageFormat,
+        /// RFC 3339 time to send the message at instead of now.
+        #[arg(long)]
+        at: Option<DateTime<Utc>>,
+    },
+    /// The chats of the bot.
+    #[command(subcommand)]
+    Chats(ChatsCommand),
+    /// The message queue of the bot.
+    #[command(subcommand)]
+    Queue(QueueCommand),
+}
+
+#[derive(Subcommand)]
+pub enum ChatsCommand {
+    /// One chat per line: id, kind, member count and name, tab separated.
+    List {
+        /// The chats the bot was removed from instead.
+        #[arg(long)]
+        archived: bool,
+        /// Part of the name, case-insensitive.
+        #[arg(long)]
+        search: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum QueueCommand {
+    /// Sends the due messages now, up to `queue.batch_size` of them, like
+    /// one pass of the queue loop. Maintenance mode pauses it as well.
+    Flush,
+}
+
+/// Connects to the database and the bot and runs the subcommand. Logging
+/// stays off so the output is only what the subcommand prints.
+pub async fn run(config: Config, bot_id: Option<String>, command: Command) -> anyhow::Result<()> {
+    let mut preflight = Preflight::default();
+    let database = preflight.database(&config).await;
+    preflight.exit_on_failure();
+    let (storage, pool) = database.expect("a failed database check exits");
+
+    let bots = config.all_bots();
+    let bot_config = match &bot_id {
+        Some(id) => bots.iter().find(|bot| &bot.id == id),
+        None => bots.first(),
+    }
+    .with_context(|| format!("no bot {}", bot_id.unwrap_or_default()))?;
+    let bot = bot::build(&config, &bot_config.token)?;
+    let id = bot_config.id.clone();
+    let state = AppState::new(config, storage, pool, &id, bot);
+
+    execute(&state, command, &mut std::io::stdout()).await
+}
+
+pub async fn execute(
+    state: &AppState,
+    command: Command,
+    out: &mut impl Write,
+) -> anyhow::Result<()> {
+    match command {
+        Command::Send {
+            chats,
+            message,
+            parse_mode,
+            at,
+        } => {
+            let targets =
+                (!chats.is_empty()).then(|| chats.into_iter().map(ChatTarget::Chat).collect());
+            let at = at.unwrap_or_else(Utc::now);
+            let options = MessageOptions {
+                parse_mode,
+                ..Default::default()
+            };
+            let id = state
+                .queue_message_with_images(
+                    targets,
+                    message,
+                    Vec::new(),
+                    at.to_rfc3339(),
+                    options,
+                    QueueMode::Send,
+                )
+                .await?;
+            writeln!(out, "queued message {id}")?;
+            if at <= Utc::now() {
+                state.message_queue_loop().await?;
+            }
+        }
+        Command::Chats(ChatsCommand::List { archived, search }) => {
+            let query = ChatQuery {
+                archived,
+                search,
+                ..Default::default()
+            };
+            let (chats, _) = state.query_chats(&query, i64::MAX, 0).await?;
+            for chat in chats {
+                let members = chat.member_count.map(|count| count.to_string());
+                writeln!(
+                    out,
+                    "{}\t{}\t{}\t{}",
+                    chat.id,
+                    chat.kind.as_deref().unwrap_or("-"),
+                    members.as_deref().unwrap_or("-"),
+                    chat.name
+                )?;
+            }
+        }
+        Command::Queue(QueueCommand::Flush) => {
+            if let Some(next_due) = state.message_queue_loop().await? {
+                writeln!(out, "next message due at {}", next_due.to_rfc3339())?;
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,707 @@
+//! Turns rich text pasted from editors, simplified HTML or CommonMark, into
+//! the markup telegram parses. What telegram has no entity for is refused
+//! with what to do instead, rather than sent mangled.
+
+use std::sync::OnceLock;
+
+use anyhow::{anyhow, bail};
+use pulldown_cmark::{CodeBlockKind, Event, Options, Parser, Tag};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use teloxide::utils::{html, markdown};
+use utoipa::ToSchema;
+
+use crate::format::MessageFormat;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SourceFormat {
+    /// HTML when the text has tags, CommonMark otherwise.
+    #[default]
+    Auto,
+    /// What editors like Google Docs put on the clipboard, formatting set by
+    /// inline styles included.
+    Html,
+    CommonMark,
+}
+
+impl SourceFormat {
+    /// The format the text is written in, `Auto` told apart by its tags.
+    pub fn detect(self, text: &str) -> SourceFormat {
+        static TAG: OnceLock<Regex> = OnceLock::new();
+        let tag = TAG.get_or_init(|| {
+            Regex::new(r"(?i)<!--|</?[a-z][a-z0-9-]*(\s[^<>]*)?/?>").expect("valid tag regex")
+        });
+        match self {
+            SourceFormat::Auto if tag.is_match(text) => SourceFormat::Html,
+            SourceFormat::Auto => SourceFormat::CommonMark,
+            format => format,
+        }
+    }
+}
+
+/// Converts `text` written in `from` to the markup of `to`.
+pub fn convert(text: &str, from: SourceFormat, to: MessageFormat) -> anyhow::Result<String> {
+    let mut out = Output::new(to);
+    match from.detect(text) {
+        SourceFormat::Html => convert_html(text, &mut out)?,
+        _ => convert_commonmark(text, &mut out)?,
+    }
+
+    Ok(out.text)
+}
+
+#[derive(Clone, PartialEq)]
+enum Style {
+    Bold,
+    Italic,
+    Underline,
+    Strike,
+    Spoiler,
+    Link(String),
+    Code,
+    Pre(Option<String>),
+    Quote,
+}
+
+/// The converted text, written as the source is walked through. Line breaks
+/// between blocks are held back until more text follows, so none trail.
+struct Output {
+    format: MessageFormat,
+    text: String,
+    styles: Vec<Style>,
+    newlines: usize,
+    line_start: bool,
+    /// Right after a quote, code block or list item started, whose first
+    /// block doesn't need a line break before it.
+    fresh: bool,
+}
+
+impl Output {
+    fn new(format: MessageFormat) -> Self {
+        Self {
+            format,
+            text: String::new(),
+            styles: Vec::new(),
+            newlines: 0,
+            line_start: true,
+            fresh: false,
+        }
+    }
+
+    fn in_code(&self) -> bool {
+        self.styles
+            .iter()
+            .any(|style| matches!(style, Style::Code | Style::Pre(_)))
+    }
+
+    fn in_link(&self) -> bool {
+        self.styles
+            .iter()
+            .any(|style| matches!(style, Style::Link(_)))
+    }
+
+    /// Ends the current block with at least `newlines` line breaks.
+    fn block(&mut self, newlines: usize) {
+        if !self.text.is_empty() && !self.fresh {
+            self.newlines = self.newlines.max(newlines);
+        }
+    }
+
+    fn line_break(&mut self) {
+        if !self.text.is_empty() {
+            self.newlines += 1;
+        }
+    }
+
+    fn flush(&mut self) {
+        for _ in 0..std::mem::take(&mut self.newlines) {
+            self.newline();
+        }
+    }
+
+    fn newline(&mut self) {
+        self.text.push('\n');
+        self.line_start = true;
+        // every line of a quote starts with '>' in markdown
+        if self.format == MessageFormat::MarkdownV2 && self.styles.contains(&Style::Quote) {
+            self.text.push('>');
+        }
+    }
+
+    fn text(&mut self, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        self.flush();
+        for (index, line) in text.split('\n').enumerate() {
+            if index > 0 {
+                self.newline();
+            }
+            if line.is_empty() {
+                continue;
+            }
+            let escaped = match (self.format, self.in_code()) {
+                (MessageFormat::MarkdownV2, true) => markdown::escape_code(line),
+                (MessageFormat::MarkdownV2, false) => markdown::escape(&line.replace('\\', r"\\")),
+                (MessageFormat::Html, _) => html::escape(line),
+                (MessageFormat::Plain, _) => line.to_string(),
+            };
+            self.text.push_str(&escaped);
+            self.line_start = false;
+            self.fresh = false;
+        }
+    }
+
+    fn markup(&mut self, markup: &str) {
+        // "___" would be read as underline first, telegram skips the '\r'
+        if markup.starts_with('_') && self.text.ends_with('_') {
+            self.text.push('\r');
+        }
+        self.text.push_str(markup);
+    }
+
+    fn open(&mut self, style: Style, offset: usize) -> anyhow::Result<()> {
+        if self.in_code() {
+            bail!("formatting inside code at offset {offset} isn't supported by telegram, leave it out");
+        }
+        if matches!(style, Style::Link(_)) && self.in_link() {
+            bail!("link at offset {offset} is inside another link, telegram can't nest them");
+        }
+        if style == Style::Quote && self.styles.contains(&Style::Quote) {
+            bail!("quote at offset {offset} is inside another quote, telegram can't nest them");
+        }
+        self.flush();
+
+        let markup = match (self.format, &style) {
+            (MessageFormat::Plain, _) => String::new(),
+            (MessageFormat::MarkdownV2, style) => match style {
+                Style::Bold => "*".into(),
+                Style::Italic => "_".into(),
+                Style::Underline => "__".into(),
+                Style::Strike => "~".into(),
+                Style::Spoiler => "||".into(),
+                Style::Link(_) => "[".into(),
+                Style::Code => "`".into(),
+                Style::Pre(language) => format!("```{}\n", language.as_deref().unwrap_or("")),
+                Style::Quote => ">".into(),
+            },
+            (MessageFormat::Html, style) => match style {
+                Style::Bold => "<b>".into(),
+                Style::Italic => "<i>".into(),
+                Style::Underline => "<u>".into(),
+                Style::Strike => "<s>".into(),
+                Style::Spoiler => "<tg-spoiler>".into(),
+                Style::Link(url) => {
+                    format!("<a href=\"{}\">", html::escape(url).replace('"', "&quot;"))
+                }
+                Style::Code => "<code>".into(),
+                Style::Pre(Some(language)) => {
+                    format!("<pre><code class=\"language-{}\">", html::escape(language))
+                }
+                Style::Pre(None) => "<pre>".into(),
+                Style::Quote => "<blockquote>".into(),
+            },
+        };
+        self.markup(&markup);
+        self.fresh = matches!(style, Style::Quote | Style::Pre(_));
+        self.styles.push(style);
+
+        Ok(())
+    }
+
+    fn close(&mut self) {
+        let Some(style) = self.styles.pop() else {
+            return;
+        };
+        let markup = match (self.format, style) {
+            (MessageFormat::Plain, _) => String::new(),
+            (MessageFormat::MarkdownV2, style) => match style {
+                Style::Bold => "*".into(),
+                Style::Italic => "_".into(),
+                Style::Underline => "__".into(),
+                Style::Strike => "~".into(),
+                Style::Spoiler => "||".into(),
+                Style::Link(url) => {
+                    format!(
+                        "]({})",
+                        markdown::escape_link_url(&url.replace('\\', r"\\"))
+                    )
+                }
+                Style::Code => "`".into(),
+                Style::Pre(_) => "\n```".into(),
+                Style::Quote => String::new(),
+            },
+            (MessageFormat::Html, style) => match style {
+                Style::Bold => "</b>".into(),
+                Style::Italic => "</i>".into(),
+                Style::Underline => "</u>".into(),
+                Style::Strike => "</s>".into(),
+                Style::Spoiler => "</tg-spoiler>".into(),
+                Style::Link(_) => "</a>".into(),
+                Style::Code => "</code>".into(),
+                Style::Pre(Some(_)) => "</code></pre>".into(),
+                Style::Pre(None) => "</pre>".into(),
+                Style::Quote => "</blockquote>".into(),
+            },
+        };
+        self.markup(&markup);
+    }
+}
+
+fn unsupported(construct: &str, offset: usize) -> anyhow::Error {
+    match construct {
+        "img" | "image" => anyhow!(
+            "image at offset {offset} can't be part of the text, send it in `images` instead"
+        ),
+        "table" => anyhow!(
+            "table at offset {offset} isn't supported by telegram, write its rows as lines of text"
+        ),
+        "hr" | "rule" => anyhow!(
+            "horizontal rule at offset {offset} isn't supported by telegram, leave it out or use an empty line"
+        ),
+        other => anyhow!("unsupported tag <{other}> at offset {offset}"),
+    }
+}
+
+/// Numbering of the lists the converter is in, `None` for bullets.
+fn list_marker(lists: &mut [Option<u64>]) -> String {
+    let indent = "  ".repeat(lists.len().saturating_sub(1));
+    match lists.last_mut() {
+        Some(Some(number)) => {
+            *number += 1;
+            format!("{indent}{}. ", *number - 1)
+        }
+        _ => format!("{indent}• "),
+    }
+}
+
+fn convert_commonmark(text: &str, out: &mut Output) -> anyhow::Result<()> {
+    let options = Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TABLES;
+    let mut lists: Vec<Option<u64>> = Vec::new();
+    // the text of a code block comes in pieces, its last line break dropped
+    let mut code_block: Option<String> = None;
+
+    for (event, range) in Parser::new_ext(text, options).into_offset_iter() {
+        let offset = range.start;
+        match event {
+            Event::Start(tag) => match tag {
+                Tag::Paragraph => out.block(2),
+                Tag::Heading(..) => {
+                    out.block(2);
+                    out.open(Style::Bold, offset)?;
+                }
+                Tag::BlockQuote => {
+                    out.block(2);
+                    out.open(Style::Quote, offset)?;
+                }
+                Tag::CodeBlock(kind) => {
+                    out.block(2);
+                    let language = match kind {
+                        CodeBlockKind::Fenced(language) if !language.is_empty() => {
+                            Some(language.to_string())
+                        }
+                        _ => None,
+                    };
+                    out.open(Style::Pre(language), offset)?;
+                    code_block = Some(String::new());
+                }
+                Tag::List(start) => {
+                    out.block(1);
+                    lists.push(start);
+                }
+                Tag::Item => {
+                    out.block(1);
+                    out.text(&list_marker(&mut lists));
+                    out.fresh = true;
+                }
+                Tag::Emphasis => out.open(Style::Italic, offset)?,
+                Tag::Strong => out.open(Style::Bold, offset)?,
+                Tag::Strikethrough => out.open(Style::Strike, offset)?,
+                Tag::Link(_, url, _) => out.open(Style::Link(url.to_string()), offset)?,
+                Tag::Image(..) => return Err(unsupported("image", offset)),
+                Tag::Table(_) | Tag::TableHead | Tag::TableRow | Tag::TableCell => {
+                    return Err(unsupported("table", offset))
+                }
+                Tag::FootnoteDefinition(_) => bail!("footnote at offset {offset} isn't supported"),
+            },
+            Event::End(tag) => match tag {
+                Tag::Paragraph => out.block(2),
+                Tag::CodeBlock(_) => {
+                    let code = code_block.take().unwrap_or_default();
+                    out.text(code.strip_suffix('\n').unwrap_or(&code));
+                    out.close();
+                    out.block(2);
+                }
+                Tag::Heading(..) | Tag::BlockQuote => {
+                    out.close();
+                    out.block(2);
+                }
+                Tag::List(_) => {
+                    lists.pop();
+                    out.block(if lists.is_empty() { 2 } else { 1 });
+                }
+                Tag::Emphasis | Tag::Strong | Tag::Strikethrough | Tag::Link(..) => out.close(),
+                _ => {}
+            },
+            Event::Text(text) => match &mut code_block {
+                Some(code) => code.push_str(&text),
+                None => out.text(&text),
+            },
+            Event::Code(code) => {
+                out.open(Style::Code, offset)?;
+                out.text(&code);
+                out.close();
+            }
+            Event::SoftBreak | Event::HardBreak => out.line_break(),
+            Event::Rule => return Err(unsupported("rule", offset)),
+            Event::Html(_) => bail!(
+                "HTML at offset {offset} can't be mixed with CommonMark, send the whole text as html"
+            ),
+            Event::FootnoteReference(_) | Event::TaskListMarker(_) => {
+                bail!("construct at offset {offset} isn't supported")
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// An element of the HTML source, with how many styles it opened.
+struct Element {
+    name: String,
+    styles: usize,
+}
+
+fn convert_html(text: &str, out: &mut Output) -> anyhow::Result<()> {
+    let mut elements: Vec<Element> = Vec::new();
+    let mut lists: Vec<Option<u64>> = Vec::new();
+    let mut rest = text;
+
+    while !rest.is_empty() {
+        let offset = text.len() - rest.len();
+        let Some(start) = rest.find('<') else {
+            html_text(rest, out);
+            break;
+        };
+        html_text(&rest[..start], out);
+        let tail = &rest[start..];
+        let offset = offset + start;
+
+        if let Some(comment) = tail.strip_prefix("<!--") {
+            let end = comment
+                .find("-->")
+                .ok_or_else(|| anyhow!("comment at offset {offset} is never closed"))?;
+            rest = &comment[end + 3..];
+            continue;
+        }
+        let end = tail
+            .find('>')
+            .ok_or_else(|| anyhow!("unclosed tag at offset {offset}"))?;
+        let tag = &tail[1..end];
+        rest = &tail[end + 1..];
+        // doctypes and the like
+        if tag.starts_with('!') || tag.starts_with('?') {
+            continue;
+        }
+
+        if let Some(name) = tag.strip_prefix('/') {
+            let name = name.trim().to_ascii_lowercase();
+            match elements.pop() {
+                Some(element) if element.name == name => {
+                    for _ in 0..element.styles {
+                        out.close();
+                    }
+                }
+                Some(element) => bail!(
+                    "closing tag </{name}> at offset {offset} doesn't match <{}>",
+                    element.name
+                ),
+                None => bail!("closing tag </{name}> at offset {offset} has no opening tag"),
+            }
+            match name.as_str() {
+                "p" | "blockquote" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "pre" => {
+                    out.block(2)
+                }
+                "div" | "li" => out.block(1),
+                "ul" | "ol" => {
+                    lists.pop();
+                    out.block(if lists.is_empty() { 2 } else { 1 });
+                }
+                _ => {}
+            }
+            continue;
+        }
+
+        let self_closing = tag.ends_with('/');
+        let tag = tag.trim_end_matches('/');
+        let (name, attributes) = tag.split_once(char::is_whitespace).unwrap_or((tag, ""));
+        let name = name.to_ascii_lowercase();
+        let attributes = parse_attributes(attributes);
+        let attribute = |key: &str| {
+            attributes
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value.as_str())
+        };
+
+        if matches!(name.as_str(), "head" | "style" | "title" | "script") {
+            let closing = format!("</{name}");
+            let end = rest
+                .to_ascii_lowercase()
+                .find(&closing)
+                .and_then(|end| rest[end..].find('>').map(|close| end + close + 1))
+                .ok_or_else(|| anyhow!("tag <{name}> at offset {offset} is never closed"))?;
+            rest = &rest[end..];
+            continue;
+        }
+
+        let mut styles = Vec::new();
+        match name.as_str() {
+            "html" | "body" | "meta" | "link" | "wbr" => {}
+            "b" | "strong" => {
+                // google docs wraps everything in a <b> that isn't bold
+                if attribute("style")
+                    .is_none_or(|style| css(style, "font-weight") != Some("normal"))
+                {
+                    styles.push(Style::Bold);
+                }
+            }
+            "i" | "em" => styles.push(Style::Italic),
+            "u" | "ins" => styles.push(Style::Underline),
+            "s" | "strike" | "del" => styles.push(Style::Strike),
+            "tg-spoiler" => styles.push(Style::Spoiler),
+            "span" => {
+                if attribute("class").is_some_and(|class| {
+                    class.split_whitespace().any(|class| class == "tg-spoiler")
+                }) {
+                    styles.push(Style::Spoiler);
+                }
+                if let Some(style) = attribute("style") {
+                    styles.extend(inline_styles(style, out.in_link()));
+                }
+            }
+            "a" => {
+                let href = attribute("href")
+                    .filter(|href| !href.is_empty())
+                    .ok_or_else(|| anyhow!("link at offset {offset} has no href"))?;
+                if !href.contains(':') {
+                    bail!("link at offset {offset} has no scheme, write the whole url like https://{href}");
+                }
+                styles.push(Style::Link(href.to_string()));
+            }
+            "code" => {
+                // the code of a <pre> is already code
+                if !matches!(out.styles.last(), Some(Style::Pre(_))) {
+                    styles.push(Style::Code);
+                }
+            }
+            "pre" => {
+                out.block(2);
+                let language = attribute("class")
+                    .or_else(|| next_code_class(rest))
+                    .and_then(|class| class.strip_prefix("language-"))
+                    .map(str::to_string);
+                styles.push(Style::Pre(language));
+            }
+            "blockquote" => {
+                out.block(2);
+                styles.push(Style::Quote);
+            }
+            "p" => out.block(2),
+            "div" => out.block(1),
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                out.block(2);
+                styles.push(Style::Bold);
+            }
+            "ul" => {
+                out.block(1);
+                lists.push(None);
+            }
+            "ol" => {
+                out.block(1);
+                let start = attribute("start").and_then(|start| start.parse().ok());
+                lists.push(Some(start.unwrap_or(1)));
+            }
+            "li" => {
+                out.block(1);
+                out.text(&list_marker(&mut lists));
+                out.fresh = true;
+            }
+            "br" => out.line_break(),
+            "img" | "hr" => return Err(unsupported(&name, offset)),
+            "table" | "thead" | "tbody" | "tfoot" | "tr" | "td" | "th" | "caption" => {
+                return Err(unsupported("table", offset))
+            }
+            other => return Err(unsupported(other, offset)),
+        }
+
+        let count = styles.len();
+        for style in styles {
+            out.open(style, offset)?;
+        }
+        let void = matches!(name.as_str(), "br" | "meta" | "link" | "wbr");
+        if !self_closing && !void {
+            elements.push(Element {
+                name,
+                styles: count,
+            });
+        }
+    }
+
+    if let Some(element) = elements.pop() {
+        bail!("tag <{}> is never closed", element.name);
+    }
+
+    Ok(())
+}
+
+/// The class of a `<code>` right at the start of `rest`, how `<pre>` blocks
+/// give their language.
+fn next_code_class(rest: &str) -> Option<&str> {
+    let tag = rest.trim_start().strip_prefix("<code")?;
+    let end = tag.find('>')?;
+    let class = tag[..end].split_once("class=")?.1.trim_start();
+    let quote = class.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+    class[1..].split(quote).next()
+}
+
+/// Text between tags with its whitespace collapsed like a browser would,
+/// except in `<pre>`.
+fn html_text(text: &str, out: &mut Output) {
+    let text = decode_entities(text);
+    if matches!(out.styles.last(), Some(Style::Pre(_))) {
+        out.text(&text);
+        return;
+    }
+
+    let mut collapsed = String::with_capacity(text.len());
+    for word in text.split(|c: char| c.is_ascii_whitespace()) {
+        if word.is_empty() {
+            if !collapsed.ends_with(' ') {
+                collapsed.push(' ');
+            }
+        } else {
+            collapsed.push_str(word);
+            collapsed.push(' ');
+        }
+    }
+    // keep a single space where the text had whitespace at its end
+    if !text.ends_with(|c: char| c.is_ascii_whitespace()) {
+        collapsed.pop();
+    }
+    let collapsed = if out.line_start || out.newlines > 0 {
+        collapsed.trim_start()
+    } else {
+        &collapsed
+    };
+    out.text(collapsed);
+}
+
+fn parse_attributes(mut text: &str) -> Vec<(String, String)> {
+    let mut attributes = Vec::new();
+    loop {
+        text = text.trim_start();
+        let end = text
+            .find(|c: char| c.is_whitespace() || c == '=')
+            .unwrap_or(text.len());
+        if end == 0 {
+            break;
+        }
+        let name = text[..end].to_ascii_lowercase();
+        text = text[end..].trim_start();
+        let Some(value) = text.strip_prefix('=') else {
+            attributes.push((name, String::new()));
+            continue;
+        };
+        let value = value.trim_start();
+        let (value, remaining) = match value.chars().next() {
+            Some(quote @ ('"' | '\'')) => value[1..].split_once(quote).unwrap_or((&value[1..], "")),
+            _ => value.split_once(char::is_whitespace).unwrap_or((value, "")),
+        };
+        attributes.push((name, decode_entities(value)));
+        text = remaining;
+    }
+
+    attributes
+}
+
+/// The value of a property of an inline style.
+fn css<'a>(style: &'a str, property: &str) -> Option<&'a str> {
+    style.split(';').find_map(|declaration| {
+        let (name, value) = declaration.split_once(':')?;
+        (name.trim().eq_ignore_ascii_case(property)).then(|| value.trim())
+    })
+}
+
+/// Formatting editors set with inline styles. Links are underlined by them
+/// anyway, so that is dropped inside links.
+fn inline_styles(style: &str, in_link: bool) -> Vec<Style> {
+    let mut styles = Vec::new();
+    let bold = css(style, "font-weight").is_some_and(|weight| {
+        matches!(weight, "bold" | "bolder")
+            || weight.parse::<u32>().is_ok_and(|weight| weight >= 600)
+    });
+    if bold {
+        styles.push(Style::Bold);
+    }
+    if css(style, "font-style").is_some_and(|font| matches!(font, "italic" | "oblique")) {
+        styles.push(Style::Italic);
+    }
+    let decoration = css(style, "text-decoration-line")
+        .or_else(|| css(style, "text-decoration"))
+        .unwrap_or_default();
+    if decoration.contains("underline") && !in_link {
+        styles.push(Style::Underline);
+    }
+    if decoration.contains("line-through") {
+        styles.push(Style::Strike);
+    }
+
+    styles
+}
+
+/// Replaces character references, a `&` starting none stays as it is.
+fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        let tail = &rest[start..];
+        let entity = tail[1..]
+            .find(';')
+            .map(|end| &tail[1..end + 1])
+            .and_then(|name| Some((decode_entity(name)?, name.len() + 2)));
+        match entity {
+            Some((c, len)) => {
+                decoded.push(c);
+                rest = &tail[len..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &tail[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+
+    decoded
+}
+
+fn decode_entity(name: &str) -> Option<char> {
+    let code = match name {
+        "amp" => return Some('&'),
+        "lt" => return Some('<'),
+        "gt" => return Some('>'),
+        "quot" => return Some('"'),
+        "apos" => return Some('\''),
+        "nbsp" => return Some('\u{a0}'),
+        name => name.strip_prefix('#')?,
+    };
+    let code = match code.strip_prefix(['x', 'X']) {
+        Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+        None => code.parse().ok()?,
+    };
+
+    char::from_u32(code)
+}
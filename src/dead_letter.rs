@@ -0,0 +1,53 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::json;
+use tracing::{error, info};
+use utoipa::ToSchema;
+
+use crate::{state::AppState, webhook::WebhookEvent};
+
+/// A queued message that failed to send `max_attempts` times in a row and
+/// is no longer picked up by the queue.
+#[derive(Serialize, ToSchema, sqlx::FromRow)]
+pub struct FailedMessage {
+    /// Id of the queued message.
+    pub id: i32,
+    pub message: String,
+    pub datetime: DateTime<Utc>,
+    /// Error of the last attempt.
+    pub error: String,
+    pub attempts: i32,
+    pub failed_at: DateTime<Utc>,
+}
+
+impl AppState {
+    /// Counts a failed attempt to send the message and moves it to the
+    /// failed messages once it ran out of attempts.
+    pub async fn fail_queued_message(&self, id: i32, err: &str) -> anyhow::Result<()> {
+        let max_attempts = self.config.max_send_attempts as i32;
+        if self
+            .storage
+            .fail_queued_message(id, err, max_attempts)
+            .await?
+        {
+            error!("queued message {id} failed {max_attempts} times, giving up on it");
+            self.notify(
+                WebhookEvent::BroadcastFailed,
+                json!({ "id": id, "error": err, "attempts": max_attempts }),
+            );
+        }
+
+        Ok(())
+    }
+
+    pub async fn get_failed_messages(&self) -> anyhow::Result<Vec<FailedMessage>> {
+        self.storage.get_failed_messages(&self.bot_id).await
+    }
+
+    /// Returns `false` when there is no such failed message.
+    pub async fn retry_failed_message(&self, id: i32) -> anyhow::Result<bool> {
+        info!("retrying failed message {id}");
+
+        self.storage.retry_failed_message(&self.bot_id, id).await
+    }
+}
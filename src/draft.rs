@@ -0,0 +1,95 @@
+use std::fmt;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+use utoipa::ToSchema;
+
+use crate::{
+    attachment::Attachments, broadcast::MessageOptions, language::Translations, state::AppState,
+    topic::ChatTarget,
+};
+
+/// A work-in-progress announcement in the shape of the `sendMessage`
+/// payload. Nothing is validated until the draft is sent, so every field
+/// may still be missing.
+#[derive(Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct DraftContent {
+    #[serde(default)]
+    pub chats: Vec<ChatTarget>,
+    #[serde(default)]
+    pub all_chats: bool,
+    #[serde(default)]
+    pub exclude_chats: Vec<i64>,
+    #[serde(default)]
+    pub message: String,
+    #[serde(default)]
+    pub translations: Translations,
+    #[serde(flatten)]
+    pub attachments: Attachments,
+    /// Base64 encoded images, `data:` URLs or https URLs like in
+    /// `sendMessage`.
+    #[serde(default)]
+    pub images: Vec<String>,
+    /// When to send the message, right away when it's missing.
+    pub datetime: Option<String>,
+    #[serde(flatten)]
+    pub options: MessageOptions,
+}
+
+#[derive(Clone, Serialize, ToSchema)]
+pub struct Draft {
+    pub id: i32,
+    #[serde(flatten)]
+    pub content: DraftContent,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Returned when sending a draft that was sent or deleted meanwhile.
+#[derive(Debug)]
+pub struct DraftGone(pub i32);
+
+impl fmt::Display for DraftGone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "draft {} not found", self.0)
+    }
+}
+
+impl std::error::Error for DraftGone {}
+
+impl AppState {
+    pub async fn add_draft(&self, content: &DraftContent) -> anyhow::Result<Draft> {
+        let draft = self.storage.add_draft(&self.bot_id, content).await?;
+        info!("saved draft {}", draft.id);
+
+        Ok(draft)
+    }
+
+    pub async fn get_drafts(&self) -> anyhow::Result<Vec<Draft>> {
+        self.storage.get_drafts(&self.bot_id).await
+    }
+
+    pub async fn get_draft(&self, id: i32) -> anyhow::Result<Option<Draft>> {
+        self.storage.get_draft(&self.bot_id, id).await
+    }
+
+    /// Replaces the content of the draft, `None` when there is no such
+    /// draft.
+    pub async fn update_draft(
+        &self,
+        id: i32,
+        content: &DraftContent,
+    ) -> anyhow::Result<Option<Draft>> {
+        info!("updating draft {id}");
+
+        self.storage.update_draft(&self.bot_id, id, content).await
+    }
+
+    /// Returns `false` when there is no such draft.
+    pub async fn delete_draft(&self, id: i32) -> anyhow::Result<bool> {
+        info!("deleting draft {id}");
+
+        self.storage.delete_draft(&self.bot_id, id).await
+    }
+}
@@ -0,0 +1,67 @@
+use std::fmt;
+
+use chrono::{DateTime, Duration, Utc};
+use sha2::{Digest, Sha256};
+
+use crate::{state::AppState, subscriber::Audience, topic::ChatTarget};
+
+/// Hash of a message text, kept on the queue row to recognise repeated
+/// broadcasts without comparing whole texts.
+pub fn text_hash(text: &str) -> String {
+    format!("{:x}", Sha256::digest(text.as_bytes()))
+}
+
+/// Returned when queueing a message whose text was queued for the same
+/// chats within the window, carrying the id of that message.
+#[derive(Debug)]
+pub struct DuplicateBroadcast(pub i32);
+
+impl fmt::Display for DuplicateBroadcast {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "queued message {} has the same text for these chats, set force to send it anyway",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for DuplicateBroadcast {}
+
+impl AppState {
+    /// Start of the window in which a message with the same text counts as a
+    /// duplicate, `None` when the window is zero.
+    pub fn duplicates_since(&self, text: &str) -> Option<DateTime<Utc>> {
+        // broadcasts of only a sticker or a location have no text to compare
+        if self.config.duplicate_window == 0 || text.trim().is_empty() {
+            return None;
+        }
+
+        Some(Utc::now() - Duration::seconds(self.config.duplicate_window as i64))
+    }
+
+    /// Id of a message with the same text queued for any of the targets
+    /// within the configured window, `None` when the window is zero.
+    pub async fn find_duplicate_broadcast(
+        &self,
+        text: &str,
+        targets: Option<&[ChatTarget]>,
+        audience: Audience,
+    ) -> anyhow::Result<Option<i32>> {
+        let Some(since) = self.duplicates_since(text) else {
+            return Ok(None);
+        };
+        let chats: Option<Vec<i64>> =
+            targets.map(|targets| targets.iter().map(|target| target.chat_id()).collect());
+
+        self.storage
+            .find_duplicate_message(
+                &self.bot_id,
+                &text_hash(text),
+                since,
+                chats.as_deref(),
+                audience,
+            )
+            .await
+    }
+}
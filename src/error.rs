@@ -0,0 +1,137 @@
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+use tracing::error;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::{
+    audit::current_audit_id, draft::DraftGone, duplicate::DuplicateBroadcast,
+    state::PostgresRequired, status_store::CleanupElsewhere, telemetry::current_request_id,
+    validation::FieldError,
+};
+
+/// Error returned by every API handler, rendered as a JSON body carrying a
+/// machine readable code and the request id that is also written to the logs.
+#[derive(Debug)]
+pub struct ApiError {
+    status: StatusCode,
+    code: &'static str,
+    message: String,
+    errors: Vec<FieldError>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ErrorBody {
+    code: &'static str,
+    message: String,
+    request_id: String,
+    /// Audit log entry of the request, to quote when asking for support.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    audit_id: Option<i64>,
+    /// Every invalid field of the request, for `validation_failed` errors.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    errors: Vec<FieldError>,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, code: &'static str, message: impl ToString) -> Self {
+        Self {
+            status,
+            code,
+            message: message.to_string(),
+            errors: Vec::new(),
+        }
+    }
+
+    pub fn bad_request(message: impl ToString) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, "bad_request", message)
+    }
+
+    pub fn not_found(message: impl ToString) -> Self {
+        Self::new(StatusCode::NOT_FOUND, "not_found", message)
+    }
+
+    pub fn validation(errors: Vec<FieldError>) -> Self {
+        Self {
+            errors,
+            ..Self::new(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "validation_failed",
+                "the request has invalid fields",
+            )
+        }
+    }
+}
+
+impl From<anyhow::Error> for ApiError {
+    fn from(err: anyhow::Error) -> Self {
+        if let Some(err) = err.downcast_ref::<sqlx::Error>() {
+            return match err {
+                sqlx::Error::RowNotFound => Self::not_found("resource not found"),
+                // invalid_regular_expression, from a user supplied pattern
+                sqlx::Error::Database(err) if err.code().as_deref() == Some("2201B") => {
+                    Self::bad_request(err.message())
+                }
+                // the details name tables and constraints, they go to the
+                // logs only
+                err => {
+                    error!("database error: {err}");
+                    Self::new(
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        "database_error",
+                        "the database failed to process the request",
+                    )
+                }
+            };
+        }
+
+        if let Some(err) = err.downcast_ref::<CleanupElsewhere>() {
+            return Self::new(StatusCode::CONFLICT, "cleanup_on_other_instance", err);
+        }
+
+        if let Some(err) = err.downcast_ref::<DraftGone>() {
+            return Self::not_found(err);
+        }
+
+        if let Some(err) = err.downcast_ref::<DuplicateBroadcast>() {
+            return Self::new(StatusCode::CONFLICT, "duplicate_broadcast", err);
+        }
+
+        if let Some(err) = err.downcast_ref::<PostgresRequired>() {
+            return Self::new(StatusCode::NOT_IMPLEMENTED, "postgres_required", err);
+        }
+
+        if let Some(err) = err.downcast_ref::<teloxide::RequestError>() {
+            return Self::new(StatusCode::BAD_GATEWAY, "telegram_error", err);
+        }
+
+        Self::new(StatusCode::INTERNAL_SERVER_ERROR, "internal_error", err)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let request_id = current_request_id().unwrap_or_else(|| Uuid::new_v4().to_string());
+
+        if self.status.is_server_error() {
+            error!(
+                "request {request_id} failed: {} {}",
+                self.code, self.message
+            );
+        }
+
+        let body = ErrorBody {
+            code: self.code,
+            message: self.message,
+            request_id,
+            audit_id: current_audit_id(),
+            errors: self.errors,
+        };
+
+        (self.status, Json(body)).into_response()
+    }
+}
@@ -0,0 +1,175 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::{
+    broadcast::requests_per_chat,
+    queue::{QueueFilter, QueueStatus},
+    split::split_message,
+    state::AppState,
+    topic::Targets,
+};
+
+// queued messages looked at for the backlog, the rest is too far out to matter
+const MAX_BACKLOG: i64 = 1000;
+
+/// How long a broadcast would take under the current limits.
+#[derive(Serialize, ToSchema)]
+pub struct BroadcastEstimate {
+    /// Chats that would receive the broadcast if it was sent now.
+    pub chats: usize,
+    /// Requests to telegram the broadcast makes in total.
+    pub requests: u32,
+    /// Time sending takes once the queue gets to the broadcast.
+    pub send_duration_secs: u64,
+    /// Broadcasts being sent or due by then, which go first.
+    pub backlog_messages: usize,
+    pub backlog_secs: u64,
+    /// Time from when the broadcast is due until it is sent everywhere,
+    /// waiting for the backlog included.
+    pub estimated_duration_secs: u64,
+    pub finishes_at: DateTime<Utc>,
+}
+
+/// Time the queue needs before it gets to a new broadcast.
+pub struct Backlog {
+    pub messages: usize,
+    pub duration: Duration,
+}
+
+impl AppState {
+    /// How long sending `requests_per_chat` requests to each of the chats
+    /// takes. Chats are sent to one after another and every request waits
+    /// for the broadcast rate, telegram's limits per chat and the chat's
+    /// rate limit override, whichever is slowest. Broadcasts queued before it
+    /// are not accounted for.
+    pub async fn estimate_broadcast_duration(
+        &self,
+        chats: &[i64],
+        requests_per_chat: u32,
+    ) -> anyhow::Result<Duration> {
+        let intervals = self.get_chat_intervals().await?;
+
+        Ok(chats
+            .iter()
+            .map(|chat_id| self.chat_send_duration(intervals.get(chat_id), requests_per_chat))
+            .sum())
+    }
+
+    fn chat_send_duration(&self, chat_interval: Option<&Duration>, requests: u32) -> Duration {
+        if requests == 0 {
+            return Duration::ZERO;
+        }
+
+        let throttle = &self.settings().throttle;
+        let rate = self
+            .config
+            .broadcast_rate
+            .min(throttle.messages_per_sec_overall)
+            .max(1);
+        let paced = Duration::from_secs(1) / rate;
+        let interval = paced
+            .max(Duration::from_secs(1) / throttle.messages_per_sec_chat.max(1))
+            .max(chat_interval.copied().unwrap_or_default());
+        let following = requests - 1;
+        let minutes = following / throttle.messages_per_min_chat.max(1);
+
+        paced + (interval * following).max(Duration::from_secs(60) * minutes)
+    }
+
+    /// Broadcasts being sent and those due by `due`, with the time left to
+    /// send them.
+    pub async fn estimate_backlog(&self, due: DateTime<Utc>) -> anyhow::Result<Backlog> {
+        let mut entries = self
+            .get_queue(
+                &QueueFilter {
+                    status: Some(QueueStatus::Processing),
+                    ..Default::default()
+                },
+                MAX_BACKLOG,
+                0,
+            )
+            .await?;
+        entries.extend(
+            self.get_queue(
+                &QueueFilter {
+                    status: Some(QueueStatus::Pending),
+                    due_before: Some(due),
+                    ..Default::default()
+                },
+                MAX_BACKLOG,
+                0,
+            )
+            .await?,
+        );
+
+        let mut backlog = Backlog {
+            messages: entries.len(),
+            duration: Duration::ZERO,
+        };
+        for entry in entries {
+            let Some(message) = self.get_queued_message(entry.id).await? else {
+                continue;
+            };
+            let options = message.options()?;
+
+            let mut chats = if message.chats.is_empty() {
+                let targets = Targets {
+                    chats: None,
+                    excluded: self.storage.get_excluded_chats(message.id).await?,
+                };
+                self.get_recipients(&targets, options.audience).await?.chats
+            } else {
+                message.chats.clone()
+            };
+            let delivered = self.get_deliveries(message.id).await?;
+            chats.retain(|chat_id| {
+                !delivered.iter().any(|delivery| {
+                    delivery.chat_id == *chat_id && delivery.deferred_until.is_none()
+                })
+            });
+
+            let parts = split_message(&message.message, options.parse_mode).len();
+            let requests = requests_per_chat(message.images.len(), parts, options.pin);
+            backlog.duration += self.estimate_broadcast_duration(&chats, requests).await?;
+        }
+
+        Ok(backlog)
+    }
+
+    /// Estimates a broadcast of `requests_per_chat` requests to the chats
+    /// that is due at `due`. The backlog is assumed to be sent from now on,
+    /// so it only delays broadcasts due before it is through.
+    pub async fn estimate_broadcast(
+        &self,
+        chats: &[i64],
+        requests_per_chat: u32,
+        due: DateTime<Utc>,
+    ) -> anyhow::Result<BroadcastEstimate> {
+        let send = self
+            .estimate_broadcast_duration(chats, requests_per_chat)
+            .await?;
+        let backlog = self.estimate_backlog(due).await?;
+
+        let now = Utc::now();
+        let due = due.max(now);
+        let starts_at = due.max(now + chrono::Duration::from_std(backlog.duration)?);
+        let finishes_at = starts_at + chrono::Duration::from_std(send)?;
+
+        Ok(BroadcastEstimate {
+            chats: chats.len(),
+            requests: chats.len() as u32 * requests_per_chat,
+            send_duration_secs: secs(send),
+            backlog_messages: backlog.messages,
+            backlog_secs: secs(backlog.duration),
+            estimated_duration_secs: secs((finishes_at - due).to_std()?),
+            finishes_at,
+        })
+    }
+}
+
+fn secs(duration: Duration) -> u64 {
+    duration.as_secs_f64().ceil() as u64
+}
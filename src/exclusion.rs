@@ -0,0 +1,89 @@
+use std::collections::HashSet;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+use utoipa::ToSchema;
+
+use crate::state::{AppState, Users};
+
+/// A user cleanups never kick, like a bot or service account the chats
+/// depend on.
+#[derive(Serialize, ToSchema, sqlx::FromRow)]
+pub struct Exclusion {
+    pub id: i32,
+    /// Chat the user is protected in, every chat of the bot when missing.
+    pub chat_id: Option<i64>,
+    pub user_id: i64,
+    pub note: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct NewExclusion {
+    #[serde(default)]
+    pub chat_id: Option<i64>,
+    pub user_id: i64,
+    /// Why the user is excluded, for whoever reads the list later.
+    #[serde(default)]
+    pub note: Option<String>,
+}
+
+impl AppState {
+    /// Adding a user that is already excluded only updates the note.
+    pub async fn add_exclusion(&self, exclusion: &NewExclusion) -> anyhow::Result<Exclusion> {
+        info!(
+            "excluding user:{} from cleanups of chat:{:?}",
+            exclusion.user_id, exclusion.chat_id
+        );
+
+        self.storage
+            .add_exclusion(
+                &self.bot_id,
+                exclusion.chat_id,
+                exclusion.user_id,
+                exclusion.note.as_deref(),
+            )
+            .await
+    }
+
+    pub async fn get_exclusions(&self) -> anyhow::Result<Vec<Exclusion>> {
+        self.storage.get_exclusions(&self.bot_id).await
+    }
+
+    pub async fn delete_exclusion(&self, id: i32) -> anyhow::Result<bool> {
+        self.storage.delete_exclusion(&self.bot_id, id).await
+    }
+
+    /// Users excluded in the chat or in every chat.
+    pub async fn get_excluded_users(&self, chat_id: i64) -> anyhow::Result<HashSet<i64>> {
+        let users = self
+            .storage
+            .get_excluded_users(&self.bot_id, chat_id)
+            .await?;
+
+        Ok(users.into_iter().collect())
+    }
+
+    /// Drops the excluded users from members about to be kicked.
+    pub async fn without_excluded(&self, chat_id: i64, members: Users) -> anyhow::Result<Users> {
+        let excluded = self.get_excluded_users(chat_id).await?;
+        if excluded.is_empty() {
+            return Ok(members);
+        }
+
+        let total = members.len();
+        let members: Users = members
+            .into_iter()
+            .filter(|user| !excluded.contains(&user.id))
+            .collect();
+        if members.len() < total {
+            info!(
+                "skipping {} excluded members of chat:{chat_id}",
+                total - members.len()
+            );
+        }
+
+        Ok(members)
+    }
+}
@@ -0,0 +1,261 @@
+use std::{fmt, str::FromStr};
+
+use anyhow::{anyhow, bail};
+use serde::{Deserialize, Serialize};
+use teloxide::{
+    types::ParseMode,
+    utils::{html, markdown},
+};
+use utoipa::ToSchema;
+
+// tags telegram accepts in html parse mode
+const HTML_TAGS: &[&str] = &[
+    "b",
+    "strong",
+    "i",
+    "em",
+    "u",
+    "ins",
+    "s",
+    "strike",
+    "del",
+    "span",
+    "tg-spoiler",
+    "a",
+    "code",
+    "pre",
+    "tg-emoji",
+    "blockquote",
+];
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum MessageFormat {
+    #[default]
+    MarkdownV2,
+    Html,
+    Plain,
+}
+
+impl MessageFormat {
+    pub fn parse_mode(self) -> Option<ParseMode> {
+        match self {
+            MessageFormat::MarkdownV2 => Some(ParseMode::MarkdownV2),
+            MessageFormat::Html => Some(ParseMode::Html),
+            MessageFormat::Plain => None,
+        }
+    }
+
+    pub fn validate(self, text: &str) -> anyhow::Result<()> {
+        match self {
+            MessageFormat::Html => validate_html(text),
+            MessageFormat::MarkdownV2 => validate_markdown_v2(text),
+            MessageFormat::Plain => Ok(()),
+        }
+    }
+
+    /// Plain text as it has to be written to show up unchanged.
+    pub fn escape(self, text: &str) -> String {
+        match self {
+            MessageFormat::Html => html::escape(text),
+            MessageFormat::MarkdownV2 => markdown::escape(text),
+            MessageFormat::Plain => text.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for MessageFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            MessageFormat::MarkdownV2 => "markdownv2",
+            MessageFormat::Html => "html",
+            MessageFormat::Plain => "plain",
+        })
+    }
+}
+
+impl FromStr for MessageFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "markdownv2" => Ok(MessageFormat::MarkdownV2),
+            "html" => Ok(MessageFormat::Html),
+            "plain" => Ok(MessageFormat::Plain),
+            other => Err(anyhow!("unknown parse mode: {other}")),
+        }
+    }
+}
+
+/// Checks that every character MarkdownV2 reserves is either escaped or part
+/// of an entity, and that entities are properly closed, so a broadcast is
+/// rejected at enqueue time instead of failing with "can't parse entities".
+fn validate_markdown_v2(text: &str) -> anyhow::Result<()> {
+    let mut open: Vec<(&str, usize)> = Vec::new();
+    let mut chars = text.char_indices().peekable();
+    let mut line_start = true;
+
+    while let Some((offset, c)) = chars.next() {
+        let at_line_start = line_start;
+        line_start = c == '\n';
+
+        match c {
+            '\\' => {
+                chars
+                    .next()
+                    .ok_or_else(|| anyhow!("dangling '\\' at byte offset {offset}"))?;
+            }
+            '`' => {
+                let rest = &text[offset..];
+                let (fence, len) = if rest.starts_with("```") {
+                    ("```", 3)
+                } else {
+                    ("`", 1)
+                };
+                // only '`' and '\' are special inside code
+                let body = &rest[len..];
+                let mut end = None;
+                let mut escaped = false;
+                for (index, c) in body.char_indices() {
+                    if escaped {
+                        escaped = false;
+                    } else if c == '\\' {
+                        escaped = true;
+                    } else if body[index..].starts_with(fence) {
+                        end = Some(index);
+                        break;
+                    }
+                }
+                let end =
+                    end.ok_or_else(|| anyhow!("code at byte offset {offset} is never closed"))?;
+                let skip_to = offset + len + end + fence.len();
+                while chars.next_if(|(index, _)| *index < skip_to).is_some() {}
+            }
+            '*' | '~' => toggle_entity(&mut open, if c == '*' { "*" } else { "~" }, offset)?,
+            '_' => {
+                if chars.next_if(|(_, c)| *c == '_').is_some() {
+                    toggle_entity(&mut open, "__", offset)?;
+                } else {
+                    toggle_entity(&mut open, "_", offset)?;
+                }
+            }
+            '|' => {
+                if chars.next_if(|(_, c)| *c == '|').is_none() {
+                    bail!("character '|' at byte offset {offset} must be escaped with '\\'");
+                }
+                toggle_entity(&mut open, "||", offset)?;
+            }
+            '[' => open.push(("[", offset)),
+            '!' if chars.peek().map(|(_, c)| *c) == Some('[') => {}
+            ']' => {
+                match open.pop() {
+                    Some(("[", _)) => {}
+                    _ => bail!("character ']' at byte offset {offset} must be escaped with '\\'"),
+                }
+                if chars.next_if(|(_, c)| *c == '(').is_none() {
+                    bail!("link at byte offset {offset} is missing its url");
+                }
+                // inside the url only ')' and '\' have to be escaped
+                loop {
+                    match chars.next() {
+                        Some((_, '\\')) => {
+                            chars.next();
+                        }
+                        Some((_, ')')) => break,
+                        Some(_) => {}
+                        None => bail!("link url at byte offset {offset} is never closed"),
+                    }
+                }
+            }
+            '>' if at_line_start => {}
+            '(' | ')' | '#' | '+' | '-' | '=' | '{' | '}' | '.' | '!' | '>' => {
+                bail!("character '{c}' at byte offset {offset} must be escaped with '\\'")
+            }
+            _ => {}
+        }
+    }
+
+    if let Some((entity, offset)) = open.pop() {
+        bail!("'{entity}' at byte offset {offset} is never closed");
+    }
+
+    Ok(())
+}
+
+fn toggle_entity<'a>(
+    open: &mut Vec<(&'a str, usize)>,
+    entity: &'a str,
+    offset: usize,
+) -> anyhow::Result<()> {
+    match open.iter().rposition(|(open, _)| *open == entity) {
+        Some(index) if index == open.len() - 1 => {
+            open.pop();
+        }
+        Some(_) => bail!("'{entity}' at byte offset {offset} closes an entity out of order"),
+        None => open.push((entity, offset)),
+    }
+
+    Ok(())
+}
+
+/// Checks that the text only uses tags telegram understands, that they are
+/// properly nested and that every `&` starts an entity.
+fn validate_html(text: &str) -> anyhow::Result<()> {
+    let mut open_tags: Vec<&str> = Vec::new();
+    let mut rest = text;
+
+    while let Some(pos) = rest.find(['<', '&']) {
+        let offset = text.len() - rest.len() + pos;
+        let tail = &rest[pos..];
+
+        if tail.starts_with('&') {
+            let end = tail
+                .find(';')
+                .filter(|end| is_html_entity(&tail[1..*end]))
+                .ok_or_else(|| anyhow!("unescaped '&' at byte offset {offset}"))?;
+            rest = &tail[end + 1..];
+            continue;
+        }
+
+        let end = tail
+            .find('>')
+            .ok_or_else(|| anyhow!("unclosed tag at byte offset {offset}"))?;
+        let tag = &tail[1..end];
+        let (closing, tag) = match tag.strip_prefix('/') {
+            Some(tag) => (true, tag),
+            None => (false, tag),
+        };
+        let name = tag.split_whitespace().next().unwrap_or_default();
+
+        if !HTML_TAGS.contains(&name) {
+            bail!("unsupported tag <{name}> at byte offset {offset}");
+        }
+
+        if closing {
+            match open_tags.pop() {
+                Some(open) if open == name => {}
+                _ => bail!("unexpected closing tag </{name}> at byte offset {offset}"),
+            }
+        } else {
+            open_tags.push(name);
+        }
+
+        rest = &tail[end + 1..];
+    }
+
+    if let Some(tag) = open_tags.pop() {
+        bail!("tag <{tag}> is never closed");
+    }
+
+    Ok(())
+}
+
+fn is_html_entity(entity: &str) -> bool {
+    match entity.strip_prefix('#') {
+        Some(code) => match code.strip_prefix('x') {
+            Some(hex) => !hex.is_empty() && hex.chars().all(|c| c.is_ascii_hexdigit()),
+            None => !code.is_empty() && code.chars().all(|c| c.is_ascii_digit()),
+        },
+        None => matches!(entity, "lt" | "gt" | "amp" | "quot"),
+    }
+}
@@ -0,0 +1,302 @@
+use std::{
+    fmt::Write,
+    future::Future,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::anyhow;
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use serde::Serialize;
+use tracing::{error, info};
+use utoipa::ToSchema;
+
+use crate::{config::IntervalsConfig, state::AppState};
+
+// how often supervised tasks are looked at, and the pause before a restart
+const SUPERVISE_INTERVAL: Duration = Duration::from_secs(1);
+
+struct Heartbeat {
+    last_beat: Instant,
+    last_beat_at: DateTime<Utc>,
+    max_age: Duration,
+    restarts: u32,
+}
+
+/// A background task of a bot as reported by `/healthz`.
+#[derive(Serialize, ToSchema)]
+pub struct TaskHeartbeat {
+    pub task: &'static str,
+    pub bot_id: String,
+    pub last_beat_at: DateTime<Utc>,
+    pub age_secs: u64,
+    /// Age after which the task counts as stale in `/readyz`.
+    pub max_age_secs: u64,
+    pub stale: bool,
+    /// Times the task was started again since the service started.
+    pub restarts: u32,
+}
+
+/// Registry of background task heartbeats per bot, used by `/readyz` to
+/// notice loops that stopped iterating and to restart them.
+#[derive(Clone)]
+pub struct Health {
+    heartbeats: Arc<DashMap<(String, &'static str), Heartbeat>>,
+    started: Instant,
+    /// Result of the last ping of the database health check.
+    database_reachable: Arc<AtomicBool>,
+}
+
+impl Default for Health {
+    fn default() -> Self {
+        Self {
+            heartbeats: Arc::default(),
+            started: Instant::now(),
+            // the database was reached at startup
+            database_reachable: Arc::new(AtomicBool::new(true)),
+        }
+    }
+}
+
+impl Health {
+    pub fn uptime(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// Starts tracking a task that is expected to beat at least once per `max_age`.
+    pub fn register(&self, bot_id: &str, task: &'static str, max_age: Duration) {
+        self.heartbeats.insert(
+            (bot_id.to_string(), task),
+            Heartbeat {
+                last_beat: Instant::now(),
+                last_beat_at: Utc::now(),
+                max_age,
+                restarts: 0,
+            },
+        );
+    }
+
+    /// For tasks whose interval changed while they run.
+    pub fn set_max_age(&self, bot_id: &str, task: &'static str, max_age: Duration) {
+        if let Some(mut heartbeat) = self.heartbeats.get_mut(&(bot_id.to_string(), task)) {
+            heartbeat.max_age = max_age;
+        }
+    }
+
+    pub fn beat(&self, bot_id: &str, task: &'static str) {
+        if let Some(mut heartbeat) = self.heartbeats.get_mut(&(bot_id.to_string(), task)) {
+            heartbeat.last_beat = Instant::now();
+            heartbeat.last_beat_at = Utc::now();
+        }
+    }
+
+    /// Time since the task last beat, `None` for tasks that aren't tracked.
+    pub fn age(&self, bot_id: &str, task: &'static str) -> Option<Duration> {
+        self.heartbeats
+            .get(&(bot_id.to_string(), task))
+            .map(|heartbeat| heartbeat.last_beat.elapsed())
+    }
+
+    /// Counts a restart, the new run gets a full `max_age` to beat.
+    fn record_restart(&self, bot_id: &str, task: &'static str) {
+        if let Some(mut heartbeat) = self.heartbeats.get_mut(&(bot_id.to_string(), task)) {
+            heartbeat.last_beat = Instant::now();
+            heartbeat.restarts += 1;
+        }
+    }
+
+    pub fn database_reachable(&self) -> bool {
+        self.database_reachable.load(Ordering::Relaxed)
+    }
+
+    /// Returns whether the state changed.
+    fn set_database_reachable(&self, reachable: bool) -> bool {
+        self.database_reachable.swap(reachable, Ordering::Relaxed) != reachable
+    }
+
+    /// Names of the tasks that are stale for any bot.
+    pub fn stale_tasks(&self) -> Vec<&'static str> {
+        let mut tasks: Vec<_> = self
+            .heartbeats
+            .iter()
+            .filter(|heartbeat| heartbeat.last_beat.elapsed() > heartbeat.max_age)
+            .map(|heartbeat| heartbeat.key().1)
+            .collect();
+        tasks.sort();
+        tasks.dedup();
+        tasks
+    }
+
+    /// Every tracked task, ordered by bot and name.
+    pub fn heartbeats(&self) -> Vec<TaskHeartbeat> {
+        let mut heartbeats: Vec<_> = self
+            .heartbeats
+            .iter()
+            .map(|heartbeat| {
+                let age = heartbeat.last_beat.elapsed();
+                TaskHeartbeat {
+                    task: heartbeat.key().1,
+                    bot_id: heartbeat.key().0.clone(),
+                    last_beat_at: heartbeat.last_beat_at,
+                    age_secs: age.as_secs(),
+                    max_age_secs: heartbeat.max_age.as_secs(),
+                    stale: age > heartbeat.max_age,
+                    restarts: heartbeat.restarts,
+                }
+            })
+            .collect();
+        heartbeats.sort_by(|a, b| (&a.bot_id, a.task).cmp(&(&b.bot_id, b.task)));
+        heartbeats
+    }
+
+    /// The heartbeats in the Prometheus text format.
+    pub fn metrics(&self) -> String {
+        let heartbeats = self.heartbeats();
+        let mut metrics = String::new();
+
+        let _ = writeln!(
+            metrics,
+            "# HELP telegram_sender_uptime_seconds Seconds since the service started.\n\
+             # TYPE telegram_sender_uptime_seconds gauge\n\
+             telegram_sender_uptime_seconds {}",
+            self.uptime().as_secs()
+        );
+        let _ = writeln!(
+            metrics,
+            "# HELP telegram_sender_task_heartbeat_age_seconds Seconds since the background task last beat.\n\
+             # TYPE telegram_sender_task_heartbeat_age_seconds gauge"
+        );
+        for heartbeat in &heartbeats {
+            let _ = writeln!(
+                metrics,
+                "telegram_sender_task_heartbeat_age_seconds{{bot=\"{}\",task=\"{}\"}} {}",
+                heartbeat.bot_id, heartbeat.task, heartbeat.age_secs
+            );
+        }
+        let _ = writeln!(
+            metrics,
+            "# HELP telegram_sender_task_restarts_total Times the background task was restarted.\n\
+             # TYPE telegram_sender_task_restarts_total counter"
+        );
+        for heartbeat in &heartbeats {
+            let _ = writeln!(
+                metrics,
+                "telegram_sender_task_restarts_total{{bot=\"{}\",task=\"{}\"}} {}",
+                heartbeat.bot_id, heartbeat.task, heartbeat.restarts
+            );
+        }
+
+        metrics
+    }
+}
+
+impl AppState {
+    pub fn heartbeat(&self, task: &'static str) {
+        self.health.beat(&self.bot_id, task);
+    }
+
+    /// Runs a background loop of the bot, expected to beat at least once per
+    /// `max_age` of the current intervals, and starts it again when it
+    /// panics, returns or didn't beat for
+    /// [`task_restart_after`](crate::config::Config::task_restart_after).
+    pub async fn supervise<F, Fut>(
+        self,
+        task: &'static str,
+        max_age: fn(&IntervalsConfig) -> Duration,
+        run: F,
+    ) -> anyhow::Result<()>
+    where
+        F: Fn(Self) -> Fut,
+        Fut: Future<Output = anyhow::Result<()>> + Send + 'static,
+    {
+        self.health
+            .register(&self.bot_id, task, max_age(&self.settings().intervals));
+
+        loop {
+            let mut handle = tokio::spawn(run(self.clone()));
+            loop {
+                tokio::select! {
+                    result = &mut handle => {
+                        match result {
+                            Ok(Ok(())) => error!("{task} of bot {} returned", self.bot_id),
+                            Ok(Err(err)) => error!("{task} of bot {} failed: {err}", self.bot_id),
+                            Err(err) => error!("{task} of bot {} panicked: {err}", self.bot_id),
+                        }
+                        break;
+                    }
+                    _ = tokio::time::sleep(SUPERVISE_INTERVAL) => {
+                        let max_age = max_age(&self.settings().intervals);
+                        self.health.set_max_age(&self.bot_id, task, max_age);
+                        let restart_after = self
+                            .config
+                            .task_restart_after()
+                            .map(|after| after.max(max_age));
+                        let age = self.health.age(&self.bot_id, task).unwrap_or_default();
+                        if restart_after.is_some_and(|after| age > after) {
+                            error!("{task} of bot {} didn't beat for {age:?}", self.bot_id);
+                            handle.abort();
+                            break;
+                        }
+                    }
+                }
+            }
+
+            self.alert(
+                &format!("task:{}:{task}", self.bot_id),
+                &format!("{task} stopped and is restarted"),
+            );
+            tokio::time::sleep(SUPERVISE_INTERVAL).await;
+            info!("restarting {task} of bot {}", self.bot_id);
+            self.health.record_restart(&self.bot_id, task);
+        }
+    }
+
+    /// Pings the database in the background for `/readyz`, so readiness
+    /// checks don't pile up on a pool waiting for connections. The pool
+    /// reconnects on its own once the database is back.
+    pub async fn database_health_check(state: Self) -> anyhow::Result<()> {
+        let interval = state.config.database_pool.health_check_interval();
+        loop {
+            let ping = match tokio::time::timeout(interval, state.storage.ping()).await {
+                Ok(ping) => ping,
+                Err(_) => Err(anyhow!("ping timed out after {interval:?}")),
+            };
+            match ping {
+                Ok(()) => {
+                    if state.health.set_database_reachable(true) {
+                        info!("database is reachable again");
+                    }
+                }
+                Err(err) => {
+                    if state.health.set_database_reachable(false) {
+                        error!("database is unreachable: {err}");
+                        state.alert("database", &format!("the database is unreachable: {err}"));
+                    }
+                }
+            }
+
+            tokio::time::sleep(interval).await;
+        }
+    }
+}
+
+/// When telegram last answered a request of the bot, so token or connection
+/// trouble can be told apart from a bot that just had nothing to do.
+#[derive(Clone, Default)]
+pub struct TelegramActivity {
+    last_success: Arc<Mutex<Option<DateTime<Utc>>>>,
+}
+
+impl TelegramActivity {
+    pub fn record_success(&self) {
+        *self.last_success.lock().unwrap() = Some(Utc::now());
+    }
+
+    pub fn last_success(&self) -> Option<DateTime<Utc>> {
+        *self.last_success.lock().unwrap()
+    }
+}
@@ -0,0 +1,212 @@
+use std::time::Duration;
+
+use axum::{
+    body::{boxed, Full},
+    extract::OriginalUri,
+    http::{header::CONTENT_TYPE, HeaderValue, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use tracing::{error, info};
+
+use crate::{api::initiator, error::ApiError, state::AppState};
+
+pub const IDEMPOTENCY_CLEANUP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Middleware making retries of a request carrying an `Idempotency-Key`
+/// header safe: the first successful response is stored for 24 hours and
+/// replayed for every later request of the same caller with the same key and
+/// path. A key whose request never finished may be taken again after a few
+/// minutes.
+pub async fn idempotency<B>(req: Request<B>, next: Next<B>) -> Response {
+    let key = req
+        .headers()
+        .get("idempotency-key")
+        .and_then(|key| key.to_str().ok())
+        .map(str::to_string);
+    let (Some(key), Some(state)) = (key, req.extensions().get::<AppState>().cloned()) else {
+        return next.run(req).await;
+    };
+    // nested bot routes see their path without the `/bots/:bot_id` prefix
+    let endpoint = match req.extensions().get::<OriginalUri>() {
+        Some(OriginalUri(uri)) => uri.path().to_string(),
+        None => req.uri().path().to_string(),
+    };
+    let key = IdempotencyKey {
+        bot_id: state.bot_id.to_string(),
+        actor: initiator(&state, req.headers()),
+        key,
+        endpoint,
+    };
+
+    match state.reserve_idempotency_key(&key).await {
+        Ok(Reservation::New) => {}
+        Ok(Reservation::Pending) => {
+            return ApiError::new(
+                StatusCode::CONFLICT,
+                "request_in_progress",
+                "a request with this idempotency key is still being processed",
+            )
+            .into_response()
+        }
+        Ok(Reservation::Done(response)) => return response,
+        Err(err) => return ApiError::from(err).into_response(),
+    }
+
+    let response = next.run(req).await;
+    let result = if response.status().is_success() {
+        state.store_idempotent_response(&key, response).await
+    } else {
+        // failed requests may be retried with the same key
+        state.release_idempotency_key(&key).await.map(|_| response)
+    };
+
+    result.unwrap_or_else(|err| ApiError::from(err).into_response())
+}
+
+/// Keys are separate per bot and caller, so nobody can replay another
+/// caller's response by guessing their key.
+struct IdempotencyKey {
+    bot_id: String,
+    actor: String,
+    key: String,
+    endpoint: String,
+}
+
+enum Reservation {
+    New,
+    Pending,
+    Done(Response),
+}
+
+impl AppState {
+    pub async fn idempotency_cleanup(state: Self) -> anyhow::Result<()> {
+        loop {
+            state.heartbeat("idempotency_cleanup");
+            if let Err(err) = state.delete_expired_idempotency_keys().await {
+                error!("failed to delete expired idempotency keys: {err}");
+            }
+            tokio::time::sleep(IDEMPOTENCY_CLEANUP_INTERVAL).await;
+        }
+    }
+
+    async fn delete_expired_idempotency_keys(&self) -> anyhow::Result<()> {
+        let deleted = sqlx::query!(
+            r#"
+DELETE FROM idempotency_key
+WHERE created_at < now() - interval '24 hours'
+            "#
+        )
+        .execute(self.pg()?)
+        .await?
+        .rows_affected();
+        if deleted > 0 {
+            info!("deleted {deleted} expired idempotency keys");
+        }
+
+        Ok(())
+    }
+
+    async fn reserve_idempotency_key(&self, key: &IdempotencyKey) -> anyhow::Result<Reservation> {
+        // a pending key is leased for five minutes, after that its request
+        // is taken to have died and a retry may run it again
+        let reserved = sqlx::query!(
+            r#"
+INSERT INTO idempotency_key ( bot_id, actor, key, endpoint )
+VALUES ( $1, $2, $3, $4 )
+ON CONFLICT ( bot_id, actor, key, endpoint ) DO UPDATE
+SET created_at = now()
+WHERE idempotency_key.status IS NULL
+    AND idempotency_key.created_at < now() - interval '5 minutes'
+            "#,
+            key.bot_id,
+            key.actor,
+            key.key,
+            key.endpoint
+        )
+        .execute(self.pg()?)
+        .await?;
+        if reserved.rows_affected() > 0 {
+            return Ok(Reservation::New);
+        }
+
+        let stored = sqlx::query!(
+            r#"
+SELECT status, content_type, body FROM idempotency_key
+WHERE bot_id = $1 AND actor = $2 AND key = $3 AND endpoint = $4
+            "#,
+            key.bot_id,
+            key.actor,
+            key.key,
+            key.endpoint
+        )
+        .fetch_one(self.pg()?)
+        .await?;
+
+        let Some(status) = stored.status else {
+            return Ok(Reservation::Pending);
+        };
+
+        let mut response = Response::new(boxed(Full::from(stored.body.unwrap_or_default())));
+        *response.status_mut() = StatusCode::from_u16(status as u16)?;
+        if let Some(content_type) = stored.content_type {
+            response
+                .headers_mut()
+                .insert(CONTENT_TYPE, HeaderValue::from_str(&content_type)?);
+        }
+
+        Ok(Reservation::Done(response))
+    }
+
+    async fn store_idempotent_response(
+        &self,
+        key: &IdempotencyKey,
+        response: Response,
+    ) -> anyhow::Result<Response> {
+        let (parts, body) = response.into_parts();
+        let body = hyper::body::to_bytes(body).await?;
+        let content_type = parts
+            .headers
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok());
+
+        sqlx::query!(
+            r#"
+UPDATE idempotency_key
+SET status = $5, content_type = $6, body = $7
+WHERE bot_id = $1 AND actor = $2 AND key = $3 AND endpoint = $4
+    AND status IS NULL
+            "#,
+            key.bot_id,
+            key.actor,
+            key.key,
+            key.endpoint,
+            parts.status.as_u16() as i16,
+            content_type,
+            body.as_ref()
+        )
+        .execute(self.pg()?)
+        .await?;
+
+        Ok(Response::from_parts(parts, boxed(Full::from(body))))
+    }
+
+    async fn release_idempotency_key(&self, key: &IdempotencyKey) -> anyhow::Result<()> {
+        sqlx::query!(
+            r#"
+DELETE FROM idempotency_key
+WHERE bot_id = $1 AND actor = $2 AND key = $3 AND endpoint = $4
+    AND status IS NULL
+            "#,
+            key.bot_id,
+            key.actor,
+            key.key,
+            key.endpoint
+        )
+        .execute(self.pg()?)
+        .await?;
+
+        Ok(())
+    }
+}
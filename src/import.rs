@@ -0,0 +1,235 @@
+use std::collections::HashSet;
+
+use anyhow::bail;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+use utoipa::ToSchema;
+
+use crate::state::AppState;
+
+/// What to do with chats and members that already exist.
+#[derive(Clone, Copy, Default, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ConflictMode {
+    #[default]
+    Skip,
+    Overwrite,
+}
+
+#[derive(Default, Deserialize, ToSchema)]
+pub struct Backup {
+    #[serde(default)]
+    pub chats: Vec<ImportedChat>,
+    #[serde(default)]
+    pub members: Vec<ImportedMember>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct ImportedChat {
+    pub id: i64,
+    pub name: String,
+    #[serde(default)]
+    pub muted: bool,
+    #[serde(default = "default_approved")]
+    pub approved: bool,
+}
+
+fn default_approved() -> bool {
+    true
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct ImportedMember {
+    pub id: i64,
+    pub chat_id: i64,
+    pub username: Option<String>,
+    pub name: String,
+}
+
+#[derive(Default, Serialize, ToSchema)]
+pub struct ImportCounts {
+    created: u64,
+    updated: u64,
+    skipped: u64,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ImportReport {
+    dry_run: bool,
+    chats: ImportCounts,
+    members: ImportCounts,
+}
+
+// one row per member: chat_id,chat_name,user_id,username,name, a row with an
+// empty user_id only describes the chat
+#[derive(Deserialize)]
+struct CsvRow {
+    chat_id: i64,
+    chat_name: String,
+    user_id: Option<i64>,
+    username: Option<String>,
+    name: Option<String>,
+}
+
+impl Backup {
+    pub fn from_csv(data: &[u8]) -> anyhow::Result<Self> {
+        let mut backup = Backup::default();
+        let mut chats = HashSet::new();
+
+        for row in csv::Reader::from_reader(data).deserialize() {
+            let row: CsvRow = row?;
+            if chats.insert(row.chat_id) {
+                backup.chats.push(ImportedChat {
+                    id: row.chat_id,
+                    name: row.chat_name,
+                    muted: false,
+                    approved: true,
+                });
+            }
+            if let Some(id) = row.user_id {
+                backup.members.push(ImportedMember {
+                    id,
+                    chat_id: row.chat_id,
+                    username: row.username.filter(|username| !username.is_empty()),
+                    name: row.name.unwrap_or_default(),
+                });
+            }
+        }
+
+        Ok(backup)
+    }
+}
+
+impl AppState {
+    /// Loads chats and members from a backup in a single transaction. A dry
+    /// run does all the work and rolls back, so the report is exact.
+    pub async fn import_backup(
+        &self,
+        backup: Backup,
+        mode: ConflictMode,
+        dry_run: bool,
+    ) -> anyhow::Result<ImportReport> {
+        info!(
+            "importing {} chats and {} members, dry run: {dry_run}",
+            backup.chats.len(),
+            backup.members.len()
+        );
+
+        let mut tx = self.pg()?.begin().await?;
+        let mut chats = ImportCounts::default();
+        let mut members = ImportCounts::default();
+
+        for chat in &backup.chats {
+            let owner = sqlx::query_scalar!(
+                r#"
+SELECT bot_id FROM tg_chat WHERE id = $1
+                "#,
+                chat.id
+            )
+            .fetch_optional(&mut tx)
+            .await?;
+
+            if matches!(&owner, Some(owner) if **owner != *self.bot_id) {
+                bail!("chat {} belongs to another bot", chat.id);
+            }
+
+            match (owner.is_some(), mode) {
+                (true, ConflictMode::Skip) => chats.skipped += 1,
+                (true, ConflictMode::Overwrite) => {
+                    sqlx::query!(
+                        r#"
+UPDATE tg_chat
+SET name = $2, muted = $3, approved = $4
+WHERE id = $1
+                        "#,
+                        chat.id,
+                        chat.name,
+                        chat.muted,
+                        chat.approved
+                    )
+                    .execute(&mut tx)
+                    .await?;
+                    chats.updated += 1;
+                }
+                (false, _) => {
+                    sqlx::query!(
+                        r#"
+INSERT INTO tg_chat ( id, name, muted, approved, bot_id )
+VALUES ( $1, $2, $3, $4, $5 )
+                        "#,
+                        chat.id,
+                        chat.name,
+                        chat.muted,
+                        chat.approved,
+                        &*self.bot_id
+                    )
+                    .execute(&mut tx)
+                    .await?;
+                    chats.created += 1;
+                }
+            }
+        }
+
+        for member in &backup.members {
+            let found = sqlx::query!(
+                r#"
+SELECT
+    EXISTS ( SELECT 1 FROM tg_chat WHERE id = $2 AND bot_id = $3 ) AS "chat_exists!",
+    EXISTS ( SELECT 1 FROM tg_user WHERE id = $1 AND chat_id = $2 ) AS "member_exists!"
+                "#,
+                member.id,
+                member.chat_id,
+                &*self.bot_id
+            )
+            .fetch_one(&mut tx)
+            .await?;
+
+            if !found.chat_exists {
+                bail!(
+                    "member {} belongs to unknown chat {}",
+                    member.id,
+                    member.chat_id
+                );
+            }
+
+            match (found.member_exists, mode) {
+                (true, ConflictMode::Skip) => members.skipped += 1,
+                (exists, _) => {
+                    sqlx::query!(
+                        r#"
+INSERT INTO tg_user ( id, chat_id, username, name, bot_id )
+VALUES ( $1, $2, $3, $4, $5 )
+ON CONFLICT ( id, chat_id ) DO UPDATE
+SET username = $3, name = $4
+                        "#,
+                        member.id,
+                        member.chat_id,
+                        member.username,
+                        member.name,
+                        &*self.bot_id
+                    )
+                    .execute(&mut tx)
+                    .await?;
+                    if exists {
+                        members.updated += 1;
+                    } else {
+                        members.created += 1;
+                    }
+                }
+            }
+        }
+
+        if dry_run {
+            tx.rollback().await?;
+        } else {
+            tx.commit().await?;
+            self.fill_status_list().await?;
+        }
+
+        Ok(ImportReport {
+            dry_run,
+            chats,
+            members,
+        })
+    }
+}
@@ -0,0 +1,139 @@
+use anyhow::bail;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use teloxide::{
+    payloads::CreateChatInviteLinkSetters,
+    requests::Requester,
+    types::{ChatId, ChatInviteLink},
+    RequestError,
+};
+use tracing::info;
+use utoipa::ToSchema;
+
+use crate::state::AppState;
+
+const MAX_NAME_LENGTH: usize = 32;
+const MAX_MEMBER_LIMIT: i32 = 99_999;
+
+/// Options of an invite link to create, the bot has to be an admin allowed to
+/// invite users in the chat.
+#[derive(Clone, Default, Deserialize, ToSchema)]
+pub struct NewInviteLink {
+    /// Shown to admins in the chat, handy to tell campaigns apart.
+    pub name: Option<String>,
+    /// The link stops working after this time.
+    pub expire_date: Option<DateTime<Utc>>,
+    /// The link stops working once this many users joined through it.
+    pub member_limit: Option<i32>,
+    /// Users joining through the link have to be approved, can't be
+    /// combined with `member_limit`.
+    #[serde(default)]
+    pub creates_join_request: bool,
+}
+
+impl NewInviteLink {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if let Some(name) = &self.name {
+            if name.chars().count() > MAX_NAME_LENGTH {
+                bail!("name is longer than {MAX_NAME_LENGTH} characters");
+            }
+        }
+        if self
+            .expire_date
+            .is_some_and(|expire_date| expire_date <= Utc::now())
+        {
+            bail!("expire_date is in the past");
+        }
+        if let Some(limit) = self.member_limit {
+            if !(1..=MAX_MEMBER_LIMIT).contains(&limit) {
+                bail!("member_limit must be between 1 and {MAX_MEMBER_LIMIT}");
+            }
+            if self.creates_join_request {
+                bail!("member_limit and creates_join_request can't be combined");
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// An invite link the bot created, kept after it is revoked so joins can
+/// still be traced back to it.
+#[derive(Clone, Serialize, ToSchema, sqlx::FromRow)]
+pub struct InviteLink {
+    pub id: i32,
+    pub chat_id: i64,
+    pub invite_link: String,
+    pub name: Option<String>,
+    pub expire_date: Option<DateTime<Utc>>,
+    pub member_limit: Option<i32>,
+    pub creates_join_request: bool,
+    pub created_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+fn is_ok_response(raw: &str) -> bool {
+    serde_json::from_str::<serde_json::Value>(raw).is_ok_and(|response| response["ok"] == true)
+}
+
+impl AppState {
+    pub async fn create_invite_link(
+        &self,
+        chat_id: i64,
+        options: &NewInviteLink,
+    ) -> anyhow::Result<InviteLink> {
+        options.validate()?;
+        info!("creating an invite link for chat:{chat_id}");
+
+        let mut request = self
+            .bot
+            .create_chat_invite_link(ChatId(chat_id))
+            .creates_join_request(options.creates_join_request);
+        if let Some(name) = &options.name {
+            request = request.name(name);
+        }
+        if let Some(expire_date) = options.expire_date {
+            request = request.expire_date(expire_date);
+        }
+        if let Some(limit) = options.member_limit {
+            request = request.member_limit(limit as u32);
+        }
+        let link: ChatInviteLink = request.await?;
+
+        self.storage.add_invite_link(chat_id, &link).await
+    }
+
+    /// Newest first, revoked links included.
+    pub async fn get_invite_links(&self, chat_id: i64) -> anyhow::Result<Vec<InviteLink>> {
+        self.storage.get_invite_links(chat_id).await
+    }
+
+    /// Revokes the link in telegram, `None` when the chat has no such link.
+    pub async fn revoke_invite_link(
+        &self,
+        chat_id: i64,
+        id: i32,
+    ) -> anyhow::Result<Option<InviteLink>> {
+        let Some(link) = self.storage.get_invite_link(chat_id, id).await? else {
+            return Ok(None);
+        };
+        if link.revoked_at.is_some() {
+            return Ok(Some(link));
+        }
+        info!("revoking invite link {id} of chat:{chat_id}");
+
+        match self
+            .bot
+            .revoke_chat_invite_link(ChatId(chat_id), &link.invite_link)
+            .await
+        {
+            Ok(_) => {}
+            // teloxide expects a string back but telegram answers with the
+            // revoked link, so a successful revoke fails to parse
+            Err(RequestError::InvalidJson { raw, .. }) if is_ok_response(&raw) => {}
+            Err(err) => return Err(err.into()),
+        }
+
+        self.storage.revoke_invite_link(chat_id, id).await
+    }
+}
@@ -0,0 +1,278 @@
+use std::{fmt, str::FromStr};
+
+use anyhow::anyhow;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use teloxide::{
+    payloads::SendMessageSetters,
+    requests::Requester,
+    types::{ChatId, ChatJoinRequest, InlineKeyboardButton, InlineKeyboardMarkup, UserId},
+};
+use tracing::{error, info};
+use utoipa::ToSchema;
+
+use crate::state::AppState;
+
+/// Prefix of the callback data of the buttons sent to the bot admins.
+pub const JOIN_CALLBACK_PREFIX: &str = "join";
+
+/// What happens to requests to join a chat that requires approval.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum JoinPolicy {
+    /// Requests wait for a decision through the API or the buttons sent to
+    /// the bot admins, chat admins can still decide in telegram.
+    #[default]
+    Manual,
+    Approve,
+    Decline,
+}
+
+impl fmt::Display for JoinPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            JoinPolicy::Manual => "manual",
+            JoinPolicy::Approve => "approve",
+            JoinPolicy::Decline => "decline",
+        })
+    }
+}
+
+impl FromStr for JoinPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "manual" => Ok(JoinPolicy::Manual),
+            "approve" => Ok(JoinPolicy::Approve),
+            "decline" => Ok(JoinPolicy::Decline),
+            other => Err(anyhow!("unknown join policy: {other}")),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum JoinRequestStatus {
+    Pending,
+    Approved,
+    Declined,
+}
+
+impl fmt::Display for JoinRequestStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            JoinRequestStatus::Pending => "pending",
+            JoinRequestStatus::Approved => "approved",
+            JoinRequestStatus::Declined => "declined",
+        })
+    }
+}
+
+impl FromStr for JoinRequestStatus {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pending" => Ok(JoinRequestStatus::Pending),
+            "approved" => Ok(JoinRequestStatus::Approved),
+            "declined" => Ok(JoinRequestStatus::Declined),
+            other => Err(anyhow!("unknown join request status: {other}")),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, ToSchema)]
+pub struct ChatJoinPolicy {
+    pub policy: JoinPolicy,
+}
+
+/// Everything a join request is recorded with.
+pub struct NewJoinRequest<'a> {
+    pub chat_id: i64,
+    pub user_id: i64,
+    pub username: Option<&'a str>,
+    pub name: &'a str,
+    pub bio: Option<&'a str>,
+    /// The invite link the user joins through, when it was not the primary
+    /// one.
+    pub invite_link: Option<&'a str>,
+}
+
+/// A request to join a chat that requires approval.
+#[derive(Clone, Serialize, ToSchema)]
+pub struct JoinRequest {
+    pub id: i32,
+    pub chat_id: i64,
+    pub user_id: i64,
+    pub username: Option<String>,
+    pub name: String,
+    pub bio: Option<String>,
+    pub invite_link: Option<String>,
+    pub status: JoinRequestStatus,
+    pub requested_at: DateTime<Utc>,
+    pub decided_at: Option<DateTime<Utc>>,
+    /// `policy`, `api` or `admin:<user id>` for a bot admin's button.
+    pub decided_by: Option<String>,
+}
+
+/// The decision and the request id behind a button sent to the bot admins.
+pub fn parse_join_callback(data: &str) -> Option<(bool, i32)> {
+    let (prefix, rest) = data.split_once(':')?;
+    if prefix != JOIN_CALLBACK_PREFIX {
+        return None;
+    }
+    let (action, id) = rest.split_once(':')?;
+    let approve = match action {
+        "approve" => true,
+        "decline" => false,
+        _ => return None,
+    };
+
+    Some((approve, id.parse().ok()?))
+}
+
+impl AppState {
+    /// `manual` for chats without a policy.
+    pub async fn get_join_policy(&self, chat_id: i64) -> anyhow::Result<JoinPolicy> {
+        Ok(self
+            .storage
+            .get_join_policy(chat_id)
+            .await?
+            .unwrap_or_default())
+    }
+
+    pub async fn set_join_policy(&self, chat_id: i64, policy: JoinPolicy) -> anyhow::Result<()> {
+        info!("setting the join policy of chat:{chat_id} to {policy}");
+
+        self.storage.set_join_policy(chat_id, policy).await
+    }
+
+    /// Records a request and applies the policy of its chat, the bot admins
+    /// are asked to decide on it when the policy is manual.
+    pub async fn handle_join_request(
+        &self,
+        request: &ChatJoinRequest,
+    ) -> anyhow::Result<JoinRequest> {
+        let chat_id = request.chat.id.0;
+        let user = &request.from;
+        let name = user.full_name();
+        let recorded = self
+            .storage
+            .add_join_request(NewJoinRequest {
+                chat_id,
+                user_id: user.id.0 as i64,
+                username: user.username.as_deref(),
+                name: &name,
+                bio: request.bio.as_deref(),
+                invite_link: request
+                    .invite_link
+                    .as_ref()
+                    .map(|link| link.invite_link.as_str()),
+            })
+            .await?;
+        info!(
+            "user:{} requested to join chat:{chat_id}, request {}",
+            user.id, recorded.id
+        );
+
+        let approve = match self.get_join_policy(chat_id).await? {
+            JoinPolicy::Approve => true,
+            JoinPolicy::Decline => false,
+            JoinPolicy::Manual => {
+                self.ask_admins(&recorded, request.chat.title().unwrap_or_default())
+                    .await;
+                return Ok(recorded);
+            }
+        };
+        let decided = self
+            .decide_join_request(&recorded, approve, "policy")
+            .await?;
+
+        Ok(decided.unwrap_or(recorded))
+    }
+
+    pub async fn get_join_requests(
+        &self,
+        chat_id: i64,
+        status: Option<JoinRequestStatus>,
+    ) -> anyhow::Result<Vec<JoinRequest>> {
+        self.storage.get_join_requests(chat_id, status).await
+    }
+
+    /// Requests to chats of other bots are `None` as well.
+    pub async fn get_join_request(&self, id: i32) -> anyhow::Result<Option<JoinRequest>> {
+        Ok(self
+            .storage
+            .get_join_request(id)
+            .await?
+            .filter(|request| self.chats_status.contains_key(&request.chat_id)))
+    }
+
+    /// Approves or declines a pending request in telegram, `None` when it
+    /// was decided already.
+    pub async fn decide_join_request(
+        &self,
+        request: &JoinRequest,
+        approve: bool,
+        decided_by: &str,
+    ) -> anyhow::Result<Option<JoinRequest>> {
+        if request.status != JoinRequestStatus::Pending {
+            return Ok(None);
+        }
+        let chat_id = ChatId(request.chat_id);
+        let user_id = UserId(request.user_id as u64);
+        let status = if approve {
+            info!("approving user:{} to join chat:{chat_id}", request.user_id);
+            self.bot.approve_chat_join_request(chat_id, user_id).await?;
+            JoinRequestStatus::Approved
+        } else {
+            info!("declining user:{} to join chat:{chat_id}", request.user_id);
+            self.bot.decline_chat_join_request(chat_id, user_id).await?;
+            JoinRequestStatus::Declined
+        };
+
+        self.storage
+            .decide_join_request(request.id, status, decided_by)
+            .await
+    }
+
+    /// Sends the request with approve and decline buttons to every bot
+    /// admin, admins that never started the bot can't be reached.
+    async fn ask_admins(&self, request: &JoinRequest, chat_title: &str) {
+        let mut text = format!(
+            "{} wants to join {chat_title}.",
+            request
+                .username
+                .as_ref()
+                .map_or_else(|| request.name.clone(), |username| format!("@{username}"))
+        );
+        if let Some(bio) = &request.bio {
+            text.push_str(&format!("\n\n{bio}"));
+        }
+        let buttons = InlineKeyboardMarkup::new([[
+            InlineKeyboardButton::callback(
+                "Approve",
+                format!("{JOIN_CALLBACK_PREFIX}:approve:{}", request.id),
+            ),
+            InlineKeyboardButton::callback(
+                "Decline",
+                format!("{JOIN_CALLBACK_PREFIX}:decline:{}", request.id),
+            ),
+        ]]);
+
+        for &admin_id in &self.settings().admin_ids {
+            if let Err(err) = self
+                .bot
+                .send_message(ChatId(admin_id), &text)
+                .reply_markup(buttons.clone())
+                .await
+            {
+                error!(
+                    "error asking admin:{admin_id} about join request {}: {err}",
+                    request.id
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,68 @@
+use anyhow::bail;
+use tracing::info;
+
+use crate::state::AppState;
+
+pub const MAX_LABELS: usize = 20;
+const MAX_LABEL_LENGTH: usize = 32;
+pub const MAX_NOTES_LENGTH: usize = 4096;
+
+/// Trims the labels and drops duplicates, sorted so the same set is always
+/// stored the same way. Commas are rejected as they separate the labels of
+/// a query.
+pub fn normalize_labels(labels: Vec<String>) -> anyhow::Result<Vec<String>> {
+    let mut normalized = Vec::with_capacity(labels.len());
+    for label in labels {
+        let label = label.trim();
+        if label.is_empty() {
+            bail!("labels can't be empty");
+        }
+        if label.chars().count() > MAX_LABEL_LENGTH {
+            bail!("label {label:?} is longer than {MAX_LABEL_LENGTH} characters");
+        }
+        if label.contains(',') {
+            bail!("label {label:?} can't contain a comma");
+        }
+        normalized.push(label.to_string());
+    }
+    normalized.sort();
+    normalized.dedup();
+    if normalized.len() > MAX_LABELS {
+        bail!("a chat can have at most {MAX_LABELS} labels");
+    }
+
+    Ok(normalized)
+}
+
+impl AppState {
+    /// Replaces the labels of the chat. Returns `false` when the chat is
+    /// unknown.
+    pub async fn set_chat_labels(&self, chat_id: i64, labels: Vec<String>) -> anyhow::Result<bool> {
+        let labels = normalize_labels(labels)?;
+        info!("setting labels {labels:?} for chat:{chat_id}");
+
+        self.storage
+            .set_chat_labels(&self.bot_id, chat_id, &labels)
+            .await
+    }
+
+    /// `None` clears the notes. Returns `false` when the chat is unknown.
+    pub async fn set_chat_notes(&self, chat_id: i64, notes: Option<&str>) -> anyhow::Result<bool> {
+        if notes.is_some_and(|notes| notes.chars().count() > MAX_NOTES_LENGTH) {
+            bail!("notes can't be longer than {MAX_NOTES_LENGTH} characters");
+        }
+
+        self.storage
+            .set_chat_notes(&self.bot_id, chat_id, notes)
+            .await
+    }
+
+    /// Chats carrying any of the labels, archived chats left out.
+    pub async fn get_labelled_chats(&self, labels: &[String]) -> anyhow::Result<Vec<i64>> {
+        if labels.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        self.storage.get_labelled_chats(&self.bot_id, labels).await
+    }
+}
@@ -0,0 +1,96 @@
+use std::collections::{BTreeMap, HashMap};
+
+use anyhow::bail;
+use tracing::info;
+
+use crate::{attachment::Attachments, state::AppState};
+
+/// Texts of a broadcast keyed by language code, like `{"en": "...", "ru":
+/// "..."}`.
+pub type Translations = BTreeMap<String, String>;
+
+/// The text of a message and its translations. Chats without a language, or
+/// one that wasn't translated to, receive `message`. The attachments go to
+/// every chat.
+#[derive(Default)]
+pub struct LocalizedMessage {
+    pub message: String,
+    pub translations: Translations,
+    pub attachments: Attachments,
+}
+
+impl From<String> for LocalizedMessage {
+    fn from(message: String) -> Self {
+        Self {
+            message,
+            ..Default::default()
+        }
+    }
+}
+
+/// Accepts lowercase IETF language tags the way telegram reports them, like
+/// `en` or `pt-br`.
+pub fn validate_language(language: &str) -> anyhow::Result<()> {
+    let mut subtags = language.split('-');
+    let primary = subtags.next().unwrap_or_default();
+    let valid = (2..=3).contains(&primary.len())
+        && primary.chars().all(|c| c.is_ascii_lowercase())
+        && subtags.all(|subtag| {
+            (2..=8).contains(&subtag.len())
+                && subtag
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit())
+        });
+    if !valid {
+        bail!("{language:?} is not a lowercase language code like en or pt-br");
+    }
+
+    Ok(())
+}
+
+/// The translation for a chat's language, falling back from a regional
+/// language like `pt-br` to `pt`. `None` sends the untranslated message.
+pub fn pick_translation<'a, T>(
+    translations: &'a HashMap<String, T>,
+    language: Option<&str>,
+) -> Option<&'a T> {
+    let language = language?;
+    translations.get(language).or_else(|| {
+        let (primary, _) = language.split_once('-')?;
+        translations.get(primary)
+    })
+}
+
+impl AppState {
+    /// `None` clears the language, the chat receives untranslated messages.
+    /// Returns `false` when the chat is unknown.
+    pub async fn set_chat_language(
+        &self,
+        chat_id: i64,
+        language: Option<&str>,
+    ) -> anyhow::Result<bool> {
+        if let Some(language) = language {
+            validate_language(language)?;
+        }
+        info!("setting language {language:?} for chat:{chat_id}");
+
+        self.storage
+            .set_chat_language(&self.bot_id, chat_id, language)
+            .await
+    }
+
+    pub async fn get_chat_languages(&self) -> anyhow::Result<HashMap<i64, String>> {
+        let languages = self
+            .get_chats()
+            .await?
+            .into_iter()
+            .filter_map(|chat| Some((chat.id, chat.language?)))
+            .collect();
+
+        Ok(languages)
+    }
+
+    pub async fn get_message_translations(&self, message_id: i32) -> anyhow::Result<Translations> {
+        self.storage.get_message_translations(message_id).await
+    }
+}
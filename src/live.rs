@@ -0,0 +1,327 @@
+//! Status changes pushed to WebSocket clients. A client subscribes to some
+//! chats or all of them, receives a snapshot of their status and from then on
+//! only what changes.
+
+use std::{collections::HashSet, sync::Arc, time::Duration};
+
+use axum::extract::ws::{Message, WebSocket};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    sync::broadcast::{self, error::RecvError},
+    time::{timeout, Instant, MissedTickBehavior},
+};
+use tracing::{error, info};
+use utoipa::ToSchema;
+
+use crate::{
+    access::Access,
+    broadcast::BroadcastProgress,
+    state::{AppState, ChatCleaningStatus, ChatStatusSummary, CleanupProgress, CleanupState},
+};
+
+/// Changes kept for each client. One that falls further behind gets a new
+/// snapshot instead of the changes it missed.
+const CHANNEL_CAPACITY: usize = 1024;
+const PING_INTERVAL: Duration = Duration::from_secs(30);
+/// A client that doesn't take a message for this long is disconnected.
+const SEND_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A change of a chat, sent as a JSON text message tagged by `type`.
+#[derive(Serialize, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LiveEvent {
+    /// The cleanup status of the chat changed, or its progress did.
+    Status {
+        chat_id: i64,
+        status: CleanupState,
+        progress: Option<CleanupProgress>,
+        error: Option<String>,
+    },
+    /// The number of tracked members of the chat changed.
+    Members { chat_id: i64, member_count: i64 },
+    /// A broadcast was sent to the chat, with the counters of the whole
+    /// broadcast.
+    Progress {
+        chat_id: i64,
+        progress: BroadcastProgress,
+    },
+}
+
+impl LiveEvent {
+    fn chat_id(&self) -> i64 {
+        match self {
+            LiveEvent::Status { chat_id, .. }
+            | LiveEvent::Members { chat_id, .. }
+            | LiveEvent::Progress { chat_id, .. } => *chat_id,
+        }
+    }
+}
+
+/// What the server sends besides the changes.
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Reply {
+    /// The chats just subscribed to, or all subscribed chats after the
+    /// client fell behind.
+    Snapshot {
+        chats: Vec<ChatStatusSummary>,
+    },
+    Error {
+        message: String,
+    },
+}
+
+/// Sent by the client as a JSON text message tagged by `action`, like
+/// `{"action": "subscribe", "chats": [-100]}` or `"chats": "all"`.
+#[derive(Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+enum Request {
+    Subscribe { chats: Chats },
+    Unsubscribe { chats: Chats },
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Chats {
+    All(AllChats),
+    Some(Vec<i64>),
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum AllChats {
+    All,
+}
+
+/// The changes of one bot, shared by its clients.
+#[derive(Clone)]
+pub struct LiveUpdates {
+    sender: broadcast::Sender<Arc<LiveEvent>>,
+    /// Member counts last sent, so only changes are.
+    member_counts: Arc<DashMap<i64, i64>>,
+}
+
+impl Default for LiveUpdates {
+    fn default() -> Self {
+        Self {
+            sender: broadcast::channel(CHANNEL_CAPACITY).0,
+            member_counts: Arc::new(DashMap::new()),
+        }
+    }
+}
+
+impl LiveUpdates {
+    pub fn publish(&self, event: LiveEvent) {
+        // nobody listening is fine
+        let _ = self.sender.send(Arc::new(event));
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<LiveEvent>> {
+        self.sender.subscribe()
+    }
+
+    /// Whether a client is connected, changes that cost a query to find are
+    /// only looked up then.
+    pub fn is_watched(&self) -> bool {
+        self.sender.receiver_count() > 0
+    }
+}
+
+#[derive(Default)]
+struct Subscription {
+    all: bool,
+    chats: HashSet<i64>,
+}
+
+impl Subscription {
+    fn contains(&self, chat_id: i64) -> bool {
+        self.all || self.chats.contains(&chat_id)
+    }
+}
+
+impl AppState {
+    pub fn publish_status(&self, chat_id: i64, status: &ChatCleaningStatus) {
+        let (progress, error) = match status {
+            ChatCleaningStatus::InProgress(progress) => (Some(progress.clone()), None),
+            ChatCleaningStatus::Error(err) => (None, Some(err.clone())),
+            _ => (None, None),
+        };
+        self.live.publish(LiveEvent::Status {
+            chat_id,
+            status: status.state(),
+            progress,
+            error,
+        });
+    }
+
+    /// Counts the members of the chat when a client is connected and
+    /// publishes the count if it changed. Failing to count doesn't fail the
+    /// update that changed the members.
+    pub async fn publish_member_count(&self, chat_id: i64) {
+        if !self.live.is_watched() {
+            return;
+        }
+        let member_count = match self.storage.count_members(chat_id).await {
+            Ok(count) => count,
+            Err(err) => {
+                error!("failed to count the members of chat:{chat_id}: {err}");
+                return;
+            }
+        };
+        if self.live.member_counts.insert(chat_id, member_count) != Some(member_count) {
+            self.live.publish(LiveEvent::Members {
+                chat_id,
+                member_count,
+            });
+        }
+    }
+
+    pub async fn publish_progress(&self, queue_id: i32, chat_id: i64) -> anyhow::Result<()> {
+        if !self.live.is_watched() {
+            return Ok(());
+        }
+        if let Some(progress) = self.get_broadcast_progress(queue_id).await? {
+            self.live.publish(LiveEvent::Progress { chat_id, progress });
+        }
+
+        Ok(())
+    }
+
+    /// Serves one client until it disconnects, stops answering pings or
+    /// doesn't take the messages sent to it.
+    pub async fn serve_live(self, access: Access, mut socket: WebSocket) {
+        info!("{} connected for live status", access.actor());
+        let mut events = self.live.subscribe();
+        let mut subscription = Subscription::default();
+        let mut ping = tokio::time::interval(PING_INTERVAL);
+        ping.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let mut last_seen = Instant::now();
+
+        loop {
+            let reply = tokio::select! {
+                message = socket.recv() => {
+                    last_seen = Instant::now();
+                    match message {
+                        Some(Ok(Message::Text(text))) => {
+                            match self.live_request(&access, &mut subscription, &text).await {
+                                Some(reply) => reply,
+                                None => continue,
+                            }
+                        }
+                        Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                        // pongs only count as a sign of life
+                        Some(Ok(_)) => continue,
+                    }
+                }
+                event = events.recv() => match event {
+                    Ok(event) => {
+                        let chat_id = event.chat_id();
+                        if !subscription.contains(chat_id) || !access.allows_chat(chat_id) {
+                            continue;
+                        }
+                        match serde_json::to_string(&*event) {
+                            Ok(text) => Message::Text(text),
+                            Err(err) => {
+                                error!("failed to serialize a live event: {err}");
+                                continue;
+                            }
+                        }
+                    }
+                    Err(RecvError::Lagged(missed)) => {
+                        info!("a live status client missed {missed} changes, sending a snapshot");
+                        self.snapshot(&access, &subscription, None).await
+                    }
+                    Err(RecvError::Closed) => break,
+                },
+                _ = ping.tick() => {
+                    if last_seen.elapsed() > PING_INTERVAL * 2 {
+                        info!("a live status client stopped answering pings");
+                        break;
+                    }
+                    Message::Ping(Vec::new())
+                }
+            };
+
+            if !matches!(timeout(SEND_TIMEOUT, socket.send(reply)).await, Ok(Ok(()))) {
+                info!("a live status client doesn't take its messages, disconnecting");
+                break;
+            }
+        }
+    }
+
+    async fn live_request(
+        &self,
+        access: &Access,
+        subscription: &mut Subscription,
+        text: &str,
+    ) -> Option<Message> {
+        let request = match serde_json::from_str(text) {
+            Ok(request) => request,
+            Err(err) => return Some(error_reply(format!("invalid request: {err}"))),
+        };
+        let reply = match request {
+            Request::Subscribe {
+                chats: Chats::All(_),
+            } => {
+                subscription.all = true;
+                self.snapshot(access, subscription, None).await
+            }
+            Request::Subscribe {
+                chats: Chats::Some(chats),
+            } => {
+                if let Some(chat_id) = chats.iter().find(|&&id| !access.allows_chat(id)) {
+                    return Some(error_reply(format!(
+                        "the API key is not allowed to access chat {chat_id}"
+                    )));
+                }
+                subscription.chats.extend(&chats);
+                self.snapshot(access, subscription, Some(&chats)).await
+            }
+            Request::Unsubscribe {
+                chats: Chats::All(_),
+            } => {
+                *subscription = Subscription::default();
+                return None;
+            }
+            Request::Unsubscribe {
+                chats: Chats::Some(chats),
+            } => {
+                for chat_id in chats {
+                    subscription.chats.remove(&chat_id);
+                }
+                return None;
+            }
+        };
+
+        Some(reply)
+    }
+
+    /// The status of the subscribed chats, only of `chats` when given.
+    async fn snapshot(
+        &self,
+        access: &Access,
+        subscription: &Subscription,
+        chats: Option<&[i64]>,
+    ) -> Message {
+        let mut overview = match self.get_status_overview(None).await {
+            Ok(overview) => overview,
+            Err(err) => return error_reply(format!("failed to read the status: {err}")),
+        };
+        overview.retain(|summary| {
+            access.allows_chat(summary.chat_id)
+                && subscription.contains(summary.chat_id)
+                && chats.is_none_or(|chats| chats.contains(&summary.chat_id))
+        });
+
+        reply(&Reply::Snapshot { chats: overview })
+    }
+}
+
+fn error_reply(message: String) -> Message {
+    reply(&Reply::Error { message })
+}
+
+fn reply(reply: &Reply) -> Message {
+    Message::Text(serde_json::to_string(reply).expect("replies serialize"))
+}
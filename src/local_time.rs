@@ -0,0 +1,99 @@
+use std::collections::HashMap;
+
+use anyhow::Context;
+use chrono::{DateTime, Duration, NaiveDateTime, TimeZone, Utc};
+use tracing::info;
+
+use crate::{quiet_hours::validate_utc_offset, state::AppState, topic::ChatTarget};
+
+const FORMAT: &str = "%Y-%m-%dT%H:%M:%S";
+
+/// Parses a wall clock time like `2024-06-01T09:00`, seconds are optional.
+pub fn parse_local_datetime(datetime: &str) -> anyhow::Result<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(datetime, FORMAT)
+        .or_else(|_| NaiveDateTime::parse_from_str(datetime, "%Y-%m-%dT%H:%M"))
+        .with_context(|| format!("expected YYYY-MM-DDTHH:MM: {datetime}"))
+}
+
+/// When the wall clock of a chat `offset_minutes` off UTC shows `local`.
+pub fn local_due(local: NaiveDateTime, offset_minutes: i32) -> DateTime<Utc> {
+    Utc.from_utc_datetime(&(local - Duration::minutes(offset_minutes.into())))
+}
+
+/// (De)serializes `MessageOptions::datetime_local` in the format of
+/// [`parse_local_datetime`].
+pub mod datetime_local {
+    use chrono::NaiveDateTime;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        datetime: &Option<NaiveDateTime>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match datetime {
+            Some(datetime) => {
+                serializer.serialize_some(&datetime.format(super::FORMAT).to_string())
+            }
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<NaiveDateTime>, D::Error> {
+        Option::<String>::deserialize(deserializer)?
+            .map(|datetime| super::parse_local_datetime(&datetime).map_err(D::Error::custom))
+            .transpose()
+    }
+}
+
+impl AppState {
+    /// `None` puts the chat back on UTC. Returns `false` when the chat is
+    /// unknown.
+    pub async fn set_chat_utc_offset(
+        &self,
+        chat_id: i64,
+        minutes: Option<i32>,
+    ) -> anyhow::Result<bool> {
+        if let Some(minutes) = minutes {
+            validate_utc_offset(minutes)?;
+        }
+        info!("setting utc offset {minutes:?} for chat:{chat_id}");
+
+        self.storage
+            .set_chat_utc_offset(&self.bot_id, chat_id, minutes)
+            .await
+    }
+
+    /// Offsets from UTC of the chats that have one.
+    pub async fn get_chat_utc_offsets(&self) -> anyhow::Result<HashMap<i64, i32>> {
+        let offsets = self
+            .get_chats()
+            .await?
+            .into_iter()
+            .filter_map(|chat| Some((chat.id, chat.utc_offset_minutes?)))
+            .collect();
+
+        Ok(offsets)
+    }
+
+    /// The first instant one of the chats reaches `local`, the chats furthest
+    /// east come first. `None` looks at every chat.
+    pub async fn earliest_local_due(
+        &self,
+        local: NaiveDateTime,
+        chats: Option<&[ChatTarget]>,
+    ) -> anyhow::Result<DateTime<Utc>> {
+        let offsets = self.get_chat_utc_offsets().await?;
+        let offset = match chats {
+            Some(chats) => chats
+                .iter()
+                .map(|target| offsets.get(&target.chat_id()).copied().unwrap_or(0))
+                .max(),
+            // chats without an offset are on UTC
+            None => offsets.into_values().chain([0]).max(),
+        };
+
+        Ok(local_due(local, offset.unwrap_or(0)))
+    }
+}
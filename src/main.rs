@@ -1,55 +1,245 @@
-use std::env;
-use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::anyhow;
-use dashmap::DashMap;
+use clap::Parser;
 use dotenv::dotenv;
-use sqlx::postgres::PgPoolOptions;
-use teloxide::adaptors::throttle::Limits;
-use teloxide::{requests::RequesterExt, Bot};
+use futures::future::try_join_all;
 use tracing::info;
-use tracing_subscriber::EnvFilter;
 
+use crate::cli::Cli;
+use crate::config::Config;
+use crate::preflight::{Failure, Preflight};
 use crate::state::AppState;
+use crate::status_store::StatusStoreKind;
 
+mod access;
+mod activity;
+mod admin;
+mod alert;
 mod api;
+mod attachment;
+mod audit;
+mod backpressure;
 mod bot;
+mod branding;
+mod broadcast;
+mod broadcast_search;
+mod calendar;
+mod captcha;
+mod channel;
+mod chat_cache;
+mod chat_group;
+mod chat_list;
+mod cleanup_pause;
+mod cli;
+mod config;
+mod convert;
+mod dead_letter;
+mod draft;
+mod duplicate;
+mod error;
+mod estimate;
+mod exclusion;
+mod format;
+mod health;
+mod idempotency;
+mod import;
+mod invite_link;
+mod join_request;
+mod label;
+mod language;
+mod live;
+mod local_time;
+mod maintenance;
+mod media;
+mod member_cache;
+mod member_import;
+mod moderation;
+mod openapi;
+mod outbound;
+mod poll;
+mod preflight;
+mod purge;
+mod queue;
+mod queue_history;
+mod queue_transfer;
+mod quiet_hours;
+mod ratelimit;
+mod reaction;
+mod reconcile;
+mod rename;
+mod reply;
+mod report;
+mod resync;
+mod schedule;
+mod self_destruct;
+mod session;
+mod settings;
+mod sink;
+mod split;
+mod staged_cleanup;
 mod state;
+mod stats;
+mod status_store;
+mod status_v1;
+mod storage;
+mod subscriber;
+mod telegram_error;
+mod telemetry;
+#[cfg(test)]
+mod tests;
+mod topic;
+mod usage;
+mod validation;
+mod variant;
+mod webhook;
+mod welcome;
+
+/// A loop counts as stale after missing a few iterations in a row.
+const STALE_GRACE: Duration = Duration::from_secs(60);
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
     dotenv().ok();
-    tracing_subscriber::fmt()
-        .with_env_filter(
-            EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
-        )
-        .init();
-
-    info!("creating postrgres pool...");
-    let pool = PgPoolOptions::new()
-        .max_connections(5)
-        .connect(&env::var("DATABASE_URL")?)
-        .await?;
-    info!("running migrations...");
-    sqlx::migrate!().run(&pool).await?;
-
-    let bot = Bot::new(env::var("BOT_TOKEN")?).throttle(Limits::default());
-
-    let state = AppState {
-        pool,
-        bot,
-        chats_status: Arc::new(DashMap::new()),
-    };
-
-    state.fill_status_list().await?;
-
-    match tokio::try_join!(
-        tokio::spawn(bot::run(state.clone())),
-        tokio::spawn(api::run(state.clone())),
-        tokio::spawn(state::AppState::message_queue(state.clone())),
-        tokio::spawn(state::AppState::cleanup_deprecated_chats(state.clone()))
-    )? {
-        (Ok(()), Ok(()), Ok(()), Ok(())) => Ok(()),
-        error => Err(anyhow!("{:?}", error)),
+
+    let config = Config::load().unwrap_or_else(|err| preflight::abort(Failure::Config, err));
+    if let Some(command) = cli.command {
+        return cli::run(config, cli.bot, command).await;
+    }
+    // the logging setup panics on a log file it can't create
+    let mut preflight = Preflight::default();
+    preflight.files(&config);
+    preflight.exit_on_failure();
+
+    // flushes the log file on exit
+    let _log_guard = telemetry::init(&config)?;
+    info!(
+        "loaded config, api on {}, admins: {:?}, otlp: {:?}",
+        config.api_bind, config.admin_ids, config.otlp_endpoint
+    );
+
+    info!("connecting to the database and running migrations...");
+    let database = preflight.database(&config).await;
+
+    let bot_configs = config.all_bots();
+    let bots = bot_configs
+        .iter()
+        .map(|bot| Ok((bot.id.as_str(), bot::build(&config, &bot.token)?)))
+        .collect::<anyhow::Result<Vec<_>>>()
+        .unwrap_or_else(|err| preflight::abort(Failure::Config, err));
+    preflight.bots(&bot_configs, &bots).await;
+
+    preflight.exit_on_failure();
+    info!("preflight passed:\n{}", preflight.summary());
+    let (storage, pool) = database.expect("a failed database check exits");
+    let mut bots = bots.into_iter();
+
+    // the first bot also serves the API paths without a `/bots/:bot_id` prefix
+    let (primary_id, primary_bot) = bots.next().expect("config requires a bot");
+    let state = AppState::new(config, storage, pool, primary_id, primary_bot);
+    let states: Vec<AppState> = std::iter::once(state.clone())
+        .chain(bots.map(|(id, bot)| state.for_bot(id, bot)))
+        .collect();
+
+    // the API and the loops start with the settings changed through the API
+    for state in &states {
+        state.load_settings().await?;
+    }
+
+    let mut tasks = vec![
+        tokio::spawn(api::run(states.clone())),
+        tokio::spawn(AppState::database_health_check(state.clone())),
+    ];
+    // idempotency keys live in postgres and are shared by all bots
+    if state.pool.is_some() {
+        tasks.push(tokio::spawn(state.clone().supervise(
+            "idempotency_cleanup",
+            |_| idempotency::IDEMPOTENCY_CLEANUP_INTERVAL * 4 + STALE_GRACE,
+            AppState::idempotency_cleanup,
+        )));
+    }
+    // stored images are shared by all bots
+    tasks.push(tokio::spawn(state.clone().supervise(
+        "media_cleanup",
+        |_| media::MEDIA_CLEANUP_INTERVAL * 4 + STALE_GRACE,
+        AppState::media_cleanup,
+    )));
+    for state in states {
+        state.fill_status_list().await?;
+        state.reconcile().await?;
+        // preflight made sure telegram answers, updates find it known
+        state.bot_user_id().await?;
+
+        tasks.push(tokio::spawn(bot::run(state.clone())));
+        tasks.push(tokio::spawn(AppState::apply_throttle_changes(
+            state.clone(),
+        )));
+        tasks.push(tokio::spawn(state.clone().supervise(
+            "message_queue",
+            |intervals| intervals.message_queue() * 4 + STALE_GRACE,
+            AppState::message_queue,
+        )));
+        tasks.push(tokio::spawn(state.clone().supervise(
+            "cleanup_deprecated_chats",
+            |intervals| intervals.cleanup_deprecated_chats() * 4 + STALE_GRACE,
+            AppState::cleanup_deprecated_chats,
+        )));
+        tasks.push(tokio::spawn(state.clone().supervise(
+            "sync_admins",
+            |intervals| intervals.sync_admins() * 4 + STALE_GRACE,
+            AppState::sync_admins,
+        )));
+        tasks.push(tokio::spawn(state.clone().supervise(
+            "scheduled_cleanups",
+            |intervals| intervals.scheduled_cleanups() * 4 + STALE_GRACE,
+            AppState::scheduled_cleanups,
+        )));
+        tasks.push(tokio::spawn(state.clone().supervise(
+            "captcha_timeouts",
+            |intervals| intervals.captcha_timeouts() * 4 + STALE_GRACE,
+            AppState::captcha_timeouts,
+        )));
+        tasks.push(tokio::spawn(state.clone().supervise(
+            "sync_chat_metadata",
+            |intervals| intervals.chat_metadata() * 4 + STALE_GRACE,
+            AppState::sync_chat_metadata,
+        )));
+        tasks.push(tokio::spawn(state.clone().supervise(
+            "self_destruct",
+            |intervals| intervals.self_destruct() * 4 + STALE_GRACE,
+            AppState::self_destruct,
+        )));
+        if state.config.status_store != StatusStoreKind::Memory {
+            tasks.push(tokio::spawn(state.clone().supervise(
+                "sync_statuses",
+                |intervals| intervals.status_sync() * 4 + STALE_GRACE,
+                AppState::sync_statuses_loop,
+            )));
+        }
+        tasks.push(tokio::spawn(state.clone().supervise(
+            "sync_settings",
+            |intervals| intervals.settings_sync() * 4 + STALE_GRACE,
+            AppState::sync_settings,
+        )));
+        tasks.push(tokio::spawn(state.clone().supervise(
+            "prune_audit_log",
+            |_| audit::AUDIT_PRUNE_INTERVAL * 4 + STALE_GRACE,
+            AppState::prune_audit_log,
+        )));
+        tasks.push(tokio::spawn(state.clone().supervise(
+            "weekly_report",
+            |_| report::CHECK_INTERVAL * 4 + STALE_GRACE,
+            AppState::weekly_reports,
+        )));
+    }
+
+    let result = try_join_all(tasks).await;
+    telemetry::shutdown();
+
+    let errors: Vec<_> = result?.into_iter().filter_map(Result::err).collect();
+    match errors.is_empty() {
+        true => Ok(()),
+        false => Err(anyhow!("{:?}", errors)),
     }
 }
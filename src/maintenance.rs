@@ -0,0 +1,49 @@
+use axum::{
+    http::{Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+use utoipa::ToSchema;
+
+use crate::{error::ApiError, state::AppState};
+
+/// While a bot is in maintenance its queue is not sent, no members are
+/// cleaned up and the API only answers reads. Meant for telegram outages
+/// and database migrations.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, ToSchema)]
+pub struct Maintenance {
+    pub enabled: bool,
+}
+
+impl AppState {
+    pub async fn in_maintenance(&self) -> anyhow::Result<bool> {
+        self.storage.get_maintenance(&self.bot_id).await
+    }
+
+    pub async fn set_maintenance(&self, enabled: bool) -> anyhow::Result<()> {
+        info!("switching maintenance of bot {} to {enabled}", self.bot_id);
+
+        self.storage.set_maintenance(&self.bot_id, enabled).await
+    }
+}
+
+/// Middleware refusing requests with `503 Service Unavailable` while the bot
+/// is in maintenance.
+pub async fn maintenance<B>(req: Request<B>, next: Next<B>) -> Response {
+    let Some(state) = req.extensions().get::<AppState>() else {
+        return next.run(req).await;
+    };
+
+    match state.in_maintenance().await {
+        Ok(false) => next.run(req).await,
+        Ok(true) => ApiError::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "maintenance",
+            "the bot is in maintenance, only reads are served",
+        )
+        .into_response(),
+        Err(err) => ApiError::from(err).into_response(),
+    }
+}
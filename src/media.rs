@@ -0,0 +1,264 @@
+use std::{io::Cursor, time::Duration};
+
+use anyhow::{anyhow, bail, Context};
+use base64::Engine;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use data_url::DataUrl;
+use image::{imageops::FilterType, io::Reader, DynamicImage, ImageFormat, ImageOutputFormat};
+use sha2::{Digest, Sha256};
+use teloxide::types::{InputFile, InputMedia, InputMediaPhoto, Message};
+use tracing::{error, info};
+
+use crate::{
+    state::AppState,
+    validation::{MAX_PHOTO_BYTES, MAX_PHOTO_DIMENSIONS},
+};
+
+const JPEG_QUALITY: u8 = 90;
+/// Prefix of `images` entries that refer to a row of `media_blob`.
+const MEDIA_BLOB_PREFIX: &str = "media:";
+pub const MEDIA_CLEANUP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+/// Age below which unused images are kept, for messages being queued with
+/// them right now.
+const UNUSED_MEDIA_GRACE: Duration = Duration::from_secs(60 * 60);
+
+/// An image that is about to be broadcast, identified by the hash of its
+/// contents so uploads of the same file can be shared between sends.
+#[derive(Clone)]
+pub struct Media {
+    pub hash: String,
+    pub data: Bytes,
+}
+
+impl Media {
+    pub fn new(data: impl Into<Bytes>) -> Self {
+        let data = data.into();
+        let hash = format!("{:x}", Sha256::digest(&data));
+        Self { hash, data }
+    }
+
+    /// The `images` entry a queued message refers to the stored image by.
+    pub fn reference(&self) -> String {
+        format!("{MEDIA_BLOB_PREFIX}{}", self.hash)
+    }
+}
+
+/// Entries of a message's `images` are base64, a `data:` URL or an https URL
+/// that is only downloaded when the message is sent.
+pub fn is_remote_image(image: &str) -> bool {
+    image.starts_with("https://")
+}
+
+/// Hash of the `media_blob` an entry of a queued message's `images` refers
+/// to. Messages queued before the blobs kept their images as base64.
+pub fn media_blob_hash(image: &str) -> Option<&str> {
+    image.strip_prefix(MEDIA_BLOB_PREFIX)
+}
+
+/// Decodes a base64 or `data:` URL entry of a message's `images`.
+pub fn decode_inline_image(image: &str) -> anyhow::Result<Vec<u8>> {
+    if image.starts_with("data:") {
+        let url = DataUrl::process(image).map_err(|err| anyhow!("invalid data URL: {err:?}"))?;
+        let (data, _) = url
+            .decode_to_vec()
+            .map_err(|_| anyhow!("invalid base64 in data URL"))?;
+        return Ok(data);
+    }
+
+    Ok(base64::engine::general_purpose::STANDARD.decode(image)?)
+}
+
+/// Makes an image acceptable as a telegram photo. Formats other than JPEG,
+/// PNG and WebP are converted to JPEG, images beyond the size limits are
+/// scaled down and re-encoded as JPEG, everything else is kept as is.
+pub fn prepare_image(data: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+    let reader = Reader::new(Cursor::new(&data)).with_guessed_format()?;
+    let format = reader.format().context("not an image")?;
+    let (width, height) = reader.into_dimensions()?;
+
+    let supported = matches!(
+        format,
+        ImageFormat::Jpeg | ImageFormat::Png | ImageFormat::WebP
+    );
+    if supported && data.len() <= MAX_PHOTO_BYTES && width + height <= MAX_PHOTO_DIMENSIONS {
+        return Ok(data);
+    }
+
+    let mut image = image::load_from_memory_with_format(&data, format)?;
+    if width + height > MAX_PHOTO_DIMENSIONS {
+        let scale = MAX_PHOTO_DIMENSIONS as f64 / (width + height) as f64;
+        image = image.resize(
+            ((width as f64 * scale) as u32).max(1),
+            ((height as f64 * scale) as u32).max(1),
+            FilterType::Triangle,
+        );
+    }
+
+    loop {
+        let encoded = encode_jpeg(&image)?;
+        if encoded.len() <= MAX_PHOTO_BYTES {
+            return Ok(encoded);
+        }
+        image = image.resize(
+            (image.width() * 3 / 4).max(1),
+            (image.height() * 3 / 4).max(1),
+            FilterType::Triangle,
+        );
+    }
+}
+
+fn encode_jpeg(image: &DynamicImage) -> anyhow::Result<Vec<u8>> {
+    let mut encoded = Cursor::new(Vec::new());
+    image
+        .to_rgb8()
+        .write_to(&mut encoded, ImageOutputFormat::Jpeg(JPEG_QUALITY))?;
+
+    Ok(encoded.into_inner())
+}
+
+impl AppState {
+    /// Runs [`prepare_image`] on every image off the async runtime.
+    pub async fn prepare_images(&self, images: Vec<Vec<u8>>) -> anyhow::Result<Vec<Vec<u8>>> {
+        tokio::task::spawn_blocking(move || images.into_iter().map(prepare_image).collect()).await?
+    }
+
+    /// Downloads an image given by URL, giving up on responses larger than
+    /// the configured limit.
+    pub async fn download_image(&self, url: &str) -> anyhow::Result<Vec<u8>> {
+        info!("downloading image {url}");
+        self.check_outbound_url(url)?;
+
+        let max_bytes = self.config.image_download_max_bytes;
+        let mut response = self.http.get(url).send().await?.error_for_status()?;
+        if response
+            .content_length()
+            .is_some_and(|length| length > max_bytes as u64)
+        {
+            bail!("image at {url} is larger than {max_bytes} bytes");
+        }
+
+        let mut data = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            if data.len() + chunk.len() > max_bytes {
+                bail!("image at {url} is larger than {max_bytes} bytes");
+            }
+            data.extend_from_slice(&chunk);
+        }
+
+        Ok(data)
+    }
+
+    pub async fn media_cleanup(state: Self) -> anyhow::Result<()> {
+        loop {
+            state.heartbeat("media_cleanup");
+            if let Err(err) = state.delete_unused_media(Utc::now()).await {
+                error!("failed to delete unused images: {err}");
+            }
+            tokio::time::sleep(MEDIA_CLEANUP_INTERVAL).await;
+        }
+    }
+
+    /// Deletes the stored images no message refers to any more, they are
+    /// shared between messages with the same ones.
+    pub async fn delete_unused_media(&self, now: DateTime<Utc>) -> anyhow::Result<()> {
+        let stored_before = now - chrono::Duration::from_std(UNUSED_MEDIA_GRACE)?;
+        let deleted = self
+            .storage
+            .delete_unused_media_blobs(stored_before)
+            .await?;
+        if deleted > 0 {
+            info!("deleted {deleted} images no message refers to");
+        }
+
+        Ok(())
+    }
+
+    /// Resolves the `images` of a queued message into the media to send,
+    /// downloading the ones given by URL and loading the stored ones.
+    pub async fn load_image_entries(&self, images: &[String]) -> anyhow::Result<Vec<Media>> {
+        let mut data = Vec::with_capacity(images.len());
+        for image in images {
+            data.push(match media_blob_hash(image) {
+                Some(hash) => self
+                    .storage
+                    .get_media_blob(hash)
+                    .await?
+                    .with_context(|| format!("stored image {hash} is missing"))?,
+                None if is_remote_image(image) => self.download_image(image).await?,
+                None => decode_inline_image(image)?,
+            });
+        }
+
+        // images queued inline were prepared already and are kept as is
+        Ok(self
+            .prepare_images(data)
+            .await?
+            .into_iter()
+            .map(Media::new)
+            .collect())
+    }
+
+    /// Always `None` without postgres, every send uploads the file again.
+    pub async fn get_media_file_id(&self, hash: &str) -> anyhow::Result<Option<String>> {
+        let Some(pool) = &self.pool else {
+            return Ok(None);
+        };
+
+        let file_id = sqlx::query_scalar!(
+            r#"
+SELECT file_id FROM media_file
+WHERE bot_id = $1 AND hash = $2
+            "#,
+            &*self.bot_id,
+            hash
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(file_id)
+    }
+
+    pub async fn save_media_file_id(&self, hash: &str, file_id: &str) -> anyhow::Result<()> {
+        let Some(pool) = &self.pool else {
+            return Ok(());
+        };
+
+        sqlx::query!(
+            r#"
+INSERT INTO media_file ( bot_id, hash, file_id )
+VALUES ( $1, $2, $3 )
+ON CONFLICT (bot_id, hash) DO NOTHING
+            "#,
+            &*self.bot_id,
+            hash,
+            file_id
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Builds the album entry for a media, pointing at an already uploaded
+    /// telegram file when there is one.
+    pub async fn input_media(&self, media: &Media) -> anyhow::Result<InputMedia> {
+        let file = match self.get_media_file_id(&media.hash).await? {
+            Some(file_id) => InputFile::file_id(file_id),
+            None => InputFile::memory(media.data.clone()),
+        };
+
+        Ok(InputMedia::Photo(InputMediaPhoto::new(file)))
+    }
+
+    /// Remembers the file ids telegram assigned to a sent album.
+    pub async fn remember_media(&self, media: &[Media], sent: &[Message]) -> anyhow::Result<()> {
+        for (media, message) in media.iter().zip(sent) {
+            if let Some(size) = message.photo().and_then(|sizes| sizes.last()) {
+                self.save_media_file_id(&media.hash, &size.file.id).await?;
+            }
+        }
+
+        Ok(())
+    }
+}
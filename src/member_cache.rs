@@ -0,0 +1,142 @@
+use std::collections::{HashSet, VecDeque};
+
+use chrono::Utc;
+use futures::future::join_all;
+use teloxide::{
+    requests::Requester,
+    types::{ChatId, ChatMember, ChatMemberKind, UserId},
+};
+use tokio::time::Instant;
+use tracing::{error, info};
+
+use crate::{
+    state::{AppState, User, Users},
+    telegram_error::TelegramErrorClass,
+};
+
+/// Members asked about at once at most. Batches shrink when telegram asks to
+/// wait and grow back by one member per batch that went through.
+const MAX_BATCH: usize = 20;
+
+/// The kind of a member as telegram last returned it or a chat member update
+/// changed it.
+#[derive(Clone)]
+pub struct CachedMemberKind {
+    kind: ChatMemberKind,
+    fetched_at: Instant,
+}
+
+impl AppState {
+    /// The kind of each member, skipping members who wrote within
+    /// `member_active_within`: they are still in the chat and didn't become
+    /// administrators, those are tracked apart. Kinds younger than
+    /// `member_cache_ttl` aren't asked for again. Members telegram couldn't
+    /// be asked about are left out.
+    pub async fn member_kinds(
+        &self,
+        chat_id: i64,
+        members: Users,
+    ) -> anyhow::Result<Vec<(User, ChatMemberKind)>> {
+        let inactive = self.inactive_member_ids(chat_id).await?;
+        let mut kinds = Vec::with_capacity(members.len());
+        let mut unknown = VecDeque::new();
+        for user in members {
+            if inactive.as_ref().is_some_and(|ids| !ids.contains(&user.id)) {
+                continue;
+            }
+            match self.cached_member_kind(chat_id, user.id) {
+                Some(kind) => kinds.push((user, kind)),
+                None => unknown.push_back(user),
+            }
+        }
+        if unknown.is_empty() {
+            return Ok(kinds);
+        }
+        info!(
+            "asking telegram about {} members of chat:{chat_id}, {} known",
+            unknown.len(),
+            kinds.len()
+        );
+
+        let mut batch_size = MAX_BATCH;
+        while !unknown.is_empty() {
+            let batch: Vec<_> = unknown.drain(..batch_size.min(unknown.len())).collect();
+            let results = join_all(batch.iter().map(|user| async move {
+                self.bot
+                    .get_chat_member(ChatId(chat_id), UserId(user.id as u64))
+                    .await
+            }))
+            .await;
+
+            let mut wait = None;
+            for (user, result) in batch.into_iter().zip(results) {
+                match result {
+                    Ok(member) => {
+                        self.cache_member_kind(chat_id, &member);
+                        kinds.push((user, member.kind));
+                    }
+                    Err(err) => match TelegramErrorClass::of(&err) {
+                        TelegramErrorClass::RateLimited(after) => {
+                            wait = wait.max(Some(after));
+                            unknown.push_back(user);
+                        }
+                        _ => error!("error while getting a user: {err}"),
+                    },
+                }
+            }
+
+            batch_size = match wait {
+                Some(wait) => {
+                    info!("member lookups in chat:{chat_id} are rate limited, waiting {wait:?}");
+                    tokio::time::sleep(wait).await;
+                    (batch_size / 2).max(1)
+                }
+                None => (batch_size + 1).min(MAX_BATCH),
+            };
+        }
+
+        Ok(kinds)
+    }
+
+    /// Remembers the member's kind, from a lookup or a chat member update,
+    /// so promotions, demotions and bans show right away.
+    pub fn cache_member_kind(&self, chat_id: i64, member: &ChatMember) {
+        self.member_cache.insert(
+            (chat_id, member.user.id.0 as i64),
+            CachedMemberKind {
+                kind: member.kind.clone(),
+                fetched_at: Instant::now(),
+            },
+        );
+    }
+
+    /// Drops the member's kind after the bot changed it, like by kicking.
+    pub fn forget_member_kind(&self, chat_id: i64, user_id: i64) {
+        self.member_cache.remove(&(chat_id, user_id));
+    }
+
+    fn cached_member_kind(&self, chat_id: i64, user_id: i64) -> Option<ChatMemberKind> {
+        let key = (chat_id, user_id);
+        let cached = self.member_cache.get(&key)?;
+        if cached.fetched_at.elapsed() < self.config.member_cache_ttl() {
+            return Some(cached.kind.clone());
+        }
+        drop(cached);
+        self.member_cache.remove(&key);
+
+        None
+    }
+
+    /// Members who didn't write within `member_active_within`, `None` when
+    /// every member counts as inactive.
+    async fn inactive_member_ids(&self, chat_id: i64) -> anyhow::Result<Option<HashSet<i64>>> {
+        let within = self.config.member_active_within();
+        if within.is_zero() {
+            return Ok(None);
+        }
+        let since = Utc::now() - chrono::Duration::from_std(within)?;
+        let inactive = self.storage.get_inactive_members(chat_id, since).await?;
+
+        Ok(Some(inactive.into_iter().map(|member| member.id).collect()))
+    }
+}
@@ -0,0 +1,144 @@
+//! Seeding the members of a chat from a list another system keeps, so a
+//! cleanup of a chat the bot just joined knows about members that didn't
+//! write since.
+
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+use teloxide::{
+    requests::Requester,
+    types::{ChatId, UserId},
+};
+use tracing::{info, instrument};
+use utoipa::ToSchema;
+
+use crate::{
+    admin::ChatAdmin,
+    state::AppState,
+    telegram_error::{member_failed, TelegramErrorClass},
+};
+
+/// Members taken in a single import at most.
+pub const MAX_SEEDED_MEMBERS: usize = 10_000;
+
+#[derive(Deserialize, ToSchema)]
+pub struct SeededMember {
+    pub id: i64,
+    pub username: Option<String>,
+    #[serde(default)]
+    pub name: String,
+}
+
+#[derive(Default, Serialize, ToSchema)]
+pub struct MemberImportReport {
+    pub imported: u64,
+    /// Already stored, the bot saw them itself and knows better.
+    pub existing: u64,
+    /// Administrators of the chat, they aren't members to clean up.
+    pub admins: u64,
+    /// Not in the chat according to telegram, only when verifying.
+    pub not_members: u64,
+    /// Telegram couldn't be asked about them, they aren't imported.
+    pub failed: u64,
+}
+
+impl AppState {
+    /// Stores the members the chat doesn't have yet. With `verify` each is
+    /// looked up in the chat first: those who aren't in it are left out,
+    /// those promoted go to the admins, and the rest are stored with the
+    /// names telegram has for them.
+    #[instrument(skip_all, fields(chat_id, members = members.len()))]
+    pub async fn import_members(
+        &self,
+        chat_id: i64,
+        members: Vec<SeededMember>,
+        verify: bool,
+    ) -> anyhow::Result<MemberImportReport> {
+        let admins: HashSet<i64> = self
+            .storage
+            .get_chat_admins(chat_id)
+            .await?
+            .into_iter()
+            .map(|admin| admin.user_id)
+            .collect();
+        let mut report = MemberImportReport::default();
+
+        for member in members {
+            if admins.contains(&member.id) {
+                report.admins += 1;
+                continue;
+            }
+            if self.storage.get_member(chat_id, member.id).await?.is_some() {
+                report.existing += 1;
+                continue;
+            }
+            if !verify {
+                self.storage
+                    .upsert_member(
+                        &self.bot_id,
+                        chat_id,
+                        member.id,
+                        member.username.as_deref().unwrap_or_default(),
+                        &member.name,
+                    )
+                    .await?;
+                report.imported += 1;
+                continue;
+            }
+
+            let result = loop {
+                let result = self
+                    .bot
+                    .get_chat_member(ChatId(chat_id), UserId(member.id as u64))
+                    .await;
+                match result.as_ref().map_err(TelegramErrorClass::of) {
+                    Err(TelegramErrorClass::RateLimited(wait)) => {
+                        info!("member import of chat:{chat_id} is rate limited, waiting {wait:?}");
+                        tokio::time::sleep(wait).await;
+                    }
+                    _ => break result,
+                }
+            };
+            if let Ok(found) = &result {
+                self.cache_member_kind(chat_id, found);
+            }
+            match result {
+                Ok(found) if !found.kind.is_present() => report.not_members += 1,
+                Ok(found) => match ChatAdmin::from_member(&found) {
+                    Some(admin) => {
+                        self.upsert_chat_admin(chat_id, &admin).await?;
+                        report.admins += 1;
+                    }
+                    None => {
+                        let username = found.user.username.clone().unwrap_or_default();
+                        self.storage
+                            .upsert_member(
+                                &self.bot_id,
+                                chat_id,
+                                member.id,
+                                &username,
+                                &found.user.full_name(),
+                            )
+                            .await?;
+                        report.imported += 1;
+                    }
+                },
+                Err(err) if TelegramErrorClass::of(&err) == TelegramErrorClass::UserGone => {
+                    report.not_members += 1
+                }
+                Err(err) => {
+                    member_failed(err, "look up", chat_id, member.id)?;
+                    report.failed += 1;
+                }
+            }
+        }
+
+        info!(
+            "imported {} members into chat:{chat_id}, {} already stored",
+            report.imported, report.existing
+        );
+        self.publish_member_count(chat_id).await;
+
+        Ok(report)
+    }
+}
@@ -0,0 +1,527 @@
+use std::{fmt, str::FromStr, sync::Arc};
+
+use anyhow::{anyhow, bail, Context};
+use chrono::{DateTime, Duration, Utc};
+use regex::{Regex, RegexBuilder, RegexSet, RegexSetBuilder};
+use serde::{Deserialize, Serialize};
+use teloxide::{
+    payloads::{RestrictChatMemberSetters, UnbanChatMemberSetters},
+    requests::Requester,
+    types::{Chat, ChatPermissions, Message, MessageEntityKind, UserId},
+    RequestError,
+};
+use tracing::{error, info};
+use utoipa::ToSchema;
+
+use crate::state::{AppState, User};
+
+const MAX_ENTRIES: usize = 500;
+// telegram restricts forever outside of 30 seconds to 366 days
+const MAX_RESTRICT_MINUTES: i32 = 366 * 24 * 60;
+
+/// What happens to messages with links from regular members.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkPolicy {
+    #[default]
+    Allow,
+    /// Messages with links are deleted and count as a warning.
+    Delete,
+}
+
+impl fmt::Display for LinkPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            LinkPolicy::Allow => "allow",
+            LinkPolicy::Delete => "delete",
+        })
+    }
+}
+
+impl FromStr for LinkPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "allow" => Ok(LinkPolicy::Allow),
+            "delete" => Ok(LinkPolicy::Delete),
+            other => Err(anyhow!("unknown link policy: {other}")),
+        }
+    }
+}
+
+fn default_restrict_minutes() -> i32 {
+    60
+}
+
+/// Spam filter of a chat. Matching messages of regular members are deleted
+/// and their sender warned, chat admins are never filtered.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, ToSchema)]
+pub struct ModerationSettings {
+    /// Words and phrases matched case-insensitively as whole words.
+    #[serde(default)]
+    pub banned_words: Vec<String>,
+    /// Regular expressions, matched case-insensitively anywhere in the text.
+    #[serde(default)]
+    pub banned_patterns: Vec<String>,
+    #[serde(default)]
+    pub links: LinkPolicy,
+    /// Members are muted once they have this many warnings.
+    pub restrict_after: Option<i32>,
+    /// How long members are muted for.
+    #[serde(default = "default_restrict_minutes")]
+    pub restrict_minutes: i32,
+    /// Members are kicked once they have this many warnings.
+    pub kick_after: Option<i32>,
+}
+
+impl ModerationSettings {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.banned_words.len() + self.banned_patterns.len() > MAX_ENTRIES {
+            bail!("at most {MAX_ENTRIES} banned words and patterns are allowed");
+        }
+        if self.banned_words.iter().any(|word| word.trim().is_empty()) {
+            bail!("banned words can't be blank");
+        }
+        for pattern in &self.banned_patterns {
+            Regex::new(pattern).with_context(|| format!("bad pattern: {pattern}"))?;
+        }
+        if self.restrict_after.is_some_and(|limit| limit < 1) {
+            bail!("restrict_after must be at least 1");
+        }
+        if self.kick_after.is_some_and(|limit| limit < 1) {
+            bail!("kick_after must be at least 1");
+        }
+        if !(1..=MAX_RESTRICT_MINUTES).contains(&self.restrict_minutes) {
+            bail!("restrict_minutes must be between 1 and {MAX_RESTRICT_MINUTES}");
+        }
+
+        Ok(())
+    }
+
+    /// Compiles the lists for matching messages, `None` when nothing would
+    /// ever match.
+    fn filter(&self) -> anyhow::Result<Option<ChatFilter>> {
+        let words = self
+            .banned_words
+            .iter()
+            .map(|word| regex::escape(word.trim()))
+            .collect::<Vec<_>>();
+        let words = match words.is_empty() {
+            true => None,
+            false => Some(
+                RegexBuilder::new(&format!(r"\b(?:{})\b", words.join("|")))
+                    .case_insensitive(true)
+                    .build()?,
+            ),
+        };
+        let patterns = match self.banned_patterns.is_empty() {
+            true => None,
+            false => Some(
+                RegexSetBuilder::new(&self.banned_patterns)
+                    .case_insensitive(true)
+                    .build()?,
+            ),
+        };
+        if words.is_none() && patterns.is_none() && self.links == LinkPolicy::Allow {
+            return Ok(None);
+        }
+
+        Ok(Some(ChatFilter {
+            words,
+            patterns,
+            links: self.links,
+        }))
+    }
+}
+
+/// The compiled spam filter of a chat, see [`AppState::chat_filter`].
+pub struct ChatFilter {
+    words: Option<Regex>,
+    patterns: Option<RegexSet>,
+    links: LinkPolicy,
+}
+
+impl ChatFilter {
+    fn check(&self, text: &str, has_link: bool) -> Option<Violation> {
+        if let Some(word) = self.words.as_ref().and_then(|words| words.find(text)) {
+            return Some(Violation::BannedWord(word.as_str().to_lowercase()));
+        }
+        if self
+            .patterns
+            .as_ref()
+            .is_some_and(|patterns| patterns.is_match(text))
+        {
+            return Some(Violation::BannedPattern);
+        }
+        if has_link && self.links == LinkPolicy::Delete {
+            return Some(Violation::Link);
+        }
+
+        None
+    }
+}
+
+/// Why a message was deleted.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Violation {
+    BannedWord(String),
+    BannedPattern,
+    Link,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::BannedWord(word) => write!(f, "banned word \"{word}\""),
+            Violation::BannedPattern => f.write_str("banned content"),
+            Violation::Link => f.write_str("links are not allowed"),
+        }
+    }
+}
+
+/// Moderator of the actions taken by the spam filter, admins are
+/// `admin:<user id>`.
+pub const SPAM_FILTER: &str = "spam_filter";
+
+/// Something done to a member by a chat admin or the spam filter.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ModerationKind {
+    Warn,
+    /// Muted for the `restrict_minutes` of the spam filter.
+    Restrict,
+    Kick,
+    Ban,
+    Unban,
+}
+
+impl fmt::Display for ModerationKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ModerationKind::Warn => "warn",
+            ModerationKind::Restrict => "restrict",
+            ModerationKind::Kick => "kick",
+            ModerationKind::Ban => "ban",
+            ModerationKind::Unban => "unban",
+        })
+    }
+}
+
+impl FromStr for ModerationKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "warn" => Ok(ModerationKind::Warn),
+            "restrict" => Ok(ModerationKind::Restrict),
+            "kick" => Ok(ModerationKind::Kick),
+            "ban" => Ok(ModerationKind::Ban),
+            "unban" => Ok(ModerationKind::Unban),
+            other => Err(anyhow!("unknown moderation action: {other}")),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, ToSchema)]
+pub struct ModerationAction {
+    pub id: i32,
+    pub chat_id: i64,
+    pub user_id: i64,
+    pub action: ModerationKind,
+    pub reason: String,
+    /// `spam_filter` or `admin:<user id>`.
+    pub moderator: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Warnings a member collected in a chat.
+#[derive(Clone, Debug, Serialize, ToSchema, sqlx::FromRow)]
+pub struct Warning {
+    pub chat_id: i64,
+    pub user_id: i64,
+    pub warnings: i32,
+    pub last_reason: String,
+    pub last_warned_at: DateTime<Utc>,
+}
+
+/// `@username` or the full name of members without one.
+pub fn display_name(user: &teloxide::types::User) -> String {
+    user.username
+        .as_ref()
+        .map_or_else(|| user.full_name(), |username| format!("@{username}"))
+}
+
+fn has_link(message: &Message) -> bool {
+    message
+        .entities()
+        .into_iter()
+        .chain(message.caption_entities())
+        .flatten()
+        .any(|entity| {
+            matches!(
+                entity.kind,
+                MessageEntityKind::Url | MessageEntityKind::TextLink { .. }
+            )
+        })
+}
+
+impl AppState {
+    pub async fn get_moderation(&self, chat_id: i64) -> anyhow::Result<Option<ModerationSettings>> {
+        self.storage.get_moderation(chat_id).await
+    }
+
+    pub async fn set_moderation(
+        &self,
+        chat_id: i64,
+        settings: &ModerationSettings,
+    ) -> anyhow::Result<()> {
+        settings.validate()?;
+        info!("setting the spam filter of chat:{chat_id}");
+
+        self.storage.set_moderation(chat_id, settings).await?;
+        self.chat_filters.remove(&chat_id);
+
+        Ok(())
+    }
+
+    /// Returns `false` when the chat had no spam filter.
+    pub async fn delete_moderation(&self, chat_id: i64) -> anyhow::Result<bool> {
+        info!("removing the spam filter of chat:{chat_id}");
+
+        let deleted = self.storage.delete_moderation(chat_id).await?;
+        self.chat_filters.remove(&chat_id);
+
+        Ok(deleted)
+    }
+
+    /// The spam filter of the chat, compiled once and kept until it is
+    /// changed. `None` when the chat has none or it never matches.
+    async fn chat_filter(&self, chat_id: i64) -> anyhow::Result<Option<Arc<ChatFilter>>> {
+        if let Some(filter) = self.chat_filters.get(&chat_id) {
+            return Ok(filter.clone());
+        }
+
+        let filter = match self.get_moderation(chat_id).await? {
+            Some(settings) => settings.filter()?.map(Arc::new),
+            None => None,
+        };
+        self.chat_filters.insert(chat_id, filter.clone());
+
+        Ok(filter)
+    }
+
+    /// Most warned first.
+    pub async fn get_warnings(&self, chat_id: i64) -> anyhow::Result<Vec<Warning>> {
+        self.storage.get_warnings(chat_id).await
+    }
+
+    /// Returns `false` when the member had no warnings.
+    pub async fn reset_warnings(&self, chat_id: i64, user_id: i64) -> anyhow::Result<bool> {
+        info!("resetting the warnings of user:{user_id} in chat:{chat_id}");
+
+        self.storage.reset_warnings(chat_id, user_id).await
+    }
+
+    /// Runs a group message through the spam filter of its chat, a matching
+    /// message is deleted and its sender warned. Returns the violation the
+    /// message was deleted for.
+    pub async fn moderate(&self, message: &Message) -> anyhow::Result<Option<Violation>> {
+        let Some(user) = message.from() else {
+            return Ok(None);
+        };
+        let chat_id = message.chat.id;
+        let Some(filter) = self.chat_filter(chat_id.0).await? else {
+            return Ok(None);
+        };
+        let text = message.text().or(message.caption()).unwrap_or_default();
+        let Some(violation) = filter.check(text, has_link(message)) else {
+            return Ok(None);
+        };
+        if self
+            .bot
+            .get_chat_member(chat_id, user.id)
+            .await?
+            .is_privileged()
+        {
+            return Ok(None);
+        }
+
+        info!(
+            "deleting message {} of user:{} in chat:{chat_id}: {violation}",
+            message.id, user.id
+        );
+        self.bot.delete_message(chat_id, message.id).await?;
+        let notice = self
+            .warn_member(&message.chat, user, &violation.to_string(), SPAM_FILTER)
+            .await?;
+        if let Err(err) = self.bot.send_message(chat_id, notice).await {
+            error!("error warning user:{} in chat:{chat_id}: {err}", user.id);
+        }
+
+        Ok(Some(violation))
+    }
+
+    /// Adds a warning to the member, who is muted or kicked once they reach
+    /// the limits of the chat's spam filter. Returns the notice for the chat.
+    pub async fn warn_member(
+        &self,
+        chat: &Chat,
+        user: &teloxide::types::User,
+        reason: &str,
+        moderator: &str,
+    ) -> anyhow::Result<String> {
+        let chat_id = chat.id.0;
+        let user_id = user.id.0 as i64;
+        let settings = self.get_moderation(chat_id).await?.unwrap_or_default();
+        let warnings = self.storage.add_warning(chat_id, user_id, reason).await?;
+        self.log_moderation(chat_id, user_id, ModerationKind::Warn, reason, moderator)
+            .await?;
+
+        let name = display_name(user);
+        if settings.kick_after.is_some_and(|limit| warnings >= limit) {
+            self.kick_member(chat, user, reason, moderator).await?;
+            return Ok(format!("{name} was removed: {reason}."));
+        }
+        if settings
+            .restrict_after
+            .is_some_and(|limit| warnings >= limit)
+        {
+            let until = Utc::now() + Duration::minutes(settings.restrict_minutes.into());
+            self.bot
+                .restrict_chat_member(chat.id, user.id, ChatPermissions::empty())
+                .until_date(until)
+                .await?;
+            self.log_moderation(
+                chat_id,
+                user_id,
+                ModerationKind::Restrict,
+                reason,
+                moderator,
+            )
+            .await?;
+            return Ok(format!(
+                "{name} can't write for {} minutes: {reason}.",
+                settings.restrict_minutes
+            ));
+        }
+
+        Ok(format!(
+            "{name} was warned: {reason}. Warnings: {warnings}."
+        ))
+    }
+
+    /// Removes the member from the chat, they can join again.
+    pub async fn kick_member(
+        &self,
+        chat: &Chat,
+        user: &teloxide::types::User,
+        reason: &str,
+        moderator: &str,
+    ) -> anyhow::Result<()> {
+        self.remove_from_chat(chat, user.id).await?;
+        self.removed(chat.id.0, user, ModerationKind::Kick, reason, moderator)
+            .await
+    }
+
+    /// Removes the user from the chat without banning them.
+    pub async fn remove_from_chat(&self, chat: &Chat, user_id: UserId) -> Result<(), RequestError> {
+        // unbanning a member of a supergroup removes them without a ban
+        if chat.is_supergroup() || chat.is_channel() {
+            self.bot.unban_chat_member(chat.id, user_id).await?;
+        } else {
+            self.bot.kick_chat_member(chat.id, user_id).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Removes the member from the chat for good.
+    pub async fn ban_member(
+        &self,
+        chat: &Chat,
+        user: &teloxide::types::User,
+        reason: &str,
+        moderator: &str,
+    ) -> anyhow::Result<()> {
+        self.bot.ban_chat_member(chat.id, user.id).await?;
+        self.removed(chat.id.0, user, ModerationKind::Ban, reason, moderator)
+            .await
+    }
+
+    /// Lets a banned user join the chat again.
+    pub async fn unban_member(
+        &self,
+        chat: &Chat,
+        user: &teloxide::types::User,
+        reason: &str,
+        moderator: &str,
+    ) -> anyhow::Result<()> {
+        self.bot
+            .unban_chat_member(chat.id, user.id)
+            .only_if_banned(true)
+            .await?;
+        self.log_moderation(
+            chat.id.0,
+            user.id.0 as i64,
+            ModerationKind::Unban,
+            reason,
+            moderator,
+        )
+        .await
+    }
+
+    async fn removed(
+        &self,
+        chat_id: i64,
+        user: &teloxide::types::User,
+        kind: ModerationKind,
+        reason: &str,
+        moderator: &str,
+    ) -> anyhow::Result<()> {
+        let member = User {
+            id: user.id.0 as i64,
+            chat_id,
+            username: user.username.clone(),
+            name: user.full_name(),
+            joined_at: None,
+            updated_at: None,
+        };
+        self.log_kick(chat_id, &member, "moderation", moderator)
+            .await?;
+        self.log_moderation(chat_id, member.id, kind, reason, moderator)
+            .await?;
+        self.remove_chat_member_by_id(chat_id, member.id).await?;
+        self.storage.reset_warnings(chat_id, member.id).await?;
+
+        Ok(())
+    }
+
+    async fn log_moderation(
+        &self,
+        chat_id: i64,
+        user_id: i64,
+        kind: ModerationKind,
+        reason: &str,
+        moderator: &str,
+    ) -> anyhow::Result<()> {
+        info!("{moderator} applied {kind} to user:{user_id} in chat:{chat_id}: {reason}");
+
+        self.storage
+            .log_moderation(chat_id, user_id, kind, reason, moderator)
+            .await
+    }
+
+    /// Newest first.
+    pub async fn get_moderation_log(
+        &self,
+        chat_id: i64,
+        user_id: Option<i64>,
+        limit: i64,
+        offset: i64,
+    ) -> anyhow::Result<Vec<ModerationAction>> {
+        self.storage
+            .get_moderation_log(chat_id, user_id, limit, offset)
+            .await
+    }
+}
@@ -0,0 +1,308 @@
+use axum::{response::Html, Json};
+use utoipa::OpenApi;
+
+use crate::{
+    activity, admin, api, attachment, audit, branding, broadcast, broadcast_search, calendar,
+    captcha, chat_list, config, convert, dead_letter, draft, error, estimate, exclusion, format,
+    health, import, invite_link, join_request, live, maintenance, member_import, moderation, poll,
+    purge, queue, queue_history, queue_transfer, quiet_hours, reaction, reconcile, rename, reply,
+    report, resync, schedule, session, settings, staged_cleanup, state, stats, status_v1,
+    subscriber, topic, usage, validation, variant, webhook, welcome,
+};
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "telegram-sender",
+        description = "Paths act on the first configured bot, every bot is also served under `/bots/{bot_id}`."
+    ),
+    paths(
+        api::healthz,
+        api::readyz,
+        api::metrics,
+        api::botinfo,
+        api::telegram_login,
+        api::current_session,
+        api::audit_log,
+        api::interrupted,
+        api::usage,
+        api::weekly_report,
+        api::chats,
+        api::chat_members,
+        api::chat_member,
+        api::inactive_members,
+        api::chat_kicks,
+        api::chat_moderation,
+        api::chat_history,
+        api::chat_stats,
+        api::chat_topics,
+        api::chat_names,
+        api::chat_admins,
+        api::update_chat,
+        api::mute_chat,
+        api::unmute_chat,
+        api::approve_chat,
+        api::restore_chat,
+        api::set_cleanup_schedule,
+        api::delete_cleanup_schedule,
+        api::exclusions,
+        api::add_exclusion,
+        api::delete_exclusion,
+        api::status,
+        api::chat_status,
+        api::chat_status_v2,
+        api::live_status,
+        api::delete_chat,
+        api::legacy_delete_chat,
+        api::bulk_delete_chats,
+        api::bulk_mute_chats,
+        api::clear_chat_token,
+        api::clear_chat,
+        api::cleanup_candidates,
+        api::confirm_cleanup,
+        api::discard_cleanup,
+        api::unban_all,
+        api::legacy_clear_chat,
+        api::clear_chats,
+        api::cancel_cleanup,
+        api::resume_cleanup,
+        api::purge,
+        api::purge_deleted,
+        api::resync_chat,
+        api::import_members,
+        api::send_message_to_chat,
+        api::test_send,
+        api::send_variants,
+        api::render,
+        api::convert_text,
+        api::estimate,
+        api::send_message_multipart,
+        api::approve_queued_message,
+        api::resume_queued_message,
+        api::queue,
+        api::schedule,
+        api::failed_messages,
+        api::retry_failed_message,
+        api::edit_queued_message,
+        api::queue_history,
+        api::export_queue,
+        api::import_queue,
+        api::send_poll,
+        api::poll_results,
+        api::copy_message,
+        api::queue_progress,
+        api::queue_deliveries,
+        api::queue_files,
+        api::broadcast_variants,
+        api::broadcast_stats,
+        api::search_broadcasts,
+        api::broadcast_replies,
+        api::import,
+        api::webhooks,
+        api::add_webhook,
+        api::delete_webhook,
+        api::drafts,
+        api::add_draft,
+        api::update_draft,
+        api::delete_draft,
+        api::send_draft,
+        api::update_throttle,
+        api::get_settings,
+        api::update_settings,
+        api::get_maintenance,
+        api::set_maintenance,
+        api::edit_broadcast,
+        api::delete_broadcast,
+        api::resend_broadcast,
+        api::retry_failed_chats,
+        api::set_welcome,
+        api::delete_welcome,
+        api::quiet_hours,
+        api::set_quiet_hours,
+        api::delete_quiet_hours,
+        api::branding,
+        api::update_branding,
+        api::invite_links,
+        api::create_invite_link,
+        api::captcha,
+        api::set_captcha,
+        api::delete_captcha,
+        api::captcha_challenges,
+        api::spam_filter,
+        api::set_spam_filter,
+        api::delete_spam_filter,
+        api::warnings,
+        api::reset_warnings,
+        api::revoke_invite_link,
+        api::join_policy,
+        api::set_join_policy,
+        api::join_requests,
+        api::approve_join_request,
+        api::decline_join_request,
+    ),
+    components(schemas(
+        activity::InactiveMember,
+        admin::ChatAdmin,
+        attachment::Attachments,
+        attachment::Location,
+        captcha::Captcha,
+        captcha::CaptchaChallenge,
+        chat_list::ChatSort,
+        api::Liveness,
+        api::Readiness,
+        api::BotInfo,
+        api::UpdateMode,
+        api::LoggedIn,
+        api::ChatPatch,
+        api::BrandingPatch,
+        api::ConfirmationToken,
+        api::ClearChatBody,
+        api::ClearChatsBody,
+        api::BulkChatsBody,
+        api::BulkMuteBody,
+        api::CleanupScheduleBody,
+        api::EditBroadcastBody,
+        api::ResendBroadcastBody,
+        api::RetriedChats,
+        api::SendTargets,
+        api::SendMessageBody,
+        api::SendVariantsBody,
+        api::RenderedMessage,
+        api::ConvertBody,
+        api::ConvertedText,
+        convert::SourceFormat,
+        api::SendMessageMetadata,
+        api::SendPollBody,
+        api::QueuedPoll,
+        api::CopyMessageBody,
+        api::QueuedMessageId,
+        api::TestSent,
+        api::PurgeStarted,
+        api::ThrottleSettings,
+        api::ThrottlePatch,
+        api::WebhookCreated,
+        audit::AuditEntry,
+        branding::Branding,
+        broadcast::BroadcastProgress,
+        broadcast::ChatResult,
+        broadcast::DeliveredFile,
+        broadcast::Delivery,
+        broadcast::MessageOptions,
+        broadcast::SentMessage,
+        calendar::Occurrence,
+        dead_letter::FailedMessage,
+        draft::Draft,
+        draft::DraftContent,
+        error::ErrorBody,
+        estimate::BroadcastEstimate,
+        exclusion::Exclusion,
+        exclusion::NewExclusion,
+        format::MessageFormat,
+        health::TaskHeartbeat,
+        import::Backup,
+        import::ImportedChat,
+        import::ImportedMember,
+        import::ConflictMode,
+        import::ImportReport,
+        import::ImportCounts,
+        invite_link::InviteLink,
+        invite_link::NewInviteLink,
+        join_request::ChatJoinPolicy,
+        join_request::JoinPolicy,
+        join_request::JoinRequest,
+        join_request::JoinRequestStatus,
+        live::LiveEvent,
+        maintenance::Maintenance,
+        moderation::LinkPolicy,
+        moderation::ModerationAction,
+        moderation::ModerationKind,
+        moderation::ModerationSettings,
+        moderation::Warning,
+        poll::PollDefinition,
+        poll::PollResults,
+        poll::PollOptionResult,
+        poll::ChatPollResult,
+        purge::PurgeFilter,
+        resync::ResyncProgress,
+        member_import::SeededMember,
+        member_import::MemberImportReport,
+        staged_cleanup::CleanupCandidate,
+        staged_cleanup::UnbanResult,
+        queue::QueueEntry,
+        queue::QueueStatus,
+        queue_history::QueuedMessageEdit,
+        queue_history::QueuedMessageVersion,
+        queue_transfer::ExportedMessage,
+        queue_transfer::QueueConflict,
+        queue_transfer::QueueExport,
+        queue_transfer::QueueImportReport,
+        queue_transfer::RemappedMessage,
+        quiet_hours::QuietHours,
+        reaction::BroadcastStats,
+        broadcast_search::FoundBroadcast,
+        reaction::ReactionTotal,
+        reaction::VariantReactions,
+        reconcile::Interrupted,
+        reconcile::InterruptedCleanup,
+        rename::ChatRename,
+        reply::BroadcastReply,
+        report::ErrorCount,
+        report::WeeklyReport,
+        schedule::CleanupSchedule,
+        session::Session,
+        session::TelegramLogin,
+        settings::Settings,
+        config::ThrottleConfig,
+        config::IntervalsConfig,
+        state::Chat,
+        state::ChatCleaningStatus,
+        state::ChatStatus,
+        state::ChatStatusSummary,
+        state::CleanupState,
+        state::ClearChatsResult,
+        state::CleanupProgress,
+        state::CleanupRecord,
+        state::Kick,
+        state::User,
+        status_v1::ChatCleaningStatusV1,
+        status_v1::ChatStatusV1,
+        stats::ChatStats,
+        stats::CleanupDay,
+        subscriber::Audience,
+        topic::ChatTarget,
+        topic::ChatTopic,
+        usage::UsageEntry,
+        validation::FieldError,
+        variant::MessageVariant,
+        variant::VariantResult,
+        webhook::NewWebhook,
+        webhook::Webhook,
+        webhook::WebhookEvent,
+        welcome::Welcome,
+    ))
+)]
+struct ApiDoc;
+
+pub async fn spec() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+// swagger ui is loaded from a cdn so the binary doesn't have to bundle it
+pub async fn swagger_ui() -> Html<&'static str> {
+    Html(
+        r##"<!DOCTYPE html>
+<html>
+<head>
+    <title>telegram-sender API</title>
+    <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5/swagger-ui.css" />
+</head>
+<body>
+    <div id="swagger-ui"></div>
+    <script src="https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js"></script>
+    <script>
+        window.ui = SwaggerUIBundle({ url: "/openapi.json", dom_id: "#swagger-ui" });
+    </script>
+</body>
+</html>"##,
+    )
+}
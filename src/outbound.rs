@@ -0,0 +1,126 @@
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail};
+use hyper::client::connect::dns::Name;
+use reqwest::{
+    dns::{Addrs, Resolve, Resolving},
+    redirect, Url,
+};
+
+use crate::state::AppState;
+
+const MAX_REDIRECTS: usize = 5;
+
+/// Client for image downloads, webhooks and sinks. Unless `allow_private` it
+/// refuses hosts resolving to addresses of the machine or its networks and
+/// follows redirects only to hosts it would accept.
+pub fn client(timeout: Duration, allow_private: bool) -> reqwest::Client {
+    let mut builder = reqwest::Client::builder().timeout(timeout);
+    if !allow_private {
+        builder =
+            builder
+                .dns_resolver(Arc::new(PublicResolver))
+                .redirect(redirect::Policy::custom(|attempt| {
+                    if attempt.previous().len() >= MAX_REDIRECTS {
+                        return attempt.error("too many redirects");
+                    }
+                    match check_host(attempt.url()) {
+                        Ok(()) => attempt.follow(),
+                        Err(err) => attempt.error(err),
+                    }
+                }));
+    }
+
+    builder.build().expect("http client")
+}
+
+/// Whether the address belongs to the machine or a network that is not the
+/// internet, where a URL from the API must not lead.
+pub fn is_internal(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                // carrier-grade NAT, 100.64.0.0/10
+                || (a == 100 && b & 0xc0 == 64)
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_internal(ip.into()),
+            None => {
+                let first = ip.segments()[0];
+                ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_multicast()
+                    // unique local, fc00::/7
+                    || first & 0xfe00 == 0xfc00
+                    // link-local, fe80::/10
+                    || first & 0xffc0 == 0xfe80
+            }
+        },
+    }
+}
+
+/// Refuses URLs naming an internal address directly, host names are checked
+/// when they are resolved.
+fn check_host(url: &Url) -> anyhow::Result<()> {
+    let Some(host) = url.host_str() else {
+        bail!("{url} has no host");
+    };
+    // IPv6 hosts keep their brackets
+    let Ok(ip) = host
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse::<IpAddr>()
+    else {
+        return Ok(());
+    };
+    if is_internal(ip) {
+        bail!("{url} points to the internal address {ip}");
+    }
+
+    Ok(())
+}
+
+/// The system resolver, failing for names with any internal address.
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> =
+                tokio::net::lookup_host((name.as_str(), 0)).await?.collect();
+            if let Some(addr) = addrs.iter().find(|addr| is_internal(addr.ip())) {
+                return Err(anyhow!(
+                    "{} resolves to the internal address {}",
+                    name.as_str(),
+                    addr.ip()
+                )
+                .into());
+            }
+
+            let addrs: Addrs = Box::new(addrs.into_iter());
+            Ok(addrs)
+        })
+    }
+}
+
+impl AppState {
+    /// Refuses URLs given through the API that name an internal address,
+    /// unless the configuration allows those.
+    pub fn check_outbound_url(&self, url: &str) -> anyhow::Result<()> {
+        if self.config.allow_private_urls {
+            return Ok(());
+        }
+
+        check_host(&Url::parse(url)?)
+    }
+}
@@ -0,0 +1,288 @@
+use anyhow::bail;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use teloxide::{
+    payloads::SendPollSetters,
+    requests::Requester,
+    types::{ChatId, Message, Poll},
+};
+use tracing::info;
+use utoipa::ToSchema;
+
+use crate::{
+    attachment::Attachments,
+    broadcast::MessageOptions,
+    language::Translations,
+    queue::parse_datetime,
+    state::AppState,
+    storage::{insert_queued_message, NewQueuedMessage},
+    subscriber::Audience,
+    telemetry::current_request_id,
+    topic::ChatTarget,
+};
+
+/// Poll settings stored next to a queued message, the question itself is
+/// kept as the message text.
+#[derive(Clone, Serialize, Deserialize, ToSchema)]
+pub struct PollDefinition {
+    pub options: Vec<String>,
+    #[serde(default = "default_anonymous")]
+    pub is_anonymous: bool,
+    #[serde(default)]
+    pub allows_multiple_answers: bool,
+}
+
+fn default_anonymous() -> bool {
+    true
+}
+
+impl PollDefinition {
+    /// Checks the limits telegram puts on polls.
+    pub fn validate(&self, question: &str) -> anyhow::Result<()> {
+        if !(1..=300).contains(&question.chars().count()) {
+            bail!("poll question must be 1-300 characters long");
+        }
+        if !(2..=10).contains(&self.options.len()) {
+            bail!("poll must have 2-10 options");
+        }
+        if let Some(option) = self
+            .options
+            .iter()
+            .find(|option| !(1..=100).contains(&option.chars().count()))
+        {
+            bail!("poll option {option:?} must be 1-100 characters long");
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct PollResults {
+    id: i32,
+    question: String,
+    total_voter_count: i64,
+    options: Vec<PollOptionResult>,
+    chats: Vec<ChatPollResult>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct PollOptionResult {
+    text: String,
+    voter_count: i64,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ChatPollResult {
+    chat_id: i64,
+    total_voter_count: i32,
+    voter_counts: Vec<i32>,
+    is_closed: bool,
+    updated_at: DateTime<Utc>,
+}
+
+impl AppState {
+    pub async fn queue_poll(
+        &self,
+        chats: Vec<i64>,
+        question: String,
+        poll: PollDefinition,
+        datetime: String,
+        options: MessageOptions,
+    ) -> anyhow::Result<i32> {
+        info!("queueing poll: {question} on datetime: {datetime}");
+
+        let mut tx = self.pg()?.begin().await?;
+
+        let targets: Vec<ChatTarget> = chats.into_iter().map(ChatTarget::Chat).collect();
+        let id = insert_queued_message(
+            &mut tx,
+            &NewQueuedMessage {
+                bot_id: &self.bot_id,
+                targets: (options.audience == Audience::Chats).then_some(targets.as_slice()),
+                excluded_chats: &[],
+                message: &question,
+                images: &[],
+                media: &[],
+                datetime: parse_datetime(&datetime)?,
+                options: &options,
+                awaiting_approval: false,
+                copy_from: None,
+                variants: &[],
+                translations: &Translations::new(),
+                attachments: &Attachments::default(),
+                request_id: current_request_id().as_deref(),
+                duplicates_since: None,
+            },
+        )
+        .await?;
+
+        sqlx::query!(
+            r#"
+INSERT INTO message_poll ( message_id, options, is_anonymous, allows_multiple_answers )
+VALUES ( $1, $2, $3, $4 )
+            "#,
+            id,
+            &poll.options,
+            poll.is_anonymous,
+            poll.allows_multiple_answers
+        )
+        .execute(&mut tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(id)
+    }
+
+    /// Always `None` without postgres, where polls can't be queued.
+    pub async fn get_queued_poll(&self, message_id: i32) -> anyhow::Result<Option<PollDefinition>> {
+        let Some(pool) = &self.pool else {
+            return Ok(None);
+        };
+
+        let poll = sqlx::query_as!(
+            PollDefinition,
+            r#"
+SELECT options, is_anonymous, allows_multiple_answers FROM message_poll
+WHERE message_id = $1
+            "#,
+            message_id
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(poll)
+    }
+
+    pub async fn send_poll_to_chat(
+        &self,
+        chat_id: i64,
+        question: &str,
+        poll: &PollDefinition,
+        options: &MessageOptions,
+    ) -> anyhow::Result<Message> {
+        info!("sending poll:{question} to chat:{chat_id}");
+
+        let mut request = self
+            .bot
+            .send_poll(ChatId(chat_id), question, poll.options.clone())
+            .is_anonymous(poll.is_anonymous)
+            .allows_multiple_answers(poll.allows_multiple_answers)
+            .disable_notification(options.silent)
+            .protect_content(options.protect_content);
+        if let Some(thread_id) = options.thread_id {
+            request = request.message_thread_id(thread_id);
+        }
+        options.reply_to(&mut request);
+        let sent = request.await?;
+
+        Ok(sent)
+    }
+
+    pub async fn record_sent_poll(
+        &self,
+        message_id: i32,
+        chat_id: i64,
+        poll: &Poll,
+    ) -> anyhow::Result<()> {
+        let voter_counts: Vec<i32> = poll
+            .options
+            .iter()
+            .map(|option| option.voter_count)
+            .collect();
+
+        sqlx::query!(
+            r#"
+INSERT INTO sent_poll ( poll_id, message_id, chat_id, voter_counts, total_voter_count )
+VALUES ( $1, $2, $3, $4, $5 )
+ON CONFLICT (poll_id) DO NOTHING
+            "#,
+            poll.id,
+            message_id,
+            chat_id,
+            &voter_counts,
+            poll.total_voter_count
+        )
+        .execute(self.pg()?)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Stores the latest counts telegram reports for a poll sent by the bot.
+    pub async fn update_poll_results(&self, poll: &Poll) -> anyhow::Result<()> {
+        let voter_counts: Vec<i32> = poll
+            .options
+            .iter()
+            .map(|option| option.voter_count)
+            .collect();
+
+        sqlx::query!(
+            r#"
+UPDATE sent_poll
+SET voter_counts = $2, total_voter_count = $3, is_closed = $4, updated_at = now()
+WHERE poll_id = $1
+            "#,
+            poll.id,
+            &voter_counts,
+            poll.total_voter_count as i32,
+            poll.is_closed
+        )
+        .execute(self.pg()?)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_poll_results(&self, message_id: i32) -> anyhow::Result<Option<PollResults>> {
+        let Some(poll) = sqlx::query!(
+            r#"
+SELECT q.message, p.options FROM message_poll p
+JOIN message_queue q ON q.id = p.message_id
+WHERE p.message_id = $1 AND q.bot_id = $2
+            "#,
+            message_id,
+            &*self.bot_id
+        )
+        .fetch_optional(self.pg()?)
+        .await?
+        else {
+            return Ok(None);
+        };
+
+        let chats = sqlx::query_as!(
+            ChatPollResult,
+            r#"
+SELECT chat_id, total_voter_count, voter_counts, is_closed, updated_at FROM sent_poll
+WHERE message_id = $1
+ORDER BY chat_id
+            "#,
+            message_id
+        )
+        .fetch_all(self.pg()?)
+        .await?;
+
+        let options = poll
+            .options
+            .into_iter()
+            .enumerate()
+            .map(|(index, text)| PollOptionResult {
+                text,
+                voter_count: chats
+                    .iter()
+                    .filter_map(|chat| chat.voter_counts.get(index))
+                    .map(|&count| count as i64)
+                    .sum(),
+            })
+            .collect();
+
+        Ok(Some(PollResults {
+            id: message_id,
+            question: poll.message,
+            total_voter_count: chats.iter().map(|chat| chat.total_voter_count as i64).sum(),
+            options,
+            chats,
+        }))
+    }
+}
@@ -0,0 +1,244 @@
+use std::{collections::HashMap, fmt::Display, path::Path, sync::Arc};
+
+use sqlx::{migrate::MigrateError, PgPool};
+use teloxide::{requests::Requester, RequestError};
+
+use crate::{
+    config::{BotConfig, Config},
+    state::WrappedBot,
+    status_store::StatusStoreKind,
+    storage::{self, Storage},
+};
+
+/// Why the service refused to start, each with its own exit code so ops can
+/// tell them apart without reading the logs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Failure {
+    /// Exit code 2, the environment doesn't make a valid configuration.
+    Config,
+    /// Exit code 3, the log file, the API socket or the TLS files can't be
+    /// used.
+    Filesystem,
+    /// Exit code 4, the database can't be reached.
+    Database,
+    /// Exit code 5, the migrations of the database don't match the ones of
+    /// this build, like after rolling back to an older release.
+    Migrations,
+    /// Exit code 6, telegram refused a bot token.
+    Token,
+    /// Exit code 7, telegram can't be reached.
+    Telegram,
+    /// Exit code 8, something else receives the updates of a bot: a webhook
+    /// that polling would remove or another bot with the same token.
+    Polling,
+}
+
+impl Failure {
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Failure::Config => 2,
+            Failure::Filesystem => 3,
+            Failure::Database => 4,
+            Failure::Migrations => 5,
+            Failure::Token => 6,
+            Failure::Telegram => 7,
+            Failure::Polling => 8,
+        }
+    }
+}
+
+/// Stops the service before anything was checked, the report can't be
+/// printed without a configuration.
+pub fn abort(failure: Failure, err: impl Display) -> ! {
+    eprintln!("preflight failed: {err:#}");
+    std::process::exit(failure.exit_code())
+}
+
+struct Check {
+    name: String,
+    outcome: Result<String, (Failure, String)>,
+}
+
+/// The outcome of every check made before the tasks are spawned, printed
+/// as a single summary.
+#[derive(Default)]
+pub struct Preflight {
+    checks: Vec<Check>,
+}
+
+impl Preflight {
+    fn ok(&mut self, name: impl Into<String>, detail: impl Into<String>) {
+        self.checks.push(Check {
+            name: name.into(),
+            outcome: Ok(detail.into()),
+        });
+    }
+
+    fn fail(&mut self, name: impl Into<String>, failure: Failure, err: impl Display) {
+        self.checks.push(Check {
+            name: name.into(),
+            outcome: Err((failure, format!("{err:#}"))),
+        });
+    }
+
+    /// The first failed check, which decides the exit code.
+    pub fn failure(&self) -> Option<Failure> {
+        self.checks
+            .iter()
+            .find_map(|check| check.outcome.as_ref().err().map(|(failure, _)| *failure))
+    }
+
+    pub fn summary(&self) -> String {
+        self.checks
+            .iter()
+            .map(|check| match &check.outcome {
+                Ok(detail) => format!("  ok    {}: {detail}", check.name),
+                Err((failure, err)) => format!(
+                    "  FAIL  {}: {err} (exit code {})",
+                    check.name,
+                    failure.exit_code()
+                ),
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Prints the summary when a check failed and exits with the code of the
+    /// first failure, returns otherwise.
+    pub fn exit_on_failure(&self) {
+        if let Some(failure) = self.failure() {
+            eprintln!("preflight failed:\n{}", self.summary());
+            std::process::exit(failure.exit_code());
+        }
+    }
+
+    /// Checks that the files the service writes or reads can be used,
+    /// before the logging is set up on them.
+    pub fn files(&mut self, config: &Config) {
+        if let Some(path) = &config.log.file {
+            match writable_directory(path) {
+                Ok(()) => self.ok("log file", path.display().to_string()),
+                Err(err) => self.fail("log file", Failure::Filesystem, err),
+            }
+        }
+        if let Some(path) = &config.api_socket {
+            match writable_directory(path) {
+                Ok(()) => self.ok("api socket", path.display().to_string()),
+                Err(err) => self.fail("api socket", Failure::Filesystem, err),
+            }
+        }
+        for path in config.tls_cert.iter().chain(&config.tls_key) {
+            match std::fs::File::open(path) {
+                Ok(_) => self.ok("tls", path.display().to_string()),
+                Err(err) => self.fail(
+                    "tls",
+                    Failure::Filesystem,
+                    format!("can't read {}: {err}", path.display()),
+                ),
+            }
+        }
+    }
+
+    /// Connects to the database and brings its migrations up to date. `None`
+    /// when that failed.
+    pub async fn database(
+        &mut self,
+        config: &Config,
+    ) -> Option<(Arc<dyn Storage>, Option<PgPool>)> {
+        let connected = storage::connect(
+            &config.database_url,
+            config.max_connections,
+            &config.database_pool,
+        )
+        .await;
+        match connected {
+            Ok((storage, pool)) => {
+                let kind = match pool {
+                    Some(_) => "postgres",
+                    None => "sqlite",
+                };
+                self.ok("database", format!("{kind}, migrations are current"));
+                if config.status_store == StatusStoreKind::Postgres && pool.is_none() {
+                    self.fail(
+                        "status store",
+                        Failure::Config,
+                        "STATUS_STORE=postgres needs a postgres database",
+                    );
+                }
+                Some((storage, pool))
+            }
+            Err(err) if err.chain().any(|cause| cause.is::<MigrateError>()) => {
+                self.fail("database", Failure::Migrations, err);
+                None
+            }
+            Err(err) => {
+                self.fail("database", Failure::Database, err);
+                None
+            }
+        }
+    }
+
+    /// Asks telegram for every bot whether its token is valid and whether
+    /// updates would reach it by polling.
+    pub async fn bots(&mut self, configs: &[BotConfig], bots: &[(&str, WrappedBot)]) {
+        let mut tokens: HashMap<&str, &str> = HashMap::new();
+        for config in configs {
+            if let Some(other) = tokens.insert(&config.token, &config.id) {
+                self.fail(
+                    format!("bot {}", config.id),
+                    Failure::Polling,
+                    format!("shares its token with bot {other}, their polling would conflict"),
+                );
+            }
+        }
+
+        for (id, bot) in bots {
+            let name = format!("bot {id}");
+            let me = match bot.get_me().await {
+                Ok(me) => me,
+                Err(err) => {
+                    let failure = match err {
+                        // telegram answered, but not for this token
+                        RequestError::Api(_) => Failure::Token,
+                        _ => Failure::Telegram,
+                    };
+                    self.fail(name, failure, err);
+                    continue;
+                }
+            };
+            let webhook = match bot.get_webhook_info().await {
+                Ok(webhook) => webhook,
+                Err(err) => {
+                    self.fail(name, Failure::Telegram, err);
+                    continue;
+                }
+            };
+            match webhook.url {
+                // the url may carry a secret, so only its host is shown
+                Some(url) => self.fail(
+                    name,
+                    Failure::Polling,
+                    format!(
+                        "a webhook to {} is set, polling would remove it",
+                        url.host_str().unwrap_or("?")
+                    ),
+                ),
+                None => self.ok(name, format!("@{}", me.username())),
+            }
+        }
+    }
+}
+
+/// Makes sure a file can be created next to `path`.
+fn writable_directory(path: &Path) -> anyhow::Result<()> {
+    let directory = match path.parent() {
+        Some(directory) if !directory.as_os_str().is_empty() => directory,
+        _ => Path::new("."),
+    };
+    let probe = directory.join(format!(".preflight-{}", std::process::id()));
+    std::fs::write(&probe, b"")
+        .map_err(|err| anyhow::anyhow!("can't write to {}: {err}", directory.display()))?;
+    std::fs::remove_file(&probe)?;
+
+    Ok(())
+}
@@ -0,0 +1,215 @@
+use anyhow::bail;
+use chrono::NaiveDate;
+use serde::Deserialize;
+use teloxide::{
+    requests::Requester,
+    types::{ChatId, UserId},
+};
+use tracing::info;
+use utoipa::ToSchema;
+
+use crate::{
+    activity::MAX_INACTIVE_DAYS,
+    state::{AppState, ChatCleaningStatus, CleanupProgress, User, Users},
+    stats::MEMBER_JOINED,
+    telegram_error::{member_failed, TelegramErrorClass},
+};
+
+const DELETED_ACCOUNT_NAME: &str = "Deleted Account";
+
+/// Selects the members a purge kicks. Every criterion that is set has to
+/// match.
+#[derive(Deserialize, ToSchema)]
+pub struct PurgeFilter {
+    /// Members whose last recorded join is before this day. Members that
+    /// joined before join events were recorded always match.
+    pub joined_before: Option<NaiveDate>,
+    /// Members without a username.
+    #[serde(default)]
+    pub no_username: bool,
+    /// Members whose username matches this postgres regular expression.
+    pub username_regex: Option<String>,
+    /// Only these user ids.
+    pub user_ids: Option<Vec<i64>>,
+    /// Members that didn't write for this many days, counted from their join
+    /// when they never wrote since activity was tracked. Members without a
+    /// message or a recorded join never match.
+    pub inactive_days: Option<u32>,
+}
+
+impl PurgeFilter {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.joined_before.is_none()
+            && !self.no_username
+            && self.username_regex.is_none()
+            && self.user_ids.is_none()
+            && self.inactive_days.is_none()
+        {
+            bail!("the filter must set at least one criterion");
+        }
+        if self
+            .inactive_days
+            .is_some_and(|days| !(1..=MAX_INACTIVE_DAYS).contains(&days))
+        {
+            bail!("inactive_days must be between 1 and {MAX_INACTIVE_DAYS}");
+        }
+
+        Ok(())
+    }
+}
+
+impl AppState {
+    pub async fn find_purge_targets(
+        &self,
+        chat_id: i64,
+        filter: &PurgeFilter,
+    ) -> anyhow::Result<Users> {
+        let users = sqlx::query_as!(
+            User,
+            r#"
+SELECT id, chat_id, username, name, joined_at, updated_at FROM tg_user u
+WHERE chat_id = $1
+AND ( $2::DATE IS NULL OR NOT EXISTS (
+    SELECT 1 FROM member_event e
+    WHERE e.chat_id = u.chat_id AND e.user_id = u.id AND e.kind = $3
+    AND e.created_at >= $2::DATE
+) )
+AND ( NOT $4 OR username IS NULL )
+AND ( $5::TEXT IS NULL OR username ~ $5 )
+AND ( $6::BIGINT[] IS NULL OR id = ANY($6) )
+AND ( $7::INT IS NULL
+    OR COALESCE(last_message_at, joined_at) < now() - make_interval(days => $7) )
+ORDER BY id
+            "#,
+            chat_id,
+            filter.joined_before,
+            MEMBER_JOINED,
+            filter.no_username,
+            filter.username_regex,
+            filter.user_ids.as_deref(),
+            filter.inactive_days.map(|days| days as i32)
+        )
+        .fetch_all(self.pg()?)
+        .await?;
+
+        Ok(users)
+    }
+
+    /// Kicks the given members of an already queued chat, see
+    /// [`AppState::queue_cleanup`].
+    pub async fn purge_members(
+        &self,
+        chat_id: i64,
+        members: Users,
+        reason: &str,
+        initiator: &str,
+    ) -> anyhow::Result<()> {
+        info!(
+            "purging {} members from chat:{chat_id} initiated by {initiator}",
+            members.len()
+        );
+
+        let result = self.kick_members(chat_id, members, reason, initiator).await;
+        match result {
+            Ok(true) => {
+                self.notify_cleanup_finished(chat_id, reason, initiator);
+                self.set_chat_status(chat_id, ChatCleaningStatus::Idle)?;
+            }
+            Ok(false) => {}
+            Err(err) => {
+                self.set_chat_status(chat_id, ChatCleaningStatus::Error(err.to_string()))?;
+                return Err(err);
+            }
+        }
+
+        info!("done purging chat:{chat_id}");
+
+        Ok(())
+    }
+
+    /// Kicks only the members whose telegram account was deleted. Like
+    /// [`AppState::purge_members`] the chat has to be queued already.
+    pub async fn purge_deleted_accounts(
+        &self,
+        chat_id: i64,
+        initiator: &str,
+    ) -> anyhow::Result<()> {
+        let members = match self.find_deleted_accounts(chat_id).await {
+            Ok(Some(members)) => members,
+            Ok(None) => return Ok(()),
+            Err(err) => {
+                self.set_chat_status(chat_id, ChatCleaningStatus::Error(err.to_string()))?;
+                return Err(err);
+            }
+        };
+
+        self.purge_members(chat_id, members, "deleted_account", initiator)
+            .await
+    }
+
+    /// Asks telegram about every stored member, telegram reports deleted
+    /// accounts with an empty first name or as "Deleted Account", or doesn't
+    /// know them at all. The `InProgress` status counts the members asked
+    /// about. Returns `None` when [`AppState::cancel_cleanup`] stopped it,
+    /// in which case the status is already `Cancelled`.
+    async fn find_deleted_accounts(&self, chat_id: i64) -> anyhow::Result<Option<Users>> {
+        let members = self.get_all_members(chat_id).await?;
+        let mut progress = CleanupProgress::new(members.len());
+        self.set_chat_status(chat_id, ChatCleaningStatus::InProgress(progress.clone()))?;
+
+        let mut deleted = Vec::new();
+        for user in members {
+            if self.cancelled_cleanups.remove(&chat_id).is_some() {
+                info!(
+                    "search for deleted accounts in chat:{chat_id} cancelled after {} members",
+                    progress.processed
+                );
+                self.set_chat_status(chat_id, ChatCleaningStatus::Cancelled(progress.processed))?;
+                return Ok(None);
+            }
+
+            let result = loop {
+                let result = self
+                    .bot
+                    .get_chat_member(ChatId(chat_id), UserId(user.id as u64))
+                    .await;
+                match result.as_ref().map_err(TelegramErrorClass::of) {
+                    Err(TelegramErrorClass::RateLimited(wait)) => {
+                        info!(
+                            "member lookups in chat:{chat_id} are rate limited, waiting {wait:?}"
+                        );
+                        tokio::time::sleep(wait).await;
+                    }
+                    _ => break result,
+                }
+            };
+            match result {
+                Ok(member) => {
+                    let first_name = member.user.first_name.trim();
+                    if first_name.is_empty() || first_name == DELETED_ACCOUNT_NAME {
+                        deleted.push(user);
+                    }
+                }
+                Err(err) if TelegramErrorClass::of(&err) == TelegramErrorClass::UserGone => {
+                    deleted.push(user);
+                }
+                Err(err) => {
+                    member_failed(err, "look up", chat_id, user.id)?;
+                    progress.failed += 1;
+                }
+            }
+            progress.processed += 1;
+            self.set_chat_status(chat_id, ChatCleaningStatus::InProgress(progress.clone()))?;
+        }
+
+        // a cancel that arrived after the last member stops the kicks, with
+        // nothing to kick it has nothing left to stop
+        if deleted.is_empty() {
+            self.cancelled_cleanups.remove(&chat_id);
+        }
+
+        info!("found {} deleted accounts in chat:{chat_id}", deleted.len());
+
+        Ok(Some(deleted))
+    }
+}
@@ -0,0 +1,104 @@
+use std::{fmt, str::FromStr};
+
+use anyhow::anyhow;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::state::AppState;
+
+/// Where a queued message is in its lifecycle.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum QueueStatus {
+    /// Waiting for its datetime or for the queue to pick it up.
+    Pending,
+    AwaitingApproval,
+    /// Claimed by an instance that is sending it.
+    Processing,
+    Completed,
+    /// Given up on after too many failed attempts.
+    Failed,
+}
+
+impl fmt::Display for QueueStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            QueueStatus::Pending => "pending",
+            QueueStatus::AwaitingApproval => "awaiting_approval",
+            QueueStatus::Processing => "processing",
+            QueueStatus::Completed => "completed",
+            QueueStatus::Failed => "failed",
+        })
+    }
+}
+
+impl FromStr for QueueStatus {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pending" => Ok(QueueStatus::Pending),
+            "awaiting_approval" => Ok(QueueStatus::AwaitingApproval),
+            "processing" => Ok(QueueStatus::Processing),
+            "completed" => Ok(QueueStatus::Completed),
+            "failed" => Ok(QueueStatus::Failed),
+            other => Err(anyhow!("unknown queue status: {other}")),
+        }
+    }
+}
+
+/// When a message given to be queued is due, the API checks the datetimes it
+/// accepts to be RFC 3339 timestamps.
+pub fn parse_datetime(datetime: &str) -> anyhow::Result<DateTime<Utc>> {
+    let datetime = DateTime::parse_from_rfc3339(datetime)
+        .map_err(|err| anyhow!("bad datetime {datetime}: {err}"))?;
+
+    Ok(datetime.with_timezone(&Utc))
+}
+
+/// Narrows down the queued messages of a bot, every field is optional.
+#[derive(Clone, Debug, Default)]
+pub struct QueueFilter {
+    pub status: Option<QueueStatus>,
+    /// Only messages scheduled before this time.
+    pub due_before: Option<DateTime<Utc>>,
+    /// Only messages targeting the chat, broadcasts to all chats target every
+    /// chat.
+    pub chat_id: Option<i64>,
+}
+
+/// A queued message as listed by `GET /queue`, without its images and
+/// sending options.
+#[derive(Clone, Debug, Serialize, ToSchema)]
+pub struct QueueEntry {
+    pub id: i32,
+    pub status: QueueStatus,
+    /// Filled with the whole audience once a broadcast to all chats or to the
+    /// subscribers is sent.
+    pub chats: Vec<i64>,
+    pub all_chats: bool,
+    pub audience: String,
+    pub message: String,
+    pub datetime: DateTime<Utc>,
+    /// Wall clock time the message goes out at in each chat's own time.
+    #[serde(with = "crate::local_time::datetime_local")]
+    #[schema(value_type = Option<String>, example = "2024-06-01T09:00")]
+    pub datetime_local: Option<NaiveDateTime>,
+    pub attempts: i32,
+    pub completed_at: Option<DateTime<Utc>>,
+}
+
+impl AppState {
+    /// Queued messages matching the filter ordered by when they are due.
+    pub async fn get_queue(
+        &self,
+        filter: &QueueFilter,
+        limit: i64,
+        offset: i64,
+    ) -> anyhow::Result<Vec<QueueEntry>> {
+        self.storage
+            .get_queue(&self.bot_id, filter, limit, offset)
+            .await
+    }
+}
@@ -0,0 +1,63 @@
+//! Edits of queued messages before they go out, with the versions they
+//! replaced and who replaced them.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+use utoipa::ToSchema;
+
+use crate::state::AppState;
+
+/// What `PATCH /queue/{id}` changes, fields left out stay as they are.
+#[derive(Deserialize, ToSchema)]
+pub struct QueuedMessageEdit {
+    pub message: Option<String>,
+    pub datetime: Option<DateTime<Utc>>,
+}
+
+/// A queued message as it was before an edit replaced it.
+#[derive(Debug, Serialize, ToSchema, sqlx::FromRow)]
+pub struct QueuedMessageVersion {
+    pub message: String,
+    pub datetime: DateTime<Utc>,
+    /// Who made the edit, named like in the audit log.
+    pub edited_by: String,
+    pub edited_at: DateTime<Utc>,
+}
+
+impl AppState {
+    /// Replaces the text or time of a message no chat was sent to yet,
+    /// keeping the version it replaces. The text has to be valid for the
+    /// parse mode of the message already. Returns `false` when there is no
+    /// such message, or it is already being sent, sent or failed.
+    pub async fn edit_queued_message(
+        &self,
+        id: i32,
+        edit: &QueuedMessageEdit,
+        editor: &str,
+    ) -> anyhow::Result<bool> {
+        let Some(message) = self.get_queued_message(id).await? else {
+            return Ok(false);
+        };
+        let text = edit.message.as_deref().unwrap_or(&message.message);
+        let datetime = edit.datetime.unwrap_or(message.datetime);
+
+        info!("queued message {id} edited by {editor}");
+        self.storage
+            .edit_queued_message(id, text, datetime, editor)
+            .await
+    }
+
+    /// Versions of the message its edits replaced, the latest first. `None`
+    /// when there is no such message.
+    pub async fn get_queued_message_history(
+        &self,
+        id: i32,
+    ) -> anyhow::Result<Option<Vec<QueuedMessageVersion>>> {
+        if self.get_queued_message(id).await?.is_none() {
+            return Ok(None);
+        }
+
+        Ok(Some(self.storage.get_queued_message_history(id).await?))
+    }
+}
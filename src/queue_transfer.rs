@@ -0,0 +1,369 @@
+//! Pending messages moved between databases, like from staging to
+//! production. Messages get new ids on the way, the import reports which
+//! and leaves out the ones that conflict with what is already there.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use anyhow::bail;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+use utoipa::ToSchema;
+
+use crate::{
+    attachment::Attachments,
+    broadcast::MessageOptions,
+    language::Translations,
+    media::{media_blob_hash, Media},
+    poll::PollDefinition,
+    queue::{QueueFilter, QueueStatus},
+    state::AppState,
+    storage::NewQueuedMessage,
+    subscriber::Audience,
+    telemetry::current_request_id,
+    topic::ChatTarget,
+    variant::MessageVariant,
+};
+
+/// The pending messages of a bot, as `GET /queue/export` returns and
+/// `POST /queue/import` takes them.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct QueueExport {
+    pub bot_id: String,
+    pub exported_at: DateTime<Utc>,
+    /// Ordered by when they are due.
+    pub messages: Vec<ExportedMessage>,
+    /// Base64 contents of the images the messages and their variants refer
+    /// to as `media:<hash>`, keyed by hash.
+    #[serde(default)]
+    pub media: BTreeMap<String, String>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ExportedMessage {
+    /// Id in the exporting database, which `reply_to` of the other messages
+    /// and the import report refer to.
+    pub id: i32,
+    /// The whole audience when `null`.
+    pub targets: Option<Vec<ChatTarget>>,
+    /// Chats the whole audience leaves out.
+    #[serde(default)]
+    pub excluded_chats: Vec<i64>,
+    pub message: String,
+    /// https URLs, `media:<hash>` references and base64 images.
+    #[serde(default)]
+    pub images: Vec<String>,
+    pub datetime: DateTime<Utc>,
+    #[serde(default)]
+    pub awaiting_approval: bool,
+    pub copy_from_chat_id: Option<i64>,
+    pub copy_message_id: Option<i32>,
+    #[serde(default)]
+    pub variants: Vec<MessageVariant>,
+    #[serde(default)]
+    pub translations: Translations,
+    #[serde(flatten)]
+    pub attachments: Attachments,
+    /// Only with postgres.
+    pub poll: Option<PollDefinition>,
+    #[serde(flatten)]
+    pub options: MessageOptions,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct QueueImportReport {
+    pub dry_run: bool,
+    pub imported: Vec<RemappedMessage>,
+    /// Messages left out, nothing was queued for them.
+    pub conflicts: Vec<QueueConflict>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct RemappedMessage {
+    /// Id in the export.
+    pub id: i32,
+    /// Id the message was queued as, `null` on a dry run.
+    pub new_id: Option<i32>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct QueueConflict {
+    /// Id in the export.
+    pub id: i32,
+    pub reason: String,
+}
+
+impl AppState {
+    /// Pending messages and messages waiting for approval, with everything
+    /// needed to queue them again elsewhere. Claimed, sent and failed
+    /// messages stay behind.
+    pub async fn export_queue(&self) -> anyhow::Result<QueueExport> {
+        let mut entries = Vec::new();
+        for status in [QueueStatus::Pending, QueueStatus::AwaitingApproval] {
+            let filter = QueueFilter {
+                status: Some(status),
+                ..Default::default()
+            };
+            entries.extend(self.get_queue(&filter, i64::MAX, 0).await?);
+        }
+        entries.sort_by_key(|entry| (entry.datetime, entry.id));
+
+        let mut messages = Vec::with_capacity(entries.len());
+        let mut media = BTreeMap::new();
+        for entry in entries {
+            let Some(message) = self.get_queued_message(entry.id).await? else {
+                continue;
+            };
+
+            // images uploaded before they were kept in `media_blob` go along
+            // as references too
+            let legacy: Vec<Media> = self
+                .get_message_images(message.id)
+                .await?
+                .into_iter()
+                .map(Media::new)
+                .collect();
+            let images: Vec<String> = message
+                .images
+                .iter()
+                .cloned()
+                .chain(legacy.iter().map(Media::reference))
+                .collect();
+            for image in &legacy {
+                media.insert(image.hash.clone(), encode(&image.data));
+            }
+            let variants = self.get_message_variants(message.id).await?;
+            let references = images
+                .iter()
+                .chain(variants.iter().flat_map(|variant| &variant.images))
+                .filter_map(|image| media_blob_hash(image));
+            for hash in references {
+                if media.contains_key(hash) {
+                    continue;
+                }
+                if let Some(data) = self.storage.get_media_blob(hash).await? {
+                    media.insert(hash.to_string(), encode(&data));
+                }
+            }
+
+            let excluded_chats = match message.all_chats {
+                true => self.storage.get_excluded_chats(message.id).await?,
+                false => Vec::new(),
+            };
+            messages.push(ExportedMessage {
+                id: message.id,
+                targets: self.get_queued_targets(&message).await?,
+                excluded_chats,
+                message: message.message.clone(),
+                images,
+                datetime: message.datetime,
+                awaiting_approval: entry.status == QueueStatus::AwaitingApproval,
+                copy_from_chat_id: message.copy_from_chat_id,
+                copy_message_id: message.copy_message_id,
+                variants,
+                translations: self.get_message_translations(message.id).await?,
+                attachments: self.storage.get_message_attachments(message.id).await?,
+                poll: self.get_queued_poll(message.id).await?,
+                options: message.options()?,
+            });
+        }
+        info!("exported {} queued messages", messages.len());
+
+        Ok(QueueExport {
+            bot_id: self.bot_id.to_string(),
+            exported_at: Utc::now(),
+            messages,
+            media,
+        })
+    }
+
+    /// Queues the messages of an export as new messages of this bot, oldest
+    /// first. `reply_to` is pointed at the new id of the replied to message,
+    /// which has to be part of the export. A message is left out when the
+    /// same text is already queued for the same time, it targets chats the
+    /// bot doesn't know, one of its images is missing or it replies to a
+    /// message that wasn't imported. A dry run only reports.
+    pub async fn import_queue(
+        &self,
+        export: QueueExport,
+        dry_run: bool,
+    ) -> anyhow::Result<QueueImportReport> {
+        info!(
+            "importing {} queued messages exported from bot {}, dry run: {dry_run}",
+            export.messages.len(),
+            export.bot_id
+        );
+
+        let mut media = HashMap::new();
+        for (hash, data) in export.media {
+            let image = Media::new(base64::engine::general_purpose::STANDARD.decode(data)?);
+            if image.hash != hash {
+                bail!("image {hash} doesn't match its contents");
+            }
+            media.insert(hash, image);
+        }
+
+        let chats: HashSet<i64> = self.get_chats().await?.iter().map(|chat| chat.id).collect();
+        let mut queued = HashMap::new();
+        for status in [QueueStatus::Pending, QueueStatus::AwaitingApproval] {
+            let filter = QueueFilter {
+                status: Some(status),
+                ..Default::default()
+            };
+            for entry in self.get_queue(&filter, i64::MAX, 0).await? {
+                queued.insert((entry.message, entry.datetime), entry.id);
+            }
+        }
+
+        let exported: HashSet<i32> = export.messages.iter().map(|message| message.id).collect();
+        let mut messages = export.messages;
+        messages.sort_by_key(|message| (message.datetime, message.id));
+
+        // new id of every exported message that is or would be queued,
+        // `None` on a dry run
+        let mut new_ids: HashMap<i32, Option<i32>> = HashMap::new();
+        let mut imported = Vec::new();
+        let mut conflicts = Vec::new();
+        for mut message in messages {
+            let id = message.id;
+            let key = (message.message.clone(), message.datetime);
+            if let Some(&existing) = queued.get(&key) {
+                new_ids.insert(id, Some(existing));
+                conflicts.push(QueueConflict {
+                    id,
+                    reason: format!("already queued as message {existing}"),
+                });
+                continue;
+            }
+            if let Some(reason) =
+                self.import_conflict(&message, &chats, &media, &exported, &new_ids)
+            {
+                conflicts.push(QueueConflict { id, reason });
+                continue;
+            }
+            message.options.reply_to = message
+                .options
+                .reply_to
+                .and_then(|reply_to| new_ids[&reply_to]);
+
+            let new_id = match dry_run {
+                true => None,
+                false => {
+                    let new_id = self.queue_exported(&message, &media).await?;
+                    queued.insert(key, new_id);
+                    Some(new_id)
+                }
+            };
+            new_ids.insert(id, new_id);
+            imported.push(RemappedMessage { id, new_id });
+        }
+
+        Ok(QueueImportReport {
+            dry_run,
+            imported,
+            conflicts,
+        })
+    }
+
+    fn import_conflict(
+        &self,
+        message: &ExportedMessage,
+        chats: &HashSet<i64>,
+        media: &HashMap<String, Media>,
+        exported: &HashSet<i32>,
+        new_ids: &HashMap<i32, Option<i32>>,
+    ) -> Option<String> {
+        let unknown: Vec<String> = message
+            .targets
+            .iter()
+            .flatten()
+            .map(|target| target.chat_id())
+            .filter(|chat_id| !chats.contains(chat_id))
+            .map(|chat_id| chat_id.to_string())
+            .collect();
+        if !unknown.is_empty() {
+            return Some(format!("unknown chats: {}", unknown.join(", ")));
+        }
+
+        if message.poll.is_some() && self.pool.is_none() {
+            return Some("polls can only be queued with postgres".to_string());
+        }
+
+        let missing = message
+            .images
+            .iter()
+            .chain(message.variants.iter().flat_map(|variant| &variant.images))
+            .filter_map(|image| media_blob_hash(image))
+            .find(|hash| !media.contains_key(*hash));
+        if let Some(hash) = missing {
+            return Some(format!("image {hash} is not in the export"));
+        }
+
+        match message.options.reply_to {
+            Some(reply_to) if !exported.contains(&reply_to) => Some(format!(
+                "replies to message {reply_to}, which is not in the export"
+            )),
+            Some(reply_to) if !new_ids.contains_key(&reply_to) => Some(format!(
+                "replies to message {reply_to}, which was not imported"
+            )),
+            _ => None,
+        }
+    }
+
+    async fn queue_exported(
+        &self,
+        message: &ExportedMessage,
+        media: &HashMap<String, Media>,
+    ) -> anyhow::Result<i32> {
+        if let Some(poll) = &message.poll {
+            let chats = match &message.targets {
+                Some(targets) => targets.iter().map(|target| target.chat_id()).collect(),
+                None if message.options.audience == Audience::Chats => {
+                    self.get_chats().await?.iter().map(|chat| chat.id).collect()
+                }
+                None => Vec::new(),
+            };
+            return self
+                .queue_poll(
+                    chats,
+                    message.message.clone(),
+                    poll.clone(),
+                    message.datetime.to_rfc3339(),
+                    message.options,
+                )
+                .await;
+        }
+
+        let media: Vec<Media> = message
+            .images
+            .iter()
+            .chain(message.variants.iter().flat_map(|variant| &variant.images))
+            .filter_map(|image| media.get(media_blob_hash(image)?))
+            .cloned()
+            .collect();
+        self.storage
+            .queue_message(NewQueuedMessage {
+                bot_id: &self.bot_id,
+                targets: message.targets.as_deref(),
+                excluded_chats: &message.excluded_chats,
+                message: &message.message,
+                images: &message.images,
+                media: &media,
+                datetime: message.datetime,
+                options: &message.options,
+                awaiting_approval: message.awaiting_approval,
+                copy_from: message.copy_from_chat_id.zip(message.copy_message_id),
+                variants: &message.variants,
+                translations: &message.translations,
+                attachments: &message.attachments,
+                request_id: current_request_id().as_deref(),
+                duplicates_since: None,
+            })
+            .await
+    }
+}
+
+fn encode(data: &[u8]) -> String {
+    base64::engine::general_purpose::STANDARD.encode(data)
+}
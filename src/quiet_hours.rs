@@ -0,0 +1,106 @@
+use std::collections::HashMap;
+
+use anyhow::{bail, Context};
+use chrono::{DateTime, FixedOffset, NaiveTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+use utoipa::ToSchema;
+
+use crate::state::AppState;
+
+// UTC-12:00 to UTC+14:00
+const MIN_UTC_OFFSET_MINUTES: i32 = -12 * 60;
+const MAX_UTC_OFFSET_MINUTES: i32 = 14 * 60;
+
+/// Time of day during which broadcasts to a chat are held back and sent once
+/// the window closes. Times are `HH:MM` in the chat's local time, a window
+/// whose end is before its start spans midnight, like `22:00` to `08:00`.
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema, sqlx::FromRow)]
+pub struct QuietHours {
+    pub start_time: String,
+    pub end_time: String,
+    /// Offset of the chat's local time from UTC, daylight saving time has to
+    /// be accounted for by updating it.
+    #[serde(default)]
+    pub utc_offset_minutes: i32,
+}
+
+impl QuietHours {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        parse_time(&self.start_time).context("bad start_time")?;
+        parse_time(&self.end_time).context("bad end_time")?;
+        validate_utc_offset(self.utc_offset_minutes)
+    }
+
+    /// When the window `now` falls into closes, `None` outside of it.
+    pub fn ends_at(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let start = parse_time(&self.start_time).ok()?;
+        let end = parse_time(&self.end_time).ok()?;
+        let offset = FixedOffset::east_opt(self.utc_offset_minutes * 60)?;
+        let local = now.with_timezone(&offset);
+        let time = local.time();
+
+        let quiet = if start <= end {
+            start <= time && time < end
+        } else {
+            time >= start || time < end
+        };
+        if !quiet {
+            return None;
+        }
+
+        // past the start of a window spanning midnight it ends tomorrow
+        let mut end_date = local.date_naive();
+        if time >= end {
+            end_date = end_date.succ_opt()?;
+        }
+        let ends_at = offset
+            .from_local_datetime(&end_date.and_time(end))
+            .single()?;
+
+        Some(ends_at.with_timezone(&Utc))
+    }
+}
+
+pub fn validate_utc_offset(minutes: i32) -> anyhow::Result<()> {
+    if !(MIN_UTC_OFFSET_MINUTES..=MAX_UTC_OFFSET_MINUTES).contains(&minutes) {
+        bail!(
+            "utc_offset_minutes must be between {MIN_UTC_OFFSET_MINUTES} and {MAX_UTC_OFFSET_MINUTES}"
+        );
+    }
+
+    Ok(())
+}
+
+fn parse_time(time: &str) -> anyhow::Result<NaiveTime> {
+    NaiveTime::parse_from_str(time, "%H:%M").with_context(|| format!("expected HH:MM: {time}"))
+}
+
+impl AppState {
+    pub async fn get_quiet_hours(&self, chat_id: i64) -> anyhow::Result<Option<QuietHours>> {
+        self.storage.get_quiet_hours(chat_id).await
+    }
+
+    /// Quiet hours of every chat of the bot that has them.
+    pub async fn get_all_quiet_hours(&self) -> anyhow::Result<HashMap<i64, QuietHours>> {
+        self.storage.get_all_quiet_hours(&self.bot_id).await
+    }
+
+    pub async fn set_quiet_hours(
+        &self,
+        chat_id: i64,
+        quiet_hours: &QuietHours,
+    ) -> anyhow::Result<()> {
+        quiet_hours.validate()?;
+        info!("setting quiet hours {quiet_hours:?} for chat:{chat_id}");
+
+        self.storage.set_quiet_hours(chat_id, quiet_hours).await
+    }
+
+    /// Returns `false` when the chat had no quiet hours.
+    pub async fn delete_quiet_hours(&self, chat_id: i64) -> anyhow::Result<bool> {
+        info!("removing the quiet hours of chat:{chat_id}");
+
+        self.storage.delete_quiet_hours(chat_id).await
+    }
+}
@@ -0,0 +1,113 @@
+use std::{
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::ConnectInfo,
+    http::{header::RETRY_AFTER, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use dashmap::DashMap;
+
+use crate::{error::ApiError, state::AppState};
+
+// buckets that refilled completely are dropped once there are this many
+const MAX_BUCKETS: usize = 10_000;
+
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+/// Token buckets keyed by client IP and API key. Every client may burst up
+/// to `burst` requests, after which tokens come back at `refill_per_sec`.
+#[derive(Clone)]
+pub struct RateLimiter {
+    buckets: Arc<DashMap<String, Bucket>>,
+    burst: f64,
+    refill_per_sec: f64,
+}
+
+impl RateLimiter {
+    pub fn new(burst: u32, refill_per_sec: f64) -> Self {
+        Self {
+            buckets: Arc::new(DashMap::new()),
+            burst: burst as f64,
+            refill_per_sec,
+        }
+    }
+
+    fn enabled(&self) -> bool {
+        self.burst > 0.0 && self.refill_per_sec > 0.0
+    }
+
+    /// Takes a token from the bucket of `key`, or returns how long the client
+    /// has to wait for the next one.
+    fn acquire(&self, key: String) -> Result<(), Duration> {
+        if self.buckets.len() > MAX_BUCKETS {
+            self.buckets
+                .retain(|_, bucket| self.refilled(bucket) < self.burst);
+        }
+
+        let now = Instant::now();
+        let mut bucket = self.buckets.entry(key).or_insert(Bucket {
+            tokens: self.burst,
+            updated_at: now,
+        });
+        bucket.tokens = self.refilled(&bucket);
+        bucket.updated_at = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - bucket.tokens) / self.refill_per_sec,
+            ))
+        }
+    }
+
+    fn refilled(&self, bucket: &Bucket) -> f64 {
+        let elapsed = bucket.updated_at.elapsed().as_secs_f64();
+        (bucket.tokens + elapsed * self.refill_per_sec).min(self.burst)
+    }
+}
+
+/// Middleware rejecting requests over the limit with `429 Too Many Requests`.
+/// The client IP and, when sent, the `X-Api-Key` both have to be within
+/// their limits.
+pub async fn rate_limit<B>(req: Request<B>, next: Next<B>) -> Response {
+    let limiter = match req.extensions().get::<AppState>() {
+        Some(state) if state.rate_limiter.enabled() => state.rate_limiter.clone(),
+        _ => return next.run(req).await,
+    };
+
+    let mut keys = Vec::with_capacity(2);
+    if let Some(ConnectInfo(addr)) = req.extensions().get::<ConnectInfo<SocketAddr>>() {
+        keys.push(format!("ip:{}", addr.ip()));
+    }
+    if let Some(key) = req
+        .headers()
+        .get("x-api-key")
+        .and_then(|key| key.to_str().ok())
+    {
+        keys.push(format!("key:{key}"));
+    }
+
+    for key in keys {
+        if let Err(wait) = limiter.acquire(key) {
+            let retry_after = wait.as_secs_f64().ceil().max(1.0) as u64;
+            let error = ApiError::new(
+                StatusCode::TOO_MANY_REQUESTS,
+                "rate_limited",
+                format!("too many requests, retry in {retry_after}s"),
+            );
+            return ([(RETRY_AFTER, retry_after.to_string())], error).into_response();
+        }
+    }
+
+    next.run(req).await
+}
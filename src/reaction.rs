@@ -0,0 +1,422 @@
+use std::time::Duration;
+
+use futures::{future, stream::BoxStream, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use teloxide::{
+    stop::StopToken,
+    types::{AllowedUpdate, Update, UpdateKind},
+    update_listeners::{polling_default, AsUpdateStream, Polling, UpdateListener},
+    Bot, RequestError,
+};
+use tracing::{error, info};
+use utoipa::ToSchema;
+
+use crate::state::{AppState, WrappedBot};
+
+/// Updates telegram only sends when asked for explicitly, teloxide has no
+/// types for them yet.
+const REACTION_UPDATES: [&str; 2] = ["message_reaction", "message_reaction_count"];
+
+/// A change of the reactions to a message, as far as it matters for
+/// counting them.
+#[derive(Debug, PartialEq)]
+pub enum ReactionUpdate {
+    /// A member changed their reactions, sent for groups the bot is an
+    /// admin of.
+    Changed {
+        chat_id: i64,
+        message_id: i32,
+        removed: Vec<String>,
+        added: Vec<String>,
+    },
+    /// New totals of the anonymous reactions, sent for channels.
+    Counts {
+        chat_id: i64,
+        message_id: i32,
+        counts: Vec<(String, i32)>,
+    },
+}
+
+#[derive(Deserialize)]
+struct RawReactionUpdate {
+    message_reaction: Option<RawReactionChanged>,
+    message_reaction_count: Option<RawReactionCounts>,
+}
+
+#[derive(Deserialize)]
+struct RawChat {
+    id: i64,
+}
+
+#[derive(Deserialize)]
+struct RawReactionChanged {
+    chat: RawChat,
+    message_id: i32,
+    old_reaction: Vec<RawReactionType>,
+    new_reaction: Vec<RawReactionType>,
+}
+
+#[derive(Deserialize)]
+struct RawReactionCounts {
+    chat: RawChat,
+    message_id: i32,
+    reactions: Vec<RawReactionCount>,
+}
+
+#[derive(Deserialize)]
+struct RawReactionCount {
+    #[serde(rename = "type")]
+    kind: RawReactionType,
+    total_count: i32,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum RawReactionType {
+    Emoji {
+        emoji: String,
+    },
+    CustomEmoji {
+        custom_emoji_id: String,
+    },
+    #[serde(other)]
+    Other,
+}
+
+impl RawReactionType {
+    /// How the reaction is counted, custom emoji by their id.
+    fn label(&self) -> Option<String> {
+        match self {
+            RawReactionType::Emoji { emoji } => Some(emoji.clone()),
+            RawReactionType::CustomEmoji { custom_emoji_id } => {
+                Some(format!("custom:{custom_emoji_id}"))
+            }
+            RawReactionType::Other => None,
+        }
+    }
+}
+
+fn labels(reactions: &[RawReactionType]) -> Vec<String> {
+    reactions
+        .iter()
+        .filter_map(RawReactionType::label)
+        .collect()
+}
+
+/// Reads a raw update, `None` for anything but reactions.
+pub fn parse_reaction_update(raw: &Value) -> Option<ReactionUpdate> {
+    let update = RawReactionUpdate::deserialize(raw).ok()?;
+    if let Some(changed) = update.message_reaction {
+        let old = labels(&changed.old_reaction);
+        let new = labels(&changed.new_reaction);
+        return Some(ReactionUpdate::Changed {
+            chat_id: changed.chat.id,
+            message_id: changed.message_id,
+            removed: old.iter().filter(|r| !new.contains(r)).cloned().collect(),
+            added: new.iter().filter(|r| !old.contains(r)).cloned().collect(),
+        });
+    }
+
+    let counts = update.message_reaction_count?;
+    Some(ReactionUpdate::Counts {
+        chat_id: counts.chat.id,
+        message_id: counts.message_id,
+        counts: counts
+            .reactions
+            .iter()
+            .filter_map(|count| Some((count.kind.label()?, count.total_count)))
+            .collect(),
+    })
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ReactionTotal {
+    /// The emoji, or `custom:` followed by the id of a custom emoji.
+    reaction: String,
+    count: i64,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct VariantReactions {
+    variant: i32,
+    /// Chats the variant landed in.
+    messages: i64,
+    reactions: i64,
+}
+
+/// How a broadcast was received. Telegram tells bots nothing about views, so
+/// reactions are all there is.
+#[derive(Serialize, ToSchema)]
+pub struct BroadcastStats {
+    id: i32,
+    /// Chats the broadcast landed in.
+    messages: i64,
+    total_reactions: i64,
+    /// Most frequent first.
+    reactions: Vec<ReactionTotal>,
+    /// Empty for broadcasts without A/B variants.
+    variants: Vec<VariantReactions>,
+}
+
+/// Long polling that also asks telegram for reaction updates. They arrive
+/// unparsed and are recorded here instead of reaching the dispatcher, which
+/// would log them as broken updates.
+pub struct ReactionPolling {
+    polling: Polling<WrappedBot>,
+    state: AppState,
+    allowed_updates: Vec<String>,
+}
+
+impl ReactionPolling {
+    pub async fn new(state: AppState) -> Self {
+        Self {
+            polling: polling_default(state.bot.clone()).await,
+            state,
+            allowed_updates: Vec::new(),
+        }
+    }
+}
+
+impl UpdateListener for ReactionPolling {
+    type Err = RequestError;
+
+    fn stop_token(&mut self) -> StopToken {
+        self.polling.stop_token()
+    }
+
+    // the polling isn't given the hint, its list would replace the one
+    // including reactions
+    fn hint_allowed_updates(&mut self, hint: &mut dyn Iterator<Item = AllowedUpdate>) {
+        self.allowed_updates = hint
+            .filter_map(|update| serde_json::to_value(update).ok())
+            .filter_map(|update| update.as_str().map(str::to_string))
+            .chain(REACTION_UPDATES.map(str::to_string))
+            .collect();
+    }
+
+    fn timeout_hint(&self) -> Option<Duration> {
+        self.polling.timeout_hint()
+    }
+}
+
+impl<'a> AsUpdateStream<'a> for ReactionPolling {
+    type StreamErr = RequestError;
+    type Stream = BoxStream<'a, Result<Update, RequestError>>;
+
+    fn as_stream(&'a mut self) -> Self::Stream {
+        let allowed_updates = std::mem::take(&mut self.allowed_updates);
+        let bot = self.state.bot.inner().clone();
+        let allow = async move {
+            if let Err(err) = allow_updates(&bot, &allowed_updates).await {
+                error!("failed to ask telegram for reaction updates: {err}");
+            }
+            None::<Result<Update, RequestError>>
+        };
+
+        let state = self.state.clone();
+        let updates = self.polling.as_stream().filter_map(move |update| {
+            let state = state.clone();
+            async move {
+                let Ok(Update {
+                    kind: UpdateKind::Error(raw),
+                    ..
+                }) = &update
+                else {
+                    return Some(update);
+                };
+                let Some(reaction) = parse_reaction_update(raw) else {
+                    return Some(update);
+                };
+                if let Err(err) = state.record_reaction(&reaction).await {
+                    error!("failed to record reaction {reaction:?}: {err}");
+                }
+                None
+            }
+        });
+
+        futures::stream::once(allow)
+            .filter_map(future::ready)
+            .chain(updates)
+            .boxed()
+    }
+}
+
+/// Sets the updates telegram sends to the next `getUpdates` calls. No offset
+/// is given, so no pending update is confirmed.
+async fn allow_updates(bot: &Bot, allowed_updates: &[String]) -> anyhow::Result<()> {
+    info!("asking telegram for updates: {allowed_updates:?}");
+    let url = bot
+        .api_url()
+        .join(&format!("/bot{}/getUpdates", bot.token()))?;
+    bot.client()
+        .post(url)
+        .json(&serde_json::json!({
+            "allowed_updates": allowed_updates,
+            "limit": 1,
+            "timeout": 0,
+        }))
+        .send()
+        .await?
+        .error_for_status()?;
+
+    Ok(())
+}
+
+impl AppState {
+    /// Keeps the reaction counts of broadcasts, reactions to other messages
+    /// are ignored. Only kept with postgres.
+    pub async fn record_reaction(&self, reaction: &ReactionUpdate) -> anyhow::Result<()> {
+        let Some(pool) = &self.pool else {
+            return Ok(());
+        };
+        let (ReactionUpdate::Changed {
+            chat_id,
+            message_id,
+            ..
+        }
+        | ReactionUpdate::Counts {
+            chat_id,
+            message_id,
+            ..
+        }) = *reaction;
+
+        let broadcast = sqlx::query_scalar!(
+            r#"
+SELECT EXISTS (
+    SELECT 1 FROM sent_message
+    WHERE chat_id = $1 AND telegram_message_id = $2
+) AS "exists!"
+            "#,
+            chat_id,
+            message_id
+        )
+        .fetch_one(pool)
+        .await?;
+        if !broadcast {
+            return Ok(());
+        }
+
+        let mut tx = pool.begin().await?;
+        match reaction {
+            ReactionUpdate::Changed { removed, added, .. } => {
+                sqlx::query!(
+                    r#"
+UPDATE message_reaction
+SET count = greatest(count - 1, 0), updated_at = now()
+WHERE chat_id = $1 AND message_id = $2 AND reaction = ANY($3)
+                    "#,
+                    chat_id,
+                    message_id,
+                    removed
+                )
+                .execute(&mut tx)
+                .await?;
+                sqlx::query!(
+                    r#"
+INSERT INTO message_reaction ( chat_id, message_id, reaction, count )
+SELECT $1, $2, reaction, 1 FROM unnest($3::TEXT[]) AS reaction
+ON CONFLICT ( chat_id, message_id, reaction ) DO UPDATE
+SET count = message_reaction.count + 1, updated_at = now()
+                    "#,
+                    chat_id,
+                    message_id,
+                    added
+                )
+                .execute(&mut tx)
+                .await?;
+            }
+            ReactionUpdate::Counts { counts, .. } => {
+                let (reactions, counts): (Vec<String>, Vec<i32>) = counts.iter().cloned().unzip();
+                sqlx::query!(
+                    r#"
+DELETE FROM message_reaction
+WHERE chat_id = $1 AND message_id = $2
+                    "#,
+                    chat_id,
+                    message_id
+                )
+                .execute(&mut tx)
+                .await?;
+                sqlx::query!(
+                    r#"
+INSERT INTO message_reaction ( chat_id, message_id, reaction, count )
+SELECT $1, $2, * FROM unnest($3::TEXT[], $4::INT[])
+                    "#,
+                    chat_id,
+                    message_id,
+                    &reactions,
+                    &counts
+                )
+                .execute(&mut tx)
+                .await?;
+            }
+        }
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// `None` for unknown messages and those of other bots.
+    pub async fn get_broadcast_stats(
+        &self,
+        queue_id: i32,
+    ) -> anyhow::Result<Option<BroadcastStats>> {
+        let pool = self.pg()?;
+        if self.get_queued_message(queue_id).await?.is_none() {
+            return Ok(None);
+        }
+
+        let messages = sqlx::query_scalar!(
+            r#"
+SELECT count(*) AS "count!" FROM sent_message
+WHERE queue_id = $1 AND telegram_message_id IS NOT NULL
+            "#,
+            queue_id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        let reactions = sqlx::query_as!(
+            ReactionTotal,
+            r#"
+SELECT r.reaction, sum(r.count)::BIGINT AS "count!"
+FROM message_reaction r
+JOIN sent_message s ON s.chat_id = r.chat_id AND s.telegram_message_id = r.message_id
+WHERE s.queue_id = $1 AND r.count > 0
+GROUP BY r.reaction
+ORDER BY 2 DESC, r.reaction
+            "#,
+            queue_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        let variants = sqlx::query_as!(
+            VariantReactions,
+            r#"
+SELECT
+    a.variant,
+    count(DISTINCT s.id) AS "messages!",
+    coalesce(sum(r.count), 0)::BIGINT AS "reactions!"
+FROM variant_assignment a
+LEFT JOIN sent_message s ON s.queue_id = a.message_id AND s.chat_id = a.chat_id AND s.telegram_message_id IS NOT NULL
+LEFT JOIN message_reaction r ON r.chat_id = s.chat_id AND r.message_id = s.telegram_message_id
+WHERE a.message_id = $1
+GROUP BY a.variant
+ORDER BY a.variant
+            "#,
+            queue_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(Some(BroadcastStats {
+            id: queue_id,
+            messages,
+            total_reactions: reactions.iter().map(|reaction| reaction.count).sum(),
+            reactions,
+            variants,
+        }))
+    }
+}
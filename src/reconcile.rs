@@ -0,0 +1,179 @@
+//! What the process was doing when it stopped. Cleanups record themselves
+//! in the storage while they run and broadcasts are claimed, both are found
+//! again at startup and either resumed or left for an admin to resume.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::{error, info, warn};
+use utoipa::ToSchema;
+
+use crate::{
+    queue::{QueueFilter, QueueStatus},
+    state::{AppState, ChatCleaningStatus},
+    telemetry::spawn_in_request,
+};
+
+/// A cleanup that started and didn't finish.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, ToSchema, sqlx::FromRow)]
+pub struct InterruptedCleanup {
+    pub chat_id: i64,
+    /// A staged cleanup, restricting the members instead of kicking them.
+    pub staged: bool,
+    pub initiator: String,
+    pub started_at: DateTime<Utc>,
+    /// The last member the cleanup got to, members are kicked in the order
+    /// of their ids and going on skips everyone up to this one.
+    pub last_user_id: Option<i64>,
+    /// Set when the cleanup paused itself, for its time box or a long flood
+    /// wait, and goes on by itself at this time.
+    pub resume_at: Option<DateTime<Utc>>,
+}
+
+/// Work found unfinished at startup and not resumed yet.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Interrupted {
+    pub cleanups: Vec<InterruptedCleanup>,
+    /// Ids of queued messages still claimed.
+    pub broadcasts: Vec<i32>,
+}
+
+impl AppState {
+    /// Runs a cleanup with a record of it in the storage, which is only
+    /// dropped once the cleanup returns, failed or not, unless it paused.
+    pub(crate) async fn record_cleanup_run(
+        &self,
+        chat_id: i64,
+        staged: bool,
+        initiator: &str,
+        run: impl std::future::Future<Output = anyhow::Result<()>>,
+    ) -> anyhow::Result<()> {
+        self.storage
+            .start_cleanup_run(chat_id, staged, initiator)
+            .await?;
+        let result = run.await;
+        // a cleanup that paused itself goes on from its record later
+        let paused = matches!(
+            self.chats_status.get(&chat_id).as_deref(),
+            Some(ChatCleaningStatus::Resumable(_))
+        );
+        if paused {
+            return result;
+        }
+        if let Err(err) = self.storage.finish_cleanup_run(chat_id).await {
+            error!("failed to record the end of the cleanup of chat:{chat_id}: {err}");
+        }
+
+        result
+    }
+
+    /// Looks for what the process was doing when it stopped, once at
+    /// startup after [`AppState::fill_status_list`]. Chats whose cleanup was
+    /// running or paused are marked `Resumable`, paused ones go on by
+    /// themselves at their time, and broadcasts still claimed are
+    /// remembered. With `resume_interrupted` both are resumed right away,
+    /// otherwise they wait for [`AppState::resume_cleanup`] and
+    /// [`AppState::resume_broadcast`], broadcasts at most until their claim
+    /// expires after an hour.
+    pub async fn reconcile(&self) -> anyhow::Result<()> {
+        let runs = self.storage.get_cleanup_runs(&self.bot_id).await?;
+        for run in runs {
+            let chat_id = run.chat_id;
+            // archived since, nothing left to resume
+            if !self.chats_status.contains_key(&chat_id) {
+                self.storage.finish_cleanup_run(chat_id).await?;
+                continue;
+            }
+            match run.resume_at {
+                Some(resume_at) => {
+                    info!("the cleanup of chat:{chat_id} is paused until {resume_at}");
+                    self.resume_cleanup_at(chat_id, &run.initiator, resume_at);
+                }
+                None => warn!(
+                    "the cleanup of chat:{chat_id} by {} started at {} was interrupted",
+                    run.initiator, run.started_at
+                ),
+            }
+            self.set_chat_status(chat_id, ChatCleaningStatus::Resumable(run))?;
+        }
+
+        let filter = QueueFilter {
+            status: Some(QueueStatus::Processing),
+            ..Default::default()
+        };
+        for entry in self.get_queue(&filter, i64::MAX, 0).await? {
+            warn!(
+                "queued message {} is claimed since before startup",
+                entry.id
+            );
+            self.interrupted_broadcasts.insert(entry.id);
+        }
+
+        if !self.config.resume_interrupted {
+            return Ok(());
+        }
+        for cleanup in self.get_interrupted().cleanups {
+            // paused ones go on by themselves once their time comes
+            if cleanup.resume_at.is_some() {
+                continue;
+            }
+            self.resume_cleanup(cleanup.chat_id, &cleanup.initiator)?;
+        }
+        let broadcasts: Vec<_> = self.interrupted_broadcasts.iter().map(|id| *id).collect();
+        for id in broadcasts {
+            self.resume_broadcast(id).await?;
+        }
+
+        Ok(())
+    }
+
+    pub fn get_interrupted(&self) -> Interrupted {
+        let mut cleanups: Vec<_> = self
+            .chats_status
+            .iter()
+            .filter_map(|status| match status.value() {
+                ChatCleaningStatus::Resumable(cleanup) => Some(cleanup.clone()),
+                _ => None,
+            })
+            .collect();
+        cleanups.sort_by_key(|cleanup| (cleanup.started_at, cleanup.chat_id));
+        let mut broadcasts: Vec<_> = self.interrupted_broadcasts.iter().map(|id| *id).collect();
+        broadcasts.sort();
+
+        Interrupted {
+            cleanups,
+            broadcasts,
+        }
+    }
+
+    /// Queues the interrupted cleanup of the chat again, staged or not like
+    /// it was, in the background. Returns `false` when the chat has none.
+    pub fn resume_cleanup(&self, chat_id: i64, initiator: &str) -> anyhow::Result<bool> {
+        let staged = match self.chats_status.get(&chat_id).as_deref() {
+            Some(ChatCleaningStatus::Resumable(cleanup)) => cleanup.staged,
+            _ => return Ok(false),
+        };
+        if !self.queue_cleanup(chat_id)? {
+            return Ok(false);
+        }
+        info!("resuming the cleanup of chat:{chat_id} for {initiator}");
+
+        let state = self.clone();
+        let initiator = initiator.to_string();
+        spawn_in_request(async move { state.clear_chats(vec![chat_id], &initiator, staged).await });
+
+        Ok(true)
+    }
+
+    /// Releases the claim of a broadcast found at startup so the queue sends
+    /// it to the chats it didn't reach yet. Returns `false` when it isn't
+    /// one of those.
+    pub async fn resume_broadcast(&self, id: i32) -> anyhow::Result<bool> {
+        if self.interrupted_broadcasts.remove(&id).is_none() {
+            return Ok(false);
+        }
+        info!("resuming queued message {id}");
+        self.storage.defer_queued_message(id, Utc::now()).await?;
+
+        Ok(true)
+    }
+}
@@ -0,0 +1,36 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tracing::info;
+use utoipa::ToSchema;
+
+use crate::state::AppState;
+
+/// A change of a chat's title, the old name was in use until `renamed_at`.
+#[derive(Serialize, ToSchema, sqlx::FromRow)]
+pub struct ChatRename {
+    pub old_name: String,
+    pub new_name: String,
+    pub renamed_at: DateTime<Utc>,
+}
+
+impl AppState {
+    /// Stores the new title of the chat and records the previous one.
+    /// Returns `false` when the chat is unknown or already had that name.
+    pub async fn rename_chat(&self, chat_id: i64, name: &str) -> anyhow::Result<bool> {
+        let renamed = self
+            .storage
+            .rename_chat(&self.bot_id, chat_id, name)
+            .await?;
+        if renamed {
+            self.forget_chat(chat_id);
+            info!("chat:{chat_id} was renamed to {name:?}");
+        }
+
+        Ok(renamed)
+    }
+
+    /// Renames of the chat, newest first.
+    pub async fn get_chat_renames(&self, chat_id: i64) -> anyhow::Result<Vec<ChatRename>> {
+        self.storage.get_chat_renames(chat_id).await
+    }
+}
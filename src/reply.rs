@@ -0,0 +1,110 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use teloxide::types::Message;
+use tracing::info;
+use utoipa::ToSchema;
+
+use crate::state::AppState;
+
+/// A reply to a broadcast that collects them.
+#[derive(Serialize, ToSchema, sqlx::FromRow)]
+pub struct BroadcastReply {
+    pub id: i64,
+    pub chat_id: i64,
+    /// Telegram message id of the reply.
+    pub message_id: i32,
+    /// Missing for replies sent on behalf of a channel or anonymous admins.
+    pub user_id: Option<i64>,
+    pub username: Option<String>,
+    pub name: String,
+    /// The text or caption, missing for replies without either.
+    pub text: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub edited_at: Option<DateTime<Utc>>,
+}
+
+impl AppState {
+    /// Keeps the message when it replies to a broadcast sent with
+    /// `collect_replies`, an edited reply replaces its text. Returns whether
+    /// it was kept. Only with postgres, which keeps the sent messages.
+    pub async fn record_reply(&self, message: &Message) -> anyhow::Result<bool> {
+        let (Some(pool), Some(replied)) = (&self.pool, message.reply_to_message()) else {
+            return Ok(false);
+        };
+        let (user_id, username, name) = match message.from() {
+            Some(user) => (
+                Some(user.id.0 as i64),
+                user.username.clone(),
+                user.full_name(),
+            ),
+            None => (
+                None,
+                None,
+                message
+                    .sender_chat()
+                    .and_then(|chat| chat.title())
+                    .unwrap_or_default()
+                    .to_string(),
+            ),
+        };
+
+        let result = sqlx::query!(
+            r#"
+INSERT INTO broadcast_reply ( queue_id, chat_id, message_id, user_id, username, name, text )
+SELECT s.queue_id, $1, $2, $3, $4, $5, $6
+FROM sent_message s
+JOIN message_queue q ON q.id = s.queue_id
+WHERE s.chat_id = $1 AND ( s.telegram_message_id = $7 OR $7 = ANY(s.media_message_ids) )
+AND q.collect_replies
+LIMIT 1
+ON CONFLICT (chat_id, message_id) DO UPDATE
+SET text = EXCLUDED.text, edited_at = now()
+            "#,
+            message.chat.id.0,
+            message.id.0,
+            user_id,
+            username,
+            name,
+            message.text().or(message.caption()),
+            replied.id.0
+        )
+        .execute(pool)
+        .await?;
+
+        let kept = result.rows_affected() > 0;
+        if kept {
+            info!(
+                "kept message {} of chat:{} replying to a broadcast",
+                message.id, message.chat.id
+            );
+        }
+
+        Ok(kept)
+    }
+
+    /// Oldest first.
+    pub async fn get_broadcast_replies(
+        &self,
+        queue_id: i32,
+        limit: i64,
+        offset: i64,
+    ) -> anyhow::Result<Vec<BroadcastReply>> {
+        let replies = sqlx::query_as!(
+            BroadcastReply,
+            r#"
+SELECT id, chat_id, message_id, user_id, username, name, text, created_at, edited_at
+FROM broadcast_reply
+WHERE queue_id = $1
+ORDER BY id
+LIMIT $2 OFFSET $3
+            "#,
+            queue_id,
+            limit,
+            offset
+        )
+        .fetch_all(self.pg()?)
+        .await?;
+
+        Ok(replies)
+    }
+}
@@ -0,0 +1,178 @@
+use std::{fmt::Write, time::Duration};
+
+use chrono::{DateTime, Datelike, NaiveTime, TimeZone, Utc, Weekday};
+use serde::Serialize;
+use teloxide::{
+    payloads::SendMessageSetters,
+    requests::Requester,
+    types::{ChatId, ParseMode},
+    utils::html,
+};
+use tracing::{error, info};
+use utoipa::ToSchema;
+
+use crate::state::AppState;
+
+/// Errors listed in a report at most.
+const TOP_ERRORS: i64 = 5;
+/// How often the loop looks whether a report is due.
+pub const CHECK_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// What happened to the chats and broadcasts of a bot in a week, as counted
+/// by the storage.
+#[derive(Debug, Default)]
+pub struct ReportCounts {
+    pub chats: i64,
+    pub chats_added: i64,
+    pub chats_lost: i64,
+    pub members_purged: i64,
+    pub broadcasts_sent: i64,
+    pub deliveries: i64,
+    pub failed_deliveries: i64,
+    /// Most frequent first.
+    pub top_errors: Vec<ErrorCount>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ErrorCount {
+    pub error: String,
+    pub count: i64,
+}
+
+/// Summary of the past week sent to the alerts chat.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct WeeklyReport {
+    pub bot_id: String,
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    /// Chats tracked now.
+    pub chats: i64,
+    /// Chats the bot was added to.
+    pub chats_added: i64,
+    /// Chats the bot was removed from and that weren't restored since.
+    pub chats_lost: i64,
+    /// Members kicked by cleanups and purges.
+    pub members_purged: i64,
+    /// Broadcasts that finished.
+    pub broadcasts_sent: i64,
+    /// Chats broadcasts were delivered to or failed in.
+    pub deliveries: i64,
+    pub failed_deliveries: i64,
+    /// Share of the deliveries that failed, 0 without any.
+    pub failure_rate: f64,
+    /// The most frequent errors of the failed deliveries.
+    pub top_errors: Vec<ErrorCount>,
+}
+
+impl WeeklyReport {
+    /// The report as an HTML telegram message.
+    pub fn to_html(&self) -> String {
+        let mut text = format!(
+            "<b>Weekly report of bot {}</b>\n{} to {}\n\n",
+            html::escape(&self.bot_id),
+            self.from.format("%Y-%m-%d"),
+            self.to.format("%Y-%m-%d")
+        );
+        let _ = writeln!(
+            text,
+            "Chats: {} (+{}, -{})",
+            self.chats, self.chats_added, self.chats_lost
+        );
+        let _ = writeln!(text, "Members purged: {}", self.members_purged);
+        let _ = writeln!(text, "Broadcasts sent: {}", self.broadcasts_sent);
+        let _ = writeln!(
+            text,
+            "Deliveries: {}, failed: {} ({:.1}%)",
+            self.deliveries,
+            self.failed_deliveries,
+            self.failure_rate * 100.0
+        );
+        if !self.top_errors.is_empty() {
+            text.push_str("\n<b>Top errors</b>\n");
+            for error in &self.top_errors {
+                let _ = writeln!(text, "{}× {}", error.count, html::escape(&error.error));
+            }
+        }
+
+        text
+    }
+}
+
+impl AppState {
+    /// The report of the 7 days before `to`.
+    pub async fn weekly_report(&self, to: DateTime<Utc>) -> anyhow::Result<WeeklyReport> {
+        let from = to - chrono::Duration::days(7);
+        let counts = self
+            .storage
+            .get_report_counts(&self.bot_id, from, to, TOP_ERRORS)
+            .await?;
+        let failure_rate = match counts.deliveries {
+            0 => 0.0,
+            deliveries => counts.failed_deliveries as f64 / deliveries as f64,
+        };
+
+        Ok(WeeklyReport {
+            bot_id: self.bot_id.to_string(),
+            from,
+            to,
+            chats: counts.chats,
+            chats_added: counts.chats_added,
+            chats_lost: counts.chats_lost,
+            members_purged: counts.members_purged,
+            broadcasts_sent: counts.broadcasts_sent,
+            deliveries: counts.deliveries,
+            failed_deliveries: counts.failed_deliveries,
+            failure_rate,
+            top_errors: counts.top_errors,
+        })
+    }
+
+    /// Sends the weekly report to the alerts chat once its day and hour
+    /// came and none was sent for it yet. A report switched on for the
+    /// first time goes out at the next check.
+    pub async fn weekly_reports(state: Self) -> anyhow::Result<()> {
+        loop {
+            state.heartbeat("weekly_report");
+            if let Err(err) = state.send_weekly_report_if_due(Utc::now()).await {
+                error!("failed to send the weekly report: {err}");
+            }
+            tokio::time::sleep(CHECK_INTERVAL).await;
+        }
+    }
+
+    /// Returns whether the report was sent.
+    pub async fn send_weekly_report_if_due(&self, now: DateTime<Utc>) -> anyhow::Result<bool> {
+        let config = &self.config.alerts;
+        let (true, Some(chat_id)) = (config.weekly_report, config.chat_id) else {
+            return Ok(false);
+        };
+        let due = last_slot(now, config.weekly_report_day, config.weekly_report_hour);
+        let last_sent = self.storage.get_weekly_report_at(&self.bot_id).await?;
+        if last_sent.is_some_and(|sent| sent >= due) {
+            return Ok(false);
+        }
+
+        let report = self.weekly_report(now).await?;
+        self.bot
+            .send_message(ChatId(chat_id), report.to_html())
+            .parse_mode(ParseMode::Html)
+            .await?;
+        self.storage.set_weekly_report_at(&self.bot_id, now).await?;
+        info!("sent the weekly report to chat:{chat_id}");
+
+        Ok(true)
+    }
+}
+
+/// The latest time at or before `now` on the weekday at the hour.
+pub fn last_slot(now: DateTime<Utc>, weekday: Weekday, hour: u32) -> DateTime<Utc> {
+    let days_back = (7 + now.weekday().num_days_from_monday() - weekday.num_days_from_monday()) % 7;
+    let time = NaiveTime::from_hms_opt(hour, 0, 0).unwrap_or_default();
+    let day = now.date_naive() - chrono::Duration::days(days_back.into());
+    let slot = Utc.from_utc_datetime(&day.and_time(time));
+    if slot > now {
+        slot - chrono::Duration::days(7)
+    } else {
+        slot
+    }
+}
@@ -0,0 +1,156 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use teloxide::{
+    requests::Requester,
+    types::{ChatId, UserId},
+};
+use tracing::{info, instrument};
+use utoipa::ToSchema;
+
+use crate::{
+    admin::ChatAdmin,
+    state::{AppState, ChatCleaningStatus},
+    telegram_error::{member_failed, TelegramErrorClass},
+};
+
+/// Live counters of a running member resync, updated after every member.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ResyncProgress {
+    pub total: usize,
+    pub processed: usize,
+    /// Members still in the chat, their names refreshed.
+    pub updated: usize,
+    /// Members that left, were removed or became administrators since they
+    /// were stored.
+    pub removed: usize,
+    /// Members telegram couldn't be asked about, they are kept as they were.
+    pub failed: usize,
+    pub started_at: DateTime<Utc>,
+}
+
+impl ResyncProgress {
+    fn new(total: usize) -> Self {
+        Self {
+            total,
+            processed: 0,
+            updated: 0,
+            removed: 0,
+            failed: 0,
+            started_at: Utc::now(),
+        }
+    }
+}
+
+impl AppState {
+    /// Marks the chat as `Resyncing` unless a cleanup or another resync is
+    /// queued or running, both go through the member list.
+    pub fn start_resync(&self, chat_id: i64) -> anyhow::Result<bool> {
+        let mut status = self
+            .chats_status
+            .get_mut(&chat_id)
+            .context("chat not found")?;
+        match status.value() {
+            ChatCleaningStatus::Idle
+            | ChatCleaningStatus::Error(_)
+            | ChatCleaningStatus::Cancelled(_)
+            | ChatCleaningStatus::Resumable(_) => {
+                *status.value_mut() = ChatCleaningStatus::Resyncing(ResyncProgress::new(0));
+                self.share_status(chat_id, Some(status.value()));
+                Ok(true)
+            }
+            ChatCleaningStatus::Queued
+            | ChatCleaningStatus::InProgress(_)
+            | ChatCleaningStatus::Resyncing(_) => Ok(false),
+        }
+    }
+
+    /// Asks telegram about every stored member of a chat marked by
+    /// [`AppState::start_resync`]: members that left are removed, the names
+    /// of the rest refreshed and those promoted since moved to the admins.
+    /// The status goes back to `Idle` when done, or `Cancelled` when
+    /// [`AppState::cancel_cleanup`] stopped it.
+    #[instrument(skip_all, fields(chat_id))]
+    pub async fn resync_members(&self, chat_id: i64) -> anyhow::Result<()> {
+        let result = self.resync_members_inner(chat_id).await;
+        if let Err(err) = &result {
+            self.set_chat_status(chat_id, ChatCleaningStatus::Error(err.to_string()))?;
+        }
+
+        result
+    }
+
+    async fn resync_members_inner(&self, chat_id: i64) -> anyhow::Result<()> {
+        let members = self.get_all_members(chat_id).await?;
+        info!("resyncing {} members of chat:{chat_id}", members.len());
+
+        let mut progress = ResyncProgress::new(members.len());
+        self.set_chat_status(chat_id, ChatCleaningStatus::Resyncing(progress.clone()))?;
+
+        for user in members {
+            if self.cancelled_cleanups.remove(&chat_id).is_some() {
+                info!(
+                    "resync of chat:{chat_id} cancelled after {} members",
+                    progress.processed
+                );
+                self.set_chat_status(chat_id, ChatCleaningStatus::Cancelled(progress.processed))?;
+                return Ok(());
+            }
+
+            let result = loop {
+                let result = self
+                    .bot
+                    .get_chat_member(ChatId(chat_id), UserId(user.id as u64))
+                    .await;
+                match result.as_ref().map_err(TelegramErrorClass::of) {
+                    Err(TelegramErrorClass::RateLimited(wait)) => {
+                        info!("resync of chat:{chat_id} is rate limited, waiting {wait:?}");
+                        tokio::time::sleep(wait).await;
+                    }
+                    _ => break result,
+                }
+            };
+            if let Ok(member) = &result {
+                self.cache_member_kind(chat_id, member);
+            }
+            match result {
+                Ok(member) if !member.kind.is_present() => {
+                    self.remove_chat_member_by_id(chat_id, user.id).await?;
+                    progress.removed += 1;
+                }
+                Ok(member) => {
+                    if let Some(admin) = ChatAdmin::from_member(&member) {
+                        self.upsert_chat_admin(chat_id, &admin).await?;
+                        self.remove_chat_member_by_id(chat_id, user.id).await?;
+                        progress.removed += 1;
+                    } else {
+                        self.upsert_chat_member(chat_id, &member.user).await?;
+                        progress.updated += 1;
+                    }
+                }
+                // the account is gone from telegram altogether
+                Err(err) if TelegramErrorClass::of(&err) == TelegramErrorClass::UserGone => {
+                    self.remove_chat_member_by_id(chat_id, user.id).await?;
+                    progress.removed += 1;
+                }
+                Err(err) => {
+                    member_failed(err, "resync", chat_id, user.id)?;
+                    progress.failed += 1;
+                }
+            }
+            progress.processed += 1;
+            self.set_chat_status(chat_id, ChatCleaningStatus::Resyncing(progress.clone()))?;
+        }
+
+        // a cancel that arrived after the last member has nothing left to stop
+        self.cancelled_cleanups.remove(&chat_id);
+        info!(
+            "resynced chat:{chat_id}, {} updated, {} removed, {} failed",
+            progress.updated, progress.removed, progress.failed
+        );
+        self.set_chat_status(chat_id, ChatCleaningStatus::Idle)?;
+        self.cleanup_records.entry(chat_id).or_default().last_resync = Some(progress);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,205 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, Context};
+use chrono::{DateTime, Utc};
+use cron::Schedule;
+use serde::Serialize;
+use tracing::{error, info};
+use utoipa::ToSchema;
+
+use crate::state::{AppState, ChatCleaningStatus};
+
+#[derive(Clone, Serialize, ToSchema)]
+pub struct CleanupSchedule {
+    pub cron: String,
+    pub next_run_at: DateTime<Utc>,
+    pub last_run_at: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+}
+
+/// Parses a cron expression. The classic five field form is accepted as
+/// well and runs at second zero.
+pub fn parse_cron(expression: &str) -> anyhow::Result<Schedule> {
+    let expression = expression.trim();
+    let expression = if expression.split_whitespace().count() == 5 {
+        format!("0 {expression}")
+    } else {
+        expression.to_string()
+    };
+
+    Schedule::from_str(&expression).map_err(|err| anyhow!("bad cron expression: {err}"))
+}
+
+fn next_run(schedule: &Schedule) -> anyhow::Result<DateTime<Utc>> {
+    schedule
+        .upcoming(Utc)
+        .next()
+        .context("cron expression never fires")
+}
+
+impl AppState {
+    pub async fn set_cleanup_schedule(
+        &self,
+        chat_id: i64,
+        cron: &str,
+    ) -> anyhow::Result<CleanupSchedule> {
+        let next_run_at = next_run(&parse_cron(cron)?)?;
+        info!("scheduling cleanup of chat:{chat_id} on {cron}, next at {next_run_at}");
+
+        let schedule = sqlx::query_as!(
+            CleanupSchedule,
+            r#"
+INSERT INTO cleanup_schedule ( chat_id, cron, next_run_at )
+VALUES ( $1, $2, $3 )
+ON CONFLICT (chat_id) DO UPDATE
+SET cron = $2, next_run_at = $3
+RETURNING cron, next_run_at, last_run_at, last_error
+            "#,
+            chat_id,
+            cron,
+            next_run_at
+        )
+        .fetch_one(self.pg()?)
+        .await?;
+
+        Ok(schedule)
+    }
+
+    /// Returns `false` when the chat had no schedule.
+    pub async fn delete_cleanup_schedule(&self, chat_id: i64) -> anyhow::Result<bool> {
+        let result = sqlx::query!(
+            r#"
+DELETE FROM cleanup_schedule
+WHERE chat_id = $1
+            "#,
+            chat_id
+        )
+        .execute(self.pg()?)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn get_cleanup_schedule(
+        &self,
+        chat_id: i64,
+    ) -> anyhow::Result<Option<CleanupSchedule>> {
+        let schedule = sqlx::query_as!(
+            CleanupSchedule,
+            r#"
+SELECT cron, next_run_at, last_run_at, last_error FROM cleanup_schedule
+WHERE chat_id = $1
+            "#,
+            chat_id
+        )
+        .fetch_optional(self.pg()?)
+        .await?;
+
+        Ok(schedule)
+    }
+
+    pub async fn scheduled_cleanups(state: Self) -> anyhow::Result<()> {
+        loop {
+            state.heartbeat("scheduled_cleanups");
+            match state.scheduled_cleanups_loop().await {
+                Ok(_) => {
+                    info!("ran scheduled cleanups!");
+                }
+                Err(err) => {
+                    error!("failed to run scheduled cleanups: {err}");
+                }
+            }
+            state
+                .sleep_interval(state.settings().intervals.scheduled_cleanups())
+                .await;
+        }
+    }
+
+    async fn scheduled_cleanups_loop(&self) -> anyhow::Result<()> {
+        if self.in_maintenance().await? {
+            info!("in maintenance, leaving scheduled cleanups for later");
+            return Ok(());
+        }
+
+        while let Some(chat_id) = self.claim_due_cleanup().await? {
+            let error = match self.run_scheduled_cleanup(chat_id).await {
+                Ok(()) => None,
+                Err(err) => {
+                    error!("scheduled cleanup of chat:{chat_id} failed: {err}");
+                    Some(err.to_string())
+                }
+            };
+
+            sqlx::query!(
+                r#"
+UPDATE cleanup_schedule
+SET last_run_at = now(), last_error = $2
+WHERE chat_id = $1
+                "#,
+                chat_id,
+                error
+            )
+            .execute(self.pg()?)
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Picks a due schedule and moves it to its next occurrence in the same
+    /// transaction, so only one instance runs each cleanup.
+    async fn claim_due_cleanup(&self) -> anyhow::Result<Option<i64>> {
+        // schedules can only be set with postgres
+        let Some(pool) = &self.pool else {
+            return Ok(None);
+        };
+        let mut tx = pool.begin().await?;
+
+        let Some(due) = sqlx::query!(
+            r#"
+SELECT chat_id, cron FROM cleanup_schedule
+WHERE next_run_at <= now()
+AND chat_id IN ( SELECT id FROM tg_chat WHERE bot_id = $1 )
+ORDER BY next_run_at
+LIMIT 1
+FOR UPDATE SKIP LOCKED
+            "#,
+            &*self.bot_id
+        )
+        .fetch_optional(&mut tx)
+        .await?
+        else {
+            return Ok(None);
+        };
+
+        let next_run_at = next_run(&parse_cron(&due.cron)?)?;
+        sqlx::query!(
+            r#"
+UPDATE cleanup_schedule
+SET next_run_at = $2
+WHERE chat_id = $1
+            "#,
+            due.chat_id,
+            next_run_at
+        )
+        .execute(&mut tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(Some(due.chat_id))
+    }
+
+    async fn run_scheduled_cleanup(&self, chat_id: i64) -> anyhow::Result<()> {
+        if !self.queue_cleanup(chat_id)? {
+            anyhow::bail!("a cleanup is already running");
+        }
+
+        if let Err(err) = self.delete_all_members(chat_id, "schedule").await {
+            self.set_chat_status(chat_id, ChatCleaningStatus::Error(err.to_string()))?;
+            return Err(err);
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,92 @@
+use chrono::Utc;
+use tracing::{error, info};
+
+use crate::{
+    broadcast::{LandedMessage, Pacer},
+    state::AppState,
+    telegram_error::TelegramErrorClass,
+};
+
+/// Longest `delete_after_seconds`, telegram refuses to delete messages older
+/// than 48 hours.
+pub const MAX_DELETE_AFTER_SECONDS: i32 = 48 * 60 * 60;
+/// Most sent messages deleted in one round.
+const BATCH_SIZE: i64 = 500;
+
+impl AppState {
+    pub async fn self_destruct(state: Self) -> anyhow::Result<()> {
+        loop {
+            state.heartbeat("self_destruct");
+            match state.delete_expired_broadcasts().await {
+                Ok(0) => {}
+                Ok(deleted) => info!("deleted {deleted} expired broadcasts"),
+                Err(err) => error!("failed to delete expired broadcasts: {err}"),
+            }
+            match state.delete_expired_greetings(Utc::now()).await {
+                Ok(0) => {}
+                Ok(deleted) => info!("deleted {deleted} expired greetings"),
+                Err(err) => error!("failed to delete expired greetings: {err}"),
+            }
+            state
+                .sleep_interval(state.settings().intervals.self_destruct())
+                .await;
+        }
+    }
+
+    /// Deletes the broadcasts sent with `delete_after_seconds` from the chats
+    /// their time ran out in, returns how many. Messages telegram refuses to
+    /// delete, like those removed by an admin already, are given up on, rate
+    /// limited ones are left for the next round. Only with postgres, which
+    /// keeps the sent messages.
+    pub async fn delete_expired_broadcasts(&self) -> anyhow::Result<usize> {
+        let Some(pool) = &self.pool else {
+            return Ok(0);
+        };
+
+        let expired = sqlx::query_as!(
+            LandedMessage,
+            r#"
+SELECT s.id, s.chat_id, s.telegram_message_id AS "telegram_message_id!", s.media_message_ids
+FROM sent_message s
+JOIN message_queue q ON q.id = s.queue_id
+WHERE q.bot_id = $1 AND s.delete_at <= now()
+AND s.telegram_message_id IS NOT NULL AND s.deleted_at IS NULL
+ORDER BY s.delete_at
+LIMIT $2
+            "#,
+            &*self.bot_id,
+            BATCH_SIZE
+        )
+        .fetch_all(pool)
+        .await?;
+
+        let pacer = Pacer::new(self.config.broadcast_rate);
+        let mut deleted = 0;
+        for sent in expired {
+            match self.delete_landed_message(&pacer, &sent).await? {
+                None => deleted += 1,
+                Some(err) => {
+                    if let TelegramErrorClass::RateLimited(_) = TelegramErrorClass::of(&err) {
+                        break;
+                    }
+                    error!(
+                        "giving up deleting the broadcast in chat:{} {err}",
+                        sent.chat_id
+                    );
+                    sqlx::query!(
+                        r#"
+UPDATE sent_message
+SET delete_at = NULL
+WHERE id = $1
+                        "#,
+                        sent.id
+                    )
+                    .execute(pool)
+                    .await?;
+                }
+            }
+        }
+
+        Ok(deleted)
+    }
+}
@@ -0,0 +1,217 @@
+use anyhow::{bail, Context};
+use axum::http::{header::AUTHORIZATION, header::COOKIE, HeaderMap};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Duration, Utc};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use utoipa::ToSchema;
+
+use crate::state::AppState;
+
+/// Name of the cookie holding the session token.
+pub const SESSION_COOKIE: &str = "session";
+/// Logins older than this are refused, so a leaked login url can't be
+/// replayed forever.
+const MAX_LOGIN_AGE_SECS: i64 = 24 * 60 * 60;
+/// Seconds a token confirming a destructive request stays valid.
+const CONFIRMATION_TTL_SECS: i64 = 5 * 60;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Fields the Telegram Login Widget hands to its callback.
+#[derive(Deserialize, ToSchema)]
+pub struct TelegramLogin {
+    pub id: i64,
+    pub first_name: String,
+    pub last_name: Option<String>,
+    pub username: Option<String>,
+    pub photo_url: Option<String>,
+    /// Unix time of the login.
+    pub auth_date: i64,
+    pub hash: String,
+}
+
+impl TelegramLogin {
+    /// Checks the hash telegram computed over the other fields with the token
+    /// of the bot the widget belongs to.
+    pub fn verify(&self, bot_token: &str) -> anyhow::Result<()> {
+        let secret = Sha256::digest(bot_token.as_bytes());
+        let mut mac = HmacSha256::new_from_slice(&secret)?;
+        mac.update(self.data_check_string().as_bytes());
+
+        let hash = decode_hex(&self.hash).context("login hash is not hex")?;
+        if mac.verify_slice(&hash).is_err() {
+            bail!("login hash doesn't match");
+        }
+        if Utc::now().timestamp() - self.auth_date > MAX_LOGIN_AGE_SECS {
+            bail!("login is too old");
+        }
+
+        Ok(())
+    }
+
+    /// Every present field but the hash as `key=value` lines, sorted by key.
+    fn data_check_string(&self) -> String {
+        let fields = [
+            ("auth_date", Some(self.auth_date.to_string())),
+            ("first_name", Some(self.first_name.clone())),
+            ("id", Some(self.id.to_string())),
+            ("last_name", self.last_name.clone()),
+            ("photo_url", self.photo_url.clone()),
+            ("username", self.username.clone()),
+        ];
+
+        fields
+            .into_iter()
+            .filter_map(|(key, value)| Some(format!("{key}={}", value?)))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// A logged in telegram user, carried signed in the session token.
+#[derive(Clone, Serialize, Deserialize, ToSchema)]
+pub struct Session {
+    pub user_id: i64,
+    pub username: Option<String>,
+    pub name: String,
+    /// Unix time the session ends at.
+    pub expires_at: i64,
+}
+
+impl Session {
+    /// How the user shows up as the initiator of cleanups and in logs.
+    pub fn actor(&self) -> String {
+        match &self.username {
+            Some(username) => format!("telegram:@{username}"),
+            None => format!("telegram:{}", self.user_id),
+        }
+    }
+}
+
+impl AppState {
+    /// Returns a session token for a login already checked with
+    /// [`TelegramLogin::verify`]. Only the configured admins can log in.
+    pub fn login(&self, login: &TelegramLogin) -> anyhow::Result<(String, Session)> {
+        if !self.settings().admin_ids.contains(&login.id) {
+            bail!("user {} is not an admin", login.id);
+        }
+
+        let name = match &login.last_name {
+            Some(last_name) => format!("{} {last_name}", login.first_name),
+            None => login.first_name.clone(),
+        };
+        let session = Session {
+            user_id: login.id,
+            username: login.username.clone(),
+            name,
+            expires_at: Utc::now().timestamp() + self.config.session_ttl as i64,
+        };
+
+        let payload = URL_SAFE_NO_PAD.encode(serde_json::to_vec(&session)?);
+        let signature = URL_SAFE_NO_PAD.encode(self.session_mac(&payload).finalize().into_bytes());
+
+        Ok((format!("{payload}.{signature}"), session))
+    }
+
+    /// The unexpired session of a token this service signed, as long as its
+    /// user is still among the admins.
+    pub fn verify_session(&self, token: &str) -> Option<Session> {
+        let (payload, signature) = token.split_once('.')?;
+        let signature = URL_SAFE_NO_PAD.decode(signature).ok()?;
+        self.session_mac(payload).verify_slice(&signature).ok()?;
+
+        let session: Session =
+            serde_json::from_slice(&URL_SAFE_NO_PAD.decode(payload).ok()?).ok()?;
+        let valid = session.expires_at > Utc::now().timestamp()
+            && self.settings().admin_ids.contains(&session.user_id);
+        valid.then_some(session)
+    }
+
+    /// The session of the request, from the session cookie or an
+    /// `Authorization: Bearer` header.
+    pub fn request_session(&self, headers: &HeaderMap) -> Option<Session> {
+        let bearer = headers
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        let cookie = headers
+            .get_all(COOKIE)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(';'))
+            .find_map(|cookie| {
+                cookie
+                    .trim()
+                    .strip_prefix(SESSION_COOKIE)?
+                    .strip_prefix('=')
+            });
+
+        self.verify_session(bearer.or(cookie)?)
+    }
+
+    /// Token a client has to send back to go through with `action`, like
+    /// clearing a chat. It is signed like the sessions and carries its
+    /// expiry, so any instance can check it.
+    pub fn confirmation_token(&self, action: &str) -> (String, DateTime<Utc>) {
+        let expires_at = Utc::now() + Duration::seconds(CONFIRMATION_TTL_SECS);
+        let expiry = expires_at.timestamp().to_string();
+        let signature = self.confirmation_mac(action, &expiry).finalize();
+
+        (
+            format!(
+                "{expiry}.{}",
+                URL_SAFE_NO_PAD.encode(signature.into_bytes())
+            ),
+            expires_at,
+        )
+    }
+
+    /// Whether the token was handed out for `action` and is unexpired.
+    pub fn verify_confirmation(&self, action: &str, token: &str) -> bool {
+        let Some((expiry, signature)) = token.split_once('.') else {
+            return false;
+        };
+        let Ok(signature) = URL_SAFE_NO_PAD.decode(signature) else {
+            return false;
+        };
+        let unexpired = expiry
+            .parse::<i64>()
+            .is_ok_and(|expiry| expiry > Utc::now().timestamp());
+
+        unexpired
+            && self
+                .confirmation_mac(action, expiry)
+                .verify_slice(&signature)
+                .is_ok()
+    }
+
+    fn confirmation_mac(&self, action: &str, expiry: &str) -> HmacSha256 {
+        self.session_mac(&format!("confirm:{}:{action}:{expiry}", self.bot_id))
+    }
+
+    /// Sessions are signed with `session_secret`, or with a key derived from
+    /// the bot token when none is configured.
+    fn session_mac(&self, payload: &str) -> HmacSha256 {
+        let key = match &self.config.session_secret {
+            Some(secret) => Sha256::digest(secret.as_bytes()),
+            None => Sha256::digest(format!("session:{}", self.bot.inner().token()).as_bytes()),
+        };
+        let mut mac = HmacSha256::new_from_slice(&key).expect("hmac accepts any key length");
+        mac.update(payload.as_bytes());
+
+        mac
+    }
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
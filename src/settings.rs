@@ -0,0 +1,220 @@
+//! Settings that can be changed through the API while the service runs. The
+//! configured values are the defaults, changes are stored per bot as a JSON
+//! object on top of them and handed to the running loops, the bot adaptor
+//! and the API through a watch channel. Other instances pick them up with
+//! [`AppState::sync_settings`].
+
+use std::{sync::Arc, time::Duration};
+
+use anyhow::{bail, Context};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use tokio::sync::watch;
+use tracing::{error, info};
+use utoipa::ToSchema;
+
+use crate::{
+    config::{Config, IntervalsConfig, ThrottleConfig},
+    state::AppState,
+};
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Settings {
+    pub throttle: ThrottleConfig,
+    pub intervals: IntervalsConfig,
+    /// Telegram users who can use the admin commands and log in to the API.
+    pub admin_ids: Vec<i64>,
+}
+
+impl Settings {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            throttle: config.throttle.clone(),
+            intervals: config.intervals.clone(),
+            admin_ids: config.admin_ids.clone(),
+        }
+    }
+
+    /// The configured settings with `overrides` merged into them.
+    fn with_overrides(config: &Config, overrides: &Value) -> anyhow::Result<Self> {
+        let mut settings = serde_json::to_value(Self::from_config(config))?;
+        merge_patch(&mut settings, overrides.clone());
+        let settings: Self = serde_json::from_value(settings)?;
+        settings.validate(config)?;
+
+        Ok(settings)
+    }
+
+    fn validate(&self, config: &Config) -> anyhow::Result<()> {
+        let throttle = &self.throttle;
+        if [
+            throttle.messages_per_sec_chat,
+            throttle.messages_per_min_chat,
+            throttle.messages_per_min_channel,
+            throttle.messages_per_sec_overall,
+        ]
+        .contains(&0)
+        {
+            bail!("throttle limits must be positive");
+        }
+        let intervals = &self.intervals;
+        if [
+            intervals.message_queue,
+            intervals.cleanup_deprecated_chats,
+            intervals.sync_admins,
+            intervals.scheduled_cleanups,
+            intervals.chat_metadata,
+            intervals.captcha_timeouts,
+            intervals.self_destruct,
+            intervals.status_sync,
+            intervals.settings_sync,
+        ]
+        .contains(&0)
+        {
+            bail!("intervals must be positive");
+        }
+        // due messages are fetched this far ahead of each iteration
+        if intervals.message_queue > config.queue.lookahead {
+            bail!("intervals.message_queue can't be above the queue lookahead");
+        }
+
+        Ok(())
+    }
+}
+
+/// Applies a JSON merge patch (RFC 7386): objects are merged key by key,
+/// `null` removes a key and anything else replaces the value.
+fn merge_patch(target: &mut Value, patch: Value) {
+    let Value::Object(patch) = patch else {
+        *target = patch;
+        return;
+    };
+    if !target.is_object() {
+        *target = Value::Object(Map::new());
+    }
+    let target = target.as_object_mut().expect("made an object above");
+    for (key, value) in patch {
+        match value {
+            Value::Null => {
+                target.remove(&key);
+            }
+            value => merge_patch(target.entry(key).or_insert(Value::Null), value),
+        }
+    }
+}
+
+/// Fails on the first key of `patch` that `known` doesn't have, so a typo
+/// isn't stored and silently ignored.
+fn check_known_keys(known: &Value, patch: &Value, path: &str) -> anyhow::Result<()> {
+    let (Value::Object(known), Value::Object(patch)) = (known, patch) else {
+        return Ok(());
+    };
+    for (key, value) in patch {
+        let path = match path {
+            "" => key.clone(),
+            path => format!("{path}.{key}"),
+        };
+        let known = known
+            .get(key)
+            .with_context(|| format!("unknown setting {path}"))?;
+        check_known_keys(known, value, &path)?;
+    }
+
+    Ok(())
+}
+
+/// The settings of a bot as its loops and handlers see them.
+pub fn channel(config: &Config) -> Arc<watch::Sender<Arc<Settings>>> {
+    Arc::new(watch::channel(Arc::new(Settings::from_config(config))).0)
+}
+
+impl AppState {
+    /// The settings in effect right now.
+    pub fn settings(&self) -> Arc<Settings> {
+        self.settings.borrow().clone()
+    }
+
+    /// Sleeps for `duration`, or until the settings change so a loop picks up
+    /// a new interval right away instead of after the old one.
+    pub async fn sleep_interval(&self, duration: Duration) {
+        let mut changes = self.settings.subscribe();
+        tokio::select! {
+            _ = tokio::time::sleep(duration) => {}
+            _ = changes.changed() => {}
+        }
+    }
+
+    /// Applies the stored settings of the bot, at startup and whenever
+    /// another instance may have changed them.
+    pub async fn load_settings(&self) -> anyhow::Result<()> {
+        let overrides = self
+            .storage
+            .get_settings_overrides(&self.bot_id)
+            .await?
+            .unwrap_or_else(|| Value::Object(Map::new()));
+        let settings = Settings::with_overrides(&self.config, &overrides)
+            .context("the stored settings are invalid")?;
+        self.apply_settings(settings);
+
+        Ok(())
+    }
+
+    /// Merges `patch` into the stored settings of the bot and applies the
+    /// result. A `null` goes back to the configured value.
+    pub async fn update_settings(&self, patch: Value) -> anyhow::Result<Arc<Settings>> {
+        check_known_keys(
+            &serde_json::to_value(Settings::from_config(&self.config))?,
+            &patch,
+            "",
+        )?;
+        let overrides = self
+            .storage
+            .update_settings_overrides(&self.bot_id, &|mut overrides| {
+                merge_patch(&mut overrides, patch.clone());
+                // invalid overrides are refused before they are stored
+                Settings::with_overrides(&self.config, &overrides)?;
+                Ok(overrides)
+            })
+            .await?;
+        let settings = Settings::with_overrides(&self.config, &overrides)?;
+        info!("settings of bot {} changed to {overrides}", self.bot_id);
+        self.apply_settings(settings);
+
+        Ok(self.settings())
+    }
+
+    /// Hands changed settings to whoever watches them.
+    fn apply_settings(&self, settings: Settings) {
+        if *self.settings() != settings {
+            self.settings.send_replace(Arc::new(settings));
+        }
+    }
+
+    /// Sets changed throttle limits on the bot adaptor. Its worker only takes
+    /// them once the next request goes out, so nothing else waits for that.
+    pub async fn apply_throttle_changes(state: Self) -> anyhow::Result<()> {
+        let mut changes = state.settings.subscribe();
+        let mut applied = state.config.throttle.clone();
+        loop {
+            let throttle = changes.borrow_and_update().throttle.clone();
+            if throttle != applied {
+                info!("updating throttle limits to {throttle:?}");
+                state.bot.set_limits(throttle.limits()).await;
+                applied = throttle;
+            }
+            changes.changed().await?;
+        }
+    }
+
+    pub async fn sync_settings(state: Self) -> anyhow::Result<()> {
+        loop {
+            state.heartbeat("sync_settings");
+            if let Err(err) = state.load_settings().await {
+                error!("failed to load the settings: {err}");
+            }
+            state
+                .sleep_interval(state.settings().intervals.settings_sync())
+                .await;
+        }
+    }
+}
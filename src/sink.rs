@@ -0,0 +1,301 @@
+//! Destinations broadcasts go to besides telegram, configured per chat
+//! group. A queued message targeting chats of a group is handed once to
+//! every other sink of the group, while telegram keeps being sent to chat by
+//! chat by the broadcast engine. Sinks other than telegram are behind cargo
+//! features, all on by default.
+
+use std::collections::{HashMap, HashSet};
+
+use async_trait::async_trait;
+#[cfg(any(feature = "webhook-sink", feature = "slack-sink"))]
+use axum::http::header::CONTENT_TYPE;
+use serde::{Deserialize, Serialize};
+use tracing::{error, info};
+
+use crate::{
+    format::MessageFormat,
+    state::{AppState, QueuedMessage},
+};
+
+/// Delivery error of the chats a group keeps off telegram.
+pub const OFF_TELEGRAM: &str = "chat group doesn't send to telegram";
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SinkConfig {
+    /// The chats of the group themselves, what groups without sinks get.
+    Telegram,
+    /// Posts the broadcast as JSON, signed like the event webhooks when a
+    /// secret is set.
+    #[cfg(feature = "webhook-sink")]
+    Webhook { url: String, secret: Option<String> },
+    /// Posts the text to an incoming webhook of a Slack channel.
+    #[cfg(feature = "slack-sink")]
+    Slack { webhook_url: String },
+}
+
+impl SinkConfig {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        match self {
+            SinkConfig::Telegram => Ok(()),
+            #[cfg(feature = "webhook-sink")]
+            SinkConfig::Webhook { url, .. } => validate_url(url),
+            #[cfg(feature = "slack-sink")]
+            SinkConfig::Slack { webhook_url } => validate_url(webhook_url),
+        }
+    }
+
+    /// `None` for telegram, which the broadcast engine sends to itself.
+    fn build(&self) -> Option<Box<dyn Sink>> {
+        match self {
+            SinkConfig::Telegram => None,
+            #[cfg(feature = "webhook-sink")]
+            SinkConfig::Webhook { url, secret } => Some(Box::new(WebhookSink {
+                url: url.clone(),
+                secret: secret.clone(),
+            })),
+            #[cfg(feature = "slack-sink")]
+            SinkConfig::Slack { webhook_url } => Some(Box::new(SlackSink {
+                webhook_url: webhook_url.clone(),
+            })),
+        }
+    }
+}
+
+#[cfg(any(feature = "webhook-sink", feature = "slack-sink"))]
+fn validate_url(url: &str) -> anyhow::Result<()> {
+    let url = reqwest::Url::parse(url)?;
+    if !matches!(url.scheme(), "http" | "https") {
+        anyhow::bail!("sink URLs must use http or https");
+    }
+
+    Ok(())
+}
+
+/// What a sink gets of a broadcast: the text in its parse mode, branded for
+/// the group, without variants or translations.
+#[derive(Serialize)]
+pub struct SinkMessage<'a> {
+    pub queue_id: i32,
+    pub bot_id: &'a str,
+    pub chat_group: &'a str,
+    pub text: String,
+    pub parse_mode: MessageFormat,
+    /// The https images, uploaded ones only go to telegram.
+    pub images: Vec<&'a str>,
+}
+
+#[async_trait]
+pub trait Sink: Send + Sync {
+    async fn deliver(
+        &self,
+        http: &reqwest::Client,
+        message: &SinkMessage<'_>,
+    ) -> anyhow::Result<()>;
+}
+
+#[cfg(feature = "webhook-sink")]
+struct WebhookSink {
+    url: String,
+    secret: Option<String>,
+}
+
+#[cfg(feature = "webhook-sink")]
+#[async_trait]
+impl Sink for WebhookSink {
+    async fn deliver(
+        &self,
+        http: &reqwest::Client,
+        message: &SinkMessage<'_>,
+    ) -> anyhow::Result<()> {
+        let body = serde_json::to_vec(message)?;
+        let mut request = http
+            .post(&self.url)
+            .header(CONTENT_TYPE, "application/json");
+        if let Some(secret) = &self.secret {
+            request = request.header(
+                crate::webhook::SIGNATURE_HEADER,
+                crate::webhook::sign(secret, &body),
+            );
+        }
+        request.body(body).send().await?.error_for_status()?;
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "slack-sink")]
+struct SlackSink {
+    webhook_url: String,
+}
+
+#[cfg(feature = "slack-sink")]
+#[async_trait]
+impl Sink for SlackSink {
+    async fn deliver(
+        &self,
+        http: &reqwest::Client,
+        message: &SinkMessage<'_>,
+    ) -> anyhow::Result<()> {
+        let text = message.images.iter().fold(
+            plain_text(&message.text, message.parse_mode),
+            |text, url| format!("{text}\n{url}"),
+        );
+        let body = serde_json::to_vec(&serde_json::json!({ "text": text }))?;
+        http.post(&self.webhook_url)
+            .header(CONTENT_TYPE, "application/json")
+            .body(body)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+}
+
+/// The text without the markup of its parse mode, for destinations that
+/// don't speak telegram's. Links keep only their text.
+#[cfg(feature = "slack-sink")]
+fn plain_text(text: &str, format: MessageFormat) -> String {
+    match format {
+        MessageFormat::Plain => text.to_string(),
+        MessageFormat::Html => {
+            let mut plain = String::with_capacity(text.len());
+            let mut in_tag = false;
+            for c in text.chars() {
+                match c {
+                    '<' => in_tag = true,
+                    '>' if in_tag => in_tag = false,
+                    c if !in_tag => plain.push(c),
+                    _ => {}
+                }
+            }
+            plain
+                .replace("&lt;", "<")
+                .replace("&gt;", ">")
+                .replace("&quot;", "\"")
+                .replace("&amp;", "&")
+        }
+        MessageFormat::MarkdownV2 => {
+            let mut plain = String::with_capacity(text.len());
+            let mut chars = text.chars();
+            let mut in_link_url = false;
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => plain.extend(chars.next()),
+                    ')' if in_link_url => in_link_url = false,
+                    _ if in_link_url => {}
+                    ']' => {
+                        if chars.clone().next() == Some('(') {
+                            chars.next();
+                            in_link_url = true;
+                        }
+                    }
+                    '*' | '_' | '~' | '|' | '`' | '[' => {}
+                    c => plain.push(c),
+                }
+            }
+            plain
+        }
+    }
+}
+
+/// Chats every group of which has sinks, none of them telegram. Chats in no
+/// group with sinks stay on telegram.
+pub fn off_telegram_chats(
+    sinks: &HashMap<String, Vec<SinkConfig>>,
+    groups: &HashMap<String, Vec<i64>>,
+) -> HashSet<i64> {
+    let mut on = HashSet::new();
+    let mut off = HashSet::new();
+    for (group, chats) in groups {
+        let telegram = sinks
+            .get(group)
+            .is_none_or(|sinks| sinks.contains(&SinkConfig::Telegram));
+        match telegram {
+            true => on.extend(chats),
+            false => off.extend(chats),
+        }
+    }
+
+    off.difference(&on).copied().collect()
+}
+
+impl AppState {
+    /// Hands the message to the sinks of the chat groups it targets, except
+    /// the ones that already took it, so a broadcast running again after a
+    /// deferral or a restart doesn't post twice. Failures are logged and
+    /// recorded, and tried again the next time the broadcast runs, telegram
+    /// isn't held up by them.
+    pub async fn deliver_to_sinks(
+        &self,
+        message: &QueuedMessage,
+        groups: &HashMap<String, Vec<i64>>,
+    ) -> anyhow::Result<()> {
+        let options = message.options()?;
+        let delivered: HashSet<String> = self
+            .storage
+            .get_sink_deliveries(message.id)
+            .await?
+            .into_iter()
+            .collect();
+        let images: Vec<&str> = message
+            .images
+            .iter()
+            .map(String::as_str)
+            .filter(|image| image.starts_with("https://"))
+            .collect();
+
+        let mut names: Vec<_> = self.config.chat_group_sinks.keys().collect();
+        names.sort();
+        for group in names {
+            let targeted = groups
+                .get(group)
+                .is_some_and(|chats| chats.iter().any(|chat| message.chats.contains(chat)));
+            if !targeted {
+                continue;
+            }
+
+            let text = match self.config.chat_group_branding.get(group) {
+                Some(branding) if !message.message.trim().is_empty() => {
+                    branding.apply(&message.message, options.parse_mode)
+                }
+                _ => message.message.clone(),
+            };
+            let sink_message = SinkMessage {
+                queue_id: message.id,
+                bot_id: &self.bot_id,
+                chat_group: group,
+                text,
+                parse_mode: options.parse_mode,
+                images: images.clone(),
+            };
+            for (index, config) in self.config.chat_group_sinks[group].iter().enumerate() {
+                // configured sinks are told apart by their place in the group
+                let name = format!("{group}/{index}");
+                let Some(sink) = config.build() else {
+                    continue;
+                };
+                if delivered.contains(&name) {
+                    continue;
+                }
+
+                let error = match sink.deliver(&self.http, &sink_message).await {
+                    Ok(()) => {
+                        info!("delivered message {} to sink {name}", message.id);
+                        None
+                    }
+                    Err(err) => {
+                        error!("sink {name} failed to take message {}: {err}", message.id);
+                        Some(err.to_string())
+                    }
+                };
+                self.storage
+                    .record_sink_delivery(message.id, &name, error)
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,278 @@
+use crate::{format::MessageFormat, validation::MAX_MESSAGE_LENGTH};
+
+/// Length of a text the way telegram counts it, in UTF-16 code units, so an
+/// emoji outside the basic plane counts twice.
+pub fn telegram_len(text: &str) -> usize {
+    text.encode_utf16().count()
+}
+
+/// An entity open at some point of the text: `open` reopens it at the start
+/// of the next part, `close` ends it with the current one.
+#[derive(Clone)]
+struct OpenEntity<'a> {
+    open: &'a str,
+    close: String,
+}
+
+/// A place the text may be cut at, never inside markup.
+struct SplitPoint<'a> {
+    offset: usize,
+    open: Vec<OpenEntity<'a>>,
+}
+
+/// Splits a text longer than telegram accepts into messages of at most
+/// [`MAX_MESSAGE_LENGTH`], preferring paragraph breaks over line breaks over
+/// spaces. Entities cut in two are closed at the end of a part and reopened
+/// at the start of the next one. The markup must be valid.
+pub fn split_message(text: &str, format: MessageFormat) -> Vec<String> {
+    split_message_within(text, format, MAX_MESSAGE_LENGTH)
+}
+
+/// [`split_message`] with parts of at most `limit` UTF-16 code units.
+pub fn split_message_within(text: &str, format: MessageFormat, limit: usize) -> Vec<String> {
+    if telegram_len(text) <= limit {
+        return vec![text.to_string()];
+    }
+
+    let points = match format {
+        MessageFormat::MarkdownV2 => markdown_points(text),
+        MessageFormat::Html => html_points(text),
+        MessageFormat::Plain => text
+            .char_indices()
+            .map(|(offset, _)| offset)
+            .chain([text.len()])
+            .map(|offset| SplitPoint {
+                offset,
+                open: Vec::new(),
+            })
+            .collect(),
+    };
+    // telegram length of the text before each point
+    let mut units = Vec::with_capacity(points.len());
+    let mut counted = 0;
+    let mut previous = 0;
+    for point in &points {
+        counted += telegram_len(&text[previous..point.offset]);
+        previous = point.offset;
+        units.push(counted);
+    }
+
+    let last = points.len() - 1;
+    let mut parts = Vec::new();
+    let mut start = 0;
+    while start < last {
+        let reopen: String = points[start]
+            .open
+            .iter()
+            .map(|entity| entity.open)
+            .collect();
+        let reopen_len = telegram_len(&reopen);
+
+        let mut best: Option<(u8, usize)> = None;
+        for end in start + 1..=last {
+            let len = reopen_len + units[end] - units[start];
+            if len > limit {
+                break;
+            }
+            let closing: usize = points[end]
+                .open
+                .iter()
+                .map(|entity| telegram_len(&entity.close))
+                .sum();
+            if len + closing > limit {
+                continue;
+            }
+            let rank = if end == last {
+                u8::MAX
+            } else {
+                boundary_rank(&text[..points[end].offset])
+            };
+            if best.is_none_or(|(best_rank, _)| rank >= best_rank) {
+                best = Some((rank, end));
+            }
+        }
+        // markup longer than a whole message can only fail at telegram
+        let end = best.map_or(start + 1, |(_, end)| end);
+
+        let mut part = reopen;
+        let body = &text[points[start].offset..points[end].offset];
+        part.push_str(if end == last { body } else { trim_break(body) });
+        for entity in points[end].open.iter().rev() {
+            part.push_str(&entity.close);
+        }
+        parts.push(part);
+        start = end;
+    }
+
+    parts
+}
+
+/// How good a place to cut the text ending in `before` is.
+fn boundary_rank(before: &str) -> u8 {
+    if before.ends_with("\n\n") {
+        3
+    } else if before.ends_with('\n') {
+        2
+    } else if before.ends_with(char::is_whitespace) {
+        1
+    } else {
+        0
+    }
+}
+
+/// Drops the whitespace a part was cut at, unless it is escaped.
+fn trim_break(body: &str) -> &str {
+    let mut body = body;
+    while let Some(trimmed) = body.strip_suffix(['\n', ' ']) {
+        if trimmed.ends_with('\\') {
+            break;
+        }
+        body = trimmed;
+    }
+
+    body
+}
+
+fn markdown_points(text: &str) -> Vec<SplitPoint<'_>> {
+    let mut points = Vec::new();
+    let mut open: Vec<OpenEntity> = Vec::new();
+    let mut offset = 0;
+
+    while offset < text.len() {
+        points.push(SplitPoint {
+            offset,
+            open: open.clone(),
+        });
+        let rest = &text[offset..];
+        let char_len = rest.chars().next().map_or(1, char::len_utf8);
+        let in_code = open
+            .last()
+            .is_some_and(|entity| entity.close.starts_with('`'));
+
+        let len = if let Some(escaped) = rest.strip_prefix('\\') {
+            1 + escaped.chars().next().map_or(0, char::len_utf8)
+        } else if in_code {
+            let fence = open.last().map(|entity| entity.close.clone());
+            match fence.filter(|fence| rest.starts_with(fence.as_str())) {
+                Some(fence) => {
+                    open.pop();
+                    fence.len()
+                }
+                None => char_len,
+            }
+        } else if rest.starts_with("```") {
+            // the language line of a pre block is repeated with it
+            let line = rest.find('\n').map(|end| &rest[..=end]);
+            let opener = line
+                .filter(|line| !line[3..].contains("```"))
+                .unwrap_or("```");
+            open.push(OpenEntity {
+                open: opener,
+                close: "```".to_string(),
+            });
+            opener.len()
+        } else if let Some(marker) = ["`", "||", "__", "*", "~", "_"]
+            .into_iter()
+            .find(|marker| rest.starts_with(*marker))
+        {
+            if open.last().is_some_and(|entity| entity.close == marker) {
+                open.pop();
+            } else {
+                open.push(OpenEntity {
+                    open: &rest[..marker.len()],
+                    close: marker.to_string(),
+                });
+            }
+            marker.len()
+        } else if rest.starts_with('[') || rest.starts_with("![") {
+            markdown_link_len(rest).unwrap_or(char_len)
+        } else {
+            char_len
+        };
+        offset += len;
+    }
+
+    points.push(SplitPoint {
+        offset: text.len(),
+        open,
+    });
+    points
+}
+
+/// Length of a `[text](url)` link or `![emoji](tg://emoji?id=...)` custom
+/// emoji, kept whole.
+fn markdown_link_len(text: &str) -> Option<usize> {
+    let mut chars = text.char_indices();
+    let mut depth = 0;
+    let mut url = false;
+    while let Some((offset, c)) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '[' if !url => depth += 1,
+            ']' if !url => {
+                depth -= 1;
+                if depth == 0 {
+                    if !text[offset + 1..].starts_with('(') {
+                        return None;
+                    }
+                    url = true;
+                    chars.next();
+                }
+            }
+            ')' if url => return Some(offset + 1),
+            _ => {}
+        }
+    }
+
+    None
+}
+
+fn html_points(text: &str) -> Vec<SplitPoint<'_>> {
+    let mut points = Vec::new();
+    let mut open: Vec<OpenEntity> = Vec::new();
+    let mut offset = 0;
+
+    while offset < text.len() {
+        points.push(SplitPoint {
+            offset,
+            open: open.clone(),
+        });
+        let rest = &text[offset..];
+        let char_len = rest.chars().next().map_or(1, char::len_utf8);
+
+        let len = if rest.starts_with('<') {
+            match rest.find('>') {
+                Some(end) => {
+                    let tag = &rest[1..end];
+                    match tag.strip_prefix('/') {
+                        Some(_) => {
+                            open.pop();
+                        }
+                        None => {
+                            let name = tag.split_whitespace().next().unwrap_or_default();
+                            open.push(OpenEntity {
+                                open: &rest[..=end],
+                                close: format!("</{name}>"),
+                            });
+                        }
+                    }
+                    end + 1
+                }
+                None => char_len,
+            }
+        } else if rest.starts_with('&') {
+            rest.find(';').map_or(char_len, |end| end + 1)
+        } else {
+            char_len
+        };
+        offset += len;
+    }
+
+    points.push(SplitPoint {
+        offset: text.len(),
+        open,
+    });
+    points
+}
@@ -0,0 +1,273 @@
+use std::collections::HashSet;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use teloxide::{
+    payloads::UnbanChatMemberSetters,
+    requests::Requester,
+    types::{ChatId, ChatPermissions, UserId},
+};
+use tracing::{error, info, instrument};
+use utoipa::ToSchema;
+
+use crate::{
+    state::{AppState, ChatCleaningStatus, CleanupProgress},
+    telegram_error::{member_failed, TelegramErrorClass},
+};
+
+/// A member a staged cleanup restricted, kicked once the cleanup is
+/// confirmed.
+#[derive(Clone, Debug, Serialize, ToSchema, sqlx::FromRow)]
+pub struct CleanupCandidate {
+    pub user_id: i64,
+    pub username: Option<String>,
+    pub name: String,
+    pub initiator: String,
+    pub staged_at: DateTime<Utc>,
+}
+
+/// Outcome of lifting the bans of a chat's kick log.
+#[derive(Debug, Default, Serialize, ToSchema)]
+pub struct UnbanResult {
+    pub unbanned: usize,
+    pub failed: usize,
+}
+
+impl AppState {
+    /// The first phase of a cleanup: instead of kicking, every member that
+    /// [`delete_all_members`](AppState::delete_all_members) would kick is
+    /// restricted from writing and recorded as a candidate.
+    /// [`confirm_staged_cleanup`](AppState::confirm_staged_cleanup) kicks
+    /// them, [`discard_staged_cleanup`](AppState::discard_staged_cleanup)
+    /// gives them their rights back. Basic groups can't restrict members,
+    /// there they are only recorded.
+    #[instrument(skip_all, fields(chat_id, initiator))]
+    pub async fn stage_all_members(&self, chat_id: i64, initiator: &str) -> anyhow::Result<()> {
+        let run = self.stage_all_members_inner(chat_id, initiator);
+        self.record_cleanup_run(chat_id, true, initiator, run).await
+    }
+
+    async fn stage_all_members_inner(&self, chat_id: i64, initiator: &str) -> anyhow::Result<()> {
+        info!("staging the cleanup of chat:{chat_id} initiated by {initiator}");
+
+        // cancelled while still queued
+        if self.cancelled_cleanups.remove(&chat_id).is_some() {
+            self.set_chat_status(chat_id, ChatCleaningStatus::Cancelled(0))?;
+            return Ok(());
+        }
+
+        self.set_chat_status(
+            chat_id,
+            ChatCleaningStatus::InProgress(CleanupProgress::new(0)),
+        )?;
+        self.cleanup_chat(chat_id).await?;
+
+        let members = self.get_all_members(chat_id).await?;
+        let members = self.without_excluded(chat_id, members).await?;
+        let chat = self.telegram_chat(chat_id).await?;
+
+        // `kicked` counts the staged members
+        let mut progress = CleanupProgress::new(members.len());
+        self.set_chat_status(chat_id, ChatCleaningStatus::InProgress(progress.clone()))?;
+
+        for user in members {
+            if self.cancelled_cleanups.remove(&chat_id).is_some() {
+                info!(
+                    "staging of chat:{chat_id} cancelled after {} members",
+                    progress.processed
+                );
+                self.set_chat_status(chat_id, ChatCleaningStatus::Cancelled(progress.processed))?;
+                return Ok(());
+            }
+
+            let result = if chat.is_supergroup() {
+                self.restrict_member(chat_id, user.id, ChatPermissions::empty())
+                    .await
+            } else {
+                Ok(())
+            };
+            match result {
+                Ok(()) => {
+                    self.storage
+                        .add_cleanup_candidate(chat_id, &user, initiator)
+                        .await?;
+                    progress.record(true);
+                }
+                Err(err) => {
+                    member_failed(err, "restrict", chat_id, user.id)?;
+                    progress.record(false);
+                }
+            }
+            self.set_chat_status(chat_id, ChatCleaningStatus::InProgress(progress.clone()))?;
+        }
+
+        // a cancel that arrived after the last member has nothing left to stop
+        self.cancelled_cleanups.remove(&chat_id);
+        self.set_chat_status(chat_id, ChatCleaningStatus::Idle)?;
+        info!(
+            "staged {} members of chat:{chat_id} for the cleanup",
+            progress.kicked
+        );
+
+        Ok(())
+    }
+
+    pub async fn get_cleanup_candidates(
+        &self,
+        chat_id: i64,
+    ) -> anyhow::Result<Vec<CleanupCandidate>> {
+        self.storage.get_cleanup_candidates(chat_id).await
+    }
+
+    /// The second phase of a staged cleanup, kicks the candidates that are
+    /// still stored as members. Returns `false` when nothing was staged.
+    /// Candidates are kept when the kicks are cancelled part way, a later
+    /// confirmation goes on with those left. A failure is recorded as the
+    /// chat's status.
+    #[instrument(skip_all, fields(chat_id, initiator))]
+    pub async fn confirm_staged_cleanup(
+        &self,
+        chat_id: i64,
+        initiator: &str,
+    ) -> anyhow::Result<bool> {
+        let result = self.confirm_staged_cleanup_inner(chat_id, initiator).await;
+        if let Err(err) = &result {
+            self.set_chat_status(chat_id, ChatCleaningStatus::Error(err.to_string()))?;
+        }
+
+        result
+    }
+
+    async fn confirm_staged_cleanup_inner(
+        &self,
+        chat_id: i64,
+        initiator: &str,
+    ) -> anyhow::Result<bool> {
+        let candidates: HashSet<i64> = self
+            .get_cleanup_candidates(chat_id)
+            .await?
+            .into_iter()
+            .map(|candidate| candidate.user_id)
+            .collect();
+        if candidates.is_empty() {
+            return Ok(false);
+        }
+        info!(
+            "kicking {} staged members from chat:{chat_id} confirmed by {initiator}",
+            candidates.len()
+        );
+
+        let members = self
+            .get_all_members(chat_id)
+            .await?
+            .into_iter()
+            .filter(|user| candidates.contains(&user.id))
+            .collect();
+        if !self
+            .kick_members(chat_id, members, "cleanup", initiator)
+            .await?
+        {
+            return Ok(true);
+        }
+
+        self.storage.delete_cleanup_candidates(chat_id).await?;
+        self.notify_cleanup_finished(chat_id, "cleanup", initiator);
+        self.set_chat_status(chat_id, ChatCleaningStatus::Idle)?;
+        self.cleanup_records
+            .entry(chat_id)
+            .or_default()
+            .last_cleanup_at = Some(Utc::now());
+
+        Ok(true)
+    }
+
+    /// Gives the candidates of a staged cleanup the chat's default rights
+    /// back and forgets them. Returns how many there were.
+    #[instrument(skip_all, fields(chat_id))]
+    pub async fn discard_staged_cleanup(&self, chat_id: i64) -> anyhow::Result<usize> {
+        let candidates = self.get_cleanup_candidates(chat_id).await?;
+        if candidates.is_empty() {
+            return Ok(0);
+        }
+        info!(
+            "discarding the staged cleanup of chat:{chat_id}, restoring {} members",
+            candidates.len()
+        );
+
+        let chat = self.telegram_chat(chat_id).await?;
+        if chat.is_supergroup() {
+            let permissions = chat.permissions().unwrap_or_else(ChatPermissions::all);
+            for candidate in &candidates {
+                if let Err(err) = self
+                    .restrict_member(chat_id, candidate.user_id, permissions)
+                    .await
+                {
+                    error!(
+                        "failed to restore the rights of user:{} in chat:{chat_id} {err}",
+                        candidate.user_id
+                    );
+                }
+            }
+        }
+        self.storage.delete_cleanup_candidates(chat_id).await?;
+
+        Ok(candidates.len())
+    }
+
+    /// Lifts the ban of everyone in the chat's kick log, for cleanups that
+    /// kicked the wrong people. Users that are not banned are left alone.
+    #[instrument(skip_all, fields(chat_id))]
+    pub async fn unban_all(&self, chat_id: i64) -> anyhow::Result<UnbanResult> {
+        let users = self.storage.get_kicked_user_ids(chat_id).await?;
+        info!("unbanning {} kicked users of chat:{chat_id}", users.len());
+
+        let mut result = UnbanResult::default();
+        for user_id in users {
+            let unbanned = loop {
+                let unbanned = self
+                    .bot
+                    .unban_chat_member(ChatId(chat_id), UserId(user_id as u64))
+                    .only_if_banned(true)
+                    .await;
+                match unbanned.as_ref().map_err(TelegramErrorClass::of) {
+                    Err(TelegramErrorClass::RateLimited(wait)) => {
+                        info!("unbans in chat:{chat_id} are rate limited, waiting {wait:?}");
+                        tokio::time::sleep(wait).await;
+                    }
+                    _ => break unbanned,
+                }
+            };
+            match unbanned {
+                Ok(_) => result.unbanned += 1,
+                Err(err) => {
+                    member_failed(err, "unban", chat_id, user_id)?;
+                    result.failed += 1;
+                }
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Sets the rights of a member, waiting out rate limits.
+    async fn restrict_member(
+        &self,
+        chat_id: i64,
+        user_id: i64,
+        permissions: ChatPermissions,
+    ) -> anyhow::Result<()> {
+        loop {
+            let result = self
+                .bot
+                .restrict_chat_member(ChatId(chat_id), UserId(user_id as u64), permissions)
+                .await;
+            match result.as_ref().map_err(TelegramErrorClass::of) {
+                Err(TelegramErrorClass::RateLimited(wait)) => {
+                    info!("restrictions in chat:{chat_id} are rate limited, waiting {wait:?}");
+                    tokio::time::sleep(wait).await;
+                }
+                _ => return Ok(result.map(|_| ())?),
+            }
+        }
+    }
+}
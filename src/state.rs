@@ -1,50 +1,409 @@
-use std::{collections::HashMap, sync::Arc, time::Duration};
+use std::{fmt, future::Future, sync::Arc, time::Duration};
 
 use anyhow::Context;
-use base64::Engine;
-use bytes::Bytes;
-use dashmap::DashMap;
-use data_url::DataUrl;
-use futures::TryStreamExt;
-use serde::Serialize;
-use sqlx::PgPool;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use dashmap::{DashMap, DashSet};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::{postgres::PgListener, PgPool};
 use teloxide::{
     adaptors::Throttle,
-    payloads::SendMessageSetters,
-    requests::{Requester, RequesterExt},
-    types::{ChatId, InputFile, InputMedia, InputMediaPhoto, ParseMode, UserId},
-    utils::markdown::escape,
+    payloads::{SendMediaGroupSetters, SendMessageSetters},
+    requests::Requester,
+    types::{ChatId, InputMedia, Message, UserId},
     Bot,
 };
-use tracing::{error, info};
+use tokio::{
+    sync::{watch, Mutex, OnceCell, Semaphore},
+    task::JoinSet,
+    time::{Instant, MissedTickBehavior},
+};
+use tracing::{error, info, info_span, instrument, warn, Instrument, Span};
+use utoipa::ToSchema;
+
+use crate::{
+    admin::ChatAdmin,
+    alert::Alerts,
+    attachment::Attachments,
+    broadcast::{ChatResult, MessageOptions},
+    channel::CHANNEL_KIND,
+    chat_cache::CachedChat,
+    cleanup_pause::{CleanupWindow, KickRun},
+    config::Config,
+    health::{Health, TelegramActivity},
+    language::{LocalizedMessage, Translations},
+    live::LiveUpdates,
+    media::{decode_inline_image, is_remote_image, Media},
+    member_cache::CachedMemberKind,
+    moderation::ChatFilter,
+    outbound,
+    queue::parse_datetime,
+    ratelimit::RateLimiter,
+    reconcile::InterruptedCleanup,
+    resync::ResyncProgress,
+    schedule::CleanupSchedule,
+    settings::{self, Settings},
+    status_store::{
+        CleanupElsewhere, MemoryStatusStore, PgStatusStore, StatusStore, StatusStoreKind,
+    },
+    storage::{ChatMetadata, NewQueuedMessage, Storage, CLAIM_LEASE},
+    subscriber::Audience,
+    telegram_error::{member_failed, TelegramErrorClass},
+    telemetry::{current_request_id, with_request_id},
+    topic::{ChatTarget, Targets},
+    webhook::WebhookEvent,
+};
 
 pub type WrappedBot = Throttle<Bot>;
 
+/// How a message enters the queue.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QueueMode {
+    /// Sent when due.
+    Send,
+    /// Sent when due unless the same text was queued for the same chats
+    /// within the duplicate window, which fails queueing with
+    /// [`DuplicateBroadcast`](crate::duplicate::DuplicateBroadcast).
+    SendOnce,
+    /// Held until approved.
+    AwaitApproval,
+}
+
+/// Returned by [`AppState::pg`] when the storage is not postgres.
+#[derive(Debug)]
+pub struct PostgresRequired;
+
+impl fmt::Display for PostgresRequired {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("this feature requires a postgres database")
+    }
+}
+
+impl std::error::Error for PostgresRequired {}
+
 #[derive(Clone)]
 pub struct AppState {
-    pub pool: PgPool,
+    pub storage: Arc<dyn Storage>,
+    /// Set when the storage is postgres, which the features beyond
+    /// [`Storage`] need.
+    pub pool: Option<PgPool>,
+    /// Id of the configured bot this state acts as, its chats and queued
+    /// messages are kept apart from those of other bots.
+    pub bot_id: Arc<str>,
     pub bot: WrappedBot,
     pub chats_status: Arc<DashMap<i64, ChatCleaningStatus>>,
+    /// Shares `chats_status` with other instances, see
+    /// [`AppState::sync_statuses`].
+    pub status_store: Arc<dyn StatusStore>,
+    /// When the statuses taken over from other instances were written.
+    pub shared_statuses_seen: Arc<DashMap<i64, DateTime<Utc>>>,
+    /// Chats whose current status was taken over from another instance.
+    pub peer_statuses: Arc<DashSet<i64>>,
+    pub config: Arc<Config>,
+    /// The part of the configuration that can change while running, see
+    /// [`AppState::settings`].
+    pub settings: Arc<watch::Sender<Arc<Settings>>>,
+    pub cleanup_records: Arc<DashMap<i64, CleanupRecord>>,
+    pub cancelled_cleanups: Arc<DashSet<i64>>,
+    /// Broadcasts claimed when the process started, see
+    /// [`AppState::reconcile`].
+    pub interrupted_broadcasts: Arc<DashSet<i32>>,
+    /// When each chat with a rate limit override was last sent to.
+    pub last_chat_sends: Arc<DashMap<i64, Instant>>,
+    /// Held while a broadcast is sent to a chat, see
+    /// [`AppState::chat_send_lock`].
+    pub chat_send_locks: Arc<DashMap<i64, Arc<Mutex<()>>>>,
+    pub health: Health,
+    pub telegram_activity: TelegramActivity,
+    pub rate_limiter: RateLimiter,
+    /// Shared by every bot, see [`AppState::alert`].
+    pub alerts: Alerts,
+    /// Client for downloading images given by URL.
+    pub http: reqwest::Client,
+    /// Id of the bot's own user, see [`AppState::bot_user_id`].
+    me: Arc<OnceCell<UserId>>,
+    /// See [`AppState::telegram_chat`].
+    pub chat_cache: Arc<DashMap<i64, CachedChat>>,
+    /// See [`AppState::member_kinds`].
+    pub member_cache: Arc<DashMap<(i64, i64), CachedMemberKind>>,
+    /// See [`AppState::chat_filter`].
+    pub chat_filters: Arc<DashMap<i64, Option<Arc<ChatFilter>>>>,
+    /// Status changes for WebSocket clients, see [`AppState::serve_live`].
+    pub live: LiveUpdates,
 }
 
-#[derive(Serialize)]
+/// Serialized as `{"state": "error", "detail": "..."}`, the state named
+/// like [`CleanupState`] and `detail` missing for states without one.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "state", content = "detail", rename_all = "snake_case")]
 pub enum ChatCleaningStatus {
     Idle,
     Queued,
-    InProgress,
+    InProgress(CleanupProgress),
     Error(String),
+    /// The cleanup was stopped on request after processing this many members.
+    Cancelled(usize),
+    /// The stored members are checked against telegram, see
+    /// [`AppState::resync_members`].
+    Resyncing(ResyncProgress),
+    /// The process stopped during this cleanup, see
+    /// [`AppState::reconcile`]. Members kicked before are gone from the
+    /// storage, running it again goes on with the rest.
+    Resumable(InterruptedCleanup),
+}
+
+/// The kind of [`ChatCleaningStatus`] without its details, for filtering.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum CleanupState {
+    Idle,
+    Queued,
+    InProgress,
+    Error,
+    Cancelled,
+    Resyncing,
+    Resumable,
+}
+
+impl ChatCleaningStatus {
+    pub fn state(&self) -> CleanupState {
+        match self {
+            ChatCleaningStatus::Idle => CleanupState::Idle,
+            ChatCleaningStatus::Queued => CleanupState::Queued,
+            ChatCleaningStatus::InProgress(_) => CleanupState::InProgress,
+            ChatCleaningStatus::Error(_) => CleanupState::Error,
+            ChatCleaningStatus::Cancelled(_) => CleanupState::Cancelled,
+            ChatCleaningStatus::Resyncing(_) => CleanupState::Resyncing,
+            ChatCleaningStatus::Resumable(_) => CleanupState::Resumable,
+        }
+    }
+
+    /// Whether a cleanup or resync is queued or running.
+    pub fn is_running(&self) -> bool {
+        matches!(
+            self,
+            ChatCleaningStatus::Queued
+                | ChatCleaningStatus::InProgress(_)
+                | ChatCleaningStatus::Resyncing(_)
+        )
+    }
+}
+
+/// Live counters of a running cleanup, updated after every member.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct CleanupProgress {
+    pub total: usize,
+    pub processed: usize,
+    pub kicked: usize,
+    pub failed: usize,
+    pub started_at: DateTime<Utc>,
+    pub eta: Option<DateTime<Utc>>,
+}
+
+impl CleanupProgress {
+    pub fn new(total: usize) -> Self {
+        Self {
+            total,
+            processed: 0,
+            kicked: 0,
+            failed: 0,
+            started_at: Utc::now(),
+            eta: None,
+        }
+    }
+
+    pub fn record(&mut self, kicked: bool) {
+        self.processed += 1;
+        if kicked {
+            self.kicked += 1;
+        } else {
+            self.failed += 1;
+        }
+
+        let elapsed = Utc::now() - self.started_at;
+        let remaining = (self.total - self.processed) as i32;
+        self.eta = Some(Utc::now() + elapsed / self.processed as i32 * remaining);
+    }
+}
+
+#[derive(Clone, Default, Serialize, ToSchema)]
+pub struct CleanupRecord {
+    pub last_cleanup_at: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+    /// Counters of the last member resync that ran to the end.
+    pub last_resync: Option<ResyncProgress>,
+}
+
+/// Outcome of queueing cleanups for a list of chats.
+#[derive(Debug, Default, Serialize, ToSchema)]
+pub struct ClearChatsResult {
+    /// Chats whose cleanup was started.
+    pub queued: Vec<i64>,
+    /// Chats with a cleanup already queued or running.
+    pub skipped: Vec<i64>,
+    /// Chats the bot doesn't know.
+    pub unknown: Vec<i64>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ChatStatus {
+    pub chat_id: i64,
+    pub status: ChatCleaningStatus,
+    #[serde(flatten)]
+    pub record: CleanupRecord,
+    pub member_count: i64,
+    pub cleanup_schedule: Option<CleanupSchedule>,
+}
+
+/// One chat of the status overview, joining the stored chat with its
+/// in-memory cleanup status.
+#[derive(Serialize, ToSchema)]
+pub struct ChatStatusSummary {
+    pub chat_id: i64,
+    pub name: String,
+    pub status: CleanupState,
+    /// Counters of the running cleanup.
+    pub progress: Option<CleanupProgress>,
+    /// Error of the current status, or of the last failed cleanup.
+    pub error: Option<String>,
+    pub last_cleanup_at: Option<DateTime<Utc>>,
+    pub member_count: i64,
+    /// Queued messages not sent yet that will reach the chat.
+    pub queued_broadcasts: i64,
 }
 
 #[derive(Clone)]
-struct QueuedMessage {
-    id: i32,
-    chats: Vec<i64>,
-    message: String,
-    images: Vec<String>,
-    datetime: String,
+pub struct QueuedMessage {
+    pub id: i32,
+    pub bot_id: String,
+    pub chats: Vec<i64>,
+    /// `chats` is filled with the whole audience when the message is sent.
+    pub all_chats: bool,
+    pub message: String,
+    pub images: Vec<String>,
+    pub datetime: DateTime<Utc>,
+    pub parse_mode: String,
+    pub pin: bool,
+    pub pin_silently: bool,
+    pub silent: bool,
+    pub disable_web_page_preview: bool,
+    pub protect_content: bool,
+    pub audience: String,
+    pub copy_from_chat_id: Option<i64>,
+    pub copy_message_id: Option<i32>,
+    pub reply_to_queue_id: Option<i32>,
+    pub datetime_local: Option<NaiveDateTime>,
+    pub collect_replies: bool,
+    pub delete_after_seconds: Option<i32>,
+    pub channel_silent: Option<bool>,
+    pub channel_protect_content: Option<bool>,
+    /// The API request that queued the message.
+    pub request_id: Option<String>,
+}
+
+impl QueuedMessage {
+    pub fn options(&self) -> anyhow::Result<MessageOptions> {
+        Ok(MessageOptions {
+            parse_mode: self.parse_mode.parse()?,
+            pin: self.pin,
+            pin_silently: self.pin_silently,
+            silent: self.silent,
+            disable_web_page_preview: self.disable_web_page_preview,
+            protect_content: self.protect_content,
+            audience: self.audience.parse()?,
+            reply_to: self.reply_to_queue_id,
+            datetime_local: self.datetime_local,
+            collect_replies: self.collect_replies,
+            delete_after_seconds: self.delete_after_seconds,
+            channel_silent: self.channel_silent,
+            channel_protect_content: self.channel_protect_content,
+            thread_id: None,
+            chat_interval: None,
+            reply_to_message_id: None,
+            unsubscribe_button: false,
+            draft_id: None,
+        })
+    }
 }
 
 impl AppState {
+    pub fn new(
+        config: Config,
+        storage: Arc<dyn Storage>,
+        pool: Option<PgPool>,
+        bot_id: &str,
+        bot: WrappedBot,
+    ) -> Self {
+        let rate_limiter =
+            RateLimiter::new(config.rate_limit.burst, config.rate_limit.refill_per_sec);
+        let http = outbound::client(
+            Duration::from_secs(config.image_download_timeout),
+            config.allow_private_urls,
+        );
+        let status_store: Arc<dyn StatusStore> = match (config.status_store, &pool) {
+            (StatusStoreKind::Postgres, Some(pool)) => Arc::new(PgStatusStore::new(pool.clone())),
+            _ => Arc::new(MemoryStatusStore),
+        };
+        let settings = settings::channel(&config);
+
+        Self {
+            storage,
+            pool,
+            bot_id: bot_id.into(),
+            bot,
+            chats_status: Arc::new(DashMap::new()),
+            status_store,
+            shared_statuses_seen: Arc::new(DashMap::new()),
+            peer_statuses: Arc::new(DashSet::new()),
+            config: Arc::new(config),
+            settings,
+            cleanup_records: Arc::new(DashMap::new()),
+            cancelled_cleanups: Arc::new(DashSet::new()),
+            interrupted_broadcasts: Arc::new(DashSet::new()),
+            last_chat_sends: Arc::new(DashMap::new()),
+            chat_send_locks: Arc::new(DashMap::new()),
+            health: Health::default(),
+            telegram_activity: TelegramActivity::default(),
+            rate_limiter,
+            alerts: Alerts::default(),
+            http,
+            me: Arc::new(OnceCell::new()),
+            chat_cache: Arc::new(DashMap::new()),
+            member_cache: Arc::new(DashMap::new()),
+            chat_filters: Arc::new(DashMap::new()),
+            live: LiveUpdates::default(),
+        }
+    }
+
+    /// A state for another bot sharing the storage, configuration, health and
+    /// API rate limit, with its own chat statuses and settings.
+    pub fn for_bot(&self, bot_id: &str, bot: WrappedBot) -> Self {
+        Self {
+            bot_id: bot_id.into(),
+            bot,
+            settings: settings::channel(&self.config),
+            chats_status: Arc::new(DashMap::new()),
+            shared_statuses_seen: Arc::new(DashMap::new()),
+            peer_statuses: Arc::new(DashSet::new()),
+            cleanup_records: Arc::new(DashMap::new()),
+            cancelled_cleanups: Arc::new(DashSet::new()),
+            interrupted_broadcasts: Arc::new(DashSet::new()),
+            chat_send_locks: Arc::new(DashMap::new()),
+            me: Arc::new(OnceCell::new()),
+            chat_cache: Arc::new(DashMap::new()),
+            member_cache: Arc::new(DashMap::new()),
+            live: LiveUpdates::default(),
+            telegram_activity: TelegramActivity::default(),
+            ..self.clone()
+        }
+    }
+
+    /// The postgres pool, for features that are not available with other
+    /// storage backends.
+    pub fn pg(&self) -> anyhow::Result<&PgPool> {
+        self.pool.as_ref().ok_or_else(|| PostgresRequired.into())
+    }
+
     pub async fn fill_status_list(&self) -> anyhow::Result<()> {
         let actual_chats = self.get_chats().await?;
         for chat in &actual_chats {
@@ -57,87 +416,355 @@ impl AppState {
     }
 
     pub fn set_chat_status(&self, chat_id: i64, status: ChatCleaningStatus) -> anyhow::Result<()> {
-        *self
+        if let ChatCleaningStatus::Error(err) = &status {
+            self.cleanup_records.entry(chat_id).or_default().last_error = Some(err.clone());
+            // a failed cleanup must not leave a cancel behind for the next one
+            self.cancelled_cleanups.remove(&chat_id);
+        }
+
+        let mut current = self
             .chats_status
             .get_mut(&chat_id)
-            .context("Chat not found")? = status;
+            .context("Chat not found")?;
+        self.publish_status(chat_id, &status);
+        self.share_status(chat_id, Some(&status));
+        self.peer_statuses.remove(&chat_id);
+        *current = status;
 
         Ok(())
     }
 
-    pub async fn get_chats(&self) -> anyhow::Result<Chats> {
-        let chats = sqlx::query_as!(
-            Chat,
-            r#"
-SELECT id, name FROM tg_chat 
-            "#
-        )
-        .fetch_all(&self.pool)
-        .await?;
+    pub async fn get_chat_status(&self, chat_id: i64) -> anyhow::Result<Option<ChatStatus>> {
+        let status = match self.chats_status.get(&chat_id) {
+            Some(status) => status.clone(),
+            None => return Ok(None),
+        };
+        let record = self
+            .cleanup_records
+            .get(&chat_id)
+            .map(|record| record.clone())
+            .unwrap_or_default();
+
+        let member_count = self.storage.count_members(chat_id).await?;
+
+        let cleanup_schedule = match self.pool {
+            Some(_) => self.get_cleanup_schedule(chat_id).await?,
+            None => None,
+        };
 
-        Ok(chats)
+        Ok(Some(ChatStatus {
+            chat_id,
+            status,
+            record,
+            member_count,
+            cleanup_schedule,
+        }))
+    }
+
+    /// Every chat of the bot, optionally only those in the given state.
+    pub async fn get_status_overview(
+        &self,
+        state: Option<CleanupState>,
+    ) -> anyhow::Result<Vec<ChatStatusSummary>> {
+        let chats = self.storage.get_chat_activity(&self.bot_id).await?;
+
+        let overview = chats
+            .into_iter()
+            .map(|chat| {
+                let status = self
+                    .chats_status
+                    .get(&chat.id)
+                    .map(|status| status.clone())
+                    .unwrap_or(ChatCleaningStatus::Idle);
+                let record = self
+                    .cleanup_records
+                    .get(&chat.id)
+                    .map(|record| record.clone())
+                    .unwrap_or_default();
+                let (progress, error) = match &status {
+                    ChatCleaningStatus::InProgress(progress) => (Some(progress.clone()), None),
+                    ChatCleaningStatus::Error(err) => (None, Some(err.clone())),
+                    _ => (None, record.last_error),
+                };
+
+                ChatStatusSummary {
+                    chat_id: chat.id,
+                    name: chat.name,
+                    status: status.state(),
+                    progress,
+                    error,
+                    last_cleanup_at: record.last_cleanup_at,
+                    member_count: chat.member_count,
+                    queued_broadcasts: chat.queued_broadcasts,
+                }
+            })
+            .filter(|summary| state.is_none_or(|state| summary.status == state))
+            .collect();
+
+        Ok(overview)
+    }
+
+    pub async fn get_chats(&self) -> anyhow::Result<Chats> {
+        self.storage.get_chats(&self.bot_id).await
     }
 
     // pub async fn get_status(&self) -> anyhow::Result<DashMap<i64, ChatCleaningStatus>> {
     //     Ok(self.chats_status)
     // }
 
-    pub async fn new_chat(&self, chat: &teloxide::types::Chat) -> anyhow::Result<()> {
+    /// Registers the chat and returns whether the bot may act in it. With the
+    /// allow-list enabled a chat seen for the first time is stored unapproved
+    /// and the admins are asked to approve it. The bot leaves chats another
+    /// bot of the service already serves.
+    #[instrument(skip_all, fields(chat_id = chat.id.0))]
+    pub async fn new_chat(&self, chat: &teloxide::types::Chat) -> anyhow::Result<bool> {
         info!("adding a new chat:{chat:?}");
         let id = chat.id.0;
         let name = chat.title().unwrap_or_default();
 
+        let Some(row) = self
+            .storage
+            .upsert_chat(
+                &self.bot_id,
+                id,
+                name,
+                !self.config.allowlist,
+                chat_kind(chat),
+                chat.username(),
+            )
+            .await?
+        else {
+            // a chat is kept for a single bot, a second one is turned away
+            warn!("chat:{id} belongs to another bot, leaving it");
+            if let Err(err) = self.bot.leave_chat(chat.id).await {
+                error!("failed to leave chat:{id} {err}");
+            }
+            return Ok(false);
+        };
+        if row.archived {
+            info!("chat:{id} is archived, ignoring it until it is restored");
+            return Ok(false);
+        }
+
         self.chats_status
             .entry(id)
             .or_insert(ChatCleaningStatus::Idle);
 
-        sqlx::query!(
-            r#"
-INSERT INTO tg_chat ( id, name )
-VALUES ( $1, $2 )
-ON CONFLICT (id) DO UPDATE
-SET name = $2
-            "#,
-            id,
-            name
-        )
-        .execute(&self.pool)
-        .await?;
+        if row.inserted {
+            self.notify(
+                WebhookEvent::ChatAdded,
+                json!({ "chat_id": id, "name": name, "approved": row.approved }),
+            );
+            // the rest is filled in by the periodic sync, don't fail the update over it
+            if let Err(err) = self.refresh_chat_metadata(id).await {
+                error!("failed to fetch metadata of chat:{id} {err}");
+            }
+            if !row.approved {
+                self.request_chat_approval(id, name).await;
+            }
+        }
 
-        Ok(())
+        Ok(row.approved)
+    }
+
+    async fn request_chat_approval(&self, chat_id: i64, name: &str) {
+        info!("chat:{chat_id} is waiting for approval");
+
+        let text =
+            format!("Chat \"{name}\" ({chat_id}) added the bot and is waiting for approval.");
+        for &admin in &self.settings().admin_ids {
+            if let Err(err) = self.bot.send_message(ChatId(admin), &text).await {
+                error!("failed to notify admin {admin} about chat:{chat_id} {err}");
+            }
+        }
+    }
+
+    /// Returns `false` when the chat is unknown.
+    pub async fn approve_chat(&self, chat_id: i64) -> anyhow::Result<bool> {
+        info!("approving chat:{chat_id}");
+
+        self.storage.approve_chat(&self.bot_id, chat_id).await
+    }
+
+    pub async fn get_unapproved_chats(&self) -> anyhow::Result<Vec<i64>> {
+        self.storage.get_unapproved_chats(&self.bot_id).await
     }
 
-    pub async fn delete_chat(&self, chat_id: i64) -> anyhow::Result<()> {
+    /// Returns `false` when the chat is unknown.
+    pub async fn set_chat_muted(&self, chat_id: i64, muted: bool) -> anyhow::Result<bool> {
+        info!("setting muted:{muted} for chat:{chat_id}");
+
+        self.storage
+            .set_chat_muted(&self.bot_id, chat_id, muted)
+            .await
+    }
+
+    /// Returns `false` when the chat is unknown.
+    pub async fn set_delete_service_messages(
+        &self,
+        chat_id: i64,
+        delete: bool,
+    ) -> anyhow::Result<bool> {
+        info!("setting delete_service_messages:{delete} for chat:{chat_id}");
+
+        self.storage
+            .set_delete_service_messages(&self.bot_id, chat_id, delete)
+            .await
+    }
+
+    /// Whether join and leave messages are deleted from the chat. Chats that
+    /// aren't stored get the default of deleting them.
+    pub async fn deletes_service_messages(&self, chat_id: i64) -> anyhow::Result<bool> {
+        Ok(self
+            .storage
+            .deletes_service_messages(chat_id)
+            .await?
+            .unwrap_or(true))
+    }
+
+    /// Slows broadcasts to the chat down to `per_minute` messages, `None`
+    /// goes back to the global throttle. Returns `false` when the chat is
+    /// unknown.
+    pub async fn set_chat_rate_limit(
+        &self,
+        chat_id: i64,
+        per_minute: Option<i32>,
+    ) -> anyhow::Result<bool> {
+        info!("setting rate limit override {per_minute:?} for chat:{chat_id}");
+
+        self.storage
+            .set_chat_rate_limit(&self.bot_id, chat_id, per_minute)
+            .await
+    }
+
+    pub async fn get_muted_chats(&self) -> anyhow::Result<Vec<i64>> {
+        self.storage.get_muted_chats(&self.bot_id).await
+    }
+
+    /// Returns `false` when the bot has no such chat.
+    #[instrument(skip_all, fields(chat_id))]
+    pub async fn delete_chat(&self, chat_id: i64) -> anyhow::Result<bool> {
         info!("deleting a chat:{chat_id}");
 
+        if !self.storage.delete_chat(&self.bot_id, chat_id).await? {
+            return Ok(false);
+        }
         self.chats_status.remove(&chat_id);
+        self.peer_statuses.remove(&chat_id);
+        self.share_status(chat_id, None);
+        self.cleanup_records.remove(&chat_id);
+        self.notify(WebhookEvent::ChatRemoved, json!({ "chat_id": chat_id }));
 
-        sqlx::query!(
-            r#"
-DELETE FROM tg_chat
-WHERE id = $1
-            "#,
-            chat_id,
-        )
-        .execute(&self.pool)
-        .await?;
+        Ok(true)
+    }
+
+    /// Mutes or unmutes the chats in one transaction. Every requested chat is
+    /// listed in the result, the unknown ones with an error.
+    pub async fn set_chats_muted(
+        &self,
+        ids: &[i64],
+        muted: bool,
+    ) -> anyhow::Result<Vec<ChatResult>> {
+        info!("setting muted:{muted} for chats: {ids:?}");
+
+        let updated = self
+            .storage
+            .set_chats_muted(&self.bot_id, ids, muted)
+            .await?;
+
+        Ok(chat_results(ids, &updated))
+    }
+
+    /// Deletes the chats in one transaction. Every requested chat is listed
+    /// in the result, the unknown ones with an error.
+    pub async fn delete_chats(&self, ids: &[i64]) -> anyhow::Result<Vec<ChatResult>> {
+        info!("deleting chats: {ids:?}");
+
+        let deleted = self.storage.delete_chats(&self.bot_id, ids).await?;
+        for &chat_id in &deleted {
+            self.chats_status.remove(&chat_id);
+            self.peer_statuses.remove(&chat_id);
+            self.share_status(chat_id, None);
+            self.cleanup_records.remove(&chat_id);
+            self.notify(WebhookEvent::ChatRemoved, json!({ "chat_id": chat_id }));
+        }
+
+        Ok(chat_results(ids, &deleted))
+    }
+
+    /// Stops tracking a chat the bot is no longer in, keeping its members
+    /// and history until the chat is restored.
+    #[instrument(skip_all, fields(chat_id))]
+    pub async fn archive_chat(&self, chat_id: i64) -> anyhow::Result<()> {
+        info!("archiving a chat:{chat_id}");
+
+        self.chats_status.remove(&chat_id);
+        self.peer_statuses.remove(&chat_id);
+        self.share_status(chat_id, None);
+        self.cleanup_records.remove(&chat_id);
+
+        self.storage
+            .set_chat_archived(&self.bot_id, chat_id, true)
+            .await?;
 
         Ok(())
     }
 
+    /// Archives a chat the bot was kicked from or left.
+    pub async fn archive_kicked_chat(&self, chat_id: i64, name: &str) -> anyhow::Result<()> {
+        self.notify(
+            WebhookEvent::BotKicked,
+            json!({ "chat_id": chat_id, "name": name }),
+        );
+        self.alert(
+            &format!("kicked:{chat_id}"),
+            &format!("the bot was removed from chat {name} ({chat_id})"),
+        );
+
+        self.archive_chat(chat_id).await
+    }
+
+    /// Tracks an archived chat again once the bot is back in it. Returns
+    /// `false` when there is no such archived chat.
+    pub async fn restore_chat(&self, chat_id: i64) -> anyhow::Result<bool> {
+        info!("restoring a chat:{chat_id}");
+
+        if !self
+            .storage
+            .set_chat_archived(&self.bot_id, chat_id, false)
+            .await?
+        {
+            return Ok(false);
+        }
+
+        self.chats_status
+            .entry(chat_id)
+            .or_insert(ChatCleaningStatus::Idle);
+        if let Err(err) = self.refresh_chat_metadata(chat_id).await {
+            error!("failed to fetch metadata of chat:{chat_id} {err}");
+        }
+
+        Ok(true)
+    }
+
     pub async fn get_all_members(&self, chat_id: i64) -> anyhow::Result<Users> {
-        let users = sqlx::query_as!(
-            User,
-            r#"
-SELECT id, chat_id, username, name FROM tg_user
-WHERE chat_id = $1
-            "#,
-            chat_id
-        )
-        .fetch_all(&self.pool)
-        .await?;
+        self.storage.get_all_members(chat_id).await
+    }
+
+    pub async fn get_members_page(
+        &self,
+        chat_id: i64,
+        search: Option<String>,
+        limit: i64,
+        offset: i64,
+    ) -> anyhow::Result<Users> {
+        self.storage
+            .get_members_page(chat_id, search.as_deref(), limit, offset)
+            .await
+    }
 
-        Ok(users)
+    pub async fn get_member(&self, chat_id: i64, user_id: i64) -> anyhow::Result<Option<User>> {
+        self.storage.get_member(chat_id, user_id).await
     }
 
     pub async fn new_chat_member(
@@ -148,34 +775,35 @@ WHERE chat_id = $1
         info!("adding a new chat member:{member:?} to chat:{chat_id} ...");
         let id = member.id.0 as i64;
 
-        if id == self.bot.get_me().await?.id.0 as i64 {
+        if id == self.bot_user_id().await?.0 as i64 {
             info!("ignoring self...");
             return Ok(());
         }
 
-        let username = member.username.clone().unwrap_or_default();
-        let name = member.full_name();
-
         let chat_member = self.bot.get_chat_member(ChatId(chat_id), member.id).await?;
         if chat_member.is_privileged() {
             info!("ignored an admin.");
             return Ok(());
         }
 
-        sqlx::query!(
-            r#"
-INSERT INTO tg_user ( id, chat_id, username, name )
-VALUES ( $1, $2, $3, $4 )
-ON CONFLICT ( id, chat_id ) DO UPDATE
-SET username = $3, name = $4
-            "#,
-            id,
-            chat_id,
-            username,
-            name
-        )
-        .execute(&self.pool)
-        .await?;
+        self.upsert_chat_member(chat_id, member).await
+    }
+
+    /// Stores a member without asking telegram about them, for callers that
+    /// already know the member is a regular one.
+    pub async fn upsert_chat_member(
+        &self,
+        chat_id: i64,
+        member: &teloxide::types::User,
+    ) -> anyhow::Result<()> {
+        let id = member.id.0 as i64;
+        let username = member.username.clone().unwrap_or_default();
+        let name = member.full_name();
+
+        self.storage
+            .upsert_member(&self.bot_id, chat_id, id, &username, &name)
+            .await?;
+        self.publish_member_count(chat_id).await;
 
         Ok(())
     }
@@ -199,52 +827,29 @@ SET username = $3, name = $4
         info!("removing a chat member:{member:?} from chat:{chat_id}");
         let id = member.id.0 as i64;
 
-        sqlx::query!(
-            r#"
-DELETE FROM tg_user
-WHERE id = $1 AND chat_id = $2
-            "#,
-            id,
-            chat_id,
-        )
-        .execute(&self.pool)
-        .await?;
+        self.storage.remove_member(chat_id, id).await?;
+        self.publish_member_count(chat_id).await;
 
         Ok(())
     }
 
     pub async fn remove_chat_member_by_id(&self, chat_id: i64, user_id: i64) -> anyhow::Result<()> {
         info!("removing a chat member by id:{user_id} from chat:{chat_id}");
-        sqlx::query!(
-            r#"
-DELETE FROM tg_user
-WHERE id = $1 AND chat_id = $2
-            "#,
-            user_id,
-            chat_id,
-        )
-        .execute(&self.pool)
-        .await?;
+
+        self.storage.remove_member(chat_id, user_id).await?;
+        self.publish_member_count(chat_id).await;
 
         Ok(())
     }
 
+    #[instrument(skip_all, fields(chat_id))]
     pub async fn cleanup_chat(&self, chat_id: i64) -> anyhow::Result<()> {
         info!("got a request to cleanup chat:{chat_id}");
 
-        for user in self.get_all_members(chat_id).await? {
-            let member = match {
-                self.bot
-                    .get_chat_member(ChatId(chat_id), UserId(user.id as u64))
-                    .await
-            } {
-                Ok(member) => member,
-                Err(err) => {
-                    error!("error while getting a user: {err}");
-                    continue;
-                }
-            };
-            match member.kind {
+        let members = self.get_all_members(chat_id).await?;
+        let members = self.without_excluded(chat_id, members).await?;
+        for (user, kind) in self.member_kinds(chat_id, members).await? {
+            match kind {
                 teloxide::types::ChatMemberKind::Restricted(_)
                 | teloxide::types::ChatMemberKind::Member => (),
                 kind => {
@@ -259,180 +864,528 @@ WHERE id = $1 AND chat_id = $2
         Ok(())
     }
 
-    pub async fn delete_all_members(&self, chat_id: i64) -> anyhow::Result<()> {
-        info!("deleting all members from chat:{chat_id}");
+    pub async fn log_kick(
+        &self,
+        chat_id: i64,
+        user: &User,
+        reason: &str,
+        initiator: &str,
+    ) -> anyhow::Result<()> {
+        self.storage
+            .log_kick(
+                chat_id,
+                user,
+                reason,
+                initiator,
+                current_request_id().as_deref(),
+            )
+            .await
+    }
 
-        self.set_chat_status(chat_id, ChatCleaningStatus::InProgress)?;
+    pub async fn get_kicks(&self, chat_id: i64, limit: i64, offset: i64) -> anyhow::Result<Kicks> {
+        self.storage.get_kicks(chat_id, limit, offset).await
+    }
+
+    #[instrument(skip_all, fields(chat_id, initiator))]
+    pub async fn delete_all_members(&self, chat_id: i64, initiator: &str) -> anyhow::Result<()> {
+        let run = self.delete_all_members_inner(chat_id, initiator);
+        self.record_cleanup_run(chat_id, false, initiator, run)
+            .await
+    }
+
+    async fn delete_all_members_inner(&self, chat_id: i64, initiator: &str) -> anyhow::Result<()> {
+        info!("deleting all members from chat:{chat_id} initiated by {initiator}");
+
+        // cancelled while still queued
+        if self.cancelled_cleanups.remove(&chat_id).is_some() {
+            self.set_chat_status(chat_id, ChatCleaningStatus::Cancelled(0))?;
+            return Ok(());
+        }
 
-        let chat = self.bot.get_chat(ChatId(chat_id)).await?;
+        self.set_chat_status(
+            chat_id,
+            ChatCleaningStatus::InProgress(CleanupProgress::new(0)),
+        )?;
 
+        let window = self.cleanup_window(chat_id).await?;
         self.cleanup_chat(chat_id).await?;
 
-        for user in self.get_all_members(chat_id).await? {
-            let ban_result = if chat.is_supergroup() || chat.is_channel() {
-                self.bot
-                    .unban_chat_member(ChatId(chat_id), UserId(user.id as u64))
-                    .await
-            } else {
-                self.bot
-                    .kick_chat_member(ChatId(chat_id), UserId(user.id as u64))
-                    .await
+        let members = self.get_all_members(chat_id).await?;
+        match self
+            .kick_members_within(chat_id, members, "cleanup", initiator, &window)
+            .await?
+        {
+            KickRun::Finished => {}
+            KickRun::Cancelled => return Ok(()),
+            KickRun::Paused(resume_at) => {
+                return self.pause_cleanup(chat_id, initiator, resume_at).await
+            }
+        }
+
+        self.notify_cleanup_finished(chat_id, "cleanup", initiator);
+        self.set_chat_status(chat_id, ChatCleaningStatus::Idle)?;
+        self.cleanup_records
+            .entry(chat_id)
+            .or_default()
+            .last_cleanup_at = Some(Utc::now());
+
+        info!("done deleting all member from chat:{chat_id}");
+
+        Ok(())
+    }
+
+    /// Kicks the given members one by one, keeping the `InProgress` status up
+    /// to date. Excluded users are never kicked. Returns `false` when the run
+    /// was cancelled part way, in which case the status is already
+    /// `Cancelled`.
+    pub async fn kick_members(
+        &self,
+        chat_id: i64,
+        members: Users,
+        reason: &str,
+        initiator: &str,
+    ) -> anyhow::Result<bool> {
+        let window = CleanupWindow::default();
+        let run = self
+            .kick_members_within(chat_id, members, reason, initiator, &window)
+            .await?;
+
+        Ok(run != KickRun::Cancelled)
+    }
+
+    /// [`AppState::kick_members`] in the order of their ids, after the last
+    /// member of an earlier run and only until the window runs out or
+    /// telegram asks for a longer wait than it allows.
+    #[instrument(skip_all, fields(chat_id, reason))]
+    pub async fn kick_members_within(
+        &self,
+        chat_id: i64,
+        members: Users,
+        reason: &str,
+        initiator: &str,
+        window: &CleanupWindow,
+    ) -> anyhow::Result<KickRun> {
+        let mut members = self.without_excluded(chat_id, members).await?;
+        if let Some(last_user_id) = window.last_user_id {
+            members.retain(|user| user.id > last_user_id);
+        }
+        members.sort_by_key(|user| user.id);
+        let chat = self.telegram_chat(chat_id).await?;
+
+        let mut progress = CleanupProgress::new(members.len());
+        self.set_chat_status(chat_id, ChatCleaningStatus::InProgress(progress.clone()))?;
+
+        for user in members {
+            if self.cancelled_cleanups.remove(&chat_id).is_some() {
+                info!(
+                    "cleanup of chat:{chat_id} cancelled after {} members",
+                    progress.processed
+                );
+                self.set_chat_status(chat_id, ChatCleaningStatus::Cancelled(progress.processed))?;
+                return Ok(KickRun::Cancelled);
+            }
+            if window
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+            {
+                info!(
+                    "cleanup of chat:{chat_id} ran out of time after {} members",
+                    progress.processed
+                );
+                let resume_at =
+                    Utc::now() + chrono::Duration::from_std(self.config.cleanup_resume_delay())?;
+                return Ok(KickRun::Paused(resume_at));
+            }
+
+            let ban_result = loop {
+                let result = self.remove_from_chat(&chat, UserId(user.id as u64)).await;
+                match result.as_ref().map_err(TelegramErrorClass::of) {
+                    Err(TelegramErrorClass::RateLimited(wait))
+                        if window.max_flood_wait.is_some_and(|max| wait > max) =>
+                    {
+                        info!("kicks in chat:{chat_id} are rate limited for {wait:?}, pausing");
+                        return Ok(KickRun::Paused(
+                            Utc::now() + chrono::Duration::from_std(wait)?,
+                        ));
+                    }
+                    Err(TelegramErrorClass::RateLimited(wait)) => {
+                        info!("kicks in chat:{chat_id} are rate limited, waiting {wait:?}");
+                        tokio::time::sleep(wait).await;
+                    }
+                    _ => break result,
+                }
             };
             match ban_result {
                 Ok(_) => {
+                    self.log_kick(chat_id, &user, reason, initiator).await?;
                     self.remove_chat_member_by_id(chat_id, user.id).await?;
+                    self.forget_member_kind(chat_id, user.id);
+                    progress.record(true);
                 }
                 Err(err) => {
-                    error!("Failed to delete a user:{user:?}! {err}");
+                    member_failed(err, "kick", chat_id, user.id)?;
+                    progress.record(false);
                 }
             };
+            if window.resumable {
+                self.storage.set_cleanup_cursor(chat_id, user.id).await?;
+            }
+            self.set_chat_status(chat_id, ChatCleaningStatus::InProgress(progress.clone()))?;
         }
 
-        self.set_chat_status(chat_id, ChatCleaningStatus::Idle)?;
+        // a cancel that arrived after the last member has nothing left to stop
+        self.cancelled_cleanups.remove(&chat_id);
 
-        info!("done deleting all member from chat:{chat_id}");
+        Ok(KickRun::Finished)
+    }
 
-        Ok(())
+    /// Marks the chat as `Queued` unless a cleanup is already queued or
+    /// running, in which case `false` is returned.
+    pub fn queue_cleanup(&self, chat_id: i64) -> anyhow::Result<bool> {
+        let mut status = self
+            .chats_status
+            .get_mut(&chat_id)
+            .context("chat not found")?;
+        match status.value() {
+            ChatCleaningStatus::Idle
+            | ChatCleaningStatus::Error(_)
+            | ChatCleaningStatus::Cancelled(_)
+            | ChatCleaningStatus::Resumable(_) => {
+                *status.value_mut() = ChatCleaningStatus::Queued;
+                self.share_status(chat_id, Some(status.value()));
+                self.peer_statuses.remove(&chat_id);
+                Ok(true)
+            }
+            ChatCleaningStatus::Queued
+            | ChatCleaningStatus::InProgress(_)
+            | ChatCleaningStatus::Resyncing(_) => Ok(false),
+        }
     }
 
-    pub async fn clear_chats(&self, chats: Vec<i64>) -> anyhow::Result<()> {
-        let mut chats_to_clean = Vec::new();
+    /// Queues a cleanup for every known chat of the list. Unknown chats and
+    /// chats with a cleanup already queued or running are left alone and
+    /// reported back.
+    pub fn queue_cleanups(&self, chats: Vec<i64>) -> ClearChatsResult {
+        let mut result = ClearChatsResult::default();
         for chat_id in chats {
-            let mut status = self
-                .chats_status
-                .get_mut(&chat_id)
-                .context("chat not found")?;
-            let valmut = status.value_mut();
-            match valmut {
-                ChatCleaningStatus::Idle | ChatCleaningStatus::Error(_) => {
-                    // self.set_chat_status(chat_id, ChatCleaningStatus::Queued)?;
-                    *valmut = ChatCleaningStatus::Queued;
-                    chats_to_clean.push(chat_id);
-                }
-                ChatCleaningStatus::Queued | ChatCleaningStatus::InProgress => {}
+            match self.queue_cleanup(chat_id) {
+                Ok(true) => result.queued.push(chat_id),
+                Ok(false) => result.skipped.push(chat_id),
+                Err(_) => result.unknown.push(chat_id),
             }
         }
 
-        for chat_id in chats_to_clean {
-            if let Err(e) = self.delete_all_members(chat_id).await {
-                self.set_chat_status(chat_id, ChatCleaningStatus::Error(e.to_string()))?
+        result
+    }
+
+    /// Runs the cleanups of chats queued by [`AppState::queue_cleanups`] one
+    /// after another, a failing chat doesn't stop the rest. Staged cleanups
+    /// only restrict the members, see [`AppState::stage_all_members`].
+    pub async fn clear_chats(&self, chats: Vec<i64>, initiator: &str, stage: bool) {
+        for chat_id in chats {
+            let result = if stage {
+                self.stage_all_members(chat_id, initiator).await
+            } else {
+                self.delete_all_members(chat_id, initiator).await
             };
+            if let Err(e) = result {
+                if let Err(err) =
+                    self.set_chat_status(chat_id, ChatCleaningStatus::Error(e.to_string()))
+                {
+                    error!("failed to record the cleanup error of chat:{chat_id} {err}");
+                }
+            }
         }
+    }
 
-        Ok(())
+    /// Asks a queued or running cleanup, or a resync, to stop before its
+    /// next member. Returns false when there is nothing to cancel, and
+    /// [`CleanupElsewhere`] when it runs on another instance.
+    pub fn cancel_cleanup(&self, chat_id: i64) -> anyhow::Result<bool> {
+        let running = self
+            .chats_status
+            .get(&chat_id)
+            .is_some_and(|status| status.is_running());
+        if !running {
+            return Ok(false);
+        }
+        if self.peer_statuses.contains(&chat_id) {
+            return Err(CleanupElsewhere(chat_id).into());
+        }
+        info!("cancelling cleanup of chat:{chat_id}");
+        self.cancelled_cleanups.insert(chat_id);
+
+        Ok(true)
     }
 
+    #[instrument(skip_all, fields(chat_id))]
     pub async fn send_media_group(
         &self,
         chat_id: i64,
         images: Vec<InputMedia>,
-    ) -> anyhow::Result<()> {
+        options: &MessageOptions,
+    ) -> anyhow::Result<Vec<Message>> {
         info!("sending images to chat:{chat_id}");
 
-        match self.bot.send_media_group(ChatId(chat_id), images).await {
-            Ok(_) => {
+        let mut request = self
+            .bot
+            .send_media_group(ChatId(chat_id), images)
+            .disable_notification(options.silent)
+            .protect_content(options.protect_content);
+        if let Some(thread_id) = options.thread_id {
+            request = request.message_thread_id(thread_id);
+        }
+        options.reply_to(&mut request);
+
+        match request.await {
+            Ok(messages) => {
                 info!("sent media group to chat {chat_id}");
+                self.telegram_activity.record_success();
+                Ok(messages)
             }
             Err(err) => {
                 error!("error sending media group {err}");
+                Err(err.into())
             }
         }
-
-        Ok(())
     }
 
-    pub async fn send_message_to_chat(&self, chat_id: i64, message: &str) -> anyhow::Result<()> {
-        info!("sending message:{message} to chat:{chat_id}");
-
-        match self
+    #[instrument(skip_all, fields(chat_id))]
+    pub async fn send_message_to_chat(
+        &self,
+        chat_id: i64,
+        message: &str,
+        options: &MessageOptions,
+    ) -> anyhow::Result<Message> {
+        info!(
+            "sending message:{message} to chat:{chat_id} as {}",
+            options.parse_mode
+        );
+
+        let mut request = self
             .bot
             .send_message(ChatId(chat_id), message)
-            .parse_mode(ParseMode::MarkdownV2)
-            .await
-        {
-            Ok(_) => {
-                info!("sent message to chat {chat_id}")
+            .disable_notification(options.silent)
+            .disable_web_page_preview(options.disable_web_page_preview)
+            .protect_content(options.protect_content);
+        if let Some(parse_mode) = options.parse_mode.parse_mode() {
+            request = request.parse_mode(parse_mode);
+        }
+        if let Some(thread_id) = options.thread_id {
+            request = request.message_thread_id(thread_id);
+        }
+        options.reply_to(&mut request);
+        if let Some(markup) = self.unsubscribe_markup(options) {
+            request = request.reply_markup(markup);
+        }
+
+        match request.await {
+            Ok(sent) => {
+                info!("sent message to chat {chat_id}");
+                self.telegram_activity.record_success();
+                Ok(sent)
             }
             Err(err) => {
                 error!("error sending message {err}");
+                Err(err.into())
             }
-        };
-
-        Ok(())
+        }
     }
 
-    pub async fn send_message_with_images_to_chats(
+    /// `targets` of `None` is every chat, resolved when the message is sent.
+    pub async fn queue_message_with_images(
         &self,
-        chats: Vec<i64>,
-        message: String,
+        targets: impl Into<Targets>,
+        message: impl Into<LocalizedMessage>,
         images: Vec<String>,
-    ) -> anyhow::Result<()> {
-        let images: Vec<InputMedia> = images
-            .into_iter()
-            .map(|body| {
-                // let (body, _) = DataUrl::process(&i).unwrap().decode_to_vec().unwrap();
-                base64::engine::general_purpose::STANDARD.decode(body)
+        datetime: String,
+        options: MessageOptions,
+        mode: QueueMode,
+    ) -> anyhow::Result<i32> {
+        let message = message.into();
+        info!(
+            "queueing message: {} on datetime: {datetime}",
+            message.message
+        );
+        let targets = targets.into();
+        let (images, media) = self.prepare_queued_images(images).await?;
+
+        self.storage
+            .queue_message(NewQueuedMessage {
+                bot_id: &self.bot_id,
+                targets: targets.chats.as_deref(),
+                excluded_chats: &targets.excluded,
+                message: &message.message,
+                images: &images,
+                media: &media,
+                datetime: parse_datetime(&datetime)?,
+                options: &options,
+                awaiting_approval: mode == QueueMode::AwaitApproval,
+                copy_from: None,
+                variants: &[],
+                translations: &message.translations,
+                attachments: &message.attachments,
+                request_id: current_request_id().as_deref(),
+                duplicates_since: match mode {
+                    QueueMode::SendOnce => self.duplicates_since(&message.message),
+                    _ => None,
+                },
             })
-            .collect::<Result<Vec<Vec<u8>>, _>>()?
-            .into_iter()
-            .map(|i| InputMedia::Photo(InputMediaPhoto::new(InputFile::memory(i))))
-            .collect();
+            .await
+    }
 
-        for chat_id in chats {
-            if images.len() > 0 {
-                for chunk in images.chunks(10) {
-                    self.send_media_group(chat_id, chunk.to_vec()).await?;
-                }
-            }
-            self.send_message_to_chat(chat_id, &message).await?;
+    /// Prepares the inline images of a message to be queued, they are
+    /// replaced by references to the returned media which are stored with
+    /// the message. Images given by URL are only downloaded when the message
+    /// is sent.
+    pub async fn prepare_queued_images(
+        &self,
+        images: Vec<String>,
+    ) -> anyhow::Result<(Vec<String>, Vec<Media>)> {
+        let mut images = images;
+        let inline: Vec<usize> = (0..images.len())
+            .filter(|&i| !is_remote_image(&images[i]))
+            .collect();
+        let decoded = inline
+            .iter()
+            .map(|&i| decode_inline_image(&images[i]))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let mut media = Vec::with_capacity(inline.len());
+        for (i, image) in inline.into_iter().zip(self.prepare_images(decoded).await?) {
+            let image = Media::new(image);
+            images[i] = image.reference();
+            media.push(image);
         }
 
-        Ok(())
+        Ok((images, media))
     }
 
-    pub async fn queue_message_with_images(
+    /// Queues a message whose images were uploaded as raw files.
+    pub async fn queue_message_with_image_files(
+        &self,
+        targets: impl Into<Targets>,
+        message: impl Into<LocalizedMessage>,
+        images: Vec<Vec<u8>>,
+        datetime: String,
+        options: MessageOptions,
+        mode: QueueMode,
+    ) -> anyhow::Result<i32> {
+        let message = message.into();
+        info!(
+            "queueing message: {} with {} image files on datetime: {datetime}",
+            message.message,
+            images.len()
+        );
+        let targets = targets.into();
+        let media: Vec<Media> = self
+            .prepare_images(images)
+            .await?
+            .into_iter()
+            .map(Media::new)
+            .collect();
+        let images: Vec<String> = media.iter().map(Media::reference).collect();
+
+        self.storage
+            .queue_message(NewQueuedMessage {
+                bot_id: &self.bot_id,
+                targets: targets.chats.as_deref(),
+                excluded_chats: &targets.excluded,
+                message: &message.message,
+                images: &images,
+                media: &media,
+                datetime: parse_datetime(&datetime)?,
+                options: &options,
+                awaiting_approval: mode == QueueMode::AwaitApproval,
+                copy_from: None,
+                variants: &[],
+                translations: &message.translations,
+                attachments: &message.attachments,
+                request_id: current_request_id().as_deref(),
+                duplicates_since: match mode {
+                    QueueMode::SendOnce => self.duplicates_since(&message.message),
+                    _ => None,
+                },
+            })
+            .await
+    }
+
+    /// Queues a copy of an existing telegram message, `message` only keeps its
+    /// text for the history.
+    pub async fn queue_copied_message(
         &self,
         chats: Vec<i64>,
+        from_chat_id: i64,
+        message_id: i32,
         message: String,
-        images: Vec<String>,
         datetime: String,
-    ) -> anyhow::Result<()> {
-        info!("queueing message: {message} on datetime: {datetime}");
-
-        sqlx::query!(
-            r#"
-            INSERT INTO message_queue ( chats, message, images, datetime )
-            VALUES ( $1, $2, $3, $4 )
-            "#,
-            &chats,
-            message,
-            &images,
-            datetime
-        )
-        .execute(&self.pool)
-        .await?;
+        options: MessageOptions,
+    ) -> anyhow::Result<i32> {
+        info!("queueing copy of message {message_id} from chat:{from_chat_id} on datetime: {datetime}");
+
+        let targets: Vec<ChatTarget> = chats.into_iter().map(ChatTarget::Chat).collect();
+        self.storage
+            .queue_message(NewQueuedMessage {
+                bot_id: &self.bot_id,
+                targets: (options.audience == Audience::Chats).then_some(targets.as_slice()),
+                excluded_chats: &[],
+                message: &message,
+                images: &[],
+                media: &[],
+                datetime: parse_datetime(&datetime)?,
+                options: &options,
+                awaiting_approval: false,
+                copy_from: Some((from_chat_id, message_id)),
+                variants: &[],
+                translations: &Translations::new(),
+                attachments: &Attachments::default(),
+                request_id: current_request_id().as_deref(),
+                duplicates_since: None,
+            })
+            .await
+    }
 
-        Ok(())
+    /// Messages queued by other bots are `None` as well.
+    pub async fn get_queued_message(&self, id: i32) -> anyhow::Result<Option<QueuedMessage>> {
+        Ok(self
+            .storage
+            .get_queued_message(id)
+            .await?
+            .filter(|message| *message.bot_id == *self.bot_id))
     }
 
-    pub async fn remove_queued_message(&self, id: i32) -> anyhow::Result<()> {
-        info!("removing queued message: {id}");
+    /// Releases a previewed message to the queue. Returns `false` when there
+    /// is no such message waiting for approval.
+    pub async fn approve_queued_message(&self, id: i32) -> anyhow::Result<bool> {
+        info!("approving queued message: {id}");
 
-        sqlx::query!(
-            r#"
-            DELETE FROM message_queue
-            WHERE id = $1
-            "#,
-            id,
-        )
-        .execute(&self.pool)
-        .await?;
+        if self.get_queued_message(id).await?.is_none() {
+            return Ok(false);
+        }
 
-        Ok(())
+        self.storage.approve_queued_message(id).await
+    }
+
+    /// Images only the message referred to are cleaned up by
+    /// [`AppState::media_cleanup`].
+    pub async fn delete_queued_message(&self, id: i32) -> anyhow::Result<()> {
+        self.storage.delete_queued_message(id).await
+    }
+
+    pub async fn get_message_images(&self, message_id: i32) -> anyhow::Result<Vec<Vec<u8>>> {
+        self.storage.get_message_images(message_id).await
+    }
+
+    pub async fn complete_queued_message(&self, id: i32) -> anyhow::Result<()> {
+        info!("completing queued message: {id}");
+
+        self.storage.complete_queued_message(id).await
     }
 
     pub async fn cleanup_deprecated_chats(state: Self) -> anyhow::Result<()> {
         loop {
+            state.heartbeat("cleanup_deprecated_chats");
+            let interval = state.settings().intervals.cleanup_deprecated_chats();
+            let started = Instant::now();
             match state.cleanup_deprecated_chats_loop().await {
                 Ok(_) => {
                     info!("cleaned up deprecated chats!");
@@ -441,160 +1394,509 @@ WHERE id = $1 AND chat_id = $2
                     error!("failed to clean deprecated chats: {err}");
                 }
             }
-            tokio::time::sleep(Duration::from_secs(300)).await;
+            // the checks are spread over the interval already
+            state
+                .sleep_interval(interval.saturating_sub(started.elapsed()))
+                .await;
         }
     }
 
+    /// Id of the bot's own user, asked from telegram once.
+    pub async fn bot_user_id(&self) -> anyhow::Result<UserId> {
+        let id = self
+            .me
+            .get_or_try_init(|| async {
+                let me = self.bot.get_me().await?;
+                self.telegram_activity.record_success();
+                anyhow::Ok(me.id)
+            })
+            .await?;
+
+        Ok(*id)
+    }
+
+    /// Checks whether the bot is still in each chat, starting the checks at
+    /// an even pace over the interval with at most
+    /// `deprecated_chats_concurrency` of them running at once.
     pub async fn cleanup_deprecated_chats_loop(&self) -> anyhow::Result<()> {
+        // telegram answering with errors during an outage would look like
+        // the bot was removed from every chat
+        if self.in_maintenance().await? {
+            info!("in maintenance, not checking for deprecated chats");
+            return Ok(());
+        }
+
         let chats = self.get_chats().await?;
+        if chats.is_empty() {
+            return Ok(());
+        }
+
+        let bot_user_id = self.bot_user_id().await?;
+        let period = (self.settings().intervals.cleanup_deprecated_chats() / chats.len() as u32)
+            .max(Duration::from_millis(1));
+        let mut pace = tokio::time::interval(period);
+        pace.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let permits = Arc::new(Semaphore::new(self.config.deprecated_chats_concurrency));
+        let mut checks = JoinSet::new();
 
         for chat in chats {
-            match self
-                .bot
-                .get_chat_member(ChatId(chat.id), self.bot.get_me().await?.id)
-                .await
-            {
-                Ok(member) => {
-                    // match member.is_present() {
-                    // true => info!("bot is present in this chat"),
-                    // false => todo!("bot is not present"),
-                    // }
-                    // info!("{member:?}")
+            pace.tick().await;
+            let permit = permits.clone().acquire_owned().await?;
+            let state = self.clone();
+            checks.spawn(
+                async move {
+                    state.check_deprecated_chat(chat, bot_user_id).await;
+                    drop(permit);
                 }
-                Err(err) => match err {
-                    teloxide::RequestError::Api(api_err) => match api_err {
-                        teloxide::ApiError::ChatNotFound
-                        | teloxide::ApiError::BotKicked
-                        | teloxide::ApiError::BotKickedFromSupergroup => {
-                            info!("found a deprecated chat:{} {api_err}", chat.id);
-                            match self.delete_chat(chat.id).await {
-                                Ok(_) => {
-                                    info!("deleted deprecated chat: {}", chat.id)
-                                }
-                                Err(err) => {
-                                    error!("failed to delete deprecated chat:{} {err}", chat.id)
-                                }
-                            };
-                        }
-                        _ => {
-                            if api_err.to_string() == "Unknown error: \"Forbidden: bot was kicked from the group chat\"" {
-                                    match self.delete_chat(chat.id).await {
-                                        Ok(_) => {
-                                            info!("deleted deprecated chat: {}", chat.id)
-                                        }
-                                        Err(err) => {
-                                            error!("failed to delete deprecated chat:{} {err}", chat.id)
-                                        }
-                                    };
-                                }
-                            error!(
-                                "bad api error when checking for deprecated chat:{}... {}",
-                                chat.id,
-                                api_err.to_string()
-                            )
-                        }
-                    },
-                    _ => {
-                        error!(
-                            "bad error when checking for deprecated chat:{}... {err}",
-                            chat.id
-                        )
-                    }
-                },
-            }
+                .instrument(Span::current()),
+            );
         }
+        while checks.join_next().await.is_some() {}
 
         Ok(())
     }
 
-    pub async fn message_queue(state: Self) -> anyhow::Result<()> {
+    #[instrument(skip_all, fields(chat_id = chat.id))]
+    async fn check_deprecated_chat(&self, chat: Chat, bot_user_id: UserId) {
+        let Err(err) = self.bot.get_chat_member(ChatId(chat.id), bot_user_id).await else {
+            return;
+        };
+
+        let class = TelegramErrorClass::of(&err);
+        if !class.is_gone() {
+            error!(
+                "bad error when checking for deprecated chat:{}... {err}",
+                chat.id
+            );
+            return;
+        }
+
+        info!("found a deprecated chat:{} {err}", chat.id);
+        let result = if class == TelegramErrorClass::Kicked {
+            self.archive_kicked_chat(chat.id, &chat.name).await
+        } else {
+            self.archive_chat(chat.id).await
+        };
+        match result {
+            Ok(_) => info!("archived deprecated chat: {}", chat.id),
+            Err(err) => error!("failed to archive deprecated chat:{} {err}", chat.id),
+        }
+    }
+
+    pub async fn sync_admins(state: Self) -> anyhow::Result<()> {
         loop {
-            match state.message_queue_loop().await {
+            state.heartbeat("sync_admins");
+            match state.sync_admins_loop().await {
                 Ok(_) => {
-                    info!("looped through queued messages successfully")
+                    info!("synced chat administrators!");
                 }
                 Err(err) => {
-                    error!("failed to process a message queue: {err}")
+                    error!("failed to sync chat administrators: {err}");
                 }
             }
-            tokio::time::sleep(Duration::from_secs(15)).await;
+            state
+                .sleep_interval(state.settings().intervals.sync_admins())
+                .await;
         }
     }
 
-    async fn message_queue_loop(&self) -> anyhow::Result<()> {
-        let mut messages = sqlx::query_as!(
-            QueuedMessage,
-            r#"
-                SELECT * from message_queue
-                "#
-        )
-        .fetch(&self.pool);
+    pub async fn sync_admins_loop(&self) -> anyhow::Result<()> {
+        for chat in self.get_chats().await? {
+            let admins = match self.bot.get_chat_administrators(ChatId(chat.id)).await {
+                Ok(admins) => admins,
+                // left to the deprecated chat check
+                Err(err) if TelegramErrorClass::of(&err).is_gone() => {
+                    info!("bot is no longer in chat:{}, skipping its admins", chat.id);
+                    continue;
+                }
+                Err(err) => {
+                    error!("failed to get administrators of chat:{} {err}", chat.id);
+                    continue;
+                }
+            };
+
+            let admins: Vec<_> = admins.iter().filter_map(ChatAdmin::from_member).collect();
+            self.set_chat_admins(chat.id, &admins).await?;
+            for admin in admins {
+                self.remove_chat_member_by_id(chat.id, admin.user_id)
+                    .await?;
+            }
+        }
 
-        while let Some(message) = messages.try_next().await? {
-            match self.process_queued_message(message.clone()).await {
-                Ok(_) => {}
+        Ok(())
+    }
+
+    pub async fn sync_chat_metadata(state: Self) -> anyhow::Result<()> {
+        loop {
+            state.heartbeat("sync_chat_metadata");
+            match state.sync_chat_metadata_loop().await {
+                Ok(_) => {
+                    info!("synced chat metadata!");
+                }
                 Err(err) => {
-                    error!("failed to process through message {}: {err}", message.id);
+                    error!("failed to sync chat metadata: {err}");
                 }
             }
+            state
+                .sleep_interval(state.settings().intervals.chat_metadata())
+                .await;
+        }
+    }
+
+    /// Refreshes the chats whose metadata is older than
+    /// `chat_metadata_max_age`, so names stay current in chats nobody posts
+    /// in. The chats are asked for one after the other.
+    pub async fn sync_chat_metadata_loop(&self) -> anyhow::Result<()> {
+        let stale_before =
+            Utc::now() - chrono::Duration::from_std(self.config.chat_metadata_max_age())?;
+        for chat in self.get_chats().await? {
+            if chat
+                .metadata_updated_at
+                .is_some_and(|updated_at| updated_at > stale_before)
+            {
+                continue;
+            }
+            if let Err(err) = self.refresh_chat_metadata(chat.id).await {
+                error!("failed to refresh metadata of chat:{} {err}", chat.id);
+            }
         }
 
         Ok(())
     }
 
-    async fn process_queued_message(&self, message: QueuedMessage) -> anyhow::Result<()> {
-        let parsed_datetime = chrono::DateTime::parse_from_rfc3339(&message.datetime)?;
-        if parsed_datetime < chrono::Utc::now() {
-            info!("queued message {} is expired! sending it now!", message.id);
-            self.send_message_with_images_to_chats(message.chats, message.message, message.images)
-                .await?;
-            self.remove_queued_message(message.id).await?;
+    /// Fetches the title, type, username, member count, description, photo
+    /// and primary invite link of the chat from telegram, waiting out rate
+    /// limits. A new title is recorded as a rename. The invite link is only
+    /// visible to the bot when it is an administrator.
+    #[instrument(skip_all, fields(chat_id))]
+    pub async fn refresh_chat_metadata(&self, chat_id: i64) -> anyhow::Result<()> {
+        let (chat, member_count) = loop {
+            let result = async {
+                let chat = self.bot.get_chat(ChatId(chat_id)).await?;
+                let member_count = self.bot.get_chat_member_count(ChatId(chat_id)).await?;
+                Ok((chat, member_count))
+            }
+            .await;
+            match result.as_ref().map_err(TelegramErrorClass::of) {
+                Err(TelegramErrorClass::RateLimited(wait)) => {
+                    info!("metadata of chat:{chat_id} is rate limited, waiting {wait:?}");
+                    tokio::time::sleep(wait).await;
+                }
+                _ => break result?,
+            }
+        };
+
+        // private chats have no title, their name comes from the user
+        if let Some(title) = chat.title() {
+            self.rename_chat(chat_id, title).await?;
         }
+        self.cache_chat(chat.clone());
+        self.storage
+            .update_chat_metadata(
+                &self.bot_id,
+                chat_id,
+                &ChatMetadata {
+                    kind: chat_kind(&chat),
+                    username: chat.username(),
+                    member_count: member_count as i32,
+                    invite_link: chat.invite_link(),
+                    description: chat.description(),
+                    photo_file_id: chat.photo.as_ref().map(|photo| photo.big_file_id.as_str()),
+                },
+            )
+            .await
+    }
+
+    /// Sleeps until the next scheduled message is due, at most one queue
+    /// interval, and wakes up early when a message is queued or rescheduled
+    /// through the `message_queue` notification channel. Without postgres
+    /// there are no notifications and the interval alone applies.
+    pub async fn message_queue(state: Self) -> anyhow::Result<()> {
+        let mut listener = match &state.pool {
+            Some(pool) => {
+                let mut listener = PgListener::connect_with(pool).await?;
+                listener.listen("message_queue").await?;
+                Some(listener)
+            }
+            None => None,
+        };
+
+        loop {
+            state.heartbeat("message_queue");
+            let mut wait = state.settings().intervals.message_queue();
+            let iteration = state
+                .message_queue_loop()
+                .instrument(info_span!("message_queue_iteration"));
+            match iteration.await {
+                Ok(next_due) => {
+                    info!("looped through queued messages successfully");
+                    if let Some(until) = next_due.and_then(|due| (due - Utc::now()).to_std().ok()) {
+                        wait = wait.min(until);
+                    }
+                }
+                Err(err) => {
+                    error!("failed to process a message queue: {err}")
+                }
+            }
+
+            let Some(listener) = &mut listener else {
+                state.sleep_interval(wait).await;
+                continue;
+            };
+            tokio::select! {
+                _ = state.sleep_interval(wait) => {}
+                notification = listener.recv() => match notification {
+                    Ok(notification) => {
+                        info!("woken up by queued message {}", notification.payload())
+                    }
+                    Err(err) => {
+                        error!("message queue listener failed: {err}");
+                        tokio::time::sleep(wait).await;
+                    }
+                },
+            }
+        }
+    }
+
+    /// Claims due messages one at a time so several instances sharing the
+    /// database never broadcast the same message twice, and broadcasts up to
+    /// `queue.concurrency` of them at once. Claims are renewed while their
+    /// message is sent, one not renewed within [`CLAIM_LEASE`] is assumed to
+    /// belong to a crashed instance and is taken over. Returns
+    /// when the earliest message within the lookahead that is not due yet is
+    /// scheduled.
+    pub async fn message_queue_loop(&self) -> anyhow::Result<Option<DateTime<Utc>>> {
+        if self.in_maintenance().await? {
+            info!("in maintenance, the message queue is paused");
+            return Ok(None);
+        }
+
+        let until = Utc::now() + chrono::Duration::from_std(self.config.queue.lookahead())?;
+        let pending = self
+            .storage
+            .get_pending_messages(&self.bot_id, until, self.config.queue.batch_size)
+            .await?;
+
+        let permits = Arc::new(Semaphore::new(self.config.queue.concurrency));
+        let mut broadcasts = JoinSet::new();
+        let mut next_due: Option<DateTime<Utc>> = None;
+        for row in pending {
+            if row.datetime > Utc::now() {
+                next_due = Some(next_due.map_or(row.datetime, |due| due.min(row.datetime)));
+                continue;
+            }
+
+            let permit = permits.clone().acquire_owned().await?;
+            let Some(message) = self.storage.claim_queued_message(row.id).await? else {
+                continue;
+            };
+
+            let state = self.clone();
+            let request_id = message.request_id.clone();
+            broadcasts.spawn(
+                with_request_id(request_id, async move {
+                    if let Err(err) = state.process_queued_message(message).await {
+                        error!("failed to process through message {}: {err}", row.id);
+                        if let Err(err) = state.fail_queued_message(row.id, &err.to_string()).await
+                        {
+                            error!("failed to record the failure of message {}: {err}", row.id);
+                        }
+                    }
+                    drop(permit);
+                })
+                .instrument(Span::current()),
+            );
+        }
+        while broadcasts.join_next().await.is_some() {}
+
+        Ok(next_due)
+    }
+
+    #[instrument(skip_all, fields(queue_id = message.id, request_id = message.request_id))]
+    async fn process_queued_message(&self, mut message: QueuedMessage) -> anyhow::Result<()> {
+        info!(
+            "queued message {} for {} is due! sending it now!",
+            message.id, message.datetime
+        );
+        // the audience is settled once, a retried or deferred broadcast keeps it
+        if message.all_chats && message.chats.is_empty() {
+            message.chats = match message.options()?.audience {
+                Audience::Chats => {
+                    self.storage
+                        .resolve_all_chats(&self.bot_id, message.id)
+                        .await?
+                }
+                Audience::Subscribers => {
+                    self.storage
+                        .resolve_subscribers(&self.bot_id, message.id)
+                        .await?
+                }
+            };
+        }
+        if let Some(until) = self
+            .renewing_claim(message.id, self.broadcast(&message))
+            .await?
+        {
+            info!(
+                "queued message {} waits for quiet hours until {until}",
+                message.id
+            );
+            return self.storage.defer_queued_message(message.id, until).await;
+        }
+        self.complete_queued_message(message.id).await?;
+
+        let progress = self.get_broadcast_progress(message.id).await?;
+        let threshold = self.config.alerts.broadcast_failures;
+        if let Some(progress) = &progress {
+            if threshold > 0 && progress.failed >= threshold {
+                self.alert(
+                    &format!("broadcast:{}", message.id),
+                    &format!(
+                        "broadcast {} finished with {} of {} chats failed",
+                        message.id, progress.failed, progress.total
+                    ),
+                );
+            }
+        }
+        self.notify(
+            WebhookEvent::BroadcastCompleted,
+            json!({ "id": message.id, "progress": progress }),
+        );
 
         Ok(())
     }
 
+    /// Runs `work` on a claimed message, renewing the claim until it is done
+    /// so other instances don't take the message over.
+    async fn renewing_claim<T>(&self, id: i32, work: impl Future<Output = T>) -> T {
+        tokio::pin!(work);
+        loop {
+            tokio::select! {
+                output = &mut work => return output,
+                _ = tokio::time::sleep(CLAIM_LEASE / 3) => {
+                    if let Err(err) = self.storage.renew_claim(id).await {
+                        error!("failed to renew the claim on message {id}: {err}");
+                    }
+                }
+            }
+        }
+    }
+
+    /// Moves everything known about a group to its new supergroup id in one
+    /// transaction: the chat row, its members, pending queued messages and
+    /// the sent message history.
+    #[instrument(skip_all, fields(chat_id = old_id, new_chat_id = new_id))]
     pub async fn migrate_chat(&self, old_id: i64, new_id: i64) -> anyhow::Result<()> {
         info!("migrating chat {old_id} -> {new_id}");
 
-        sqlx::query!(
-            r#"
-        UPDATE tg_chat
-        SET id = $1
-        WHERE id = $2
-        "#,
-            new_id,
-            old_id
-        )
-        .execute(&self.pool)
-        .await?;
+        self.storage.migrate_chat(old_id, new_id).await?;
+        self.forget_chat(old_id);
 
-        self.chats_status
+        let status = self
+            .chats_status
             .remove(&old_id)
-            .context("Tried to migrate from an unexisting status")?;
-
-        self.chats_status
-            .entry(new_id)
-            .or_insert(ChatCleaningStatus::Idle);
+            .map(|(_, status)| status)
+            .unwrap_or(ChatCleaningStatus::Idle);
+        self.peer_statuses.remove(&old_id);
+        self.share_status(old_id, None);
+        self.share_status(new_id, Some(&status));
+        self.chats_status.insert(new_id, status);
+
+        if let Some((_, record)) = self.cleanup_records.remove(&old_id) {
+            self.cleanup_records.insert(new_id, record);
+        }
 
         Ok(())
     }
 }
 
+/// One result per requested chat, in order, failing those not in `found`.
+fn chat_results(ids: &[i64], found: &[i64]) -> Vec<ChatResult> {
+    ids.iter()
+        .map(|&chat_id| ChatResult {
+            chat_id,
+            error: (!found.contains(&chat_id)).then(|| "chat not found".to_string()),
+        })
+        .collect()
+}
+
+fn chat_kind(chat: &teloxide::types::Chat) -> &'static str {
+    if chat.is_channel() {
+        CHANNEL_KIND
+    } else if chat.is_supergroup() {
+        "supergroup"
+    } else if chat.is_group() {
+        "group"
+    } else {
+        "private"
+    }
+}
+
 pub type Chats = Vec<Chat>;
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 pub struct Chat {
-    id: i64,
-    name: String,
+    pub id: i64,
+    pub name: String,
+    pub muted: bool,
+    pub approved: bool,
+    /// `group`, `supergroup`, `channel` or `private`.
+    pub kind: Option<String>,
+    /// The channel shows who posted, broadcasts then carry the bot's name.
+    /// Learned from the posts of the channel, `null` until it posted.
+    pub signs_messages: Option<bool>,
+    pub username: Option<String>,
+    pub member_count: Option<i32>,
+    pub invite_link: Option<String>,
+    pub description: Option<String>,
+    /// `file_id` of the chat photo, for downloading it with `getFile`.
+    pub photo_file_id: Option<String>,
+    pub metadata_updated_at: Option<DateTime<Utc>>,
+    /// Messages per minute sent to this chat, on top of the global throttle.
+    pub rate_limit_override: Option<i32>,
+    /// The bot was removed from the chat, its members are kept until it is
+    /// restored.
+    pub archived: bool,
+    /// Picks the translation of broadcasts that have one for it.
+    pub language: Option<String>,
+    /// Free-form labels broadcasts can target the chat by.
+    pub labels: Vec<String>,
+    pub notes: Option<String>,
+    /// Join and leave messages are deleted from the chat.
+    pub delete_service_messages: bool,
+    /// Offset of the chat's local time from UTC, broadcasts scheduled in
+    /// local time go out by it. Chats without one are on UTC.
+    pub utc_offset_minutes: Option<i32>,
 }
 
 pub type Users = Vec<User>;
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, ToSchema, sqlx::FromRow)]
 pub struct User {
-    id: i64,
-    chat_id: i64,
-    username: Option<String>,
-    name: String,
+    pub id: i64,
+    pub chat_id: i64,
+    pub username: Option<String>,
+    pub name: String,
+    /// When the member was first tracked, by joining or by writing. `null`
+    /// for members tracked before it was kept.
+    pub joined_at: Option<DateTime<Utc>>,
+    /// When the username or name last changed, or the member was tracked.
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+pub type Kicks = Vec<Kick>;
+
+#[derive(Serialize, ToSchema, sqlx::FromRow)]
+pub struct Kick {
+    pub id: i64,
+    pub chat_id: i64,
+    pub user_id: i64,
+    pub username: Option<String>,
+    pub name: String,
+    pub reason: String,
+    pub initiator: String,
+    pub kicked_at: DateTime<Utc>,
+    /// The API request behind the kick, missing for kicks not started over
+    /// the API.
+    pub request_id: Option<String>,
 }
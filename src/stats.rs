@@ -0,0 +1,115 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::state::{AppState, CleanupRecord};
+
+pub const MEMBER_JOINED: &str = "join";
+pub const MEMBER_LEFT: &str = "leave";
+
+#[derive(Serialize, ToSchema)]
+pub struct ChatStats {
+    chat_id: i64,
+    member_count: i64,
+    joins_7d: i64,
+    joins_30d: i64,
+    leaves_7d: i64,
+    leaves_30d: i64,
+    last_broadcast_at: Option<DateTime<Utc>>,
+    last_cleanup: CleanupRecord,
+    /// Members kicked by cleanups per day over the last 30 days.
+    cleanups: Vec<CleanupDay>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct CleanupDay {
+    day: NaiveDate,
+    kicked: i64,
+}
+
+impl AppState {
+    /// Records a join or leave. Telegram reports the same change both as a
+    /// service message and as a `chat_member` update, so an identical event
+    /// within the last minute is not stored twice. Only kept with postgres.
+    pub async fn log_member_event(
+        &self,
+        chat_id: i64,
+        user_id: i64,
+        kind: &str,
+    ) -> anyhow::Result<()> {
+        let Some(pool) = &self.pool else {
+            return Ok(());
+        };
+
+        sqlx::query!(
+            r#"
+INSERT INTO member_event ( chat_id, user_id, kind )
+SELECT $1, $2, $3
+WHERE NOT EXISTS (
+    SELECT 1 FROM member_event
+    WHERE chat_id = $1 AND user_id = $2 AND kind = $3
+    AND created_at > now() - interval '1 minute'
+)
+            "#,
+            chat_id,
+            user_id,
+            kind
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_chat_stats(&self, chat_id: i64) -> anyhow::Result<ChatStats> {
+        let counts = sqlx::query!(
+            r#"
+SELECT
+    ( SELECT count(*) FROM tg_user WHERE chat_id = $1 ) AS "member_count!",
+    count(*) FILTER (WHERE kind = $2 AND created_at > now() - interval '7 days') AS "joins_7d!",
+    count(*) FILTER (WHERE kind = $2) AS "joins_30d!",
+    count(*) FILTER (WHERE kind = $3 AND created_at > now() - interval '7 days') AS "leaves_7d!",
+    count(*) FILTER (WHERE kind = $3) AS "leaves_30d!",
+    ( SELECT max(sent_at) FROM sent_message WHERE chat_id = $1 AND error IS NULL ) AS last_broadcast_at
+FROM member_event
+WHERE chat_id = $1 AND created_at > now() - interval '30 days'
+            "#,
+            chat_id,
+            MEMBER_JOINED,
+            MEMBER_LEFT
+        )
+        .fetch_one(self.pg()?)
+        .await?;
+
+        let cleanups = sqlx::query_as!(
+            CleanupDay,
+            r#"
+SELECT kicked_at::date AS "day!", count(*) AS "kicked!" FROM kick_log
+WHERE chat_id = $1 AND reason = 'cleanup' AND kicked_at > now() - interval '30 days'
+GROUP BY 1
+ORDER BY 1
+            "#,
+            chat_id
+        )
+        .fetch_all(self.pg()?)
+        .await?;
+
+        let last_cleanup = self
+            .cleanup_records
+            .get(&chat_id)
+            .map(|record| record.clone())
+            .unwrap_or_default();
+
+        Ok(ChatStats {
+            chat_id,
+            member_count: counts.member_count,
+            joins_7d: counts.joins_7d,
+            joins_30d: counts.joins_30d,
+            leaves_7d: counts.leaves_7d,
+            leaves_30d: counts.leaves_30d,
+            last_broadcast_at: counts.last_broadcast_at,
+            last_cleanup,
+            cleanups,
+        })
+    }
+}
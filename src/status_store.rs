@@ -0,0 +1,283 @@
+//! Where the cleanup statuses of the chats are shared. Every instance keeps
+//! them in [`AppState::chats_status`]; a single instance needs nothing more,
+//! replicas write theirs to postgres and take over the ones the others
+//! wrote, so each reports, and doesn't start again, the cleanups running
+//! on another. The statuses of running cleanups are leased: their instance
+//! renews them every sync, those left to expire belong to an instance that
+//! stopped and are taken over as interrupted.
+
+use std::{
+    fmt,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicI64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{de::IntoDeserializer, Deserialize};
+use sqlx::{types::Json, PgPool};
+use tracing::{error, warn};
+use uuid::Uuid;
+
+use crate::state::{AppState, ChatCleaningStatus};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StatusStoreKind {
+    /// Only the memory of the instance.
+    #[default]
+    Memory,
+    /// A table every instance reads and writes, needs a postgres database.
+    Postgres,
+}
+
+// parsed like it is written in the config file
+impl FromStr for StatusStoreKind {
+    type Err = serde::de::value::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::deserialize(s.into_deserializer())
+    }
+}
+
+/// Time on top of two syncs a running status is not renewed in before it
+/// counts as left behind by a stopped instance.
+const LEASE_GRACE: Duration = Duration::from_secs(30);
+
+/// Returned when cancelling a cleanup that runs on another instance, which
+/// only that instance can stop.
+#[derive(Debug)]
+pub struct CleanupElsewhere(pub i64);
+
+impl fmt::Display for CleanupElsewhere {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "the cleanup of chat {} runs on another instance, cancel it there",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for CleanupElsewhere {}
+
+/// A status another instance wrote.
+pub struct SharedStatus {
+    pub chat_id: i64,
+    /// `None` when the instance dropped the chat.
+    pub status: Option<ChatCleaningStatus>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[async_trait]
+pub trait StatusStore: Send + Sync {
+    /// The latest statuses other instances wrote for the chats of the bot.
+    async fn load(&self, bot_id: &str) -> anyhow::Result<Vec<SharedStatus>>;
+    /// Shares the status in the background, `None` once the chat is gone.
+    /// Writes of one instance land in the order they were made.
+    fn save(&self, bot_id: &str, chat_id: i64, status: Option<&ChatCleaningStatus>);
+    /// Renews the lease of the running statuses this instance wrote and
+    /// forgets the dropped chats written before `expired`, which every live
+    /// instance has seen by then.
+    async fn renew(&self, bot_id: &str, expired: DateTime<Utc>) -> anyhow::Result<()>;
+}
+
+/// Shares nothing, the default for a single instance.
+pub struct MemoryStatusStore;
+
+#[async_trait]
+impl StatusStore for MemoryStatusStore {
+    async fn load(&self, _bot_id: &str) -> anyhow::Result<Vec<SharedStatus>> {
+        Ok(Vec::new())
+    }
+
+    fn save(&self, _bot_id: &str, _chat_id: i64, _status: Option<&ChatCleaningStatus>) {}
+
+    async fn renew(&self, _bot_id: &str, _expired: DateTime<Utc>) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+pub struct PgStatusStore {
+    pool: PgPool,
+    /// Tells the rows of this instance apart from those of the others.
+    instance: String,
+    seq: Arc<AtomicI64>,
+}
+
+impl PgStatusStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self {
+            pool,
+            instance: Uuid::new_v4().to_string(),
+            seq: Arc::new(AtomicI64::new(0)),
+        }
+    }
+}
+
+#[async_trait]
+impl StatusStore for PgStatusStore {
+    async fn load(&self, bot_id: &str) -> anyhow::Result<Vec<SharedStatus>> {
+        let rows = sqlx::query!(
+            r#"
+SELECT chat_id, status AS "status: Json<ChatCleaningStatus>", updated_at FROM chat_status
+WHERE bot_id = $1 AND instance <> $2
+            "#,
+            bot_id,
+            self.instance
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| SharedStatus {
+                chat_id: row.chat_id,
+                status: row.status.map(|status| status.0),
+                updated_at: row.updated_at,
+            })
+            .collect())
+    }
+
+    fn save(&self, bot_id: &str, chat_id: i64, status: Option<&ChatCleaningStatus>) {
+        let pool = self.pool.clone();
+        let instance = self.instance.clone();
+        let seq = self.seq.fetch_add(1, Ordering::Relaxed);
+        let bot_id = bot_id.to_string();
+        let status = status.cloned().map(Json);
+        tokio::spawn(async move {
+            // an older write of this instance arriving late doesn't replace a
+            // newer one, a dropped chat is kept as a NULL status for the
+            // others to see
+            let result = sqlx::query!(
+                r#"
+INSERT INTO chat_status ( bot_id, chat_id, status, instance, seq )
+VALUES ( $1, $2, $3, $4, $5 )
+ON CONFLICT ( bot_id, chat_id ) DO UPDATE
+SET status = $3, instance = $4, seq = $5, updated_at = now()
+WHERE chat_status.instance <> $4 OR chat_status.seq < $5
+                "#,
+                bot_id,
+                chat_id,
+                status as _,
+                instance,
+                seq
+            )
+            .execute(&pool)
+            .await;
+            if let Err(err) = result {
+                error!("failed to share the status of chat:{chat_id}: {err}");
+            }
+        });
+    }
+
+    async fn renew(&self, bot_id: &str, expired: DateTime<Utc>) -> anyhow::Result<()> {
+        sqlx::query!(
+            r#"
+UPDATE chat_status
+SET updated_at = now()
+WHERE bot_id = $1 AND instance = $2
+AND status->>'state' IN ( 'queued', 'in_progress', 'resyncing' )
+            "#,
+            bot_id,
+            self.instance
+        )
+        .execute(&self.pool)
+        .await?;
+        sqlx::query!(
+            "DELETE FROM chat_status WHERE bot_id = $1 AND status IS NULL AND updated_at < $2",
+            bot_id,
+            expired
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+impl AppState {
+    /// Hands the status of the chat to the other instances.
+    pub(crate) fn share_status(&self, chat_id: i64, status: Option<&ChatCleaningStatus>) {
+        self.status_store.save(&self.bot_id, chat_id, status);
+    }
+
+    /// Renews the running statuses of this instance and takes over the
+    /// statuses other instances wrote since the last sync, chats they know
+    /// and this one doesn't yet included, chats they dropped left out. Each
+    /// write is taken over once, a status set here since isn't replaced by
+    /// it again. Running statuses whose lease expired are taken over as
+    /// interrupted, see [`AppState::take_over_stale_status`].
+    pub async fn sync_statuses(&self) -> anyhow::Result<()> {
+        let lease = self.settings().intervals.status_sync() * 2 + LEASE_GRACE;
+        let expired = Utc::now() - chrono::Duration::from_std(lease)?;
+        self.status_store.renew(&self.bot_id, expired).await?;
+
+        for shared in self.status_store.load(&self.bot_id).await? {
+            let chat_id = shared.chat_id;
+            let seen = self
+                .shared_statuses_seen
+                .get(&chat_id)
+                .is_some_and(|seen| *seen >= shared.updated_at);
+            if seen {
+                continue;
+            }
+            self.shared_statuses_seen.insert(chat_id, shared.updated_at);
+            let Some(status) = shared.status else {
+                self.chats_status.remove(&chat_id);
+                self.peer_statuses.remove(&chat_id);
+                self.cleanup_records.remove(&chat_id);
+                continue;
+            };
+            if status.is_running() && shared.updated_at < expired {
+                self.take_over_stale_status(chat_id).await?;
+                continue;
+            }
+            let changed = match self.chats_status.get(&chat_id).as_deref() {
+                Some(current) => *current != status,
+                None => true,
+            };
+            if changed {
+                self.publish_status(chat_id, &status);
+                self.chats_status.insert(chat_id, status);
+            }
+            self.peer_statuses.insert(chat_id);
+        }
+
+        Ok(())
+    }
+
+    /// Marks the chat whose cleanup ran on a stopped instance `Resumable`
+    /// from its stored run, or as failed when it never started, and shares
+    /// that as this instance's own status.
+    async fn take_over_stale_status(&self, chat_id: i64) -> anyhow::Result<()> {
+        if !self.chats_status.contains_key(&chat_id) {
+            return Ok(());
+        }
+        warn!("the instance running the cleanup of chat:{chat_id} stopped");
+        let status = match self.storage.get_cleanup_run(chat_id).await? {
+            Some(run) => ChatCleaningStatus::Resumable(run),
+            None => {
+                ChatCleaningStatus::Error("the instance running the cleanup stopped".to_string())
+            }
+        };
+
+        self.set_chat_status(chat_id, status)
+    }
+
+    pub async fn sync_statuses_loop(state: Self) -> anyhow::Result<()> {
+        loop {
+            state.heartbeat("sync_statuses");
+            if let Err(err) = state.sync_statuses().await {
+                error!("failed to sync the chat statuses: {err}");
+            }
+            state
+                .sleep_interval(state.settings().intervals.status_sync())
+                .await;
+        }
+    }
+}
@@ -0,0 +1,60 @@
+//! `GET /status/{chat_id}` as it was before `/v2`, kept for clients that
+//! didn't move yet. The status is externally tagged there, like `"Idle"` or
+//! `{"Error": "..."}`, where `/v2` has `{"state": "error", "detail": "..."}`.
+
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::{
+    reconcile::InterruptedCleanup,
+    resync::ResyncProgress,
+    schedule::CleanupSchedule,
+    state::{ChatCleaningStatus, ChatStatus, CleanupProgress, CleanupRecord},
+};
+
+#[derive(Serialize, ToSchema)]
+pub enum ChatCleaningStatusV1 {
+    Idle,
+    Queued,
+    InProgress(CleanupProgress),
+    Error(String),
+    Cancelled(usize),
+    Resyncing(ResyncProgress),
+    Resumable(InterruptedCleanup),
+}
+
+impl From<ChatCleaningStatus> for ChatCleaningStatusV1 {
+    fn from(status: ChatCleaningStatus) -> Self {
+        match status {
+            ChatCleaningStatus::Idle => ChatCleaningStatusV1::Idle,
+            ChatCleaningStatus::Queued => ChatCleaningStatusV1::Queued,
+            ChatCleaningStatus::InProgress(progress) => ChatCleaningStatusV1::InProgress(progress),
+            ChatCleaningStatus::Error(err) => ChatCleaningStatusV1::Error(err),
+            ChatCleaningStatus::Cancelled(processed) => ChatCleaningStatusV1::Cancelled(processed),
+            ChatCleaningStatus::Resyncing(progress) => ChatCleaningStatusV1::Resyncing(progress),
+            ChatCleaningStatus::Resumable(cleanup) => ChatCleaningStatusV1::Resumable(cleanup),
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ChatStatusV1 {
+    chat_id: i64,
+    status: ChatCleaningStatusV1,
+    #[serde(flatten)]
+    record: CleanupRecord,
+    member_count: i64,
+    cleanup_schedule: Option<CleanupSchedule>,
+}
+
+impl From<ChatStatus> for ChatStatusV1 {
+    fn from(status: ChatStatus) -> Self {
+        ChatStatusV1 {
+            chat_id: status.chat_id,
+            status: status.status.into(),
+            record: status.record,
+            member_count: status.member_count,
+            cleanup_schedule: status.cleanup_schedule,
+        }
+    }
+}
@@ -0,0 +1,844 @@
+use std::{collections::HashMap, future::Future, sync::Arc, time::Duration};
+
+use anyhow::{bail, Context};
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate, Utc};
+use serde_json::Value;
+use sqlx::{pool::PoolOptions, Database, PgPool};
+use teloxide::types::ChatInviteLink;
+use tracing::warn;
+
+use crate::{
+    activity::InactiveMember,
+    admin::ChatAdmin,
+    attachment::Attachments,
+    audit::{AuditEntry, NewAuditEntry},
+    branding::Branding,
+    broadcast::{BroadcastProgress, DeliveredFile, Delivery, MessageOptions},
+    captcha::{Captcha, CaptchaChallenge},
+    chat_list::ChatQuery,
+    config::DatabasePoolConfig,
+    dead_letter::FailedMessage,
+    draft::{Draft, DraftContent},
+    exclusion::Exclusion,
+    invite_link::InviteLink,
+    join_request::{JoinPolicy, JoinRequest, JoinRequestStatus, NewJoinRequest},
+    language::Translations,
+    media::Media,
+    moderation::{ModerationAction, ModerationKind, ModerationSettings, Warning},
+    queue::{QueueEntry, QueueFilter},
+    queue_history::QueuedMessageVersion,
+    quiet_hours::QuietHours,
+    reconcile::InterruptedCleanup,
+    rename::ChatRename,
+    report::ReportCounts,
+    staged_cleanup::CleanupCandidate,
+    state::{Chats, Kicks, QueuedMessage, User, Users},
+    subscriber::Audience,
+    topic::ChatTarget,
+    usage::{Usage, UsageEntry},
+    variant::{MessageVariant, VariantResult},
+    webhook::{Webhook, WebhookEvent},
+    welcome::{Welcome, WelcomeDeletion},
+};
+
+mod postgres;
+mod sqlite;
+
+pub use self::postgres::{insert_queued_message, PgStorage};
+pub use self::sqlite::SqliteStorage;
+
+/// Result of registering a chat.
+pub struct UpsertedChat {
+    pub approved: bool,
+    /// The chat was not known before.
+    pub inserted: bool,
+    /// The chat was archived after the bot was removed from it.
+    pub archived: bool,
+}
+
+/// What telegram tells about a chat besides its title.
+pub struct ChatMetadata<'a> {
+    pub kind: &'a str,
+    pub username: Option<&'a str>,
+    pub member_count: i32,
+    pub invite_link: Option<&'a str>,
+    pub description: Option<&'a str>,
+    /// `file_id` of the large version of the chat photo.
+    pub photo_file_id: Option<&'a str>,
+}
+
+/// Everything a message is queued with.
+pub struct NewQueuedMessage<'a> {
+    pub bot_id: &'a str,
+    /// `None` marks the message for every chat.
+    pub targets: Option<&'a [ChatTarget]>,
+    /// Chats left out when the whole audience is resolved.
+    pub excluded_chats: &'a [i64],
+    pub message: &'a str,
+    /// Images as kept on the queue row, https URLs or references to
+    /// `media_blob` made by [`Media::reference`].
+    pub images: &'a [String],
+    /// Contents of the images referenced by the message and its variants,
+    /// stored in `media_blob` unless a message holds them already.
+    pub media: &'a [Media],
+    pub datetime: DateTime<Utc>,
+    pub options: &'a MessageOptions,
+    pub awaiting_approval: bool,
+    /// Chat and message id of a telegram message to copy instead of sending
+    /// the text.
+    pub copy_from: Option<(i64, i32)>,
+    /// A/B variants the chats are split between, empty to send `message`
+    /// everywhere.
+    pub variants: &'a [MessageVariant],
+    /// Texts sent instead of `message` to chats with these languages.
+    pub translations: &'a Translations,
+    pub attachments: &'a Attachments,
+    /// The API request queueing the message, its broadcast logs under it.
+    pub request_id: Option<&'a str>,
+    /// Refuses the message with [`DuplicateBroadcast`] when one with the
+    /// same text was queued for overlapping targets after this, checked in
+    /// the transaction inserting it.
+    ///
+    /// [`DuplicateBroadcast`]: crate::duplicate::DuplicateBroadcast
+    pub duplicates_since: Option<DateTime<Utc>>,
+}
+
+/// A queued message that is neither completed, waiting for approval nor
+/// claimed by a live instance.
+pub struct PendingMessage {
+    pub id: i32,
+    /// When it is due, later than scheduled while it is deferred.
+    pub datetime: DateTime<Utc>,
+}
+
+/// Counts behind the status overview of a chat.
+#[derive(sqlx::FromRow)]
+pub struct ChatActivity {
+    pub id: i64,
+    pub name: String,
+    pub member_count: i64,
+    /// Queued messages not sent yet that will reach the chat.
+    pub queued_broadcasts: i64,
+}
+
+/// Persistence of chats, their members and the message queue. Features
+/// beyond these, like polls, statistics and schedules, need postgres and go
+/// through [`AppState::pg`](crate::state::AppState::pg).
+#[async_trait]
+pub trait Storage: Send + Sync {
+    async fn ping(&self) -> anyhow::Result<()>;
+
+    /// Leaves out archived chats.
+    async fn get_chats(&self, bot_id: &str) -> anyhow::Result<Chats>;
+    /// One page of the matching chats and how many match in all.
+    async fn query_chats(
+        &self,
+        bot_id: &str,
+        query: &ChatQuery,
+        limit: i64,
+        offset: i64,
+    ) -> anyhow::Result<(Chats, i64)>;
+    /// Inserts the chat with the given approval or updates its name, kind and
+    /// username, keeping the stored approval. Returns `None` without touching
+    /// the chat when it belongs to another bot.
+    async fn upsert_chat(
+        &self,
+        bot_id: &str,
+        id: i64,
+        name: &str,
+        approved: bool,
+        kind: &str,
+        username: Option<&str>,
+    ) -> anyhow::Result<Option<UpsertedChat>>;
+    async fn update_chat_metadata(
+        &self,
+        bot_id: &str,
+        id: i64,
+        metadata: &ChatMetadata<'_>,
+    ) -> anyhow::Result<()>;
+    /// Returns `false` when the chat is unknown.
+    async fn approve_chat(&self, bot_id: &str, id: i64) -> anyhow::Result<bool>;
+    async fn get_unapproved_chats(&self, bot_id: &str) -> anyhow::Result<Vec<i64>>;
+    /// Returns `false` when the chat is unknown.
+    async fn set_chat_muted(&self, bot_id: &str, id: i64, muted: bool) -> anyhow::Result<bool>;
+    /// Returns `false` when the chat is unknown.
+    async fn set_delete_service_messages(
+        &self,
+        bot_id: &str,
+        id: i64,
+        delete: bool,
+    ) -> anyhow::Result<bool>;
+    /// `None` when the chat is unknown.
+    async fn deletes_service_messages(&self, id: i64) -> anyhow::Result<Option<bool>>;
+    /// Returns whether the chat is known and didn't sign its posts the same
+    /// way already.
+    async fn set_chat_signs_messages(
+        &self,
+        bot_id: &str,
+        id: i64,
+        signs: bool,
+    ) -> anyhow::Result<bool>;
+    /// Returns `false` when the chat is unknown.
+    async fn set_chat_rate_limit(
+        &self,
+        bot_id: &str,
+        id: i64,
+        per_minute: Option<i32>,
+    ) -> anyhow::Result<bool>;
+    async fn set_chat_language(
+        &self,
+        bot_id: &str,
+        id: i64,
+        language: Option<&str>,
+    ) -> anyhow::Result<bool>;
+    /// `None` puts the chat back on UTC.
+    async fn set_chat_utc_offset(
+        &self,
+        bot_id: &str,
+        id: i64,
+        minutes: Option<i32>,
+    ) -> anyhow::Result<bool>;
+    async fn set_chat_labels(
+        &self,
+        bot_id: &str,
+        id: i64,
+        labels: &[String],
+    ) -> anyhow::Result<bool>;
+    async fn set_chat_notes(
+        &self,
+        bot_id: &str,
+        id: i64,
+        notes: Option<&str>,
+    ) -> anyhow::Result<bool>;
+    /// Chats the bot is in carrying any of the labels.
+    async fn get_labelled_chats(&self, bot_id: &str, labels: &[String])
+        -> anyhow::Result<Vec<i64>>;
+    async fn get_muted_chats(&self, bot_id: &str) -> anyhow::Result<Vec<i64>>;
+    /// Returns `false` when the bot has no such chat.
+    async fn delete_chat(&self, bot_id: &str, id: i64) -> anyhow::Result<bool>;
+    /// Mutes or unmutes the chats of the bot in one transaction, returns
+    /// those that were found.
+    async fn set_chats_muted(
+        &self,
+        bot_id: &str,
+        ids: &[i64],
+        muted: bool,
+    ) -> anyhow::Result<Vec<i64>>;
+    /// Deletes the chats of the bot in one transaction, returns those that
+    /// were found.
+    async fn delete_chats(&self, bot_id: &str, ids: &[i64]) -> anyhow::Result<Vec<i64>>;
+    /// Archives or restores the chat of the bot. Returns `false` when the
+    /// chat is unknown or already archived, respectively restored.
+    async fn set_chat_archived(
+        &self,
+        bot_id: &str,
+        id: i64,
+        archived: bool,
+    ) -> anyhow::Result<bool>;
+    /// Updates the name of the chat and records the old one in its name
+    /// history. Returns `false` when the chat is unknown or the name didn't
+    /// change.
+    async fn rename_chat(&self, bot_id: &str, id: i64, name: &str) -> anyhow::Result<bool>;
+    /// Newest first.
+    async fn get_chat_renames(&self, chat_id: i64) -> anyhow::Result<Vec<ChatRename>>;
+    /// Moves the chat row, its members and pending queued messages to the
+    /// new id in one transaction.
+    async fn migrate_chat(&self, old_id: i64, new_id: i64) -> anyhow::Result<()>;
+    /// Puts the chat into the group, replacing the one it was in. `None`
+    /// takes it out.
+    async fn set_chat_group(
+        &self,
+        bot_id: &str,
+        chat_id: i64,
+        group: Option<&str>,
+        assigned_by: i64,
+    ) -> anyhow::Result<()>;
+    /// Chats put into a group and the name of their group.
+    async fn get_chat_group_assignments(&self, bot_id: &str) -> anyhow::Result<Vec<(i64, String)>>;
+
+    async fn get_all_members(&self, chat_id: i64) -> anyhow::Result<Users>;
+    /// Members ordered by id, `search` matches the username or name
+    /// case-insensitively.
+    async fn get_members_page(
+        &self,
+        chat_id: i64,
+        search: Option<&str>,
+        limit: i64,
+        offset: i64,
+    ) -> anyhow::Result<Users>;
+    async fn get_member(&self, chat_id: i64, user_id: i64) -> anyhow::Result<Option<User>>;
+    async fn count_members(&self, chat_id: i64) -> anyhow::Result<i64>;
+    async fn get_chat_activity(&self, bot_id: &str) -> anyhow::Result<Vec<ChatActivity>>;
+    /// Sets `joined_at` for new members and `updated_at` when the username or
+    /// name changed.
+    async fn upsert_member(
+        &self,
+        bot_id: &str,
+        chat_id: i64,
+        user_id: i64,
+        username: &str,
+        name: &str,
+    ) -> anyhow::Result<()>;
+    async fn remove_member(&self, chat_id: i64, user_id: i64) -> anyhow::Result<()>;
+    /// Moves the member's `last_message_at` forward to `at`.
+    async fn touch_member(
+        &self,
+        chat_id: i64,
+        user_id: i64,
+        at: DateTime<Utc>,
+    ) -> anyhow::Result<()>;
+    /// Members whose last message, or their join when they never wrote, is
+    /// before `before`, the longest silent first.
+    async fn get_inactive_members(
+        &self,
+        chat_id: i64,
+        before: DateTime<Utc>,
+    ) -> anyhow::Result<Vec<InactiveMember>>;
+    async fn log_kick(
+        &self,
+        chat_id: i64,
+        user: &User,
+        reason: &str,
+        initiator: &str,
+        request_id: Option<&str>,
+    ) -> anyhow::Result<()>;
+    async fn get_kicks(&self, chat_id: i64, limit: i64, offset: i64) -> anyhow::Result<Kicks>;
+    /// Every user the kick log has for the chat, once each.
+    async fn get_kicked_user_ids(&self, chat_id: i64) -> anyhow::Result<Vec<i64>>;
+    /// Staging a member again keeps the first record.
+    async fn add_cleanup_candidate(
+        &self,
+        chat_id: i64,
+        user: &User,
+        initiator: &str,
+    ) -> anyhow::Result<()>;
+    /// Ordered by name.
+    async fn get_cleanup_candidates(&self, chat_id: i64) -> anyhow::Result<Vec<CleanupCandidate>>;
+    /// Returns how many candidates were dropped.
+    async fn delete_cleanup_candidates(&self, chat_id: i64) -> anyhow::Result<u64>;
+    /// Records that a cleanup of the chat started, replacing the record of
+    /// an earlier one but for how far it got.
+    async fn start_cleanup_run(
+        &self,
+        chat_id: i64,
+        staged: bool,
+        initiator: &str,
+    ) -> anyhow::Result<()>;
+    async fn finish_cleanup_run(&self, chat_id: i64) -> anyhow::Result<()>;
+    /// Remembers the last member the cleanup of the chat got to.
+    async fn set_cleanup_cursor(&self, chat_id: i64, last_user_id: i64) -> anyhow::Result<()>;
+    /// Marks the cleanup of the chat as paused until `resume_at`.
+    async fn pause_cleanup_run(&self, chat_id: i64, resume_at: DateTime<Utc>)
+        -> anyhow::Result<()>;
+    async fn get_cleanup_run(&self, chat_id: i64) -> anyhow::Result<Option<InterruptedCleanup>>;
+    /// Cleanups of the bot that started and didn't finish.
+    async fn get_cleanup_runs(&self, bot_id: &str) -> anyhow::Result<Vec<InterruptedCleanup>>;
+    /// Replaces the administrators of the chat.
+    async fn set_chat_admins(&self, chat_id: i64, admins: &[ChatAdmin]) -> anyhow::Result<()>;
+    async fn upsert_chat_admin(&self, chat_id: i64, admin: &ChatAdmin) -> anyhow::Result<()>;
+    async fn remove_chat_admin(&self, chat_id: i64, user_id: i64) -> anyhow::Result<()>;
+    /// The creator first, then administrators by name.
+    async fn get_chat_admins(&self, chat_id: i64) -> anyhow::Result<Vec<ChatAdmin>>;
+
+    async fn get_welcome(&self, chat_id: i64) -> anyhow::Result<Option<Welcome>>;
+    async fn set_welcome(&self, chat_id: i64, welcome: &Welcome) -> anyhow::Result<()>;
+    /// Returns `false` when the chat had no welcome message.
+    async fn delete_welcome(&self, chat_id: i64) -> anyhow::Result<bool>;
+    async fn add_welcome_deletion(
+        &self,
+        deletion: &WelcomeDeletion,
+        delete_at: DateTime<Utc>,
+    ) -> anyhow::Result<()>;
+    /// Greetings in the bot's chats due for deletion at `now`, longest due
+    /// first.
+    async fn get_due_welcome_deletions(
+        &self,
+        bot_id: &str,
+        now: DateTime<Utc>,
+        limit: i64,
+    ) -> anyhow::Result<Vec<WelcomeDeletion>>;
+    async fn remove_welcome_deletion(&self, deletion: &WelcomeDeletion) -> anyhow::Result<()>;
+
+    async fn get_branding(&self, chat_id: i64) -> anyhow::Result<Option<Branding>>;
+    async fn get_all_brandings(&self, bot_id: &str) -> anyhow::Result<HashMap<i64, Branding>>;
+    /// An empty branding deletes the row.
+    async fn set_branding(&self, chat_id: i64, branding: &Branding) -> anyhow::Result<()>;
+    async fn get_quiet_hours(&self, chat_id: i64) -> anyhow::Result<Option<QuietHours>>;
+    async fn get_all_quiet_hours(&self, bot_id: &str) -> anyhow::Result<HashMap<i64, QuietHours>>;
+    async fn set_quiet_hours(&self, chat_id: i64, quiet_hours: &QuietHours) -> anyhow::Result<()>;
+    /// Returns `false` when the chat had no quiet hours.
+    async fn delete_quiet_hours(&self, chat_id: i64) -> anyhow::Result<bool>;
+
+    async fn get_moderation(&self, chat_id: i64) -> anyhow::Result<Option<ModerationSettings>>;
+    async fn set_moderation(
+        &self,
+        chat_id: i64,
+        settings: &ModerationSettings,
+    ) -> anyhow::Result<()>;
+    /// Returns `false` when the chat had no spam filter.
+    async fn delete_moderation(&self, chat_id: i64) -> anyhow::Result<bool>;
+    /// Adds a warning to the member, returns how many they have now.
+    async fn add_warning(&self, chat_id: i64, user_id: i64, reason: &str) -> anyhow::Result<i32>;
+    async fn get_warnings(&self, chat_id: i64) -> anyhow::Result<Vec<Warning>>;
+    /// Returns `false` when the member had no warnings.
+    async fn reset_warnings(&self, chat_id: i64, user_id: i64) -> anyhow::Result<bool>;
+    async fn log_moderation(
+        &self,
+        chat_id: i64,
+        user_id: i64,
+        kind: ModerationKind,
+        reason: &str,
+        moderator: &str,
+    ) -> anyhow::Result<()>;
+    /// Newest first, optionally only the actions taken on one user.
+    async fn get_moderation_log(
+        &self,
+        chat_id: i64,
+        user_id: Option<i64>,
+        limit: i64,
+        offset: i64,
+    ) -> anyhow::Result<Vec<ModerationAction>>;
+
+    /// `false` until maintenance was switched on for the bot.
+    async fn get_maintenance(&self, bot_id: &str) -> anyhow::Result<bool>;
+    async fn set_maintenance(&self, bot_id: &str, enabled: bool) -> anyhow::Result<()>;
+    /// The settings of the bot changed through the API, see
+    /// [`crate::settings`].
+    async fn get_settings_overrides(&self, bot_id: &str) -> anyhow::Result<Option<Value>>;
+    /// Replaces the stored overrides of the bot, an empty object when there
+    /// are none, by what `update` makes of them while holding their row, so
+    /// concurrent changes don't undo each other. Returns the new overrides.
+    async fn update_settings_overrides(
+        &self,
+        bot_id: &str,
+        update: &(dyn Fn(Value) -> anyhow::Result<Value> + Send + Sync),
+    ) -> anyhow::Result<Value>;
+    /// When the last weekly report of the bot went out.
+    async fn get_weekly_report_at(&self, bot_id: &str) -> anyhow::Result<Option<DateTime<Utc>>>;
+    async fn set_weekly_report_at(&self, bot_id: &str, at: DateTime<Utc>) -> anyhow::Result<()>;
+    /// Counts of the bot from `from` up to `to`, with at most `top_errors`
+    /// errors.
+    async fn get_report_counts(
+        &self,
+        bot_id: &str,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        top_errors: i64,
+    ) -> anyhow::Result<ReportCounts>;
+
+    async fn get_captcha(&self, chat_id: i64) -> anyhow::Result<Option<Captcha>>;
+    async fn set_captcha(&self, chat_id: i64, captcha: &Captcha) -> anyhow::Result<()>;
+    /// Returns `false` when the chat had no captcha.
+    async fn delete_captcha(&self, chat_id: i64) -> anyhow::Result<bool>;
+    /// Replaces the challenge of a member that joined again.
+    async fn add_captcha_challenge(&self, challenge: &CaptchaChallenge) -> anyhow::Result<()>;
+    /// Soonest to expire first.
+    async fn get_captcha_challenges(&self, chat_id: i64) -> anyhow::Result<Vec<CaptchaChallenge>>;
+    async fn get_captcha_challenge(
+        &self,
+        chat_id: i64,
+        user_id: i64,
+    ) -> anyhow::Result<Option<CaptchaChallenge>>;
+    async fn delete_captcha_challenge(
+        &self,
+        chat_id: i64,
+        user_id: i64,
+    ) -> anyhow::Result<Option<CaptchaChallenge>>;
+    /// Returns the challenges of the bot's chats that expired before `now`
+    /// and moves their expiry to `retry_at`, so every challenge is handled
+    /// by one instance and tried again when handling it fails.
+    async fn claim_expired_captcha_challenges(
+        &self,
+        bot_id: &str,
+        now: DateTime<Utc>,
+        retry_at: DateTime<Utc>,
+    ) -> anyhow::Result<Vec<CaptchaChallenge>>;
+
+    async fn add_invite_link(
+        &self,
+        chat_id: i64,
+        link: &ChatInviteLink,
+    ) -> anyhow::Result<InviteLink>;
+    /// Newest first.
+    async fn get_invite_links(&self, chat_id: i64) -> anyhow::Result<Vec<InviteLink>>;
+    async fn get_invite_link(&self, chat_id: i64, id: i32) -> anyhow::Result<Option<InviteLink>>;
+    /// Marks the link as revoked, `None` when the chat has no such link.
+    async fn revoke_invite_link(&self, chat_id: i64, id: i32)
+        -> anyhow::Result<Option<InviteLink>>;
+
+    async fn get_join_policy(&self, chat_id: i64) -> anyhow::Result<Option<JoinPolicy>>;
+    async fn set_join_policy(&self, chat_id: i64, policy: JoinPolicy) -> anyhow::Result<()>;
+    async fn add_join_request(&self, request: NewJoinRequest<'_>) -> anyhow::Result<JoinRequest>;
+    /// Newest first.
+    async fn get_join_requests(
+        &self,
+        chat_id: i64,
+        status: Option<JoinRequestStatus>,
+    ) -> anyhow::Result<Vec<JoinRequest>>;
+    async fn get_join_request(&self, id: i32) -> anyhow::Result<Option<JoinRequest>>;
+    /// Records the decision on a pending request, `None` when it was decided
+    /// already.
+    async fn decide_join_request(
+        &self,
+        id: i32,
+        status: JoinRequestStatus,
+        decided_by: &str,
+    ) -> anyhow::Result<Option<JoinRequest>>;
+
+    /// `None` for the chat excludes the user in every chat of the bot.
+    async fn add_exclusion(
+        &self,
+        bot_id: &str,
+        chat_id: Option<i64>,
+        user_id: i64,
+        note: Option<&str>,
+    ) -> anyhow::Result<Exclusion>;
+    async fn get_exclusions(&self, bot_id: &str) -> anyhow::Result<Vec<Exclusion>>;
+    async fn delete_exclusion(&self, bot_id: &str, id: i32) -> anyhow::Result<bool>;
+    async fn get_excluded_users(&self, bot_id: &str, chat_id: i64) -> anyhow::Result<Vec<i64>>;
+
+    /// Returns `false` when the user was already subscribed, their name is
+    /// refreshed either way.
+    async fn add_subscriber(
+        &self,
+        bot_id: &str,
+        user_id: i64,
+        username: Option<&str>,
+        name: &str,
+    ) -> anyhow::Result<bool>;
+    /// Returns `false` when the user was not subscribed.
+    async fn remove_subscriber(&self, bot_id: &str, user_id: i64) -> anyhow::Result<bool>;
+    async fn get_subscribers(&self, bot_id: &str) -> anyhow::Result<Vec<i64>>;
+
+    async fn queue_message(&self, message: NewQueuedMessage<'_>) -> anyhow::Result<i32>;
+    async fn get_queued_message(&self, id: i32) -> anyhow::Result<Option<QueuedMessage>>;
+    /// Latest message of the bot queued after `since` with the same text for
+    /// the same audience whose targets overlap `chats`, where `None` is the
+    /// whole audience. Messages waiting for approval don't count.
+    async fn find_duplicate_message(
+        &self,
+        bot_id: &str,
+        text_hash: &str,
+        since: DateTime<Utc>,
+        chats: Option<&[i64]>,
+        audience: Audience,
+    ) -> anyhow::Result<Option<i32>>;
+    /// Queued messages of the bot matching the filter, ordered by when they
+    /// are due.
+    async fn get_queue(
+        &self,
+        bot_id: &str,
+        filter: &QueueFilter,
+        limit: i64,
+        offset: i64,
+    ) -> anyhow::Result<Vec<QueueEntry>>;
+    /// Up to `limit` messages due by `until`, the earliest first.
+    async fn get_pending_messages(
+        &self,
+        bot_id: &str,
+        until: DateTime<Utc>,
+        limit: i64,
+    ) -> anyhow::Result<Vec<PendingMessage>>;
+    /// Messages not completed yet of every bot, scheduled and awaiting
+    /// approval included, those given up on left out.
+    async fn get_queue_depths(&self) -> anyhow::Result<Vec<(String, i64)>>;
+    /// Marks the message as processing unless another instance holds it. A
+    /// claim not renewed within [`CLAIM_LEASE`] is assumed to belong to a
+    /// crashed instance.
+    async fn claim_queued_message(&self, id: i32) -> anyhow::Result<Option<QueuedMessage>>;
+    /// Extends the claim on a message that is still being sent.
+    async fn renew_claim(&self, id: i32) -> anyhow::Result<()>;
+    async fn complete_queued_message(&self, id: i32) -> anyhow::Result<()>;
+    /// Releases the claim on a message and holds it back until `until`, for
+    /// the chats it still has to reach. Its `datetime` stays as scheduled.
+    async fn defer_queued_message(&self, id: i32, until: DateTime<Utc>) -> anyhow::Result<()>;
+    /// Drops the failed deliveries of a completed message and makes it due
+    /// again at `datetime`, so the queue sends it to those chats only.
+    /// Returns the chats, empty when nothing failed or the message is not
+    /// completed.
+    async fn retry_failed_deliveries(
+        &self,
+        id: i32,
+        datetime: DateTime<Utc>,
+    ) -> anyhow::Result<Vec<i64>>;
+    /// Releases the claim on a message that failed to send and counts the
+    /// attempt. Once `max_attempts` is reached the message is moved to the
+    /// failed messages, which the queue skips, and `true` is returned.
+    async fn fail_queued_message(
+        &self,
+        id: i32,
+        error: &str,
+        max_attempts: i32,
+    ) -> anyhow::Result<bool>;
+    /// Newest first.
+    async fn get_failed_messages(&self, bot_id: &str) -> anyhow::Result<Vec<FailedMessage>>;
+    /// Puts a failed message of the bot back into the queue with its attempts
+    /// reset. Returns `false` when there is no such failed message.
+    async fn retry_failed_message(&self, bot_id: &str, id: i32) -> anyhow::Result<bool>;
+    /// Replaces the text and time of the message while it is neither claimed,
+    /// delivered to any chat, completed nor failed, and keeps the replaced
+    /// version. Returns `false` when it isn't.
+    async fn edit_queued_message(
+        &self,
+        id: i32,
+        message: &str,
+        datetime: DateTime<Utc>,
+        edited_by: &str,
+    ) -> anyhow::Result<bool>;
+    /// Newest first.
+    async fn get_queued_message_history(
+        &self,
+        id: i32,
+    ) -> anyhow::Result<Vec<QueuedMessageVersion>>;
+    /// Returns `false` when there is no such message waiting for approval.
+    async fn approve_queued_message(&self, id: i32) -> anyhow::Result<bool>;
+    async fn delete_queued_message(&self, id: i32) -> anyhow::Result<()>;
+    /// Stores every unmuted, approved chat of the bot as the targets of the
+    /// message and returns them.
+    async fn resolve_all_chats(&self, bot_id: &str, id: i32) -> anyhow::Result<Vec<i64>>;
+    /// Stores every subscriber of the bot as the targets of the message and
+    /// returns them, private chats share the id of their user.
+    async fn resolve_subscribers(&self, bot_id: &str, id: i32) -> anyhow::Result<Vec<i64>>;
+    async fn get_excluded_chats(&self, message_id: i32) -> anyhow::Result<Vec<i64>>;
+    /// Images uploaded as files before they were kept in `media_blob`.
+    async fn get_message_images(&self, message_id: i32) -> anyhow::Result<Vec<Vec<u8>>>;
+    async fn get_media_blob(&self, hash: &str) -> anyhow::Result<Option<Vec<u8>>>;
+    /// Deletes the blobs stored before `stored_before` that no queued
+    /// message or variant refers to any more. Queueing a message with a
+    /// stored blob counts as storing it again.
+    async fn delete_unused_media_blobs(&self, stored_before: DateTime<Utc>) -> anyhow::Result<u64>;
+    async fn get_message_threads(&self, message_id: i32) -> anyhow::Result<HashMap<i64, i32>>;
+
+    /// Chats the message was sent to or failed in, deferred ones aren't done
+    /// yet.
+    async fn get_delivered_chats(&self, message_id: i32) -> anyhow::Result<Vec<i64>>;
+    async fn record_delivery(
+        &self,
+        message_id: i32,
+        chat_id: i64,
+        error: Option<String>,
+        pin_error: Option<String>,
+        request_id: Option<&str>,
+    ) -> anyhow::Result<()>;
+    /// Sinks that took the message, failed ones aren't counted.
+    async fn get_sink_deliveries(&self, message_id: i32) -> anyhow::Result<Vec<String>>;
+    async fn record_sink_delivery(
+        &self,
+        message_id: i32,
+        sink: &str,
+        error: Option<String>,
+    ) -> anyhow::Result<()>;
+    /// Notes that the chat is held back until `until`, replaced by the
+    /// delivery once the message is sent there.
+    async fn defer_delivery(
+        &self,
+        message_id: i32,
+        chat_id: i64,
+        until: DateTime<Utc>,
+    ) -> anyhow::Result<()>;
+    async fn get_broadcast_progress(
+        &self,
+        message_id: i32,
+    ) -> anyhow::Result<Option<BroadcastProgress>>;
+    async fn get_deliveries(&self, message_id: i32) -> anyhow::Result<Vec<Delivery>>;
+    /// Replaces what was kept for the same telegram message, like after a
+    /// retry.
+    async fn record_delivered_files(
+        &self,
+        message_id: i32,
+        files: &[DeliveredFile],
+    ) -> anyhow::Result<()>;
+    /// Ordered by chat and telegram message.
+    async fn get_delivered_files(
+        &self,
+        message_id: i32,
+        chat_id: Option<i64>,
+    ) -> anyhow::Result<Vec<DeliveredFile>>;
+    async fn get_message_variants(&self, message_id: i32) -> anyhow::Result<Vec<MessageVariant>>;
+    async fn get_message_translations(&self, message_id: i32) -> anyhow::Result<Translations>;
+    /// Empty for messages queued without attachments.
+    async fn get_message_attachments(&self, message_id: i32) -> anyhow::Result<Attachments>;
+    /// Keeps the first assignment of a chat, a resumed broadcast picks the
+    /// same variant anyway.
+    async fn assign_variant(
+        &self,
+        message_id: i32,
+        chat_id: i64,
+        variant: i32,
+    ) -> anyhow::Result<()>;
+    async fn get_variant_results(&self, message_id: i32) -> anyhow::Result<Vec<VariantResult>>;
+
+    async fn add_webhook(
+        &self,
+        bot_id: &str,
+        url: &str,
+        secret: &str,
+        events: &[WebhookEvent],
+    ) -> anyhow::Result<Webhook>;
+    async fn get_webhooks(&self, bot_id: &str) -> anyhow::Result<Vec<Webhook>>;
+    async fn delete_webhook(&self, bot_id: &str, id: i32) -> anyhow::Result<bool>;
+
+    async fn add_draft(&self, bot_id: &str, content: &DraftContent) -> anyhow::Result<Draft>;
+    async fn get_drafts(&self, bot_id: &str) -> anyhow::Result<Vec<Draft>>;
+    async fn get_draft(&self, bot_id: &str, id: i32) -> anyhow::Result<Option<Draft>>;
+    async fn update_draft(
+        &self,
+        bot_id: &str,
+        id: i32,
+        content: &DraftContent,
+    ) -> anyhow::Result<Option<Draft>>;
+    async fn delete_draft(&self, bot_id: &str, id: i32) -> anyhow::Result<bool>;
+
+    /// Records a request before it is handled and returns the entry id.
+    async fn start_audit(&self, entry: NewAuditEntry<'_>) -> anyhow::Result<i64>;
+    async fn finish_audit(&self, id: i64, status: i32) -> anyhow::Result<()>;
+    /// Deletes the entries of the bot made before `before`.
+    async fn delete_audit_entries(
+        &self,
+        bot_id: &str,
+        before: DateTime<Utc>,
+    ) -> anyhow::Result<u64>;
+    async fn get_audit_log(
+        &self,
+        bot_id: &str,
+        since: Option<DateTime<Utc>>,
+        actor: Option<&str>,
+        request_id: Option<&str>,
+        limit: i64,
+    ) -> anyhow::Result<Vec<AuditEntry>>;
+
+    /// Adds to the counters of the actor on that day.
+    async fn record_usage(
+        &self,
+        bot_id: &str,
+        actor: &str,
+        day: NaiveDate,
+        usage: &Usage,
+    ) -> anyhow::Result<()>;
+    /// Ordered by day and actor.
+    async fn get_usage(
+        &self,
+        bot_id: &str,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> anyhow::Result<Vec<UsageEntry>>;
+}
+
+/// Connects to the database named by the url scheme and runs its
+/// migrations. The postgres pool is also returned for the features only
+/// postgres supports.
+pub async fn connect(
+    url: &str,
+    max_connections: u32,
+    config: &DatabasePoolConfig,
+) -> anyhow::Result<(Arc<dyn Storage>, Option<PgPool>)> {
+    if url.starts_with("postgres:") || url.starts_with("postgresql:") {
+        let storage = PgStorage::connect(url, max_connections, config).await?;
+        let pool = storage.pool().clone();
+        Ok((Arc::new(storage), Some(pool)))
+    } else if url.starts_with("sqlite:") {
+        let storage = SqliteStorage::connect(url, max_connections, config).await?;
+        Ok((Arc::new(storage), None))
+    } else {
+        bail!("unsupported DATABASE_URL, expected a postgres:// or sqlite: url");
+    }
+}
+
+fn pool_options<DB: Database>(
+    max_connections: u32,
+    config: &DatabasePoolConfig,
+) -> PoolOptions<DB> {
+    PoolOptions::new()
+        .max_connections(max_connections)
+        .min_connections(config.min_connections)
+        .acquire_timeout(config.acquire_timeout())
+        .idle_timeout(config.idle_timeout())
+        .max_lifetime(config.max_lifetime())
+}
+
+/// Keeps trying to reach a database that is still starting up or briefly
+/// down, doubling the delay between the attempts.
+async fn connect_with_retry<T, F, Fut>(
+    config: &DatabasePoolConfig,
+    mut connect: F,
+) -> anyhow::Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, sqlx::Error>>,
+{
+    let mut delay = Duration::from_secs(1);
+    let mut attempt = 1;
+    loop {
+        match connect().await {
+            Ok(connected) => return Ok(connected),
+            Err(err) if config.connect_attempts == 0 || attempt < config.connect_attempts => {
+                warn!("database connection attempt {attempt} failed, retrying in {delay:?}: {err}");
+                tokio::time::sleep(delay).await;
+                delay = (delay * 2).min(config.connect_retry_max_delay());
+                attempt += 1;
+            }
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| format!("no database connection after {attempt} attempts"))
+            }
+        }
+    }
+}
+
+/// Postgres aborts one of two transactions that conflict under concurrent
+/// load, running it again from the start usually succeeds.
+/// How long a claim on a queued message holds without being renewed, a
+/// claim not renewed for longer is assumed to belong to a crashed instance.
+pub const CLAIM_LEASE: Duration = Duration::from_secs(5 * 60);
+
+/// A `LIKE` pattern matching `search` anywhere in the text. Its own `%`, `_`
+/// and `\` are escaped, so the query has to declare `ESCAPE '\'`.
+pub(crate) fn contains_pattern(search: &str) -> String {
+    let mut pattern = String::with_capacity(search.len() + 2);
+    pattern.push('%');
+    for c in search.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern.push('%');
+    pattern
+}
+
+const SERIALIZATION_FAILURE: &str = "40001";
+const DEADLOCK_DETECTED: &str = "40P01";
+const MAX_TRANSACTION_ATTEMPTS: u32 = 4;
+
+/// Whether the error is postgres giving up on a transaction for a
+/// serialization failure or a deadlock, rather than anything wrong with it.
+pub(crate) fn is_transaction_conflict(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<sqlx::Error>()
+        .and_then(|err| err.as_database_error())
+        .and_then(|err| err.code())
+        .is_some_and(|code| code == SERIALIZATION_FAILURE || code == DEADLOCK_DETECTED)
+}
+
+/// Runs the transaction again when it was aborted by a conflict with a
+/// concurrent one, a few times with a short growing delay, so concurrent API
+/// calls don't fail with spurious 500s. Other errors are returned right away.
+pub(crate) async fn retry_transaction<T, F, Fut>(mut run: F) -> anyhow::Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = anyhow::Result<T>>,
+{
+    let mut delay = Duration::from_millis(20);
+    let mut attempt = 1;
+    loop {
+        match run().await {
+            Err(err) if attempt < MAX_TRANSACTION_ATTEMPTS && is_transaction_conflict(&err) => {
+                warn!("transaction attempt {attempt} conflicted, retrying in {delay:?}: {err}");
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
@@ -0,0 +1,3979 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate, Utc};
+use serde_json::Value;
+use sqlx::{PgConnection, PgPool, Postgres};
+use teloxide::types::ChatInviteLink;
+
+use super::{
+    connect_with_retry, contains_pattern, pool_options, retry_transaction, ChatActivity,
+    ChatMetadata, NewQueuedMessage, PendingMessage, Storage, UpsertedChat, CLAIM_LEASE,
+};
+use crate::{
+    activity::InactiveMember,
+    admin::ChatAdmin,
+    attachment::Attachments,
+    audit::{AuditEntry, NewAuditEntry},
+    branding::Branding,
+    broadcast::{BroadcastProgress, DeliveredFile, Delivery},
+    captcha::{Captcha, CaptchaChallenge},
+    chat_list::ChatQuery,
+    config::DatabasePoolConfig,
+    dead_letter::FailedMessage,
+    draft::DraftGone,
+    draft::{Draft, DraftContent},
+    duplicate::{text_hash, DuplicateBroadcast},
+    exclusion::Exclusion,
+    invite_link::InviteLink,
+    join_request::{JoinPolicy, JoinRequest, JoinRequestStatus, NewJoinRequest},
+    language::Translations,
+    moderation::{ModerationAction, ModerationKind, ModerationSettings, Warning},
+    queue::{QueueEntry, QueueFilter},
+    queue_history::QueuedMessageVersion,
+    quiet_hours::QuietHours,
+    reconcile::InterruptedCleanup,
+    rename::ChatRename,
+    report::{ErrorCount, ReportCounts},
+    staged_cleanup::CleanupCandidate,
+    state::{Chat, Chats, Kick, Kicks, QueuedMessage, User, Users},
+    subscriber::Audience,
+    topic::insert_message_threads,
+    usage::{Usage, UsageEntry},
+    variant::{MessageVariant, VariantResult},
+    webhook::{Webhook, WebhookEvent},
+    welcome::{Welcome, WelcomeDeletion},
+};
+
+pub struct PgStorage {
+    pool: PgPool,
+}
+
+impl PgStorage {
+    pub async fn connect(
+        url: &str,
+        max_connections: u32,
+        config: &DatabasePoolConfig,
+    ) -> anyhow::Result<Self> {
+        let pool = connect_with_retry(config, || {
+            pool_options::<Postgres>(max_connections, config).connect(url)
+        })
+        .await?;
+        sqlx::migrate!().run(&pool).await?;
+
+        Ok(Self { pool })
+    }
+
+    pub fn pool(&self) -> &PgPool {
+        &self.pool
+    }
+}
+
+/// Inserts the message with its threads, images and copy source, for callers
+/// that add more rows in the same transaction.
+pub async fn insert_queued_message(
+    conn: &mut PgConnection,
+    message: &NewQueuedMessage<'_>,
+) -> anyhow::Result<i32> {
+    let all_chats = message.targets.is_none();
+    let targets = message.targets.unwrap_or_default();
+    let chats: Vec<i64> = targets.iter().map(|target| target.chat_id()).collect();
+    let options = message.options;
+
+    if let Some(since) = message.duplicates_since {
+        let hash = text_hash(message.message);
+        // queueing the same text concurrently waits for the first message,
+        // so the check below sees it
+        sqlx::query("SELECT pg_advisory_xact_lock(hashtext($1))")
+            .bind(&hash)
+            .execute(&mut *conn)
+            .await?;
+        let chats = message.targets.map(|_| chats.as_slice());
+        if let Some(id) = find_duplicate_message(
+            &mut *conn,
+            message.bot_id,
+            &hash,
+            since,
+            chats,
+            options.audience,
+        )
+        .await?
+        {
+            return Err(DuplicateBroadcast(id).into());
+        }
+    }
+
+    if let Some(draft_id) = options.draft_id {
+        let deleted = sqlx::query!(
+            "DELETE FROM draft WHERE bot_id = $1 AND id = $2",
+            message.bot_id,
+            draft_id
+        )
+        .execute(&mut *conn)
+        .await?;
+        if deleted.rows_affected() == 0 {
+            return Err(DraftGone(draft_id).into());
+        }
+    }
+
+    for media in message.media {
+        sqlx::query!(
+            r#"
+            INSERT INTO media_blob ( hash, data )
+            VALUES ( $1, $2 )
+            ON CONFLICT (hash) DO UPDATE
+            SET created_at = now()
+            "#,
+            media.hash,
+            &media.data[..]
+        )
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    let id = sqlx::query_scalar!(
+        r#"
+        INSERT INTO message_queue ( chats, all_chats, message, images, datetime, parse_mode, pin, pin_silently, silent, disable_web_page_preview, protect_content, awaiting_approval, bot_id, audience, text_hash, reply_to_queue_id, datetime_local, collect_replies, delete_after_seconds, channel_silent, channel_protect_content, request_id )
+        VALUES ( $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20, $21, $22 )
+        RETURNING id
+        "#,
+        &chats,
+        all_chats,
+        message.message,
+        message.images,
+        message.datetime,
+        options.parse_mode.to_string(),
+        options.pin,
+        options.pin_silently,
+        options.silent,
+        options.disable_web_page_preview,
+        options.protect_content,
+        message.awaiting_approval,
+        message.bot_id,
+        options.audience.to_string(),
+        text_hash(message.message),
+        options.reply_to,
+        options.datetime_local,
+        options.collect_replies,
+        options.delete_after_seconds,
+        options.channel_silent,
+        options.channel_protect_content,
+        message.request_id
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+
+    insert_message_threads(&mut *conn, id, targets).await?;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO message_excluded_chat ( message_id, chat_id )
+        SELECT $1, * FROM unnest($2::BIGINT[])
+        ON CONFLICT DO NOTHING
+        "#,
+        id,
+        message.excluded_chats
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    for (variant, content) in message.variants.iter().enumerate() {
+        sqlx::query!(
+            r#"
+            INSERT INTO message_variant ( message_id, variant, message, images, weight )
+            VALUES ( $1, $2, $3, $4, $5 )
+            "#,
+            id,
+            variant as i32,
+            content.message,
+            &content.images,
+            content.weight
+        )
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    for (language, text) in message.translations {
+        sqlx::query!(
+            r#"
+            INSERT INTO message_translation ( message_id, language, message )
+            VALUES ( $1, $2, $3 )
+            "#,
+            id,
+            language,
+            text
+        )
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    if !message.attachments.is_empty() {
+        let location = message.attachments.location.as_ref();
+        sqlx::query!(
+            r#"
+            INSERT INTO message_attachment ( message_id, sticker, latitude, longitude, venue_title, venue_address )
+            VALUES ( $1, $2, $3, $4, $5, $6 )
+            "#,
+            id,
+            message.attachments.sticker,
+            location.map(|location| location.latitude),
+            location.map(|location| location.longitude),
+            location.and_then(|location| location.title.as_deref()),
+            location.and_then(|location| location.address.as_deref())
+        )
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    if let Some((from_chat_id, message_id)) = message.copy_from {
+        sqlx::query!(
+            r#"
+            UPDATE message_queue
+            SET copy_from_chat_id = $2, copy_message_id = $3
+            WHERE id = $1
+            "#,
+            id,
+            from_chat_id,
+            message_id
+        )
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    Ok(id)
+}
+
+/// A `join_request` row with its status as stored.
+struct JoinRequestRow {
+    id: i32,
+    chat_id: i64,
+    user_id: i64,
+    username: Option<String>,
+    name: String,
+    bio: Option<String>,
+    invite_link: Option<String>,
+    status: String,
+    requested_at: DateTime<Utc>,
+    decided_at: Option<DateTime<Utc>>,
+    decided_by: Option<String>,
+}
+
+impl JoinRequestRow {
+    fn parse(self) -> anyhow::Result<JoinRequest> {
+        Ok(JoinRequest {
+            id: self.id,
+            chat_id: self.chat_id,
+            user_id: self.user_id,
+            username: self.username,
+            name: self.name,
+            bio: self.bio,
+            invite_link: self.invite_link,
+            status: self.status.parse()?,
+            requested_at: self.requested_at,
+            decided_at: self.decided_at,
+            decided_by: self.decided_by,
+        })
+    }
+}
+
+async fn find_duplicate_message(
+    conn: &mut PgConnection,
+    bot_id: &str,
+    text_hash: &str,
+    since: DateTime<Utc>,
+    chats: Option<&[i64]>,
+    audience: Audience,
+) -> anyhow::Result<Option<i32>> {
+    let id = sqlx::query_scalar!(
+        r#"
+        SELECT id FROM message_queue
+        WHERE bot_id = $1 AND text_hash = $2 AND created_at > $3
+        AND audience = $4 AND NOT awaiting_approval
+        AND ( all_chats OR $5 OR chats && $6 )
+        ORDER BY id DESC
+        LIMIT 1
+        "#,
+        bot_id,
+        text_hash,
+        since,
+        audience.to_string(),
+        chats.is_none(),
+        chats.unwrap_or_default()
+    )
+    .fetch_optional(conn)
+    .await?;
+
+    Ok(id)
+}
+
+fn parse_events(events: &[String]) -> anyhow::Result<Vec<WebhookEvent>> {
+    events.iter().map(|event| event.parse()).collect()
+}
+
+#[async_trait]
+impl Storage for PgStorage {
+    async fn ping(&self) -> anyhow::Result<()> {
+        sqlx::query("SELECT 1").execute(&self.pool).await?;
+
+        Ok(())
+    }
+
+    async fn get_chats(&self, bot_id: &str) -> anyhow::Result<Chats> {
+        let chats = sqlx::query_as!(
+            Chat,
+            r#"
+SELECT id, name, muted, approved, kind, signs_messages, username, member_count, invite_link, description, photo_file_id, metadata_updated_at, rate_limit_override, archived, language, labels, notes, delete_service_messages, utc_offset_minutes
+FROM tg_chat
+WHERE bot_id = $1 AND NOT archived
+            "#,
+            bot_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(chats)
+    }
+
+    async fn query_chats(
+        &self,
+        bot_id: &str,
+        query: &ChatQuery,
+        limit: i64,
+        offset: i64,
+    ) -> anyhow::Result<(Chats, i64)> {
+        let total = sqlx::query_scalar!(
+            r#"
+SELECT COUNT(*) AS "count!" FROM tg_chat
+WHERE bot_id = $1 AND archived = $2
+AND ( $3::TEXT IS NULL OR name ILIKE '%' || $3 || '%' )
+AND ( $4::TEXT IS NULL OR $4 = ANY(labels) )
+AND ( $5::BOOL IS NULL OR muted = $5 )
+AND ( $6::BIGINT[] IS NULL OR id = ANY($6) )
+AND NOT id = ANY($7)
+AND ( $8::TEXT IS NULL OR kind = $8 )
+            "#,
+            bot_id,
+            query.archived,
+            query.search,
+            query.label,
+            query.muted,
+            query.only.as_deref(),
+            &query.except,
+            query.kind
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        let chats = sqlx::query_as!(
+            Chat,
+            r#"
+SELECT c.id, c.name, c.muted, c.approved, c.kind, c.signs_messages, c.username, c.member_count, c.invite_link, c.description, c.photo_file_id, c.metadata_updated_at, c.rate_limit_override, c.archived, c.language, c.labels, c.notes, c.delete_service_messages, c.utc_offset_minutes
+FROM tg_chat c
+LEFT JOIN (
+    SELECT chat_id, MAX(delivered_at) AS delivered_at FROM message_delivery
+    WHERE error IS NULL AND deferred_until IS NULL
+    GROUP BY chat_id
+) d ON d.chat_id = c.id
+WHERE c.bot_id = $1 AND c.archived = $2
+AND ( $3::TEXT IS NULL OR c.name ILIKE '%' || $3 || '%' )
+AND ( $4::TEXT IS NULL OR $4 = ANY(c.labels) )
+AND ( $5::BOOL IS NULL OR c.muted = $5 )
+AND ( $6::BIGINT[] IS NULL OR c.id = ANY($6) )
+AND NOT c.id = ANY($7)
+AND ( $8::TEXT IS NULL OR c.kind = $8 )
+ORDER BY
+    CASE WHEN $9 = 'name' AND NOT $10 THEN lower(c.name) END ASC,
+    CASE WHEN $9 = 'name' AND $10 THEN lower(c.name) END DESC,
+    CASE WHEN $9 = 'member_count' AND NOT $10 THEN c.member_count END ASC NULLS LAST,
+    CASE WHEN $9 = 'member_count' AND $10 THEN c.member_count END DESC NULLS LAST,
+    CASE WHEN $9 = 'last_broadcast' AND NOT $10 THEN d.delivered_at END ASC NULLS LAST,
+    CASE WHEN $9 = 'last_broadcast' AND $10 THEN d.delivered_at END DESC NULLS LAST,
+    c.id
+LIMIT $11 OFFSET $12
+            "#,
+            bot_id,
+            query.archived,
+            query.search,
+            query.label,
+            query.muted,
+            query.only.as_deref(),
+            &query.except,
+            query.kind,
+            query.sort.as_str(),
+            query.descending,
+            limit,
+            offset
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok((chats, total))
+    }
+
+    async fn upsert_chat(
+        &self,
+        bot_id: &str,
+        id: i64,
+        name: &str,
+        approved: bool,
+        kind: &str,
+        username: Option<&str>,
+    ) -> anyhow::Result<Option<UpsertedChat>> {
+        let row = sqlx::query!(
+            r#"
+INSERT INTO tg_chat ( id, name, approved, kind, username, bot_id )
+VALUES ( $1, $2, $3, $4, $5, $6 )
+ON CONFLICT (id) DO UPDATE
+SET name = $2, kind = $4, username = $5
+WHERE tg_chat.bot_id = $6
+RETURNING approved, archived, xmax = 0 AS "inserted!"
+            "#,
+            id,
+            name,
+            approved,
+            kind,
+            username,
+            bot_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(|row| UpsertedChat {
+            approved: row.approved,
+            inserted: row.inserted,
+            archived: row.archived,
+        }))
+    }
+
+    async fn update_chat_metadata(
+        &self,
+        bot_id: &str,
+        id: i64,
+        metadata: &ChatMetadata<'_>,
+    ) -> anyhow::Result<()> {
+        sqlx::query!(
+            r#"
+UPDATE tg_chat
+SET kind = $2, username = $3, member_count = $4, invite_link = $5, description = $6, photo_file_id = $7, metadata_updated_at = now()
+WHERE id = $1 AND bot_id = $8
+            "#,
+            id,
+            metadata.kind,
+            metadata.username,
+            metadata.member_count,
+            metadata.invite_link,
+            metadata.description,
+            metadata.photo_file_id,
+            bot_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn approve_chat(&self, bot_id: &str, id: i64) -> anyhow::Result<bool> {
+        let result = sqlx::query!(
+            r#"
+UPDATE tg_chat
+SET approved = true
+WHERE id = $1 AND bot_id = $2
+            "#,
+            id,
+            bot_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn get_unapproved_chats(&self, bot_id: &str) -> anyhow::Result<Vec<i64>> {
+        let chats = sqlx::query_scalar!(
+            r#"
+SELECT id FROM tg_chat
+WHERE NOT approved AND bot_id = $1
+            "#,
+            bot_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(chats)
+    }
+
+    async fn set_chat_muted(&self, bot_id: &str, id: i64, muted: bool) -> anyhow::Result<bool> {
+        let result = sqlx::query!(
+            r#"
+UPDATE tg_chat
+SET muted = $2
+WHERE id = $1 AND bot_id = $3
+            "#,
+            id,
+            muted,
+            bot_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn set_delete_service_messages(
+        &self,
+        bot_id: &str,
+        id: i64,
+        delete: bool,
+    ) -> anyhow::Result<bool> {
+        let result = sqlx::query!(
+            r#"
+UPDATE tg_chat
+SET delete_service_messages = $2
+WHERE id = $1 AND bot_id = $3
+            "#,
+            id,
+            delete,
+            bot_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn set_chat_signs_messages(
+        &self,
+        bot_id: &str,
+        id: i64,
+        signs: bool,
+    ) -> anyhow::Result<bool> {
+        let result = sqlx::query!(
+            r#"
+UPDATE tg_chat
+SET signs_messages = $2
+WHERE id = $1 AND bot_id = $3 AND signs_messages IS DISTINCT FROM $2
+            "#,
+            id,
+            signs,
+            bot_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn deletes_service_messages(&self, id: i64) -> anyhow::Result<Option<bool>> {
+        let delete = sqlx::query_scalar!(
+            r#"
+SELECT delete_service_messages FROM tg_chat
+WHERE id = $1
+            "#,
+            id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(delete)
+    }
+
+    async fn set_chat_rate_limit(
+        &self,
+        bot_id: &str,
+        id: i64,
+        per_minute: Option<i32>,
+    ) -> anyhow::Result<bool> {
+        let result = sqlx::query!(
+            r#"
+UPDATE tg_chat
+SET rate_limit_override = $2
+WHERE id = $1 AND bot_id = $3
+            "#,
+            id,
+            per_minute,
+            bot_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn set_chat_language(
+        &self,
+        bot_id: &str,
+        id: i64,
+        language: Option<&str>,
+    ) -> anyhow::Result<bool> {
+        let result = sqlx::query!(
+            r#"
+UPDATE tg_chat
+SET language = $2
+WHERE id = $1 AND bot_id = $3
+            "#,
+            id,
+            language,
+            bot_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn set_chat_utc_offset(
+        &self,
+        bot_id: &str,
+        id: i64,
+        minutes: Option<i32>,
+    ) -> anyhow::Result<bool> {
+        let result = sqlx::query!(
+            r#"
+UPDATE tg_chat
+SET utc_offset_minutes = $2
+WHERE id = $1 AND bot_id = $3
+            "#,
+            id,
+            minutes,
+            bot_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn set_chat_labels(
+        &self,
+        bot_id: &str,
+        id: i64,
+        labels: &[String],
+    ) -> anyhow::Result<bool> {
+        let result = sqlx::query!(
+            r#"
+UPDATE tg_chat
+SET labels = $2
+WHERE id = $1 AND bot_id = $3
+            "#,
+            id,
+            labels,
+            bot_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn set_chat_notes(
+        &self,
+        bot_id: &str,
+        id: i64,
+        notes: Option<&str>,
+    ) -> anyhow::Result<bool> {
+        let result = sqlx::query!(
+            r#"
+UPDATE tg_chat
+SET notes = $2
+WHERE id = $1 AND bot_id = $3
+            "#,
+            id,
+            notes,
+            bot_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn get_labelled_chats(
+        &self,
+        bot_id: &str,
+        labels: &[String],
+    ) -> anyhow::Result<Vec<i64>> {
+        let chats = sqlx::query_scalar!(
+            r#"
+SELECT id FROM tg_chat
+WHERE bot_id = $1 AND NOT archived AND labels && $2
+ORDER BY id
+            "#,
+            bot_id,
+            labels
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(chats)
+    }
+
+    async fn get_muted_chats(&self, bot_id: &str) -> anyhow::Result<Vec<i64>> {
+        let chats = sqlx::query_scalar!(
+            r#"
+SELECT id FROM tg_chat
+WHERE muted AND bot_id = $1
+            "#,
+            bot_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(chats)
+    }
+
+    async fn delete_chat(&self, bot_id: &str, id: i64) -> anyhow::Result<bool> {
+        let result = sqlx::query!(
+            r#"
+DELETE FROM tg_chat
+WHERE id = $1 AND bot_id = $2
+            "#,
+            id,
+            bot_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn set_chats_muted(
+        &self,
+        bot_id: &str,
+        ids: &[i64],
+        muted: bool,
+    ) -> anyhow::Result<Vec<i64>> {
+        let updated = sqlx::query_scalar!(
+            r#"
+UPDATE tg_chat
+SET muted = $3
+WHERE bot_id = $1 AND id = ANY($2)
+RETURNING id
+            "#,
+            bot_id,
+            ids,
+            muted
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(updated)
+    }
+
+    async fn delete_chats(&self, bot_id: &str, ids: &[i64]) -> anyhow::Result<Vec<i64>> {
+        retry_transaction(|| async move {
+            let deleted = sqlx::query_scalar!(
+                r#"
+DELETE FROM tg_chat
+WHERE bot_id = $1 AND id = ANY($2)
+RETURNING id
+                "#,
+                bot_id,
+                ids
+            )
+            .fetch_all(&self.pool)
+            .await?;
+
+            Ok(deleted)
+        })
+        .await
+    }
+
+    async fn set_chat_archived(
+        &self,
+        bot_id: &str,
+        id: i64,
+        archived: bool,
+    ) -> anyhow::Result<bool> {
+        let result = sqlx::query!(
+            r#"
+UPDATE tg_chat
+SET archived = $3, archived_at = CASE WHEN $3 THEN now() END
+WHERE id = $2 AND bot_id = $1 AND archived <> $3
+            "#,
+            bot_id,
+            id,
+            archived
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn rename_chat(&self, bot_id: &str, id: i64, name: &str) -> anyhow::Result<bool> {
+        let result = sqlx::query!(
+            r#"
+WITH old AS (
+    SELECT id, name FROM tg_chat
+    WHERE id = $1 AND bot_id = $3 AND name <> $2
+    FOR UPDATE
+), renamed AS (
+    UPDATE tg_chat SET name = $2
+    FROM old
+    WHERE tg_chat.id = old.id
+    RETURNING old.name AS old_name
+)
+INSERT INTO chat_name_history ( chat_id, old_name, new_name )
+SELECT $1, old_name, $2 FROM renamed
+            "#,
+            id,
+            name,
+            bot_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn get_chat_renames(&self, chat_id: i64) -> anyhow::Result<Vec<ChatRename>> {
+        let renames = sqlx::query_as!(
+            ChatRename,
+            r#"
+SELECT old_name, new_name, renamed_at FROM chat_name_history
+WHERE chat_id = $1
+ORDER BY renamed_at DESC, id DESC
+            "#,
+            chat_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(renames)
+    }
+
+    /// Also moves the cleanup schedule and the sent message and poll history,
+    /// which only postgres keeps.
+    async fn migrate_chat(&self, old_id: i64, new_id: i64) -> anyhow::Result<()> {
+        retry_transaction(|| async move {
+            let mut tx = self.pool.begin().await?;
+
+            let new_exists = sqlx::query_scalar!(
+                r#"
+        SELECT EXISTS ( SELECT 1 FROM tg_chat WHERE id = $1 ) AS "exists!"
+            "#,
+                new_id
+            )
+            .fetch_one(&mut tx)
+            .await?;
+
+            if new_exists {
+                // the new chat was already registered by a message, fold the old one into it
+                sqlx::query!(
+                    r#"
+        INSERT INTO tg_user ( id, chat_id, username, name, bot_id, last_message_at, joined_at, updated_at )
+        SELECT id, $1, username, name, bot_id, last_message_at, joined_at, updated_at FROM tg_user
+        WHERE chat_id = $2
+        ON CONFLICT ( id, chat_id ) DO NOTHING
+            "#,
+                    new_id,
+                    old_id
+                )
+                .execute(&mut tx)
+                .await?;
+
+                sqlx::query!(
+                    r#"
+        UPDATE tg_chat AS new
+        SET muted = new.muted OR old.muted, approved = new.approved OR old.approved,
+            labels = ARRAY( SELECT DISTINCT unnest( new.labels || old.labels ) ORDER BY 1 ),
+            notes = COALESCE( new.notes, old.notes )
+        FROM tg_chat AS old
+        WHERE new.id = $1 AND old.id = $2
+            "#,
+                    new_id,
+                    old_id
+                )
+                .execute(&mut tx)
+                .await?;
+
+                sqlx::query!(
+                    r#"
+        INSERT INTO cleanup_schedule ( chat_id, cron, next_run_at, last_run_at, last_error )
+        SELECT $1, cron, next_run_at, last_run_at, last_error FROM cleanup_schedule
+        WHERE chat_id = $2
+        ON CONFLICT ( chat_id ) DO NOTHING
+            "#,
+                    new_id,
+                    old_id
+                )
+                .execute(&mut tx)
+                .await?;
+
+                sqlx::query!(
+                    r#"
+        INSERT INTO chat_welcome ( chat_id, template, delete_after_minutes, updated_at )
+        SELECT $1, template, delete_after_minutes, updated_at FROM chat_welcome
+        WHERE chat_id = $2
+        ON CONFLICT ( chat_id ) DO NOTHING
+            "#,
+                    new_id,
+                    old_id
+                )
+                .execute(&mut tx)
+                .await?;
+
+                sqlx::query!(
+                    r#"
+        INSERT INTO chat_branding ( chat_id, header, footer, updated_at )
+        SELECT $1, header, footer, updated_at FROM chat_branding
+        WHERE chat_id = $2
+        ON CONFLICT ( chat_id ) DO NOTHING
+            "#,
+                    new_id,
+                    old_id
+                )
+                .execute(&mut tx)
+                .await?;
+
+                sqlx::query!(
+                    r#"
+        INSERT INTO chat_quiet_hours ( chat_id, start_time, end_time, utc_offset_minutes, updated_at )
+        SELECT $1, start_time, end_time, utc_offset_minutes, updated_at FROM chat_quiet_hours
+        WHERE chat_id = $2
+        ON CONFLICT ( chat_id ) DO NOTHING
+            "#,
+                    new_id,
+                    old_id
+                )
+                .execute(&mut tx)
+                .await?;
+
+                sqlx::query!(
+                    r#"
+        INSERT INTO chat_moderation ( chat_id, banned_words, banned_patterns, links, restrict_after, restrict_minutes, kick_after, updated_at )
+        SELECT $1, banned_words, banned_patterns, links, restrict_after, restrict_minutes, kick_after, updated_at FROM chat_moderation
+        WHERE chat_id = $2
+        ON CONFLICT ( chat_id ) DO NOTHING
+            "#,
+                    new_id,
+                    old_id
+                )
+                .execute(&mut tx)
+                .await?;
+
+                sqlx::query!(
+                    r#"
+        INSERT INTO chat_warning ( chat_id, user_id, warnings, last_reason, last_warned_at )
+        SELECT $1, user_id, warnings, last_reason, last_warned_at FROM chat_warning
+        WHERE chat_id = $2
+        ON CONFLICT ( chat_id, user_id ) DO NOTHING
+            "#,
+                    new_id,
+                    old_id
+                )
+                .execute(&mut tx)
+                .await?;
+
+                sqlx::query!(
+                    r#"
+        INSERT INTO tg_admin ( chat_id, user_id, username, name, status, updated_at )
+        SELECT $1, user_id, username, name, status, updated_at FROM tg_admin
+        WHERE chat_id = $2
+        ON CONFLICT ( chat_id, user_id ) DO NOTHING
+            "#,
+                    new_id,
+                    old_id
+                )
+                .execute(&mut tx)
+                .await?;
+
+                sqlx::query!(
+                    r#"
+        UPDATE chat_name_history
+        SET chat_id = $1
+        WHERE chat_id = $2
+            "#,
+                    new_id,
+                    old_id
+                )
+                .execute(&mut tx)
+                .await?;
+
+                sqlx::query!(
+                    r#"
+        UPDATE chat_group_member
+        SET chat_id = $1
+        WHERE chat_id = $2
+            "#,
+                    new_id,
+                    old_id
+                )
+                .execute(&mut tx)
+                .await?;
+
+                sqlx::query!(
+                    r#"
+        UPDATE moderation_log
+        SET chat_id = $1
+        WHERE chat_id = $2
+            "#,
+                    new_id,
+                    old_id
+                )
+                .execute(&mut tx)
+                .await?;
+
+                sqlx::query!(
+                    r#"
+        INSERT INTO exclusion ( bot_id, chat_id, user_id, note, created_at )
+        SELECT bot_id, $1, user_id, note, created_at FROM exclusion
+        WHERE chat_id = $2
+        ON CONFLICT DO NOTHING
+            "#,
+                    new_id,
+                    old_id
+                )
+                .execute(&mut tx)
+                .await?;
+
+                sqlx::query!(
+                    r#"
+        DELETE FROM tg_chat
+        WHERE id = $1
+            "#,
+                    old_id
+                )
+                .execute(&mut tx)
+                .await?;
+            } else {
+                // members follow through `ON UPDATE CASCADE`
+                sqlx::query!(
+                    r#"
+        UPDATE tg_chat
+        SET id = $1
+        WHERE id = $2
+            "#,
+                    new_id,
+                    old_id
+                )
+                .execute(&mut tx)
+                .await?;
+            }
+
+            sqlx::query!(
+                r#"
+        UPDATE message_queue
+        SET chats = ARRAY(
+            SELECT chat FROM unnest(array_replace(chats, $2, $1)) WITH ORDINALITY AS t ( chat, position )
+            GROUP BY chat
+            ORDER BY min(position)
+        )
+        WHERE $2 = ANY(chats) AND completed_at IS NULL
+            "#,
+                new_id,
+                old_id
+            )
+            .execute(&mut tx)
+            .await?;
+
+            sqlx::query!(
+                r#"
+        UPDATE sent_message
+        SET chat_id = $1
+        WHERE chat_id = $2
+            "#,
+                new_id,
+                old_id
+            )
+            .execute(&mut tx)
+            .await?;
+
+            sqlx::query!(
+                r#"
+        UPDATE sent_poll
+        SET chat_id = $1
+        WHERE chat_id = $2
+            "#,
+                new_id,
+                old_id
+            )
+            .execute(&mut tx)
+            .await?;
+
+            tx.commit().await?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    async fn set_chat_group(
+        &self,
+        bot_id: &str,
+        chat_id: i64,
+        group: Option<&str>,
+        assigned_by: i64,
+    ) -> anyhow::Result<()> {
+        match group {
+            Some(group) => {
+                sqlx::query!(
+                    r#"
+INSERT INTO chat_group_member ( bot_id, chat_id, group_name, assigned_by )
+VALUES ( $1, $2, $3, $4 )
+ON CONFLICT (bot_id, chat_id) DO UPDATE
+SET group_name = $3, assigned_by = $4, assigned_at = now()
+                    "#,
+                    bot_id,
+                    chat_id,
+                    group,
+                    assigned_by
+                )
+                .execute(&self.pool)
+                .await?;
+            }
+            None => {
+                sqlx::query!(
+                    r#"
+DELETE FROM chat_group_member
+WHERE bot_id = $1 AND chat_id = $2
+                    "#,
+                    bot_id,
+                    chat_id
+                )
+                .execute(&self.pool)
+                .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn get_chat_group_assignments(&self, bot_id: &str) -> anyhow::Result<Vec<(i64, String)>> {
+        let assignments = sqlx::query!(
+            r#"
+SELECT chat_id, group_name FROM chat_group_member
+WHERE bot_id = $1
+ORDER BY chat_id
+            "#,
+            bot_id
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|row| (row.chat_id, row.group_name))
+        .collect();
+
+        Ok(assignments)
+    }
+
+    async fn get_all_members(&self, chat_id: i64) -> anyhow::Result<Users> {
+        let users = sqlx::query_as!(
+            User,
+            r#"
+SELECT id, chat_id, username, name, joined_at, updated_at FROM tg_user
+WHERE chat_id = $1
+            "#,
+            chat_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(users)
+    }
+
+    async fn get_members_page(
+        &self,
+        chat_id: i64,
+        search: Option<&str>,
+        limit: i64,
+        offset: i64,
+    ) -> anyhow::Result<Users> {
+        let pattern = search.map(contains_pattern);
+
+        let users = sqlx::query_as!(
+            User,
+            r#"
+SELECT id, chat_id, username, name, joined_at, updated_at FROM tg_user
+WHERE chat_id = $1
+AND ( $2::TEXT IS NULL OR username ILIKE $2 ESCAPE '\' OR name ILIKE $2 ESCAPE '\' )
+ORDER BY id
+LIMIT $3 OFFSET $4
+            "#,
+            chat_id,
+            pattern,
+            limit,
+            offset
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(users)
+    }
+
+    async fn get_member(&self, chat_id: i64, user_id: i64) -> anyhow::Result<Option<User>> {
+        let user = sqlx::query_as!(
+            User,
+            r#"
+SELECT id, chat_id, username, name, joined_at, updated_at FROM tg_user
+WHERE chat_id = $1 AND id = $2
+            "#,
+            chat_id,
+            user_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(user)
+    }
+
+    async fn count_members(&self, chat_id: i64) -> anyhow::Result<i64> {
+        let count = sqlx::query_scalar!(
+            r#"
+SELECT count(*) AS "count!" FROM tg_user
+WHERE chat_id = $1
+            "#,
+            chat_id
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(count)
+    }
+
+    async fn get_chat_activity(&self, bot_id: &str) -> anyhow::Result<Vec<ChatActivity>> {
+        let activity = sqlx::query_as!(
+            ChatActivity,
+            r#"
+SELECT c.id, c.name,
+    ( SELECT count(*) FROM tg_user u WHERE u.chat_id = c.id ) AS "member_count!",
+    (
+        SELECT count(*) FROM message_queue q
+        WHERE q.bot_id = c.bot_id AND q.completed_at IS NULL
+        AND ( c.id = ANY(q.chats) OR ( q.all_chats AND q.audience = 'chats' ) )
+        AND NOT EXISTS ( SELECT 1 FROM failed_message f WHERE f.queue_id = q.id )
+    ) AS "queued_broadcasts!"
+FROM tg_chat c
+WHERE c.bot_id = $1 AND NOT c.archived
+ORDER BY c.id
+            "#,
+            bot_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(activity)
+    }
+
+    async fn upsert_member(
+        &self,
+        bot_id: &str,
+        chat_id: i64,
+        user_id: i64,
+        username: &str,
+        name: &str,
+    ) -> anyhow::Result<()> {
+        sqlx::query!(
+            r#"
+INSERT INTO tg_user ( id, chat_id, username, name, bot_id, joined_at, updated_at )
+VALUES ( $1, $2, $3, $4, $5, now(), now() )
+ON CONFLICT ( id, chat_id ) DO UPDATE
+SET username = $3, name = $4, updated_at = CASE
+    WHEN tg_user.username IS DISTINCT FROM $3 OR tg_user.name <> $4 THEN now()
+    ELSE tg_user.updated_at
+END
+            "#,
+            user_id,
+            chat_id,
+            username,
+            name,
+            bot_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn touch_member(
+        &self,
+        chat_id: i64,
+        user_id: i64,
+        at: DateTime<Utc>,
+    ) -> anyhow::Result<()> {
+        sqlx::query!(
+            r#"
+UPDATE tg_user
+SET last_message_at = $3
+WHERE chat_id = $1 AND id = $2
+AND ( last_message_at IS NULL OR last_message_at < $3 )
+            "#,
+            chat_id,
+            user_id,
+            at
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_inactive_members(
+        &self,
+        chat_id: i64,
+        before: DateTime<Utc>,
+    ) -> anyhow::Result<Vec<InactiveMember>> {
+        let members = sqlx::query_as!(
+            InactiveMember,
+            r#"
+SELECT id, chat_id, username, name, last_message_at FROM tg_user
+WHERE chat_id = $1
+AND COALESCE(last_message_at, joined_at) < $2
+ORDER BY COALESCE(last_message_at, joined_at), id
+            "#,
+            chat_id,
+            before
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(members)
+    }
+
+    async fn remove_member(&self, chat_id: i64, user_id: i64) -> anyhow::Result<()> {
+        sqlx::query!(
+            r#"
+DELETE FROM tg_user
+WHERE id = $1 AND chat_id = $2
+            "#,
+            user_id,
+            chat_id,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn log_kick(
+        &self,
+        chat_id: i64,
+        user: &User,
+        reason: &str,
+        initiator: &str,
+        request_id: Option<&str>,
+    ) -> anyhow::Result<()> {
+        sqlx::query!(
+            r#"
+INSERT INTO kick_log ( chat_id, user_id, username, name, reason, initiator, request_id )
+VALUES ( $1, $2, $3, $4, $5, $6, $7 )
+            "#,
+            chat_id,
+            user.id,
+            user.username,
+            user.name,
+            reason,
+            initiator,
+            request_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_kicks(&self, chat_id: i64, limit: i64, offset: i64) -> anyhow::Result<Kicks> {
+        let kicks = sqlx::query_as!(
+            Kick,
+            r#"
+SELECT id, chat_id, user_id, username, name, reason, initiator, kicked_at, request_id FROM kick_log
+WHERE chat_id = $1
+ORDER BY kicked_at DESC, id DESC
+LIMIT $2 OFFSET $3
+            "#,
+            chat_id,
+            limit,
+            offset
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(kicks)
+    }
+
+    async fn get_kicked_user_ids(&self, chat_id: i64) -> anyhow::Result<Vec<i64>> {
+        let users = sqlx::query_scalar!(
+            r#"
+SELECT DISTINCT user_id FROM kick_log
+WHERE chat_id = $1
+ORDER BY user_id
+            "#,
+            chat_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(users)
+    }
+
+    async fn add_cleanup_candidate(
+        &self,
+        chat_id: i64,
+        user: &User,
+        initiator: &str,
+    ) -> anyhow::Result<()> {
+        sqlx::query!(
+            r#"
+INSERT INTO cleanup_candidate ( chat_id, user_id, username, name, initiator )
+VALUES ( $1, $2, $3, $4, $5 )
+ON CONFLICT DO NOTHING
+            "#,
+            chat_id,
+            user.id,
+            user.username,
+            user.name,
+            initiator
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_cleanup_candidates(&self, chat_id: i64) -> anyhow::Result<Vec<CleanupCandidate>> {
+        let candidates = sqlx::query_as!(
+            CleanupCandidate,
+            r#"
+SELECT user_id, username, name, initiator, staged_at FROM cleanup_candidate
+WHERE chat_id = $1
+ORDER BY name, user_id
+            "#,
+            chat_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(candidates)
+    }
+
+    async fn delete_cleanup_candidates(&self, chat_id: i64) -> anyhow::Result<u64> {
+        let result = sqlx::query!(
+            r#"
+DELETE FROM cleanup_candidate
+WHERE chat_id = $1
+            "#,
+            chat_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn start_cleanup_run(
+        &self,
+        chat_id: i64,
+        staged: bool,
+        initiator: &str,
+    ) -> anyhow::Result<()> {
+        sqlx::query!(
+            r#"
+INSERT INTO cleanup_run ( chat_id, staged, initiator )
+VALUES ( $1, $2, $3 )
+ON CONFLICT (chat_id) DO UPDATE
+SET staged = $2, initiator = $3, started_at = now(), resume_at = NULL
+            "#,
+            chat_id,
+            staged,
+            initiator
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn finish_cleanup_run(&self, chat_id: i64) -> anyhow::Result<()> {
+        sqlx::query!(
+            r#"
+DELETE FROM cleanup_run
+WHERE chat_id = $1
+            "#,
+            chat_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn set_cleanup_cursor(&self, chat_id: i64, last_user_id: i64) -> anyhow::Result<()> {
+        sqlx::query!(
+            r#"
+UPDATE cleanup_run
+SET last_user_id = $2
+WHERE chat_id = $1
+            "#,
+            chat_id,
+            last_user_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn pause_cleanup_run(
+        &self,
+        chat_id: i64,
+        resume_at: DateTime<Utc>,
+    ) -> anyhow::Result<()> {
+        sqlx::query!(
+            r#"
+UPDATE cleanup_run
+SET resume_at = $2
+WHERE chat_id = $1
+            "#,
+            chat_id,
+            resume_at
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_cleanup_run(&self, chat_id: i64) -> anyhow::Result<Option<InterruptedCleanup>> {
+        let run = sqlx::query_as!(
+            InterruptedCleanup,
+            r#"
+SELECT chat_id, staged, initiator, started_at, last_user_id, resume_at
+FROM cleanup_run
+WHERE chat_id = $1
+            "#,
+            chat_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(run)
+    }
+
+    async fn get_cleanup_runs(&self, bot_id: &str) -> anyhow::Result<Vec<InterruptedCleanup>> {
+        let runs = sqlx::query_as!(
+            InterruptedCleanup,
+            r#"
+SELECT r.chat_id, r.staged, r.initiator, r.started_at, r.last_user_id, r.resume_at
+FROM cleanup_run r
+JOIN tg_chat c ON c.id = r.chat_id
+WHERE c.bot_id = $1
+ORDER BY r.started_at, r.chat_id
+            "#,
+            bot_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(runs)
+    }
+
+    async fn set_chat_admins(&self, chat_id: i64, admins: &[ChatAdmin]) -> anyhow::Result<()> {
+        retry_transaction(|| async move {
+            let mut tx = self.pool.begin().await?;
+
+            sqlx::query!(
+                r#"
+DELETE FROM tg_admin
+WHERE chat_id = $1
+                "#,
+                chat_id
+            )
+            .execute(&mut tx)
+            .await?;
+
+            for admin in admins {
+                sqlx::query!(
+                    r#"
+INSERT INTO tg_admin ( chat_id, user_id, username, name, status, updated_at )
+VALUES ( $1, $2, $3, $4, $5, $6 )
+                    "#,
+                    chat_id,
+                    admin.user_id,
+                    admin.username,
+                    admin.name,
+                    admin.status,
+                    admin.updated_at
+                )
+                .execute(&mut tx)
+                .await?;
+            }
+
+            tx.commit().await?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    async fn upsert_chat_admin(&self, chat_id: i64, admin: &ChatAdmin) -> anyhow::Result<()> {
+        sqlx::query!(
+            r#"
+INSERT INTO tg_admin ( chat_id, user_id, username, name, status, updated_at )
+VALUES ( $1, $2, $3, $4, $5, $6 )
+ON CONFLICT ( chat_id, user_id ) DO UPDATE
+SET username = $3, name = $4, status = $5, updated_at = $6
+            "#,
+            chat_id,
+            admin.user_id,
+            admin.username,
+            admin.name,
+            admin.status,
+            admin.updated_at
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn remove_chat_admin(&self, chat_id: i64, user_id: i64) -> anyhow::Result<()> {
+        sqlx::query!(
+            r#"
+DELETE FROM tg_admin
+WHERE chat_id = $1 AND user_id = $2
+            "#,
+            chat_id,
+            user_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_chat_admins(&self, chat_id: i64) -> anyhow::Result<Vec<ChatAdmin>> {
+        let admins = sqlx::query_as!(
+            ChatAdmin,
+            r#"
+SELECT user_id, username, name, status, updated_at FROM tg_admin
+WHERE chat_id = $1
+ORDER BY status = 'creator' DESC, name, user_id
+            "#,
+            chat_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(admins)
+    }
+
+    async fn get_welcome(&self, chat_id: i64) -> anyhow::Result<Option<Welcome>> {
+        let welcome = sqlx::query_as!(
+            Welcome,
+            r#"
+SELECT template, delete_after_minutes FROM chat_welcome
+WHERE chat_id = $1
+            "#,
+            chat_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(welcome)
+    }
+
+    async fn set_welcome(&self, chat_id: i64, welcome: &Welcome) -> anyhow::Result<()> {
+        sqlx::query!(
+            r#"
+INSERT INTO chat_welcome ( chat_id, template, delete_after_minutes )
+VALUES ( $1, $2, $3 )
+ON CONFLICT (chat_id) DO UPDATE
+SET template = $2, delete_after_minutes = $3, updated_at = now()
+            "#,
+            chat_id,
+            welcome.template,
+            welcome.delete_after_minutes
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn delete_welcome(&self, chat_id: i64) -> anyhow::Result<bool> {
+        let result = sqlx::query!(
+            r#"
+DELETE FROM chat_welcome
+WHERE chat_id = $1
+            "#,
+            chat_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn add_welcome_deletion(
+        &self,
+        deletion: &WelcomeDeletion,
+        delete_at: DateTime<Utc>,
+    ) -> anyhow::Result<()> {
+        sqlx::query!(
+            r#"
+INSERT INTO welcome_deletion ( chat_id, message_id, delete_at )
+VALUES ( $1, $2, $3 )
+ON CONFLICT (chat_id, message_id) DO NOTHING
+            "#,
+            deletion.chat_id,
+            deletion.message_id,
+            delete_at
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_due_welcome_deletions(
+        &self,
+        bot_id: &str,
+        now: DateTime<Utc>,
+        limit: i64,
+    ) -> anyhow::Result<Vec<WelcomeDeletion>> {
+        let deletions = sqlx::query_as!(
+            WelcomeDeletion,
+            r#"
+SELECT chat_id, message_id
+FROM welcome_deletion
+WHERE delete_at <= $2
+AND chat_id IN ( SELECT id FROM tg_chat WHERE bot_id = $1 )
+ORDER BY delete_at
+LIMIT $3
+            "#,
+            bot_id,
+            now,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(deletions)
+    }
+
+    async fn remove_welcome_deletion(&self, deletion: &WelcomeDeletion) -> anyhow::Result<()> {
+        sqlx::query!(
+            "DELETE FROM welcome_deletion WHERE chat_id = $1 AND message_id = $2",
+            deletion.chat_id,
+            deletion.message_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_branding(&self, chat_id: i64) -> anyhow::Result<Option<Branding>> {
+        let branding = sqlx::query_as!(
+            Branding,
+            r#"
+SELECT header, footer FROM chat_branding
+WHERE chat_id = $1
+            "#,
+            chat_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(branding)
+    }
+
+    async fn get_all_brandings(&self, bot_id: &str) -> anyhow::Result<HashMap<i64, Branding>> {
+        let rows = sqlx::query!(
+            r#"
+SELECT b.chat_id, b.header, b.footer
+FROM chat_branding b
+JOIN tg_chat c ON c.id = b.chat_id
+WHERE c.bot_id = $1
+            "#,
+            bot_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                (
+                    row.chat_id,
+                    Branding {
+                        header: row.header,
+                        footer: row.footer,
+                    },
+                )
+            })
+            .collect())
+    }
+
+    async fn set_branding(&self, chat_id: i64, branding: &Branding) -> anyhow::Result<()> {
+        if branding.is_empty() {
+            sqlx::query!(
+                r#"
+DELETE FROM chat_branding
+WHERE chat_id = $1
+                "#,
+                chat_id
+            )
+            .execute(&self.pool)
+            .await?;
+
+            return Ok(());
+        }
+
+        sqlx::query!(
+            r#"
+INSERT INTO chat_branding ( chat_id, header, footer )
+VALUES ( $1, $2, $3 )
+ON CONFLICT (chat_id) DO UPDATE
+SET header = $2, footer = $3, updated_at = now()
+            "#,
+            chat_id,
+            branding.header,
+            branding.footer
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_quiet_hours(&self, chat_id: i64) -> anyhow::Result<Option<QuietHours>> {
+        let quiet_hours = sqlx::query_as!(
+            QuietHours,
+            r#"
+SELECT start_time, end_time, utc_offset_minutes FROM chat_quiet_hours
+WHERE chat_id = $1
+            "#,
+            chat_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(quiet_hours)
+    }
+
+    async fn get_all_quiet_hours(&self, bot_id: &str) -> anyhow::Result<HashMap<i64, QuietHours>> {
+        let rows = sqlx::query!(
+            r#"
+SELECT h.chat_id, h.start_time, h.end_time, h.utc_offset_minutes
+FROM chat_quiet_hours h
+JOIN tg_chat c ON c.id = h.chat_id
+WHERE c.bot_id = $1
+            "#,
+            bot_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                (
+                    row.chat_id,
+                    QuietHours {
+                        start_time: row.start_time,
+                        end_time: row.end_time,
+                        utc_offset_minutes: row.utc_offset_minutes,
+                    },
+                )
+            })
+            .collect())
+    }
+
+    async fn set_quiet_hours(&self, chat_id: i64, quiet_hours: &QuietHours) -> anyhow::Result<()> {
+        sqlx::query!(
+            r#"
+INSERT INTO chat_quiet_hours ( chat_id, start_time, end_time, utc_offset_minutes )
+VALUES ( $1, $2, $3, $4 )
+ON CONFLICT (chat_id) DO UPDATE
+SET start_time = $2, end_time = $3, utc_offset_minutes = $4, updated_at = now()
+            "#,
+            chat_id,
+            quiet_hours.start_time,
+            quiet_hours.end_time,
+            quiet_hours.utc_offset_minutes
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn delete_quiet_hours(&self, chat_id: i64) -> anyhow::Result<bool> {
+        let result = sqlx::query!(
+            r#"
+DELETE FROM chat_quiet_hours
+WHERE chat_id = $1
+            "#,
+            chat_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn get_moderation(&self, chat_id: i64) -> anyhow::Result<Option<ModerationSettings>> {
+        let row = sqlx::query!(
+            r#"
+SELECT banned_words, banned_patterns, links, restrict_after, restrict_minutes, kick_after
+FROM chat_moderation
+WHERE chat_id = $1
+            "#,
+            chat_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.map(|row| {
+            Ok(ModerationSettings {
+                banned_words: row.banned_words,
+                banned_patterns: row.banned_patterns,
+                links: row.links.parse()?,
+                restrict_after: row.restrict_after,
+                restrict_minutes: row.restrict_minutes,
+                kick_after: row.kick_after,
+            })
+        })
+        .transpose()
+    }
+
+    async fn set_moderation(
+        &self,
+        chat_id: i64,
+        settings: &ModerationSettings,
+    ) -> anyhow::Result<()> {
+        sqlx::query!(
+            r#"
+INSERT INTO chat_moderation ( chat_id, banned_words, banned_patterns, links, restrict_after, restrict_minutes, kick_after )
+VALUES ( $1, $2, $3, $4, $5, $6, $7 )
+ON CONFLICT (chat_id) DO UPDATE
+SET banned_words = $2, banned_patterns = $3, links = $4, restrict_after = $5,
+    restrict_minutes = $6, kick_after = $7, updated_at = now()
+            "#,
+            chat_id,
+            &settings.banned_words,
+            &settings.banned_patterns,
+            settings.links.to_string(),
+            settings.restrict_after,
+            settings.restrict_minutes,
+            settings.kick_after
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn delete_moderation(&self, chat_id: i64) -> anyhow::Result<bool> {
+        let result = sqlx::query!(
+            r#"
+DELETE FROM chat_moderation
+WHERE chat_id = $1
+            "#,
+            chat_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn add_warning(&self, chat_id: i64, user_id: i64, reason: &str) -> anyhow::Result<i32> {
+        let warnings = sqlx::query_scalar!(
+            r#"
+INSERT INTO chat_warning ( chat_id, user_id, warnings, last_reason )
+VALUES ( $1, $2, 1, $3 )
+ON CONFLICT ( chat_id, user_id ) DO UPDATE
+SET warnings = chat_warning.warnings + 1, last_reason = $3, last_warned_at = now()
+RETURNING warnings
+            "#,
+            chat_id,
+            user_id,
+            reason
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(warnings)
+    }
+
+    async fn get_warnings(&self, chat_id: i64) -> anyhow::Result<Vec<Warning>> {
+        let warnings = sqlx::query_as!(
+            Warning,
+            r#"
+SELECT chat_id, user_id, warnings, last_reason, last_warned_at FROM chat_warning
+WHERE chat_id = $1
+ORDER BY warnings DESC, last_warned_at DESC
+            "#,
+            chat_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(warnings)
+    }
+
+    async fn reset_warnings(&self, chat_id: i64, user_id: i64) -> anyhow::Result<bool> {
+        let result = sqlx::query!(
+            r#"
+DELETE FROM chat_warning
+WHERE chat_id = $1 AND user_id = $2
+            "#,
+            chat_id,
+            user_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn log_moderation(
+        &self,
+        chat_id: i64,
+        user_id: i64,
+        kind: ModerationKind,
+        reason: &str,
+        moderator: &str,
+    ) -> anyhow::Result<()> {
+        sqlx::query!(
+            r#"
+INSERT INTO moderation_log ( chat_id, user_id, action, reason, moderator )
+VALUES ( $1, $2, $3, $4, $5 )
+            "#,
+            chat_id,
+            user_id,
+            kind.to_string(),
+            reason,
+            moderator
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_moderation_log(
+        &self,
+        chat_id: i64,
+        user_id: Option<i64>,
+        limit: i64,
+        offset: i64,
+    ) -> anyhow::Result<Vec<ModerationAction>> {
+        let rows = sqlx::query!(
+            r#"
+SELECT id, chat_id, user_id, action, reason, moderator, created_at FROM moderation_log
+WHERE chat_id = $1
+AND ( $2::BIGINT IS NULL OR user_id = $2 )
+ORDER BY created_at DESC, id DESC
+LIMIT $3 OFFSET $4
+            "#,
+            chat_id,
+            user_id,
+            limit,
+            offset
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter()
+            .map(|row| {
+                Ok(ModerationAction {
+                    id: row.id,
+                    chat_id: row.chat_id,
+                    user_id: row.user_id,
+                    action: row.action.parse()?,
+                    reason: row.reason,
+                    moderator: row.moderator,
+                    created_at: row.created_at,
+                })
+            })
+            .collect()
+    }
+
+    async fn get_maintenance(&self, bot_id: &str) -> anyhow::Result<bool> {
+        let maintenance = sqlx::query_scalar!(
+            r#"
+SELECT maintenance FROM bot_settings
+WHERE bot_id = $1
+            "#,
+            bot_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(maintenance.unwrap_or(false))
+    }
+
+    async fn set_maintenance(&self, bot_id: &str, enabled: bool) -> anyhow::Result<()> {
+        sqlx::query!(
+            r#"
+INSERT INTO bot_settings ( bot_id, maintenance )
+VALUES ( $1, $2 )
+ON CONFLICT (bot_id) DO UPDATE
+SET maintenance = $2, updated_at = now()
+            "#,
+            bot_id,
+            enabled
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_settings_overrides(&self, bot_id: &str) -> anyhow::Result<Option<Value>> {
+        let overrides = sqlx::query_scalar!(
+            r#"
+SELECT overrides FROM bot_settings
+WHERE bot_id = $1
+            "#,
+            bot_id
+        )
+        .fetch_optional(&self.pool)
+        .await?
+        .flatten();
+
+        Ok(overrides
+            .map(|overrides| serde_json::from_str(&overrides))
+            .transpose()?)
+    }
+
+    async fn update_settings_overrides(
+        &self,
+        bot_id: &str,
+        update: &(dyn Fn(Value) -> anyhow::Result<Value> + Send + Sync),
+    ) -> anyhow::Result<Value> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query!(
+            r#"
+INSERT INTO bot_settings ( bot_id )
+VALUES ( $1 )
+ON CONFLICT DO NOTHING
+            "#,
+            bot_id
+        )
+        .execute(&mut tx)
+        .await?;
+        let stored = sqlx::query_scalar!(
+            r#"
+SELECT overrides FROM bot_settings
+WHERE bot_id = $1
+FOR UPDATE
+            "#,
+            bot_id
+        )
+        .fetch_one(&mut tx)
+        .await?;
+        let overrides = update(match stored {
+            Some(stored) => serde_json::from_str(&stored)?,
+            None => Value::Object(Default::default()),
+        })?;
+        sqlx::query!(
+            r#"
+UPDATE bot_settings
+SET overrides = $2, updated_at = now()
+WHERE bot_id = $1
+            "#,
+            bot_id,
+            serde_json::to_string(&overrides)?
+        )
+        .execute(&mut tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(overrides)
+    }
+
+    async fn get_weekly_report_at(&self, bot_id: &str) -> anyhow::Result<Option<DateTime<Utc>>> {
+        let at = sqlx::query_scalar!(
+            r#"
+SELECT weekly_report_at FROM bot_settings
+WHERE bot_id = $1
+            "#,
+            bot_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(at.flatten())
+    }
+
+    async fn set_weekly_report_at(&self, bot_id: &str, at: DateTime<Utc>) -> anyhow::Result<()> {
+        sqlx::query!(
+            r#"
+INSERT INTO bot_settings ( bot_id, weekly_report_at )
+VALUES ( $1, $2 )
+ON CONFLICT (bot_id) DO UPDATE
+SET weekly_report_at = $2
+            "#,
+            bot_id,
+            at
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_report_counts(
+        &self,
+        bot_id: &str,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        top_errors: i64,
+    ) -> anyhow::Result<ReportCounts> {
+        let counts = sqlx::query!(
+            r#"
+SELECT
+    ( SELECT COUNT(*) FROM tg_chat WHERE bot_id = $1 AND NOT archived ) AS "chats!",
+    ( SELECT COUNT(*) FROM tg_chat WHERE bot_id = $1 AND created_at >= $2 AND created_at < $3 ) AS "chats_added!",
+    ( SELECT COUNT(*) FROM tg_chat WHERE bot_id = $1 AND archived AND archived_at >= $2 AND archived_at < $3 ) AS "chats_lost!",
+    ( SELECT COUNT(*) FROM kick_log k JOIN tg_chat c ON c.id = k.chat_id
+      WHERE c.bot_id = $1 AND k.kicked_at >= $2 AND k.kicked_at < $3 ) AS "members_purged!",
+    ( SELECT COUNT(*) FROM message_queue WHERE bot_id = $1 AND completed_at >= $2 AND completed_at < $3 ) AS "broadcasts_sent!",
+    d.deliveries AS "deliveries!",
+    d.failed AS "failed_deliveries!"
+FROM (
+    SELECT COUNT(*) AS deliveries, COUNT(*) FILTER (WHERE d.error IS NOT NULL) AS failed
+    FROM message_delivery d
+    JOIN message_queue q ON q.id = d.message_id
+    WHERE q.bot_id = $1 AND d.deferred_until IS NULL AND d.delivered_at >= $2 AND d.delivered_at < $3
+) d
+            "#,
+            bot_id,
+            from,
+            to
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        let top_errors = sqlx::query_as!(
+            ErrorCount,
+            r#"
+SELECT d.error AS "error!", COUNT(*) AS "count!"
+FROM message_delivery d
+JOIN message_queue q ON q.id = d.message_id
+WHERE q.bot_id = $1 AND d.error IS NOT NULL AND d.deferred_until IS NULL
+AND d.delivered_at >= $2 AND d.delivered_at < $3
+GROUP BY d.error
+ORDER BY COUNT(*) DESC, d.error
+LIMIT $4
+            "#,
+            bot_id,
+            from,
+            to,
+            top_errors
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(ReportCounts {
+            chats: counts.chats,
+            chats_added: counts.chats_added,
+            chats_lost: counts.chats_lost,
+            members_purged: counts.members_purged,
+            broadcasts_sent: counts.broadcasts_sent,
+            deliveries: counts.deliveries,
+            failed_deliveries: counts.failed_deliveries,
+            top_errors,
+        })
+    }
+
+    async fn get_captcha(&self, chat_id: i64) -> anyhow::Result<Option<Captcha>> {
+        let captcha = sqlx::query_as!(
+            Captcha,
+            r#"
+SELECT timeout_minutes FROM chat_captcha
+WHERE chat_id = $1
+            "#,
+            chat_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(captcha)
+    }
+
+    async fn set_captcha(&self, chat_id: i64, captcha: &Captcha) -> anyhow::Result<()> {
+        sqlx::query!(
+            r#"
+INSERT INTO chat_captcha ( chat_id, timeout_minutes )
+VALUES ( $1, $2 )
+ON CONFLICT (chat_id) DO UPDATE
+SET timeout_minutes = $2, updated_at = now()
+            "#,
+            chat_id,
+            captcha.timeout_minutes
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn delete_captcha(&self, chat_id: i64) -> anyhow::Result<bool> {
+        let result = sqlx::query!(
+            r#"
+DELETE FROM chat_captcha
+WHERE chat_id = $1
+            "#,
+            chat_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn add_captcha_challenge(&self, challenge: &CaptchaChallenge) -> anyhow::Result<()> {
+        sqlx::query!(
+            r#"
+INSERT INTO captcha_challenge ( chat_id, user_id, username, name, message_id, expires_at )
+VALUES ( $1, $2, $3, $4, $5, $6 )
+ON CONFLICT ( chat_id, user_id ) DO UPDATE
+SET username = $3, name = $4, message_id = $5, expires_at = $6
+            "#,
+            challenge.chat_id,
+            challenge.user_id,
+            challenge.username,
+            challenge.name,
+            challenge.message_id,
+            challenge.expires_at
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_captcha_challenges(&self, chat_id: i64) -> anyhow::Result<Vec<CaptchaChallenge>> {
+        let challenges = sqlx::query_as!(
+            CaptchaChallenge,
+            r#"
+SELECT chat_id, user_id, username, name, message_id, expires_at FROM captcha_challenge
+WHERE chat_id = $1
+ORDER BY expires_at
+            "#,
+            chat_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(challenges)
+    }
+
+    async fn get_captcha_challenge(
+        &self,
+        chat_id: i64,
+        user_id: i64,
+    ) -> anyhow::Result<Option<CaptchaChallenge>> {
+        let challenge = sqlx::query_as!(
+            CaptchaChallenge,
+            r#"
+SELECT chat_id, user_id, username, name, message_id, expires_at FROM captcha_challenge
+WHERE chat_id = $1 AND user_id = $2
+            "#,
+            chat_id,
+            user_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(challenge)
+    }
+
+    async fn delete_captcha_challenge(
+        &self,
+        chat_id: i64,
+        user_id: i64,
+    ) -> anyhow::Result<Option<CaptchaChallenge>> {
+        let challenge = sqlx::query_as!(
+            CaptchaChallenge,
+            r#"
+DELETE FROM captcha_challenge
+WHERE chat_id = $1 AND user_id = $2
+RETURNING chat_id, user_id, username, name, message_id, expires_at
+            "#,
+            chat_id,
+            user_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(challenge)
+    }
+
+    async fn claim_expired_captcha_challenges(
+        &self,
+        bot_id: &str,
+        now: DateTime<Utc>,
+        retry_at: DateTime<Utc>,
+    ) -> anyhow::Result<Vec<CaptchaChallenge>> {
+        let challenges = sqlx::query_as!(
+            CaptchaChallenge,
+            r#"
+UPDATE captcha_challenge
+SET expires_at = $3
+WHERE expires_at <= $2
+AND chat_id IN ( SELECT id FROM tg_chat WHERE bot_id = $1 )
+RETURNING chat_id, user_id, username, name, message_id, expires_at
+            "#,
+            bot_id,
+            now,
+            retry_at
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(challenges)
+    }
+
+    async fn add_invite_link(
+        &self,
+        chat_id: i64,
+        link: &ChatInviteLink,
+    ) -> anyhow::Result<InviteLink> {
+        let link = sqlx::query_as!(
+            InviteLink,
+            r#"
+INSERT INTO chat_invite_link ( chat_id, invite_link, name, expire_date, member_limit, creates_join_request )
+VALUES ( $1, $2, $3, $4, $5, $6 )
+RETURNING id, chat_id, invite_link, name, expire_date, member_limit, creates_join_request, created_at, revoked_at
+            "#,
+            chat_id,
+            link.invite_link,
+            link.name,
+            link.expire_date,
+            link.member_limit.map(|limit| limit as i32),
+            link.creates_join_request
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(link)
+    }
+
+    async fn get_invite_links(&self, chat_id: i64) -> anyhow::Result<Vec<InviteLink>> {
+        let links = sqlx::query_as!(
+            InviteLink,
+            r#"
+SELECT id, chat_id, invite_link, name, expire_date, member_limit, creates_join_request, created_at, revoked_at
+FROM chat_invite_link
+WHERE chat_id = $1
+ORDER BY id DESC
+            "#,
+            chat_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(links)
+    }
+
+    async fn get_invite_link(&self, chat_id: i64, id: i32) -> anyhow::Result<Option<InviteLink>> {
+        let link = sqlx::query_as!(
+            InviteLink,
+            r#"
+SELECT id, chat_id, invite_link, name, expire_date, member_limit, creates_join_request, created_at, revoked_at
+FROM chat_invite_link
+WHERE chat_id = $1 AND id = $2
+            "#,
+            chat_id,
+            id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(link)
+    }
+
+    async fn revoke_invite_link(
+        &self,
+        chat_id: i64,
+        id: i32,
+    ) -> anyhow::Result<Option<InviteLink>> {
+        let link = sqlx::query_as!(
+            InviteLink,
+            r#"
+UPDATE chat_invite_link
+SET revoked_at = coalesce(revoked_at, now())
+WHERE chat_id = $1 AND id = $2
+RETURNING id, chat_id, invite_link, name, expire_date, member_limit, creates_join_request, created_at, revoked_at
+            "#,
+            chat_id,
+            id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(link)
+    }
+
+    async fn get_join_policy(&self, chat_id: i64) -> anyhow::Result<Option<JoinPolicy>> {
+        let policy = sqlx::query_scalar!(
+            r#"
+SELECT policy FROM chat_join_policy
+WHERE chat_id = $1
+            "#,
+            chat_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        policy.map(|policy| policy.parse()).transpose()
+    }
+
+    async fn set_join_policy(&self, chat_id: i64, policy: JoinPolicy) -> anyhow::Result<()> {
+        sqlx::query!(
+            r#"
+INSERT INTO chat_join_policy ( chat_id, policy )
+VALUES ( $1, $2 )
+ON CONFLICT (chat_id) DO UPDATE
+SET policy = $2, updated_at = now()
+            "#,
+            chat_id,
+            policy.to_string()
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn add_join_request(&self, request: NewJoinRequest<'_>) -> anyhow::Result<JoinRequest> {
+        let row = sqlx::query_as!(
+            JoinRequestRow,
+            r#"
+INSERT INTO join_request ( chat_id, user_id, username, name, bio, invite_link )
+VALUES ( $1, $2, $3, $4, $5, $6 )
+RETURNING id, chat_id, user_id, username, name, bio, invite_link, status, requested_at, decided_at, decided_by
+            "#,
+            request.chat_id,
+            request.user_id,
+            request.username,
+            request.name,
+            request.bio,
+            request.invite_link
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        row.parse()
+    }
+
+    async fn get_join_requests(
+        &self,
+        chat_id: i64,
+        status: Option<JoinRequestStatus>,
+    ) -> anyhow::Result<Vec<JoinRequest>> {
+        let rows = sqlx::query_as!(
+            JoinRequestRow,
+            r#"
+SELECT id, chat_id, user_id, username, name, bio, invite_link, status, requested_at, decided_at, decided_by
+FROM join_request
+WHERE chat_id = $1 AND ( $2::TEXT IS NULL OR status = $2 )
+ORDER BY id DESC
+            "#,
+            chat_id,
+            status.map(|status| status.to_string())
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter().map(JoinRequestRow::parse).collect()
+    }
+
+    async fn get_join_request(&self, id: i32) -> anyhow::Result<Option<JoinRequest>> {
+        let row = sqlx::query_as!(
+            JoinRequestRow,
+            r#"
+SELECT id, chat_id, user_id, username, name, bio, invite_link, status, requested_at, decided_at, decided_by
+FROM join_request
+WHERE id = $1
+            "#,
+            id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.map(JoinRequestRow::parse).transpose()
+    }
+
+    async fn decide_join_request(
+        &self,
+        id: i32,
+        status: JoinRequestStatus,
+        decided_by: &str,
+    ) -> anyhow::Result<Option<JoinRequest>> {
+        let row = sqlx::query_as!(
+            JoinRequestRow,
+            r#"
+UPDATE join_request
+SET status = $2, decided_at = now(), decided_by = $3
+WHERE id = $1 AND status = 'pending'
+RETURNING id, chat_id, user_id, username, name, bio, invite_link, status, requested_at, decided_at, decided_by
+            "#,
+            id,
+            status.to_string(),
+            decided_by
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.map(JoinRequestRow::parse).transpose()
+    }
+
+    async fn add_exclusion(
+        &self,
+        bot_id: &str,
+        chat_id: Option<i64>,
+        user_id: i64,
+        note: Option<&str>,
+    ) -> anyhow::Result<Exclusion> {
+        let exclusion = sqlx::query_as!(
+            Exclusion,
+            r#"
+INSERT INTO exclusion ( bot_id, chat_id, user_id, note )
+VALUES ( $1, $2, $3, $4 )
+ON CONFLICT ( bot_id, COALESCE(chat_id, 0), user_id ) DO UPDATE
+SET note = $4
+RETURNING id, chat_id, user_id, note, created_at
+            "#,
+            bot_id,
+            chat_id,
+            user_id,
+            note
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(exclusion)
+    }
+
+    async fn get_exclusions(&self, bot_id: &str) -> anyhow::Result<Vec<Exclusion>> {
+        let exclusions = sqlx::query_as!(
+            Exclusion,
+            r#"
+SELECT id, chat_id, user_id, note, created_at FROM exclusion
+WHERE bot_id = $1
+ORDER BY id
+            "#,
+            bot_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(exclusions)
+    }
+
+    async fn delete_exclusion(&self, bot_id: &str, id: i32) -> anyhow::Result<bool> {
+        let result = sqlx::query!(
+            "DELETE FROM exclusion WHERE bot_id = $1 AND id = $2",
+            bot_id,
+            id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn get_excluded_users(&self, bot_id: &str, chat_id: i64) -> anyhow::Result<Vec<i64>> {
+        let users = sqlx::query_scalar!(
+            r#"
+SELECT DISTINCT user_id FROM exclusion
+WHERE bot_id = $1 AND ( chat_id IS NULL OR chat_id = $2 )
+            "#,
+            bot_id,
+            chat_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(users)
+    }
+
+    async fn add_subscriber(
+        &self,
+        bot_id: &str,
+        user_id: i64,
+        username: Option<&str>,
+        name: &str,
+    ) -> anyhow::Result<bool> {
+        let inserted = sqlx::query_scalar!(
+            r#"
+INSERT INTO tg_subscriber ( bot_id, user_id, username, name )
+VALUES ( $1, $2, $3, $4 )
+ON CONFLICT ( bot_id, user_id ) DO UPDATE
+SET username = $3, name = $4
+RETURNING xmax = 0 AS "inserted!"
+            "#,
+            bot_id,
+            user_id,
+            username,
+            name
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(inserted)
+    }
+
+    async fn remove_subscriber(&self, bot_id: &str, user_id: i64) -> anyhow::Result<bool> {
+        let result = sqlx::query!(
+            r#"
+DELETE FROM tg_subscriber
+WHERE bot_id = $1 AND user_id = $2
+            "#,
+            bot_id,
+            user_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn get_subscribers(&self, bot_id: &str) -> anyhow::Result<Vec<i64>> {
+        let users = sqlx::query_scalar!(
+            r#"
+SELECT user_id FROM tg_subscriber
+WHERE bot_id = $1
+ORDER BY user_id
+            "#,
+            bot_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(users)
+    }
+
+    async fn queue_message(&self, message: NewQueuedMessage<'_>) -> anyhow::Result<i32> {
+        let message = &message;
+        retry_transaction(|| async move {
+            let mut tx = self.pool.begin().await?;
+            let id = insert_queued_message(&mut tx, message).await?;
+            tx.commit().await?;
+
+            Ok(id)
+        })
+        .await
+    }
+
+    async fn get_queued_message(&self, id: i32) -> anyhow::Result<Option<QueuedMessage>> {
+        let message = sqlx::query_as!(
+            QueuedMessage,
+            r#"
+                SELECT id, bot_id, chats, all_chats, message, images, datetime, parse_mode, pin, pin_silently, silent, disable_web_page_preview, protect_content, audience, copy_from_chat_id, copy_message_id, reply_to_queue_id, datetime_local, collect_replies, delete_after_seconds, channel_silent, channel_protect_content, request_id FROM message_queue
+                WHERE id = $1
+                "#,
+            id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(message)
+    }
+
+    async fn find_duplicate_message(
+        &self,
+        bot_id: &str,
+        text_hash: &str,
+        since: DateTime<Utc>,
+        chats: Option<&[i64]>,
+        audience: Audience,
+    ) -> anyhow::Result<Option<i32>> {
+        let mut conn = self.pool.acquire().await?;
+        find_duplicate_message(&mut conn, bot_id, text_hash, since, chats, audience).await
+    }
+
+    async fn get_queue(
+        &self,
+        bot_id: &str,
+        filter: &QueueFilter,
+        limit: i64,
+        offset: i64,
+    ) -> anyhow::Result<Vec<QueueEntry>> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT id AS "id!", status AS "status!", chats AS "chats!", all_chats AS "all_chats!",
+                audience AS "audience!", message AS "message!", datetime AS "datetime!",
+                datetime_local, attempts AS "attempts!", completed_at
+            FROM (
+                SELECT *, CASE
+                    WHEN completed_at IS NOT NULL THEN 'completed'
+                    WHEN EXISTS ( SELECT 1 FROM failed_message WHERE queue_id = message_queue.id ) THEN 'failed'
+                    WHEN awaiting_approval THEN 'awaiting_approval'
+                    WHEN processing_at IS NOT NULL THEN 'processing'
+                    ELSE 'pending'
+                END AS status
+                FROM message_queue
+                WHERE bot_id = $1
+            ) q
+            WHERE ( $2::TEXT IS NULL OR status = $2 )
+            AND ( $3::TIMESTAMPTZ IS NULL OR datetime < $3 )
+            AND ( $4::BIGINT IS NULL OR all_chats OR $4 = ANY(chats) )
+            ORDER BY datetime, id
+            LIMIT $5 OFFSET $6
+            "#,
+            bot_id,
+            filter.status.map(|status| status.to_string()),
+            filter.due_before,
+            filter.chat_id,
+            limit,
+            offset
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter()
+            .map(|row| {
+                Ok(QueueEntry {
+                    id: row.id,
+                    status: row.status.parse()?,
+                    chats: row.chats,
+                    all_chats: row.all_chats,
+                    audience: row.audience,
+                    message: row.message,
+                    datetime: row.datetime,
+                    datetime_local: row.datetime_local,
+                    attempts: row.attempts,
+                    completed_at: row.completed_at,
+                })
+            })
+            .collect()
+    }
+
+    async fn get_queue_depths(&self) -> anyhow::Result<Vec<(String, i64)>> {
+        let depths = sqlx::query!(
+            r#"
+                SELECT bot_id, COUNT(*) AS "depth!" FROM message_queue
+                WHERE completed_at IS NULL
+                AND NOT EXISTS ( SELECT 1 FROM failed_message WHERE queue_id = message_queue.id )
+                GROUP BY bot_id
+                ORDER BY bot_id
+                "#
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(depths
+            .into_iter()
+            .map(|row| (row.bot_id, row.depth))
+            .collect())
+    }
+
+    async fn get_pending_messages(
+        &self,
+        bot_id: &str,
+        until: DateTime<Utc>,
+        limit: i64,
+    ) -> anyhow::Result<Vec<PendingMessage>> {
+        let pending = sqlx::query_as!(
+            PendingMessage,
+            r#"
+                SELECT id, COALESCE(not_before, datetime) AS "datetime!" FROM message_queue
+                WHERE completed_at IS NULL AND NOT awaiting_approval AND bot_id = $1
+                AND COALESCE(not_before, datetime) <= $2
+                AND (processing_at IS NULL OR processing_at < now() - make_interval(secs => $4))
+                AND NOT EXISTS ( SELECT 1 FROM failed_message WHERE queue_id = message_queue.id )
+                ORDER BY COALESCE(not_before, datetime), id
+                LIMIT $3
+                "#,
+            bot_id,
+            until,
+            limit,
+            CLAIM_LEASE.as_secs_f64()
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(pending)
+    }
+
+    async fn claim_queued_message(&self, id: i32) -> anyhow::Result<Option<QueuedMessage>> {
+        retry_transaction(|| async move {
+            let mut tx = self.pool.begin().await?;
+
+            let message = sqlx::query_as!(
+                QueuedMessage,
+                r#"
+                SELECT id, bot_id, chats, all_chats, message, images, datetime, parse_mode, pin, pin_silently, silent, disable_web_page_preview, protect_content, audience, copy_from_chat_id, copy_message_id, reply_to_queue_id, datetime_local, collect_replies, delete_after_seconds, channel_silent, channel_protect_content, request_id FROM message_queue
+                WHERE id = $1 AND completed_at IS NULL AND NOT awaiting_approval
+                AND (processing_at IS NULL OR processing_at < now() - make_interval(secs => $2))
+                AND NOT EXISTS ( SELECT 1 FROM failed_message WHERE queue_id = message_queue.id )
+                FOR UPDATE SKIP LOCKED
+                    "#,
+                id,
+                CLAIM_LEASE.as_secs_f64()
+            )
+            .fetch_optional(&mut tx)
+            .await?;
+
+            if message.is_some() {
+                sqlx::query!(
+                    r#"
+                UPDATE message_queue
+                SET processing_at = now()
+                WHERE id = $1
+                    "#,
+                    id
+                )
+                .execute(&mut tx)
+                .await?;
+            }
+
+            tx.commit().await?;
+
+            Ok(message)
+        })
+        .await
+    }
+
+    async fn renew_claim(&self, id: i32) -> anyhow::Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE message_queue
+            SET processing_at = now()
+            WHERE id = $1 AND processing_at IS NOT NULL
+            "#,
+            id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn fail_queued_message(
+        &self,
+        id: i32,
+        error: &str,
+        max_attempts: i32,
+    ) -> anyhow::Result<bool> {
+        retry_transaction(|| async move {
+            let mut tx = self.pool.begin().await?;
+
+            let attempts = sqlx::query_scalar!(
+                r#"
+            UPDATE message_queue
+            SET processing_at = NULL, attempts = attempts + 1
+            WHERE id = $1
+            RETURNING attempts
+                "#,
+                id
+            )
+            .fetch_optional(&mut tx)
+            .await?;
+
+            let failed = matches!(attempts, Some(attempts) if attempts >= max_attempts);
+            if failed {
+                sqlx::query!(
+                    r#"
+            INSERT INTO failed_message ( queue_id, error, attempts )
+            VALUES ( $1, $2, $3 )
+            ON CONFLICT ( queue_id ) DO UPDATE
+            SET error = $2, attempts = $3, failed_at = now()
+                "#,
+                    id,
+                    error,
+                    attempts
+                )
+                .execute(&mut tx)
+                .await?;
+            }
+
+            tx.commit().await?;
+
+            Ok(failed)
+        })
+        .await
+    }
+
+    async fn get_failed_messages(&self, bot_id: &str) -> anyhow::Result<Vec<FailedMessage>> {
+        let failed = sqlx::query_as!(
+            FailedMessage,
+            r#"
+            SELECT f.queue_id AS id, q.message, q.datetime, f.error, f.attempts, f.failed_at
+            FROM failed_message f
+            JOIN message_queue q ON q.id = f.queue_id
+            WHERE q.bot_id = $1
+            ORDER BY f.failed_at DESC
+            "#,
+            bot_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(failed)
+    }
+
+    async fn retry_failed_message(&self, bot_id: &str, id: i32) -> anyhow::Result<bool> {
+        retry_transaction(|| async move {
+            let mut tx = self.pool.begin().await?;
+
+            let result = sqlx::query!(
+                r#"
+            DELETE FROM failed_message f
+            USING message_queue q
+            WHERE f.queue_id = q.id AND q.id = $1 AND q.bot_id = $2
+                "#,
+                id,
+                bot_id
+            )
+            .execute(&mut tx)
+            .await?;
+            if result.rows_affected() == 0 {
+                return Ok(false);
+            }
+
+            sqlx::query!(
+                r#"
+            UPDATE message_queue
+            SET attempts = 0
+            WHERE id = $1
+                "#,
+                id
+            )
+            .execute(&mut tx)
+            .await?;
+
+            tx.commit().await?;
+
+            Ok(true)
+        })
+        .await
+    }
+
+    async fn complete_queued_message(&self, id: i32) -> anyhow::Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE message_queue
+            SET completed_at = now()
+            WHERE id = $1
+            "#,
+            id,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn defer_queued_message(&self, id: i32, until: DateTime<Utc>) -> anyhow::Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE message_queue
+            SET not_before = $2, processing_at = NULL
+            WHERE id = $1
+            "#,
+            id,
+            until
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn retry_failed_deliveries(
+        &self,
+        id: i32,
+        datetime: DateTime<Utc>,
+    ) -> anyhow::Result<Vec<i64>> {
+        retry_transaction(|| async move {
+            let mut tx = self.pool.begin().await?;
+
+            let completed = sqlx::query_scalar!(
+                r#"
+            SELECT completed_at IS NOT NULL AS "completed!" FROM message_queue
+            WHERE id = $1
+            FOR UPDATE
+                "#,
+                id
+            )
+            .fetch_optional(&mut tx)
+            .await?;
+            if completed != Some(true) {
+                return Ok(Vec::new());
+            }
+
+            let chats = sqlx::query_scalar!(
+                r#"
+            DELETE FROM message_delivery
+            WHERE message_id = $1 AND error IS NOT NULL
+            RETURNING chat_id
+                "#,
+                id
+            )
+            .fetch_all(&mut tx)
+            .await?;
+            if !chats.is_empty() {
+                sqlx::query!(
+                    r#"
+                UPDATE message_queue
+                SET completed_at = NULL, processing_at = NULL, attempts = 0, datetime = $2, not_before = NULL
+                WHERE id = $1
+                    "#,
+                    id,
+                    datetime
+                )
+                .execute(&mut tx)
+                .await?;
+            }
+            tx.commit().await?;
+
+            Ok(chats)
+        })
+        .await
+    }
+
+    async fn edit_queued_message(
+        &self,
+        id: i32,
+        message: &str,
+        datetime: DateTime<Utc>,
+        edited_by: &str,
+    ) -> anyhow::Result<bool> {
+        retry_transaction(|| async move {
+            let mut tx = self.pool.begin().await?;
+
+            let current = sqlx::query!(
+                r#"
+            SELECT message, datetime FROM message_queue
+            WHERE id = $1 AND completed_at IS NULL AND processing_at IS NULL
+            AND NOT EXISTS ( SELECT 1 FROM failed_message WHERE queue_id = message_queue.id )
+            AND NOT EXISTS ( SELECT 1 FROM message_delivery WHERE message_id = message_queue.id )
+            FOR UPDATE
+            "#,
+                id
+            )
+            .fetch_optional(&mut tx)
+            .await?;
+            let Some(current) = current else {
+                return Ok(false);
+            };
+
+            sqlx::query!(
+                r#"
+            INSERT INTO message_queue_history ( message_id, message, datetime, edited_by )
+            VALUES ( $1, $2, $3, $4 )
+            "#,
+                id,
+                current.message,
+                current.datetime,
+                edited_by
+            )
+            .execute(&mut tx)
+            .await?;
+
+            sqlx::query!(
+                r#"
+            UPDATE message_queue
+            SET message = $2, datetime = $3, text_hash = $4, not_before = NULL
+            WHERE id = $1
+            "#,
+                id,
+                message,
+                datetime,
+                text_hash(message)
+            )
+            .execute(&mut tx)
+            .await?;
+
+            tx.commit().await?;
+
+            Ok(true)
+        })
+        .await
+    }
+
+    async fn get_queued_message_history(
+        &self,
+        id: i32,
+    ) -> anyhow::Result<Vec<QueuedMessageVersion>> {
+        let versions = sqlx::query_as!(
+            QueuedMessageVersion,
+            r#"
+            SELECT message, datetime, edited_by, edited_at FROM message_queue_history
+            WHERE message_id = $1
+            ORDER BY edited_at DESC, id DESC
+            "#,
+            id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(versions)
+    }
+
+    async fn approve_queued_message(&self, id: i32) -> anyhow::Result<bool> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE message_queue
+            SET awaiting_approval = false
+            WHERE id = $1 AND awaiting_approval
+            "#,
+            id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn delete_queued_message(&self, id: i32) -> anyhow::Result<()> {
+        sqlx::query!(
+            r#"
+            DELETE FROM message_queue
+            WHERE id = $1
+            "#,
+            id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn resolve_all_chats(&self, bot_id: &str, id: i32) -> anyhow::Result<Vec<i64>> {
+        let chats = sqlx::query_scalar!(
+            r#"
+            UPDATE message_queue
+            SET chats = ARRAY(
+                SELECT id FROM tg_chat
+                WHERE NOT muted AND approved AND NOT archived AND bot_id = $2
+                AND id NOT IN ( SELECT chat_id FROM message_excluded_chat WHERE message_id = $1 )
+                ORDER BY id
+            )
+            WHERE id = $1
+            RETURNING chats
+            "#,
+            id,
+            bot_id
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(chats)
+    }
+
+    async fn resolve_subscribers(&self, bot_id: &str, id: i32) -> anyhow::Result<Vec<i64>> {
+        let chats = sqlx::query_scalar!(
+            r#"
+            UPDATE message_queue
+            SET chats = ARRAY(
+                SELECT user_id FROM tg_subscriber
+                WHERE bot_id = $2
+                AND user_id NOT IN ( SELECT chat_id FROM message_excluded_chat WHERE message_id = $1 )
+                ORDER BY user_id
+            )
+            WHERE id = $1
+            RETURNING chats
+            "#,
+            id,
+            bot_id
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(chats)
+    }
+
+    async fn get_excluded_chats(&self, message_id: i32) -> anyhow::Result<Vec<i64>> {
+        let chats = sqlx::query_scalar!(
+            r#"
+            SELECT chat_id FROM message_excluded_chat
+            WHERE message_id = $1
+            ORDER BY chat_id
+            "#,
+            message_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(chats)
+    }
+
+    async fn get_message_images(&self, message_id: i32) -> anyhow::Result<Vec<Vec<u8>>> {
+        let images = sqlx::query_scalar!(
+            r#"
+            SELECT data FROM message_image
+            WHERE message_id = $1
+            ORDER BY position
+            "#,
+            message_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(images)
+    }
+
+    async fn get_media_blob(&self, hash: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        let data = sqlx::query_scalar!(
+            r#"
+            SELECT data FROM media_blob
+            WHERE hash = $1
+            "#,
+            hash
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(data)
+    }
+
+    async fn delete_unused_media_blobs(&self, stored_before: DateTime<Utc>) -> anyhow::Result<u64> {
+        retry_transaction(|| async move {
+            let result = sqlx::query!(
+                r#"
+            DELETE FROM media_blob b
+            WHERE created_at < $1
+            AND NOT EXISTS ( SELECT 1 FROM message_queue WHERE 'media:' || b.hash = ANY(images) )
+            AND NOT EXISTS ( SELECT 1 FROM message_variant WHERE 'media:' || b.hash = ANY(images) )
+                "#,
+                stored_before
+            )
+            .execute(&self.pool)
+            .await?;
+
+            Ok(result.rows_affected())
+        })
+        .await
+    }
+
+    async fn get_message_threads(&self, message_id: i32) -> anyhow::Result<HashMap<i64, i32>> {
+        let threads = sqlx::query!(
+            r#"
+SELECT chat_id, thread_id FROM message_thread
+WHERE message_id = $1
+            "#,
+            message_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(threads
+            .into_iter()
+            .map(|thread| (thread.chat_id, thread.thread_id))
+            .collect())
+    }
+
+    async fn get_delivered_chats(&self, message_id: i32) -> anyhow::Result<Vec<i64>> {
+        let chats = sqlx::query_scalar!(
+            r#"
+SELECT chat_id FROM message_delivery
+WHERE message_id = $1 AND deferred_until IS NULL
+            "#,
+            message_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(chats)
+    }
+
+    async fn record_delivery(
+        &self,
+        message_id: i32,
+        chat_id: i64,
+        error: Option<String>,
+        pin_error: Option<String>,
+        request_id: Option<&str>,
+    ) -> anyhow::Result<()> {
+        sqlx::query!(
+            r#"
+INSERT INTO message_delivery ( message_id, chat_id, error, pin_error, request_id )
+VALUES ( $1, $2, $3, $4, $5 )
+ON CONFLICT ( message_id, chat_id ) DO UPDATE
+SET error = $3, pin_error = $4, request_id = $5, delivered_at = now(), deferred_until = NULL
+            "#,
+            message_id,
+            chat_id,
+            error,
+            pin_error,
+            request_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_sink_deliveries(&self, message_id: i32) -> anyhow::Result<Vec<String>> {
+        let sinks = sqlx::query_scalar!(
+            r#"
+SELECT sink FROM sink_delivery
+WHERE message_id = $1 AND error IS NULL
+            "#,
+            message_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(sinks)
+    }
+
+    async fn record_sink_delivery(
+        &self,
+        message_id: i32,
+        sink: &str,
+        error: Option<String>,
+    ) -> anyhow::Result<()> {
+        sqlx::query!(
+            r#"
+INSERT INTO sink_delivery ( message_id, sink, error )
+VALUES ( $1, $2, $3 )
+ON CONFLICT ( message_id, sink ) DO UPDATE
+SET error = $3, delivered_at = now()
+            "#,
+            message_id,
+            sink,
+            error
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn defer_delivery(
+        &self,
+        message_id: i32,
+        chat_id: i64,
+        until: DateTime<Utc>,
+    ) -> anyhow::Result<()> {
+        sqlx::query!(
+            r#"
+INSERT INTO message_delivery ( message_id, chat_id, deferred_until )
+VALUES ( $1, $2, $3 )
+ON CONFLICT ( message_id, chat_id ) DO UPDATE
+SET deferred_until = $3, delivered_at = now()
+            "#,
+            message_id,
+            chat_id,
+            until
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_broadcast_progress(
+        &self,
+        message_id: i32,
+    ) -> anyhow::Result<Option<BroadcastProgress>> {
+        let progress = sqlx::query_as!(
+            BroadcastProgress,
+            r#"
+SELECT
+    q.id,
+    cardinality(q.chats)::BIGINT AS "total!",
+    count(d.chat_id) FILTER (WHERE d.error IS NULL AND d.deferred_until IS NULL) AS "sent!",
+    count(d.chat_id) FILTER (WHERE d.error IS NOT NULL) AS "failed!",
+    count(d.chat_id) FILTER (WHERE d.deferred_until IS NOT NULL) AS "deferred!",
+    count(d.chat_id) FILTER (WHERE d.pin_error IS NOT NULL) AS "pin_failed!",
+    q.completed_at IS NOT NULL AS "completed!",
+    min(d.delivered_at) FILTER (WHERE d.deferred_until IS NULL) AS first_delivered_at,
+    max(d.delivered_at) FILTER (WHERE d.deferred_until IS NULL) AS last_delivered_at
+FROM message_queue q
+LEFT JOIN message_delivery d ON d.message_id = q.id
+WHERE q.id = $1
+GROUP BY q.id
+            "#,
+            message_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(progress)
+    }
+
+    async fn get_deliveries(&self, message_id: i32) -> anyhow::Result<Vec<Delivery>> {
+        let deliveries = sqlx::query_as!(
+            Delivery,
+            r#"
+SELECT chat_id, error, pin_error, delivered_at, deferred_until, request_id FROM message_delivery
+WHERE message_id = $1
+ORDER BY delivered_at
+            "#,
+            message_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(deliveries)
+    }
+
+    async fn record_delivered_files(
+        &self,
+        message_id: i32,
+        files: &[DeliveredFile],
+    ) -> anyhow::Result<()> {
+        retry_transaction(|| async move {
+            let mut tx = self.pool.begin().await?;
+            for file in files {
+                sqlx::query!(
+                    r#"
+INSERT INTO delivered_file ( message_id, chat_id, telegram_message_id, kind, file_id, file_unique_id, file_size, width, height )
+VALUES ( $1, $2, $3, $4, $5, $6, $7, $8, $9 )
+ON CONFLICT ( message_id, chat_id, telegram_message_id ) DO UPDATE
+SET kind = $4, file_id = $5, file_unique_id = $6, file_size = $7, width = $8, height = $9
+                    "#,
+                    message_id,
+                    file.chat_id,
+                    file.telegram_message_id,
+                    file.kind,
+                    file.file_id,
+                    file.file_unique_id,
+                    file.file_size,
+                    file.width,
+                    file.height
+                )
+                .execute(&mut tx)
+                .await?;
+            }
+            tx.commit().await?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_delivered_files(
+        &self,
+        message_id: i32,
+        chat_id: Option<i64>,
+    ) -> anyhow::Result<Vec<DeliveredFile>> {
+        let files = sqlx::query_as!(
+            DeliveredFile,
+            r#"
+SELECT chat_id, telegram_message_id, kind, file_id, file_unique_id, file_size, width, height
+FROM delivered_file
+WHERE message_id = $1 AND ( $2::BIGINT IS NULL OR chat_id = $2 )
+ORDER BY chat_id, telegram_message_id
+            "#,
+            message_id,
+            chat_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(files)
+    }
+
+    async fn get_message_variants(&self, message_id: i32) -> anyhow::Result<Vec<MessageVariant>> {
+        let variants = sqlx::query_as!(
+            MessageVariant,
+            r#"
+SELECT message, images, weight FROM message_variant
+WHERE message_id = $1
+ORDER BY variant
+            "#,
+            message_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(variants)
+    }
+
+    async fn get_message_translations(&self, message_id: i32) -> anyhow::Result<Translations> {
+        let rows = sqlx::query!(
+            r#"
+SELECT language, message FROM message_translation
+WHERE message_id = $1
+            "#,
+            message_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| (row.language, row.message))
+            .collect())
+    }
+
+    async fn get_message_attachments(&self, message_id: i32) -> anyhow::Result<Attachments> {
+        let row = sqlx::query!(
+            r#"
+SELECT sticker, latitude, longitude, venue_title, venue_address FROM message_attachment
+WHERE message_id = $1
+            "#,
+            message_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map_or_else(Attachments::default, |row| {
+            Attachments::from_columns(
+                row.sticker,
+                row.latitude,
+                row.longitude,
+                row.venue_title,
+                row.venue_address,
+            )
+        }))
+    }
+
+    async fn assign_variant(
+        &self,
+        message_id: i32,
+        chat_id: i64,
+        variant: i32,
+    ) -> anyhow::Result<()> {
+        sqlx::query!(
+            r#"
+INSERT INTO variant_assignment ( message_id, chat_id, variant )
+VALUES ( $1, $2, $3 )
+ON CONFLICT ( message_id, chat_id ) DO NOTHING
+            "#,
+            message_id,
+            chat_id,
+            variant
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_variant_results(&self, message_id: i32) -> anyhow::Result<Vec<VariantResult>> {
+        let results = sqlx::query_as!(
+            VariantResult,
+            r#"
+SELECT
+    v.variant,
+    v.message,
+    v.weight,
+    count(a.chat_id) AS "chats!",
+    count(d.chat_id) FILTER (WHERE d.error IS NULL AND d.deferred_until IS NULL) AS "sent!",
+    count(d.chat_id) FILTER (WHERE d.error IS NOT NULL) AS "failed!"
+FROM message_variant v
+LEFT JOIN variant_assignment a ON a.message_id = v.message_id AND a.variant = v.variant
+LEFT JOIN message_delivery d ON d.message_id = a.message_id AND d.chat_id = a.chat_id
+WHERE v.message_id = $1
+GROUP BY v.variant, v.message, v.weight
+ORDER BY v.variant
+            "#,
+            message_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(results)
+    }
+
+    async fn add_webhook(
+        &self,
+        bot_id: &str,
+        url: &str,
+        secret: &str,
+        events: &[WebhookEvent],
+    ) -> anyhow::Result<Webhook> {
+        let events: Vec<String> = events.iter().map(ToString::to_string).collect();
+        let row = sqlx::query!(
+            r#"
+INSERT INTO webhook ( bot_id, url, events, secret )
+VALUES ( $1, $2, $3, $4 )
+RETURNING id, url, events, secret, created_at
+            "#,
+            bot_id,
+            url,
+            &events,
+            secret
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(Webhook {
+            id: row.id,
+            url: row.url,
+            events: parse_events(&row.events)?,
+            secret: row.secret,
+            created_at: row.created_at,
+        })
+    }
+
+    async fn get_webhooks(&self, bot_id: &str) -> anyhow::Result<Vec<Webhook>> {
+        let rows = sqlx::query!(
+            r#"
+SELECT id, url, events, secret, created_at FROM webhook
+WHERE bot_id = $1
+ORDER BY id
+            "#,
+            bot_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter()
+            .map(|row| {
+                Ok(Webhook {
+                    id: row.id,
+                    url: row.url,
+                    events: parse_events(&row.events)?,
+                    secret: row.secret,
+                    created_at: row.created_at,
+                })
+            })
+            .collect()
+    }
+
+    async fn delete_webhook(&self, bot_id: &str, id: i32) -> anyhow::Result<bool> {
+        let result = sqlx::query!(
+            "DELETE FROM webhook WHERE bot_id = $1 AND id = $2",
+            bot_id,
+            id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn add_draft(&self, bot_id: &str, content: &DraftContent) -> anyhow::Result<Draft> {
+        let row = sqlx::query!(
+            r#"
+INSERT INTO draft ( bot_id, content )
+VALUES ( $1, $2 )
+RETURNING id, content, created_at, updated_at
+            "#,
+            bot_id,
+            serde_json::to_string(content)?
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(Draft {
+            id: row.id,
+            content: serde_json::from_str(&row.content)?,
+            created_at: row.created_at,
+            updated_at: row.updated_at,
+        })
+    }
+
+    async fn get_drafts(&self, bot_id: &str) -> anyhow::Result<Vec<Draft>> {
+        let rows = sqlx::query!(
+            r#"
+SELECT id, content, created_at, updated_at FROM draft
+WHERE bot_id = $1
+ORDER BY updated_at DESC, id DESC
+            "#,
+            bot_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter()
+            .map(|row| {
+                Ok(Draft {
+                    id: row.id,
+                    content: serde_json::from_str(&row.content)?,
+                    created_at: row.created_at,
+                    updated_at: row.updated_at,
+                })
+            })
+            .collect()
+    }
+
+    async fn get_draft(&self, bot_id: &str, id: i32) -> anyhow::Result<Option<Draft>> {
+        let row = sqlx::query!(
+            r#"
+SELECT id, content, created_at, updated_at FROM draft
+WHERE bot_id = $1 AND id = $2
+            "#,
+            bot_id,
+            id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.map(|row| {
+            Ok(Draft {
+                id: row.id,
+                content: serde_json::from_str(&row.content)?,
+                created_at: row.created_at,
+                updated_at: row.updated_at,
+            })
+        })
+        .transpose()
+    }
+
+    async fn update_draft(
+        &self,
+        bot_id: &str,
+        id: i32,
+        content: &DraftContent,
+    ) -> anyhow::Result<Option<Draft>> {
+        let row = sqlx::query!(
+            r#"
+UPDATE draft SET content = $3, updated_at = now()
+WHERE bot_id = $1 AND id = $2
+RETURNING id, content, created_at, updated_at
+            "#,
+            bot_id,
+            id,
+            serde_json::to_string(content)?
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.map(|row| {
+            Ok(Draft {
+                id: row.id,
+                content: serde_json::from_str(&row.content)?,
+                created_at: row.created_at,
+                updated_at: row.updated_at,
+            })
+        })
+        .transpose()
+    }
+
+    async fn delete_draft(&self, bot_id: &str, id: i32) -> anyhow::Result<bool> {
+        let result = sqlx::query!(
+            "DELETE FROM draft WHERE bot_id = $1 AND id = $2",
+            bot_id,
+            id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn start_audit(&self, entry: NewAuditEntry<'_>) -> anyhow::Result<i64> {
+        let id = sqlx::query_scalar!(
+            r#"
+            INSERT INTO audit_log (bot_id, actor, method, endpoint, payload, request_id)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            RETURNING id
+            "#,
+            entry.bot_id,
+            entry.actor,
+            entry.method,
+            entry.endpoint,
+            entry.payload,
+            entry.request_id
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(id)
+    }
+
+    async fn finish_audit(&self, id: i64, status: i32) -> anyhow::Result<()> {
+        sqlx::query!("UPDATE audit_log SET status = $1 WHERE id = $2", status, id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn delete_audit_entries(
+        &self,
+        bot_id: &str,
+        before: DateTime<Utc>,
+    ) -> anyhow::Result<u64> {
+        let result = sqlx::query!(
+            "DELETE FROM audit_log WHERE bot_id = $1 AND created_at < $2",
+            bot_id,
+            before
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn get_audit_log(
+        &self,
+        bot_id: &str,
+        since: Option<DateTime<Utc>>,
+        actor: Option<&str>,
+        request_id: Option<&str>,
+        limit: i64,
+    ) -> anyhow::Result<Vec<AuditEntry>> {
+        let entries = sqlx::query_as!(
+            AuditEntry,
+            r#"
+            SELECT id, actor, method, endpoint, payload, status, created_at, request_id
+            FROM audit_log
+            WHERE bot_id = $1
+                AND ($2::timestamptz IS NULL OR created_at >= $2)
+                AND ($3::text IS NULL OR actor = $3)
+                AND ($4::text IS NULL OR request_id = $4)
+            ORDER BY id DESC
+            LIMIT $5
+            "#,
+            bot_id,
+            since,
+            actor,
+            request_id,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(entries)
+    }
+
+    async fn record_usage(
+        &self,
+        bot_id: &str,
+        actor: &str,
+        day: NaiveDate,
+        usage: &Usage,
+    ) -> anyhow::Result<()> {
+        sqlx::query!(
+            r#"
+            INSERT INTO api_usage (bot_id, actor, day, messages, target_chats, cleanups)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            ON CONFLICT (bot_id, actor, day) DO UPDATE SET
+                messages = api_usage.messages + EXCLUDED.messages,
+                target_chats = api_usage.target_chats + EXCLUDED.target_chats,
+                cleanups = api_usage.cleanups + EXCLUDED.cleanups
+            "#,
+            bot_id,
+            actor,
+            day,
+            usage.messages,
+            usage.target_chats,
+            usage.cleanups
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_usage(
+        &self,
+        bot_id: &str,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> anyhow::Result<Vec<UsageEntry>> {
+        let entries = sqlx::query_as!(
+            UsageEntry,
+            r#"
+            SELECT actor, day, messages, target_chats, cleanups
+            FROM api_usage
+            WHERE bot_id = $1 AND day BETWEEN $2 AND $3
+            ORDER BY day, actor
+            "#,
+            bot_id,
+            from,
+            to
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(entries)
+    }
+}
@@ -0,0 +1,3606 @@
+use std::{
+    collections::{HashMap, HashSet},
+    str::FromStr,
+};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use serde_json::Value;
+use sqlx::{
+    sqlite::{SqliteConnectOptions, SqlitePoolOptions, SqliteRow},
+    Row, Sqlite, SqliteConnection, SqlitePool,
+};
+use teloxide::types::ChatInviteLink;
+
+use super::{
+    connect_with_retry, contains_pattern, pool_options, ChatActivity, ChatMetadata,
+    NewQueuedMessage, PendingMessage, Storage, UpsertedChat, CLAIM_LEASE,
+};
+use crate::{
+    activity::InactiveMember,
+    admin::ChatAdmin,
+    attachment::Attachments,
+    audit::{AuditEntry, NewAuditEntry},
+    branding::Branding,
+    broadcast::{BroadcastProgress, DeliveredFile, Delivery},
+    captcha::{Captcha, CaptchaChallenge},
+    chat_list::ChatQuery,
+    config::DatabasePoolConfig,
+    dead_letter::FailedMessage,
+    draft::DraftGone,
+    draft::{Draft, DraftContent},
+    duplicate::{text_hash, DuplicateBroadcast},
+    exclusion::Exclusion,
+    invite_link::InviteLink,
+    join_request::{JoinPolicy, JoinRequest, JoinRequestStatus, NewJoinRequest},
+    language::Translations,
+    moderation::{ModerationAction, ModerationKind, ModerationSettings, Warning},
+    queue::{QueueEntry, QueueFilter},
+    queue_history::QueuedMessageVersion,
+    quiet_hours::QuietHours,
+    reconcile::InterruptedCleanup,
+    rename::ChatRename,
+    report::{ErrorCount, ReportCounts},
+    staged_cleanup::CleanupCandidate,
+    state::{Chat, Chats, Kick, Kicks, QueuedMessage, User, Users},
+    subscriber::Audience,
+    usage::{Usage, UsageEntry},
+    variant::{MessageVariant, VariantResult},
+    webhook::{Webhook, WebhookEvent},
+    welcome::{Welcome, WelcomeDeletion},
+};
+
+const QUEUED_MESSAGE_COLUMNS: &str = "id, bot_id, chats, all_chats, message, images, datetime, parse_mode, pin, pin_silently, silent, disable_web_page_preview, protect_content, audience, copy_from_chat_id, copy_message_id, reply_to_queue_id, datetime_local, collect_replies, delete_after_seconds, channel_silent, channel_protect_content, request_id";
+
+const INVITE_LINK_COLUMNS: &str = "id, chat_id, invite_link, name, expire_date, member_limit, creates_join_request, created_at, revoked_at";
+
+const JOIN_REQUEST_COLUMNS: &str = "id, chat_id, user_id, username, name, bio, invite_link, status, requested_at, decided_at, decided_by";
+
+/// Storage for small single instance deployments. Arrays are kept as JSON
+/// text and timestamps as RFC 3339 text written by the application.
+pub struct SqliteStorage {
+    pool: SqlitePool,
+}
+
+impl SqliteStorage {
+    pub async fn connect(
+        url: &str,
+        max_connections: u32,
+        config: &DatabasePoolConfig,
+    ) -> anyhow::Result<Self> {
+        let options = SqliteConnectOptions::from_str(url)?.create_if_missing(true);
+
+        let pool = connect_with_retry(config, || {
+            // every connection to an in-memory database opens a fresh one, so
+            // keep exactly one alive for the lifetime of the pool
+            let pool = if url.contains(":memory:") {
+                SqlitePoolOptions::new()
+                    .max_connections(1)
+                    .idle_timeout(None)
+                    .max_lifetime(None)
+            } else {
+                pool_options::<Sqlite>(max_connections, config)
+            };
+            pool.connect_with(options.clone())
+        })
+        .await?;
+        sqlx::migrate!("./migrations/sqlite").run(&pool).await?;
+
+        Ok(Self { pool })
+    }
+}
+
+async fn find_duplicate_message(
+    conn: &mut SqliteConnection,
+    bot_id: &str,
+    text_hash: &str,
+    since: DateTime<Utc>,
+    chats: Option<&[i64]>,
+    audience: Audience,
+) -> anyhow::Result<Option<i32>> {
+    let candidates: Vec<(i32, bool, String)> = sqlx::query_as(
+        r#"
+        SELECT id, all_chats, chats FROM message_queue
+        WHERE bot_id = ? AND text_hash = ? AND created_at > ?
+        AND audience = ? AND NOT awaiting_approval
+        ORDER BY id DESC
+        "#,
+    )
+    .bind(bot_id)
+    .bind(text_hash)
+    .bind(since)
+    .bind(audience.to_string())
+    .fetch_all(conn)
+    .await?;
+
+    for (id, all_chats, queued) in candidates {
+        let queued: Vec<i64> = serde_json::from_str(&queued)?;
+        let overlaps = match chats {
+            None => true,
+            Some(chats) => all_chats || queued.iter().any(|chat| chats.contains(chat)),
+        };
+        if overlaps {
+            return Ok(Some(id));
+        }
+    }
+
+    Ok(None)
+}
+
+fn chat(row: &SqliteRow) -> anyhow::Result<Chat> {
+    Ok(Chat {
+        id: row.try_get("id")?,
+        name: row.try_get("name")?,
+        muted: row.try_get("muted")?,
+        approved: row.try_get("approved")?,
+        kind: row.try_get("kind")?,
+        signs_messages: row.try_get("signs_messages")?,
+        username: row.try_get("username")?,
+        member_count: row.try_get("member_count")?,
+        invite_link: row.try_get("invite_link")?,
+        description: row.try_get("description")?,
+        photo_file_id: row.try_get("photo_file_id")?,
+        metadata_updated_at: row.try_get("metadata_updated_at")?,
+        rate_limit_override: row.try_get("rate_limit_override")?,
+        archived: row.try_get("archived")?,
+        language: row.try_get("language")?,
+        labels: serde_json::from_str(row.try_get("labels")?)?,
+        notes: row.try_get("notes")?,
+        delete_service_messages: row.try_get("delete_service_messages")?,
+        utc_offset_minutes: row.try_get("utc_offset_minutes")?,
+    })
+}
+
+fn webhook(row: &SqliteRow) -> anyhow::Result<Webhook> {
+    Ok(Webhook {
+        id: row.try_get("id")?,
+        url: row.try_get("url")?,
+        events: serde_json::from_str(row.try_get("events")?)?,
+        secret: row.try_get("secret")?,
+        created_at: row.try_get("created_at")?,
+    })
+}
+
+fn draft(row: &SqliteRow) -> anyhow::Result<Draft> {
+    Ok(Draft {
+        id: row.try_get("id")?,
+        content: serde_json::from_str(row.try_get("content")?)?,
+        created_at: row.try_get("created_at")?,
+        updated_at: row.try_get("updated_at")?,
+    })
+}
+
+fn moderation_action(row: &SqliteRow) -> anyhow::Result<ModerationAction> {
+    Ok(ModerationAction {
+        id: row.try_get("id")?,
+        chat_id: row.try_get("chat_id")?,
+        user_id: row.try_get("user_id")?,
+        action: row.try_get::<&str, _>("action")?.parse()?,
+        reason: row.try_get("reason")?,
+        moderator: row.try_get("moderator")?,
+        created_at: row.try_get("created_at")?,
+    })
+}
+
+fn join_request(row: &SqliteRow) -> anyhow::Result<JoinRequest> {
+    Ok(JoinRequest {
+        id: row.try_get("id")?,
+        chat_id: row.try_get("chat_id")?,
+        user_id: row.try_get("user_id")?,
+        username: row.try_get("username")?,
+        name: row.try_get("name")?,
+        bio: row.try_get("bio")?,
+        invite_link: row.try_get("invite_link")?,
+        status: row.try_get::<&str, _>("status")?.parse()?,
+        requested_at: row.try_get("requested_at")?,
+        decided_at: row.try_get("decided_at")?,
+        decided_by: row.try_get("decided_by")?,
+    })
+}
+
+fn queued_message(row: &SqliteRow) -> anyhow::Result<QueuedMessage> {
+    Ok(QueuedMessage {
+        id: row.try_get("id")?,
+        bot_id: row.try_get("bot_id")?,
+        chats: serde_json::from_str(row.try_get("chats")?)?,
+        all_chats: row.try_get("all_chats")?,
+        message: row.try_get("message")?,
+        images: serde_json::from_str(row.try_get("images")?)?,
+        datetime: row.try_get("datetime")?,
+        parse_mode: row.try_get("parse_mode")?,
+        pin: row.try_get("pin")?,
+        pin_silently: row.try_get("pin_silently")?,
+        silent: row.try_get("silent")?,
+        disable_web_page_preview: row.try_get("disable_web_page_preview")?,
+        protect_content: row.try_get("protect_content")?,
+        audience: row.try_get("audience")?,
+        copy_from_chat_id: row.try_get("copy_from_chat_id")?,
+        copy_message_id: row.try_get("copy_message_id")?,
+        reply_to_queue_id: row.try_get("reply_to_queue_id")?,
+        datetime_local: row.try_get("datetime_local")?,
+        collect_replies: row.try_get("collect_replies")?,
+        delete_after_seconds: row.try_get("delete_after_seconds")?,
+        channel_silent: row.try_get("channel_silent")?,
+        channel_protect_content: row.try_get("channel_protect_content")?,
+        request_id: row.try_get("request_id")?,
+    })
+}
+
+#[async_trait]
+impl Storage for SqliteStorage {
+    async fn ping(&self) -> anyhow::Result<()> {
+        sqlx::query("SELECT 1").execute(&self.pool).await?;
+
+        Ok(())
+    }
+
+    async fn get_chats(&self, bot_id: &str) -> anyhow::Result<Chats> {
+        let rows = sqlx::query(
+            r#"
+SELECT id, name, muted, approved, kind, signs_messages, username, member_count, invite_link, description, photo_file_id, metadata_updated_at, rate_limit_override, archived, language, labels, notes, delete_service_messages, utc_offset_minutes
+FROM tg_chat
+WHERE bot_id = ? AND NOT archived
+            "#,
+        )
+        .bind(bot_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter().map(chat).collect()
+    }
+
+    async fn query_chats(
+        &self,
+        bot_id: &str,
+        query: &ChatQuery,
+        limit: i64,
+        offset: i64,
+    ) -> anyhow::Result<(Chats, i64)> {
+        // the chat lists are bound as json arrays
+        let only = query.only.as_ref().map(serde_json::to_string).transpose()?;
+        let except = serde_json::to_string(&query.except)?;
+        let filter = r#"
+WHERE c.bot_id = ?1 AND c.archived = ?2
+AND ( ?3 IS NULL OR c.name LIKE '%' || ?3 || '%' )
+AND ( ?4 IS NULL OR EXISTS ( SELECT 1 FROM json_each(c.labels) WHERE value = ?4 ) )
+AND ( ?5 IS NULL OR c.muted = ?5 )
+AND ( ?6 IS NULL OR c.id IN ( SELECT value FROM json_each(?6) ) )
+AND c.id NOT IN ( SELECT value FROM json_each(?7) )
+AND ( ?8 IS NULL OR c.kind = ?8 )
+        "#;
+
+        let total: i64 = sqlx::query_scalar(&format!("SELECT COUNT(*) FROM tg_chat c {filter}"))
+            .bind(bot_id)
+            .bind(query.archived)
+            .bind(&query.search)
+            .bind(&query.label)
+            .bind(query.muted)
+            .bind(&only)
+            .bind(&except)
+            .bind(&query.kind)
+            .fetch_one(&self.pool)
+            .await?;
+
+        let rows = sqlx::query(&format!(
+            r#"
+SELECT c.id, c.name, c.muted, c.approved, c.kind, c.signs_messages, c.username, c.member_count, c.invite_link, c.description, c.photo_file_id, c.metadata_updated_at, c.rate_limit_override, c.archived, c.language, c.labels, c.notes, c.delete_service_messages, c.utc_offset_minutes
+FROM tg_chat c
+LEFT JOIN (
+    SELECT chat_id, MAX(delivered_at) AS delivered_at FROM message_delivery
+    WHERE error IS NULL AND deferred_until IS NULL
+    GROUP BY chat_id
+) d ON d.chat_id = c.id
+{filter}
+ORDER BY
+    CASE WHEN ?9 = 'name' AND NOT ?10 THEN lower(c.name) END ASC,
+    CASE WHEN ?9 = 'name' AND ?10 THEN lower(c.name) END DESC,
+    CASE WHEN ?9 = 'member_count' AND NOT ?10 THEN c.member_count END ASC NULLS LAST,
+    CASE WHEN ?9 = 'member_count' AND ?10 THEN c.member_count END DESC NULLS LAST,
+    CASE WHEN ?9 = 'last_broadcast' AND NOT ?10 THEN d.delivered_at END ASC NULLS LAST,
+    CASE WHEN ?9 = 'last_broadcast' AND ?10 THEN d.delivered_at END DESC NULLS LAST,
+    c.id
+LIMIT ?11 OFFSET ?12
+            "#
+        ))
+        .bind(bot_id)
+        .bind(query.archived)
+        .bind(&query.search)
+        .bind(&query.label)
+        .bind(query.muted)
+        .bind(&only)
+        .bind(&except)
+        .bind(&query.kind)
+        .bind(query.sort.as_str())
+        .bind(query.descending)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let chats = rows.iter().map(chat).collect::<anyhow::Result<_>>()?;
+
+        Ok((chats, total))
+    }
+
+    async fn upsert_chat(
+        &self,
+        bot_id: &str,
+        id: i64,
+        name: &str,
+        approved: bool,
+        kind: &str,
+        username: Option<&str>,
+    ) -> anyhow::Result<Option<UpsertedChat>> {
+        let mut tx = self.pool.begin().await?;
+
+        let existing: Option<(bool, bool, String)> =
+            sqlx::query_as("SELECT approved, archived, bot_id FROM tg_chat WHERE id = ?")
+                .bind(id)
+                .fetch_optional(&mut tx)
+                .await?;
+        if matches!(&existing, Some((_, _, owner)) if owner != bot_id) {
+            return Ok(None);
+        }
+
+        sqlx::query(
+            r#"
+INSERT INTO tg_chat ( id, name, approved, kind, username, bot_id, created_at )
+VALUES ( ?1, ?2, ?3, ?4, ?5, ?6, ?7 )
+ON CONFLICT (id) DO UPDATE
+SET name = ?2, kind = ?4, username = ?5
+            "#,
+        )
+        .bind(id)
+        .bind(name)
+        .bind(approved)
+        .bind(kind)
+        .bind(username)
+        .bind(bot_id)
+        .bind(Utc::now())
+        .execute(&mut tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(Some(UpsertedChat {
+            approved: existing
+                .as_ref()
+                .map_or(approved, |(approved, _, _)| *approved),
+            inserted: existing.is_none(),
+            archived: existing.as_ref().is_some_and(|(_, archived, _)| *archived),
+        }))
+    }
+
+    async fn update_chat_metadata(
+        &self,
+        bot_id: &str,
+        id: i64,
+        metadata: &ChatMetadata<'_>,
+    ) -> anyhow::Result<()> {
+        sqlx::query(
+            r#"
+UPDATE tg_chat
+SET kind = ?2, username = ?3, member_count = ?4, invite_link = ?5, description = ?6, photo_file_id = ?7, metadata_updated_at = ?8
+WHERE id = ?1 AND bot_id = ?9
+            "#,
+        )
+        .bind(id)
+        .bind(metadata.kind)
+        .bind(metadata.username)
+        .bind(metadata.member_count)
+        .bind(metadata.invite_link)
+        .bind(metadata.description)
+        .bind(metadata.photo_file_id)
+        .bind(Utc::now())
+        .bind(bot_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn approve_chat(&self, bot_id: &str, id: i64) -> anyhow::Result<bool> {
+        let result = sqlx::query("UPDATE tg_chat SET approved = 1 WHERE id = ? AND bot_id = ?")
+            .bind(id)
+            .bind(bot_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn get_unapproved_chats(&self, bot_id: &str) -> anyhow::Result<Vec<i64>> {
+        let chats = sqlx::query_scalar("SELECT id FROM tg_chat WHERE NOT approved AND bot_id = ?")
+            .bind(bot_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(chats)
+    }
+
+    async fn set_chat_muted(&self, bot_id: &str, id: i64, muted: bool) -> anyhow::Result<bool> {
+        let result = sqlx::query("UPDATE tg_chat SET muted = ? WHERE id = ? AND bot_id = ?")
+            .bind(muted)
+            .bind(id)
+            .bind(bot_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn set_delete_service_messages(
+        &self,
+        bot_id: &str,
+        id: i64,
+        delete: bool,
+    ) -> anyhow::Result<bool> {
+        let result = sqlx::query(
+            "UPDATE tg_chat SET delete_service_messages = ? WHERE id = ? AND bot_id = ?",
+        )
+        .bind(delete)
+        .bind(id)
+        .bind(bot_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn set_chat_signs_messages(
+        &self,
+        bot_id: &str,
+        id: i64,
+        signs: bool,
+    ) -> anyhow::Result<bool> {
+        let result = sqlx::query(
+            "UPDATE tg_chat SET signs_messages = ?1 WHERE id = ?2 AND bot_id = ?3 AND signs_messages IS NOT ?1",
+        )
+        .bind(signs)
+        .bind(id)
+        .bind(bot_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn deletes_service_messages(&self, id: i64) -> anyhow::Result<Option<bool>> {
+        let delete = sqlx::query_scalar("SELECT delete_service_messages FROM tg_chat WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(delete)
+    }
+
+    async fn set_chat_rate_limit(
+        &self,
+        bot_id: &str,
+        id: i64,
+        per_minute: Option<i32>,
+    ) -> anyhow::Result<bool> {
+        let result =
+            sqlx::query("UPDATE tg_chat SET rate_limit_override = ? WHERE id = ? AND bot_id = ?")
+                .bind(per_minute)
+                .bind(id)
+                .bind(bot_id)
+                .execute(&self.pool)
+                .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn set_chat_language(
+        &self,
+        bot_id: &str,
+        id: i64,
+        language: Option<&str>,
+    ) -> anyhow::Result<bool> {
+        let result = sqlx::query("UPDATE tg_chat SET language = ? WHERE id = ? AND bot_id = ?")
+            .bind(language)
+            .bind(id)
+            .bind(bot_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn set_chat_utc_offset(
+        &self,
+        bot_id: &str,
+        id: i64,
+        minutes: Option<i32>,
+    ) -> anyhow::Result<bool> {
+        let result =
+            sqlx::query("UPDATE tg_chat SET utc_offset_minutes = ? WHERE id = ? AND bot_id = ?")
+                .bind(minutes)
+                .bind(id)
+                .bind(bot_id)
+                .execute(&self.pool)
+                .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn set_chat_labels(
+        &self,
+        bot_id: &str,
+        id: i64,
+        labels: &[String],
+    ) -> anyhow::Result<bool> {
+        let result = sqlx::query("UPDATE tg_chat SET labels = ? WHERE id = ? AND bot_id = ?")
+            .bind(serde_json::to_string(labels)?)
+            .bind(id)
+            .bind(bot_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn set_chat_notes(
+        &self,
+        bot_id: &str,
+        id: i64,
+        notes: Option<&str>,
+    ) -> anyhow::Result<bool> {
+        let result = sqlx::query("UPDATE tg_chat SET notes = ? WHERE id = ? AND bot_id = ?")
+            .bind(notes)
+            .bind(id)
+            .bind(bot_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn get_labelled_chats(
+        &self,
+        bot_id: &str,
+        labels: &[String],
+    ) -> anyhow::Result<Vec<i64>> {
+        let chats = sqlx::query_scalar(
+            r#"
+SELECT id FROM tg_chat
+WHERE bot_id = ? AND NOT archived AND EXISTS (
+    SELECT 1 FROM json_each(tg_chat.labels)
+    WHERE value IN ( SELECT value FROM json_each(?) )
+)
+ORDER BY id
+            "#,
+        )
+        .bind(bot_id)
+        .bind(serde_json::to_string(labels)?)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(chats)
+    }
+
+    async fn get_muted_chats(&self, bot_id: &str) -> anyhow::Result<Vec<i64>> {
+        let chats = sqlx::query_scalar("SELECT id FROM tg_chat WHERE muted AND bot_id = ?")
+            .bind(bot_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(chats)
+    }
+
+    async fn delete_chat(&self, bot_id: &str, id: i64) -> anyhow::Result<bool> {
+        let result = sqlx::query("DELETE FROM tg_chat WHERE id = ? AND bot_id = ?")
+            .bind(id)
+            .bind(bot_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn set_chats_muted(
+        &self,
+        bot_id: &str,
+        ids: &[i64],
+        muted: bool,
+    ) -> anyhow::Result<Vec<i64>> {
+        let mut tx = self.pool.begin().await?;
+
+        let mut updated = Vec::new();
+        for &id in ids {
+            let result = sqlx::query("UPDATE tg_chat SET muted = ? WHERE id = ? AND bot_id = ?")
+                .bind(muted)
+                .bind(id)
+                .bind(bot_id)
+                .execute(&mut tx)
+                .await?;
+            if result.rows_affected() > 0 {
+                updated.push(id);
+            }
+        }
+
+        tx.commit().await?;
+
+        Ok(updated)
+    }
+
+    async fn delete_chats(&self, bot_id: &str, ids: &[i64]) -> anyhow::Result<Vec<i64>> {
+        let mut tx = self.pool.begin().await?;
+
+        let mut deleted = Vec::new();
+        for &id in ids {
+            let result = sqlx::query("DELETE FROM tg_chat WHERE id = ? AND bot_id = ?")
+                .bind(id)
+                .bind(bot_id)
+                .execute(&mut tx)
+                .await?;
+            if result.rows_affected() > 0 {
+                deleted.push(id);
+            }
+        }
+
+        tx.commit().await?;
+
+        Ok(deleted)
+    }
+
+    async fn set_chat_archived(
+        &self,
+        bot_id: &str,
+        id: i64,
+        archived: bool,
+    ) -> anyhow::Result<bool> {
+        let result = sqlx::query(
+            r#"
+UPDATE tg_chat SET archived = ?3, archived_at = CASE WHEN ?3 THEN ?4 END
+WHERE id = ?2 AND bot_id = ?1 AND archived <> ?3
+            "#,
+        )
+        .bind(bot_id)
+        .bind(id)
+        .bind(archived)
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn rename_chat(&self, bot_id: &str, id: i64, name: &str) -> anyhow::Result<bool> {
+        let mut tx = self.pool.begin().await?;
+
+        let old_name: Option<String> =
+            sqlx::query_scalar("SELECT name FROM tg_chat WHERE id = ? AND bot_id = ?")
+                .bind(id)
+                .bind(bot_id)
+                .fetch_optional(&mut tx)
+                .await?;
+        let Some(old_name) = old_name.filter(|old_name| old_name != name) else {
+            return Ok(false);
+        };
+
+        sqlx::query("UPDATE tg_chat SET name = ? WHERE id = ?")
+            .bind(name)
+            .bind(id)
+            .execute(&mut tx)
+            .await?;
+        sqlx::query(
+            r#"
+INSERT INTO chat_name_history ( chat_id, old_name, new_name, renamed_at )
+VALUES ( ?, ?, ?, ? )
+            "#,
+        )
+        .bind(id)
+        .bind(old_name)
+        .bind(name)
+        .bind(Utc::now())
+        .execute(&mut tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(true)
+    }
+
+    async fn get_chat_renames(&self, chat_id: i64) -> anyhow::Result<Vec<ChatRename>> {
+        let renames = sqlx::query_as::<_, ChatRename>(
+            r#"
+SELECT old_name, new_name, renamed_at FROM chat_name_history
+WHERE chat_id = ?
+ORDER BY renamed_at DESC, id DESC
+            "#,
+        )
+        .bind(chat_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(renames)
+    }
+
+    async fn migrate_chat(&self, old_id: i64, new_id: i64) -> anyhow::Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        let new_exists: bool =
+            sqlx::query_scalar("SELECT EXISTS ( SELECT 1 FROM tg_chat WHERE id = ? )")
+                .bind(new_id)
+                .fetch_one(&mut tx)
+                .await?;
+
+        if new_exists {
+            // the new chat was already registered by a message, fold the old one into it
+            sqlx::query(
+                r#"
+        INSERT OR IGNORE INTO tg_user ( id, chat_id, username, name, bot_id, last_message_at, joined_at, updated_at )
+        SELECT id, ?1, username, name, bot_id, last_message_at, joined_at, updated_at FROM tg_user
+        WHERE chat_id = ?2
+        "#,
+            )
+            .bind(new_id)
+            .bind(old_id)
+            .execute(&mut tx)
+            .await?;
+
+            sqlx::query(
+                r#"
+        UPDATE tg_chat
+        SET muted = muted OR ( SELECT muted FROM tg_chat WHERE id = ?2 ),
+            approved = approved OR ( SELECT approved FROM tg_chat WHERE id = ?2 ),
+            labels = (
+                SELECT json_group_array(value) FROM (
+                    SELECT value FROM json_each(tg_chat.labels)
+                    UNION
+                    SELECT value FROM json_each(( SELECT labels FROM tg_chat WHERE id = ?2 ))
+                    ORDER BY value
+                )
+            ),
+            notes = COALESCE( notes, ( SELECT notes FROM tg_chat WHERE id = ?2 ) )
+        WHERE id = ?1
+        "#,
+            )
+            .bind(new_id)
+            .bind(old_id)
+            .execute(&mut tx)
+            .await?;
+
+            sqlx::query(
+                r#"
+        INSERT OR IGNORE INTO chat_welcome ( chat_id, template, delete_after_minutes, updated_at )
+        SELECT ?1, template, delete_after_minutes, updated_at FROM chat_welcome
+        WHERE chat_id = ?2
+        "#,
+            )
+            .bind(new_id)
+            .bind(old_id)
+            .execute(&mut tx)
+            .await?;
+
+            sqlx::query(
+                r#"
+        INSERT OR IGNORE INTO chat_branding ( chat_id, header, footer, updated_at )
+        SELECT ?1, header, footer, updated_at FROM chat_branding
+        WHERE chat_id = ?2
+        "#,
+            )
+            .bind(new_id)
+            .bind(old_id)
+            .execute(&mut tx)
+            .await?;
+
+            sqlx::query(
+                r#"
+        INSERT OR IGNORE INTO chat_quiet_hours ( chat_id, start_time, end_time, utc_offset_minutes, updated_at )
+        SELECT ?1, start_time, end_time, utc_offset_minutes, updated_at FROM chat_quiet_hours
+        WHERE chat_id = ?2
+        "#,
+            )
+            .bind(new_id)
+            .bind(old_id)
+            .execute(&mut tx)
+            .await?;
+
+            sqlx::query(
+                r#"
+        INSERT OR IGNORE INTO chat_moderation ( chat_id, banned_words, banned_patterns, links, restrict_after, restrict_minutes, kick_after, updated_at )
+        SELECT ?1, banned_words, banned_patterns, links, restrict_after, restrict_minutes, kick_after, updated_at FROM chat_moderation
+        WHERE chat_id = ?2
+        "#,
+            )
+            .bind(new_id)
+            .bind(old_id)
+            .execute(&mut tx)
+            .await?;
+
+            sqlx::query(
+                r#"
+        INSERT OR IGNORE INTO chat_warning ( chat_id, user_id, warnings, last_reason, last_warned_at )
+        SELECT ?1, user_id, warnings, last_reason, last_warned_at FROM chat_warning
+        WHERE chat_id = ?2
+        "#,
+            )
+            .bind(new_id)
+            .bind(old_id)
+            .execute(&mut tx)
+            .await?;
+
+            sqlx::query(
+                r#"
+        INSERT OR IGNORE INTO tg_admin ( chat_id, user_id, username, name, status, updated_at )
+        SELECT ?1, user_id, username, name, status, updated_at FROM tg_admin
+        WHERE chat_id = ?2
+        "#,
+            )
+            .bind(new_id)
+            .bind(old_id)
+            .execute(&mut tx)
+            .await?;
+
+            sqlx::query("UPDATE chat_name_history SET chat_id = ?1 WHERE chat_id = ?2")
+                .bind(new_id)
+                .bind(old_id)
+                .execute(&mut tx)
+                .await?;
+
+            sqlx::query("UPDATE chat_group_member SET chat_id = ?1 WHERE chat_id = ?2")
+                .bind(new_id)
+                .bind(old_id)
+                .execute(&mut tx)
+                .await?;
+
+            sqlx::query("UPDATE moderation_log SET chat_id = ?1 WHERE chat_id = ?2")
+                .bind(new_id)
+                .bind(old_id)
+                .execute(&mut tx)
+                .await?;
+
+            sqlx::query(
+                r#"
+        INSERT OR IGNORE INTO exclusion ( bot_id, chat_id, user_id, note, created_at )
+        SELECT bot_id, ?1, user_id, note, created_at FROM exclusion
+        WHERE chat_id = ?2
+        "#,
+            )
+            .bind(new_id)
+            .bind(old_id)
+            .execute(&mut tx)
+            .await?;
+
+            sqlx::query("DELETE FROM tg_chat WHERE id = ?")
+                .bind(old_id)
+                .execute(&mut tx)
+                .await?;
+        } else {
+            // members follow through `ON UPDATE CASCADE`
+            sqlx::query("UPDATE tg_chat SET id = ? WHERE id = ?")
+                .bind(new_id)
+                .bind(old_id)
+                .execute(&mut tx)
+                .await?;
+        }
+
+        let pending: Vec<(i32, String)> =
+            sqlx::query_as("SELECT id, chats FROM message_queue WHERE completed_at IS NULL")
+                .fetch_all(&mut tx)
+                .await?;
+        for (id, chats) in pending {
+            let mut chats: Vec<i64> = serde_json::from_str(&chats)?;
+            if !chats.contains(&old_id) {
+                continue;
+            }
+            for chat in chats.iter_mut().filter(|chat| **chat == old_id) {
+                *chat = new_id;
+            }
+            // messages for both chats reach the supergroup once
+            let mut seen = HashSet::new();
+            chats.retain(|chat| seen.insert(*chat));
+            sqlx::query("UPDATE message_queue SET chats = ? WHERE id = ?")
+                .bind(serde_json::to_string(&chats)?)
+                .bind(id)
+                .execute(&mut tx)
+                .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn set_chat_group(
+        &self,
+        bot_id: &str,
+        chat_id: i64,
+        group: Option<&str>,
+        assigned_by: i64,
+    ) -> anyhow::Result<()> {
+        match group {
+            Some(group) => {
+                sqlx::query(
+                    r#"
+                    INSERT INTO chat_group_member ( bot_id, chat_id, group_name, assigned_by, assigned_at )
+                    VALUES ( ?1, ?2, ?3, ?4, ?5 )
+                    ON CONFLICT (bot_id, chat_id) DO UPDATE
+                    SET group_name = ?3, assigned_by = ?4, assigned_at = ?5
+                    "#,
+                )
+                .bind(bot_id)
+                .bind(chat_id)
+                .bind(group)
+                .bind(assigned_by)
+                .bind(Utc::now())
+                .execute(&self.pool)
+                .await?;
+            }
+            None => {
+                sqlx::query("DELETE FROM chat_group_member WHERE bot_id = ? AND chat_id = ?")
+                    .bind(bot_id)
+                    .bind(chat_id)
+                    .execute(&self.pool)
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn get_chat_group_assignments(&self, bot_id: &str) -> anyhow::Result<Vec<(i64, String)>> {
+        let assignments = sqlx::query_as(
+            "SELECT chat_id, group_name FROM chat_group_member WHERE bot_id = ? ORDER BY chat_id",
+        )
+        .bind(bot_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(assignments)
+    }
+
+    async fn get_all_members(&self, chat_id: i64) -> anyhow::Result<Users> {
+        let users = sqlx::query_as::<_, User>(
+            "SELECT id, chat_id, username, name, joined_at, updated_at FROM tg_user WHERE chat_id = ?",
+        )
+        .bind(chat_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(users)
+    }
+
+    async fn get_members_page(
+        &self,
+        chat_id: i64,
+        search: Option<&str>,
+        limit: i64,
+        offset: i64,
+    ) -> anyhow::Result<Users> {
+        let pattern = search.map(contains_pattern);
+
+        // LIKE is case-insensitive for ASCII in sqlite
+        let users = sqlx::query_as::<_, User>(
+            r#"
+SELECT id, chat_id, username, name, joined_at, updated_at FROM tg_user
+WHERE chat_id = ?1
+AND ( ?2 IS NULL OR username LIKE ?2 ESCAPE '\' OR name LIKE ?2 ESCAPE '\' )
+ORDER BY id
+LIMIT ?3 OFFSET ?4
+            "#,
+        )
+        .bind(chat_id)
+        .bind(pattern)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(users)
+    }
+
+    async fn get_member(&self, chat_id: i64, user_id: i64) -> anyhow::Result<Option<User>> {
+        let user = sqlx::query_as::<_, User>(
+            "SELECT id, chat_id, username, name, joined_at, updated_at FROM tg_user WHERE chat_id = ? AND id = ?",
+        )
+        .bind(chat_id)
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(user)
+    }
+
+    async fn count_members(&self, chat_id: i64) -> anyhow::Result<i64> {
+        let count = sqlx::query_scalar("SELECT count(*) FROM tg_user WHERE chat_id = ?")
+            .bind(chat_id)
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(count)
+    }
+
+    async fn get_chat_activity(&self, bot_id: &str) -> anyhow::Result<Vec<ChatActivity>> {
+        let activity = sqlx::query_as::<_, ChatActivity>(
+            r#"
+SELECT c.id, c.name,
+    ( SELECT count(*) FROM tg_user u WHERE u.chat_id = c.id ) AS member_count,
+    (
+        SELECT count(*) FROM message_queue q
+        WHERE q.bot_id = c.bot_id AND q.completed_at IS NULL
+        AND (
+            EXISTS ( SELECT 1 FROM json_each(q.chats) WHERE value = c.id )
+            OR ( q.all_chats AND q.audience = 'chats' )
+        )
+        AND NOT EXISTS ( SELECT 1 FROM failed_message f WHERE f.queue_id = q.id )
+    ) AS queued_broadcasts
+FROM tg_chat c
+WHERE c.bot_id = ? AND NOT c.archived
+ORDER BY c.id
+            "#,
+        )
+        .bind(bot_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(activity)
+    }
+
+    async fn upsert_member(
+        &self,
+        bot_id: &str,
+        chat_id: i64,
+        user_id: i64,
+        username: &str,
+        name: &str,
+    ) -> anyhow::Result<()> {
+        sqlx::query(
+            r#"
+INSERT INTO tg_user ( id, chat_id, username, name, bot_id, joined_at, updated_at )
+VALUES ( ?1, ?2, ?3, ?4, ?5, ?6, ?6 )
+ON CONFLICT ( id, chat_id ) DO UPDATE
+SET username = ?3, name = ?4, updated_at = CASE
+    WHEN tg_user.username IS NOT ?3 OR tg_user.name <> ?4 THEN ?6
+    ELSE tg_user.updated_at
+END
+            "#,
+        )
+        .bind(user_id)
+        .bind(chat_id)
+        .bind(username)
+        .bind(name)
+        .bind(bot_id)
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn touch_member(
+        &self,
+        chat_id: i64,
+        user_id: i64,
+        at: DateTime<Utc>,
+    ) -> anyhow::Result<()> {
+        sqlx::query(
+            r#"
+UPDATE tg_user
+SET last_message_at = ?3
+WHERE chat_id = ?1 AND id = ?2
+AND ( last_message_at IS NULL OR julianday(last_message_at) < julianday(?3) )
+            "#,
+        )
+        .bind(chat_id)
+        .bind(user_id)
+        .bind(at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_inactive_members(
+        &self,
+        chat_id: i64,
+        before: DateTime<Utc>,
+    ) -> anyhow::Result<Vec<InactiveMember>> {
+        let members = sqlx::query_as::<_, InactiveMember>(
+            r#"
+SELECT id, chat_id, username, name, last_message_at FROM tg_user
+WHERE chat_id = ?1
+AND julianday(COALESCE(last_message_at, joined_at)) < julianday(?2)
+ORDER BY julianday(COALESCE(last_message_at, joined_at)), id
+            "#,
+        )
+        .bind(chat_id)
+        .bind(before)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(members)
+    }
+
+    async fn remove_member(&self, chat_id: i64, user_id: i64) -> anyhow::Result<()> {
+        sqlx::query("DELETE FROM tg_user WHERE id = ? AND chat_id = ?")
+            .bind(user_id)
+            .bind(chat_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn log_kick(
+        &self,
+        chat_id: i64,
+        user: &User,
+        reason: &str,
+        initiator: &str,
+        request_id: Option<&str>,
+    ) -> anyhow::Result<()> {
+        sqlx::query(
+            r#"
+INSERT INTO kick_log ( chat_id, user_id, username, name, reason, initiator, request_id, kicked_at )
+VALUES ( ?, ?, ?, ?, ?, ?, ?, ? )
+            "#,
+        )
+        .bind(chat_id)
+        .bind(user.id)
+        .bind(&user.username)
+        .bind(&user.name)
+        .bind(reason)
+        .bind(initiator)
+        .bind(request_id)
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_kicks(&self, chat_id: i64, limit: i64, offset: i64) -> anyhow::Result<Kicks> {
+        let kicks = sqlx::query_as::<_, Kick>(
+            r#"
+SELECT id, chat_id, user_id, username, name, reason, initiator, kicked_at, request_id FROM kick_log
+WHERE chat_id = ?
+ORDER BY kicked_at DESC, id DESC
+LIMIT ? OFFSET ?
+            "#,
+        )
+        .bind(chat_id)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(kicks)
+    }
+
+    async fn get_kicked_user_ids(&self, chat_id: i64) -> anyhow::Result<Vec<i64>> {
+        let users = sqlx::query_scalar(
+            "SELECT DISTINCT user_id FROM kick_log WHERE chat_id = ? ORDER BY user_id",
+        )
+        .bind(chat_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(users)
+    }
+
+    async fn add_cleanup_candidate(
+        &self,
+        chat_id: i64,
+        user: &User,
+        initiator: &str,
+    ) -> anyhow::Result<()> {
+        sqlx::query(
+            r#"
+INSERT OR IGNORE INTO cleanup_candidate ( chat_id, user_id, username, name, initiator, staged_at )
+VALUES ( ?, ?, ?, ?, ?, ? )
+            "#,
+        )
+        .bind(chat_id)
+        .bind(user.id)
+        .bind(&user.username)
+        .bind(&user.name)
+        .bind(initiator)
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_cleanup_candidates(&self, chat_id: i64) -> anyhow::Result<Vec<CleanupCandidate>> {
+        let candidates = sqlx::query_as::<_, CleanupCandidate>(
+            r#"
+SELECT user_id, username, name, initiator, staged_at FROM cleanup_candidate
+WHERE chat_id = ?
+ORDER BY name, user_id
+            "#,
+        )
+        .bind(chat_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(candidates)
+    }
+
+    async fn delete_cleanup_candidates(&self, chat_id: i64) -> anyhow::Result<u64> {
+        let result = sqlx::query("DELETE FROM cleanup_candidate WHERE chat_id = ?")
+            .bind(chat_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn start_cleanup_run(
+        &self,
+        chat_id: i64,
+        staged: bool,
+        initiator: &str,
+    ) -> anyhow::Result<()> {
+        sqlx::query(
+            r#"
+INSERT INTO cleanup_run ( chat_id, staged, initiator, started_at )
+VALUES ( ?1, ?2, ?3, ?4 )
+ON CONFLICT (chat_id) DO UPDATE
+SET staged = ?2, initiator = ?3, started_at = ?4, resume_at = NULL
+            "#,
+        )
+        .bind(chat_id)
+        .bind(staged)
+        .bind(initiator)
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn finish_cleanup_run(&self, chat_id: i64) -> anyhow::Result<()> {
+        sqlx::query("DELETE FROM cleanup_run WHERE chat_id = ?")
+            .bind(chat_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn set_cleanup_cursor(&self, chat_id: i64, last_user_id: i64) -> anyhow::Result<()> {
+        sqlx::query("UPDATE cleanup_run SET last_user_id = ? WHERE chat_id = ?")
+            .bind(last_user_id)
+            .bind(chat_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn pause_cleanup_run(
+        &self,
+        chat_id: i64,
+        resume_at: DateTime<Utc>,
+    ) -> anyhow::Result<()> {
+        sqlx::query("UPDATE cleanup_run SET resume_at = ? WHERE chat_id = ?")
+            .bind(resume_at)
+            .bind(chat_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn get_cleanup_run(&self, chat_id: i64) -> anyhow::Result<Option<InterruptedCleanup>> {
+        let run = sqlx::query_as::<_, InterruptedCleanup>(
+            r#"
+SELECT chat_id, staged, initiator, started_at, last_user_id, resume_at
+FROM cleanup_run
+WHERE chat_id = ?
+            "#,
+        )
+        .bind(chat_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(run)
+    }
+
+    async fn get_cleanup_runs(&self, bot_id: &str) -> anyhow::Result<Vec<InterruptedCleanup>> {
+        let runs = sqlx::query_as::<_, InterruptedCleanup>(
+            r#"
+SELECT r.chat_id, r.staged, r.initiator, r.started_at, r.last_user_id, r.resume_at
+FROM cleanup_run r
+JOIN tg_chat c ON c.id = r.chat_id
+WHERE c.bot_id = ?
+ORDER BY julianday(r.started_at), r.chat_id
+            "#,
+        )
+        .bind(bot_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(runs)
+    }
+
+    async fn set_chat_admins(&self, chat_id: i64, admins: &[ChatAdmin]) -> anyhow::Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query("DELETE FROM tg_admin WHERE chat_id = ?")
+            .bind(chat_id)
+            .execute(&mut tx)
+            .await?;
+
+        for admin in admins {
+            sqlx::query(
+                r#"
+INSERT INTO tg_admin ( chat_id, user_id, username, name, status, updated_at )
+VALUES ( ?, ?, ?, ?, ?, ? )
+                "#,
+            )
+            .bind(chat_id)
+            .bind(admin.user_id)
+            .bind(&admin.username)
+            .bind(&admin.name)
+            .bind(&admin.status)
+            .bind(admin.updated_at)
+            .execute(&mut tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn upsert_chat_admin(&self, chat_id: i64, admin: &ChatAdmin) -> anyhow::Result<()> {
+        sqlx::query(
+            r#"
+INSERT INTO tg_admin ( chat_id, user_id, username, name, status, updated_at )
+VALUES ( ?1, ?2, ?3, ?4, ?5, ?6 )
+ON CONFLICT ( chat_id, user_id ) DO UPDATE
+SET username = ?3, name = ?4, status = ?5, updated_at = ?6
+            "#,
+        )
+        .bind(chat_id)
+        .bind(admin.user_id)
+        .bind(&admin.username)
+        .bind(&admin.name)
+        .bind(&admin.status)
+        .bind(admin.updated_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn remove_chat_admin(&self, chat_id: i64, user_id: i64) -> anyhow::Result<()> {
+        sqlx::query("DELETE FROM tg_admin WHERE chat_id = ? AND user_id = ?")
+            .bind(chat_id)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn get_chat_admins(&self, chat_id: i64) -> anyhow::Result<Vec<ChatAdmin>> {
+        let admins = sqlx::query_as::<_, ChatAdmin>(
+            r#"
+SELECT user_id, username, name, status, updated_at FROM tg_admin
+WHERE chat_id = ?
+ORDER BY status = 'creator' DESC, name, user_id
+            "#,
+        )
+        .bind(chat_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(admins)
+    }
+
+    async fn get_welcome(&self, chat_id: i64) -> anyhow::Result<Option<Welcome>> {
+        let welcome = sqlx::query_as::<_, Welcome>(
+            "SELECT template, delete_after_minutes FROM chat_welcome WHERE chat_id = ?",
+        )
+        .bind(chat_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(welcome)
+    }
+
+    async fn set_welcome(&self, chat_id: i64, welcome: &Welcome) -> anyhow::Result<()> {
+        sqlx::query(
+            r#"
+INSERT INTO chat_welcome ( chat_id, template, delete_after_minutes, updated_at )
+VALUES ( ?1, ?2, ?3, ?4 )
+ON CONFLICT (chat_id) DO UPDATE
+SET template = ?2, delete_after_minutes = ?3, updated_at = ?4
+            "#,
+        )
+        .bind(chat_id)
+        .bind(&welcome.template)
+        .bind(welcome.delete_after_minutes)
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn delete_welcome(&self, chat_id: i64) -> anyhow::Result<bool> {
+        let result = sqlx::query("DELETE FROM chat_welcome WHERE chat_id = ?")
+            .bind(chat_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn add_welcome_deletion(
+        &self,
+        deletion: &WelcomeDeletion,
+        delete_at: DateTime<Utc>,
+    ) -> anyhow::Result<()> {
+        sqlx::query(
+            r#"
+INSERT INTO welcome_deletion ( chat_id, message_id, delete_at )
+VALUES ( ?, ?, ? )
+ON CONFLICT (chat_id, message_id) DO NOTHING
+            "#,
+        )
+        .bind(deletion.chat_id)
+        .bind(deletion.message_id)
+        .bind(delete_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_due_welcome_deletions(
+        &self,
+        bot_id: &str,
+        now: DateTime<Utc>,
+        limit: i64,
+    ) -> anyhow::Result<Vec<WelcomeDeletion>> {
+        let deletions = sqlx::query_as::<_, WelcomeDeletion>(
+            r#"
+SELECT chat_id, message_id
+FROM welcome_deletion
+WHERE julianday(delete_at) <= julianday(?2)
+AND chat_id IN ( SELECT id FROM tg_chat WHERE bot_id = ?1 )
+ORDER BY julianday(delete_at)
+LIMIT ?3
+            "#,
+        )
+        .bind(bot_id)
+        .bind(now)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(deletions)
+    }
+
+    async fn remove_welcome_deletion(&self, deletion: &WelcomeDeletion) -> anyhow::Result<()> {
+        sqlx::query("DELETE FROM welcome_deletion WHERE chat_id = ? AND message_id = ?")
+            .bind(deletion.chat_id)
+            .bind(deletion.message_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn get_branding(&self, chat_id: i64) -> anyhow::Result<Option<Branding>> {
+        let branding = sqlx::query_as::<_, Branding>(
+            "SELECT header, footer FROM chat_branding WHERE chat_id = ?",
+        )
+        .bind(chat_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(branding)
+    }
+
+    async fn get_all_brandings(&self, bot_id: &str) -> anyhow::Result<HashMap<i64, Branding>> {
+        let rows: Vec<(i64, Option<String>, Option<String>)> = sqlx::query_as(
+            r#"
+SELECT b.chat_id, b.header, b.footer
+FROM chat_branding b
+JOIN tg_chat c ON c.id = b.chat_id
+WHERE c.bot_id = ?
+            "#,
+        )
+        .bind(bot_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|(chat_id, header, footer)| (chat_id, Branding { header, footer }))
+            .collect())
+    }
+
+    async fn set_branding(&self, chat_id: i64, branding: &Branding) -> anyhow::Result<()> {
+        if branding.is_empty() {
+            sqlx::query("DELETE FROM chat_branding WHERE chat_id = ?")
+                .bind(chat_id)
+                .execute(&self.pool)
+                .await?;
+
+            return Ok(());
+        }
+
+        sqlx::query(
+            r#"
+INSERT INTO chat_branding ( chat_id, header, footer, updated_at )
+VALUES ( ?1, ?2, ?3, ?4 )
+ON CONFLICT (chat_id) DO UPDATE
+SET header = ?2, footer = ?3, updated_at = ?4
+            "#,
+        )
+        .bind(chat_id)
+        .bind(&branding.header)
+        .bind(&branding.footer)
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_quiet_hours(&self, chat_id: i64) -> anyhow::Result<Option<QuietHours>> {
+        let quiet_hours = sqlx::query_as::<_, QuietHours>(
+            "SELECT start_time, end_time, utc_offset_minutes FROM chat_quiet_hours WHERE chat_id = ?",
+        )
+        .bind(chat_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(quiet_hours)
+    }
+
+    async fn get_all_quiet_hours(&self, bot_id: &str) -> anyhow::Result<HashMap<i64, QuietHours>> {
+        let rows: Vec<(i64, String, String, i32)> = sqlx::query_as(
+            r#"
+SELECT h.chat_id, h.start_time, h.end_time, h.utc_offset_minutes
+FROM chat_quiet_hours h
+JOIN tg_chat c ON c.id = h.chat_id
+WHERE c.bot_id = ?
+            "#,
+        )
+        .bind(bot_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|(chat_id, start_time, end_time, utc_offset_minutes)| {
+                (
+                    chat_id,
+                    QuietHours {
+                        start_time,
+                        end_time,
+                        utc_offset_minutes,
+                    },
+                )
+            })
+            .collect())
+    }
+
+    async fn set_quiet_hours(&self, chat_id: i64, quiet_hours: &QuietHours) -> anyhow::Result<()> {
+        sqlx::query(
+            r#"
+INSERT INTO chat_quiet_hours ( chat_id, start_time, end_time, utc_offset_minutes, updated_at )
+VALUES ( ?1, ?2, ?3, ?4, ?5 )
+ON CONFLICT (chat_id) DO UPDATE
+SET start_time = ?2, end_time = ?3, utc_offset_minutes = ?4, updated_at = ?5
+            "#,
+        )
+        .bind(chat_id)
+        .bind(&quiet_hours.start_time)
+        .bind(&quiet_hours.end_time)
+        .bind(quiet_hours.utc_offset_minutes)
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn delete_quiet_hours(&self, chat_id: i64) -> anyhow::Result<bool> {
+        let result = sqlx::query("DELETE FROM chat_quiet_hours WHERE chat_id = ?")
+            .bind(chat_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn get_moderation(&self, chat_id: i64) -> anyhow::Result<Option<ModerationSettings>> {
+        let row = sqlx::query(
+            r#"
+SELECT banned_words, banned_patterns, links, restrict_after, restrict_minutes, kick_after
+FROM chat_moderation
+WHERE chat_id = ?
+            "#,
+        )
+        .bind(chat_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.map(|row| {
+            Ok(ModerationSettings {
+                banned_words: serde_json::from_str(row.try_get("banned_words")?)?,
+                banned_patterns: serde_json::from_str(row.try_get("banned_patterns")?)?,
+                links: row.try_get::<&str, _>("links")?.parse()?,
+                restrict_after: row.try_get("restrict_after")?,
+                restrict_minutes: row.try_get("restrict_minutes")?,
+                kick_after: row.try_get("kick_after")?,
+            })
+        })
+        .transpose()
+    }
+
+    async fn set_moderation(
+        &self,
+        chat_id: i64,
+        settings: &ModerationSettings,
+    ) -> anyhow::Result<()> {
+        sqlx::query(
+            r#"
+INSERT INTO chat_moderation ( chat_id, banned_words, banned_patterns, links, restrict_after, restrict_minutes, kick_after, updated_at )
+VALUES ( ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8 )
+ON CONFLICT (chat_id) DO UPDATE
+SET banned_words = ?2, banned_patterns = ?3, links = ?4, restrict_after = ?5,
+    restrict_minutes = ?6, kick_after = ?7, updated_at = ?8
+            "#,
+        )
+        .bind(chat_id)
+        .bind(serde_json::to_string(&settings.banned_words)?)
+        .bind(serde_json::to_string(&settings.banned_patterns)?)
+        .bind(settings.links.to_string())
+        .bind(settings.restrict_after)
+        .bind(settings.restrict_minutes)
+        .bind(settings.kick_after)
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn delete_moderation(&self, chat_id: i64) -> anyhow::Result<bool> {
+        let result = sqlx::query("DELETE FROM chat_moderation WHERE chat_id = ?")
+            .bind(chat_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn add_warning(&self, chat_id: i64, user_id: i64, reason: &str) -> anyhow::Result<i32> {
+        let warnings = sqlx::query_scalar(
+            r#"
+INSERT INTO chat_warning ( chat_id, user_id, warnings, last_reason, last_warned_at )
+VALUES ( ?1, ?2, 1, ?3, ?4 )
+ON CONFLICT ( chat_id, user_id ) DO UPDATE
+SET warnings = warnings + 1, last_reason = ?3, last_warned_at = ?4
+RETURNING warnings
+            "#,
+        )
+        .bind(chat_id)
+        .bind(user_id)
+        .bind(reason)
+        .bind(Utc::now())
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(warnings)
+    }
+
+    async fn get_warnings(&self, chat_id: i64) -> anyhow::Result<Vec<Warning>> {
+        let warnings = sqlx::query_as::<_, Warning>(
+            r#"
+SELECT chat_id, user_id, warnings, last_reason, last_warned_at FROM chat_warning
+WHERE chat_id = ?
+ORDER BY warnings DESC, julianday(last_warned_at) DESC
+            "#,
+        )
+        .bind(chat_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(warnings)
+    }
+
+    async fn reset_warnings(&self, chat_id: i64, user_id: i64) -> anyhow::Result<bool> {
+        let result = sqlx::query("DELETE FROM chat_warning WHERE chat_id = ? AND user_id = ?")
+            .bind(chat_id)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn log_moderation(
+        &self,
+        chat_id: i64,
+        user_id: i64,
+        kind: ModerationKind,
+        reason: &str,
+        moderator: &str,
+    ) -> anyhow::Result<()> {
+        sqlx::query(
+            r#"
+INSERT INTO moderation_log ( chat_id, user_id, action, reason, moderator, created_at )
+VALUES ( ?, ?, ?, ?, ?, ? )
+            "#,
+        )
+        .bind(chat_id)
+        .bind(user_id)
+        .bind(kind.to_string())
+        .bind(reason)
+        .bind(moderator)
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_moderation_log(
+        &self,
+        chat_id: i64,
+        user_id: Option<i64>,
+        limit: i64,
+        offset: i64,
+    ) -> anyhow::Result<Vec<ModerationAction>> {
+        let rows = sqlx::query(
+            r#"
+SELECT id, chat_id, user_id, action, reason, moderator, created_at FROM moderation_log
+WHERE chat_id = ?1
+AND ( ?2 IS NULL OR user_id = ?2 )
+ORDER BY julianday(created_at) DESC, id DESC
+LIMIT ?3 OFFSET ?4
+            "#,
+        )
+        .bind(chat_id)
+        .bind(user_id)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter().map(moderation_action).collect()
+    }
+
+    async fn get_maintenance(&self, bot_id: &str) -> anyhow::Result<bool> {
+        let maintenance: Option<bool> =
+            sqlx::query_scalar("SELECT maintenance FROM bot_settings WHERE bot_id = ?")
+                .bind(bot_id)
+                .fetch_optional(&self.pool)
+                .await?;
+
+        Ok(maintenance.unwrap_or(false))
+    }
+
+    async fn set_maintenance(&self, bot_id: &str, enabled: bool) -> anyhow::Result<()> {
+        sqlx::query(
+            r#"
+INSERT INTO bot_settings ( bot_id, maintenance, updated_at )
+VALUES ( ?1, ?2, ?3 )
+ON CONFLICT (bot_id) DO UPDATE
+SET maintenance = ?2, updated_at = ?3
+            "#,
+        )
+        .bind(bot_id)
+        .bind(enabled)
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_settings_overrides(&self, bot_id: &str) -> anyhow::Result<Option<Value>> {
+        let overrides: Option<Option<String>> =
+            sqlx::query_scalar("SELECT overrides FROM bot_settings WHERE bot_id = ?")
+                .bind(bot_id)
+                .fetch_optional(&self.pool)
+                .await?;
+
+        Ok(overrides
+            .flatten()
+            .map(|overrides| serde_json::from_str(&overrides))
+            .transpose()?)
+    }
+
+    async fn update_settings_overrides(
+        &self,
+        bot_id: &str,
+        update: &(dyn Fn(Value) -> anyhow::Result<Value> + Send + Sync),
+    ) -> anyhow::Result<Value> {
+        let mut tx = self.pool.begin().await?;
+        // writing first takes the lock before the overrides are read
+        sqlx::query(
+            r#"
+INSERT INTO bot_settings ( bot_id, updated_at )
+VALUES ( ?1, ?2 )
+ON CONFLICT (bot_id) DO UPDATE
+SET updated_at = ?2
+            "#,
+        )
+        .bind(bot_id)
+        .bind(Utc::now())
+        .execute(&mut tx)
+        .await?;
+        let stored: Option<String> =
+            sqlx::query_scalar("SELECT overrides FROM bot_settings WHERE bot_id = ?")
+                .bind(bot_id)
+                .fetch_one(&mut tx)
+                .await?;
+        let overrides = update(match stored {
+            Some(stored) => serde_json::from_str(&stored)?,
+            None => Value::Object(Default::default()),
+        })?;
+        sqlx::query("UPDATE bot_settings SET overrides = ? WHERE bot_id = ?")
+            .bind(serde_json::to_string(&overrides)?)
+            .bind(bot_id)
+            .execute(&mut tx)
+            .await?;
+        tx.commit().await?;
+
+        Ok(overrides)
+    }
+
+    async fn get_weekly_report_at(&self, bot_id: &str) -> anyhow::Result<Option<DateTime<Utc>>> {
+        let at: Option<Option<DateTime<Utc>>> =
+            sqlx::query_scalar("SELECT weekly_report_at FROM bot_settings WHERE bot_id = ?")
+                .bind(bot_id)
+                .fetch_optional(&self.pool)
+                .await?;
+
+        Ok(at.flatten())
+    }
+
+    async fn set_weekly_report_at(&self, bot_id: &str, at: DateTime<Utc>) -> anyhow::Result<()> {
+        sqlx::query(
+            r#"
+INSERT INTO bot_settings ( bot_id, weekly_report_at, updated_at )
+VALUES ( ?1, ?2, ?2 )
+ON CONFLICT (bot_id) DO UPDATE
+SET weekly_report_at = ?2
+            "#,
+        )
+        .bind(bot_id)
+        .bind(at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_report_counts(
+        &self,
+        bot_id: &str,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        top_errors: i64,
+    ) -> anyhow::Result<ReportCounts> {
+        let row = sqlx::query(
+            r#"
+SELECT
+    ( SELECT COUNT(*) FROM tg_chat WHERE bot_id = ?1 AND NOT archived ) AS chats,
+    ( SELECT COUNT(*) FROM tg_chat WHERE bot_id = ?1
+      AND julianday(created_at) >= julianday(?2) AND julianday(created_at) < julianday(?3) ) AS chats_added,
+    ( SELECT COUNT(*) FROM tg_chat WHERE bot_id = ?1 AND archived
+      AND julianday(archived_at) >= julianday(?2) AND julianday(archived_at) < julianday(?3) ) AS chats_lost,
+    ( SELECT COUNT(*) FROM kick_log k JOIN tg_chat c ON c.id = k.chat_id WHERE c.bot_id = ?1
+      AND julianday(k.kicked_at) >= julianday(?2) AND julianday(k.kicked_at) < julianday(?3) ) AS members_purged,
+    ( SELECT COUNT(*) FROM message_queue WHERE bot_id = ?1
+      AND julianday(completed_at) >= julianday(?2) AND julianday(completed_at) < julianday(?3) ) AS broadcasts_sent,
+    COUNT(*) AS deliveries,
+    COUNT(d.error) AS failed_deliveries
+FROM message_delivery d
+JOIN message_queue q ON q.id = d.message_id
+WHERE q.bot_id = ?1 AND d.deferred_until IS NULL
+AND julianday(d.delivered_at) >= julianday(?2) AND julianday(d.delivered_at) < julianday(?3)
+            "#,
+        )
+        .bind(bot_id)
+        .bind(from)
+        .bind(to)
+        .fetch_one(&self.pool)
+        .await?;
+
+        let top_errors: Vec<(String, i64)> = sqlx::query_as(
+            r#"
+SELECT d.error, COUNT(*)
+FROM message_delivery d
+JOIN message_queue q ON q.id = d.message_id
+WHERE q.bot_id = ?1 AND d.error IS NOT NULL AND d.deferred_until IS NULL
+AND julianday(d.delivered_at) >= julianday(?2) AND julianday(d.delivered_at) < julianday(?3)
+GROUP BY d.error
+ORDER BY COUNT(*) DESC, d.error
+LIMIT ?4
+            "#,
+        )
+        .bind(bot_id)
+        .bind(from)
+        .bind(to)
+        .bind(top_errors)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(ReportCounts {
+            chats: row.try_get("chats")?,
+            chats_added: row.try_get("chats_added")?,
+            chats_lost: row.try_get("chats_lost")?,
+            members_purged: row.try_get("members_purged")?,
+            broadcasts_sent: row.try_get("broadcasts_sent")?,
+            deliveries: row.try_get("deliveries")?,
+            failed_deliveries: row.try_get("failed_deliveries")?,
+            top_errors: top_errors
+                .into_iter()
+                .map(|(error, count)| ErrorCount { error, count })
+                .collect(),
+        })
+    }
+
+    async fn get_captcha(&self, chat_id: i64) -> anyhow::Result<Option<Captcha>> {
+        let captcha = sqlx::query_as::<_, Captcha>(
+            "SELECT timeout_minutes FROM chat_captcha WHERE chat_id = ?",
+        )
+        .bind(chat_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(captcha)
+    }
+
+    async fn set_captcha(&self, chat_id: i64, captcha: &Captcha) -> anyhow::Result<()> {
+        sqlx::query(
+            r#"
+INSERT INTO chat_captcha ( chat_id, timeout_minutes, updated_at )
+VALUES ( ?1, ?2, ?3 )
+ON CONFLICT (chat_id) DO UPDATE
+SET timeout_minutes = ?2, updated_at = ?3
+            "#,
+        )
+        .bind(chat_id)
+        .bind(captcha.timeout_minutes)
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn delete_captcha(&self, chat_id: i64) -> anyhow::Result<bool> {
+        let result = sqlx::query("DELETE FROM chat_captcha WHERE chat_id = ?")
+            .bind(chat_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn add_captcha_challenge(&self, challenge: &CaptchaChallenge) -> anyhow::Result<()> {
+        sqlx::query(
+            r#"
+INSERT INTO captcha_challenge ( chat_id, user_id, username, name, message_id, expires_at )
+VALUES ( ?1, ?2, ?3, ?4, ?5, ?6 )
+ON CONFLICT ( chat_id, user_id ) DO UPDATE
+SET username = ?3, name = ?4, message_id = ?5, expires_at = ?6
+            "#,
+        )
+        .bind(challenge.chat_id)
+        .bind(challenge.user_id)
+        .bind(&challenge.username)
+        .bind(&challenge.name)
+        .bind(challenge.message_id)
+        .bind(challenge.expires_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_captcha_challenges(&self, chat_id: i64) -> anyhow::Result<Vec<CaptchaChallenge>> {
+        let challenges = sqlx::query_as::<_, CaptchaChallenge>(
+            r#"
+SELECT chat_id, user_id, username, name, message_id, expires_at FROM captcha_challenge
+WHERE chat_id = ?
+ORDER BY julianday(expires_at)
+            "#,
+        )
+        .bind(chat_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(challenges)
+    }
+
+    async fn get_captcha_challenge(
+        &self,
+        chat_id: i64,
+        user_id: i64,
+    ) -> anyhow::Result<Option<CaptchaChallenge>> {
+        let challenge = sqlx::query_as::<_, CaptchaChallenge>(
+            r#"
+SELECT chat_id, user_id, username, name, message_id, expires_at FROM captcha_challenge
+WHERE chat_id = ? AND user_id = ?
+            "#,
+        )
+        .bind(chat_id)
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(challenge)
+    }
+
+    async fn delete_captcha_challenge(
+        &self,
+        chat_id: i64,
+        user_id: i64,
+    ) -> anyhow::Result<Option<CaptchaChallenge>> {
+        let challenge = sqlx::query_as::<_, CaptchaChallenge>(
+            r#"
+DELETE FROM captcha_challenge
+WHERE chat_id = ? AND user_id = ?
+RETURNING chat_id, user_id, username, name, message_id, expires_at
+            "#,
+        )
+        .bind(chat_id)
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(challenge)
+    }
+
+    async fn claim_expired_captcha_challenges(
+        &self,
+        bot_id: &str,
+        now: DateTime<Utc>,
+        retry_at: DateTime<Utc>,
+    ) -> anyhow::Result<Vec<CaptchaChallenge>> {
+        let challenges = sqlx::query_as::<_, CaptchaChallenge>(
+            r#"
+UPDATE captcha_challenge
+SET expires_at = ?3
+WHERE julianday(expires_at) <= julianday(?2)
+AND chat_id IN ( SELECT id FROM tg_chat WHERE bot_id = ?1 )
+RETURNING chat_id, user_id, username, name, message_id, expires_at
+            "#,
+        )
+        .bind(bot_id)
+        .bind(now)
+        .bind(retry_at)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(challenges)
+    }
+
+    async fn add_invite_link(
+        &self,
+        chat_id: i64,
+        link: &ChatInviteLink,
+    ) -> anyhow::Result<InviteLink> {
+        let link = sqlx::query_as::<_, InviteLink>(&format!(
+            r#"
+INSERT INTO chat_invite_link ( chat_id, invite_link, name, expire_date, member_limit, creates_join_request, created_at )
+VALUES ( ?, ?, ?, ?, ?, ?, ? )
+RETURNING {INVITE_LINK_COLUMNS}
+            "#
+        ))
+        .bind(chat_id)
+        .bind(&link.invite_link)
+        .bind(&link.name)
+        .bind(link.expire_date)
+        .bind(link.member_limit)
+        .bind(link.creates_join_request)
+        .bind(Utc::now())
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(link)
+    }
+
+    async fn get_invite_links(&self, chat_id: i64) -> anyhow::Result<Vec<InviteLink>> {
+        let links = sqlx::query_as::<_, InviteLink>(&format!(
+            "SELECT {INVITE_LINK_COLUMNS} FROM chat_invite_link WHERE chat_id = ? ORDER BY id DESC"
+        ))
+        .bind(chat_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(links)
+    }
+
+    async fn get_invite_link(&self, chat_id: i64, id: i32) -> anyhow::Result<Option<InviteLink>> {
+        let link = sqlx::query_as::<_, InviteLink>(&format!(
+            "SELECT {INVITE_LINK_COLUMNS} FROM chat_invite_link WHERE chat_id = ? AND id = ?"
+        ))
+        .bind(chat_id)
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(link)
+    }
+
+    async fn revoke_invite_link(
+        &self,
+        chat_id: i64,
+        id: i32,
+    ) -> anyhow::Result<Option<InviteLink>> {
+        let link = sqlx::query_as::<_, InviteLink>(&format!(
+            r#"
+UPDATE chat_invite_link
+SET revoked_at = coalesce(revoked_at, ?3)
+WHERE chat_id = ?1 AND id = ?2
+RETURNING {INVITE_LINK_COLUMNS}
+            "#
+        ))
+        .bind(chat_id)
+        .bind(id)
+        .bind(Utc::now())
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(link)
+    }
+
+    async fn get_join_policy(&self, chat_id: i64) -> anyhow::Result<Option<JoinPolicy>> {
+        let policy: Option<String> =
+            sqlx::query_scalar("SELECT policy FROM chat_join_policy WHERE chat_id = ?")
+                .bind(chat_id)
+                .fetch_optional(&self.pool)
+                .await?;
+
+        policy.map(|policy| policy.parse()).transpose()
+    }
+
+    async fn set_join_policy(&self, chat_id: i64, policy: JoinPolicy) -> anyhow::Result<()> {
+        sqlx::query(
+            r#"
+INSERT INTO chat_join_policy ( chat_id, policy, updated_at )
+VALUES ( ?1, ?2, ?3 )
+ON CONFLICT (chat_id) DO UPDATE
+SET policy = ?2, updated_at = ?3
+            "#,
+        )
+        .bind(chat_id)
+        .bind(policy.to_string())
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn add_join_request(&self, request: NewJoinRequest<'_>) -> anyhow::Result<JoinRequest> {
+        let row = sqlx::query(&format!(
+            r#"
+INSERT INTO join_request ( chat_id, user_id, username, name, bio, invite_link, requested_at )
+VALUES ( ?, ?, ?, ?, ?, ?, ? )
+RETURNING {JOIN_REQUEST_COLUMNS}
+            "#
+        ))
+        .bind(request.chat_id)
+        .bind(request.user_id)
+        .bind(request.username)
+        .bind(request.name)
+        .bind(request.bio)
+        .bind(request.invite_link)
+        .bind(Utc::now())
+        .fetch_one(&self.pool)
+        .await?;
+
+        join_request(&row)
+    }
+
+    async fn get_join_requests(
+        &self,
+        chat_id: i64,
+        status: Option<JoinRequestStatus>,
+    ) -> anyhow::Result<Vec<JoinRequest>> {
+        sqlx::query(&format!(
+            r#"
+SELECT {JOIN_REQUEST_COLUMNS} FROM join_request
+WHERE chat_id = ?1 AND ( ?2 IS NULL OR status = ?2 )
+ORDER BY id DESC
+            "#
+        ))
+        .bind(chat_id)
+        .bind(status.map(|status| status.to_string()))
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(join_request)
+        .collect()
+    }
+
+    async fn get_join_request(&self, id: i32) -> anyhow::Result<Option<JoinRequest>> {
+        sqlx::query(&format!(
+            "SELECT {JOIN_REQUEST_COLUMNS} FROM join_request WHERE id = ?"
+        ))
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?
+        .as_ref()
+        .map(join_request)
+        .transpose()
+    }
+
+    async fn decide_join_request(
+        &self,
+        id: i32,
+        status: JoinRequestStatus,
+        decided_by: &str,
+    ) -> anyhow::Result<Option<JoinRequest>> {
+        sqlx::query(&format!(
+            r#"
+UPDATE join_request
+SET status = ?2, decided_at = ?3, decided_by = ?4
+WHERE id = ?1 AND status = 'pending'
+RETURNING {JOIN_REQUEST_COLUMNS}
+            "#
+        ))
+        .bind(id)
+        .bind(status.to_string())
+        .bind(Utc::now())
+        .bind(decided_by)
+        .fetch_optional(&self.pool)
+        .await?
+        .as_ref()
+        .map(join_request)
+        .transpose()
+    }
+
+    async fn add_exclusion(
+        &self,
+        bot_id: &str,
+        chat_id: Option<i64>,
+        user_id: i64,
+        note: Option<&str>,
+    ) -> anyhow::Result<Exclusion> {
+        let exclusion = sqlx::query_as::<_, Exclusion>(
+            r#"
+INSERT INTO exclusion ( bot_id, chat_id, user_id, note, created_at )
+VALUES ( ?1, ?2, ?3, ?4, ?5 )
+ON CONFLICT ( bot_id, COALESCE(chat_id, 0), user_id ) DO UPDATE
+SET note = ?4
+RETURNING id, chat_id, user_id, note, created_at
+            "#,
+        )
+        .bind(bot_id)
+        .bind(chat_id)
+        .bind(user_id)
+        .bind(note)
+        .bind(Utc::now())
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(exclusion)
+    }
+
+    async fn get_exclusions(&self, bot_id: &str) -> anyhow::Result<Vec<Exclusion>> {
+        let exclusions = sqlx::query_as::<_, Exclusion>(
+            r#"
+SELECT id, chat_id, user_id, note, created_at FROM exclusion
+WHERE bot_id = ?
+ORDER BY id
+            "#,
+        )
+        .bind(bot_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(exclusions)
+    }
+
+    async fn delete_exclusion(&self, bot_id: &str, id: i32) -> anyhow::Result<bool> {
+        let result = sqlx::query("DELETE FROM exclusion WHERE bot_id = ? AND id = ?")
+            .bind(bot_id)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn get_excluded_users(&self, bot_id: &str, chat_id: i64) -> anyhow::Result<Vec<i64>> {
+        let users = sqlx::query_scalar(
+            r#"
+SELECT DISTINCT user_id FROM exclusion
+WHERE bot_id = ?1 AND ( chat_id IS NULL OR chat_id = ?2 )
+            "#,
+        )
+        .bind(bot_id)
+        .bind(chat_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(users)
+    }
+
+    async fn add_subscriber(
+        &self,
+        bot_id: &str,
+        user_id: i64,
+        username: Option<&str>,
+        name: &str,
+    ) -> anyhow::Result<bool> {
+        let mut tx = self.pool.begin().await?;
+
+        let exists: bool = sqlx::query_scalar(
+            "SELECT EXISTS ( SELECT 1 FROM tg_subscriber WHERE bot_id = ? AND user_id = ? )",
+        )
+        .bind(bot_id)
+        .bind(user_id)
+        .fetch_one(&mut tx)
+        .await?;
+
+        sqlx::query(
+            r#"
+INSERT INTO tg_subscriber ( bot_id, user_id, username, name, subscribed_at )
+VALUES ( ?1, ?2, ?3, ?4, ?5 )
+ON CONFLICT ( bot_id, user_id ) DO UPDATE
+SET username = ?3, name = ?4
+            "#,
+        )
+        .bind(bot_id)
+        .bind(user_id)
+        .bind(username)
+        .bind(name)
+        .bind(Utc::now())
+        .execute(&mut tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(!exists)
+    }
+
+    async fn remove_subscriber(&self, bot_id: &str, user_id: i64) -> anyhow::Result<bool> {
+        let result = sqlx::query("DELETE FROM tg_subscriber WHERE bot_id = ? AND user_id = ?")
+            .bind(bot_id)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn get_subscribers(&self, bot_id: &str) -> anyhow::Result<Vec<i64>> {
+        let users = sqlx::query_scalar(
+            "SELECT user_id FROM tg_subscriber WHERE bot_id = ? ORDER BY user_id",
+        )
+        .bind(bot_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(users)
+    }
+
+    async fn queue_message(&self, message: NewQueuedMessage<'_>) -> anyhow::Result<i32> {
+        let all_chats = message.targets.is_none();
+        let targets = message.targets.unwrap_or_default();
+        let chats: Vec<i64> = targets.iter().map(|target| target.chat_id()).collect();
+        let options = message.options;
+        let (copy_from_chat_id, copy_message_id) = message.copy_from.unzip();
+
+        let mut tx = self.pool.begin().await?;
+
+        // sqlite lets one transaction write at a time, one that read before
+        // another queued the same text fails instead of inserting a duplicate
+        if let Some(since) = message.duplicates_since {
+            let chats = message.targets.map(|_| chats.as_slice());
+            if let Some(id) = find_duplicate_message(
+                &mut tx,
+                message.bot_id,
+                &text_hash(message.message),
+                since,
+                chats,
+                options.audience,
+            )
+            .await?
+            {
+                return Err(DuplicateBroadcast(id).into());
+            }
+        }
+
+        if let Some(draft_id) = options.draft_id {
+            let deleted = sqlx::query("DELETE FROM draft WHERE bot_id = ? AND id = ?")
+                .bind(message.bot_id)
+                .bind(draft_id)
+                .execute(&mut tx)
+                .await?;
+            if deleted.rows_affected() == 0 {
+                return Err(DraftGone(draft_id).into());
+            }
+        }
+
+        for media in message.media {
+            sqlx::query(
+                r#"
+            INSERT INTO media_blob ( hash, data, created_at )
+            VALUES ( ?1, ?2, ?3 )
+            ON CONFLICT (hash) DO UPDATE
+            SET created_at = ?3
+                "#,
+            )
+            .bind(&media.hash)
+            .bind(&media.data[..])
+            .bind(Utc::now())
+            .execute(&mut tx)
+            .await?;
+        }
+
+        let id: i32 = sqlx::query_scalar(
+            r#"
+        INSERT INTO message_queue ( chats, all_chats, message, images, datetime, parse_mode, pin, pin_silently, silent, disable_web_page_preview, protect_content, awaiting_approval, copy_from_chat_id, copy_message_id, bot_id, audience, text_hash, reply_to_queue_id, datetime_local, collect_replies, delete_after_seconds, channel_silent, channel_protect_content, request_id, created_at )
+        VALUES ( ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ? )
+        RETURNING id
+        "#,
+        )
+        .bind(serde_json::to_string(&chats)?)
+        .bind(all_chats)
+        .bind(message.message)
+        .bind(serde_json::to_string(message.images)?)
+        .bind(message.datetime)
+        .bind(options.parse_mode.to_string())
+        .bind(options.pin)
+        .bind(options.pin_silently)
+        .bind(options.silent)
+        .bind(options.disable_web_page_preview)
+        .bind(options.protect_content)
+        .bind(message.awaiting_approval)
+        .bind(copy_from_chat_id)
+        .bind(copy_message_id)
+        .bind(message.bot_id)
+        .bind(options.audience.to_string())
+        .bind(text_hash(message.message))
+        .bind(options.reply_to)
+        .bind(options.datetime_local)
+        .bind(options.collect_replies)
+        .bind(options.delete_after_seconds)
+        .bind(options.channel_silent)
+        .bind(options.channel_protect_content)
+        .bind(message.request_id)
+        .bind(Utc::now())
+        .fetch_one(&mut tx)
+        .await?;
+
+        for target in targets {
+            let Some(thread_id) = target.thread_id() else {
+                continue;
+            };
+            sqlx::query(
+                "INSERT OR REPLACE INTO message_thread ( message_id, chat_id, thread_id ) VALUES ( ?, ?, ? )",
+            )
+            .bind(id)
+            .bind(target.chat_id())
+            .bind(thread_id)
+            .execute(&mut tx)
+            .await?;
+        }
+
+        for chat_id in message.excluded_chats {
+            sqlx::query(
+                "INSERT OR IGNORE INTO message_excluded_chat ( message_id, chat_id ) VALUES ( ?, ? )",
+            )
+            .bind(id)
+            .bind(chat_id)
+            .execute(&mut tx)
+            .await?;
+        }
+
+        for (variant, content) in message.variants.iter().enumerate() {
+            sqlx::query(
+                "INSERT INTO message_variant ( message_id, variant, message, images, weight ) VALUES ( ?, ?, ?, ?, ? )",
+            )
+            .bind(id)
+            .bind(variant as i32)
+            .bind(&content.message)
+            .bind(serde_json::to_string(&content.images)?)
+            .bind(content.weight)
+            .execute(&mut tx)
+            .await?;
+        }
+
+        for (language, text) in message.translations {
+            sqlx::query(
+                "INSERT INTO message_translation ( message_id, language, message ) VALUES ( ?, ?, ? )",
+            )
+            .bind(id)
+            .bind(language)
+            .bind(text)
+            .execute(&mut tx)
+            .await?;
+        }
+
+        if !message.attachments.is_empty() {
+            let location = message.attachments.location.as_ref();
+            sqlx::query(
+                "INSERT INTO message_attachment ( message_id, sticker, latitude, longitude, venue_title, venue_address ) VALUES ( ?, ?, ?, ?, ?, ? )",
+            )
+            .bind(id)
+            .bind(&message.attachments.sticker)
+            .bind(location.map(|location| location.latitude))
+            .bind(location.map(|location| location.longitude))
+            .bind(location.and_then(|location| location.title.as_deref()))
+            .bind(location.and_then(|location| location.address.as_deref()))
+            .execute(&mut tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(id)
+    }
+
+    async fn get_queued_message(&self, id: i32) -> anyhow::Result<Option<QueuedMessage>> {
+        let row = sqlx::query(&format!(
+            "SELECT {QUEUED_MESSAGE_COLUMNS} FROM message_queue WHERE id = ?"
+        ))
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.as_ref().map(queued_message).transpose()
+    }
+
+    async fn find_duplicate_message(
+        &self,
+        bot_id: &str,
+        text_hash: &str,
+        since: DateTime<Utc>,
+        chats: Option<&[i64]>,
+        audience: Audience,
+    ) -> anyhow::Result<Option<i32>> {
+        let mut conn = self.pool.acquire().await?;
+        find_duplicate_message(&mut conn, bot_id, text_hash, since, chats, audience).await
+    }
+
+    async fn get_queue(
+        &self,
+        bot_id: &str,
+        filter: &QueueFilter,
+        limit: i64,
+        offset: i64,
+    ) -> anyhow::Result<Vec<QueueEntry>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT id, status, chats, all_chats, audience, message, datetime, datetime_local, attempts, completed_at
+            FROM (
+                SELECT *, CASE
+                    WHEN completed_at IS NOT NULL THEN 'completed'
+                    WHEN EXISTS ( SELECT 1 FROM failed_message WHERE queue_id = message_queue.id ) THEN 'failed'
+                    WHEN awaiting_approval THEN 'awaiting_approval'
+                    WHEN processing_at IS NOT NULL THEN 'processing'
+                    ELSE 'pending'
+                END AS status
+                FROM message_queue
+                WHERE bot_id = ?1
+            ) q
+            WHERE ( ?2 IS NULL OR status = ?2 )
+            AND ( ?3 IS NULL OR datetime < ?3 )
+            AND ( ?4 IS NULL OR all_chats OR EXISTS ( SELECT 1 FROM json_each(chats) WHERE value = ?4 ) )
+            ORDER BY datetime, id
+            LIMIT ?5 OFFSET ?6
+            "#,
+        )
+        .bind(bot_id)
+        .bind(filter.status.map(|status| status.to_string()))
+        .bind(filter.due_before)
+        .bind(filter.chat_id)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter()
+            .map(|row| {
+                Ok(QueueEntry {
+                    id: row.try_get("id")?,
+                    status: row.try_get::<&str, _>("status")?.parse()?,
+                    chats: serde_json::from_str(row.try_get("chats")?)?,
+                    all_chats: row.try_get("all_chats")?,
+                    audience: row.try_get("audience")?,
+                    message: row.try_get("message")?,
+                    datetime: row.try_get("datetime")?,
+                    datetime_local: row.try_get("datetime_local")?,
+                    attempts: row.try_get("attempts")?,
+                    completed_at: row.try_get("completed_at")?,
+                })
+            })
+            .collect()
+    }
+
+    async fn get_queue_depths(&self) -> anyhow::Result<Vec<(String, i64)>> {
+        let depths = sqlx::query_as(
+            r#"
+                SELECT bot_id, COUNT(*) FROM message_queue
+                WHERE completed_at IS NULL
+                AND NOT EXISTS ( SELECT 1 FROM failed_message WHERE queue_id = message_queue.id )
+                GROUP BY bot_id
+                ORDER BY bot_id
+                "#,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(depths)
+    }
+
+    async fn get_pending_messages(
+        &self,
+        bot_id: &str,
+        until: DateTime<Utc>,
+        limit: i64,
+    ) -> anyhow::Result<Vec<PendingMessage>> {
+        let pending: Vec<(i32, DateTime<Utc>)> = sqlx::query_as(
+            r#"
+                SELECT id, COALESCE(not_before, datetime) FROM message_queue
+                WHERE completed_at IS NULL AND NOT awaiting_approval AND bot_id = ?1
+                AND COALESCE(not_before, datetime) <= ?2
+                AND (processing_at IS NULL OR processing_at < ?3)
+                AND NOT EXISTS ( SELECT 1 FROM failed_message WHERE queue_id = message_queue.id )
+                ORDER BY COALESCE(not_before, datetime), id
+                LIMIT ?4
+                "#,
+        )
+        .bind(bot_id)
+        .bind(until)
+        .bind(Utc::now() - Duration::from_std(CLAIM_LEASE)?)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(pending
+            .into_iter()
+            .map(|(id, datetime)| PendingMessage { id, datetime })
+            .collect())
+    }
+
+    async fn claim_queued_message(&self, id: i32) -> anyhow::Result<Option<QueuedMessage>> {
+        // a single statement, sqlite serializes writers
+        let claimed = sqlx::query(
+            r#"
+                UPDATE message_queue
+                SET processing_at = ?2
+                WHERE id = ?1 AND completed_at IS NULL AND NOT awaiting_approval
+                AND (processing_at IS NULL OR processing_at < ?3)
+                AND NOT EXISTS ( SELECT 1 FROM failed_message WHERE queue_id = message_queue.id )
+                "#,
+        )
+        .bind(id)
+        .bind(Utc::now())
+        .bind(Utc::now() - Duration::from_std(CLAIM_LEASE)?)
+        .execute(&self.pool)
+        .await?;
+
+        if claimed.rows_affected() == 0 {
+            return Ok(None);
+        }
+
+        self.get_queued_message(id).await
+    }
+
+    async fn renew_claim(&self, id: i32) -> anyhow::Result<()> {
+        sqlx::query(
+            "UPDATE message_queue SET processing_at = ? WHERE id = ? AND processing_at IS NOT NULL",
+        )
+        .bind(Utc::now())
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn fail_queued_message(
+        &self,
+        id: i32,
+        error: &str,
+        max_attempts: i32,
+    ) -> anyhow::Result<bool> {
+        let mut tx = self.pool.begin().await?;
+
+        let attempts: Option<i32> = sqlx::query_scalar(
+            r#"
+            UPDATE message_queue
+            SET processing_at = NULL, attempts = attempts + 1
+            WHERE id = ?
+            RETURNING attempts
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&mut tx)
+        .await?;
+
+        let failed = matches!(attempts, Some(attempts) if attempts >= max_attempts);
+        if failed {
+            sqlx::query(
+                r#"
+            INSERT INTO failed_message ( queue_id, error, attempts, failed_at )
+            VALUES ( ?1, ?2, ?3, ?4 )
+            ON CONFLICT ( queue_id ) DO UPDATE
+            SET error = ?2, attempts = ?3, failed_at = ?4
+            "#,
+            )
+            .bind(id)
+            .bind(error)
+            .bind(attempts)
+            .bind(Utc::now())
+            .execute(&mut tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(failed)
+    }
+
+    async fn get_failed_messages(&self, bot_id: &str) -> anyhow::Result<Vec<FailedMessage>> {
+        let failed = sqlx::query_as::<_, FailedMessage>(
+            r#"
+            SELECT f.queue_id AS id, q.message, q.datetime, f.error, f.attempts, f.failed_at
+            FROM failed_message f
+            JOIN message_queue q ON q.id = f.queue_id
+            WHERE q.bot_id = ?
+            ORDER BY f.failed_at DESC
+            "#,
+        )
+        .bind(bot_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(failed)
+    }
+
+    async fn retry_failed_message(&self, bot_id: &str, id: i32) -> anyhow::Result<bool> {
+        let mut tx = self.pool.begin().await?;
+
+        let result = sqlx::query(
+            r#"
+            DELETE FROM failed_message
+            WHERE queue_id = ( SELECT id FROM message_queue WHERE id = ?1 AND bot_id = ?2 )
+            "#,
+        )
+        .bind(id)
+        .bind(bot_id)
+        .execute(&mut tx)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Ok(false);
+        }
+
+        sqlx::query("UPDATE message_queue SET attempts = 0 WHERE id = ?")
+            .bind(id)
+            .execute(&mut tx)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(true)
+    }
+
+    async fn complete_queued_message(&self, id: i32) -> anyhow::Result<()> {
+        sqlx::query("UPDATE message_queue SET completed_at = ? WHERE id = ?")
+            .bind(Utc::now())
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn defer_queued_message(&self, id: i32, until: DateTime<Utc>) -> anyhow::Result<()> {
+        sqlx::query("UPDATE message_queue SET not_before = ?, processing_at = NULL WHERE id = ?")
+            .bind(until)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn retry_failed_deliveries(
+        &self,
+        id: i32,
+        datetime: DateTime<Utc>,
+    ) -> anyhow::Result<Vec<i64>> {
+        let mut tx = self.pool.begin().await?;
+
+        let completed: Option<bool> =
+            sqlx::query_scalar("SELECT completed_at IS NOT NULL FROM message_queue WHERE id = ?")
+                .bind(id)
+                .fetch_optional(&mut tx)
+                .await?;
+        if completed != Some(true) {
+            return Ok(Vec::new());
+        }
+
+        let chats: Vec<i64> = sqlx::query_scalar(
+            "DELETE FROM message_delivery WHERE message_id = ? AND error IS NOT NULL RETURNING chat_id",
+        )
+        .bind(id)
+        .fetch_all(&mut tx)
+        .await?;
+        if !chats.is_empty() {
+            sqlx::query(
+                "UPDATE message_queue SET completed_at = NULL, processing_at = NULL, attempts = 0, datetime = ?, not_before = NULL WHERE id = ?",
+            )
+            .bind(datetime)
+            .bind(id)
+            .execute(&mut tx)
+            .await?;
+        }
+        tx.commit().await?;
+
+        Ok(chats)
+    }
+
+    async fn edit_queued_message(
+        &self,
+        id: i32,
+        message: &str,
+        datetime: DateTime<Utc>,
+        edited_by: &str,
+    ) -> anyhow::Result<bool> {
+        let mut tx = self.pool.begin().await?;
+
+        let current: Option<(String, DateTime<Utc>)> = sqlx::query_as(
+            r#"
+SELECT message, datetime FROM message_queue
+WHERE id = ? AND completed_at IS NULL AND processing_at IS NULL
+AND NOT EXISTS ( SELECT 1 FROM failed_message WHERE queue_id = message_queue.id )
+AND NOT EXISTS ( SELECT 1 FROM message_delivery WHERE message_id = message_queue.id )
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&mut tx)
+        .await?;
+        let Some((old_message, old_datetime)) = current else {
+            return Ok(false);
+        };
+
+        sqlx::query(
+            r#"
+INSERT INTO message_queue_history ( message_id, message, datetime, edited_by, edited_at )
+VALUES ( ?, ?, ?, ?, ? )
+            "#,
+        )
+        .bind(id)
+        .bind(old_message)
+        .bind(old_datetime)
+        .bind(edited_by)
+        .bind(Utc::now())
+        .execute(&mut tx)
+        .await?;
+
+        sqlx::query(
+            "UPDATE message_queue SET message = ?, datetime = ?, text_hash = ?, not_before = NULL WHERE id = ?",
+        )
+        .bind(message)
+        .bind(datetime)
+        .bind(text_hash(message))
+        .bind(id)
+        .execute(&mut tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(true)
+    }
+
+    async fn get_queued_message_history(
+        &self,
+        id: i32,
+    ) -> anyhow::Result<Vec<QueuedMessageVersion>> {
+        let versions = sqlx::query_as(
+            r#"
+SELECT message, datetime, edited_by, edited_at FROM message_queue_history
+WHERE message_id = ?
+ORDER BY edited_at DESC, id DESC
+            "#,
+        )
+        .bind(id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(versions)
+    }
+
+    async fn approve_queued_message(&self, id: i32) -> anyhow::Result<bool> {
+        let result = sqlx::query(
+            "UPDATE message_queue SET awaiting_approval = 0 WHERE id = ? AND awaiting_approval",
+        )
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn delete_queued_message(&self, id: i32) -> anyhow::Result<()> {
+        sqlx::query("DELETE FROM message_queue WHERE id = ?")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn resolve_all_chats(&self, bot_id: &str, id: i32) -> anyhow::Result<Vec<i64>> {
+        let mut tx = self.pool.begin().await?;
+
+        let chats: Vec<i64> = sqlx::query_scalar(
+            r#"
+SELECT id FROM tg_chat
+WHERE NOT muted AND approved AND NOT archived AND bot_id = ?
+AND id NOT IN ( SELECT chat_id FROM message_excluded_chat WHERE message_id = ? )
+ORDER BY id
+            "#,
+        )
+        .bind(bot_id)
+        .bind(id)
+        .fetch_all(&mut tx)
+        .await?;
+
+        sqlx::query("UPDATE message_queue SET chats = ? WHERE id = ?")
+            .bind(serde_json::to_string(&chats)?)
+            .bind(id)
+            .execute(&mut tx)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(chats)
+    }
+
+    async fn resolve_subscribers(&self, bot_id: &str, id: i32) -> anyhow::Result<Vec<i64>> {
+        let mut tx = self.pool.begin().await?;
+
+        let chats: Vec<i64> = sqlx::query_scalar(
+            r#"
+SELECT user_id FROM tg_subscriber
+WHERE bot_id = ?
+AND user_id NOT IN ( SELECT chat_id FROM message_excluded_chat WHERE message_id = ? )
+ORDER BY user_id
+            "#,
+        )
+        .bind(bot_id)
+        .bind(id)
+        .fetch_all(&mut tx)
+        .await?;
+
+        sqlx::query("UPDATE message_queue SET chats = ? WHERE id = ?")
+            .bind(serde_json::to_string(&chats)?)
+            .bind(id)
+            .execute(&mut tx)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(chats)
+    }
+
+    async fn get_excluded_chats(&self, message_id: i32) -> anyhow::Result<Vec<i64>> {
+        let chats = sqlx::query_scalar(
+            "SELECT chat_id FROM message_excluded_chat WHERE message_id = ? ORDER BY chat_id",
+        )
+        .bind(message_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(chats)
+    }
+
+    async fn get_message_images(&self, message_id: i32) -> anyhow::Result<Vec<Vec<u8>>> {
+        let images = sqlx::query_scalar(
+            "SELECT data FROM message_image WHERE message_id = ? ORDER BY position",
+        )
+        .bind(message_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(images)
+    }
+
+    async fn get_media_blob(&self, hash: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        let data = sqlx::query_scalar("SELECT data FROM media_blob WHERE hash = ?")
+            .bind(hash)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(data)
+    }
+
+    async fn delete_unused_media_blobs(&self, stored_before: DateTime<Utc>) -> anyhow::Result<u64> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM media_blob
+            WHERE ( created_at IS NULL OR created_at < ? )
+            AND NOT EXISTS (
+                SELECT 1 FROM message_queue, json_each(message_queue.images)
+                WHERE value = 'media:' || media_blob.hash
+            )
+            AND NOT EXISTS (
+                SELECT 1 FROM message_variant, json_each(message_variant.images)
+                WHERE value = 'media:' || media_blob.hash
+            )
+            "#,
+        )
+        .bind(stored_before)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn get_message_threads(&self, message_id: i32) -> anyhow::Result<HashMap<i64, i32>> {
+        let threads: Vec<(i64, i32)> =
+            sqlx::query_as("SELECT chat_id, thread_id FROM message_thread WHERE message_id = ?")
+                .bind(message_id)
+                .fetch_all(&self.pool)
+                .await?;
+
+        Ok(threads.into_iter().collect())
+    }
+
+    async fn get_delivered_chats(&self, message_id: i32) -> anyhow::Result<Vec<i64>> {
+        let chats = sqlx::query_scalar(
+            "SELECT chat_id FROM message_delivery WHERE message_id = ? AND deferred_until IS NULL",
+        )
+        .bind(message_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(chats)
+    }
+
+    async fn record_delivery(
+        &self,
+        message_id: i32,
+        chat_id: i64,
+        error: Option<String>,
+        pin_error: Option<String>,
+        request_id: Option<&str>,
+    ) -> anyhow::Result<()> {
+        sqlx::query(
+            r#"
+INSERT INTO message_delivery ( message_id, chat_id, error, pin_error, delivered_at, request_id )
+VALUES ( ?1, ?2, ?3, ?4, ?5, ?6 )
+ON CONFLICT ( message_id, chat_id ) DO UPDATE
+SET error = ?3, pin_error = ?4, delivered_at = ?5, request_id = ?6, deferred_until = NULL
+            "#,
+        )
+        .bind(message_id)
+        .bind(chat_id)
+        .bind(error)
+        .bind(pin_error)
+        .bind(Utc::now())
+        .bind(request_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_sink_deliveries(&self, message_id: i32) -> anyhow::Result<Vec<String>> {
+        let sinks = sqlx::query_scalar(
+            "SELECT sink FROM sink_delivery WHERE message_id = ? AND error IS NULL",
+        )
+        .bind(message_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(sinks)
+    }
+
+    async fn record_sink_delivery(
+        &self,
+        message_id: i32,
+        sink: &str,
+        error: Option<String>,
+    ) -> anyhow::Result<()> {
+        sqlx::query(
+            r#"
+INSERT INTO sink_delivery ( message_id, sink, error, delivered_at )
+VALUES ( ?1, ?2, ?3, ?4 )
+ON CONFLICT ( message_id, sink ) DO UPDATE
+SET error = ?3, delivered_at = ?4
+            "#,
+        )
+        .bind(message_id)
+        .bind(sink)
+        .bind(error)
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn defer_delivery(
+        &self,
+        message_id: i32,
+        chat_id: i64,
+        until: DateTime<Utc>,
+    ) -> anyhow::Result<()> {
+        sqlx::query(
+            r#"
+INSERT INTO message_delivery ( message_id, chat_id, delivered_at, deferred_until )
+VALUES ( ?1, ?2, ?3, ?4 )
+ON CONFLICT ( message_id, chat_id ) DO UPDATE
+SET delivered_at = ?3, deferred_until = ?4
+            "#,
+        )
+        .bind(message_id)
+        .bind(chat_id)
+        .bind(Utc::now())
+        .bind(until)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_broadcast_progress(
+        &self,
+        message_id: i32,
+    ) -> anyhow::Result<Option<BroadcastProgress>> {
+        let progress = sqlx::query_as::<_, BroadcastProgress>(
+            r#"
+SELECT
+    q.id,
+    json_array_length(q.chats) AS total,
+    count(d.chat_id) FILTER (WHERE d.error IS NULL AND d.deferred_until IS NULL) AS sent,
+    count(d.chat_id) FILTER (WHERE d.error IS NOT NULL) AS failed,
+    count(d.chat_id) FILTER (WHERE d.deferred_until IS NOT NULL) AS deferred,
+    count(d.chat_id) FILTER (WHERE d.pin_error IS NOT NULL) AS pin_failed,
+    q.completed_at IS NOT NULL AS completed,
+    min(d.delivered_at) FILTER (WHERE d.deferred_until IS NULL) AS first_delivered_at,
+    max(d.delivered_at) FILTER (WHERE d.deferred_until IS NULL) AS last_delivered_at
+FROM message_queue q
+LEFT JOIN message_delivery d ON d.message_id = q.id
+WHERE q.id = ?
+GROUP BY q.id
+            "#,
+        )
+        .bind(message_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(progress)
+    }
+
+    async fn get_deliveries(&self, message_id: i32) -> anyhow::Result<Vec<Delivery>> {
+        let deliveries = sqlx::query_as::<_, Delivery>(
+            r#"
+SELECT chat_id, error, pin_error, delivered_at, deferred_until, request_id FROM message_delivery
+WHERE message_id = ?
+ORDER BY delivered_at
+            "#,
+        )
+        .bind(message_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(deliveries)
+    }
+
+    async fn record_delivered_files(
+        &self,
+        message_id: i32,
+        files: &[DeliveredFile],
+    ) -> anyhow::Result<()> {
+        let mut tx = self.pool.begin().await?;
+        for file in files {
+            sqlx::query(
+                r#"
+INSERT INTO delivered_file ( message_id, chat_id, telegram_message_id, kind, file_id, file_unique_id, file_size, width, height )
+VALUES ( ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9 )
+ON CONFLICT ( message_id, chat_id, telegram_message_id ) DO UPDATE
+SET kind = ?4, file_id = ?5, file_unique_id = ?6, file_size = ?7, width = ?8, height = ?9
+                "#,
+            )
+            .bind(message_id)
+            .bind(file.chat_id)
+            .bind(file.telegram_message_id)
+            .bind(&file.kind)
+            .bind(&file.file_id)
+            .bind(&file.file_unique_id)
+            .bind(file.file_size)
+            .bind(file.width)
+            .bind(file.height)
+            .execute(&mut tx)
+            .await?;
+        }
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn get_delivered_files(
+        &self,
+        message_id: i32,
+        chat_id: Option<i64>,
+    ) -> anyhow::Result<Vec<DeliveredFile>> {
+        let files = sqlx::query_as::<_, DeliveredFile>(
+            r#"
+SELECT chat_id, telegram_message_id, kind, file_id, file_unique_id, file_size, width, height
+FROM delivered_file
+WHERE message_id = ?1 AND ( ?2 IS NULL OR chat_id = ?2 )
+ORDER BY chat_id, telegram_message_id
+            "#,
+        )
+        .bind(message_id)
+        .bind(chat_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(files)
+    }
+
+    async fn get_message_variants(&self, message_id: i32) -> anyhow::Result<Vec<MessageVariant>> {
+        let rows = sqlx::query(
+            "SELECT message, images, weight FROM message_variant WHERE message_id = ? ORDER BY variant",
+        )
+        .bind(message_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter()
+            .map(|row| {
+                Ok(MessageVariant {
+                    message: row.try_get("message")?,
+                    images: serde_json::from_str(row.try_get("images")?)?,
+                    weight: row.try_get("weight")?,
+                })
+            })
+            .collect()
+    }
+
+    async fn get_message_translations(&self, message_id: i32) -> anyhow::Result<Translations> {
+        let rows: Vec<(String, String)> = sqlx::query_as(
+            "SELECT language, message FROM message_translation WHERE message_id = ?",
+        )
+        .bind(message_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().collect())
+    }
+
+    async fn get_message_attachments(&self, message_id: i32) -> anyhow::Result<Attachments> {
+        type Row = (
+            Option<String>,
+            Option<f64>,
+            Option<f64>,
+            Option<String>,
+            Option<String>,
+        );
+        let row: Option<Row> = sqlx::query_as(
+            "SELECT sticker, latitude, longitude, venue_title, venue_address FROM message_attachment WHERE message_id = ?",
+        )
+        .bind(message_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map_or_else(
+            Attachments::default,
+            |(sticker, latitude, longitude, title, address)| {
+                Attachments::from_columns(sticker, latitude, longitude, title, address)
+            },
+        ))
+    }
+
+    async fn assign_variant(
+        &self,
+        message_id: i32,
+        chat_id: i64,
+        variant: i32,
+    ) -> anyhow::Result<()> {
+        sqlx::query(
+            "INSERT OR IGNORE INTO variant_assignment ( message_id, chat_id, variant, assigned_at ) VALUES ( ?, ?, ?, ? )",
+        )
+        .bind(message_id)
+        .bind(chat_id)
+        .bind(variant)
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_variant_results(&self, message_id: i32) -> anyhow::Result<Vec<VariantResult>> {
+        let results = sqlx::query_as::<_, VariantResult>(
+            r#"
+SELECT
+    v.variant,
+    v.message,
+    v.weight,
+    count(a.chat_id) AS chats,
+    count(d.chat_id) FILTER (WHERE d.error IS NULL AND d.deferred_until IS NULL) AS sent,
+    count(d.chat_id) FILTER (WHERE d.error IS NOT NULL) AS failed
+FROM message_variant v
+LEFT JOIN variant_assignment a ON a.message_id = v.message_id AND a.variant = v.variant
+LEFT JOIN message_delivery d ON d.message_id = a.message_id AND d.chat_id = a.chat_id
+WHERE v.message_id = ?
+GROUP BY v.variant, v.message, v.weight
+ORDER BY v.variant
+            "#,
+        )
+        .bind(message_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(results)
+    }
+
+    async fn add_webhook(
+        &self,
+        bot_id: &str,
+        url: &str,
+        secret: &str,
+        events: &[WebhookEvent],
+    ) -> anyhow::Result<Webhook> {
+        let row = sqlx::query(
+            r#"
+INSERT INTO webhook ( bot_id, url, events, secret, created_at )
+VALUES ( ?, ?, ?, ?, ? )
+RETURNING id, url, events, secret, created_at
+            "#,
+        )
+        .bind(bot_id)
+        .bind(url)
+        .bind(serde_json::to_string(events)?)
+        .bind(secret)
+        .bind(Utc::now())
+        .fetch_one(&self.pool)
+        .await?;
+
+        webhook(&row)
+    }
+
+    async fn get_webhooks(&self, bot_id: &str) -> anyhow::Result<Vec<Webhook>> {
+        sqlx::query(
+            "SELECT id, url, events, secret, created_at FROM webhook WHERE bot_id = ? ORDER BY id",
+        )
+        .bind(bot_id)
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(webhook)
+        .collect()
+    }
+
+    async fn delete_webhook(&self, bot_id: &str, id: i32) -> anyhow::Result<bool> {
+        let result = sqlx::query("DELETE FROM webhook WHERE bot_id = ? AND id = ?")
+            .bind(bot_id)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn add_draft(&self, bot_id: &str, content: &DraftContent) -> anyhow::Result<Draft> {
+        let now = Utc::now();
+        let row = sqlx::query(
+            r#"
+INSERT INTO draft ( bot_id, content, created_at, updated_at )
+VALUES ( ?, ?, ?, ? )
+RETURNING id, content, created_at, updated_at
+            "#,
+        )
+        .bind(bot_id)
+        .bind(serde_json::to_string(content)?)
+        .bind(now)
+        .bind(now)
+        .fetch_one(&self.pool)
+        .await?;
+
+        draft(&row)
+    }
+
+    async fn get_drafts(&self, bot_id: &str) -> anyhow::Result<Vec<Draft>> {
+        sqlx::query(
+            r#"
+SELECT id, content, created_at, updated_at FROM draft
+WHERE bot_id = ?
+ORDER BY updated_at DESC, id DESC
+            "#,
+        )
+        .bind(bot_id)
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(draft)
+        .collect()
+    }
+
+    async fn get_draft(&self, bot_id: &str, id: i32) -> anyhow::Result<Option<Draft>> {
+        sqlx::query(
+            "SELECT id, content, created_at, updated_at FROM draft WHERE bot_id = ? AND id = ?",
+        )
+        .bind(bot_id)
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?
+        .as_ref()
+        .map(draft)
+        .transpose()
+    }
+
+    async fn update_draft(
+        &self,
+        bot_id: &str,
+        id: i32,
+        content: &DraftContent,
+    ) -> anyhow::Result<Option<Draft>> {
+        sqlx::query(
+            r#"
+UPDATE draft SET content = ?, updated_at = ?
+WHERE bot_id = ? AND id = ?
+RETURNING id, content, created_at, updated_at
+            "#,
+        )
+        .bind(serde_json::to_string(content)?)
+        .bind(Utc::now())
+        .bind(bot_id)
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?
+        .as_ref()
+        .map(draft)
+        .transpose()
+    }
+
+    async fn delete_draft(&self, bot_id: &str, id: i32) -> anyhow::Result<bool> {
+        let result = sqlx::query("DELETE FROM draft WHERE bot_id = ? AND id = ?")
+            .bind(bot_id)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn start_audit(&self, entry: NewAuditEntry<'_>) -> anyhow::Result<i64> {
+        let id = sqlx::query_scalar(
+            r#"
+            INSERT INTO audit_log (bot_id, actor, method, endpoint, payload, request_id, created_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?)
+            RETURNING id
+            "#,
+        )
+        .bind(entry.bot_id)
+        .bind(entry.actor)
+        .bind(entry.method)
+        .bind(entry.endpoint)
+        .bind(entry.payload)
+        .bind(entry.request_id)
+        .bind(Utc::now())
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(id)
+    }
+
+    async fn finish_audit(&self, id: i64, status: i32) -> anyhow::Result<()> {
+        sqlx::query("UPDATE audit_log SET status = ? WHERE id = ?")
+            .bind(status)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn delete_audit_entries(
+        &self,
+        bot_id: &str,
+        before: DateTime<Utc>,
+    ) -> anyhow::Result<u64> {
+        let result = sqlx::query("DELETE FROM audit_log WHERE bot_id = ? AND created_at < ?")
+            .bind(bot_id)
+            .bind(before)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn get_audit_log(
+        &self,
+        bot_id: &str,
+        since: Option<DateTime<Utc>>,
+        actor: Option<&str>,
+        request_id: Option<&str>,
+        limit: i64,
+    ) -> anyhow::Result<Vec<AuditEntry>> {
+        let entries = sqlx::query_as::<_, AuditEntry>(
+            r#"
+            SELECT id, actor, method, endpoint, payload, status, created_at, request_id
+            FROM audit_log
+            WHERE bot_id = ?1
+                AND (?2 IS NULL OR created_at >= ?2)
+                AND (?3 IS NULL OR actor = ?3)
+                AND (?4 IS NULL OR request_id = ?4)
+            ORDER BY id DESC
+            LIMIT ?5
+            "#,
+        )
+        .bind(bot_id)
+        .bind(since)
+        .bind(actor)
+        .bind(request_id)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(entries)
+    }
+
+    async fn record_usage(
+        &self,
+        bot_id: &str,
+        actor: &str,
+        day: NaiveDate,
+        usage: &Usage,
+    ) -> anyhow::Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO api_usage (bot_id, actor, day, messages, target_chats, cleanups)
+            VALUES (?, ?, ?, ?, ?, ?)
+            ON CONFLICT (bot_id, actor, day) DO UPDATE SET
+                messages = api_usage.messages + excluded.messages,
+                target_chats = api_usage.target_chats + excluded.target_chats,
+                cleanups = api_usage.cleanups + excluded.cleanups
+            "#,
+        )
+        .bind(bot_id)
+        .bind(actor)
+        .bind(day)
+        .bind(usage.messages)
+        .bind(usage.target_chats)
+        .bind(usage.cleanups)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_usage(
+        &self,
+        bot_id: &str,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> anyhow::Result<Vec<UsageEntry>> {
+        let entries = sqlx::query_as::<_, UsageEntry>(
+            r#"
+            SELECT actor, day, messages, target_chats, cleanups
+            FROM api_usage
+            WHERE bot_id = ? AND day BETWEEN ? AND ?
+            ORDER BY day, actor
+            "#,
+        )
+        .bind(bot_id)
+        .bind(from)
+        .bind(to)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(entries)
+    }
+}
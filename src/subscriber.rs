@@ -0,0 +1,84 @@
+use std::{fmt, str::FromStr};
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup};
+use tracing::info;
+use utoipa::ToSchema;
+
+use crate::{broadcast::MessageOptions, state::AppState};
+
+/// `callback_data` of the button that unsubscribes whoever presses it.
+pub const UNSUBSCRIBE_CALLBACK: &str = "unsubscribe";
+
+/// Who a message without explicit targets goes to: every approved, unmuted
+/// chat of the bot or every user subscribed to it in a private chat.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Audience {
+    #[default]
+    Chats,
+    Subscribers,
+}
+
+impl fmt::Display for Audience {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Audience::Chats => "chats",
+            Audience::Subscribers => "subscribers",
+        })
+    }
+}
+
+impl FromStr for Audience {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "chats" => Ok(Audience::Chats),
+            "subscribers" => Ok(Audience::Subscribers),
+            other => Err(anyhow!("unknown audience: {other}")),
+        }
+    }
+}
+
+impl AppState {
+    /// Returns `false` when the user was already subscribed.
+    pub async fn subscribe(&self, user: &teloxide::types::User) -> anyhow::Result<bool> {
+        info!("subscribing user:{} to bot {}", user.id, self.bot_id);
+
+        self.storage
+            .add_subscriber(
+                &self.bot_id,
+                user.id.0 as i64,
+                user.username.as_deref(),
+                &user.full_name(),
+            )
+            .await
+    }
+
+    /// Returns `false` when the user was not subscribed.
+    pub async fn unsubscribe(&self, user_id: i64) -> anyhow::Result<bool> {
+        info!("unsubscribing user:{user_id} from bot {}", self.bot_id);
+
+        self.storage.remove_subscriber(&self.bot_id, user_id).await
+    }
+
+    /// The button under a message sent with `unsubscribe_button`, `None`
+    /// for other messages or unless a label is configured.
+    pub fn unsubscribe_markup(&self, options: &MessageOptions) -> Option<InlineKeyboardMarkup> {
+        if !options.unsubscribe_button {
+            return None;
+        }
+        let label = self.config.unsubscribe_button.as_deref()?;
+
+        Some(InlineKeyboardMarkup::new([[
+            InlineKeyboardButton::callback(label, UNSUBSCRIBE_CALLBACK),
+        ]]))
+    }
+
+    /// User ids of everyone a message to the subscribers would go to.
+    pub async fn get_subscribers(&self) -> anyhow::Result<Vec<i64>> {
+        self.storage.get_subscribers(&self.bot_id).await
+    }
+}
@@ -0,0 +1,102 @@
+use std::time::Duration;
+
+use anyhow::bail;
+use teloxide::{ApiError, RequestError};
+use tracing::error;
+
+/// What a failed Bot API request means for the chat it was made in, so
+/// callers don't have to match on telegram's error descriptions.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TelegramErrorClass {
+    /// The bot was kicked from the chat, or the user behind a private chat
+    /// blocked the bot.
+    Kicked,
+    /// The chat doesn't exist (any more).
+    NotFound,
+    /// The user the request is about deleted their account or never had
+    /// one. For a private chat that is the chat gone too.
+    UserGone,
+    /// The bot is in the chat but lacks the rights for the request.
+    Forbidden,
+    /// The group was upgraded to the supergroup with this id.
+    Migrated(i64),
+    /// Flood control, the request may be repeated after the duration.
+    RateLimited(Duration),
+    /// Network trouble, bad requests and everything else.
+    Other,
+}
+
+impl TelegramErrorClass {
+    pub fn of(err: &RequestError) -> Self {
+        match err {
+            RequestError::Api(err) => Self::of_api(err),
+            RequestError::MigrateToChatId(id) => TelegramErrorClass::Migrated(*id),
+            RequestError::RetryAfter(duration) => TelegramErrorClass::RateLimited(*duration),
+            _ => TelegramErrorClass::Other,
+        }
+    }
+
+    /// Classifies errors coming from telegram, [`Other`](Self::Other) for
+    /// the rest.
+    pub fn of_anyhow(err: &anyhow::Error) -> Self {
+        err.downcast_ref()
+            .map_or(TelegramErrorClass::Other, Self::of)
+    }
+
+    fn of_api(err: &ApiError) -> Self {
+        match err {
+            ApiError::BotKicked | ApiError::BotKickedFromSupergroup | ApiError::BotBlocked => {
+                TelegramErrorClass::Kicked
+            }
+            ApiError::ChatNotFound | ApiError::GroupDeactivated => TelegramErrorClass::NotFound,
+            ApiError::UserDeactivated | ApiError::UserNotFound => TelegramErrorClass::UserGone,
+            ApiError::CantInitiateConversation
+            | ApiError::CantTalkWithBots
+            | ApiError::NotEnoughRightsToPinMessage
+            | ApiError::NotEnoughRightsToManagePins
+            | ApiError::NotEnoughRightsToChangeChatPermissions
+            | ApiError::NotEnoughRightsToRestrict
+            | ApiError::NotEnoughRightsToPostMessages => TelegramErrorClass::Forbidden,
+            // teloxide only knows some of the descriptions, the rest keep
+            // telegram's "Forbidden: " prefix of 403 responses
+            ApiError::Unknown(description) => match description.strip_prefix("Forbidden: ") {
+                Some(reason)
+                    if reason.starts_with("bot was kicked")
+                        || reason.starts_with("bot is not a member") =>
+                {
+                    TelegramErrorClass::Kicked
+                }
+                Some(_) => TelegramErrorClass::Forbidden,
+                None => TelegramErrorClass::Other,
+            },
+            _ => TelegramErrorClass::Other,
+        }
+    }
+
+    /// The bot can't reach the chat any more, so it should stop tracking it.
+    pub fn is_gone(self) -> bool {
+        matches!(
+            self,
+            TelegramErrorClass::Kicked | TelegramErrorClass::NotFound
+        )
+    }
+}
+
+/// Handles a request about one member failing in a run over the members of
+/// a chat. The run goes on past the member, unless the bot lost the chat or
+/// its rights there and every other member would fail the same way.
+pub fn member_failed(
+    err: impl Into<anyhow::Error>,
+    action: &str,
+    chat_id: i64,
+    user_id: i64,
+) -> anyhow::Result<()> {
+    let err = err.into();
+    let class = TelegramErrorClass::of_anyhow(&err);
+    if class.is_gone() || class == TelegramErrorClass::Forbidden {
+        bail!("can't {action} members of chat:{chat_id}: {err}");
+    }
+    error!("failed to {action} member {user_id} of chat:{chat_id} {err}");
+
+    Ok(())
+}
@@ -0,0 +1,165 @@
+use axum::{
+    http::{HeaderName, Request},
+    middleware::Next,
+    response::Response,
+};
+use opentelemetry::{
+    sdk::{trace, Resource},
+    KeyValue,
+};
+use opentelemetry_otlp::WithExportConfig;
+use std::{future::Future, path::Path};
+
+use anyhow::Context;
+use tokio::task::JoinHandle;
+use tracing::{info_span, Instrument, Span, Subscriber};
+use tracing_appender::{
+    non_blocking::WorkerGuard,
+    rolling::{RollingFileAppender, Rotation},
+};
+use tracing_subscriber::{
+    fmt::MakeWriter, layer::SubscriberExt, registry::LookupSpan, util::SubscriberInitExt,
+    EnvFilter, Layer,
+};
+
+use crate::config::{Config, LogFormat, LogRotation};
+
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Sets up logging to stdout and the log file in the configured format and,
+/// when an OTLP endpoint is configured, span export to the collector. Lines
+/// still buffered for the log file are written when the guard is dropped.
+pub fn init(config: &Config) -> anyhow::Result<Option<WorkerGuard>> {
+    let otlp = match &config.otlp_endpoint {
+        Some(endpoint) => {
+            let tracer = opentelemetry_otlp::new_pipeline()
+                .tracing()
+                .with_exporter(
+                    opentelemetry_otlp::new_exporter()
+                        .tonic()
+                        .with_endpoint(endpoint),
+                )
+                .with_trace_config(trace::config().with_resource(Resource::new([KeyValue::new(
+                    "service.name",
+                    "telegram-sender",
+                )])))
+                .install_batch(opentelemetry::runtime::Tokio)?;
+            Some(tracing_opentelemetry::layer().with_tracer(tracer))
+        }
+        None => None,
+    };
+
+    let (file, guard) = match &config.log.file {
+        Some(path) => {
+            let directory = match path.parent() {
+                Some(directory) if !directory.as_os_str().is_empty() => directory,
+                _ => Path::new("."),
+            };
+            let prefix = path
+                .file_name()
+                .with_context(|| format!("bad LOG_FILE: {}", path.display()))?;
+            let rotation = match config.log.rotation {
+                LogRotation::Hourly => Rotation::HOURLY,
+                LogRotation::Daily => Rotation::DAILY,
+                LogRotation::Never => Rotation::NEVER,
+            };
+            let (writer, guard) = tracing_appender::non_blocking(RollingFileAppender::new(
+                rotation, directory, prefix,
+            ));
+            (
+                Some(fmt_layer(config.log.format, writer, false)),
+                Some(guard),
+            )
+        }
+        None => (None, None),
+    };
+
+    tracing_subscriber::registry()
+        .with(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")))
+        .with(fmt_layer(config.log.format, std::io::stdout, true))
+        .with(file)
+        .with(otlp)
+        .init();
+
+    Ok(guard)
+}
+
+fn fmt_layer<S, W>(format: LogFormat, writer: W, ansi: bool) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let layer = tracing_subscriber::fmt::layer()
+        .with_writer(writer)
+        .with_ansi(ansi);
+    match format {
+        LogFormat::Full => layer.boxed(),
+        LogFormat::Pretty => layer.pretty().boxed(),
+        LogFormat::Compact => layer.compact().boxed(),
+        // the fields of every enclosing span, like chat_id and queue_id
+        LogFormat::Json => layer.json().with_span_list(true).boxed(),
+    }
+}
+
+/// Flushes spans that were not exported yet.
+pub fn shutdown() {
+    opentelemetry::global::shutdown_tracer_provider();
+}
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+/// Id of the API request being handled by the current task, if any. Work
+/// started by a request, like broadcasts and cleanups, keeps its id.
+pub fn current_request_id() -> Option<String> {
+    REQUEST_ID
+        .try_with(Clone::clone)
+        .ok()
+        .filter(|id| !id.is_empty())
+}
+
+/// Runs `future` as part of the request `id`, so the kicks and deliveries it
+/// records are tied to it.
+pub async fn with_request_id<F: Future>(id: Option<String>, future: F) -> F::Output {
+    match id {
+        Some(id) => REQUEST_ID.scope(id, future).await,
+        None => future.await,
+    }
+}
+
+/// Spawns work that outlives the request starting it, logging under the
+/// span of the request and keeping its id.
+pub fn spawn_in_request<F>(future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    tokio::spawn(with_request_id(current_request_id(), future).instrument(Span::current()))
+}
+
+fn request_id<B>(request: &Request<B>) -> &str {
+    request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|id| id.to_str().ok())
+        .unwrap_or_default()
+}
+
+/// Span every API request runs in, so everything logged while handling it,
+/// down to the telegram calls, carries the request id.
+pub fn make_request_span<B>(request: &Request<B>) -> Span {
+    info_span!(
+        "request",
+        method = %request.method(),
+        path = %request.uri().path(),
+        request_id = request_id(request),
+    )
+}
+
+/// Makes the request id available to [`current_request_id`] while the
+/// request is handled.
+pub async fn scope_request_id<B>(request: Request<B>, next: Next<B>) -> Response {
+    let id = request_id(&request).to_string();
+    REQUEST_ID.scope(id, next.run(request)).await
+}
@@ -0,0 +1,89 @@
+use std::collections::HashMap;
+
+use axum::{
+    http::{HeaderMap, StatusCode},
+    response::IntoResponse,
+};
+
+use super::{state_with_config, test_state};
+use crate::{
+    access::Permission, config::ApiKeyConfig, error::ApiError, state::AppState,
+    subscriber::Audience, topic::ChatTarget,
+};
+
+fn with_key(key: &str) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert("x-api-key", key.parse().unwrap());
+    headers
+}
+
+async fn state_with_keys() -> AppState {
+    state_with_config(|config| {
+        config.api_keys = vec![
+            ApiKeyConfig {
+                name: "ops".to_string(),
+                key: "ops-key".to_string(),
+                chats: Vec::new(),
+                chat_groups: Vec::new(),
+                permissions: Permission::ALL.to_vec(),
+            },
+            ApiKeyConfig {
+                name: "marketing".to_string(),
+                key: "marketing-key".to_string(),
+                chats: vec![3],
+                chat_groups: vec!["marketing".to_string()],
+                permissions: vec![Permission::Send],
+            },
+        ];
+        config.chat_groups = HashMap::from([("marketing".to_string(), vec![1, 2])]);
+    })
+    .await
+}
+
+#[tokio::test]
+async fn api_keys_are_required_once_configured() {
+    let state = test_state().await;
+    assert!(state.access(&HeaderMap::new()).await.is_ok());
+
+    let state = state_with_keys().await;
+    let status = |result: Result<_, ApiError>| result.err().map(|err| err.into_response().status());
+    assert_eq!(
+        status(state.access(&HeaderMap::new()).await),
+        Some(StatusCode::UNAUTHORIZED)
+    );
+    assert_eq!(
+        status(state.access(&with_key("wrong")).await),
+        Some(StatusCode::UNAUTHORIZED)
+    );
+
+    let ops = state.access(&with_key("ops-key")).await.unwrap();
+    assert!(ops.require(Permission::Cleanup).is_ok());
+    assert!(ops.all_chats().is_ok());
+    assert!(ops.chat(42).is_ok());
+}
+
+#[tokio::test]
+async fn limited_keys_only_reach_their_chats() {
+    let state = state_with_keys().await;
+    let access = state.access(&with_key("marketing-key")).await.unwrap();
+
+    assert!(access.require(Permission::Send).is_ok());
+    assert!(access.require(Permission::Cleanup).is_err());
+    assert!(access.chats([1, 2, 3]).is_ok());
+    assert!(access.chat(4).is_err());
+    assert!(access.all_chats().is_err());
+
+    let targets = [
+        ChatTarget::Chat(1),
+        ChatTarget::Thread {
+            chat_id: 3,
+            thread_id: 7,
+        },
+    ];
+    assert!(access.targets(Some(&targets), Audience::Chats).is_ok());
+    assert!(access
+        .targets(Some(&[ChatTarget::Chat(4)]), Audience::Chats)
+        .is_err());
+    assert!(access.targets(None, Audience::Chats).is_err());
+    assert!(access.targets(None, Audience::Subscribers).is_err());
+}
@@ -0,0 +1,43 @@
+use std::time::{Duration, Instant};
+
+use super::{add_chat, eventually, mock_telegram::MockTelegram, state_with_config};
+use crate::alert::Alerts;
+
+#[test]
+fn alerts_are_deduplicated_and_capped() {
+    let alerts = Alerts::default();
+    let window = Duration::from_secs(60);
+    let start = Instant::now();
+
+    assert!(alerts.admit("database", window, 3, start));
+    assert!(!alerts.admit("database", window, 3, start + Duration::from_secs(30)));
+    assert!(alerts.admit("database", window, 3, start + Duration::from_secs(61)));
+    assert!(alerts.admit("kicked:1", window, 3, start + Duration::from_secs(62)));
+    // the fourth within the hour is held back, even with a new key
+    assert!(!alerts.admit("kicked:2", window, 3, start + Duration::from_secs(63)));
+    assert!(alerts.admit("kicked:2", window, 3, start + Duration::from_secs(3601)));
+}
+
+#[tokio::test]
+async fn a_kicked_bot_alerts_the_alerts_chat() {
+    let (mock, url) = MockTelegram::start();
+    let state = state_with_config(|config| {
+        config.telegram_api_url = Some(url);
+        config.alerts.chat_id = Some(-900);
+    })
+    .await;
+    add_chat(&state, 1).await;
+
+    state.archive_kicked_chat(1, "chat 1").await.unwrap();
+    state.archive_kicked_chat(1, "chat 1").await.unwrap();
+
+    // alerts are sent in the background
+    eventually(|| !mock.calls("sendMessage").is_empty()).await;
+    let sent = mock.calls("sendMessage");
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0]["chat_id"], -900);
+    assert!(sent[0]["text"]
+        .as_str()
+        .unwrap()
+        .contains("removed from chat chat 1 (1)"));
+}
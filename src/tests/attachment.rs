@@ -0,0 +1,114 @@
+use chrono::Utc;
+
+use super::{add_chat, mock_telegram::MockTelegram, state_with_telegram};
+use crate::{
+    attachment::{Attachments, Location},
+    broadcast::MessageOptions,
+    language::LocalizedMessage,
+    state::QueueMode,
+    topic::ChatTarget,
+    validation::Validator,
+};
+
+fn venue() -> Location {
+    Location {
+        latitude: 52.52,
+        longitude: 13.405,
+        title: Some("Meetup".to_string()),
+        address: Some("Alexanderplatz 1".to_string()),
+    }
+}
+
+#[test]
+fn locations_are_checked() {
+    let mut validator = Validator::default();
+    validator.attachments(&Attachments {
+        sticker: Some(" ".to_string()),
+        location: Some(Location {
+            latitude: 91.0,
+            longitude: -181.0,
+            title: Some("Meetup".to_string()),
+            address: None,
+        }),
+    });
+    let fields: Vec<_> = validator
+        .into_errors()
+        .into_iter()
+        .map(|error| error.field)
+        .collect();
+    assert_eq!(
+        fields,
+        [
+            "sticker",
+            "location.latitude",
+            "location.longitude",
+            "location"
+        ]
+    );
+}
+
+#[tokio::test]
+async fn stickers_and_venues_follow_the_text() {
+    let (mock, url) = MockTelegram::start();
+    let state = state_with_telegram(&url).await;
+    add_chat(&state, 1).await;
+
+    let id = state
+        .queue_message_with_images(
+            Some(vec![ChatTarget::Chat(1)]),
+            LocalizedMessage {
+                message: "see you there".to_string(),
+                attachments: Attachments {
+                    sticker: Some("CAACAgIAAxkBAAE".to_string()),
+                    location: Some(venue()),
+                },
+                ..Default::default()
+            },
+            Vec::new(),
+            Utc::now().to_rfc3339(),
+            MessageOptions::default(),
+            QueueMode::Send,
+        )
+        .await
+        .unwrap();
+    let message = state.get_queued_message(id).await.unwrap().unwrap();
+    state.broadcast(&message).await.unwrap();
+
+    assert_eq!(mock.calls("sendMessage").len(), 1);
+    // stickers go out as multipart, their params aren't recorded
+    assert_eq!(mock.calls("sendSticker").len(), 1);
+    let venues = mock.calls("sendVenue");
+    assert_eq!(venues.len(), 1);
+    assert_eq!(venues[0]["title"], "Meetup");
+    assert_eq!(venues[0]["latitude"], 52.52);
+
+    // a bare pin without any text
+    let id = state
+        .queue_message_with_images(
+            Some(vec![ChatTarget::Chat(1)]),
+            LocalizedMessage {
+                attachments: Attachments {
+                    sticker: None,
+                    location: Some(Location {
+                        title: None,
+                        address: None,
+                        ..venue()
+                    }),
+                },
+                ..Default::default()
+            },
+            Vec::new(),
+            Utc::now().to_rfc3339(),
+            MessageOptions::default(),
+            QueueMode::Send,
+        )
+        .await
+        .unwrap();
+    let message = state.get_queued_message(id).await.unwrap().unwrap();
+    state.broadcast(&message).await.unwrap();
+
+    assert_eq!(mock.calls("sendMessage").len(), 1);
+    assert_eq!(mock.calls("sendLocation").len(), 1);
+    let progress = state.get_broadcast_progress(id).await.unwrap().unwrap();
+    assert_eq!(progress.sent, 1);
+}
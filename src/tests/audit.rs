@@ -0,0 +1,135 @@
+use std::net::{SocketAddr, TcpListener};
+
+use axum::{
+    http::{header::CONTENT_TYPE, StatusCode},
+    middleware,
+    routing::post,
+    Extension, Router,
+};
+use chrono::{Duration, Utc};
+use serde_json::json;
+
+use super::test_state;
+use crate::audit::{audit, NewAuditEntry};
+
+#[tokio::test]
+async fn audit_log_filters_by_actor_and_time() {
+    let state = test_state().await;
+    let entry = |actor| NewAuditEntry {
+        bot_id: &state.bot_id,
+        actor,
+        method: "POST",
+        endpoint: "/chats/1/mute",
+        payload: None,
+        request_id: None,
+    };
+
+    let first = state.storage.start_audit(entry("api")).await.unwrap();
+    let second = state
+        .storage
+        .start_audit(entry("telegram:@admin"))
+        .await
+        .unwrap();
+    state.storage.finish_audit(first, 200).await.unwrap();
+
+    let entries = state.get_audit_log(None, None, None, 100).await.unwrap();
+    let ids: Vec<i64> = entries.iter().map(|entry| entry.id).collect();
+    assert_eq!(ids, vec![second, first]);
+    assert_eq!(entries[0].status, None);
+    assert_eq!(entries[1].status, Some(200));
+
+    let admin = state
+        .get_audit_log(None, Some("telegram:@admin"), None, 100)
+        .await
+        .unwrap();
+    assert_eq!(admin.len(), 1);
+    assert_eq!(admin[0].id, second);
+
+    let later = Utc::now() + Duration::minutes(1);
+    assert!(state
+        .get_audit_log(Some(later), None, None, 100)
+        .await
+        .unwrap()
+        .is_empty());
+    assert_eq!(
+        state
+            .get_audit_log(None, None, None, 1)
+            .await
+            .unwrap()
+            .len(),
+        1
+    );
+}
+
+#[tokio::test]
+async fn calls_are_recorded_with_their_result() {
+    let state = test_state().await;
+    let app = Router::new()
+        .route(
+            "/chats/:chat_id/mute",
+            post(|| async { StatusCode::NOT_FOUND }),
+        )
+        .route_layer(middleware::from_fn(audit))
+        .layer(Extension(state.clone()));
+    let listener = TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], 0))).unwrap();
+    let url = format!("http://{}/chats/1/mute", listener.local_addr().unwrap());
+    let server = axum::Server::from_tcp(listener)
+        .unwrap()
+        .serve(app.into_make_service());
+    tokio::spawn(server);
+
+    let response = reqwest::Client::new()
+        .post(url)
+        .header(CONTENT_TYPE, "application/json")
+        .body(json!({ "reason": "x".repeat(300), "muted": true }).to_string())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 404);
+
+    let entries = state.get_audit_log(None, None, None, 100).await.unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].actor, "api");
+    assert_eq!(entries[0].method, "POST");
+    assert_eq!(entries[0].endpoint, "/chats/1/mute");
+    assert_eq!(entries[0].status, Some(404));
+    // long strings are left out of the payload
+    let payload: serde_json::Value =
+        serde_json::from_str(entries[0].payload.as_deref().unwrap()).unwrap();
+    assert_eq!(payload, json!({ "reason": "<300 chars>", "muted": true }));
+}
+
+#[tokio::test]
+async fn old_entries_are_pruned() {
+    let state = test_state().await;
+    state
+        .storage
+        .start_audit(NewAuditEntry {
+            bot_id: &state.bot_id,
+            actor: "api",
+            method: "POST",
+            endpoint: "/chats/1/mute",
+            payload: None,
+            request_id: None,
+        })
+        .await
+        .unwrap();
+
+    state.delete_old_audit_entries(Utc::now()).await.unwrap();
+    assert_eq!(
+        state
+            .get_audit_log(None, None, None, 100)
+            .await
+            .unwrap()
+            .len(),
+        1
+    );
+
+    let later = Utc::now() + Duration::seconds(state.config.audit_retention as i64 + 60);
+    state.delete_old_audit_entries(later).await.unwrap();
+    assert!(state
+        .get_audit_log(None, None, None, 100)
+        .await
+        .unwrap()
+        .is_empty());
+}
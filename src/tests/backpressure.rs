@@ -0,0 +1,57 @@
+use std::net::{SocketAddr, TcpListener};
+
+use axum::{extract::Extension, middleware, routing::post, Router};
+
+use super::{add_chat, queue::queue, state_with_config};
+use crate::{backpressure::backpressure, topic::ChatTarget};
+
+#[tokio::test]
+async fn queueing_is_refused_for_large_bodies_and_a_full_queue() {
+    let state = state_with_config(|config| {
+        config.max_pending_messages = 1;
+        config.max_payload_bytes = 100;
+    })
+    .await;
+    add_chat(&state, 1).await;
+
+    let app = Router::new()
+        .route("/sendMessage/", post(|| async { "queued" }))
+        .route_layer(middleware::from_fn(backpressure))
+        .layer(Extension(state.clone()));
+    let listener = TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], 0))).unwrap();
+    let url = format!("http://{}/sendMessage/", listener.local_addr().unwrap());
+    let server = axum::Server::from_tcp(listener)
+        .unwrap()
+        .serve(app.into_make_service());
+    tokio::spawn(server);
+    let client = reqwest::Client::new();
+
+    let response = client.post(&url).body("x".repeat(50)).send().await.unwrap();
+    assert_eq!(response.status(), 200);
+    let response = client
+        .post(&url)
+        .body("x".repeat(101))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 413);
+    // without a length the body is counted as it arrives
+    let chunks: Vec<Result<_, std::io::Error>> = vec![Ok("x".repeat(60)), Ok("x".repeat(60))];
+    let response = client
+        .post(&url)
+        .body(reqwest::Body::wrap_stream(futures::stream::iter(chunks)))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 413);
+
+    queue(&state, Some(vec![ChatTarget::Chat(1)]), false).await;
+    assert_eq!(state.queue_depth().await.unwrap(), 1);
+    let response = client.post(&url).body("{}").send().await.unwrap();
+    assert_eq!(response.status(), 429);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["code"], "queue_full");
+
+    let metrics = state.queue_metrics().await.unwrap();
+    assert!(metrics.contains("telegram_sender_queue_depth{bot=\"default\"} 1"));
+}
@@ -0,0 +1,98 @@
+use serde_json::json;
+use teloxide::requests::Requester;
+
+use super::{
+    add_chat,
+    mock_telegram::MockTelegram,
+    queue::{pending, queue},
+    state_with_telegram, test_state,
+};
+use crate::{bot, config::Config};
+
+#[tokio::test]
+async fn bots_keep_their_chats_apart() {
+    let first = test_state().await;
+    let second = first.for_bot("second", bot::build(&first.config, "1:test").unwrap());
+    add_chat(&first, 1).await;
+    add_chat(&second, 2).await;
+
+    let ids = |chats: Vec<crate::state::Chat>| chats.iter().map(|chat| chat.id).collect::<Vec<_>>();
+    assert_eq!(ids(first.get_chats().await.unwrap()), vec![1]);
+    assert_eq!(ids(second.get_chats().await.unwrap()), vec![2]);
+
+    // a chat of the first bot is left untouched when the second one sees it
+    assert!(first
+        .storage
+        .upsert_chat(&second.bot_id, 1, "taken", true, "group", None)
+        .await
+        .unwrap()
+        .is_none());
+    assert_eq!(first.get_chats().await.unwrap()[0].name, "chat 1");
+
+    // nor can the second one change or delete it
+    assert!(!second.set_chat_muted(1, true).await.unwrap());
+    assert!(!second.rename_chat(1, "taken").await.unwrap());
+    assert!(!second.delete_chat(1).await.unwrap());
+    let chats = first.get_chats().await.unwrap();
+    assert_eq!((chats[0].name.as_str(), chats[0].muted), ("chat 1", false));
+}
+
+#[tokio::test]
+async fn a_second_bot_leaves_a_chat_that_is_taken() {
+    let (mock, url) = MockTelegram::start();
+    let first = state_with_telegram(&url).await;
+    let second = first.for_bot("second", bot::build(&first.config, "1:test").unwrap());
+    add_chat(&first, -100).await;
+
+    let chat = serde_json::from_value(json!({ "id": -100, "type": "supergroup", "title": "chat" }))
+        .unwrap();
+    assert!(!second.new_chat(&chat).await.unwrap());
+
+    assert_eq!(mock.calls("leaveChat")[0]["chat_id"], -100);
+    assert!(second.get_chats().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn bots_keep_their_queues_apart() {
+    let first = test_state().await;
+    let second = first.for_bot("second", bot::build(&first.config, "1:test").unwrap());
+    add_chat(&first, 1).await;
+    add_chat(&second, 2).await;
+
+    let id = queue(&first, None, false).await;
+
+    assert_eq!(pending(&first).await, vec![id]);
+    assert!(pending(&second).await.is_empty());
+    assert!(second.get_queued_message(id).await.unwrap().is_none());
+    assert!(!second.approve_queued_message(id).await.unwrap());
+    assert_eq!(
+        first
+            .storage
+            .resolve_all_chats(&first.bot_id, id)
+            .await
+            .unwrap(),
+        vec![1]
+    );
+}
+
+#[tokio::test]
+async fn telegram_is_reached_through_the_proxy() {
+    let (mock, proxy) = MockTelegram::start();
+    let config = Config {
+        // only reachable through the mock, which answers like telegram
+        telegram_api_url: Some("http://telegram.invalid".to_string()),
+        telegram_proxy_url: Some(proxy),
+        ..Default::default()
+    };
+
+    let bot = bot::build(&config, "0:test").unwrap();
+    let me = bot.get_me().await.unwrap();
+    assert_eq!(me.username(), "test_bot");
+    assert_eq!(mock.calls("getMe").len(), 1);
+
+    let config = Config {
+        telegram_proxy_url: Some("ftp://proxy.invalid".to_string()),
+        ..Default::default()
+    };
+    assert!(bot::build(&config, "0:test").is_err());
+}
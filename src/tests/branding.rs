@@ -0,0 +1,78 @@
+use std::collections::HashMap;
+
+use super::{add_chat, mock_telegram::MockTelegram, queue::queue, state_with_config};
+use crate::{branding::Branding, format::MessageFormat, topic::ChatTarget};
+
+fn branding(header: Option<&str>, footer: Option<&str>) -> Branding {
+    Branding {
+        header: header.map(str::to_string),
+        footer: footer.map(str::to_string),
+    }
+}
+
+#[test]
+fn branding_is_escaped_for_the_parse_mode() {
+    let news = branding(Some("<News>"), Some("see you."));
+
+    assert_eq!(
+        news.apply("*hello*", MessageFormat::MarkdownV2),
+        "<News\\>\n\n*hello*\n\nsee you\\."
+    );
+    assert_eq!(
+        news.apply("<b>hello</b>", MessageFormat::Html),
+        "&lt;News&gt;\n\n<b>hello</b>\n\nsee you."
+    );
+    assert!(branding(Some(" "), None).validate().is_err());
+}
+
+#[tokio::test]
+async fn broadcasts_get_the_branding_of_the_chat_or_its_group() {
+    let (mock, url) = MockTelegram::start();
+    let state = state_with_config(|config| {
+        config.telegram_api_url = Some(url);
+        config.chat_groups = HashMap::from([("news".to_string(), vec![1, 2])]);
+        config.chat_group_branding = HashMap::from([(
+            "news".to_string(),
+            branding(Some("News!"), Some("from the newsroom")),
+        )]);
+    })
+    .await;
+    for chat_id in 1..=3 {
+        add_chat(&state, chat_id).await;
+    }
+    // the chat's own footer wins over the group's, the header comes from it
+    state
+        .set_branding(1, &branding(None, Some("see you.")))
+        .await
+        .unwrap();
+
+    let id = queue(
+        &state,
+        Some(vec![
+            ChatTarget::Chat(1),
+            ChatTarget::Chat(2),
+            ChatTarget::Chat(3),
+        ]),
+        false,
+    )
+    .await;
+    let message = state.get_queued_message(id).await.unwrap().unwrap();
+    state.broadcast(&message).await.unwrap();
+
+    let texts: HashMap<_, _> = mock
+        .calls("sendMessage")
+        .into_iter()
+        .map(|call| (call["chat_id"].as_i64().unwrap(), call["text"].clone()))
+        .collect();
+    assert_eq!(texts[&1], "News\\!\n\nhello\n\nsee you\\.");
+    assert_eq!(texts[&2], "News\\!\n\nhello\n\nfrom the newsroom");
+    assert_eq!(texts[&3], "hello");
+
+    // an empty branding leaves the chat with the group's
+    state.set_branding(1, &Branding::default()).await.unwrap();
+    assert_eq!(state.get_branding(1).await.unwrap(), None);
+    assert_eq!(
+        state.get_brandings().await.unwrap()[&1],
+        branding(Some("News!"), Some("from the newsroom"))
+    );
+}
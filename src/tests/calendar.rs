@@ -0,0 +1,77 @@
+use chrono::{Duration, Utc};
+
+use super::{add_chat, test_state};
+use crate::{
+    broadcast::MessageOptions,
+    local_time::{local_due, parse_local_datetime},
+    state::QueueMode,
+    topic::ChatTarget,
+};
+
+#[tokio::test]
+async fn local_time_messages_occur_once_per_offset() {
+    let state = test_state().await;
+    add_chat(&state, 1).await;
+    add_chat(&state, 2).await;
+    add_chat(&state, 3).await;
+    state.set_chat_utc_offset(1, Some(180)).await.unwrap();
+
+    let now = Utc::now();
+    let plain = state
+        .queue_message_with_images(
+            Some(vec![ChatTarget::Chat(3)]),
+            "plain".to_string(),
+            Vec::new(),
+            (now + Duration::hours(2)).to_rfc3339(),
+            MessageOptions::default(),
+            QueueMode::Send,
+        )
+        .await
+        .unwrap();
+    let local = parse_local_datetime(
+        &(now + Duration::hours(5))
+            .format("%Y-%m-%dT%H:%M:%S")
+            .to_string(),
+    )
+    .unwrap();
+    let everyone = state
+        .queue_message_with_images(
+            None,
+            "x".repeat(300),
+            Vec::new(),
+            local_due(local, 180).to_rfc3339(),
+            MessageOptions {
+                datetime_local: Some(local),
+                ..Default::default()
+            },
+            QueueMode::Send,
+        )
+        .await
+        .unwrap();
+
+    let calendar = state
+        .get_calendar(now, now + Duration::days(1))
+        .await
+        .unwrap();
+    let occurrences: Vec<_> = calendar
+        .iter()
+        .map(|occurrence| (occurrence.id, occurrence.at, occurrence.chats.clone()))
+        .collect();
+    assert_eq!(
+        occurrences,
+        vec![
+            (everyone, local_due(local, 180), vec![1]),
+            (plain, (now + Duration::hours(2)), vec![3]),
+            (everyone, local_due(local, 0), vec![2, 3]),
+        ]
+    );
+    assert_eq!(calendar[0].preview.len(), 100);
+
+    // only the chats on UTC are still to come in three hours
+    let later = state
+        .get_calendar(now + Duration::hours(3), now + Duration::days(1))
+        .await
+        .unwrap();
+    assert_eq!(later.len(), 1);
+    assert_eq!(later[0].at, local_due(local, 0));
+}
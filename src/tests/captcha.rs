@@ -0,0 +1,181 @@
+use chrono::{Duration, Utc};
+use serde_json::json;
+use teloxide::types::CallbackQuery;
+
+use super::{
+    e2e::{message, user},
+    mock_telegram::MockTelegram,
+    state_with_telegram,
+};
+use crate::{
+    bot::{handle_callback_query, handle_message},
+    captcha::{parse_captcha_callback, Captcha, CaptchaChallenge},
+    state::AppState,
+};
+
+async fn setup() -> (MockTelegram, AppState) {
+    let (mock, url) = MockTelegram::start();
+    let state = state_with_telegram(&url).await;
+    handle_message(
+        message(-100, json!({ "from": user(1), "text": "hi" })),
+        state.bot.clone(),
+        state.clone(),
+    )
+    .await
+    .unwrap();
+    state
+        .set_captcha(-100, &Captcha { timeout_minutes: 5 })
+        .await
+        .unwrap();
+    (mock, state)
+}
+
+fn button_press(from: i64, message_id: i64) -> CallbackQuery {
+    serde_json::from_value(json!({
+        "id": "1",
+        "from": user(from),
+        "chat_instance": "1",
+        "data": format!("captcha:{}", 10),
+        "message": {
+            "message_id": message_id,
+            "date": 0,
+            "chat": { "id": -100, "type": "supergroup", "title": "chat" },
+            "text": "press the button",
+        },
+    }))
+    .unwrap()
+}
+
+#[test]
+fn captcha_settings_are_checked() {
+    assert_eq!(parse_captcha_callback("captcha:10"), Some(10));
+    assert_eq!(parse_captcha_callback("join:approve:10"), None);
+    assert!(Captcha { timeout_minutes: 0 }.validate().is_err());
+    assert!(Captcha {
+        timeout_minutes: 24 * 60 + 1
+    }
+    .validate()
+    .is_err());
+}
+
+#[tokio::test]
+async fn new_members_have_to_press_the_button() {
+    let (mock, state) = setup().await;
+    let mut bot = user(2);
+    bot["is_bot"] = json!(true);
+    handle_message(
+        message(
+            -100,
+            json!({ "from": user(10), "new_chat_members": [user(10), bot] }),
+        ),
+        state.bot.clone(),
+        state.clone(),
+    )
+    .await
+    .unwrap();
+
+    // bots are never challenged
+    let restricted = mock.calls("restrictChatMember");
+    assert_eq!(restricted.len(), 1);
+    assert_eq!(restricted[0]["user_id"], 10);
+    let challenges = state.get_captcha_challenges(-100).await.unwrap();
+    assert_eq!(challenges.len(), 1);
+    let sent = mock.calls("sendMessage");
+    assert_eq!(
+        sent[0]["reply_markup"]["inline_keyboard"][0][0]["callback_data"],
+        "captcha:10"
+    );
+    let message_id = challenges[0].message_id.unwrap().into();
+
+    handle_callback_query(
+        button_press(11, message_id),
+        state.bot.clone(),
+        state.clone(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(mock.calls("restrictChatMember").len(), 1);
+    handle_callback_query(
+        button_press(10, message_id),
+        state.bot.clone(),
+        state.clone(),
+    )
+    .await
+    .unwrap();
+
+    let answers: Vec<_> = mock
+        .calls("answerCallbackQuery")
+        .into_iter()
+        .map(|answer| answer["text"].clone())
+        .collect();
+    assert_eq!(
+        answers,
+        vec!["This button is not for you.", "Welcome, you can write now."]
+    );
+    let restricted = mock.calls("restrictChatMember");
+    assert_eq!(restricted.len(), 2);
+    assert!(restricted[1]["permissions"]
+        .as_object()
+        .unwrap()
+        .values()
+        .all(|allowed| allowed == true));
+    assert_eq!(
+        mock.calls("deleteMessage").last().unwrap()["message_id"],
+        message_id
+    );
+    assert!(state.get_captcha_challenges(-100).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn members_that_dont_press_the_button_are_kicked() {
+    let (mock, state) = setup().await;
+    for (user_id, expires_in) in [(10, -1), (11, 5)] {
+        state
+            .storage
+            .add_captcha_challenge(&CaptchaChallenge {
+                chat_id: -100,
+                user_id,
+                username: None,
+                name: format!("user {user_id}"),
+                message_id: Some(user_id as i32),
+                expires_at: Utc::now() + Duration::minutes(expires_in),
+            })
+            .await
+            .unwrap();
+    }
+
+    state.kick_unverified_members().await.unwrap();
+    let kicked = mock.calls("unbanChatMember");
+    assert_eq!(kicked.len(), 1);
+    assert_eq!(kicked[0]["user_id"], 10);
+    assert_eq!(mock.calls("deleteMessage")[0]["message_id"], 10);
+    let kicks = state.storage.get_kicks(-100, 10, 0).await.unwrap();
+    assert_eq!(kicks[0].reason, "captcha");
+    let pending = state.get_captcha_challenges(-100).await.unwrap();
+    assert_eq!(pending.len(), 1);
+    assert_eq!(pending[0].user_id, 11);
+}
+
+#[tokio::test]
+async fn members_that_cant_be_kicked_stay_challenged() {
+    let (mock, state) = setup().await;
+    state
+        .storage
+        .add_captcha_challenge(&CaptchaChallenge {
+            chat_id: -100,
+            user_id: 10,
+            username: None,
+            name: "user 10".to_string(),
+            message_id: Some(10),
+            expires_at: Utc::now() - Duration::minutes(1),
+        })
+        .await
+        .unwrap();
+    mock.flood_kicks(10, 1);
+
+    state.kick_unverified_members().await.unwrap();
+    let pending = state.get_captcha_challenges(-100).await.unwrap();
+    assert_eq!(pending.len(), 1);
+    assert!(pending[0].expires_at > Utc::now());
+    assert!(mock.calls("deleteMessage").is_empty());
+}
@@ -0,0 +1,93 @@
+use chrono::Utc;
+use serde_json::json;
+
+use super::{add_chat, e2e::message, mock_telegram::MockTelegram, state_with_telegram};
+use crate::{
+    bot::handle_channel_post,
+    broadcast::MessageOptions,
+    chat_list::ChatQuery,
+    state::{AppState, QueueMode},
+    topic::ChatTarget,
+};
+
+fn channel() -> serde_json::Value {
+    json!({ "id": -100, "type": "channel", "title": "news" })
+}
+
+fn channel_post(mut fields: serde_json::Value) -> teloxide::types::Message {
+    fields["chat"] = channel();
+    message(-100, fields)
+}
+
+/// A group and the mock answering for a channel.
+async fn setup() -> (MockTelegram, AppState) {
+    let (mock, url) = MockTelegram::start();
+    mock.set_chat(-100, channel());
+    let state = state_with_telegram(&url).await;
+    add_chat(&state, -200).await;
+    (mock, state)
+}
+
+#[tokio::test]
+async fn channel_posts_register_the_channel() {
+    let (_mock, state) = setup().await;
+
+    let post = channel_post(json!({ "text": "hi", "author_signature": "Ann" }));
+    handle_channel_post(post, state.clone()).await.unwrap();
+
+    let query = ChatQuery {
+        kind: Some("channel".to_string()),
+        ..Default::default()
+    };
+    let (channels, total) = state.query_chats(&query, i64::MAX, 0).await.unwrap();
+    assert_eq!(total, 1);
+    assert_eq!(channels[0].id, -100);
+    assert_eq!(channels[0].signs_messages, Some(true));
+
+    let post = channel_post(json!({ "text": "unsigned" }));
+    handle_channel_post(post, state.clone()).await.unwrap();
+    let (channels, _) = state.query_chats(&query, i64::MAX, 0).await.unwrap();
+    assert_eq!(channels[0].signs_messages, Some(false));
+}
+
+#[tokio::test]
+async fn channels_are_sent_with_their_own_options() {
+    let (mock, state) = setup().await;
+    let post = channel_post(json!({ "text": "hi" }));
+    handle_channel_post(post, state.clone()).await.unwrap();
+
+    let options = MessageOptions {
+        protect_content: true,
+        channel_silent: Some(true),
+        channel_protect_content: Some(false),
+        ..Default::default()
+    };
+    let id = state
+        .queue_message_with_images(
+            Some(vec![ChatTarget::Chat(-100), ChatTarget::Chat(-200)]),
+            "hello".to_string(),
+            Vec::new(),
+            Utc::now().to_rfc3339(),
+            options,
+            QueueMode::Send,
+        )
+        .await
+        .unwrap();
+    let message = state.get_queued_message(id).await.unwrap().unwrap();
+    state.broadcast(&message).await.unwrap();
+
+    let calls = mock.calls("sendMessage");
+    let sent = |chat_id: i64| {
+        calls
+            .iter()
+            .find(|call| call["chat_id"] == json!(chat_id))
+            .cloned()
+            .unwrap()
+    };
+    let channel = sent(-100);
+    assert_eq!(channel["disable_notification"], json!(true));
+    assert_ne!(channel["protect_content"], json!(true));
+    let group = sent(-200);
+    assert_ne!(group["disable_notification"], json!(true));
+    assert_eq!(group["protect_content"], json!(true));
+}
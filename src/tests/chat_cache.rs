@@ -0,0 +1,34 @@
+use super::{add_chat, e2e::user, mock_telegram::MockTelegram, state_with_telegram};
+
+#[tokio::test]
+async fn chats_are_asked_for_once_until_they_change() {
+    let (mock, url) = MockTelegram::start();
+    let state = state_with_telegram(&url).await;
+    add_chat(&state, -100).await;
+
+    for _ in 0..3 {
+        state.telegram_chat(-100).await.unwrap();
+    }
+    assert_eq!(mock.calls("getChat").len(), 1);
+
+    state.rename_chat(-100, "renamed").await.unwrap();
+    state.telegram_chat(-100).await.unwrap();
+    assert_eq!(mock.calls("getChat").len(), 2);
+
+    state.migrate_chat(-100, -200).await.unwrap();
+    assert!(!state.chat_cache.contains_key(&-100));
+}
+
+#[tokio::test]
+async fn the_bot_is_asked_about_itself_once() {
+    let (mock, url) = MockTelegram::start();
+    let state = state_with_telegram(&url).await;
+    add_chat(&state, -100).await;
+
+    for user_id in [10, 11] {
+        let member = serde_json::from_value(user(user_id)).unwrap();
+        state.new_chat_member(-100, &member).await.unwrap();
+    }
+
+    assert_eq!(mock.calls("getMe").len(), 1);
+}
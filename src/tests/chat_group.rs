@@ -0,0 +1,85 @@
+use std::collections::HashMap;
+
+use serde_json::json;
+
+use super::{
+    add_chat,
+    e2e::{message, user},
+    mock_telegram::MockTelegram,
+    state_with_config,
+};
+use crate::{
+    bot::{handle_command, Command},
+    state::AppState,
+};
+
+async fn setup() -> (MockTelegram, AppState) {
+    let (mock, url) = MockTelegram::start();
+    let state = state_with_config(|config| {
+        config.telegram_api_url = Some(url);
+        config.chat_groups = HashMap::from([
+            ("eu-announcements".to_string(), vec![1]),
+            ("us-announcements".to_string(), Vec::new()),
+        ]);
+    })
+    .await;
+    (mock, state)
+}
+
+async fn set_group(state: &AppState, chat_id: i64, group: &str) {
+    let command = message(
+        chat_id,
+        json!({ "from": user(1000), "text": format!("/setgroup {group}") }),
+    );
+    handle_command(
+        command,
+        state.bot.clone(),
+        state.clone(),
+        Command::SetGroup(group.to_string()),
+    )
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn admins_put_their_chat_into_a_group() {
+    let (mock, state) = setup().await;
+    mock.add_admin(1000);
+    add_chat(&state, -100).await;
+
+    set_group(&state, -100, "eu-announcements").await;
+    let groups = state.get_chat_groups().await.unwrap();
+    assert_eq!(groups["eu-announcements"], vec![1, -100]);
+
+    // joining another group leaves the first one
+    set_group(&state, -100, "us-announcements").await;
+    let groups = state.get_chat_groups().await.unwrap();
+    assert_eq!(groups["eu-announcements"], vec![1]);
+    assert_eq!(groups["us-announcements"], vec![-100]);
+
+    set_group(&state, -100, "").await;
+    let groups = state.get_chat_groups().await.unwrap();
+    assert!(groups["us-announcements"].is_empty());
+    assert_eq!(mock.calls("sendMessage").len(), 3);
+}
+
+#[tokio::test]
+async fn unknown_groups_are_refused() {
+    let (mock, state) = setup().await;
+    mock.add_admin(1000);
+    add_chat(&state, -100).await;
+
+    set_group(&state, -100, "marketing").await;
+
+    assert!(!state
+        .set_chat_group(-100, Some("marketing"), 1000)
+        .await
+        .unwrap());
+    let groups = state.get_chat_groups().await.unwrap();
+    assert!(!groups.contains_key("marketing"));
+    let replies = mock.calls("sendMessage");
+    assert!(replies[0]["text"]
+        .as_str()
+        .unwrap()
+        .contains("eu-announcements, us-announcements"));
+}
@@ -0,0 +1,88 @@
+use super::{add_chat, test_state};
+use crate::{
+    chat_list::{ChatQuery, ChatSort},
+    state::{AppState, ChatCleaningStatus, CleanupState},
+    storage::ChatMetadata,
+};
+
+async fn ids(state: &AppState, query: &ChatQuery, limit: i64, offset: i64) -> (Vec<i64>, i64) {
+    let (chats, total) = state.query_chats(query, limit, offset).await.unwrap();
+    (chats.iter().map(|chat| chat.id).collect(), total)
+}
+
+#[tokio::test]
+async fn chats_are_filtered_sorted_and_paged_by_the_storage() {
+    let state = test_state().await;
+    for (chat_id, name, member_count) in [(1, "Zeta", 5), (2, "alpha", 50), (3, "Beta team", 20)] {
+        add_chat(&state, chat_id).await;
+        state
+            .storage
+            .rename_chat(&state.bot_id, chat_id, name)
+            .await
+            .unwrap();
+        let metadata = ChatMetadata {
+            kind: "supergroup",
+            username: None,
+            member_count,
+            invite_link: None,
+            description: None,
+            photo_file_id: None,
+        };
+        state
+            .storage
+            .update_chat_metadata(&state.bot_id, chat_id, &metadata)
+            .await
+            .unwrap();
+    }
+    add_chat(&state, 4).await;
+    state
+        .storage
+        .set_chat_labels(&state.bot_id, 3, &["news".to_string()])
+        .await
+        .unwrap();
+    state
+        .storage
+        .set_chat_muted(&state.bot_id, 2, true)
+        .await
+        .unwrap();
+
+    let by_name = ChatQuery::default();
+    assert_eq!(
+        ids(&state, &by_name, i64::MAX, 0).await,
+        (vec![2, 3, 4, 1], 4)
+    );
+    assert_eq!(ids(&state, &by_name, 2, 2).await, (vec![4, 1], 4));
+
+    let by_members = ChatQuery {
+        sort: ChatSort::MemberCount,
+        descending: true,
+        ..Default::default()
+    };
+    // chats without a count come last either way
+    assert_eq!(ids(&state, &by_members, 3, 0).await, (vec![2, 3, 1], 4));
+
+    let search = ChatQuery {
+        search: Some("TA".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(ids(&state, &search, i64::MAX, 0).await, (vec![3, 1], 2));
+    let labelled = ChatQuery {
+        label: Some("news".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(ids(&state, &labelled, i64::MAX, 0).await, (vec![3], 1));
+    let unmuted = ChatQuery {
+        muted: Some(false),
+        only: Some(vec![1, 2, 3]),
+        ..Default::default()
+    };
+    assert_eq!(ids(&state, &unmuted, i64::MAX, 0).await, (vec![3, 1], 2));
+
+    state.chats_status.insert(1, ChatCleaningStatus::Queued);
+    let mut queued = ChatQuery::default();
+    state.restrict_to_state(&mut queued, CleanupState::Queued);
+    assert_eq!(ids(&state, &queued, i64::MAX, 0).await, (vec![1], 1));
+    let mut idle = ChatQuery::default();
+    state.restrict_to_state(&mut idle, CleanupState::Idle);
+    assert_eq!(ids(&state, &idle, i64::MAX, 0).await, (vec![2, 3, 4], 3));
+}
@@ -0,0 +1,46 @@
+use serde_json::json;
+
+use super::{add_chat, mock_telegram::MockTelegram, state_with_telegram};
+
+#[tokio::test]
+async fn metadata_sync_refreshes_stale_chats_only() {
+    let (mock, url) = MockTelegram::start();
+    let state = state_with_telegram(&url).await;
+    add_chat(&state, 1).await;
+    add_chat(&state, 2).await;
+    state.refresh_chat_metadata(2).await.unwrap();
+    mock.set_chat(
+        1,
+        json!({
+            "id": 1,
+            "type": "supergroup",
+            "title": "announcements",
+            "description": "news of the week",
+            "photo": {
+                "small_file_id": "small",
+                "small_file_unique_id": "small-unique",
+                "big_file_id": "big",
+                "big_file_unique_id": "big-unique",
+            },
+        }),
+    );
+
+    state.sync_chat_metadata_loop().await.unwrap();
+
+    // chat 2 was just refreshed
+    let asked: Vec<_> = mock
+        .calls("getChat")
+        .iter()
+        .map(|params| params["chat_id"].as_i64().unwrap())
+        .collect();
+    assert_eq!(asked, vec![2, 1]);
+    let chats = state.get_chats().await.unwrap();
+    let chat = chats.iter().find(|chat| chat.id == 1).unwrap();
+    assert_eq!(chat.name, "announcements");
+    assert_eq!(chat.description.as_deref(), Some("news of the week"));
+    assert_eq!(chat.photo_file_id.as_deref(), Some("big"));
+    assert!(chat.metadata_updated_at.is_some());
+    let renames = state.get_chat_renames(1).await.unwrap();
+    assert_eq!(renames.len(), 1);
+    assert_eq!(renames[0].old_name, "chat 1");
+}
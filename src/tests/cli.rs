@@ -0,0 +1,68 @@
+use clap::{CommandFactory, Parser};
+
+use super::{add_chat, mock_telegram::MockTelegram, state_with_telegram};
+use crate::{
+    cli::{execute, Cli},
+    state::AppState,
+};
+
+async fn run(state: &AppState, args: &[&str]) -> String {
+    let cli = Cli::try_parse_from(std::iter::once("telegram-sender").chain(args.iter().copied()))
+        .unwrap();
+    let mut out = Vec::new();
+    execute(state, cli.command.unwrap(), &mut out)
+        .await
+        .unwrap();
+
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn the_cli_is_well_formed() {
+    Cli::command().debug_assert();
+    // no subcommand runs the server
+    assert!(Cli::try_parse_from(["telegram-sender"])
+        .unwrap()
+        .command
+        .is_none());
+    assert!(Cli::try_parse_from(["telegram-sender", "send", "--parse-mode", "bold"]).is_err());
+}
+
+#[tokio::test]
+async fn subcommands_send_list_and_flush() {
+    let (mock, url) = MockTelegram::start();
+    let state = state_with_telegram(&url).await;
+    for chat_id in [-1, -2, -3] {
+        add_chat(&state, chat_id).await;
+    }
+
+    let out = run(
+        &state,
+        &[
+            "send",
+            "--chats",
+            "-1,-2",
+            "--message",
+            "<b>hi</b>",
+            "--parse-mode",
+            "html",
+        ],
+    )
+    .await;
+    assert!(out.starts_with("queued message "));
+    let sent = mock.calls("sendMessage");
+    assert_eq!(sent.len(), 2);
+    assert_eq!(sent[0]["parse_mode"], "HTML");
+
+    // scheduled messages wait for the queue
+    run(
+        &state,
+        &["send", "--message", "later", "--at", "2999-01-01T00:00:00Z"],
+    )
+    .await;
+    run(&state, &["queue", "flush"]).await;
+    assert_eq!(mock.calls("sendMessage").len(), 2);
+
+    let out = run(&state, &["chats", "list", "--search", "chat -2"]).await;
+    assert_eq!(out, "-2\tsupergroup\t-\tchat -2\n");
+}
@@ -0,0 +1,169 @@
+use axum::http::header::CONTENT_TYPE;
+use chrono::Utc;
+use serde_json::{json, Value};
+
+use super::{add_chat, serve_api, test_state};
+use crate::{
+    convert::{convert, SourceFormat},
+    format::MessageFormat,
+};
+
+#[test]
+fn google_docs_html_becomes_markdown() {
+    let html = r#"<meta charset="utf-8"><b style="font-weight:normal;" id="docs-internal-guid-1"><p dir="ltr"><span style="font-weight:700;">Maintenance</span><span style="font-weight:400;"> on 1.2. from 10:00</span></p><ul><li><span style="font-style:italic;">api</span></li><li><a href="https://status.example.com/a_(b)"><span style="text-decoration:underline;">status page</span></a></li></ul></b><br>"#;
+
+    let text = convert(html, SourceFormat::Auto, MessageFormat::MarkdownV2).unwrap();
+    assert_eq!(
+        text,
+        "*Maintenance* on 1\\.2\\. from 10:00\n\n• _api_\n• [status page](https://status.example.com/a_(b\\))"
+    );
+    MessageFormat::MarkdownV2.validate(&text).unwrap();
+}
+
+#[test]
+fn commonmark_becomes_html() {
+    let markdown = "# Release\n\nNow with **bold** & `a<b`, see [the notes](https://example.com/?a=1&b=\"2\").\n\n1. one\n2. two\n\n> quoted\n> lines\n\n```rust\nlet a = 1;\n```";
+
+    let text = convert(markdown, SourceFormat::Auto, MessageFormat::Html).unwrap();
+    assert_eq!(
+        text,
+        "<b>Release</b>\n\nNow with <b>bold</b> &amp; <code>a&lt;b</code>, see <a href=\"https://example.com/?a=1&amp;b=&quot;2&quot;\">the notes</a>.\n\n1. one\n2. two\n\n<blockquote>quoted\nlines</blockquote>\n\n<pre><code class=\"language-rust\">let a = 1;</code></pre>"
+    );
+    MessageFormat::Html.validate(&text).unwrap();
+
+    let text = convert(
+        "> a\n> b\n\nc\\_d",
+        SourceFormat::CommonMark,
+        MessageFormat::MarkdownV2,
+    )
+    .unwrap();
+    assert_eq!(text, ">a\n>b\n\nc\\_d");
+    MessageFormat::MarkdownV2.validate(&text).unwrap();
+}
+
+#[test]
+fn what_telegram_cant_show_is_refused() {
+    let table = convert(
+        "<table><tr><td>a</td></tr></table>",
+        SourceFormat::Auto,
+        MessageFormat::Html,
+    );
+    assert!(table
+        .unwrap_err()
+        .to_string()
+        .contains("write its rows as lines"));
+    let image = convert(
+        "![logo](https://example.com/logo.png)",
+        SourceFormat::Auto,
+        MessageFormat::Html,
+    );
+    assert!(image
+        .unwrap_err()
+        .to_string()
+        .contains("send it in `images`"));
+    let nested = convert(
+        "<code><b>a</b></code>",
+        SourceFormat::Html,
+        MessageFormat::Html,
+    );
+    assert!(nested
+        .unwrap_err()
+        .to_string()
+        .contains("formatting inside code"));
+    let unknown = convert(
+        "<marquee>a</marquee>",
+        SourceFormat::Html,
+        MessageFormat::Html,
+    );
+    assert_eq!(
+        unknown.unwrap_err().to_string(),
+        "unsupported tag <marquee> at offset 0"
+    );
+}
+
+#[tokio::test]
+async fn the_api_converts_texts() {
+    let state = test_state().await;
+    add_chat(&state, 1).await;
+    let url = serve_api(&state);
+    let client = reqwest::Client::new();
+    let post = |path: &str, body: Value| {
+        client
+            .post(format!("{url}{path}"))
+            .header(CONTENT_TYPE, "application/json")
+            .body(body.to_string())
+            .send()
+    };
+
+    let response = post(
+        "/convert",
+        json!({ "text": "<b>hi</b> & bye", "to": "html" }),
+    )
+    .await
+    .unwrap();
+    assert_eq!(response.status(), 200);
+    let converted: Value = response.json().await.unwrap();
+    assert_eq!(converted["text"], "<b>hi</b> &amp; bye");
+    assert_eq!(converted["from"], "html");
+    let response = post("/convert", json!({ "text": "<table></table>" }))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+
+    let response = post(
+        "/sendMessage/",
+        json!({
+            "chats": [1],
+            "message": "**big** news",
+            "translations": { "de": "*große* Neuigkeiten" },
+            "images": [],
+            "datetime": Utc::now().to_rfc3339(),
+            "convert_from": "commonmark",
+        }),
+    )
+    .await
+    .unwrap();
+    assert_eq!(response.status(), 200);
+    let queued: Value = response.json().await.unwrap();
+    let id = queued["id"].as_i64().unwrap() as i32;
+    let message = state.get_queued_message(id).await.unwrap().unwrap();
+    assert_eq!(message.message, "*big* news");
+
+    let response = post(
+        "/sendVariants/",
+        json!({
+            "chats": [1],
+            "variants": [
+                { "message": "**a**" },
+                { "message": "![b](https://example.com/b.png)" },
+            ],
+            "datetime": Utc::now().to_rfc3339(),
+            "convert_from": "commonmark",
+        }),
+    )
+    .await
+    .unwrap();
+    assert_eq!(response.status(), 422);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["code"], "validation_failed");
+    assert_eq!(body["errors"][0]["field"], "variants[1].message");
+
+    let response = post(
+        "/sendMessage/",
+        json!({
+            "chats": [1],
+            "message": "fine",
+            "translations": { "de": "<table></table>" },
+            "images": [],
+            "datetime": Utc::now().to_rfc3339(),
+            "convert_from": "html",
+        }),
+    )
+    .await
+    .unwrap();
+    assert_eq!(response.status(), 422);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["errors"].as_array().unwrap().len(), 1);
+    assert_eq!(body["errors"][0]["field"], "translations.de");
+    assert_eq!(state.queue_depth().await.unwrap(), 1);
+}
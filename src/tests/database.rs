@@ -0,0 +1,114 @@
+use std::{borrow::Cow, error::Error, fmt, time::Instant};
+
+use axum::response::IntoResponse;
+use sqlx::error::DatabaseError;
+
+use crate::{
+    config::DatabasePoolConfig,
+    error::ApiError,
+    storage::{retry_transaction, SqliteStorage},
+};
+
+/// A postgres error with just a SQLSTATE.
+#[derive(Debug)]
+struct PgError(&'static str);
+
+impl fmt::Display for PgError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "sqlstate {}", self.0)
+    }
+}
+
+impl Error for PgError {}
+
+impl DatabaseError for PgError {
+    fn message(&self) -> &str {
+        self.0
+    }
+
+    fn code(&self) -> Option<Cow<'_, str>> {
+        Some(Cow::Borrowed(self.0))
+    }
+
+    fn as_error(&self) -> &(dyn Error + Send + Sync + 'static) {
+        self
+    }
+
+    fn as_error_mut(&mut self) -> &mut (dyn Error + Send + Sync + 'static) {
+        self
+    }
+
+    fn into_error(self: Box<Self>) -> Box<dyn Error + Send + Sync + 'static> {
+        self
+    }
+}
+
+fn pg_error(code: &'static str) -> anyhow::Error {
+    sqlx::Error::Database(Box::new(PgError(code))).into()
+}
+
+#[tokio::test]
+async fn connecting_gives_up_after_the_configured_attempts() {
+    let config = DatabasePoolConfig {
+        connect_attempts: 2,
+        ..Default::default()
+    };
+
+    let started = Instant::now();
+    let err = SqliteStorage::connect("sqlite:/nonexistent/sender.db", 1, &config)
+        .await
+        .err()
+        .expect("the directory doesn't exist");
+
+    assert!(err.to_string().contains("after 2 attempts"), "{err}");
+    // one delay between the two attempts
+    assert!(started.elapsed().as_secs_f64() >= 1.0);
+}
+
+#[tokio::test]
+async fn conflicting_transactions_are_run_again() {
+    let mut attempts = 0;
+    let result = retry_transaction(|| {
+        attempts += 1;
+        let attempt = attempts;
+        async move {
+            match attempt {
+                1 => Err(pg_error("40001")),
+                2 => Err(pg_error("40P01")),
+                _ => Ok(attempt),
+            }
+        }
+    })
+    .await;
+    assert_eq!(result.unwrap(), 3);
+
+    // other errors and conflicts that keep coming are given up on
+    let mut attempts = 0;
+    let result: anyhow::Result<()> = retry_transaction(|| {
+        attempts += 1;
+        async { Err(pg_error("23505")) }
+    })
+    .await;
+    assert!(result.is_err());
+    assert_eq!(attempts, 1);
+
+    let mut attempts = 0;
+    let result: anyhow::Result<()> = retry_transaction(|| {
+        attempts += 1;
+        async { Err(pg_error("40001")) }
+    })
+    .await;
+    assert!(result.is_err());
+    assert_eq!(attempts, 4);
+}
+
+#[tokio::test]
+async fn database_errors_are_not_shown_to_callers() {
+    let response = ApiError::from(pg_error("23505")).into_response();
+    assert_eq!(response.status(), 500);
+
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(body["code"], "database_error");
+    assert!(!body["message"].as_str().unwrap().contains("sqlstate"));
+}
@@ -0,0 +1,102 @@
+use chrono::Utc;
+use serde_json::json;
+
+use super::{add_chat, test_state};
+use crate::{
+    broadcast::MessageOptions,
+    draft::{DraftContent, DraftGone},
+    duplicate::DuplicateBroadcast,
+    state::QueueMode,
+    topic::ChatTarget,
+};
+
+#[tokio::test]
+async fn drafts_keep_the_payload_until_discarded() {
+    let state = test_state().await;
+    let content: DraftContent = serde_json::from_value(json!({
+        "chats": [1, {"chat_id": 2, "thread_id": 7}],
+        "message": "work in *progress*",
+        "pin": true,
+    }))
+    .unwrap();
+
+    let draft = state.add_draft(&content).await.unwrap();
+    let saved = serde_json::to_value(&draft).unwrap();
+    assert_eq!(saved["chats"], json!([1, {"chat_id": 2, "thread_id": 7}]));
+    assert_eq!(saved["message"], "work in *progress*");
+    assert_eq!(saved["pin"], true);
+    assert_eq!(saved["datetime"], json!(null));
+
+    let mut content = draft.content.clone();
+    content.message = "done".to_string();
+    let updated = state
+        .update_draft(draft.id, &content)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(updated.content.message, "done");
+    assert!(updated.updated_at >= draft.updated_at);
+    assert_eq!(state.get_drafts().await.unwrap().len(), 1);
+
+    assert!(state.delete_draft(draft.id).await.unwrap());
+    assert!(state.get_draft(draft.id).await.unwrap().is_none());
+    assert!(state
+        .update_draft(draft.id, &content)
+        .await
+        .unwrap()
+        .is_none());
+    assert!(!state.delete_draft(draft.id).await.unwrap());
+}
+
+#[tokio::test]
+async fn sent_drafts_are_queued_once() {
+    let state = test_state().await;
+    add_chat(&state, 1).await;
+    let content = DraftContent {
+        chats: vec![ChatTarget::Chat(1)],
+        message: "ready".to_string(),
+        ..Default::default()
+    };
+    let draft = state.add_draft(&content).await.unwrap();
+    let options = MessageOptions {
+        draft_id: Some(draft.id),
+        ..Default::default()
+    };
+    let queue = |mode| {
+        state.queue_message_with_images(
+            Some(vec![ChatTarget::Chat(1)]),
+            "ready".to_string(),
+            Vec::new(),
+            Utc::now().to_rfc3339(),
+            options,
+            mode,
+        )
+    };
+
+    queue(QueueMode::Send).await.unwrap();
+    assert!(state.get_draft(draft.id).await.unwrap().is_none());
+
+    let err = queue(QueueMode::Send).await.unwrap_err();
+    assert_eq!(err.downcast_ref::<DraftGone>().unwrap().0, draft.id);
+    assert_eq!(state.queue_depth().await.unwrap(), 1);
+
+    // a message that isn't queued keeps its draft
+    let draft = state.add_draft(&content).await.unwrap();
+    let options = MessageOptions {
+        draft_id: Some(draft.id),
+        ..Default::default()
+    };
+    let err = state
+        .queue_message_with_images(
+            Some(vec![ChatTarget::Chat(1)]),
+            "ready".to_string(),
+            Vec::new(),
+            Utc::now().to_rfc3339(),
+            options,
+            QueueMode::SendOnce,
+        )
+        .await
+        .unwrap_err();
+    assert!(err.downcast_ref::<DuplicateBroadcast>().is_some());
+    assert!(state.get_draft(draft.id).await.unwrap().is_some());
+}
@@ -0,0 +1,908 @@
+//! The bot handlers and the send paths against [`MockTelegram`].
+
+use base64::Engine;
+use chrono::{Duration, Utc};
+use serde_json::{json, Value};
+use teloxide::types::{ChatMemberUpdated, Message};
+
+use super::{
+    add_chat, add_member, member_ids, mock_telegram::MockTelegram, png, state_with_config,
+    state_with_telegram,
+};
+use crate::{
+    bot::{handle_chat_member, handle_command, handle_message, handle_my_chat_member, Command},
+    broadcast::MessageOptions,
+    chat_list::ChatQuery,
+    exclusion::NewExclusion,
+    format::MessageFormat,
+    state::{AppState, Chats, QueueMode},
+    subscriber::Audience,
+    telemetry::with_request_id,
+    topic::ChatTarget,
+    welcome::Welcome,
+};
+
+async fn setup() -> (MockTelegram, AppState) {
+    let (mock, url) = MockTelegram::start();
+    let state = state_with_telegram(&url).await;
+    (mock, state)
+}
+
+async fn archived_chats(state: &AppState) -> Chats {
+    let query = ChatQuery {
+        archived: true,
+        ..Default::default()
+    };
+    state.query_chats(&query, i64::MAX, 0).await.unwrap().0
+}
+
+pub(super) fn user(id: i64) -> Value {
+    json!({ "id": id, "is_bot": false, "first_name": format!("user {id}") })
+}
+
+pub(super) fn message(chat_id: i64, fields: Value) -> Message {
+    let mut message = json!({
+        "message_id": 1,
+        "date": 0,
+        "chat": { "id": chat_id, "type": "supergroup", "title": "chat" },
+    });
+    message
+        .as_object_mut()
+        .unwrap()
+        .extend(fields.as_object().unwrap().clone());
+    serde_json::from_value(message).unwrap()
+}
+
+async fn broadcast(state: &AppState, targets: Vec<ChatTarget>, options: MessageOptions) -> i32 {
+    let id = state
+        .queue_message_with_images(
+            Some(targets),
+            "hello".to_string(),
+            Vec::new(),
+            Utc::now().to_rfc3339(),
+            options,
+            QueueMode::Send,
+        )
+        .await
+        .unwrap();
+    let message = state.get_queued_message(id).await.unwrap().unwrap();
+    state.broadcast(&message).await.unwrap();
+    id
+}
+
+#[tokio::test]
+async fn messages_register_the_chat_and_the_sender() {
+    let (mock, state) = setup().await;
+
+    let text = message(-100, json!({ "from": user(10), "text": "hi" }));
+    handle_message(text, state.bot.clone(), state.clone())
+        .await
+        .unwrap();
+
+    let chats = state.get_chats().await.unwrap();
+    assert_eq!(chats.len(), 1);
+    assert_eq!(chats[0].member_count, Some(3));
+    assert!(state.chats_status.contains_key(&-100));
+    assert_eq!(member_ids(&state, -100).await, vec![10]);
+    assert_eq!(mock.calls("getChat").len(), 1);
+}
+
+#[tokio::test]
+async fn members_that_stopped_writing_are_inactive() {
+    let (_mock, state) = setup().await;
+    let long_ago = (Utc::now() - Duration::days(100)).timestamp();
+
+    for (user_id, date) in [(10, Utc::now().timestamp()), (11, long_ago)] {
+        let text = message(
+            -100,
+            json!({ "from": user(user_id), "text": "hi", "date": date }),
+        );
+        handle_message(text, state.bot.clone(), state.clone())
+            .await
+            .unwrap();
+    }
+    // a member that just joined is silent since then, not since ever
+    let joined = message(
+        -100,
+        json!({ "from": user(12), "new_chat_members": [user(12)] }),
+    );
+    handle_message(joined, state.bot.clone(), state.clone())
+        .await
+        .unwrap();
+
+    let inactive = state.get_inactive_members(-100, 90).await.unwrap();
+    let ids: Vec<i64> = inactive.iter().map(|member| member.id).collect();
+    assert_eq!(ids, vec![11]);
+    assert_eq!(
+        inactive[0].last_message_at.map(|at| at.timestamp()),
+        Some(long_ago)
+    );
+    assert!(state
+        .get_inactive_members(-100, 101)
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[tokio::test]
+async fn members_keep_when_they_joined_and_changed() {
+    let (_mock, state) = setup().await;
+    let write = |name: &str| {
+        let from = json!({ "id": 10, "is_bot": false, "first_name": name });
+        message(-100, json!({ "from": from, "text": "hi" }))
+    };
+
+    handle_message(write("ann"), state.bot.clone(), state.clone())
+        .await
+        .unwrap();
+    let joined = state.get_member(-100, 10).await.unwrap().unwrap();
+    assert!(joined.joined_at.is_some());
+    assert_eq!(joined.updated_at, joined.joined_at);
+
+    tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    handle_message(write("ann"), state.bot.clone(), state.clone())
+        .await
+        .unwrap();
+    let unchanged = state.get_member(-100, 10).await.unwrap().unwrap();
+    assert_eq!(unchanged.updated_at, joined.updated_at);
+
+    handle_message(write("anna"), state.bot.clone(), state.clone())
+        .await
+        .unwrap();
+    let renamed = state.get_member(-100, 10).await.unwrap().unwrap();
+    assert_eq!(renamed.name, "anna");
+    assert_eq!(renamed.joined_at, joined.joined_at);
+    assert!(renamed.updated_at > joined.updated_at);
+    assert!(state.get_member(-100, 11).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn joins_are_stored_and_the_service_message_deleted() {
+    let (mock, state) = setup().await;
+    mock.add_admin(12);
+
+    let joined = message(
+        -100,
+        json!({ "from": user(11), "new_chat_members": [user(11), user(12)] }),
+    );
+    handle_message(joined, state.bot.clone(), state.clone())
+        .await
+        .unwrap();
+
+    // admins are never kicked, so they are not stored
+    assert_eq!(member_ids(&state, -100).await, vec![11]);
+    let deleted = mock.calls("deleteMessage");
+    assert_eq!(deleted.len(), 1);
+    assert_eq!(deleted[0]["chat_id"], -100);
+}
+
+#[tokio::test]
+async fn joins_are_greeted_with_the_welcome_message() {
+    let (mock, state) = setup().await;
+    add_chat(&state, -100).await;
+    state
+        .set_welcome(
+            -100,
+            &Welcome {
+                template: "Welcome {name} ({username})!".to_string(),
+                delete_after_minutes: None,
+            },
+        )
+        .await
+        .unwrap();
+
+    let mut bot = user(13);
+    bot["is_bot"] = json!(true);
+    let mut member = user(11);
+    member["username"] = json!("eleven");
+    let joined = message(
+        -100,
+        json!({ "from": user(11), "new_chat_members": [member, bot] }),
+    );
+    handle_message(joined, state.bot.clone(), state.clone())
+        .await
+        .unwrap();
+
+    let sent = mock.calls("sendMessage");
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0]["text"], "Welcome user 11 (@eleven)!");
+}
+
+#[tokio::test]
+async fn greetings_are_deleted_once_their_time_runs_out() {
+    let (mock, state) = setup().await;
+    add_chat(&state, -100).await;
+    state
+        .set_welcome(
+            -100,
+            &Welcome {
+                template: "Welcome {name}!".to_string(),
+                delete_after_minutes: Some(5),
+            },
+        )
+        .await
+        .unwrap();
+
+    let joined = message(
+        -100,
+        json!({ "from": user(11), "new_chat_members": [user(11)] }),
+    );
+    handle_message(joined, state.bot.clone(), state.clone())
+        .await
+        .unwrap();
+    assert_eq!(mock.calls("sendMessage").len(), 1);
+    // the join service message is deleted right away
+    assert_eq!(mock.calls("deleteMessage").len(), 1);
+
+    let now = Utc::now();
+    assert_eq!(state.delete_expired_greetings(now).await.unwrap(), 0);
+    assert_eq!(mock.calls("deleteMessage").len(), 1);
+
+    // the pending deletion is stored, so a restart in between keeps it
+    let later = now + Duration::minutes(6);
+    assert_eq!(state.delete_expired_greetings(later).await.unwrap(), 1);
+    let deleted = mock.calls("deleteMessage");
+    assert_eq!(deleted.len(), 2);
+    assert_eq!(deleted[1]["chat_id"], -100);
+    assert_eq!(state.delete_expired_greetings(later).await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn leaves_remove_the_member() {
+    let (mock, state) = setup().await;
+    add_chat(&state, -100).await;
+    add_member(&state, -100, 11).await;
+
+    let left = message(
+        -100,
+        json!({ "from": user(11), "left_chat_member": user(11) }),
+    );
+    handle_message(left, state.bot.clone(), state.clone())
+        .await
+        .unwrap();
+
+    assert!(member_ids(&state, -100).await.is_empty());
+    assert_eq!(mock.calls("deleteMessage").len(), 1);
+}
+
+#[tokio::test]
+async fn chats_can_keep_the_service_messages() {
+    let (mock, state) = setup().await;
+    mock.add_admin(1000);
+    let command = message(
+        -100,
+        json!({ "from": user(1000), "text": "/servicemessages keep" }),
+    );
+    handle_command(
+        command,
+        state.bot.clone(),
+        state.clone(),
+        Command::ServiceMessages("keep".to_string()),
+    )
+    .await
+    .unwrap();
+    assert!(!state.deletes_service_messages(-100).await.unwrap());
+
+    for fields in [
+        json!({ "from": user(11), "new_chat_members": [user(11)] }),
+        json!({ "from": user(11), "left_chat_member": user(11) }),
+    ] {
+        handle_message(message(-100, fields), state.bot.clone(), state.clone())
+            .await
+            .unwrap();
+    }
+
+    assert!(mock.calls("deleteMessage").is_empty());
+    let chats = state.get_chats().await.unwrap();
+    assert!(!chats[0].delete_service_messages);
+}
+
+#[tokio::test]
+async fn migration_messages_move_the_chat() {
+    let (_mock, state) = setup().await;
+    add_chat(&state, -1).await;
+    add_member(&state, -1, 11).await;
+
+    let migrated = message(
+        -1001,
+        json!({
+            "sender_chat": { "id": -1001, "type": "supergroup", "title": "chat" },
+            "migrate_from_chat_id": -1,
+        }),
+    );
+    handle_message(migrated, state.bot.clone(), state.clone())
+        .await
+        .unwrap();
+
+    assert_eq!(member_ids(&state, -1001).await, vec![11]);
+    assert!(state.chats_status.contains_key(&-1001));
+    assert!(!state.chats_status.contains_key(&-1));
+}
+
+#[tokio::test]
+async fn broadcasts_reach_every_chat_and_record_failures() {
+    let (mock, state) = setup().await;
+    for chat_id in [1, 2, 3] {
+        add_chat(&state, chat_id).await;
+    }
+    mock.fail_chat(3, "Forbidden: bot was kicked from the supergroup chat");
+    assert!(state.telegram_activity.last_success().is_none());
+
+    let id = broadcast(
+        &state,
+        vec![
+            ChatTarget::Chat(1),
+            ChatTarget::Chat(2),
+            ChatTarget::Chat(3),
+        ],
+        MessageOptions {
+            parse_mode: MessageFormat::Html,
+            pin: true,
+            disable_web_page_preview: true,
+            ..Default::default()
+        },
+    )
+    .await;
+
+    let sent = mock.calls("sendMessage");
+    assert_eq!(sent.len(), 3);
+    assert_eq!(sent[0]["text"], "hello");
+    assert_eq!(sent[0]["parse_mode"], "HTML");
+    assert_eq!(sent[0]["disable_web_page_preview"], true);
+    assert_eq!(mock.calls("pinChatMessage").len(), 2);
+
+    let progress = state.get_broadcast_progress(id).await.unwrap().unwrap();
+    assert_eq!((progress.sent, progress.failed), (2, 1));
+    assert!(progress.first_delivered_at <= progress.last_delivered_at);
+    assert!(progress.first_delivered_at.is_some());
+    assert!(state.telegram_activity.last_success().is_some());
+
+    // a second run only retries chats without a delivery
+    let message = state.get_queued_message(id).await.unwrap().unwrap();
+    state.broadcast(&message).await.unwrap();
+    assert_eq!(mock.calls("sendMessage").len(), 3);
+}
+
+#[tokio::test]
+async fn completed_broadcasts_are_retried_for_the_failed_chats_only() {
+    let (mock, state) = setup().await;
+    for chat_id in [1, 2, 3] {
+        add_chat(&state, chat_id).await;
+    }
+    mock.fail_chat(2, "Forbidden: bot was kicked from the supergroup chat");
+
+    let id = state
+        .queue_message_with_images(
+            None,
+            "hello".to_string(),
+            Vec::new(),
+            Utc::now().to_rfc3339(),
+            MessageOptions::default(),
+            QueueMode::Send,
+        )
+        .await
+        .unwrap();
+    // nothing to retry before the broadcast is completed
+    assert!(state.retry_failed_chats(id).await.unwrap().is_empty());
+    state.message_queue_loop().await.unwrap();
+    assert_eq!(mock.calls("sendMessage").len(), 3);
+
+    // chats added since don't receive the retry
+    add_chat(&state, 4).await;
+    mock.recover_chat(2);
+    assert_eq!(state.retry_failed_chats(id).await.unwrap(), vec![2]);
+    let progress = state.get_broadcast_progress(id).await.unwrap().unwrap();
+    assert!(!progress.completed);
+    state.message_queue_loop().await.unwrap();
+
+    let sent = mock.calls("sendMessage");
+    assert_eq!(sent.len(), 4);
+    assert_eq!(sent[3]["chat_id"], 2);
+    let progress = state.get_broadcast_progress(id).await.unwrap().unwrap();
+    assert!(progress.completed);
+    assert_eq!((progress.sent, progress.failed), (3, 0));
+    assert!(state.retry_failed_chats(id).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn broadcasts_follow_chats_upgraded_to_supergroups() {
+    let (mock, state) = setup().await;
+    add_chat(&state, -1).await;
+    add_member(&state, -1, 11).await;
+    mock.migrate_chat(-1, -1001);
+
+    let id = broadcast(
+        &state,
+        vec![ChatTarget::Chat(-1)],
+        MessageOptions::default(),
+    )
+    .await;
+
+    let sent = mock.calls("sendMessage");
+    assert_eq!(sent.len(), 2);
+    assert_eq!(sent[1]["chat_id"], -1001);
+    let progress = state.get_broadcast_progress(id).await.unwrap().unwrap();
+    assert_eq!((progress.sent, progress.failed), (1, 0));
+    assert!(state.chats_status.contains_key(&-1001));
+    assert_eq!(member_ids(&state, -1001).await, vec![11]);
+}
+
+#[tokio::test]
+async fn broadcasts_skip_muted_chats_and_go_to_topics() {
+    let (mock, state) = setup().await;
+    add_chat(&state, 1).await;
+    add_chat(&state, 2).await;
+    state.set_chat_muted(2, true).await.unwrap();
+
+    let id = broadcast(
+        &state,
+        vec![
+            ChatTarget::Thread {
+                chat_id: 1,
+                thread_id: 7,
+            },
+            ChatTarget::Chat(2),
+        ],
+        MessageOptions::default(),
+    )
+    .await;
+
+    let sent = mock.calls("sendMessage");
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0]["chat_id"], 1);
+    assert_eq!(sent[0]["message_thread_id"], 7);
+
+    let deliveries = state.get_deliveries(id).await.unwrap();
+    let muted = deliveries
+        .iter()
+        .find(|delivery| delivery.chat_id == 2)
+        .unwrap();
+    assert_eq!(muted.error.as_deref(), Some("chat is muted"));
+}
+
+#[tokio::test]
+async fn copied_messages_are_copied() {
+    let (mock, state) = setup().await;
+    add_chat(&state, 1).await;
+
+    let id = state
+        .queue_copied_message(
+            vec![1],
+            -500,
+            42,
+            "copied".to_string(),
+            Utc::now().to_rfc3339(),
+            MessageOptions::default(),
+        )
+        .await
+        .unwrap();
+    let message = state.get_queued_message(id).await.unwrap().unwrap();
+    state.broadcast(&message).await.unwrap();
+
+    assert!(mock.calls("sendMessage").is_empty());
+    let copied = mock.calls("copyMessage");
+    assert_eq!(copied.len(), 1);
+    assert_eq!(copied[0]["from_chat_id"], -500);
+    assert_eq!(copied[0]["message_id"], 42);
+}
+
+#[tokio::test]
+async fn protected_broadcasts_can_not_be_forwarded() {
+    let (mock, state) = setup().await;
+    add_chat(&state, 1).await;
+
+    let options = MessageOptions {
+        protect_content: true,
+        ..Default::default()
+    };
+    broadcast(&state, vec![ChatTarget::Chat(1)], options).await;
+    broadcast(&state, vec![ChatTarget::Chat(1)], MessageOptions::default()).await;
+
+    let sent = mock.calls("sendMessage");
+    assert_eq!(sent[0]["protect_content"], true);
+    assert_ne!(sent[1]["protect_content"], true);
+}
+
+#[tokio::test]
+async fn albums_and_text_of_a_broadcast_arrive_together() {
+    let (mock, state) = setup().await;
+    add_chat(&state, -100).await;
+
+    let image = base64::engine::general_purpose::STANDARD.encode(png(2, 2));
+    let mut messages = Vec::new();
+    for images in [vec![image; 11], Vec::new()] {
+        let id = state
+            .queue_message_with_images(
+                Some(vec![ChatTarget::Chat(-100)]),
+                "hello".to_string(),
+                images,
+                Utc::now().to_rfc3339(),
+                MessageOptions::default(),
+                QueueMode::Send,
+            )
+            .await
+            .unwrap();
+        messages.push(state.get_queued_message(id).await.unwrap().unwrap());
+    }
+    let (album, text) = tokio::join!(state.broadcast(&messages[0]), state.broadcast(&messages[1]));
+    album.unwrap();
+    text.unwrap();
+
+    // two albums of the 11 images and their text, never split by the other
+    let sends: Vec<String> = mock
+        .methods()
+        .into_iter()
+        .map(|method| method.to_ascii_lowercase())
+        .filter(|method| method.starts_with("send"))
+        .collect();
+    let album = ["sendmediagroup", "sendmediagroup", "sendmessage"];
+    assert!(
+        sends == [&album[..], &["sendmessage"]].concat()
+            || sends == [&["sendmessage"], &album[..]].concat(),
+        "{sends:?}"
+    );
+}
+
+#[tokio::test]
+async fn images_can_be_sent_without_text() {
+    let (mock, state) = setup().await;
+    add_chat(&state, -100).await;
+
+    let image = base64::engine::general_purpose::STANDARD.encode(png(2, 2));
+    let id = state
+        .queue_message_with_images(
+            Some(vec![ChatTarget::Chat(-100)]),
+            String::new(),
+            vec![image],
+            Utc::now().to_rfc3339(),
+            MessageOptions::default(),
+            QueueMode::Send,
+        )
+        .await
+        .unwrap();
+    let message = state.get_queued_message(id).await.unwrap().unwrap();
+    state.broadcast(&message).await.unwrap();
+
+    assert_eq!(mock.calls("sendMediaGroup").len(), 1);
+    assert!(mock.calls("sendMessage").is_empty());
+    let progress = state.get_broadcast_progress(id).await.unwrap().unwrap();
+    assert_eq!((progress.sent, progress.failed), (1, 0));
+}
+
+#[tokio::test]
+async fn files_telegram_stored_for_a_broadcast_are_kept_per_chat() {
+    let (_mock, state) = setup().await;
+    add_chat(&state, -100).await;
+    add_chat(&state, -200).await;
+
+    let image = base64::engine::general_purpose::STANDARD.encode(png(2, 2));
+    let id = state
+        .queue_message_with_images(
+            Some(vec![ChatTarget::Chat(-100), ChatTarget::Chat(-200)]),
+            "hello".to_string(),
+            vec![image],
+            Utc::now().to_rfc3339(),
+            MessageOptions::default(),
+            QueueMode::Send,
+        )
+        .await
+        .unwrap();
+    let message = state.get_queued_message(id).await.unwrap().unwrap();
+    state.broadcast(&message).await.unwrap();
+
+    let files = state.get_delivered_files(id, None).await.unwrap().unwrap();
+    assert_eq!(files.len(), 2);
+    assert_eq!(files[0].chat_id, -200);
+    assert!(files.iter().all(|file| file.kind == "photo"
+        && file.file_unique_id == "photo"
+        && file.file_size == Some(68)
+        && (file.width, file.height) == (2, 2)));
+    assert_ne!(files[0].file_id, files[1].file_id);
+    let files = state
+        .get_delivered_files(id, Some(-100))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(files.len(), 1);
+    assert_eq!(
+        files[0].file_id,
+        format!("photo{}", files[0].telegram_message_id)
+    );
+    assert!(state
+        .get_delivered_files(id + 1, None)
+        .await
+        .unwrap()
+        .is_none());
+}
+
+#[tokio::test]
+async fn subscribers_get_direct_messages_until_they_block_the_bot() {
+    let (mock, state) = setup().await;
+    for id in [10, 11] {
+        let user = serde_json::from_value(user(id)).unwrap();
+        assert!(state.subscribe(&user).await.unwrap());
+    }
+    mock.fail_chat(11, "Forbidden: bot was blocked by the user");
+
+    let id = state
+        .queue_message_with_images(
+            None,
+            "hello".to_string(),
+            Vec::new(),
+            Utc::now().to_rfc3339(),
+            MessageOptions {
+                audience: Audience::Subscribers,
+                ..Default::default()
+            },
+            QueueMode::Send,
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        state
+            .storage
+            .resolve_subscribers(&state.bot_id, id)
+            .await
+            .unwrap(),
+        vec![10, 11]
+    );
+    let message = state.get_queued_message(id).await.unwrap().unwrap();
+    state.broadcast(&message).await.unwrap();
+
+    let progress = state.get_broadcast_progress(id).await.unwrap().unwrap();
+    assert_eq!((progress.sent, progress.failed), (1, 1));
+    assert!(state.unsubscribe(10).await.unwrap());
+    // the blocked subscriber was already dropped
+    assert!(!state.unsubscribe(11).await.unwrap());
+}
+
+#[tokio::test]
+async fn cleanups_never_kick_excluded_users() {
+    let (mock, state) = setup().await;
+    for chat_id in [-100, -200] {
+        add_chat(&state, chat_id).await;
+        for user_id in [10, 11, 12] {
+            add_member(&state, chat_id, user_id).await;
+        }
+    }
+    let exclude = |chat_id, user_id| NewExclusion {
+        chat_id,
+        user_id,
+        note: Some("service bot".to_string()),
+    };
+    state.add_exclusion(&exclude(None, 11)).await.unwrap();
+    state.add_exclusion(&exclude(Some(-100), 12)).await.unwrap();
+    // excluding again only updates the note
+    state.add_exclusion(&exclude(None, 11)).await.unwrap();
+    assert_eq!(state.get_exclusions().await.unwrap().len(), 2);
+
+    state.delete_all_members(-100, "test").await.unwrap();
+    let kicked: Vec<Value> = mock
+        .calls("unbanChatMember")
+        .iter()
+        .map(|call| call["user_id"].clone())
+        .collect();
+    assert_eq!(kicked, vec![json!(10)]);
+    assert_eq!(member_ids(&state, -100).await, vec![11, 12]);
+
+    state.delete_all_members(-200, "test").await.unwrap();
+    assert_eq!(member_ids(&state, -200).await, vec![11]);
+}
+
+#[tokio::test]
+async fn deleted_accounts_dont_stop_a_cleanup() {
+    let (mock, state) = setup().await;
+    add_chat(&state, -100).await;
+    for user_id in [10, 11, 12] {
+        add_member(&state, -100, user_id).await;
+    }
+    mock.deactivate(11);
+
+    state.delete_all_members(-100, "test").await.unwrap();
+    assert_eq!(mock.calls("unbanChatMember").len(), 3);
+    assert_eq!(member_ids(&state, -100).await, vec![11]);
+}
+
+#[tokio::test]
+async fn broadcasts_and_kicks_keep_the_request_starting_them() {
+    let (_mock, state) = setup().await;
+    add_chat(&state, -100).await;
+    add_member(&state, -100, 10).await;
+
+    let request_id = Some("req-1".to_string());
+    let id = with_request_id(
+        request_id.clone(),
+        state.queue_message_with_images(
+            Some(vec![ChatTarget::Chat(-100)]),
+            "hello".to_string(),
+            Vec::new(),
+            Utc::now().to_rfc3339(),
+            MessageOptions::default(),
+            QueueMode::Send,
+        ),
+    )
+    .await
+    .unwrap();
+    let message = state.get_queued_message(id).await.unwrap().unwrap();
+    assert_eq!(message.request_id, request_id);
+    // the queue sends it outside of the request
+    state.message_queue_loop().await.unwrap();
+    let deliveries = state.get_deliveries(id).await.unwrap();
+    assert_eq!(deliveries[0].request_id, request_id);
+
+    with_request_id(
+        Some("req-2".to_string()),
+        state.delete_all_members(-100, "test"),
+    )
+    .await
+    .unwrap();
+    let kicks = state.get_kicks(-100, 10, 0).await.unwrap();
+    assert_eq!(kicks[0].request_id.as_deref(), Some("req-2"));
+}
+
+fn my_chat_member(chat_id: i64, old_status: &str, new_status: &str) -> ChatMemberUpdated {
+    let mut bot = user(1);
+    bot["is_bot"] = json!(true);
+    serde_json::from_value(json!({
+        "chat": { "id": chat_id, "type": "supergroup", "title": "added" },
+        "from": user(11),
+        "date": 0,
+        "old_chat_member": { "user": bot, "status": old_status, "until_date": 0 },
+        "new_chat_member": { "user": bot, "status": new_status, "until_date": 0 },
+    }))
+    .unwrap()
+}
+
+#[tokio::test]
+async fn chats_follow_the_membership_of_the_bot() {
+    let (_mock, state) = setup().await;
+
+    handle_my_chat_member(my_chat_member(-100, "left", "member"), state.clone())
+        .await
+        .unwrap();
+    assert!(state.chats_status.contains_key(&-100));
+    assert!(state
+        .get_chats()
+        .await
+        .unwrap()
+        .iter()
+        .any(|chat| chat.id == -100));
+    add_member(&state, -100, 11).await;
+
+    handle_my_chat_member(my_chat_member(-100, "member", "kicked"), state.clone())
+        .await
+        .unwrap();
+    assert!(!state.chats_status.contains_key(&-100));
+    assert!(state.get_chats().await.unwrap().is_empty());
+    let archived = archived_chats(&state).await;
+    assert_eq!(archived.len(), 1);
+    assert!(archived[0].archived);
+    assert_eq!(member_ids(&state, -100).await, vec![11]);
+
+    // archived chats stay archived until they are restored
+    handle_my_chat_member(my_chat_member(-100, "kicked", "member"), state.clone())
+        .await
+        .unwrap();
+    assert!(!state.chats_status.contains_key(&-100));
+
+    assert!(state.restore_chat(-100).await.unwrap());
+    assert!(!state.restore_chat(-100).await.unwrap());
+    assert!(state.chats_status.contains_key(&-100));
+    assert!(archived_chats(&state).await.is_empty());
+}
+
+fn chat_member(chat_id: i64, user_id: i64, old: Value, new: Value) -> ChatMemberUpdated {
+    let mut old = old;
+    let mut new = new;
+    old["user"] = user(user_id);
+    new["user"] = user(user_id);
+    serde_json::from_value(json!({
+        "chat": { "id": chat_id, "type": "supergroup", "title": "chat" },
+        "from": user(11),
+        "date": 0,
+        "old_chat_member": old,
+        "new_chat_member": new,
+    }))
+    .unwrap()
+}
+
+fn administrator() -> Value {
+    json!({
+        "status": "administrator",
+        "is_anonymous": false,
+        "can_be_edited": true,
+        "can_manage_chat": true,
+        "can_change_info": false,
+        "can_delete_messages": true,
+        "can_manage_video_chats": false,
+        "can_invite_users": true,
+        "can_restrict_members": true,
+        "can_promote_members": false,
+    })
+}
+
+#[tokio::test]
+async fn chat_admins_follow_the_sync_and_promotions() {
+    let (mock, state) = setup().await;
+    add_chat(&state, -100).await;
+    add_member(&state, -100, 12).await;
+    mock.add_admin(10);
+
+    state.sync_admins_loop().await.unwrap();
+    let admins = state.get_chat_admins(-100).await.unwrap();
+    assert_eq!(admins.len(), 1);
+    assert_eq!(
+        (admins[0].user_id, admins[0].status.as_str()),
+        (10, "creator")
+    );
+
+    let member = json!({ "status": "member" });
+    handle_chat_member(
+        chat_member(-100, 12, member.clone(), administrator()),
+        state.clone(),
+    )
+    .await
+    .unwrap();
+    let admins = state.get_chat_admins(-100).await.unwrap();
+    let ids: Vec<(i64, &str)> = admins
+        .iter()
+        .map(|admin| (admin.user_id, admin.status.as_str()))
+        .collect();
+    assert_eq!(ids, vec![(10, "creator"), (12, "administrator")]);
+    assert!(member_ids(&state, -100).await.is_empty());
+
+    handle_chat_member(
+        chat_member(-100, 12, administrator(), member),
+        state.clone(),
+    )
+    .await
+    .unwrap();
+    let admins = state.get_chat_admins(-100).await.unwrap();
+    assert_eq!(admins.len(), 1);
+    assert_eq!(member_ids(&state, -100).await, vec![12]);
+
+    // the sync replaces what was stored
+    mock.add_admin(13);
+    state.sync_admins_loop().await.unwrap();
+    let ids: Vec<i64> = state
+        .get_chat_admins(-100)
+        .await
+        .unwrap()
+        .iter()
+        .map(|admin| admin.user_id)
+        .collect();
+    assert_eq!(ids.len(), 2);
+    assert!(ids.contains(&13));
+}
+
+#[tokio::test]
+async fn deprecated_chats_are_archived_by_the_checker() {
+    let (mock, url) = MockTelegram::start();
+    let state = state_with_config(|config| {
+        config.telegram_api_url = Some(url);
+        config.deprecated_chats_concurrency = 2;
+        config.intervals.cleanup_deprecated_chats = 1;
+    })
+    .await;
+    for chat_id in [-100, -200, -300] {
+        add_chat(&state, chat_id).await;
+    }
+    mock.fail_chat(-200, "Forbidden: bot was kicked from the supergroup chat");
+
+    state.cleanup_deprecated_chats_loop().await.unwrap();
+    state.cleanup_deprecated_chats_loop().await.unwrap();
+
+    let mut chats: Vec<i64> = state
+        .get_chats()
+        .await
+        .unwrap()
+        .iter()
+        .map(|chat| chat.id)
+        .collect();
+    chats.sort();
+    assert_eq!(chats, vec![-300, -100]);
+    assert_eq!(archived_chats(&state).await[0].id, -200);
+    // one check per chat and run, the bot itself is only looked up once
+    assert_eq!(mock.calls("getChatMember").len(), 5);
+    assert_eq!(mock.calls("getMe").len(), 1);
+}
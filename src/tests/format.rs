@@ -0,0 +1,17 @@
+use crate::format::MessageFormat;
+
+#[test]
+fn validation_errors_point_at_byte_offsets() {
+    let err = MessageFormat::MarkdownV2.validate("привет.").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "character '.' at byte offset 12 must be escaped with '\\'"
+    );
+    let err = MessageFormat::Html
+        .validate("привет <marquee>")
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "unsupported tag <marquee> at byte offset 13"
+    );
+}
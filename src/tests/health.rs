@@ -0,0 +1,52 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use super::{eventually, state_with_config};
+
+#[tokio::test]
+async fn loops_that_stop_beating_or_panic_are_restarted() {
+    let state = state_with_config(|config| config.task_restart_after = 1).await;
+
+    // beats once and hangs
+    let hangs = Arc::new(AtomicUsize::new(0));
+    let runs = hangs.clone();
+    tokio::spawn(state.clone().supervise(
+        "hangs",
+        |_| Duration::from_millis(100),
+        move |state| {
+            runs.fetch_add(1, Ordering::SeqCst);
+            async move {
+                state.heartbeat("hangs");
+                std::future::pending::<()>().await;
+                Ok(())
+            }
+        },
+    ));
+    let panics = Arc::new(AtomicUsize::new(0));
+    let runs = panics.clone();
+    tokio::spawn(state.clone().supervise(
+        "panics",
+        |_| Duration::from_secs(60),
+        move |_| {
+            runs.fetch_add(1, Ordering::SeqCst);
+            async move { panic!("loop failed") }
+        },
+    ));
+
+    eventually(|| hangs.load(Ordering::SeqCst) >= 2 && panics.load(Ordering::SeqCst) >= 2).await;
+
+    let heartbeats = state.health.heartbeats();
+    let tasks: Vec<&str> = heartbeats.iter().map(|heartbeat| heartbeat.task).collect();
+    assert_eq!(tasks, vec!["hangs", "panics"]);
+    assert!(heartbeats[0].restarts >= 1);
+    assert!(!heartbeats[1].stale);
+    assert!(state
+        .health
+        .metrics()
+        .contains("telegram_sender_task_restarts_total{bot=\"default\",task=\"panics\"}"));
+}
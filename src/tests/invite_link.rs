@@ -0,0 +1,77 @@
+use chrono::{Duration, Utc};
+
+use super::{add_chat, mock_telegram::MockTelegram, state_with_telegram};
+use crate::invite_link::NewInviteLink;
+
+#[test]
+fn invite_link_options_are_checked() {
+    let limited = NewInviteLink {
+        member_limit: Some(10),
+        ..Default::default()
+    };
+    assert!(limited.validate().is_ok());
+    assert!(NewInviteLink {
+        creates_join_request: true,
+        ..limited.clone()
+    }
+    .validate()
+    .is_err());
+    assert!(NewInviteLink {
+        member_limit: Some(0),
+        ..Default::default()
+    }
+    .validate()
+    .is_err());
+    assert!(NewInviteLink {
+        expire_date: Some(Utc::now() - Duration::hours(1)),
+        ..Default::default()
+    }
+    .validate()
+    .is_err());
+}
+
+#[tokio::test]
+async fn invite_links_are_kept_after_revoking() {
+    let (mock, url) = MockTelegram::start();
+    let state = state_with_telegram(&url).await;
+    add_chat(&state, -100).await;
+
+    let options = NewInviteLink {
+        name: Some("june campaign".to_string()),
+        member_limit: Some(50),
+        ..Default::default()
+    };
+    let first = state.create_invite_link(-100, &options).await.unwrap();
+    let second = state
+        .create_invite_link(-100, &NewInviteLink::default())
+        .await
+        .unwrap();
+    assert_eq!(first.name.as_deref(), Some("june campaign"));
+    assert_eq!(first.member_limit, Some(50));
+    assert_ne!(first.invite_link, second.invite_link);
+    assert_eq!(mock.calls("createChatInviteLink")[0]["member_limit"], 50);
+
+    let revoked = state
+        .revoke_invite_link(-100, first.id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(revoked.revoked_at.is_some());
+    // revoking again is a no-op
+    state.revoke_invite_link(-100, first.id).await.unwrap();
+    assert_eq!(mock.calls("revokeChatInviteLink").len(), 1);
+    assert_eq!(
+        mock.calls("revokeChatInviteLink")[0]["invite_link"],
+        first.invite_link
+    );
+    assert!(state
+        .revoke_invite_link(-200, second.id)
+        .await
+        .unwrap()
+        .is_none());
+
+    let links = state.get_invite_links(-100).await.unwrap();
+    let ids: Vec<i32> = links.iter().map(|link| link.id).collect();
+    assert_eq!(ids, vec![second.id, first.id]);
+    assert!(links[0].revoked_at.is_none());
+}
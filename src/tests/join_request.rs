@@ -0,0 +1,151 @@
+use serde_json::{json, Value};
+use teloxide::types::{CallbackQuery, ChatJoinRequest};
+
+use super::{add_chat, mock_telegram::MockTelegram, state_with_config};
+use crate::{
+    bot::{handle_callback_query, handle_chat_join_request},
+    join_request::{parse_join_callback, JoinPolicy, JoinRequestStatus},
+    state::AppState,
+};
+
+const ADMIN_ID: i64 = 7;
+
+async fn setup() -> (MockTelegram, AppState) {
+    let (mock, url) = MockTelegram::start();
+    let state = state_with_config(|config| {
+        config.telegram_api_url = Some(url);
+        config.admin_ids = vec![ADMIN_ID];
+    })
+    .await;
+    add_chat(&state, -100).await;
+    (mock, state)
+}
+
+fn join_request(user_id: i64) -> ChatJoinRequest {
+    serde_json::from_value(json!({
+        "chat": { "id": -100, "type": "supergroup", "title": "chat" },
+        "from": { "id": user_id, "is_bot": false, "first_name": "user", "username": "joiner" },
+        "date": 0,
+        "bio": "hello",
+    }))
+    .unwrap()
+}
+
+fn button_press(from: i64, data: &str) -> CallbackQuery {
+    serde_json::from_value(json!({
+        "id": "1",
+        "from": { "id": from, "is_bot": false, "first_name": "admin" },
+        "chat_instance": "1",
+        "data": data,
+    }))
+    .unwrap()
+}
+
+#[test]
+fn join_callbacks_are_parsed() {
+    assert_eq!(parse_join_callback("join:approve:3"), Some((true, 3)));
+    assert_eq!(parse_join_callback("join:decline:12"), Some((false, 12)));
+    assert_eq!(parse_join_callback("join:ban:3"), None);
+    assert_eq!(parse_join_callback("poll:approve:3"), None);
+    assert_eq!(parse_join_callback("join:approve:x"), None);
+}
+
+#[tokio::test]
+async fn join_requests_follow_the_chat_policy() {
+    let (mock, state) = setup().await;
+    assert_eq!(
+        state.get_join_policy(-100).await.unwrap(),
+        JoinPolicy::Manual
+    );
+
+    state
+        .set_join_policy(-100, JoinPolicy::Approve)
+        .await
+        .unwrap();
+    handle_chat_join_request(join_request(10), state.clone())
+        .await
+        .unwrap();
+    state
+        .set_join_policy(-100, JoinPolicy::Decline)
+        .await
+        .unwrap();
+    handle_chat_join_request(join_request(11), state.clone())
+        .await
+        .unwrap();
+
+    let approved = mock.calls("approveChatJoinRequest");
+    assert_eq!(approved.len(), 1);
+    assert_eq!(approved[0]["user_id"], 10);
+    assert_eq!(mock.calls("declineChatJoinRequest")[0]["user_id"], 11);
+    // nobody is asked when the policy decides
+    assert!(mock.calls("sendMessage").is_empty());
+
+    let requests = state.get_join_requests(-100, None).await.unwrap();
+    let decisions: Vec<_> = requests
+        .iter()
+        .map(|request| (request.user_id, request.status))
+        .collect();
+    assert_eq!(
+        decisions,
+        vec![
+            (11, JoinRequestStatus::Declined),
+            (10, JoinRequestStatus::Approved)
+        ]
+    );
+    assert_eq!(requests[0].decided_by.as_deref(), Some("policy"));
+    assert_eq!(requests[0].bio.as_deref(), Some("hello"));
+}
+
+#[tokio::test]
+async fn bot_admins_decide_on_manual_requests() {
+    let (mock, state) = setup().await;
+
+    handle_chat_join_request(join_request(10), state.clone())
+        .await
+        .unwrap();
+    let pending = state
+        .get_join_requests(-100, Some(JoinRequestStatus::Pending))
+        .await
+        .unwrap();
+    assert_eq!(pending.len(), 1);
+    assert!(mock.calls("approveChatJoinRequest").is_empty());
+
+    let asked = mock.calls("sendMessage");
+    assert_eq!(asked.len(), 1);
+    assert_eq!(asked[0]["chat_id"], ADMIN_ID);
+    let data = asked[0]["reply_markup"]["inline_keyboard"][0][0]["callback_data"].clone();
+    assert_eq!(data, Value::from(format!("join:approve:{}", pending[0].id)));
+
+    // only bot admins may press the buttons
+    handle_callback_query(
+        button_press(8, data.as_str().unwrap()),
+        state.bot.clone(),
+        state.clone(),
+    )
+    .await
+    .unwrap();
+    assert!(mock.calls("approveChatJoinRequest").is_empty());
+
+    for _ in 0..2 {
+        handle_callback_query(
+            button_press(ADMIN_ID, data.as_str().unwrap()),
+            state.bot.clone(),
+            state.clone(),
+        )
+        .await
+        .unwrap();
+    }
+    assert_eq!(mock.calls("approveChatJoinRequest").len(), 1);
+    let answers = mock.calls("answerCallbackQuery");
+    assert_eq!(answers.len(), 3);
+    assert_eq!(answers[2]["text"], "The request was approved already.");
+
+    let request = state
+        .get_join_request(pending[0].id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(request.status, JoinRequestStatus::Approved);
+    assert_eq!(request.decided_by, Some(format!("admin:{ADMIN_ID}")));
+    assert!(request.decided_at.is_some());
+}
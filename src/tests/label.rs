@@ -0,0 +1,65 @@
+use super::{add_chat, test_state};
+use crate::label::normalize_labels;
+
+fn labels(labels: &[&str]) -> Vec<String> {
+    labels.iter().map(|label| label.to_string()).collect()
+}
+
+#[test]
+fn labels_are_trimmed_sorted_and_deduplicated() {
+    assert_eq!(
+        normalize_labels(labels(&[" vip", "retail", "vip "])).unwrap(),
+        labels(&["retail", "vip"])
+    );
+    assert!(normalize_labels(labels(&["  "])).is_err());
+    assert!(normalize_labels(labels(&["a,b"])).is_err());
+    assert!(normalize_labels(labels(&[&"x".repeat(33)])).is_err());
+    let many: Vec<String> = (0..21).map(|i| format!("label {i}")).collect();
+    assert!(normalize_labels(many).is_err());
+}
+
+#[tokio::test]
+async fn chats_are_found_by_their_labels() {
+    let state = test_state().await;
+    for chat_id in [1, 2, 3] {
+        add_chat(&state, chat_id).await;
+    }
+    assert!(state
+        .set_chat_labels(1, labels(&["vip", "retail"]))
+        .await
+        .unwrap());
+    assert!(state.set_chat_labels(2, labels(&["retail"])).await.unwrap());
+    assert!(state.set_chat_notes(2, Some("call first")).await.unwrap());
+    assert!(!state.set_chat_labels(4, labels(&["vip"])).await.unwrap());
+
+    assert_eq!(
+        state
+            .get_labelled_chats(&labels(&["retail"]))
+            .await
+            .unwrap(),
+        vec![1, 2]
+    );
+    assert_eq!(
+        state
+            .get_labelled_chats(&labels(&["vip", "wholesale"]))
+            .await
+            .unwrap(),
+        vec![1]
+    );
+    assert!(state.get_labelled_chats(&[]).await.unwrap().is_empty());
+
+    let chats = state.get_chats().await.unwrap();
+    let chat = chats.iter().find(|chat| chat.id == 2).unwrap();
+    assert_eq!(chat.labels, labels(&["retail"]));
+    assert_eq!(chat.notes.as_deref(), Some("call first"));
+
+    // a chat migrating into a known one keeps the labels of both
+    assert!(state
+        .set_chat_labels(3, labels(&["wholesale"]))
+        .await
+        .unwrap());
+    state.migrate_chat(1, 3).await.unwrap();
+    let chats = state.get_chats().await.unwrap();
+    let chat = chats.iter().find(|chat| chat.id == 3).unwrap();
+    assert_eq!(chat.labels, labels(&["retail", "vip", "wholesale"]));
+}
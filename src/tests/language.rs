@@ -0,0 +1,91 @@
+use std::collections::HashMap;
+
+use chrono::Utc;
+
+use super::{add_chat, mock_telegram::MockTelegram, state_with_telegram};
+use crate::{
+    broadcast::MessageOptions,
+    language::{pick_translation, validate_language, LocalizedMessage, Translations},
+    state::QueueMode,
+    topic::ChatTarget,
+};
+
+#[test]
+fn regional_languages_fall_back_to_their_primary_language() {
+    let translations = HashMap::from([
+        ("pt".to_string(), "olá"),
+        ("en-gb".to_string(), "hello, mate"),
+    ]);
+    assert_eq!(pick_translation(&translations, Some("pt-br")), Some(&"olá"));
+    assert_eq!(
+        pick_translation(&translations, Some("en-gb")),
+        Some(&"hello, mate")
+    );
+    assert_eq!(pick_translation(&translations, Some("en")), None);
+    assert_eq!(pick_translation(&translations, None), None);
+
+    assert!(validate_language("en").is_ok());
+    assert!(validate_language("pt-br").is_ok());
+    assert!(validate_language("EN").is_err());
+    assert!(validate_language("english").is_err());
+    assert!(validate_language("").is_err());
+}
+
+#[tokio::test]
+async fn chats_receive_the_translation_for_their_language() {
+    let (mock, url) = MockTelegram::start();
+    let state = state_with_telegram(&url).await;
+    for chat_id in [1, 2, 3] {
+        add_chat(&state, chat_id).await;
+    }
+    assert!(state.set_chat_language(1, Some("ru")).await.unwrap());
+    assert!(state.set_chat_language(2, Some("de")).await.unwrap());
+    assert!(state.set_chat_language(2, Some("German")).await.is_err());
+    assert!(!state.set_chat_language(4, Some("ru")).await.unwrap());
+
+    let id = state
+        .queue_message_with_images(
+            Some([1, 2, 3].map(ChatTarget::Chat).to_vec()),
+            LocalizedMessage {
+                message: "hello".to_string(),
+                translations: Translations::from([("ru".to_string(), "привет".to_string())]),
+                ..Default::default()
+            },
+            Vec::new(),
+            Utc::now().to_rfc3339(),
+            MessageOptions::default(),
+            QueueMode::Send,
+        )
+        .await
+        .unwrap();
+    let message = state.get_queued_message(id).await.unwrap().unwrap();
+    state.broadcast(&message).await.unwrap();
+
+    let mut texts: Vec<(i64, String)> = mock
+        .calls("sendMessage")
+        .iter()
+        .map(|sent| {
+            let chat_id = sent["chat_id"].as_i64().unwrap();
+            (chat_id, sent["text"].as_str().unwrap().to_string())
+        })
+        .collect();
+    texts.sort();
+    assert_eq!(
+        texts,
+        vec![
+            (1, "привет".to_string()),
+            (2, "hello".to_string()),
+            (3, "hello".to_string()),
+        ]
+    );
+
+    // a resent broadcast keeps its translations
+    let resent = state
+        .resend_broadcast(&message, None, Utc::now().to_rfc3339())
+        .await
+        .unwrap();
+    assert_eq!(
+        state.get_message_translations(resent).await.unwrap().len(),
+        1
+    );
+}
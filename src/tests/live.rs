@@ -0,0 +1,42 @@
+use super::{add_chat, add_member, e2e::user, test_state};
+use crate::{
+    live::LiveEvent,
+    state::{ChatCleaningStatus, CleanupState},
+};
+
+#[tokio::test]
+async fn status_and_member_count_changes_are_published() {
+    let state = test_state().await;
+    add_chat(&state, -100).await;
+    state.fill_status_list().await.unwrap();
+    let mut events = state.live.subscribe();
+
+    state
+        .set_chat_status(-100, ChatCleaningStatus::Error("kicked".to_string()))
+        .unwrap();
+    let event = events.try_recv().unwrap();
+    assert!(matches!(
+        &*event,
+        LiveEvent::Status {
+            chat_id: -100,
+            status: CleanupState::Error,
+            error: Some(error),
+            ..
+        } if error == "kicked"
+    ));
+
+    add_member(&state, -100, 10).await;
+    let member = serde_json::from_value(user(11)).unwrap();
+    state.upsert_chat_member(-100, &member).await.unwrap();
+    // the same member again changes nothing
+    state.upsert_chat_member(-100, &member).await.unwrap();
+    let event = events.try_recv().unwrap();
+    assert!(matches!(
+        &*event,
+        LiveEvent::Members {
+            chat_id: -100,
+            member_count: 2
+        }
+    ));
+    assert!(events.try_recv().is_err());
+}
@@ -0,0 +1,83 @@
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+
+use super::{add_chat, mock_telegram::MockTelegram, state_with_telegram};
+use crate::{
+    broadcast::MessageOptions,
+    local_time::{local_due, parse_local_datetime},
+    state::QueueMode,
+    topic::ChatTarget,
+};
+
+fn local(datetime: &str) -> NaiveDateTime {
+    parse_local_datetime(datetime).unwrap()
+}
+
+fn at(datetime: &str) -> DateTime<Utc> {
+    datetime.parse().unwrap()
+}
+
+#[test]
+fn local_times_fall_on_different_instants_per_offset() {
+    assert_eq!(local("2024-06-01T09:00"), local("2024-06-01T09:00:00"));
+    assert!(parse_local_datetime("2024-06-01T09:00:00Z").is_err());
+    assert!(parse_local_datetime("2024-06-01").is_err());
+
+    let nine = local("2024-06-01T09:00");
+    assert_eq!(local_due(nine, 0), at("2024-06-01T09:00:00Z"));
+    assert_eq!(local_due(nine, 180), at("2024-06-01T06:00:00Z"));
+    assert_eq!(local_due(nine, -300), at("2024-06-01T14:00:00Z"));
+}
+
+#[tokio::test]
+async fn broadcasts_wait_for_the_local_time_of_each_chat() {
+    let (mock, url) = MockTelegram::start();
+    let state = state_with_telegram(&url).await;
+    add_chat(&state, 1).await;
+    add_chat(&state, 2).await;
+    assert!(state.set_chat_utc_offset(1, Some(180)).await.unwrap());
+    assert!(state.set_chat_utc_offset(2, Some(15 * 60)).await.is_err());
+
+    // an hour from now on UTC, already two hours ago at UTC+03:00
+    let now = Utc::now();
+    let local = local(
+        &(now + Duration::hours(1))
+            .format("%Y-%m-%dT%H:%M:%S")
+            .to_string(),
+    );
+    let targets = vec![ChatTarget::Chat(1), ChatTarget::Chat(2)];
+    let earliest = state
+        .earliest_local_due(local, Some(&targets))
+        .await
+        .unwrap();
+    assert_eq!(earliest, local_due(local, 180));
+
+    let id = state
+        .queue_message_with_images(
+            Some(targets),
+            "hello".to_string(),
+            Vec::new(),
+            earliest.to_rfc3339(),
+            MessageOptions {
+                datetime_local: Some(local),
+                ..Default::default()
+            },
+            QueueMode::Send,
+        )
+        .await
+        .unwrap();
+    let message = state.get_queued_message(id).await.unwrap().unwrap();
+    assert_eq!(message.datetime_local, Some(local));
+    let deferred_until = state.broadcast(&message).await.unwrap();
+
+    assert_eq!(deferred_until, Some(local_due(local, 0)));
+    let sent = mock.calls("sendMessage");
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0]["chat_id"], 1);
+    let progress = state.get_broadcast_progress(id).await.unwrap().unwrap();
+    assert_eq!((progress.sent, progress.deferred), (1, 1));
+
+    // once chat 2 reaches the time too it gets the message
+    state.set_chat_utc_offset(2, Some(120)).await.unwrap();
+    assert_eq!(state.broadcast(&message).await.unwrap(), None);
+    assert_eq!(mock.calls("sendMessage").len(), 2);
+}
@@ -0,0 +1,29 @@
+use super::{
+    add_chat,
+    mock_telegram::MockTelegram,
+    queue::{pending, queue},
+    state_with_telegram,
+};
+use crate::topic::ChatTarget;
+
+#[tokio::test]
+async fn maintenance_pauses_the_queue_and_cleanups() {
+    let (mock, url) = MockTelegram::start();
+    let state = state_with_telegram(&url).await;
+    add_chat(&state, -100).await;
+    assert!(!state.in_maintenance().await.unwrap());
+
+    state.set_maintenance(true).await.unwrap();
+    assert!(state.in_maintenance().await.unwrap());
+    let id = queue(&state, Some(vec![ChatTarget::Chat(-100)]), false).await;
+    state.message_queue_loop().await.unwrap();
+    state.cleanup_deprecated_chats_loop().await.unwrap();
+    assert_eq!(pending(&state).await, vec![id]);
+    assert!(mock.calls("sendMessage").is_empty());
+    assert!(mock.calls("getChatMember").is_empty());
+
+    state.set_maintenance(false).await.unwrap();
+    state.message_queue_loop().await.unwrap();
+    assert!(pending(&state).await.is_empty());
+    assert_eq!(mock.calls("sendMessage")[0]["text"], "hello");
+}
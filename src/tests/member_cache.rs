@@ -0,0 +1,44 @@
+use chrono::{Duration, Utc};
+use teloxide::types::ChatMemberKind;
+
+use super::{add_chat, add_member, member_ids, mock_telegram::MockTelegram, state_with_telegram};
+
+#[tokio::test]
+async fn cleanups_skip_active_members_and_reuse_known_kinds() {
+    let (mock, url) = MockTelegram::start();
+    let state = state_with_telegram(&url).await;
+    add_chat(&state, 1).await;
+    for user_id in [10, 11, 12] {
+        add_member(&state, 1, user_id).await;
+    }
+    mock.add_admin(12);
+    // members that just joined count as present too
+    for user_id in [10, 12] {
+        state
+            .record_activity(1, user_id, Utc::now() - Duration::days(1))
+            .await
+            .unwrap();
+    }
+    state.record_activity(1, 11, Utc::now()).await.unwrap();
+
+    let members = state.get_all_members(1).await.unwrap();
+    let mut kinds: Vec<_> = state
+        .member_kinds(1, members)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|(user, kind)| (user.id, matches!(kind, ChatMemberKind::Member)))
+        .collect();
+    kinds.sort();
+    assert_eq!(kinds, vec![(10, true), (12, false)]);
+    assert_eq!(mock.calls("getChatMember").len(), 2);
+
+    // known kinds aren't asked for again
+    let members = state.get_all_members(1).await.unwrap();
+    state.member_kinds(1, members).await.unwrap();
+    assert_eq!(mock.calls("getChatMember").len(), 2);
+
+    state.cleanup_chat(1).await.unwrap();
+    assert_eq!(member_ids(&state, 1).await, vec![10, 11]);
+    assert_eq!(mock.calls("getChatMember").len(), 2);
+}
@@ -0,0 +1,56 @@
+use super::{add_chat, add_member, mock_telegram::MockTelegram, state_with_telegram, test_state};
+use crate::member_import::SeededMember;
+
+fn seeded(id: i64) -> SeededMember {
+    SeededMember {
+        id,
+        username: Some(format!("seeded{id}")),
+        name: "seeded".into(),
+    }
+}
+
+#[tokio::test]
+async fn imported_members_keep_the_stored_ones() {
+    let state = test_state().await;
+    add_chat(&state, 1).await;
+    add_member(&state, 1, 10).await;
+
+    let report = state
+        .import_members(1, vec![seeded(10), seeded(11)], false)
+        .await
+        .unwrap();
+    assert_eq!((report.imported, report.existing), (1, 1));
+
+    let members = state.get_all_members(1).await.unwrap();
+    assert_eq!(members.len(), 2);
+    let stored = members.iter().find(|member| member.id == 10).unwrap();
+    assert_ne!(stored.name, "seeded");
+    let imported = members.iter().find(|member| member.id == 11).unwrap();
+    assert_eq!(imported.username.as_deref(), Some("seeded11"));
+}
+
+#[tokio::test]
+async fn verified_imports_leave_out_who_isnt_a_member() {
+    let (mock, url) = MockTelegram::start();
+    let state = state_with_telegram(&url).await;
+    add_chat(&state, 1).await;
+    mock.depart(11);
+    mock.add_admin(12);
+
+    let report = state
+        .import_members(1, vec![seeded(10), seeded(11), seeded(12)], true)
+        .await
+        .unwrap();
+    assert_eq!(
+        (report.imported, report.not_members, report.admins),
+        (1, 1, 1)
+    );
+
+    let members = state.get_all_members(1).await.unwrap();
+    assert_eq!(members.len(), 1);
+    assert_eq!(members[0].id, 10);
+    // the name telegram has wins over the imported one
+    assert_eq!(members[0].name, "user");
+    let admins = state.get_chat_admins(1).await.unwrap();
+    assert_eq!(admins[0].user_id, 12);
+}
@@ -0,0 +1,35 @@
+use super::{add_chat, test_state};
+
+#[tokio::test]
+async fn member_search_matches_wildcards_literally() {
+    let state = test_state().await;
+    add_chat(&state, 1).await;
+    for (user_id, name) in [
+        (10, "ann"),
+        (11, "bob_smith"),
+        (12, "100% bob"),
+        (13, r"c\d"),
+    ] {
+        state
+            .storage
+            .upsert_member(&state.bot_id, 1, user_id, "", name)
+            .await
+            .unwrap();
+    }
+
+    let search = |search: &str| {
+        let state = state.clone();
+        let search = search.to_string();
+        async move {
+            let members = state
+                .get_members_page(1, Some(search), 50, 0)
+                .await
+                .unwrap();
+            members.into_iter().map(|user| user.id).collect::<Vec<_>>()
+        }
+    };
+    assert_eq!(search("_").await, vec![11]);
+    assert_eq!(search("%").await, vec![12]);
+    assert_eq!(search(r"\").await, vec![13]);
+    assert_eq!(search("BOB").await, vec![11, 12]);
+}
@@ -0,0 +1,127 @@
+use chrono::Utc;
+
+use super::{add_chat, add_member, member_ids, test_state};
+use crate::{
+    broadcast::MessageOptions,
+    state::{ChatCleaningStatus, QueueMode},
+    topic::ChatTarget,
+};
+
+#[tokio::test]
+async fn migration_moves_members_status_and_pending_messages() {
+    let state = test_state().await;
+    add_chat(&state, 1).await;
+    add_chat(&state, 2).await;
+    add_member(&state, 1, 10).await;
+    add_member(&state, 1, 11).await;
+    state
+        .set_chat_status(1, ChatCleaningStatus::Cancelled(5))
+        .unwrap();
+
+    let pending = state
+        .queue_message_with_images(
+            Some(vec![ChatTarget::Chat(1), ChatTarget::Chat(2)]),
+            "pending".to_string(),
+            Vec::new(),
+            Utc::now().to_rfc3339(),
+            MessageOptions::default(),
+            QueueMode::Send,
+        )
+        .await
+        .unwrap();
+    let completed = state
+        .queue_message_with_images(
+            Some(vec![ChatTarget::Chat(1)]),
+            "completed".to_string(),
+            Vec::new(),
+            Utc::now().to_rfc3339(),
+            MessageOptions::default(),
+            QueueMode::Send,
+        )
+        .await
+        .unwrap();
+    state.complete_queued_message(completed).await.unwrap();
+
+    state.migrate_chat(1, -1001).await.unwrap();
+
+    assert!(member_ids(&state, 1).await.is_empty());
+    assert_eq!(member_ids(&state, -1001).await, vec![10, 11]);
+    assert!(state.chats_status.get(&1).is_none());
+    assert!(matches!(
+        state.chats_status.get(&-1001).unwrap().clone(),
+        ChatCleaningStatus::Cancelled(5)
+    ));
+
+    let pending = state.get_queued_message(pending).await.unwrap().unwrap();
+    assert_eq!(pending.chats, vec![-1001, 2]);
+    // history is left pointing at the chat it was sent to
+    let completed = state.get_queued_message(completed).await.unwrap().unwrap();
+    assert_eq!(completed.chats, vec![1]);
+
+    let mut chats: Vec<i64> = state
+        .get_chats()
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|chat| chat.id)
+        .collect();
+    chats.sort();
+    assert_eq!(chats, vec![-1001, 2]);
+}
+
+#[tokio::test]
+async fn migration_into_a_known_chat_merges_them() {
+    let state = test_state().await;
+    add_chat(&state, 1).await;
+    add_chat(&state, -1001).await;
+    add_member(&state, 1, 10).await;
+    add_member(&state, 1, 11).await;
+    add_member(&state, -1001, 11).await;
+    add_member(&state, -1001, 12).await;
+    state.set_chat_muted(1, true).await.unwrap();
+    let pending = state
+        .queue_message_with_images(
+            Some(vec![ChatTarget::Chat(1), ChatTarget::Chat(-1001)]),
+            "pending".to_string(),
+            Vec::new(),
+            Utc::now().to_rfc3339(),
+            MessageOptions::default(),
+            QueueMode::Send,
+        )
+        .await
+        .unwrap();
+
+    state.migrate_chat(1, -1001).await.unwrap();
+
+    assert_eq!(member_ids(&state, -1001).await, vec![10, 11, 12]);
+    let chats = state.get_chats().await.unwrap();
+    assert_eq!(chats.len(), 1);
+    assert_eq!(chats[0].id, -1001);
+    // a muted group stays muted after becoming a supergroup
+    assert!(chats[0].muted);
+    assert!(chats[0].approved);
+    // the supergroup gets the message once
+    let pending = state.get_queued_message(pending).await.unwrap().unwrap();
+    assert_eq!(pending.chats, vec![-1001]);
+}
+
+#[tokio::test]
+async fn renames_are_kept_across_a_migration() {
+    let state = test_state().await;
+    add_chat(&state, 1).await;
+
+    assert!(state.rename_chat(1, "first").await.unwrap());
+    assert!(!state.rename_chat(1, "first").await.unwrap());
+    assert!(state.rename_chat(1, "second").await.unwrap());
+    assert!(!state.rename_chat(3, "unknown").await.unwrap());
+
+    add_chat(&state, 2).await;
+    state.migrate_chat(1, 2).await.unwrap();
+
+    let renames = state.get_chat_renames(2).await.unwrap();
+    let names: Vec<(&str, &str)> = renames
+        .iter()
+        .map(|rename| (rename.old_name.as_str(), rename.new_name.as_str()))
+        .collect();
+    assert_eq!(names, vec![("first", "second"), ("chat 1", "first")]);
+}
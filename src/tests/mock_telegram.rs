@@ -0,0 +1,302 @@
+//! A minimal Bot API server answering the methods the service calls with
+//! canned results and recording every call.
+
+use std::{
+    collections::{HashMap, HashSet},
+    net::{SocketAddr, TcpListener},
+    sync::{
+        atomic::{AtomicI32, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use axum::{
+    body::Bytes,
+    extract::{Extension, Path},
+    routing::post,
+    Json, Router,
+};
+use serde_json::{json, Value};
+
+pub struct Call {
+    pub method: String,
+    pub params: Value,
+}
+
+#[derive(Clone, Default)]
+pub struct MockTelegram {
+    calls: Arc<Mutex<Vec<Call>>>,
+    /// Chats every send fails in, with the error description telegram gives.
+    failing_chats: Arc<Mutex<HashMap<i64, String>>>,
+    /// Groups upgraded to a supergroup, with the id of the supergroup.
+    migrated_chats: Arc<Mutex<HashMap<i64, i64>>>,
+    admins: Arc<Mutex<HashSet<i64>>>,
+    /// Users reported as having left every chat.
+    departed: Arc<Mutex<HashSet<i64>>>,
+    /// Users that deleted their account, every request about them fails.
+    deactivated: Arc<Mutex<HashSet<i64>>>,
+    /// Users reported as "Deleted Account", like telegram names deleted
+    /// accounts that are still members.
+    deleted_accounts: Arc<Mutex<HashSet<i64>>>,
+    webhook: Arc<Mutex<Option<String>>>,
+    /// Users kicking is flood limited for, with the seconds to retry after.
+    flooded_kicks: Arc<Mutex<HashMap<i64, u32>>>,
+    /// `getChat` answers replacing the default one.
+    chats: Arc<Mutex<HashMap<i64, Value>>>,
+    next_message_id: Arc<AtomicI32>,
+}
+
+impl MockTelegram {
+    /// Starts the server on a free local port and returns its url.
+    pub fn start() -> (Self, String) {
+        let mock = Self::default();
+        let listener = TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], 0))).unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+
+        let app = Router::new()
+            .route("/:token/:method", post(handle))
+            .layer(Extension(mock.clone()));
+        let server = axum::Server::from_tcp(listener)
+            .unwrap()
+            .serve(app.into_make_service());
+        tokio::spawn(server);
+
+        (mock, url)
+    }
+
+    /// Parameters of every call of `method`, in order.
+    pub fn calls(&self, method: &str) -> Vec<Value> {
+        self.calls
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|call| call.method.eq_ignore_ascii_case(method))
+            .map(|call| call.params.clone())
+            .collect()
+    }
+
+    /// Every method called, in order.
+    pub fn methods(&self) -> Vec<String> {
+        self.calls
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|call| call.method.clone())
+            .collect()
+    }
+
+    pub fn fail_chat(&self, chat_id: i64, description: &str) {
+        self.failing_chats
+            .lock()
+            .unwrap()
+            .insert(chat_id, description.to_string());
+    }
+
+    /// Lets sends to a chat passed to [`fail_chat`](Self::fail_chat) succeed
+    /// again.
+    pub fn recover_chat(&self, chat_id: i64) {
+        self.failing_chats.lock().unwrap().remove(&chat_id);
+    }
+
+    /// Answers kicking the user with a flood wait until
+    /// [`unflood_kicks`](Self::unflood_kicks).
+    pub fn flood_kicks(&self, user_id: i64, retry_after: u32) {
+        self.flooded_kicks
+            .lock()
+            .unwrap()
+            .insert(user_id, retry_after);
+    }
+
+    pub fn unflood_kicks(&self, user_id: i64) {
+        self.flooded_kicks.lock().unwrap().remove(&user_id);
+    }
+
+    pub fn migrate_chat(&self, chat_id: i64, new_id: i64) {
+        self.migrated_chats.lock().unwrap().insert(chat_id, new_id);
+    }
+
+    pub fn add_admin(&self, user_id: i64) {
+        self.admins.lock().unwrap().insert(user_id);
+    }
+
+    pub fn deactivate(&self, user_id: i64) {
+        self.deactivated.lock().unwrap().insert(user_id);
+    }
+
+    pub fn delete_account(&self, user_id: i64) {
+        self.deleted_accounts.lock().unwrap().insert(user_id);
+    }
+
+    pub fn depart(&self, user_id: i64) {
+        self.departed.lock().unwrap().insert(user_id);
+    }
+
+    pub fn set_webhook(&self, url: &str) {
+        *self.webhook.lock().unwrap() = Some(url.to_string());
+    }
+
+    pub fn set_chat(&self, chat_id: i64, chat: Value) {
+        self.chats.lock().unwrap().insert(chat_id, chat);
+    }
+
+    fn respond(&self, method: &str, params: &Value) -> Value {
+        let chat_id = params["chat_id"].as_i64().unwrap_or_default();
+        if let Some(description) = self.failing_chats.lock().unwrap().get(&chat_id) {
+            return json!({ "ok": false, "error_code": 403, "description": description });
+        }
+        if let Some(new_id) = self.migrated_chats.lock().unwrap().get(&chat_id) {
+            return json!({
+                "ok": false,
+                "error_code": 400,
+                "description": "Bad Request: group chat was upgraded to a supergroup chat",
+                "parameters": { "migrate_to_chat_id": new_id },
+            });
+        }
+
+        let method = method.to_ascii_lowercase();
+        let user_id = params["user_id"].as_i64().unwrap_or_default();
+        if let Some(retry_after) = self.flooded_kicks.lock().unwrap().get(&user_id) {
+            if matches!(
+                method.as_str(),
+                "unbanchatmember" | "kickchatmember" | "banchatmember"
+            ) {
+                return json!({
+                    "ok": false,
+                    "error_code": 429,
+                    "description": format!("Too Many Requests: retry after {retry_after}"),
+                    "parameters": { "retry_after": retry_after },
+                });
+            }
+        }
+
+        if self.deactivated.lock().unwrap().contains(&user_id) {
+            return json!({
+                "ok": false,
+                "error_code": 403,
+                "description": "Forbidden: user is deactivated",
+            });
+        }
+
+        // like telegram, method names are case-insensitive
+        let result = match method.as_str() {
+            "getme" => json!({
+                "id": 1,
+                "is_bot": true,
+                "first_name": "bot",
+                "username": "test_bot",
+                "can_join_groups": true,
+                "can_read_all_group_messages": true,
+                "supports_inline_queries": false,
+            }),
+            "getchat" => match self.chats.lock().unwrap().get(&chat_id) {
+                Some(chat) => chat.clone(),
+                None => chat(chat_id),
+            },
+            "getwebhookinfo" => json!({
+                "url": self.webhook.lock().unwrap().clone().unwrap_or_default(),
+                "has_custom_certificate": false,
+                "pending_update_count": 0,
+            }),
+            "getchatmembercount" => json!(3),
+            "getchatmember" => {
+                let first_name = match self.deleted_accounts.lock().unwrap().contains(&user_id) {
+                    true => "Deleted Account",
+                    false => "user",
+                };
+                let user = json!({ "id": user_id, "is_bot": false, "first_name": first_name });
+                if self.admins.lock().unwrap().contains(&user_id) {
+                    json!({ "status": "creator", "user": user, "is_anonymous": false })
+                } else if self.departed.lock().unwrap().contains(&user_id) {
+                    json!({ "status": "left", "user": user })
+                } else {
+                    json!({ "status": "member", "user": user })
+                }
+            }
+            "getchatadministrators" => {
+                let admins = self.admins.lock().unwrap();
+                json!(admins
+                    .iter()
+                    .map(|&user_id| json!({
+                        "status": "creator",
+                        "user": { "id": user_id, "is_bot": false, "first_name": "admin" },
+                        "is_anonymous": false,
+                    }))
+                    .collect::<Vec<_>>())
+            }
+            "sendmessage" | "sendpoll" | "sendsticker" | "sendvenue" | "sendlocation" => json!({
+                "message_id": self.next_message_id.fetch_add(1, Ordering::SeqCst) + 1,
+                "date": 0,
+                "chat": chat(chat_id),
+                // stickers and locations are answered as empty texts
+                "text": params["text"].as_str().unwrap_or_default(),
+            }),
+            // uploaded albums aren't parsed, a single photo stands for them
+            "sendmediagroup" => {
+                let message_id = self.next_message_id.fetch_add(1, Ordering::SeqCst) + 1;
+                json!([{
+                    "message_id": message_id,
+                    "date": 0,
+                    "chat": chat(chat_id),
+                    "photo": [{
+                        "file_id": format!("photo{message_id}"),
+                        "file_unique_id": "photo",
+                        "file_size": 68,
+                        "width": 2,
+                        "height": 2,
+                    }],
+                }])
+            }
+            "createchatinvitelink" => json!({
+                "invite_link": format!(
+                    "https://t.me/+link{}",
+                    self.next_message_id.fetch_add(1, Ordering::SeqCst) + 1
+                ),
+                "creator": { "id": 1, "is_bot": true, "first_name": "bot" },
+                "creates_join_request": params["creates_join_request"],
+                "is_primary": false,
+                "is_revoked": false,
+                "name": params["name"],
+                "expire_date": params["expire_date"],
+                "member_limit": params["member_limit"],
+            }),
+            "revokechatinvitelink" => json!({
+                "invite_link": params["invite_link"],
+                "creator": { "id": 1, "is_bot": true, "first_name": "bot" },
+                "creates_join_request": false,
+                "is_primary": false,
+                "is_revoked": true,
+                "name": null,
+                "member_limit": null,
+            }),
+            "editmessagetext" => json!({
+                "message_id": params["message_id"],
+                "date": 0,
+                "chat": chat(chat_id),
+                "text": params["text"],
+            }),
+            "copymessage" => json!({
+                "message_id": self.next_message_id.fetch_add(1, Ordering::SeqCst) + 1,
+            }),
+            _ => json!(true),
+        };
+
+        json!({ "ok": true, "result": result })
+    }
+}
+
+fn chat(chat_id: i64) -> Value {
+    json!({ "id": chat_id, "type": "supergroup", "title": format!("chat {chat_id}") })
+}
+
+async fn handle(
+    Path((_token, method)): Path<(String, String)>,
+    Extension(mock): Extension<MockTelegram>,
+    body: Bytes,
+) -> Json<Value> {
+    // uploads come as multipart, only their method is recorded
+    let params = serde_json::from_slice(&body).unwrap_or(Value::Null);
+    let response = mock.respond(&method, &params);
+    mock.calls.lock().unwrap().push(Call { method, params });
+
+    Json(response)
+}
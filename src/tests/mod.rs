@@ -0,0 +1,191 @@
+//! Tests running [`AppState`] against an in-memory sqlite database, with the
+//! bot pointed at a closed local port or at an in-process mock of the Bot
+//! API, so nothing ever reaches telegram.
+
+use std::{
+    io::Cursor,
+    net::{SocketAddr, TcpListener},
+    sync::Arc,
+    time::Duration,
+};
+
+use axum::extract::Extension;
+use image::{ImageOutputFormat, RgbImage};
+
+use crate::{
+    api, bot,
+    config::{Config, DatabasePoolConfig, DEFAULT_BOT_ID},
+    state::{AppState, ChatCleaningStatus},
+    storage::SqliteStorage,
+};
+
+mod access;
+mod alert;
+mod attachment;
+mod audit;
+mod backpressure;
+mod bots;
+mod branding;
+mod calendar;
+mod captcha;
+mod channel;
+mod chat_cache;
+mod chat_group;
+mod chat_list;
+mod chat_metadata;
+mod cli;
+mod convert;
+mod database;
+mod draft;
+mod e2e;
+mod format;
+mod health;
+mod invite_link;
+mod join_request;
+mod label;
+mod language;
+mod live;
+mod local_time;
+mod maintenance;
+mod member_cache;
+mod member_import;
+mod member_search;
+mod migration;
+mod mock_telegram;
+mod moderation;
+mod outbound;
+mod preflight;
+mod purge;
+mod queue;
+mod queue_history;
+mod queue_transfer;
+mod quiet_hours;
+mod reaction;
+mod reconcile;
+mod reply;
+mod report;
+mod resync;
+mod self_destruct;
+mod session;
+mod settings;
+mod sink;
+mod split;
+mod staged_cleanup;
+mod status;
+mod status_store;
+mod subscriber;
+mod telegram_error;
+mod usage;
+mod validation;
+mod variant;
+mod webhook;
+
+pub async fn test_state() -> AppState {
+    state_with_config(|_| {}).await
+}
+
+/// State whose bot talks to the Bot API server at `url`, see
+/// [`mock_telegram::MockTelegram`].
+pub async fn state_with_telegram(url: &str) -> AppState {
+    state_with_config(|config| config.telegram_api_url = Some(url.to_string())).await
+}
+
+/// State with the test configuration as changed by `configure`.
+pub async fn state_with_config(configure: impl FnOnce(&mut Config)) -> AppState {
+    let mut config = Config {
+        bot_token: "0:test".to_string(),
+        // nothing listens on the discard port, so stray telegram calls fail fast
+        telegram_api_url: Some("http://127.0.0.1:9".to_string()),
+        // the webhook receivers of the tests listen on loopback
+        allow_private_urls: true,
+        ..Default::default()
+    };
+    configure(&mut config);
+    let storage = SqliteStorage::connect("sqlite::memory:", 1, &DatabasePoolConfig::default())
+        .await
+        .expect("in-memory database");
+    let bot = bot::build(&config, &config.bot_token).unwrap();
+
+    AppState::new(config, Arc::new(storage), None, DEFAULT_BOT_ID, bot)
+}
+
+/// Registers an approved chat the way the bot would on its first update.
+pub async fn add_chat(state: &AppState, chat_id: i64) {
+    state
+        .storage
+        .upsert_chat(
+            &state.bot_id,
+            chat_id,
+            &format!("chat {chat_id}"),
+            true,
+            "supergroup",
+            None,
+        )
+        .await
+        .unwrap()
+        .expect("chat of another bot");
+    state.chats_status.insert(chat_id, ChatCleaningStatus::Idle);
+}
+
+pub async fn add_member(state: &AppState, chat_id: i64, user_id: i64) {
+    state
+        .storage
+        .upsert_member(
+            &state.bot_id,
+            chat_id,
+            user_id,
+            "",
+            &format!("user {user_id}"),
+        )
+        .await
+        .unwrap();
+}
+
+/// Ids of the members known in the chat, sorted.
+pub async fn member_ids(state: &AppState, chat_id: i64) -> Vec<i64> {
+    let mut ids: Vec<i64> = state
+        .get_all_members(chat_id)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|user| user.id)
+        .collect();
+    ids.sort();
+    ids
+}
+
+/// A black PNG of the given size.
+pub fn png(width: u32, height: u32) -> Vec<u8> {
+    let mut data = Cursor::new(Vec::new());
+    RgbImage::new(width, height)
+        .write_to(&mut data, ImageOutputFormat::Png)
+        .unwrap();
+    data.into_inner()
+}
+
+/// Serves the API routes of the state on a free local port, returns the url
+/// they are under.
+pub fn serve_api(state: &AppState) -> String {
+    let app = api::routes(&state.config).layer(Extension(state.clone()));
+    let listener = TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], 0))).unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let server = axum::Server::from_tcp(listener)
+        .unwrap()
+        .serve(app.into_make_service_with_connect_info::<SocketAddr>());
+    tokio::spawn(server);
+
+    url
+}
+
+/// Waits for work done in the background until `done`, failing the test
+/// after five seconds.
+pub async fn eventually(mut done: impl FnMut() -> bool) {
+    let wait = async {
+        while !done() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    };
+    tokio::time::timeout(Duration::from_secs(5), wait)
+        .await
+        .expect("timed out waiting for the background work");
+}
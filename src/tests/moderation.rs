@@ -0,0 +1,268 @@
+use serde_json::json;
+
+use super::{
+    e2e::{message, user},
+    member_ids,
+    mock_telegram::MockTelegram,
+    state_with_telegram,
+};
+use crate::{
+    bot::{handle_command, handle_message, Command},
+    moderation::{LinkPolicy, ModerationKind, ModerationSettings, Violation, SPAM_FILTER},
+    state::AppState,
+};
+
+const ADMIN_ID: i64 = 20;
+
+#[test]
+fn spam_filters_are_checked() {
+    let settings = ModerationSettings {
+        banned_patterns: vec![r"t\.me/\w+".to_string()],
+        restrict_minutes: 60,
+        ..Default::default()
+    };
+    assert!(settings.validate().is_ok());
+    assert!(ModerationSettings {
+        banned_patterns: vec!["(unclosed".to_string()],
+        ..settings.clone()
+    }
+    .validate()
+    .is_err());
+    assert!(ModerationSettings {
+        banned_words: vec![" ".to_string()],
+        ..settings.clone()
+    }
+    .validate()
+    .is_err());
+    assert!(ModerationSettings {
+        restrict_minutes: 0,
+        ..settings.clone()
+    }
+    .validate()
+    .is_err());
+    assert!(ModerationSettings {
+        kick_after: Some(0),
+        ..settings
+    }
+    .validate()
+    .is_err());
+}
+
+async fn send(state: &AppState, user_id: i64, fields: serde_json::Value) {
+    let mut fields = fields;
+    fields["from"] = user(user_id);
+    handle_message(message(-100, fields), state.bot.clone(), state.clone())
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn offenders_are_warned_muted_and_kicked() {
+    let (mock, url) = MockTelegram::start();
+    let state = state_with_telegram(&url).await;
+    mock.add_admin(ADMIN_ID);
+    send(&state, 10, json!({ "text": "hi" })).await;
+    state
+        .set_moderation(
+            -100,
+            &ModerationSettings {
+                banned_words: vec!["free money".to_string()],
+                links: LinkPolicy::Delete,
+                restrict_after: Some(2),
+                restrict_minutes: 30,
+                kick_after: Some(3),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+
+    // only whole words count and admins are never filtered
+    send(&state, 10, json!({ "text": "carefree moneylender" })).await;
+    send(&state, ADMIN_ID, json!({ "text": "FREE MONEY" })).await;
+    assert!(mock.calls("deleteMessage").is_empty());
+
+    send(
+        &state,
+        10,
+        json!({ "text": "Free  money? no, free money!" }),
+    )
+    .await;
+    let warnings = state.get_warnings(-100).await.unwrap();
+    assert_eq!(warnings[0].warnings, 1);
+    assert_eq!(
+        warnings[0].last_reason,
+        Violation::BannedWord("free money".to_string()).to_string()
+    );
+    assert!(mock.calls("restrictChatMember").is_empty());
+
+    send(
+        &state,
+        10,
+        json!({
+            "text": "look at example.com",
+            "entities": [{ "type": "url", "offset": 8, "length": 11 }],
+        }),
+    )
+    .await;
+    let restricted = mock.calls("restrictChatMember");
+    assert_eq!(restricted.len(), 1);
+    assert_eq!(restricted[0]["user_id"], 10);
+    assert!(restricted[0]["until_date"].is_number());
+    assert!(restricted[0]["permissions"]
+        .as_object()
+        .unwrap()
+        .values()
+        .all(|allowed| allowed != true));
+
+    send(&state, 10, json!({ "caption": "free money", "photo": [] })).await;
+    assert_eq!(mock.calls("unbanChatMember")[0]["user_id"], 10);
+    assert_eq!(mock.calls("deleteMessage").len(), 3);
+    assert!(state.get_warnings(-100).await.unwrap().is_empty());
+    let kicks = state.storage.get_kicks(-100, 10, 0).await.unwrap();
+    assert_eq!(kicks[0].initiator, SPAM_FILTER);
+    let actions: Vec<_> = state
+        .get_moderation_log(-100, None, 10, 0)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|action| action.action)
+        .collect();
+    assert_eq!(
+        actions,
+        vec![
+            ModerationKind::Kick,
+            ModerationKind::Warn,
+            ModerationKind::Restrict,
+            ModerationKind::Warn,
+            ModerationKind::Warn
+        ]
+    );
+}
+
+#[tokio::test]
+async fn changed_filters_apply_at_once() {
+    let (mock, url) = MockTelegram::start();
+    let state = state_with_telegram(&url).await;
+    let settings = ModerationSettings {
+        banned_words: vec!["spam".to_string()],
+        restrict_minutes: 60,
+        ..Default::default()
+    };
+    send(&state, 10, json!({ "text": "hi" })).await;
+    state.set_moderation(-100, &settings).await.unwrap();
+    send(&state, 10, json!({ "text": "spam" })).await;
+    assert_eq!(mock.calls("deleteMessage").len(), 1);
+
+    state
+        .set_moderation(
+            -100,
+            &ModerationSettings {
+                banned_words: vec!["eggs".to_string()],
+                ..settings
+            },
+        )
+        .await
+        .unwrap();
+    send(&state, 10, json!({ "text": "spam" })).await;
+    assert_eq!(mock.calls("deleteMessage").len(), 1);
+    send(&state, 10, json!({ "text": "eggs" })).await;
+    assert_eq!(mock.calls("deleteMessage").len(), 2);
+
+    assert!(state.delete_moderation(-100).await.unwrap());
+    send(&state, 10, json!({ "text": "eggs" })).await;
+    assert_eq!(mock.calls("deleteMessage").len(), 2);
+}
+
+async fn command(state: &AppState, fields: serde_json::Value, command: Command) {
+    let mut fields = fields;
+    fields["from"] = user(ADMIN_ID);
+    // the text was parsed into `command` already
+    fields["text"] = json!("/command");
+    handle_command(
+        message(-100, fields),
+        state.bot.clone(),
+        state.clone(),
+        command,
+    )
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn chat_admins_moderate_with_commands() {
+    let (mock, url) = MockTelegram::start();
+    let state = state_with_telegram(&url).await;
+    mock.add_admin(ADMIN_ID);
+    send(&state, 10, json!({ "text": "buy now" })).await;
+    state
+        .set_moderation(
+            -100,
+            &ModerationSettings {
+                kick_after: Some(2),
+                restrict_minutes: 60,
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+    let reply = json!({
+        "reply_to_message": {
+            "message_id": 1,
+            "date": 0,
+            "chat": { "id": -100, "type": "supergroup", "title": "chat" },
+            "from": user(10),
+            "text": "buy now",
+        },
+    });
+
+    command(&state, reply.clone(), Command::Warn("spamming".to_string())).await;
+    assert_eq!(state.get_warnings(-100).await.unwrap()[0].warnings, 1);
+    command(&state, json!({}), Command::Ban("11 flooding".to_string())).await;
+    assert_eq!(mock.calls("banChatMember")[0]["user_id"], 11);
+    command(&state, json!({}), Command::Unban("11".to_string())).await;
+    assert_eq!(mock.calls("unbanChatMember")[0]["only_if_banned"], true);
+    // the second warning reaches the limit
+    command(&state, reply, Command::Warn(String::new())).await;
+    assert_eq!(mock.calls("unbanChatMember")[1]["user_id"], 10);
+    assert!(member_ids(&state, -100).await.is_empty());
+
+    command(&state, json!({}), Command::Kick(ADMIN_ID.to_string())).await;
+    command(&state, json!({}), Command::Kick("nobody".to_string())).await;
+    let replies: Vec<_> = mock
+        .calls("sendMessage")
+        .into_iter()
+        .map(|call| call["text"].as_str().unwrap().to_string())
+        .collect();
+    assert_eq!(
+        replies[replies.len() - 2..],
+        [
+            "Chat admins can't be moderated.",
+            "Reply to a message of the member or give their user id."
+        ]
+    );
+
+    let log = state.get_moderation_log(-100, None, 10, 0).await.unwrap();
+    let actions: Vec<_> = log
+        .iter()
+        .map(|action| (action.user_id, action.action, action.reason.as_str()))
+        .collect();
+    assert_eq!(
+        actions,
+        vec![
+            (10, ModerationKind::Kick, "no reason given"),
+            (10, ModerationKind::Warn, "no reason given"),
+            (11, ModerationKind::Unban, "no reason given"),
+            (11, ModerationKind::Ban, "flooding"),
+            (10, ModerationKind::Warn, "spamming"),
+        ]
+    );
+    assert!(log
+        .iter()
+        .all(|action| action.moderator == format!("admin:{ADMIN_ID}")));
+    let banned = state
+        .get_moderation_log(-100, Some(11), 10, 0)
+        .await
+        .unwrap();
+    assert_eq!(banned.len(), 2);
+}
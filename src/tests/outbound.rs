@@ -0,0 +1,50 @@
+use std::time::Duration;
+
+use super::state_with_config;
+use crate::outbound::{client, is_internal};
+
+#[test]
+fn addresses_of_the_machine_and_its_networks_are_internal() {
+    for ip in [
+        "127.0.0.1",
+        "10.1.2.3",
+        "172.16.0.1",
+        "192.168.1.1",
+        "169.254.169.254",
+        "100.64.0.1",
+        "0.0.0.0",
+        "::1",
+        "fd00::1",
+        "fe80::1",
+        "::ffff:127.0.0.1",
+    ] {
+        assert!(is_internal(ip.parse().unwrap()), "{ip}");
+    }
+    for ip in ["93.184.216.34", "100.128.0.1", "2606:2800:220:1::1"] {
+        assert!(!is_internal(ip.parse().unwrap()), "{ip}");
+    }
+}
+
+#[tokio::test]
+async fn urls_of_internal_hosts_are_refused() {
+    let state = state_with_config(|config| config.allow_private_urls = false).await;
+
+    for url in [
+        "https://127.0.0.1/image.png",
+        "https://[::1]/image.png",
+        "https://169.254.169.254/latest/meta-data",
+    ] {
+        let err = state.download_image(url).await.unwrap_err();
+        assert!(err.to_string().contains("internal address"), "{err}");
+    }
+
+    let err = client(Duration::from_secs(5), false)
+        .get("http://localhost:9/hook")
+        .send()
+        .await
+        .unwrap_err();
+    assert!(
+        format!("{err:?}").contains("resolves to the internal address"),
+        "{err:?}"
+    );
+}
@@ -0,0 +1,75 @@
+use std::path::PathBuf;
+
+use super::{mock_telegram::MockTelegram, state_with_telegram, test_state};
+use crate::{
+    config::{BotConfig, Config, LogConfig},
+    preflight::{Failure, Preflight},
+};
+
+fn bot(id: &str, token: &str) -> BotConfig {
+    BotConfig {
+        id: id.to_string(),
+        token: token.to_string(),
+    }
+}
+
+#[tokio::test]
+async fn bots_fail_on_a_webhook_a_shared_token_or_no_telegram() {
+    let (mock, url) = MockTelegram::start();
+    let state = state_with_telegram(&url).await;
+    let configs = [bot("default", "0:test")];
+
+    let mut preflight = Preflight::default();
+    preflight
+        .bots(&configs, &[("default", state.bot.clone())])
+        .await;
+    assert_eq!(preflight.failure(), None);
+    assert!(preflight.summary().contains("ok    bot default: @test_bot"));
+
+    mock.set_webhook("https://hooks.example.com/secret");
+    let mut preflight = Preflight::default();
+    preflight
+        .bots(&configs, &[("default", state.bot.clone())])
+        .await;
+    assert_eq!(preflight.failure(), Some(Failure::Polling));
+    let summary = preflight.summary();
+    assert!(summary.contains("hooks.example.com") && !summary.contains("secret"));
+    assert!(summary.contains("(exit code 8)"));
+
+    let mut preflight = Preflight::default();
+    preflight
+        .bots(&[bot("a", "1:same"), bot("b", "1:same")], &[])
+        .await;
+    assert_eq!(preflight.failure(), Some(Failure::Polling));
+
+    let offline = test_state().await;
+    let mut preflight = Preflight::default();
+    preflight.bots(&configs, &[("default", offline.bot)]).await;
+    assert_eq!(preflight.failure(), Some(Failure::Telegram));
+}
+
+#[test]
+fn log_files_need_a_writable_directory() {
+    let directory = std::env::temp_dir();
+    let mut preflight = Preflight::default();
+    preflight.files(&Config {
+        log: LogConfig {
+            file: Some(directory.join("sender.log")),
+            ..Default::default()
+        },
+        ..Default::default()
+    });
+    assert_eq!(preflight.failure(), None);
+
+    let mut preflight = Preflight::default();
+    preflight.files(&Config {
+        log: LogConfig {
+            file: Some(PathBuf::from("/nonexistent/sender.log")),
+            ..Default::default()
+        },
+        tls_cert: Some(directory.join("missing.pem")),
+        ..Default::default()
+    });
+    assert_eq!(preflight.failure(), Some(Failure::Filesystem));
+    assert_eq!(Failure::Filesystem.exit_code(), 3);
+}
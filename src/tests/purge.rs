@@ -0,0 +1,29 @@
+use super::{
+    add_chat, add_member, eventually, member_ids, mock_telegram::MockTelegram, serve_api,
+    state_with_telegram,
+};
+use crate::state::CleanupState;
+
+#[tokio::test]
+async fn only_deleted_accounts_are_purged() {
+    let (mock, url) = MockTelegram::start();
+    let state = state_with_telegram(&url).await;
+    add_chat(&state, 1).await;
+    add_member(&state, 1, 11).await;
+    add_member(&state, 1, 12).await;
+    mock.delete_account(11);
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/chats/1/purgeDeleted", serve_api(&state)))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    eventually(|| state.chats_status.get(&1).unwrap().state() == CleanupState::Idle).await;
+
+    let kicked = mock.calls("unbanChatMember");
+    assert_eq!(kicked.len(), 1);
+    assert_eq!(kicked[0]["user_id"], 11);
+    assert_eq!(mock.calls("getChatMember").len(), 2);
+    assert_eq!(member_ids(&state, 1).await, vec![12]);
+}
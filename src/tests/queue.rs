@@ -0,0 +1,782 @@
+use chrono::{Duration, Utc};
+
+use base64::Engine;
+
+use super::{add_chat, png, test_state};
+use crate::{
+    broadcast::{requests_per_chat, MessageOptions},
+    duplicate::DuplicateBroadcast,
+    media::Media,
+    queue::{QueueFilter, QueueStatus},
+    state::{AppState, QueueMode},
+    subscriber::Audience,
+    topic::{ChatTarget, Targets},
+};
+
+pub(super) async fn queue(
+    state: &AppState,
+    targets: impl Into<Targets>,
+    awaiting_approval: bool,
+) -> i32 {
+    state
+        .queue_message_with_images(
+            targets,
+            "hello".to_string(),
+            Vec::new(),
+            Utc::now().to_rfc3339(),
+            MessageOptions::default(),
+            if awaiting_approval {
+                QueueMode::AwaitApproval
+            } else {
+                QueueMode::Send
+            },
+        )
+        .await
+        .unwrap()
+}
+
+/// Every message still to be sent, however far off.
+pub(super) async fn pending(state: &AppState) -> Vec<i32> {
+    state
+        .storage
+        .get_pending_messages(&state.bot_id, Utc::now() + Duration::days(100 * 365), 1000)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|message| message.id)
+        .collect()
+}
+
+#[tokio::test]
+async fn queued_message_keeps_targets_and_options() {
+    let state = test_state().await;
+    let options = MessageOptions {
+        pin: true,
+        silent: true,
+        disable_web_page_preview: true,
+        ..Default::default()
+    };
+
+    let image = base64::engine::general_purpose::STANDARD.encode(png(2, 2));
+    let id = state
+        .queue_message_with_images(
+            Some(vec![
+                ChatTarget::Chat(1),
+                ChatTarget::Thread {
+                    chat_id: 2,
+                    thread_id: 7,
+                },
+            ]),
+            "hello".to_string(),
+            vec![image.clone()],
+            "2030-01-01T00:00:00Z".to_string(),
+            options,
+            QueueMode::Send,
+        )
+        .await
+        .unwrap();
+
+    let message = state.get_queued_message(id).await.unwrap().unwrap();
+    assert_eq!(message.chats, vec![1, 2]);
+    assert!(!message.all_chats);
+    assert_eq!(message.images, vec![Media::new(png(2, 2)).reference()]);
+    assert_eq!(message.datetime.to_rfc3339(), "2030-01-01T00:00:00+00:00");
+    assert!(message.pin && message.silent && message.disable_web_page_preview);
+    assert!(!message.pin_silently);
+
+    let threads = state.get_message_threads(id).await.unwrap();
+    assert_eq!(threads.len(), 1);
+    assert_eq!(threads.get(&2), Some(&7));
+}
+
+#[tokio::test]
+async fn image_files_and_copies_are_stored() {
+    let state = test_state().await;
+
+    let id = state
+        .queue_message_with_image_files(
+            Some(vec![ChatTarget::Chat(1)]),
+            "album".to_string(),
+            vec![png(1, 1), png(2, 2)],
+            Utc::now().to_rfc3339(),
+            MessageOptions::default(),
+            QueueMode::Send,
+        )
+        .await
+        .unwrap();
+    let message = state.get_queued_message(id).await.unwrap().unwrap();
+    let images = state.load_image_entries(&message.images).await.unwrap();
+    assert_eq!(images.len(), 2);
+    assert_eq!(images[0].data, png(1, 1));
+    assert_eq!(images[1].data, png(2, 2));
+
+    let id = state
+        .queue_copied_message(
+            vec![1],
+            -100,
+            42,
+            "copied".to_string(),
+            Utc::now().to_rfc3339(),
+            MessageOptions::default(),
+        )
+        .await
+        .unwrap();
+    let message = state.get_queued_message(id).await.unwrap().unwrap();
+    assert_eq!(message.copy_from_chat_id, Some(-100));
+    assert_eq!(message.copy_message_id, Some(42));
+}
+
+#[tokio::test]
+async fn resent_broadcasts_copy_the_original() {
+    let state = test_state().await;
+    let original = state
+        .queue_message_with_image_files(
+            Some(vec![
+                ChatTarget::Chat(1),
+                ChatTarget::Thread {
+                    chat_id: 2,
+                    thread_id: 7,
+                },
+            ]),
+            "monthly".to_string(),
+            vec![png(2, 2)],
+            Utc::now().to_rfc3339(),
+            MessageOptions {
+                pin: true,
+                ..Default::default()
+            },
+            QueueMode::Send,
+        )
+        .await
+        .unwrap();
+    state.complete_queued_message(original).await.unwrap();
+    let message = state.get_queued_message(original).await.unwrap().unwrap();
+
+    let targets = state.get_queued_targets(&message).await.unwrap();
+    let id = state
+        .resend_broadcast(&message, targets, "2030-01-01T00:00:00Z".to_string())
+        .await
+        .unwrap();
+
+    let copy = state.get_queued_message(id).await.unwrap().unwrap();
+    assert_eq!(copy.chats, vec![1, 2]);
+    assert_eq!(copy.message, "monthly");
+    assert_eq!(copy.datetime.to_rfc3339(), "2030-01-01T00:00:00+00:00");
+    assert!(copy.pin);
+    assert_eq!(
+        state.get_message_threads(id).await.unwrap().get(&2),
+        Some(&7)
+    );
+    assert_eq!(copy.images, message.images);
+    assert_eq!(pending(&state).await, vec![id]);
+
+    let id = state
+        .resend_broadcast(&message, None, Utc::now().to_rfc3339())
+        .await
+        .unwrap();
+    assert!(
+        state
+            .get_queued_message(id)
+            .await
+            .unwrap()
+            .unwrap()
+            .all_chats
+    );
+}
+
+#[tokio::test]
+async fn replies_remember_the_broadcast_they_answer() {
+    let state = test_state().await;
+    let original = queue(&state, Some(vec![ChatTarget::Chat(1)]), false).await;
+
+    let id = state
+        .queue_message_with_images(
+            Some(vec![ChatTarget::Chat(1)]),
+            "follow-up".to_string(),
+            Vec::new(),
+            Utc::now().to_rfc3339(),
+            MessageOptions {
+                reply_to: Some(original),
+                ..Default::default()
+            },
+            QueueMode::Send,
+        )
+        .await
+        .unwrap();
+
+    let message = state.get_queued_message(id).await.unwrap().unwrap();
+    assert_eq!(message.reply_to_queue_id, Some(original));
+    let options = message.options().unwrap();
+    assert_eq!(options.reply_to, Some(original));
+    // resolved per chat while broadcasting
+    assert_eq!(options.reply_to_message_id, None);
+}
+
+#[tokio::test]
+async fn messages_awaiting_approval_are_not_pending() {
+    let state = test_state().await;
+    let id = queue(&state, Some(vec![ChatTarget::Chat(1)]), true).await;
+
+    assert!(pending(&state).await.is_empty());
+    assert!(state.approve_queued_message(id).await.unwrap());
+    assert_eq!(pending(&state).await, vec![id]);
+    // approving twice is a no-op
+    assert!(!state.approve_queued_message(id).await.unwrap());
+}
+
+#[tokio::test]
+async fn claims_are_exclusive_until_the_attempt_fails() {
+    let state = test_state().await;
+    let id = queue(&state, Some(vec![ChatTarget::Chat(1)]), false).await;
+
+    // renewing only extends an existing claim
+    state.storage.renew_claim(id).await.unwrap();
+    assert_eq!(pending(&state).await, vec![id]);
+    assert!(state
+        .storage
+        .claim_queued_message(id)
+        .await
+        .unwrap()
+        .is_some());
+    state.storage.renew_claim(id).await.unwrap();
+    assert!(state
+        .storage
+        .claim_queued_message(id)
+        .await
+        .unwrap()
+        .is_none());
+    assert!(pending(&state).await.is_empty());
+
+    // a failed attempt gives the message back to the queue
+    state.fail_queued_message(id, "boom").await.unwrap();
+    assert_eq!(pending(&state).await, vec![id]);
+    assert!(state
+        .storage
+        .claim_queued_message(id)
+        .await
+        .unwrap()
+        .is_some());
+}
+
+#[tokio::test]
+async fn completed_and_deleted_messages_leave_the_queue() {
+    let state = test_state().await;
+    let completed = queue(&state, Some(vec![ChatTarget::Chat(1)]), false).await;
+    let deleted = queue(&state, Some(vec![ChatTarget::Chat(1)]), false).await;
+    let kept = queue(&state, Some(vec![ChatTarget::Chat(1)]), false).await;
+
+    state.complete_queued_message(completed).await.unwrap();
+    state.delete_queued_message(deleted).await.unwrap();
+
+    assert_eq!(pending(&state).await, vec![kept]);
+    assert!(state.get_queued_message(deleted).await.unwrap().is_none());
+    let progress = state
+        .get_broadcast_progress(completed)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(progress.completed);
+}
+
+#[tokio::test]
+async fn all_chats_resolve_to_unmuted_approved_chats() {
+    let state = test_state().await;
+    for chat_id in [1, 2, 3] {
+        add_chat(&state, chat_id).await;
+    }
+    state.set_chat_muted(2, true).await.unwrap();
+    state
+        .storage
+        .upsert_chat(&state.bot_id, 4, "waiting", false, "group", None)
+        .await
+        .unwrap();
+
+    let id = queue(&state, None, false).await;
+    let message = state.get_queued_message(id).await.unwrap().unwrap();
+    assert!(message.all_chats);
+    assert!(message.chats.is_empty());
+
+    assert_eq!(
+        state
+            .storage
+            .resolve_all_chats(&state.bot_id, id)
+            .await
+            .unwrap(),
+        vec![1, 3]
+    );
+    let message = state.get_queued_message(id).await.unwrap().unwrap();
+    assert_eq!(message.chats, vec![1, 3]);
+}
+
+#[tokio::test]
+async fn excluded_chats_are_left_out_of_all_chats() {
+    let state = test_state().await;
+    for chat_id in [1, 2, 3] {
+        add_chat(&state, chat_id).await;
+    }
+
+    let id = queue(
+        &state,
+        Targets {
+            chats: None,
+            excluded: vec![2],
+        },
+        false,
+    )
+    .await;
+    assert_eq!(state.storage.get_excluded_chats(id).await.unwrap(), vec![2]);
+    assert_eq!(
+        state
+            .storage
+            .resolve_all_chats(&state.bot_id, id)
+            .await
+            .unwrap(),
+        vec![1, 3]
+    );
+}
+
+#[tokio::test]
+async fn recipients_and_send_time_are_previewed_without_queueing() {
+    let state = test_state().await;
+    for chat_id in [1, 2, 3] {
+        add_chat(&state, chat_id).await;
+    }
+    state.set_chat_muted(2, true).await.unwrap();
+    state
+        .storage
+        .upsert_chat(&state.bot_id, 4, "waiting", false, "group", None)
+        .await
+        .unwrap();
+    state.set_chat_rate_limit(3, Some(6)).await.unwrap();
+
+    let mut everyone = state
+        .get_recipients(&Targets::default(), Audience::Chats)
+        .await
+        .unwrap();
+    everyone.chats.sort();
+    assert_eq!(everyone.chats, vec![1, 3]);
+    assert!(everyone.skipped.is_empty());
+
+    let targets = [1, 2, 4].map(ChatTarget::Chat);
+    let recipients = state
+        .get_recipients(&Targets::from(Some(targets.to_vec())), Audience::Chats)
+        .await
+        .unwrap();
+    assert_eq!(recipients.chats, vec![1]);
+    assert_eq!(
+        serde_json::to_value(&recipients.skipped).unwrap(),
+        serde_json::json!([
+            { "chat_id": 2, "error": "chat is muted" },
+            { "chat_id": 4, "error": "chat is not approved" },
+        ])
+    );
+    assert!(pending(&state).await.is_empty());
+
+    // two albums, the text and the pin, a second apart in the same chat
+    let requests = requests_per_chat(11, 1, true);
+    assert_eq!(requests, 4);
+    assert_eq!(
+        state
+            .estimate_broadcast_duration(&[1], requests)
+            .await
+            .unwrap(),
+        std::time::Duration::from_millis(3050)
+    );
+    // chat 3 may only receive a message every ten seconds
+    assert_eq!(
+        state
+            .estimate_broadcast_duration(&[1, 3], requests)
+            .await
+            .unwrap(),
+        std::time::Duration::from_millis(3050 + 30050)
+    );
+}
+
+#[tokio::test]
+async fn broadcasts_due_before_delay_the_estimate() {
+    let state = test_state().await;
+    for chat_id in [1, 2, 3] {
+        add_chat(&state, chat_id).await;
+    }
+    state.set_chat_rate_limit(3, Some(6)).await.unwrap();
+
+    let estimate = state.estimate_broadcast(&[1], 2, Utc::now()).await.unwrap();
+    assert_eq!(estimate.requests, 2);
+    assert_eq!(estimate.backlog_messages, 0);
+    assert_eq!(estimate.estimated_duration_secs, 2);
+
+    // the text and the pin: a second for chats 1 and 2, ten for chat 3
+    state
+        .queue_message_with_images(
+            None,
+            "hello".to_string(),
+            Vec::new(),
+            Utc::now().to_rfc3339(),
+            MessageOptions {
+                pin: true,
+                ..Default::default()
+            },
+            QueueMode::Send,
+        )
+        .await
+        .unwrap();
+    queue(&state, None, true).await;
+    let estimate = state.estimate_broadcast(&[1], 2, Utc::now()).await.unwrap();
+    assert_eq!(estimate.backlog_messages, 1);
+    assert_eq!(estimate.backlog_secs, 13);
+    assert_eq!(estimate.send_duration_secs, 2);
+    assert_eq!(estimate.estimated_duration_secs, 14);
+
+    // the queue is through with it long before
+    let estimate = state
+        .estimate_broadcast(&[1], 2, Utc::now() + Duration::hours(1))
+        .await
+        .unwrap();
+    assert_eq!(estimate.backlog_messages, 1);
+    assert_eq!(estimate.estimated_duration_secs, 2);
+}
+
+#[tokio::test]
+async fn deliveries_count_towards_progress() {
+    let state = test_state().await;
+    let id = queue(
+        &state,
+        Some(vec![
+            ChatTarget::Chat(1),
+            ChatTarget::Chat(2),
+            ChatTarget::Chat(3),
+        ]),
+        false,
+    )
+    .await;
+
+    state
+        .storage
+        .record_delivery(id, 1, None, None, None)
+        .await
+        .unwrap();
+    state
+        .storage
+        .record_delivery(id, 2, Some("chat is muted".into()), None, None)
+        .await
+        .unwrap();
+
+    let progress = state.get_broadcast_progress(id).await.unwrap().unwrap();
+    assert_eq!(
+        (
+            progress.total,
+            progress.sent,
+            progress.failed,
+            progress.completed
+        ),
+        (3, 1, 1, false)
+    );
+
+    let mut delivered = state.storage.get_delivered_chats(id).await.unwrap();
+    delivered.sort();
+    assert_eq!(delivered, vec![1, 2]);
+
+    // a datetime in the future is still pending, the loop decides when it is due
+    let later = state
+        .queue_message_with_images(
+            Some(vec![ChatTarget::Chat(1)]),
+            "later".to_string(),
+            Vec::new(),
+            (Utc::now() + Duration::days(1)).to_rfc3339(),
+            MessageOptions::default(),
+            QueueMode::Send,
+        )
+        .await
+        .unwrap();
+    assert_eq!(pending(&state).await, vec![id, later]);
+}
+
+#[tokio::test]
+async fn only_messages_due_within_the_lookahead_are_fetched() {
+    let state = test_state().await;
+    let queue_at = |datetime: String| {
+        let state = state.clone();
+        async move {
+            state
+                .queue_message_with_images(
+                    Some(vec![ChatTarget::Chat(1)]),
+                    "hello".to_string(),
+                    Vec::new(),
+                    datetime,
+                    MessageOptions::default(),
+                    QueueMode::Send,
+                )
+                .await
+                .unwrap()
+        }
+    };
+    let tomorrow = queue_at((Utc::now() + Duration::days(1)).to_rfc3339()).await;
+    let soon = queue_at((Utc::now() + Duration::minutes(1)).to_rfc3339()).await;
+    let later = queue_at("2023-07-01T11:00:00Z".to_string()).await;
+    // 10:00 UTC, ordered by the time and not the text
+    let due = queue_at("2023-07-01T12:00:00+02:00".to_string()).await;
+
+    let fetch = |until, limit| {
+        let state = state.clone();
+        async move {
+            state
+                .storage
+                .get_pending_messages(&state.bot_id, until, limit)
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|message| message.id)
+                .collect::<Vec<_>>()
+        }
+    };
+    assert_eq!(fetch(Utc::now(), 10).await, vec![due, later]);
+    assert_eq!(
+        fetch(Utc::now() + Duration::hours(1), 10).await,
+        vec![due, later, soon]
+    );
+    assert_eq!(
+        fetch(Utc::now() + Duration::days(2), 2).await,
+        vec![due, later]
+    );
+    assert_eq!(fetch(Utc::now() + Duration::days(2), 10).await[3], tomorrow);
+
+    let bad = state
+        .queue_message_with_images(
+            Some(vec![ChatTarget::Chat(1)]),
+            "hello".to_string(),
+            Vec::new(),
+            "next tuesday".to_string(),
+            MessageOptions::default(),
+            QueueMode::Send,
+        )
+        .await;
+    assert!(bad.is_err());
+}
+
+#[tokio::test]
+async fn repeated_texts_for_the_same_chats_are_duplicates() {
+    let state = test_state().await;
+    let id = queue(&state, Some(vec![ChatTarget::Chat(1)]), false).await;
+
+    let duplicate = |targets: Option<Vec<ChatTarget>>, text: &'static str| {
+        let state = state.clone();
+        async move {
+            state
+                .find_duplicate_broadcast(text, targets.as_deref(), Audience::Chats)
+                .await
+                .unwrap()
+        }
+    };
+    assert_eq!(
+        duplicate(
+            Some(vec![ChatTarget::Chat(2), ChatTarget::Chat(1)]),
+            "hello"
+        )
+        .await,
+        Some(id)
+    );
+    assert_eq!(duplicate(None, "hello").await, Some(id));
+    assert_eq!(
+        duplicate(Some(vec![ChatTarget::Chat(2)]), "hello").await,
+        None
+    );
+    assert_eq!(
+        duplicate(Some(vec![ChatTarget::Chat(1)]), "bye").await,
+        None
+    );
+    assert_eq!(
+        state
+            .find_duplicate_broadcast("hello", None, Audience::Subscribers)
+            .await
+            .unwrap(),
+        None
+    );
+}
+
+#[tokio::test]
+async fn concurrent_duplicates_are_queued_once() {
+    let state = test_state().await;
+    let send = || {
+        state.queue_message_with_images(
+            Some(vec![ChatTarget::Chat(1)]),
+            "hello".to_string(),
+            Vec::new(),
+            Utc::now().to_rfc3339(),
+            MessageOptions::default(),
+            QueueMode::SendOnce,
+        )
+    };
+
+    let (first, second) = tokio::join!(send(), send());
+    assert!(first.is_ok() != second.is_ok());
+    let id = first.as_ref().or(second.as_ref()).copied().unwrap();
+    let err = send().await.unwrap_err();
+    assert_eq!(err.downcast_ref::<DuplicateBroadcast>().unwrap().0, id);
+}
+
+#[tokio::test]
+async fn the_queue_is_filtered_and_paged_in_the_database() {
+    let state = test_state().await;
+    let later = state
+        .queue_message_with_images(
+            Some(vec![ChatTarget::Chat(1)]),
+            "later".to_string(),
+            Vec::new(),
+            (Utc::now() + Duration::days(1)).to_rfc3339(),
+            MessageOptions::default(),
+            QueueMode::Send,
+        )
+        .await
+        .unwrap();
+    let soon = queue(&state, Some(vec![ChatTarget::Chat(1)]), false).await;
+    let other_chat = queue(&state, Some(vec![ChatTarget::Chat(2)]), false).await;
+    let everyone = queue(&state, None, false).await;
+    let unapproved = queue(&state, Some(vec![ChatTarget::Chat(1)]), true).await;
+    let completed = queue(&state, Some(vec![ChatTarget::Chat(1)]), false).await;
+    state.complete_queued_message(completed).await.unwrap();
+
+    let ids = |filter: QueueFilter, limit: i64, offset: i64| {
+        let state = state.clone();
+        async move {
+            state
+                .get_queue(&filter, limit, offset)
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|entry| entry.id)
+                .collect::<Vec<_>>()
+        }
+    };
+
+    // due soonest first
+    let all = ids(QueueFilter::default(), 50, 0).await;
+    assert_eq!(all.len(), 6);
+    assert_eq!(all.last(), Some(&later));
+    assert_eq!(ids(QueueFilter::default(), 2, 4).await, all[4..]);
+
+    let chat = QueueFilter {
+        chat_id: Some(1),
+        status: Some(QueueStatus::Pending),
+        ..Default::default()
+    };
+    assert_eq!(ids(chat.clone(), 50, 0).await, vec![soon, everyone, later]);
+    let due = QueueFilter {
+        due_before: Some(Utc::now() + Duration::hours(1)),
+        ..chat
+    };
+    assert_eq!(ids(due, 50, 0).await, vec![soon, everyone]);
+
+    let status = |status| QueueFilter {
+        status: Some(status),
+        ..Default::default()
+    };
+    assert_eq!(
+        ids(status(QueueStatus::AwaitingApproval), 50, 0).await,
+        vec![unapproved]
+    );
+    assert_eq!(
+        ids(status(QueueStatus::Completed), 50, 0).await,
+        vec![completed]
+    );
+    assert!(!ids(
+        QueueFilter {
+            chat_id: Some(1),
+            ..Default::default()
+        },
+        50,
+        0
+    )
+    .await
+    .contains(&other_chat));
+}
+
+#[tokio::test]
+async fn messages_failing_too_often_are_set_aside_until_retried() {
+    let state = test_state().await;
+    let id = queue(&state, Some(vec![ChatTarget::Chat(1)]), false).await;
+
+    for _ in 0..state.config.max_send_attempts {
+        assert_eq!(pending(&state).await, vec![id]);
+        state.fail_queued_message(id, "boom").await.unwrap();
+    }
+    assert!(pending(&state).await.is_empty());
+    assert!(state
+        .storage
+        .claim_queued_message(id)
+        .await
+        .unwrap()
+        .is_none());
+
+    let failed = state.get_failed_messages().await.unwrap();
+    assert_eq!(failed.len(), 1);
+    assert_eq!(failed[0].id, id);
+    assert_eq!(failed[0].error, "boom");
+    assert_eq!(failed[0].attempts, state.config.max_send_attempts as i32);
+
+    assert!(state.retry_failed_message(id).await.unwrap());
+    assert!(!state.retry_failed_message(id).await.unwrap());
+    assert!(state.get_failed_messages().await.unwrap().is_empty());
+    assert_eq!(pending(&state).await, vec![id]);
+}
+
+#[tokio::test]
+async fn data_urls_are_inlined_and_image_urls_kept_for_sending() {
+    let state = test_state().await;
+    let image = base64::engine::general_purpose::STANDARD.encode(png(2, 2));
+    let url = "https://example.com/image.png".to_string();
+
+    let id = state
+        .queue_message_with_images(
+            Some(vec![ChatTarget::Chat(1)]),
+            "hello".to_string(),
+            vec![format!("data:image/png;base64,{image}"), url.clone()],
+            Utc::now().to_rfc3339(),
+            MessageOptions::default(),
+            QueueMode::Send,
+        )
+        .await
+        .unwrap();
+
+    let message = state.get_queued_message(id).await.unwrap().unwrap();
+    assert_eq!(message.images, vec![Media::new(png(2, 2)).reference(), url]);
+}
+
+#[tokio::test]
+async fn images_are_stored_once_until_no_message_refers_to_them() {
+    let state = test_state().await;
+    let image = base64::engine::general_purpose::STANDARD.encode(png(2, 2));
+
+    let mut ids = Vec::new();
+    for _ in 0..2 {
+        ids.push(
+            state
+                .queue_message_with_images(
+                    Some(vec![ChatTarget::Chat(1)]),
+                    "hello".to_string(),
+                    vec![image.clone()],
+                    Utc::now().to_rfc3339(),
+                    MessageOptions::default(),
+                    QueueMode::Send,
+                )
+                .await
+                .unwrap(),
+        );
+    }
+    let message = state.get_queued_message(ids[0]).await.unwrap().unwrap();
+
+    state.delete_queued_message(ids[1]).await.unwrap();
+    let images = state.load_image_entries(&message.images).await.unwrap();
+    assert_eq!(images[0].data, png(2, 2));
+    // images queued as base64 before still load
+    assert_eq!(state.load_image_entries(&[image]).await.unwrap().len(), 1);
+
+    state.delete_queued_message(ids[0]).await.unwrap();
+    // messages being queued with them right now may still need them
+    state.delete_unused_media(Utc::now()).await.unwrap();
+    assert!(state.load_image_entries(&message.images).await.is_ok());
+    state
+        .delete_unused_media(Utc::now() + Duration::hours(2))
+        .await
+        .unwrap();
+    assert!(state.load_image_entries(&message.images).await.is_err());
+}
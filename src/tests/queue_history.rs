@@ -0,0 +1,56 @@
+use super::{add_chat, queue::queue, test_state};
+use crate::{queue_history::QueuedMessageEdit, topic::ChatTarget};
+
+#[tokio::test]
+async fn edits_keep_the_replaced_versions() {
+    let state = test_state().await;
+    add_chat(&state, 1).await;
+    let id = queue(&state, Some(vec![ChatTarget::Chat(1)]), false).await;
+    let queued = state.get_queued_message(id).await.unwrap().unwrap();
+
+    let edit = QueuedMessageEdit {
+        message: Some("hello there".to_string()),
+        datetime: None,
+    };
+    assert!(state.edit_queued_message(id, &edit, "ann").await.unwrap());
+    let later = queued.datetime + chrono::Duration::hours(1);
+    let edit = QueuedMessageEdit {
+        message: Some("hello again".to_string()),
+        datetime: Some(later),
+    };
+    assert!(state.edit_queued_message(id, &edit, "bob").await.unwrap());
+
+    let message = state.get_queued_message(id).await.unwrap().unwrap();
+    assert_eq!(message.message, "hello again");
+    assert_eq!(message.datetime, later);
+
+    let history = state.get_queued_message_history(id).await.unwrap().unwrap();
+    let versions: Vec<_> = history
+        .iter()
+        .map(|version| (version.message.as_str(), version.edited_by.as_str()))
+        .collect();
+    assert_eq!(versions, vec![("hello there", "bob"), ("hello", "ann")]);
+    assert_eq!(history[1].datetime, queued.datetime);
+
+    // once a chat got it the text stays
+    state
+        .storage
+        .record_delivery(id, 1, None, None, None)
+        .await
+        .unwrap();
+    assert!(!state.edit_queued_message(id, &edit, "ann").await.unwrap());
+    assert_eq!(
+        state
+            .get_queued_message_history(id)
+            .await
+            .unwrap()
+            .unwrap()
+            .len(),
+        2
+    );
+    assert!(state
+        .get_queued_message_history(id + 1)
+        .await
+        .unwrap()
+        .is_none());
+}
@@ -0,0 +1,134 @@
+use base64::Engine;
+
+use super::{add_chat, png, test_state};
+use crate::{
+    broadcast::MessageOptions, media::Media, queue_transfer::QueueExport, state::QueueMode,
+    topic::ChatTarget,
+};
+
+#[tokio::test]
+async fn export_is_imported_under_new_ids() {
+    let source = test_state().await;
+    add_chat(&source, 1).await;
+    let image = base64::engine::general_purpose::STANDARD.encode(png(2, 2));
+    let first = source
+        .queue_message_with_images(
+            Some(vec![ChatTarget::Chat(1)]),
+            "first".to_string(),
+            vec![image],
+            "2030-01-01T00:00:00Z".to_string(),
+            MessageOptions::default(),
+            QueueMode::Send,
+        )
+        .await
+        .unwrap();
+    let reply = source
+        .queue_message_with_images(
+            Some(vec![ChatTarget::Chat(1)]),
+            "reply".to_string(),
+            Vec::new(),
+            "2030-01-02T00:00:00Z".to_string(),
+            MessageOptions {
+                reply_to: Some(first),
+                ..Default::default()
+            },
+            QueueMode::AwaitApproval,
+        )
+        .await
+        .unwrap();
+
+    let export = source.export_queue().await.unwrap();
+    let json = serde_json::to_string(&export).unwrap();
+    assert_eq!(export.messages.len(), 2);
+    let reference = Media::new(png(2, 2)).reference();
+    assert_eq!(export.messages[0].images, vec![reference.clone()]);
+    assert!(export.messages[1].awaiting_approval);
+
+    let target = test_state().await;
+    add_chat(&target, 1).await;
+    // shifts the ids of the target database
+    target
+        .queue_message_with_images(
+            Some(vec![ChatTarget::Chat(1)]),
+            "other".to_string(),
+            Vec::new(),
+            "2030-01-01T00:00:00Z".to_string(),
+            MessageOptions::default(),
+            QueueMode::Send,
+        )
+        .await
+        .unwrap();
+
+    let export: QueueExport = serde_json::from_str(&json).unwrap();
+    let report = target.import_queue(export, false).await.unwrap();
+    assert!(report.conflicts.is_empty());
+    assert_eq!(report.imported.len(), 2);
+    assert_eq!(report.imported[0].id, first);
+    assert_eq!(report.imported[1].id, reply);
+    let new_first = report.imported[0].new_id.unwrap();
+    let new_reply = report.imported[1].new_id.unwrap();
+    assert_ne!(new_first, first);
+
+    let message = target.get_queued_message(new_first).await.unwrap().unwrap();
+    assert_eq!(message.images, vec![reference]);
+    let images = target.load_image_entries(&message.images).await.unwrap();
+    assert_eq!(images[0].data, png(2, 2));
+    let message = target.get_queued_message(new_reply).await.unwrap().unwrap();
+    assert_eq!(message.reply_to_queue_id, Some(new_first));
+
+    // importing again finds both queued already
+    let export: QueueExport = serde_json::from_str(&json).unwrap();
+    let report = target.import_queue(export, false).await.unwrap();
+    assert!(report.imported.is_empty());
+    assert_eq!(report.conflicts.len(), 2);
+    assert_eq!(
+        report.conflicts[0].reason,
+        format!("already queued as message {new_first}")
+    );
+}
+
+#[tokio::test]
+async fn conflicting_messages_are_left_out() {
+    let source = test_state().await;
+    add_chat(&source, 1).await;
+    add_chat(&source, 2).await;
+    let first = source
+        .queue_message_with_images(
+            Some(vec![ChatTarget::Chat(2)]),
+            "first".to_string(),
+            Vec::new(),
+            "2030-01-01T00:00:00Z".to_string(),
+            MessageOptions::default(),
+            QueueMode::Send,
+        )
+        .await
+        .unwrap();
+    source
+        .queue_message_with_images(
+            Some(vec![ChatTarget::Chat(1)]),
+            "reply".to_string(),
+            Vec::new(),
+            "2030-01-02T00:00:00Z".to_string(),
+            MessageOptions {
+                reply_to: Some(first),
+                ..Default::default()
+            },
+            QueueMode::Send,
+        )
+        .await
+        .unwrap();
+    let export = source.export_queue().await.unwrap();
+
+    // the target doesn't know chat 2
+    let target = test_state().await;
+    add_chat(&target, 1).await;
+    let report = target.import_queue(export, true).await.unwrap();
+    assert!(report.dry_run);
+    assert!(report.imported.is_empty());
+    assert_eq!(report.conflicts.len(), 2);
+    assert_eq!(report.conflicts[0].reason, "unknown chats: 2");
+    assert_eq!(
+        report.conflicts[1].reason,
+        format!("replies to message {first}, which was not imported")
+    );
+}
@@ -0,0 +1,120 @@
+use chrono::{DateTime, Duration, Utc};
+
+use super::{add_chat, mock_telegram::MockTelegram, queue::queue, state_with_telegram};
+use crate::{quiet_hours::QuietHours, topic::ChatTarget};
+
+fn quiet_hours(start_time: &str, end_time: &str, utc_offset_minutes: i32) -> QuietHours {
+    QuietHours {
+        start_time: start_time.to_string(),
+        end_time: end_time.to_string(),
+        utc_offset_minutes,
+    }
+}
+
+fn at(datetime: &str) -> DateTime<Utc> {
+    datetime.parse().unwrap()
+}
+
+#[test]
+fn windows_spanning_midnight_end_the_next_morning() {
+    let night = quiet_hours("22:00", "08:00", 0);
+
+    assert_eq!(night.ends_at(at("2023-06-01T21:59:00Z")), None);
+    assert_eq!(
+        night.ends_at(at("2023-06-01T23:30:00Z")),
+        Some(at("2023-06-02T08:00:00Z"))
+    );
+    assert_eq!(
+        night.ends_at(at("2023-06-02T07:59:00Z")),
+        Some(at("2023-06-02T08:00:00Z"))
+    );
+    assert_eq!(night.ends_at(at("2023-06-02T08:00:00Z")), None);
+}
+
+#[test]
+fn windows_are_in_the_local_time_of_the_chat() {
+    // 22:00 to 08:00 at UTC+02:00 is 20:00 to 06:00 UTC
+    let night = quiet_hours("22:00", "08:00", 120);
+
+    assert_eq!(
+        night.ends_at(at("2023-06-01T20:30:00Z")),
+        Some(at("2023-06-02T06:00:00Z"))
+    );
+    assert_eq!(night.ends_at(at("2023-06-02T06:30:00Z")), None);
+    assert!(quiet_hours("25:00", "08:00", 0).validate().is_err());
+    assert!(quiet_hours("22:00", "08:00", 15 * 60).validate().is_err());
+}
+
+#[tokio::test]
+async fn broadcasts_wait_for_the_quiet_hours_of_a_chat() {
+    let (mock, url) = MockTelegram::start();
+    let state = state_with_telegram(&url).await;
+    add_chat(&state, 1).await;
+    add_chat(&state, 2).await;
+
+    let now = Utc::now();
+    let window = quiet_hours(
+        &(now - Duration::hours(1)).format("%H:%M").to_string(),
+        &(now + Duration::hours(1)).format("%H:%M").to_string(),
+        0,
+    );
+    state.set_quiet_hours(1, &window).await.unwrap();
+
+    let id = queue(
+        &state,
+        Some(vec![ChatTarget::Chat(1), ChatTarget::Chat(2)]),
+        false,
+    )
+    .await;
+    let message = state.get_queued_message(id).await.unwrap().unwrap();
+    let deferred_until = state.broadcast(&message).await.unwrap();
+
+    assert_eq!(deferred_until, window.ends_at(now));
+    let sent = mock.calls("sendMessage");
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0]["chat_id"], 2);
+    let progress = state.get_broadcast_progress(id).await.unwrap().unwrap();
+    assert_eq!((progress.sent, progress.deferred), (1, 1));
+    let deliveries = state.get_deliveries(id).await.unwrap();
+    let deferred = deliveries.iter().find(|d| d.chat_id == 1).unwrap();
+    assert_eq!(deferred.deferred_until, deferred_until);
+
+    // once the window is over the chat gets the message
+    state.delete_quiet_hours(1).await.unwrap();
+    assert_eq!(state.broadcast(&message).await.unwrap(), None);
+    assert_eq!(mock.calls("sendMessage").len(), 2);
+    let progress = state.get_broadcast_progress(id).await.unwrap().unwrap();
+    assert_eq!((progress.sent, progress.deferred), (2, 0));
+}
+
+#[tokio::test]
+async fn deferred_broadcasts_keep_their_scheduled_time() {
+    let (_mock, url) = MockTelegram::start();
+    let state = state_with_telegram(&url).await;
+    let id = queue(&state, Some(vec![ChatTarget::Chat(1)]), false).await;
+    let scheduled = state
+        .get_queued_message(id)
+        .await
+        .unwrap()
+        .unwrap()
+        .datetime;
+
+    let until = Utc::now() + Duration::hours(1);
+    state.storage.defer_queued_message(id, until).await.unwrap();
+
+    let pending = |until| {
+        let state = state.clone();
+        async move {
+            state
+                .storage
+                .get_pending_messages(&state.bot_id, until, 10)
+                .await
+                .unwrap()
+        }
+    };
+    assert!(pending(Utc::now()).await.is_empty());
+    let due = pending(until).await;
+    assert_eq!((due[0].id, due[0].datetime), (id, until));
+    let message = state.get_queued_message(id).await.unwrap().unwrap();
+    assert_eq!(message.datetime, scheduled);
+}
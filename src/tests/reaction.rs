@@ -0,0 +1,64 @@
+use serde_json::json;
+
+use crate::reaction::{parse_reaction_update, ReactionUpdate};
+
+#[test]
+fn reaction_changes_are_diffed() {
+    let update = json!({
+        "update_id": 1,
+        "message_reaction": {
+            "chat": { "id": -100, "type": "supergroup", "title": "chat" },
+            "message_id": 5,
+            "user": { "id": 10, "is_bot": false, "first_name": "user" },
+            "date": 0,
+            "old_reaction": [
+                { "type": "emoji", "emoji": "👍" },
+                { "type": "emoji", "emoji": "🔥" },
+            ],
+            "new_reaction": [
+                { "type": "emoji", "emoji": "🔥" },
+                { "type": "custom_emoji", "custom_emoji_id": "42" },
+                { "type": "paid" },
+            ],
+        },
+    });
+
+    assert_eq!(
+        parse_reaction_update(&update),
+        Some(ReactionUpdate::Changed {
+            chat_id: -100,
+            message_id: 5,
+            removed: vec!["👍".to_string()],
+            added: vec!["custom:42".to_string()],
+        })
+    );
+}
+
+#[test]
+fn anonymous_reactions_are_totals() {
+    let update = json!({
+        "update_id": 2,
+        "message_reaction_count": {
+            "chat": { "id": -100, "type": "channel", "title": "news" },
+            "message_id": 7,
+            "date": 0,
+            "reactions": [
+                { "type": { "type": "emoji", "emoji": "❤" }, "total_count": 3 },
+                { "type": { "type": "emoji", "emoji": "👍" }, "total_count": 1 },
+            ],
+        },
+    });
+
+    assert_eq!(
+        parse_reaction_update(&update),
+        Some(ReactionUpdate::Counts {
+            chat_id: -100,
+            message_id: 7,
+            counts: vec![("❤".to_string(), 3), ("👍".to_string(), 1)],
+        })
+    );
+    assert_eq!(
+        parse_reaction_update(&json!({ "update_id": 3, "business_message": {} })),
+        None
+    );
+}
@@ -0,0 +1,115 @@
+use std::time::Duration;
+
+use chrono::Utc;
+
+use super::{
+    add_chat, add_member, member_ids, mock_telegram::MockTelegram, queue::queue,
+    state_with_telegram,
+};
+use crate::{
+    state::{AppState, ChatCleaningStatus},
+    topic::ChatTarget,
+};
+
+async fn wait_for_idle(state: &AppState, chat_id: i64) {
+    for _ in 0..50 {
+        if matches!(
+            state.chats_status.get(&chat_id).as_deref(),
+            Some(ChatCleaningStatus::Idle)
+        ) {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("the cleanup of chat:{chat_id} didn't finish");
+}
+
+#[tokio::test]
+async fn interrupted_cleanups_and_broadcasts_are_found_and_resumed() {
+    let (mock, url) = MockTelegram::start();
+    let state = state_with_telegram(&url).await;
+    add_chat(&state, 1).await;
+    for user_id in [10, 11] {
+        add_member(&state, 1, user_id).await;
+    }
+    // what a process dying mid-cleanup and mid-broadcast leaves behind
+    state
+        .storage
+        .start_cleanup_run(1, false, "api")
+        .await
+        .unwrap();
+    let id = queue(&state, Some(vec![ChatTarget::Chat(1)]), false).await;
+    state.storage.claim_queued_message(id).await.unwrap();
+
+    state.reconcile().await.unwrap();
+    let interrupted = state.get_interrupted();
+    assert_eq!(interrupted.cleanups.len(), 1);
+    assert_eq!(interrupted.cleanups[0].initiator, "api");
+    assert_eq!(interrupted.broadcasts, vec![id]);
+    assert!(matches!(
+        state.chats_status.get(&1).as_deref(),
+        Some(ChatCleaningStatus::Resumable(_))
+    ));
+
+    // claimed messages stay with the crashed process until resumed
+    state.message_queue_loop().await.unwrap();
+    assert!(mock.calls("sendMessage").is_empty());
+    assert!(state.resume_broadcast(id).await.unwrap());
+    assert!(!state.resume_broadcast(id).await.unwrap());
+    state.message_queue_loop().await.unwrap();
+    assert_eq!(mock.calls("sendMessage").len(), 1);
+
+    assert!(state.resume_cleanup(1, "admin").unwrap());
+    wait_for_idle(&state, 1).await;
+    assert!(state.get_all_members(1).await.unwrap().is_empty());
+    assert!(state
+        .storage
+        .get_cleanup_runs(&state.bot_id)
+        .await
+        .unwrap()
+        .is_empty());
+    assert!(!state.resume_cleanup(1, "admin").unwrap());
+}
+
+#[tokio::test]
+async fn long_flood_waits_pause_cleanups_where_they_stopped() {
+    let (mock, url) = MockTelegram::start();
+    let state = state_with_telegram(&url).await;
+    add_chat(&state, 1).await;
+    for user_id in [10, 11, 12, 13] {
+        add_member(&state, 1, user_id).await;
+    }
+    // longer than `cleanup_max_flood_wait`
+    mock.flood_kicks(12, 600);
+
+    assert!(state.queue_cleanup(1).unwrap());
+    state.delete_all_members(1, "api").await.unwrap();
+    let run = match state.chats_status.get(&1).as_deref() {
+        Some(ChatCleaningStatus::Resumable(run)) => run.clone(),
+        _ => panic!("the cleanup didn't pause"),
+    };
+    assert_eq!(run.last_user_id, Some(11));
+    let resume_at = run.resume_at.unwrap();
+    assert!(resume_at > Utc::now() + chrono::Duration::seconds(590));
+    assert_eq!(member_ids(&state, 1).await, vec![12, 13]);
+    // the record stays for going on after a restart
+    let runs = state.storage.get_cleanup_runs(&state.bot_id).await.unwrap();
+    assert_eq!(runs[0].resume_at, Some(resume_at));
+
+    mock.unflood_kicks(12);
+    assert!(state.resume_cleanup(1, "admin").unwrap());
+    wait_for_idle(&state, 1).await;
+    assert!(state.get_all_members(1).await.unwrap().is_empty());
+    let kicked: Vec<i64> = mock
+        .calls("unbanChatMember")
+        .iter()
+        .map(|call| call["user_id"].as_i64().unwrap())
+        .collect();
+    assert_eq!(kicked, vec![10, 11, 12, 12, 13]);
+    assert!(state
+        .storage
+        .get_cleanup_runs(&state.bot_id)
+        .await
+        .unwrap()
+        .is_empty());
+}
@@ -0,0 +1,69 @@
+use serde_json::json;
+
+use super::{
+    add_chat,
+    e2e::{message, user},
+    mock_telegram::MockTelegram,
+    state_with_telegram, test_state,
+};
+use crate::{broadcast::MessageOptions, state::QueueMode, topic::ChatTarget};
+
+#[tokio::test]
+async fn replies_are_only_collected_with_postgres() {
+    let state = test_state().await;
+    add_chat(&state, 1).await;
+    let id = state
+        .queue_message_with_images(
+            Some(vec![ChatTarget::Chat(1)]),
+            "what do you think?".to_string(),
+            Vec::new(),
+            chrono::Utc::now().to_rfc3339(),
+            MessageOptions {
+                collect_replies: true,
+                ..Default::default()
+            },
+            QueueMode::Send,
+        )
+        .await
+        .unwrap();
+    let queued = state.get_queued_message(id).await.unwrap().unwrap();
+    assert!(queued.options().unwrap().collect_replies);
+
+    // sqlite keeps no sent messages to match the reply by
+    let reply = message(
+        1,
+        json!({
+            "message_id": 2,
+            "from": user(10),
+            "text": "looks good",
+            "reply_to_message": {
+                "message_id": 1,
+                "date": 0,
+                "chat": { "id": 1, "type": "supergroup", "title": "chat" },
+                "text": "what do you think?",
+            },
+        }),
+    );
+    assert!(!state.record_reply(&reply).await.unwrap());
+    assert!(state.get_broadcast_replies(id, 50, 0).await.is_err());
+}
+
+#[tokio::test]
+async fn messages_answer_the_broadcast_they_reply_to() {
+    let (mock, url) = MockTelegram::start();
+    let state = state_with_telegram(&url).await;
+    add_chat(&state, 1).await;
+
+    let options = MessageOptions {
+        reply_to_message_id: Some(5),
+        ..Default::default()
+    };
+    state
+        .send_message_to_chat(1, "see above", &options)
+        .await
+        .unwrap();
+
+    let calls = mock.calls("sendMessage");
+    assert_eq!(calls[0]["reply_to_message_id"], json!(5));
+    assert_eq!(calls[0]["allow_sending_without_reply"], json!(true));
+}
@@ -0,0 +1,76 @@
+use chrono::{Duration, TimeZone, Utc, Weekday};
+
+use super::{add_chat, mock_telegram::MockTelegram, state_with_config};
+use crate::{broadcast::MessageOptions, report::last_slot, state::QueueMode};
+
+#[test]
+fn the_last_slot_is_the_latest_at_or_before_now() {
+    // a wednesday
+    let now = Utc.with_ymd_and_hms(2023, 8, 2, 12, 30, 0).unwrap();
+
+    assert_eq!(
+        last_slot(now, Weekday::Mon, 9),
+        Utc.with_ymd_and_hms(2023, 7, 31, 9, 0, 0).unwrap()
+    );
+    assert_eq!(
+        last_slot(now, Weekday::Wed, 12),
+        Utc.with_ymd_and_hms(2023, 8, 2, 12, 0, 0).unwrap()
+    );
+    // later today only comes next week
+    assert_eq!(
+        last_slot(now, Weekday::Wed, 13),
+        Utc.with_ymd_and_hms(2023, 7, 26, 13, 0, 0).unwrap()
+    );
+}
+
+#[tokio::test]
+async fn the_weekly_report_counts_the_week_and_is_sent_once() {
+    let (mock, url) = MockTelegram::start();
+    let state = state_with_config(|config| {
+        config.telegram_api_url = Some(url);
+        config.alerts.chat_id = Some(-900);
+        config.alerts.weekly_report = true;
+    })
+    .await;
+    for chat_id in [1, 2, 3] {
+        add_chat(&state, chat_id).await;
+    }
+    mock.fail_chat(3, "Bad Request: not enough rights to send text messages");
+    state
+        .queue_message_with_images(
+            None,
+            "hello".to_string(),
+            Vec::new(),
+            Utc::now().to_rfc3339(),
+            MessageOptions::default(),
+            QueueMode::Send,
+        )
+        .await
+        .unwrap();
+    state.message_queue_loop().await.unwrap();
+    state.archive_kicked_chat(2, "chat 2").await.unwrap();
+
+    // sqlite compares times to a fraction of a millisecond only
+    let to = Utc::now() + Duration::seconds(1);
+    let report = state.weekly_report(to).await.unwrap();
+    assert_eq!(report.chats, 2);
+    assert_eq!(report.chats_added, 3);
+    assert_eq!(report.chats_lost, 1);
+    assert_eq!(report.broadcasts_sent, 1);
+    assert_eq!((report.deliveries, report.failed_deliveries), (3, 1));
+    assert_eq!(report.top_errors.len(), 1);
+    assert_eq!(report.top_errors[0].count, 1);
+    assert!(report.to_html().contains("Broadcasts sent: 1"));
+
+    let now = Utc::now();
+    assert!(state.send_weekly_report_if_due(now).await.unwrap());
+    assert!(!state.send_weekly_report_if_due(now).await.unwrap());
+    let sent: Vec<_> = mock
+        .calls("sendMessage")
+        .into_iter()
+        .filter(|call| call["chat_id"] == -900)
+        .filter(|call| call["text"].as_str().unwrap().contains("Weekly report"))
+        .collect();
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0]["parse_mode"], "HTML");
+}
@@ -0,0 +1,58 @@
+use super::{add_chat, add_member, mock_telegram::MockTelegram, state_with_telegram};
+use crate::state::ChatCleaningStatus;
+
+#[tokio::test]
+async fn resync_drops_departed_members_and_refreshes_the_rest() {
+    let (mock, url) = MockTelegram::start();
+    let state = state_with_telegram(&url).await;
+    add_chat(&state, 1).await;
+    for user_id in [10, 11, 12] {
+        add_member(&state, 1, user_id).await;
+    }
+    mock.depart(11);
+    mock.add_admin(12);
+
+    assert!(state.start_resync(1).unwrap());
+    // the member list is busy until the resync is done
+    assert!(!state.start_resync(1).unwrap());
+    assert!(!state.queue_cleanup(1).unwrap());
+    state.resync_members(1).await.unwrap();
+
+    let members = state.get_all_members(1).await.unwrap();
+    assert_eq!(members.len(), 1);
+    assert_eq!(members[0].id, 10);
+    assert_eq!(members[0].name, "user");
+    let admins = state.get_chat_admins(1).await.unwrap();
+    assert_eq!(admins.len(), 1);
+    assert_eq!(admins[0].user_id, 12);
+
+    assert!(matches!(
+        state.chats_status.get(&1).as_deref(),
+        Some(ChatCleaningStatus::Idle)
+    ));
+    let record = state.cleanup_records.get(&1).unwrap();
+    let progress = record.last_resync.as_ref().unwrap();
+    assert_eq!(
+        (progress.total, progress.processed, progress.updated),
+        (3, 3, 1)
+    );
+    assert_eq!((progress.removed, progress.failed), (2, 0));
+}
+
+#[tokio::test]
+async fn resync_of_a_chat_the_bot_left_fails() {
+    let (mock, url) = MockTelegram::start();
+    let state = state_with_telegram(&url).await;
+    add_chat(&state, 1).await;
+    add_member(&state, 1, 10).await;
+    mock.fail_chat(1, "Forbidden: bot was kicked from the supergroup chat");
+
+    assert!(state.start_resync(1).unwrap());
+    assert!(state.resync_members(1).await.is_err());
+
+    assert!(matches!(
+        state.chats_status.get(&1).as_deref(),
+        Some(ChatCleaningStatus::Error(_))
+    ));
+    assert_eq!(state.get_all_members(1).await.unwrap().len(), 1);
+}
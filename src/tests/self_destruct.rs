@@ -0,0 +1,32 @@
+use super::{add_chat, mock_telegram::MockTelegram, state_with_telegram};
+use crate::{broadcast::MessageOptions, state::QueueMode, topic::ChatTarget};
+
+#[tokio::test]
+async fn self_destructing_broadcasts_need_postgres() {
+    let (mock, url) = MockTelegram::start();
+    let state = state_with_telegram(&url).await;
+    add_chat(&state, 1).await;
+    let id = state
+        .queue_message_with_images(
+            Some(vec![ChatTarget::Chat(1)]),
+            "half price until noon".to_string(),
+            Vec::new(),
+            chrono::Utc::now().to_rfc3339(),
+            MessageOptions {
+                delete_after_seconds: Some(60),
+                ..Default::default()
+            },
+            QueueMode::Send,
+        )
+        .await
+        .unwrap();
+    let queued = state.get_queued_message(id).await.unwrap().unwrap();
+    assert_eq!(queued.options().unwrap().delete_after_seconds, Some(60));
+
+    state.message_queue_loop().await.unwrap();
+    assert_eq!(mock.calls("sendMessage").len(), 1);
+
+    // sqlite keeps no sent messages to delete them by
+    assert_eq!(state.delete_expired_broadcasts().await.unwrap(), 0);
+    assert!(mock.calls("deleteMessage").is_empty());
+}
@@ -0,0 +1,84 @@
+use axum::http::{header::COOKIE, HeaderMap};
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+
+use super::{state_with_config, test_state};
+use crate::session::TelegramLogin;
+
+/// A login signed the way the widget signs it for the bot token `0:test`.
+fn signed_login(id: i64, auth_date: i64) -> TelegramLogin {
+    let data = format!("auth_date={auth_date}\nfirst_name=Ann\nid={id}\nusername=ann");
+    let mut mac = Hmac::<Sha256>::new_from_slice(&Sha256::digest(b"0:test")).unwrap();
+    mac.update(data.as_bytes());
+    let hash = mac
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+
+    TelegramLogin {
+        id,
+        first_name: "Ann".to_string(),
+        last_name: None,
+        username: Some("ann".to_string()),
+        photo_url: None,
+        auth_date,
+        hash,
+    }
+}
+
+#[test]
+fn widget_logins_are_verified() {
+    let now = Utc::now().timestamp();
+    assert!(signed_login(1, now).verify("0:test").is_ok());
+    assert!(signed_login(1, now).verify("0:other").is_err());
+    assert!(signed_login(1, now - 2 * 24 * 60 * 60)
+        .verify("0:test")
+        .is_err());
+
+    let mut tampered = signed_login(1, now);
+    tampered.id = 2;
+    assert!(tampered.verify("0:test").is_err());
+}
+
+#[tokio::test]
+async fn sessions_name_the_user_as_initiator() {
+    let state = state_with_config(|config| config.admin_ids = vec![1]).await;
+
+    assert!(state
+        .login(&signed_login(2, Utc::now().timestamp()))
+        .is_err());
+    let (token, session) = state
+        .login(&signed_login(1, Utc::now().timestamp()))
+        .unwrap();
+    assert_eq!(session.actor(), "telegram:@ann");
+
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        COOKIE,
+        format!("theme=dark; session={token}").parse().unwrap(),
+    );
+    assert_eq!(state.request_session(&headers).unwrap().user_id, 1);
+
+    let forged = format!("{token}x");
+    assert!(state.verify_session(&forged).is_none());
+}
+
+#[tokio::test]
+async fn confirmation_tokens_only_confirm_their_action() {
+    let state = test_state().await;
+    let (token, expires_at) = state.confirmation_token("clear:-100");
+    assert!(expires_at > Utc::now());
+
+    assert!(state.verify_confirmation("clear:-100", &token));
+    assert!(!state.verify_confirmation("clear:-200", &token));
+    assert!(!state.verify_confirmation("clear:-100", "garbage"));
+    // the expiry is covered by the signature
+    let (_, signature) = token.split_once('.').unwrap();
+    let extended = format!("{}.{signature}", expires_at.timestamp() + 3600);
+    assert!(!state.verify_confirmation("clear:-100", &extended));
+    let expired = format!("{}.{signature}", Utc::now().timestamp() - 1);
+    assert!(!state.verify_confirmation("clear:-100", &expired));
+}
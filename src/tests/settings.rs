@@ -0,0 +1,97 @@
+use std::{sync::Arc, time::Duration};
+
+use serde_json::json;
+
+use super::test_state;
+use crate::settings::Settings;
+
+#[tokio::test]
+async fn changed_settings_are_applied_and_stored() {
+    let state = test_state().await;
+
+    let settings = state
+        .update_settings(json!({
+            "throttle": { "messages_per_sec_overall": 5 },
+            "intervals": { "sync_admins": 20 },
+            "admin_ids": [42],
+        }))
+        .await
+        .unwrap();
+    assert_eq!(settings.intervals.sync_admins, 20);
+    assert_eq!(state.settings().admin_ids, vec![42]);
+    assert_eq!(settings.throttle.messages_per_sec_overall, 5);
+
+    // another instance starts out with the configured settings
+    let configured = Settings::from_config(&state.config);
+    state.settings.send_replace(Arc::new(configured.clone()));
+    state.load_settings().await.unwrap();
+    assert_eq!(*state.settings(), *settings);
+
+    // null goes back to the configured value, the rest stays
+    let settings = state
+        .update_settings(json!({ "admin_ids": null }))
+        .await
+        .unwrap();
+    assert_eq!(settings.admin_ids, configured.admin_ids);
+    assert_eq!(settings.intervals.sync_admins, 20);
+}
+
+#[tokio::test]
+async fn concurrent_changes_are_all_kept() {
+    let state = test_state().await;
+
+    let (first, second) = tokio::join!(
+        state.update_settings(json!({ "intervals": { "sync_admins": 20 } })),
+        state.update_settings(json!({ "admin_ids": [42] })),
+    );
+    first.unwrap();
+    second.unwrap();
+
+    state.load_settings().await.unwrap();
+    assert_eq!(state.settings().intervals.sync_admins, 20);
+    assert_eq!(state.settings().admin_ids, vec![42]);
+}
+
+#[tokio::test]
+async fn unknown_or_invalid_settings_are_refused() {
+    let state = test_state().await;
+
+    let err = state
+        .update_settings(json!({ "intervals": { "sync_admin": 20 } }))
+        .await
+        .unwrap_err();
+    assert_eq!(err.to_string(), "unknown setting intervals.sync_admin");
+    let err = state
+        .update_settings(json!({ "intervals": { "self_destruct": 0 } }))
+        .await
+        .unwrap_err();
+    assert_eq!(err.to_string(), "intervals must be positive");
+
+    assert_eq!(*state.settings(), Settings::from_config(&state.config));
+    assert!(state
+        .storage
+        .get_settings_overrides(&state.bot_id)
+        .await
+        .unwrap()
+        .is_none());
+}
+
+#[tokio::test]
+async fn loops_wake_up_for_new_intervals() {
+    let state = test_state().await;
+
+    let sleeping = tokio::spawn({
+        let state = state.clone();
+        async move { state.sleep_interval(Duration::from_secs(3600)).await }
+    });
+    tokio::task::yield_now().await;
+    state
+        .update_settings(json!({ "intervals": { "chat_metadata": 60 } }))
+        .await
+        .unwrap();
+
+    tokio::time::timeout(Duration::from_secs(1), sleeping)
+        .await
+        .expect("the sleep ends on the change")
+        .unwrap();
+}
@@ -0,0 +1,85 @@
+use std::collections::HashMap;
+
+use serde_json::Value;
+
+use super::{
+    add_chat, mock_telegram::MockTelegram, queue::queue, state_with_config, webhook::receiver,
+};
+use crate::{
+    sink::{off_telegram_chats, SinkConfig},
+    topic::ChatTarget,
+    webhook::{sign, SIGNATURE_HEADER},
+};
+
+#[test]
+fn chats_leave_telegram_only_when_all_their_groups_do() {
+    let groups = HashMap::from([
+        ("ops".to_string(), vec![1, 2]),
+        ("news".to_string(), vec![2, 3]),
+        ("plain".to_string(), vec![4]),
+    ]);
+    let slack = SinkConfig::Slack {
+        webhook_url: "https://hooks.slack.com/services/x".to_string(),
+    };
+    let sinks = HashMap::from([
+        ("ops".to_string(), vec![slack.clone()]),
+        ("news".to_string(), vec![SinkConfig::Telegram, slack]),
+    ]);
+
+    let off = off_telegram_chats(&sinks, &groups);
+    assert_eq!(off.into_iter().collect::<Vec<_>>(), vec![1]);
+}
+
+#[tokio::test]
+async fn broadcasts_fan_out_to_the_sinks_of_their_groups_once() {
+    let (mock, url) = MockTelegram::start();
+    let (received, hook) = receiver();
+    let state = state_with_config(|config| {
+        config.telegram_api_url = Some(url);
+        config.chat_groups = HashMap::from([("ops".to_string(), vec![1])]);
+        config.chat_group_sinks = HashMap::from([(
+            "ops".to_string(),
+            vec![SinkConfig::Webhook {
+                url: hook,
+                secret: Some("secret".to_string()),
+            }],
+        )]);
+    })
+    .await;
+    add_chat(&state, 1).await;
+    add_chat(&state, 2).await;
+
+    let id = queue(
+        &state,
+        Some(vec![ChatTarget::Chat(1), ChatTarget::Chat(2)]),
+        false,
+    )
+    .await;
+    let message = state.get_queued_message(id).await.unwrap().unwrap();
+    state.broadcast(&message).await.unwrap();
+
+    let chats: Vec<Value> = mock
+        .calls("sendMessage")
+        .into_iter()
+        .map(|call| call["chat_id"].clone())
+        .collect();
+    assert_eq!(chats, vec![Value::from(2)]);
+
+    {
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 1);
+        let (headers, body) = &received[0];
+        assert_eq!(
+            headers[SIGNATURE_HEADER].to_str().unwrap(),
+            sign("secret", body)
+        );
+        let body: Value = serde_json::from_slice(body).unwrap();
+        assert_eq!(body["queue_id"], id);
+        assert_eq!(body["chat_group"], "ops");
+        assert_eq!(body["text"], "hello");
+    }
+
+    // running the broadcast again doesn't post twice
+    state.broadcast(&message).await.unwrap();
+    assert_eq!(received.lock().unwrap().len(), 1);
+}
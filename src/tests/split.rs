@@ -0,0 +1,101 @@
+use super::{add_chat, mock_telegram::MockTelegram, state_with_telegram};
+use crate::{
+    broadcast::MessageOptions,
+    format::MessageFormat,
+    split::{split_message, split_message_within, telegram_len},
+    state::QueueMode,
+    topic::ChatTarget,
+};
+
+#[test]
+fn length_is_counted_like_telegram_does() {
+    assert_eq!(telegram_len("hello"), 5);
+    assert_eq!(telegram_len("привет"), 6);
+    // outside the basic plane, so two UTF-16 code units
+    assert_eq!(telegram_len("👍"), 2);
+    assert_eq!(
+        split_message_within(&"👍".repeat(3), MessageFormat::Plain, 4),
+        vec!["👍👍", "👍"]
+    );
+}
+
+#[test]
+fn short_texts_are_kept_whole() {
+    let text = "*short*";
+    assert_eq!(split_message(text, MessageFormat::MarkdownV2), vec![text]);
+}
+
+#[test]
+fn texts_are_split_at_paragraphs_before_lines_and_spaces() {
+    let text = "first paragraph\n\nsecond line\nthird words here";
+    assert_eq!(
+        split_message_within(text, MessageFormat::Plain, 30),
+        vec!["first paragraph", "second line\nthird words here"]
+    );
+    assert_eq!(
+        split_message_within("one two three", MessageFormat::Plain, 8),
+        vec!["one two", "three"]
+    );
+}
+
+#[test]
+fn entities_cut_in_two_are_closed_and_reopened() {
+    let text = "*bold words _and italic_ text*";
+    let parts = split_message_within(text, MessageFormat::MarkdownV2, 20);
+    assert_eq!(parts, vec!["*bold words _and_*", "*_italic_ text*"]);
+    for part in &parts {
+        assert!(MessageFormat::MarkdownV2.validate(part).is_ok(), "{part}");
+    }
+
+    let code = "```rust\nlet a = 1;\nlet b = 2;\n```";
+    assert_eq!(
+        split_message_within(code, MessageFormat::MarkdownV2, 24),
+        vec!["```rust\nlet a = 1;```", "```rust\nlet b = 2;\n```"]
+    );
+
+    // links and escapes are never cut
+    let link = "see [the docs](https://example.com/docs) \\.";
+    assert_eq!(
+        split_message_within(link, MessageFormat::MarkdownV2, 38),
+        vec!["see", "[the docs](https://example.com/docs)", "\\."]
+    );
+
+    let html = "<b>bold <i>words</i> here</b> &amp; more";
+    let parts = split_message_within(html, MessageFormat::Html, 25);
+    assert_eq!(
+        parts,
+        vec!["<b>bold <i>words</i></b>", "<b>here</b> &amp; more"]
+    );
+}
+
+#[tokio::test]
+async fn long_texts_are_sent_in_order_after_the_images() {
+    let (mock, url) = MockTelegram::start();
+    let state = state_with_telegram(&url).await;
+    add_chat(&state, 1).await;
+
+    let text = format!("{}\n\n{}", "a".repeat(4000), "b".repeat(4000));
+    let id = state
+        .queue_message_with_images(
+            Some(vec![ChatTarget::Chat(1)]),
+            text,
+            Vec::new(),
+            chrono::Utc::now().to_rfc3339(),
+            MessageOptions {
+                parse_mode: MessageFormat::Plain,
+                ..Default::default()
+            },
+            QueueMode::Send,
+        )
+        .await
+        .unwrap();
+    let message = state.get_queued_message(id).await.unwrap().unwrap();
+    state.broadcast(&message).await.unwrap();
+
+    let texts: Vec<String> = mock
+        .calls("sendMessage")
+        .iter()
+        .map(|sent| sent["text"].as_str().unwrap().to_string())
+        .collect();
+    assert_eq!(texts, vec!["a".repeat(4000), "b".repeat(4000)]);
+}
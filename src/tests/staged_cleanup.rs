@@ -0,0 +1,54 @@
+use super::{add_chat, add_member, mock_telegram::MockTelegram, state_with_telegram};
+
+#[tokio::test]
+async fn staged_cleanups_kick_once_confirmed_and_can_be_undone() {
+    let (mock, url) = MockTelegram::start();
+    let state = state_with_telegram(&url).await;
+    add_chat(&state, 1).await;
+    for user_id in [10, 11, 12] {
+        add_member(&state, 1, user_id).await;
+    }
+
+    state.stage_all_members(1, "test").await.unwrap();
+    let restricted = mock.calls("restrictChatMember");
+    assert_eq!(restricted.len(), 3);
+    assert!(restricted[0]["permissions"]["can_send_messages"].is_null());
+    assert!(mock.calls("unbanChatMember").is_empty());
+    assert_eq!(state.get_all_members(1).await.unwrap().len(), 3);
+    let candidates = state.get_cleanup_candidates(1).await.unwrap();
+    assert_eq!(candidates.len(), 3);
+    assert_eq!(candidates[0].initiator, "test");
+
+    // a member that left since isn't kicked
+    state.remove_chat_member_by_id(1, 12).await.unwrap();
+    assert!(state.queue_cleanup(1).unwrap());
+    assert!(state.confirm_staged_cleanup(1, "test").await.unwrap());
+    assert_eq!(mock.calls("unbanChatMember").len(), 2);
+    assert!(state.get_all_members(1).await.unwrap().is_empty());
+    assert!(state.get_cleanup_candidates(1).await.unwrap().is_empty());
+    assert!(!state.confirm_staged_cleanup(1, "test").await.unwrap());
+
+    let result = state.unban_all(1).await.unwrap();
+    assert_eq!((result.unbanned, result.failed), (2, 0));
+    let unbans = mock.calls("unbanChatMember");
+    assert_eq!(unbans.len(), 4);
+    assert_eq!(unbans[3]["only_if_banned"], true);
+}
+
+#[tokio::test]
+async fn discarding_a_staged_cleanup_restores_the_members() {
+    let (mock, url) = MockTelegram::start();
+    let state = state_with_telegram(&url).await;
+    add_chat(&state, 1).await;
+    add_member(&state, 1, 10).await;
+
+    state.stage_all_members(1, "test").await.unwrap();
+    assert_eq!(state.discard_staged_cleanup(1).await.unwrap(), 1);
+
+    let restricted = mock.calls("restrictChatMember");
+    assert_eq!(restricted.len(), 2);
+    assert_eq!(restricted[1]["permissions"]["can_send_messages"], true);
+    assert!(state.get_cleanup_candidates(1).await.unwrap().is_empty());
+    assert_eq!(state.discard_staged_cleanup(1).await.unwrap(), 0);
+    assert_eq!(state.get_all_members(1).await.unwrap().len(), 1);
+}
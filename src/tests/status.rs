@@ -0,0 +1,283 @@
+use serde_json::json;
+
+use super::{add_chat, add_member, queue::queue, test_state};
+use crate::{
+    broadcast::ChatResult,
+    state::{ChatCleaningStatus, CleanupState},
+    status_v1::ChatStatusV1,
+    topic::ChatTarget,
+};
+
+fn status(state: &crate::state::AppState, chat_id: i64) -> ChatCleaningStatus {
+    state.chats_status.get(&chat_id).unwrap().clone()
+}
+
+#[tokio::test]
+async fn cleanup_is_queued_once() {
+    let state = test_state().await;
+    add_chat(&state, 1).await;
+
+    assert!(state.queue_cleanup(1).unwrap());
+    assert!(matches!(status(&state, 1), ChatCleaningStatus::Queued));
+    assert!(!state.queue_cleanup(1).unwrap());
+}
+
+#[tokio::test]
+async fn unknown_chats_cannot_be_queued() {
+    let state = test_state().await;
+
+    assert!(state.queue_cleanup(1).is_err());
+    assert!(state.set_chat_status(1, ChatCleaningStatus::Idle).is_err());
+}
+
+#[tokio::test]
+async fn unknown_chats_do_not_stop_the_others_from_being_queued() {
+    let state = test_state().await;
+    add_chat(&state, 1).await;
+    add_chat(&state, 2).await;
+    state.queue_cleanup(2).unwrap();
+
+    let result = state.queue_cleanups(vec![3, 1, 2]);
+
+    assert_eq!(result.queued, vec![1]);
+    assert_eq!(result.skipped, vec![2]);
+    assert_eq!(result.unknown, vec![3]);
+    assert!(matches!(status(&state, 1), ChatCleaningStatus::Queued));
+}
+
+#[tokio::test]
+async fn failed_and_cancelled_cleanups_can_be_queued_again() {
+    let state = test_state().await;
+    add_chat(&state, 1).await;
+
+    state
+        .set_chat_status(1, ChatCleaningStatus::Error("boom".into()))
+        .unwrap();
+    assert!(state.queue_cleanup(1).unwrap());
+
+    state
+        .set_chat_status(1, ChatCleaningStatus::Cancelled(3))
+        .unwrap();
+    assert!(state.queue_cleanup(1).unwrap());
+}
+
+#[tokio::test]
+async fn errors_are_recorded() {
+    let state = test_state().await;
+    add_chat(&state, 1).await;
+
+    state
+        .set_chat_status(1, ChatCleaningStatus::Error("boom".into()))
+        .unwrap();
+
+    let record = state.cleanup_records.get(&1).unwrap().clone();
+    assert_eq!(record.last_error.as_deref(), Some("boom"));
+    assert!(record.last_cleanup_at.is_none());
+}
+
+#[tokio::test]
+async fn only_running_cleanups_can_be_cancelled() {
+    let state = test_state().await;
+    add_chat(&state, 1).await;
+
+    assert!(!state.cancel_cleanup(1).unwrap());
+    assert!(!state.cancel_cleanup(2).unwrap());
+
+    state.queue_cleanup(1).unwrap();
+    assert!(state.cancel_cleanup(1).unwrap());
+    assert!(state.cancelled_cleanups.contains(&1));
+
+    // an error must not leave the cancel behind for the next run
+    state
+        .set_chat_status(1, ChatCleaningStatus::Error("boom".into()))
+        .unwrap();
+    assert!(!state.cancelled_cleanups.contains(&1));
+}
+
+#[tokio::test]
+async fn cleanup_cancelled_while_queued_never_starts() {
+    let state = test_state().await;
+    add_chat(&state, 1).await;
+    add_member(&state, 1, 10).await;
+
+    state.queue_cleanup(1).unwrap();
+    state.cancel_cleanup(1).unwrap();
+    state.delete_all_members(1, "test").await.unwrap();
+
+    assert!(matches!(
+        status(&state, 1),
+        ChatCleaningStatus::Cancelled(0)
+    ));
+    assert!(state.cancelled_cleanups.is_empty());
+    assert_eq!(state.get_all_members(1).await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn chat_status_reports_members() {
+    let state = test_state().await;
+    add_chat(&state, 1).await;
+    add_member(&state, 1, 10).await;
+    add_member(&state, 1, 11).await;
+
+    let chat_status =
+        serde_json::to_value(state.get_chat_status(1).await.unwrap().unwrap()).unwrap();
+    assert_eq!(chat_status["member_count"], 2);
+    assert_eq!(chat_status["status"], json!({ "state": "idle" }));
+    assert!(chat_status["cleanup_schedule"].is_null());
+
+    assert!(state.get_chat_status(2).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn chat_status_keeps_its_first_shape_outside_of_v2() {
+    let state = test_state().await;
+    add_chat(&state, 1).await;
+    state
+        .set_chat_status(1, ChatCleaningStatus::Error("boom".into()))
+        .unwrap();
+
+    let chat_status = state.get_chat_status(1).await.unwrap().unwrap();
+    let v2 = serde_json::to_value(&chat_status.status).unwrap();
+    assert_eq!(v2, json!({ "state": "error", "detail": "boom" }));
+    let v1 = serde_json::to_value(ChatStatusV1::from(chat_status)).unwrap();
+    assert_eq!(v1["status"], json!({ "Error": "boom" }));
+    assert_eq!(v1["last_error"], "boom");
+
+    state
+        .set_chat_status(1, ChatCleaningStatus::Cancelled(3))
+        .unwrap();
+    let chat_status = state.get_chat_status(1).await.unwrap().unwrap();
+    let v2 = serde_json::to_value(&chat_status.status).unwrap();
+    assert_eq!(v2, json!({ "state": "cancelled", "detail": 3 }));
+}
+
+#[tokio::test]
+async fn deleted_chats_lose_their_status() {
+    let state = test_state().await;
+    add_chat(&state, 1).await;
+
+    state.delete_chat(1).await.unwrap();
+
+    assert!(state.chats_status.get(&1).is_none());
+    assert!(state.get_chats().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn chats_are_muted_and_deleted_in_bulk() {
+    let state = test_state().await;
+    for chat_id in [1, 2, 3] {
+        add_chat(&state, chat_id).await;
+    }
+    let errors = |results: Vec<ChatResult>| {
+        results
+            .into_iter()
+            .map(|result| (result.chat_id, result.error))
+            .collect::<Vec<_>>()
+    };
+
+    let muted = state.set_chats_muted(&[2, 4, 3], true).await.unwrap();
+    assert_eq!(
+        errors(muted),
+        vec![
+            (2, None),
+            (4, Some("chat not found".to_string())),
+            (3, None)
+        ]
+    );
+    let mut muted = state.get_muted_chats().await.unwrap();
+    muted.sort();
+    assert_eq!(muted, vec![2, 3]);
+
+    let deleted = state.delete_chats(&[1, 2, 5]).await.unwrap();
+    assert_eq!(
+        errors(deleted),
+        vec![
+            (1, None),
+            (2, None),
+            (5, Some("chat not found".to_string()))
+        ]
+    );
+    assert!(state.chats_status.get(&1).is_none());
+    let chats: Vec<i64> = state
+        .get_chats()
+        .await
+        .unwrap()
+        .iter()
+        .map(|chat| chat.id)
+        .collect();
+    assert_eq!(chats, vec![3]);
+}
+
+#[tokio::test]
+async fn rate_limit_overrides_are_stored_per_chat() {
+    let state = test_state().await;
+    add_chat(&state, 1).await;
+    add_chat(&state, 2).await;
+
+    assert!(state.set_chat_rate_limit(1, Some(6)).await.unwrap());
+    assert!(!state.set_chat_rate_limit(3, Some(6)).await.unwrap());
+
+    let overrides = |chats: Vec<crate::state::Chat>| {
+        let mut overrides: Vec<(i64, Option<i32>)> = chats
+            .into_iter()
+            .map(|chat| (chat.id, chat.rate_limit_override))
+            .collect();
+        overrides.sort();
+        overrides
+    };
+    assert_eq!(
+        overrides(state.get_chats().await.unwrap()),
+        vec![(1, Some(6)), (2, None)]
+    );
+
+    state.set_chat_rate_limit(1, None).await.unwrap();
+    assert_eq!(
+        overrides(state.get_chats().await.unwrap()),
+        vec![(1, None), (2, None)]
+    );
+}
+
+#[tokio::test]
+async fn status_overview_joins_chats_with_their_cleanup_state() {
+    let state = test_state().await;
+    for chat_id in [1, 2, 3] {
+        add_chat(&state, chat_id).await;
+    }
+    add_member(&state, 1, 10).await;
+    add_member(&state, 1, 11).await;
+    state.queue_cleanup(2).unwrap();
+    state
+        .set_chat_status(3, ChatCleaningStatus::Error("boom".into()))
+        .unwrap();
+    queue(&state, Some(vec![ChatTarget::Chat(1)]), false).await;
+    queue(&state, None, false).await;
+
+    let overview = state.get_status_overview(None).await.unwrap();
+    let summary: Vec<_> = overview
+        .iter()
+        .map(|chat| {
+            (
+                chat.chat_id,
+                chat.status,
+                chat.member_count,
+                chat.queued_broadcasts,
+            )
+        })
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            (1, CleanupState::Idle, 2, 2),
+            (2, CleanupState::Queued, 0, 1),
+            (3, CleanupState::Error, 0, 1),
+        ]
+    );
+    assert_eq!(overview[2].error.as_deref(), Some("boom"));
+
+    let queued = state
+        .get_status_overview(Some(CleanupState::Queued))
+        .await
+        .unwrap();
+    assert_eq!(queued.len(), 1);
+    assert_eq!(queued[0].chat_id, 2);
+}
@@ -0,0 +1,145 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+
+use super::{add_chat, test_state};
+use crate::{
+    state::{AppState, ChatCleaningStatus, CleanupProgress, CleanupState},
+    status_store::{CleanupElsewhere, SharedStatus, StatusStore},
+};
+
+type Rows = Arc<Mutex<HashMap<i64, (&'static str, Option<ChatCleaningStatus>, DateTime<Utc>)>>>;
+
+/// What the postgres store does, in memory, for instances that are states
+/// of the same process.
+struct SharedMemory {
+    instance: &'static str,
+    rows: Rows,
+}
+
+#[async_trait]
+impl StatusStore for SharedMemory {
+    async fn load(&self, _bot_id: &str) -> anyhow::Result<Vec<SharedStatus>> {
+        Ok(self
+            .rows
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, (instance, _, _))| *instance != self.instance)
+            .map(|(chat_id, (_, status, updated_at))| SharedStatus {
+                chat_id: *chat_id,
+                status: status.clone(),
+                updated_at: *updated_at,
+            })
+            .collect())
+    }
+
+    fn save(&self, _bot_id: &str, chat_id: i64, status: Option<&ChatCleaningStatus>) {
+        self.rows
+            .lock()
+            .unwrap()
+            .insert(chat_id, (self.instance, status.cloned(), Utc::now()));
+    }
+
+    async fn renew(&self, _bot_id: &str, expired: DateTime<Utc>) -> anyhow::Result<()> {
+        let mut rows = self.rows.lock().unwrap();
+        for (instance, status, updated_at) in rows.values_mut() {
+            if *instance == self.instance && status.as_ref().is_some_and(|s| s.is_running()) {
+                *updated_at = Utc::now();
+            }
+        }
+        rows.retain(|_, (_, status, updated_at)| status.is_some() || *updated_at >= expired);
+
+        Ok(())
+    }
+}
+
+async fn instance(name: &'static str, rows: &Rows) -> AppState {
+    let mut state = test_state().await;
+    state.status_store = Arc::new(SharedMemory {
+        instance: name,
+        rows: rows.clone(),
+    });
+    add_chat(&state, 1).await;
+    state
+}
+
+fn status(state: &AppState, chat_id: i64) -> CleanupState {
+    state.chats_status.get(&chat_id).unwrap().state()
+}
+
+#[tokio::test]
+async fn instances_take_over_the_statuses_of_the_others() {
+    let rows = Rows::default();
+    let a = instance("a", &rows).await;
+    let b = instance("b", &rows).await;
+
+    assert!(a.queue_cleanup(1).unwrap());
+    b.sync_statuses().await.unwrap();
+    assert_eq!(status(&b, 1), CleanupState::Queued);
+    // the cleanup queued on the other instance isn't started again
+    assert!(!b.queue_cleanup(1).unwrap());
+
+    a.set_chat_status(1, ChatCleaningStatus::Error("boom".into()))
+        .unwrap();
+    b.sync_statuses().await.unwrap();
+    assert_eq!(status(&b, 1), CleanupState::Error);
+
+    // a status set since isn't replaced by the same write again
+    assert!(b.queue_cleanup(1).unwrap());
+    b.sync_statuses().await.unwrap();
+    assert_eq!(status(&b, 1), CleanupState::Queued);
+    a.sync_statuses().await.unwrap();
+    assert_eq!(status(&a, 1), CleanupState::Queued);
+
+    // only the instance running the cleanup can stop it
+    let err = a.cancel_cleanup(1).unwrap_err();
+    assert_eq!(err.downcast_ref::<CleanupElsewhere>().unwrap().0, 1);
+
+    // the other instances drop a chat dropped on one
+    a.delete_chat(1).await.unwrap();
+    assert!(rows.lock().unwrap()[&1].1.is_none());
+    b.sync_statuses().await.unwrap();
+    assert!(!b.chats_status.contains_key(&1));
+}
+
+#[tokio::test]
+async fn statuses_of_stopped_instances_expire() {
+    let rows = Rows::default();
+    let a = instance("a", &rows).await;
+    add_chat(&a, 2).await;
+    a.storage.start_cleanup_run(1, false, "ops").await.unwrap();
+
+    // written by an instance that stopped long ago, maybe this one before a
+    // restart
+    let long_ago = Utc::now() - Duration::hours(1);
+    let running = ChatCleaningStatus::InProgress(CleanupProgress::new(10));
+    rows.lock()
+        .unwrap()
+        .insert(1, ("gone", Some(running.clone()), long_ago));
+    rows.lock()
+        .unwrap()
+        .insert(2, ("gone", Some(ChatCleaningStatus::Queued), long_ago));
+
+    a.sync_statuses().await.unwrap();
+    assert_eq!(status(&a, 1), CleanupState::Resumable);
+    assert_eq!(status(&a, 2), CleanupState::Error);
+    // taken over, the next sync leaves them alone
+    assert_eq!(rows.lock().unwrap()[&1].0, "a");
+    assert!(a.queue_cleanup(1).unwrap());
+    assert!(a.queue_cleanup(2).unwrap());
+
+    // running statuses of live instances are renewed and kept
+    let b = instance("b", &rows).await;
+    add_chat(&b, 2).await;
+    rows.lock()
+        .unwrap()
+        .insert(2, ("a", Some(ChatCleaningStatus::Queued), long_ago));
+    a.sync_statuses().await.unwrap();
+    b.sync_statuses().await.unwrap();
+    assert_eq!(status(&b, 2), CleanupState::Queued);
+}
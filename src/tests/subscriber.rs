@@ -0,0 +1,93 @@
+use chrono::Utc;
+use serde_json::{json, Value};
+use teloxide::types::CallbackQuery;
+
+use super::{add_chat, e2e::user, mock_telegram::MockTelegram, state_with_config};
+use crate::{
+    bot::handle_callback_query,
+    broadcast::MessageOptions,
+    state::{AppState, QueueMode},
+    subscriber::{Audience, UNSUBSCRIBE_CALLBACK},
+    topic::{ChatTarget, Targets},
+};
+
+async fn setup() -> (MockTelegram, AppState) {
+    let (mock, url) = MockTelegram::start();
+    let state = state_with_config(|config| {
+        config.telegram_api_url = Some(url);
+        config.unsubscribe_button = Some("Stop receiving these".to_string());
+    })
+    .await;
+    (mock, state)
+}
+
+async fn queue(state: &AppState, targets: impl Into<Targets>, audience: Audience) {
+    state
+        .queue_message_with_images(
+            targets,
+            "hello".to_string(),
+            Vec::new(),
+            Utc::now().to_rfc3339(),
+            MessageOptions {
+                audience,
+                ..Default::default()
+            },
+            QueueMode::Send,
+        )
+        .await
+        .unwrap();
+}
+
+fn button_press(user_id: i64, message: &Value) -> CallbackQuery {
+    serde_json::from_value(json!({
+        "id": "1",
+        "from": user(user_id),
+        "chat_instance": "1",
+        "data": UNSUBSCRIBE_CALLBACK,
+        "message": {
+            "message_id": message["message_id"],
+            "date": 0,
+            "chat": { "id": user_id, "type": "private", "first_name": "user" },
+            "text": message["text"],
+        },
+    }))
+    .unwrap()
+}
+
+#[tokio::test]
+async fn the_button_under_subscriber_broadcasts_unsubscribes() {
+    let (mock, state) = setup().await;
+    let subscriber = serde_json::from_value(user(10)).unwrap();
+    state.subscribe(&subscriber).await.unwrap();
+    add_chat(&state, -100).await;
+
+    queue(&state, None, Audience::Subscribers).await;
+    queue(&state, Some(vec![ChatTarget::Chat(-100)]), Audience::Chats).await;
+    state.message_queue_loop().await.unwrap();
+    let sent = mock.calls("sendMessage");
+    assert_eq!(sent.len(), 2);
+    let sent_to = |chat_id: i64| sent.iter().find(|call| call["chat_id"] == chat_id).unwrap();
+    let button = &sent_to(10)["reply_markup"]["inline_keyboard"][0][0];
+    assert_eq!(button["text"], "Stop receiving these");
+    assert_eq!(button["callback_data"], UNSUBSCRIBE_CALLBACK);
+    // chats get no button
+    assert!(sent_to(-100)["reply_markup"].is_null());
+
+    let message = json!({ "message_id": 1, "text": "hello" });
+    handle_callback_query(button_press(10, &message), state.bot.clone(), state.clone())
+        .await
+        .unwrap();
+    assert!(state.get_subscribers().await.unwrap().is_empty());
+    let edits = mock.calls("editMessageText");
+    assert_eq!(edits.len(), 1);
+    assert_eq!(
+        edits[0]["text"],
+        "hello\n\nYou are unsubscribed, send /start to subscribe again."
+    );
+    assert!(edits[0]["reply_markup"].is_null());
+    let answers = mock.calls("answerCallbackQuery");
+    assert_eq!(
+        answers[0]["text"],
+        "You are unsubscribed, send /start to subscribe again."
+    );
+}
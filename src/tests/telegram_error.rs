@@ -0,0 +1,86 @@
+use std::time::Duration;
+
+use teloxide::{ApiError, RequestError};
+
+use crate::telegram_error::TelegramErrorClass;
+
+fn unknown(description: &str) -> RequestError {
+    RequestError::Api(ApiError::Unknown(description.to_string()))
+}
+
+#[test]
+fn known_errors_are_classified_by_variant() {
+    let cases = [
+        (
+            RequestError::Api(ApiError::BotKickedFromSupergroup),
+            TelegramErrorClass::Kicked,
+        ),
+        (
+            RequestError::Api(ApiError::BotBlocked),
+            TelegramErrorClass::Kicked,
+        ),
+        (
+            RequestError::Api(ApiError::ChatNotFound),
+            TelegramErrorClass::NotFound,
+        ),
+        (
+            RequestError::Api(ApiError::UserNotFound),
+            TelegramErrorClass::UserGone,
+        ),
+        (
+            RequestError::Api(ApiError::NotEnoughRightsToRestrict),
+            TelegramErrorClass::Forbidden,
+        ),
+        (
+            RequestError::MigrateToChatId(-100123),
+            TelegramErrorClass::Migrated(-100123),
+        ),
+        (
+            RequestError::RetryAfter(Duration::from_secs(3)),
+            TelegramErrorClass::RateLimited(Duration::from_secs(3)),
+        ),
+        (
+            RequestError::Api(ApiError::MessageTextIsEmpty),
+            TelegramErrorClass::Other,
+        ),
+    ];
+
+    for (err, class) in cases {
+        assert_eq!(TelegramErrorClass::of(&err), class, "{err}");
+    }
+}
+
+#[test]
+fn unknown_forbidden_errors_are_classified_by_their_reason() {
+    assert_eq!(
+        TelegramErrorClass::of(&unknown("Forbidden: bot was kicked from the group chat")),
+        TelegramErrorClass::Kicked
+    );
+    assert_eq!(
+        TelegramErrorClass::of(&unknown(
+            "Forbidden: bot is not a member of the channel chat"
+        )),
+        TelegramErrorClass::Kicked
+    );
+    assert_eq!(
+        TelegramErrorClass::of(&unknown("Forbidden: not enough rights to send photos")),
+        TelegramErrorClass::Forbidden
+    );
+    assert_eq!(
+        TelegramErrorClass::of(&unknown("Bad Request: something new")),
+        TelegramErrorClass::Other
+    );
+}
+
+#[test]
+fn wrapped_errors_are_classified() {
+    let err = anyhow::Error::from(RequestError::Api(ApiError::UserDeactivated));
+    assert_eq!(
+        TelegramErrorClass::of_anyhow(&err),
+        TelegramErrorClass::UserGone
+    );
+    assert_eq!(
+        TelegramErrorClass::of_anyhow(&anyhow::anyhow!("not from telegram")),
+        TelegramErrorClass::Other
+    );
+}
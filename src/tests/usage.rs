@@ -0,0 +1,46 @@
+use chrono::{Duration, Utc};
+
+use super::{add_chat, test_state};
+use crate::{subscriber::Audience, topic::Targets, usage::Usage};
+
+#[tokio::test]
+async fn usage_is_summed_per_actor_and_day() {
+    let state = test_state().await;
+    add_chat(&state, 1).await;
+    add_chat(&state, 2).await;
+
+    // the whole audience counts as the chats it has now
+    let usage = state
+        .message_usage(&Targets::from(None), Audience::Chats)
+        .await;
+    assert_eq!((usage.messages, usage.target_chats), (1, 2));
+    state.record_usage("api-key:ops", usage).await;
+    state.record_usage("api-key:ops", Usage::cleanups(3)).await;
+    state.record_usage("api", Usage::cleanups(1)).await;
+
+    let today = Utc::now().date_naive();
+    let entries = state.get_usage(today, today).await.unwrap();
+    let totals: Vec<_> = entries
+        .iter()
+        .map(|entry| {
+            (
+                entry.actor.as_str(),
+                entry.day,
+                entry.messages,
+                entry.target_chats,
+                entry.cleanups,
+            )
+        })
+        .collect();
+    assert_eq!(
+        totals,
+        vec![("api", today, 0, 0, 1), ("api-key:ops", today, 1, 2, 3)]
+    );
+
+    let yesterday = today - Duration::days(1);
+    assert!(state
+        .get_usage(yesterday, yesterday)
+        .await
+        .unwrap()
+        .is_empty());
+}
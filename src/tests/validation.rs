@@ -0,0 +1,91 @@
+use image::{ImageOutputFormat, RgbImage};
+
+use super::png;
+use crate::{
+    attachment::Attachments,
+    format::MessageFormat,
+    media::prepare_image,
+    validation::{Validator, MAX_MESSAGE_LENGTH, MAX_MESSAGE_PARTS, MAX_PHOTO_DIMENSIONS},
+};
+
+fn fields(validator: Validator) -> Vec<String> {
+    validator
+        .into_errors()
+        .into_iter()
+        .map(|error| error.field)
+        .collect()
+}
+
+#[test]
+fn valid_messages_pass() {
+    let mut validator = Validator::default();
+    validator.message("hello", MessageFormat::Plain);
+    validator.datetime("2023-06-01T10:00:00Z");
+    validator.image_count(1, 10);
+    validator.image(0, &png(100, 50));
+
+    assert!(validator.finish().is_ok());
+}
+
+#[test]
+fn every_invalid_field_is_reported() {
+    let mut validator = Validator::default();
+    validator.message(
+        &"a".repeat(MAX_MESSAGE_PARTS * MAX_MESSAGE_LENGTH + 1),
+        MessageFormat::Plain,
+    );
+    validator.datetime("tomorrow");
+    validator.image_count(3, 2);
+    validator.image(0, b"not an image");
+    validator.image(1, &png(1, 40));
+    validator.image(2, &png(9_999, 2));
+
+    assert_eq!(
+        fields(validator),
+        [
+            "message",
+            "datetime",
+            "images",
+            "images[0]",
+            "images[1]",
+            "images[2]",
+        ]
+    );
+}
+
+#[test]
+fn text_may_be_left_out_when_there_is_something_else_to_send() {
+    let mut validator = Validator::default();
+    validator.content(" ", &Attachments::default(), 1, MessageFormat::Plain);
+    assert!(validator.finish().is_ok());
+
+    let mut validator = Validator::default();
+    validator.content("", &Attachments::default(), 0, MessageFormat::Plain);
+    let errors = validator.into_errors();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].field, "message");
+    assert_eq!(errors[0].message, "message is empty");
+}
+
+#[test]
+fn photos_within_the_limits_are_kept() {
+    let image = png(20, 10);
+    assert_eq!(prepare_image(image.clone()).unwrap(), image);
+}
+
+#[test]
+fn other_formats_and_oversized_images_become_jpegs() {
+    let mut bmp = std::io::Cursor::new(Vec::new());
+    RgbImage::new(20, 10)
+        .write_to(&mut bmp, ImageOutputFormat::Bmp)
+        .unwrap();
+    let converted = prepare_image(bmp.into_inner()).unwrap();
+    assert_eq!(
+        image::guess_format(&converted).unwrap(),
+        image::ImageFormat::Jpeg
+    );
+
+    let scaled = image::load_from_memory(&prepare_image(png(9_990, 20)).unwrap()).unwrap();
+    assert!(scaled.width() + scaled.height() <= MAX_PHOTO_DIMENSIONS);
+    assert!(scaled.width() > scaled.height() * 4);
+}
@@ -0,0 +1,88 @@
+use chrono::Utc;
+
+use super::{add_chat, mock_telegram::MockTelegram, state_with_telegram};
+use crate::{
+    broadcast::MessageOptions,
+    topic::ChatTarget,
+    variant::{pick_variant, validate_variants, MessageVariant},
+};
+
+fn variant(message: &str, weight: i32) -> MessageVariant {
+    MessageVariant {
+        message: message.to_string(),
+        images: Vec::new(),
+        weight,
+    }
+}
+
+#[test]
+fn chats_are_split_by_weight() {
+    let weights = [1, 3];
+    let picks: Vec<_> = (0..1000)
+        .map(|chat_id| pick_variant(7, chat_id, &weights).unwrap())
+        .collect();
+    let first = picks.iter().filter(|&&pick| pick == 0).count();
+    assert!(
+        (200..300).contains(&first),
+        "{first} chats got the first variant"
+    );
+
+    // the same chat always gets the same variant of a message
+    assert!((0..1000)
+        .all(|chat_id| pick_variant(7, chat_id, &weights) == Some(picks[chat_id as usize])));
+    assert_eq!(pick_variant(7, 1, &[]), None);
+
+    assert!(validate_variants(&[variant("a", 1), variant("b", 1)]).is_ok());
+    assert!(validate_variants(&[variant("a", 1)]).is_err());
+    assert!(validate_variants(&[variant("a", 1), variant("b", 0)]).is_err());
+}
+
+#[tokio::test]
+async fn every_chat_receives_its_variant() {
+    let (mock, url) = MockTelegram::start();
+    let state = state_with_telegram(&url).await;
+    let chats: Vec<i64> = (1..=20).collect();
+    for &chat_id in &chats {
+        add_chat(&state, chat_id).await;
+    }
+    mock.fail_chat(3, "Forbidden: bot was kicked from the group chat");
+
+    let id = state
+        .queue_variants(
+            Some(chats.iter().copied().map(ChatTarget::Chat).collect()),
+            vec![variant("plain", 1), variant("*bold*", 1)],
+            Utc::now().to_rfc3339(),
+            MessageOptions::default(),
+        )
+        .await
+        .unwrap();
+    let message = state.get_queued_message(id).await.unwrap().unwrap();
+    assert_eq!(message.message, "plain");
+    state.broadcast(&message).await.unwrap();
+
+    let weights = [1, 1];
+    for sent in mock.calls("sendMessage") {
+        let chat_id = sent["chat_id"].as_i64().unwrap();
+        let expected = ["plain", "*bold*"][pick_variant(id, chat_id, &weights).unwrap()];
+        assert_eq!(sent["text"], expected);
+    }
+
+    let results = state.get_variant_results(id).await.unwrap().unwrap();
+    assert_eq!(results.len(), 2);
+    assert_eq!(results.iter().map(|result| result.chats).sum::<i64>(), 20);
+    assert!(results.iter().all(|result| result.chats > 0));
+    assert_eq!(results.iter().map(|result| result.sent).sum::<i64>(), 19);
+    let failed = results.iter().find(|result| result.failed == 1).unwrap();
+    assert_eq!(
+        failed.variant as usize,
+        pick_variant(id, 3, &weights).unwrap()
+    );
+
+    // a resent broadcast keeps its variants
+    let resent = state
+        .resend_broadcast(&message, None, Utc::now().to_rfc3339())
+        .await
+        .unwrap();
+    assert_eq!(state.get_message_variants(resent).await.unwrap().len(), 2);
+    assert!(state.get_variant_results(-1).await.unwrap().is_none());
+}
@@ -0,0 +1,93 @@
+use std::{
+    net::{SocketAddr, TcpListener},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use axum::{body::Bytes, http::HeaderMap, routing::post, Extension, Router};
+use serde_json::Value;
+
+use super::{add_chat, test_state};
+use crate::webhook::{sign, NewWebhook, WebhookEvent, SIGNATURE_HEADER};
+
+pub(super) type Received = Arc<Mutex<Vec<(HeaderMap, Bytes)>>>;
+
+/// Starts a receiver recording every callback and returns its url.
+pub(super) fn receiver() -> (Received, String) {
+    let received = Received::default();
+    let listener = TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], 0))).unwrap();
+    let url = format!("http://{}/hook", listener.local_addr().unwrap());
+
+    let app = Router::new()
+        .route(
+            "/hook",
+            post(
+                |Extension(received): Extension<Received>, headers: HeaderMap, body: Bytes| async move {
+                    received.lock().unwrap().push((headers, body));
+                },
+            ),
+        )
+        .layer(Extension(received.clone()));
+    let server = axum::Server::from_tcp(listener)
+        .unwrap()
+        .serve(app.into_make_service());
+    tokio::spawn(server);
+
+    (received, url)
+}
+
+#[tokio::test]
+async fn subscribed_events_are_posted_signed() {
+    let state = test_state().await;
+    let (received, url) = receiver();
+    let webhook = state
+        .add_webhook(&NewWebhook {
+            url,
+            events: vec![WebhookEvent::ChatRemoved],
+        })
+        .await
+        .unwrap();
+
+    add_chat(&state, 1).await;
+    state.delete_chat(1).await.unwrap();
+
+    for _ in 0..100 {
+        if !received.lock().unwrap().is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    let received = received.lock().unwrap();
+    assert_eq!(received.len(), 1);
+
+    let (headers, body) = &received[0];
+    assert_eq!(
+        headers[SIGNATURE_HEADER].to_str().unwrap(),
+        sign(&webhook.secret, body)
+    );
+    let body: Value = serde_json::from_slice(body).unwrap();
+    assert_eq!(body["event"], "chat_removed");
+    assert_eq!(body["data"]["chat_id"], 1);
+}
+
+#[tokio::test]
+async fn webhooks_are_listed_and_deleted() {
+    let state = test_state().await;
+    let events = vec![WebhookEvent::BroadcastFailed, WebhookEvent::BotKicked];
+    let webhook = state
+        .add_webhook(&NewWebhook {
+            url: "https://example.com/hook".to_string(),
+            events: events.clone(),
+        })
+        .await
+        .unwrap();
+
+    let webhooks = state.get_webhooks().await.unwrap();
+    assert_eq!(webhooks.len(), 1);
+    assert_eq!(webhooks[0].events, events);
+    assert_eq!(webhooks[0].secret, webhook.secret);
+
+    assert!(state.delete_webhook(webhook.id).await.unwrap());
+    assert!(!state.delete_webhook(webhook.id).await.unwrap());
+    assert!(state.get_webhooks().await.unwrap().is_empty());
+}
@@ -0,0 +1,140 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgConnection;
+use utoipa::ToSchema;
+
+use crate::state::AppState;
+
+/// A chat a message is queued for, optionally a topic of a forum
+/// supergroup. Accepted as a bare chat id or as `{chat_id, thread_id}`.
+#[derive(Clone, Copy, Serialize, Deserialize, ToSchema)]
+#[serde(untagged)]
+pub enum ChatTarget {
+    Chat(i64),
+    Thread { chat_id: i64, thread_id: i32 },
+}
+
+impl ChatTarget {
+    pub fn chat_id(self) -> i64 {
+        match self {
+            ChatTarget::Chat(chat_id) | ChatTarget::Thread { chat_id, .. } => chat_id,
+        }
+    }
+
+    pub fn thread_id(self) -> Option<i32> {
+        match self {
+            ChatTarget::Chat(_) => None,
+            ChatTarget::Thread { thread_id, .. } => Some(thread_id),
+        }
+    }
+}
+
+/// Who a queued message goes to.
+#[derive(Default)]
+pub struct Targets {
+    /// `None` is the whole audience, resolved when the message is sent.
+    pub chats: Option<Vec<ChatTarget>>,
+    /// Chats left out of the whole audience.
+    pub excluded: Vec<i64>,
+}
+
+impl From<Option<Vec<ChatTarget>>> for Targets {
+    fn from(chats: Option<Vec<ChatTarget>>) -> Self {
+        Self {
+            chats,
+            excluded: Vec::new(),
+        }
+    }
+}
+
+/// A topic the bot has seen in a forum supergroup. The name is only known
+/// once the topic was created or renamed while the bot was in the chat.
+#[derive(Serialize, ToSchema)]
+pub struct ChatTopic {
+    thread_id: i32,
+    name: Option<String>,
+    updated_at: DateTime<Utc>,
+}
+
+pub async fn insert_message_threads(
+    conn: &mut PgConnection,
+    message_id: i32,
+    targets: &[ChatTarget],
+) -> anyhow::Result<()> {
+    for target in targets {
+        let Some(thread_id) = target.thread_id() else {
+            continue;
+        };
+        sqlx::query!(
+            r#"
+INSERT INTO message_thread ( message_id, chat_id, thread_id )
+VALUES ( $1, $2, $3 )
+ON CONFLICT ( message_id, chat_id ) DO UPDATE
+SET thread_id = $3
+            "#,
+            message_id,
+            target.chat_id(),
+            thread_id
+        )
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    Ok(())
+}
+
+impl AppState {
+    /// Topics a queued message goes to, keyed by chat id. Chats without an
+    /// entry get the message in their general topic.
+    pub async fn get_message_threads(&self, message_id: i32) -> anyhow::Result<HashMap<i64, i32>> {
+        self.storage.get_message_threads(message_id).await
+    }
+
+    /// Remembers a topic, `name` of `None` keeps a name that is already known.
+    /// Topics are only kept with postgres.
+    pub async fn record_topic(
+        &self,
+        chat_id: i64,
+        thread_id: i32,
+        name: Option<&str>,
+    ) -> anyhow::Result<()> {
+        let Some(pool) = &self.pool else {
+            return Ok(());
+        };
+
+        sqlx::query!(
+            r#"
+INSERT INTO chat_topic ( chat_id, thread_id, name )
+VALUES ( $1, $2, $3 )
+ON CONFLICT ( chat_id, thread_id ) DO UPDATE
+SET name = coalesce($3, chat_topic.name), updated_at = now()
+WHERE $3 IS NOT NULL
+            "#,
+            chat_id,
+            thread_id,
+            name
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_topics(&self, chat_id: i64) -> anyhow::Result<Vec<ChatTopic>> {
+        let topics = sqlx::query_as!(
+            ChatTopic,
+            r#"
+SELECT thread_id, name, updated_at FROM chat_topic
+WHERE chat_id = $1
+ORDER BY thread_id
+            "#,
+            chat_id
+        )
+        .fetch_all(self.pg()?)
+        .await?;
+
+        Ok(topics)
+    }
+}
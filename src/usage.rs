@@ -0,0 +1,79 @@
+use chrono::{NaiveDate, Utc};
+use serde::Serialize;
+use tracing::error;
+use utoipa::ToSchema;
+
+use crate::{state::AppState, subscriber::Audience, topic::Targets};
+
+/// What one request added to the usage of its actor.
+#[derive(Debug, Default)]
+pub struct Usage {
+    pub messages: i64,
+    /// Chats the messages were queued for, the whole audience counted as the
+    /// chats it had when queued.
+    pub target_chats: i64,
+    /// Chats cleanups, purges and staged cleanups were started for.
+    pub cleanups: i64,
+}
+
+impl Usage {
+    pub fn cleanups(chats: usize) -> Self {
+        Self {
+            cleanups: chats as i64,
+            ..Default::default()
+        }
+    }
+}
+
+/// The usage of an actor on one day (UTC).
+#[derive(Debug, Serialize, ToSchema, sqlx::FromRow)]
+pub struct UsageEntry {
+    /// Named like in the audit log, `api-key:<name>` for API keys.
+    pub actor: String,
+    pub day: NaiveDate,
+    pub messages: i64,
+    pub target_chats: i64,
+    pub cleanups: i64,
+}
+
+impl AppState {
+    /// Adds to today's usage of the actor. Failing to record it doesn't fail
+    /// the request it was made for.
+    pub async fn record_usage(&self, actor: &str, usage: Usage) {
+        let day = Utc::now().date_naive();
+        if let Err(err) = self
+            .storage
+            .record_usage(&self.bot_id, actor, day, &usage)
+            .await
+        {
+            error!("failed to record the usage {usage:?} of {actor}: {err}");
+        }
+    }
+
+    /// Counts a queued message and the chats it goes to, look them up before
+    /// the targets are handed over for queueing.
+    pub async fn message_usage(&self, targets: &Targets, audience: Audience) -> Usage {
+        let target_chats = match self.get_recipients(targets, audience).await {
+            Ok(recipients) => recipients.chats.len() as i64,
+            Err(err) => {
+                error!("failed to count the recipients of a message: {err}");
+                0
+            }
+        };
+
+        Usage {
+            messages: 1,
+            target_chats,
+            ..Default::default()
+        }
+    }
+
+    /// Usage per actor and day between both days, inclusive.
+    pub async fn get_usage(
+        &self,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> anyhow::Result<Vec<UsageEntry>> {
+        self.storage.get_usage(&self.bot_id, from, to).await
+    }
+}
@@ -0,0 +1,173 @@
+use std::io::Cursor;
+
+use chrono::DateTime;
+use image::io::Reader;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::{
+    attachment::Attachments,
+    error::ApiError,
+    format::MessageFormat,
+    language::{validate_language, Translations},
+    split::split_message,
+};
+
+/// Telegram's limit for the text of a message, in UTF-16 code units.
+pub const MAX_MESSAGE_LENGTH: usize = 4096;
+/// Messages a long text may be split into.
+pub const MAX_MESSAGE_PARTS: usize = 10;
+/// Telegram's limits for photos.
+pub const MAX_PHOTO_BYTES: usize = 10 * 1024 * 1024;
+pub const MAX_PHOTO_DIMENSIONS: u32 = 10_000;
+pub const MAX_PHOTO_RATIO: u32 = 20;
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct FieldError {
+    /// The offending field, images are named by their position like
+    /// `images[2]`.
+    pub field: String,
+    pub message: String,
+}
+
+/// Collects every problem of a request instead of stopping at the first one.
+#[derive(Default)]
+pub struct Validator(Vec<FieldError>);
+
+impl Validator {
+    pub fn error(&mut self, field: impl Into<String>, message: impl Into<String>) {
+        self.0.push(FieldError {
+            field: field.into(),
+            message: message.into(),
+        });
+    }
+
+    pub fn message(&mut self, text: &str, parse_mode: MessageFormat) {
+        if text.trim().is_empty() {
+            self.error("message", "message is empty");
+        } else if let Err(err) = parse_mode.validate(text) {
+            self.error("message", err.to_string());
+        } else if split_message(text, parse_mode).len() > MAX_MESSAGE_PARTS {
+            self.error(
+                "message",
+                format!(
+                    "message doesn't fit into {MAX_MESSAGE_PARTS} messages of {MAX_MESSAGE_LENGTH} characters"
+                ),
+            );
+        }
+    }
+
+    /// Checks the text of a message, which may be left empty when the
+    /// message has images or attachments to send instead.
+    pub fn content(
+        &mut self,
+        text: &str,
+        attachments: &Attachments,
+        images: usize,
+        parse_mode: MessageFormat,
+    ) {
+        if !text.trim().is_empty() || (attachments.is_empty() && images == 0) {
+            self.message(text, parse_mode);
+        }
+    }
+
+    /// Checks the translated texts like the message itself, reported as
+    /// `translations.ru.message`.
+    pub fn translations(&mut self, translations: &Translations, parse_mode: MessageFormat) {
+        for (language, text) in translations {
+            let field = format!("translations.{language}");
+            if let Err(err) = validate_language(language) {
+                self.error(field.as_str(), err.to_string());
+            }
+            let mut checks = Validator::default();
+            checks.message(text, parse_mode);
+            self.nested(&field, checks);
+        }
+    }
+
+    pub fn attachments(&mut self, attachments: &Attachments) {
+        if attachments
+            .sticker
+            .as_ref()
+            .is_some_and(|sticker| sticker.trim().is_empty())
+        {
+            self.error("sticker", "sticker file_id is empty");
+        }
+        let Some(location) = &attachments.location else {
+            return;
+        };
+        if !(-90.0..=90.0).contains(&location.latitude) {
+            self.error("location.latitude", "latitude is not within -90 and 90");
+        }
+        if !(-180.0..=180.0).contains(&location.longitude) {
+            self.error("location.longitude", "longitude is not within -180 and 180");
+        }
+        if location.title.is_some() != location.address.is_some() {
+            self.error(
+                "location",
+                "a venue needs both a title and an address, leave both out for a pin",
+            );
+        }
+    }
+
+    pub fn datetime(&mut self, datetime: &str) {
+        if let Err(err) = DateTime::parse_from_rfc3339(datetime) {
+            self.error("datetime", format!("not an RFC 3339 timestamp: {err}"));
+        }
+    }
+
+    pub fn image_count(&mut self, count: usize, max: usize) {
+        if count > max {
+            self.error("images", format!("at most {max} images can be sent"));
+        }
+    }
+
+    /// Checks that the image can be sent as a photo. Its format and size
+    /// are taken care of by [`prepare_image`](crate::media::prepare_image),
+    /// only the aspect ratio can't be fixed.
+    pub fn image(&mut self, index: usize, data: &[u8]) {
+        let field = format!("images[{index}]");
+        let reader = match Reader::new(Cursor::new(data)).with_guessed_format() {
+            Ok(reader) => reader,
+            Err(err) => return self.error(field, err.to_string()),
+        };
+        if reader.format().is_none() {
+            return self.error(field, "not an image");
+        }
+
+        match reader.into_dimensions() {
+            Ok((width, height))
+                if width.max(height) > width.min(height).saturating_mul(MAX_PHOTO_RATIO) =>
+            {
+                self.error(
+                    field,
+                    format!("aspect ratio is more than {MAX_PHOTO_RATIO} to 1"),
+                )
+            }
+            Ok(_) => {}
+            Err(err) => self.error(field, format!("unreadable image: {err}")),
+        }
+    }
+
+    /// Takes over the errors of a nested object, prefixing their fields with
+    /// its name like `variants[1].message`.
+    pub fn nested(&mut self, name: &str, nested: Validator) {
+        self.0.extend(nested.0.into_iter().map(|error| FieldError {
+            field: format!("{name}.{}", error.field),
+            ..error
+        }));
+    }
+
+    /// The problems found, for reporting them without refusing the request.
+    pub fn into_errors(self) -> Vec<FieldError> {
+        self.0
+    }
+
+    pub fn finish(self) -> Result<(), ApiError> {
+        if self.0.is_empty() {
+            return Ok(());
+        }
+
+        Err(ApiError::validation(self.0))
+    }
+}
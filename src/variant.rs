@@ -0,0 +1,167 @@
+use anyhow::bail;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+use utoipa::ToSchema;
+
+use crate::{
+    attachment::Attachments, broadcast::MessageOptions, language::Translations,
+    queue::parse_datetime, state::AppState, storage::NewQueuedMessage,
+    telemetry::current_request_id, topic::Targets,
+};
+
+pub const MAX_VARIANTS: usize = 10;
+const MAX_WEIGHT: i32 = 100;
+
+/// One of the versions of an A/B tested broadcast, every chat receives
+/// exactly one of them.
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct MessageVariant {
+    pub message: String,
+    /// Base64 encoded images, `data:` URLs or https URLs, like the images of
+    /// `sendMessage`.
+    #[serde(default)]
+    pub images: Vec<String>,
+    /// Share of the chats relative to the other variants, two variants with
+    /// weights 1 and 3 split the chats 25/75.
+    #[serde(default = "default_weight")]
+    pub weight: i32,
+}
+
+fn default_weight() -> i32 {
+    1
+}
+
+/// How a variant of a broadcast did so far.
+#[derive(Serialize, ToSchema, sqlx::FromRow)]
+pub struct VariantResult {
+    pub variant: i32,
+    pub message: String,
+    pub weight: i32,
+    /// Chats the variant was assigned to.
+    pub chats: i64,
+    pub sent: i64,
+    pub failed: i64,
+}
+
+/// Checks the number of variants and their split, their texts and images
+/// are checked like those of any message.
+pub fn validate_variants(variants: &[MessageVariant]) -> anyhow::Result<()> {
+    if !(2..=MAX_VARIANTS).contains(&variants.len()) {
+        bail!("a broadcast must have 2-{MAX_VARIANTS} variants");
+    }
+    if variants
+        .iter()
+        .any(|variant| !(1..=MAX_WEIGHT).contains(&variant.weight))
+    {
+        bail!("variant weights must be between 1 and {MAX_WEIGHT}");
+    }
+
+    Ok(())
+}
+
+/// The variant a chat receives, picked by weight from a hash of the message
+/// and the chat so it stays the same when a broadcast is resumed.
+pub fn pick_variant(message_id: i32, chat_id: i64, weights: &[i32]) -> Option<usize> {
+    let total: u64 = weights.iter().map(|&weight| weight.max(0) as u64).sum();
+    if total == 0 {
+        return None;
+    }
+
+    let mut point = mix(((message_id as u64) << 32) ^ chat_id as u64) % total;
+    weights.iter().position(|&weight| {
+        let weight = weight.max(0) as u64;
+        if point < weight {
+            return true;
+        }
+        point -= weight;
+        false
+    })
+}
+
+/// The splitmix64 finalizer, spreads neighbouring chat ids evenly.
+fn mix(mut value: u64) -> u64 {
+    value = (value ^ (value >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    value = (value ^ (value >> 27)).wrapping_mul(0x94d049bb133111eb);
+    value ^ (value >> 31)
+}
+
+impl AppState {
+    /// Queues a broadcast whose chats are split between the variants. The
+    /// first variant's text stands for the message in the queue.
+    pub async fn queue_variants(
+        &self,
+        targets: impl Into<Targets>,
+        variants: Vec<MessageVariant>,
+        datetime: String,
+        options: MessageOptions,
+    ) -> anyhow::Result<i32> {
+        validate_variants(&variants)?;
+        info!(
+            "queueing {} variants of message: {} on datetime: {datetime}",
+            variants.len(),
+            variants[0].message
+        );
+
+        let targets = targets.into();
+        let mut variants = variants;
+        let mut media = Vec::new();
+        for variant in &mut variants {
+            let (images, variant_media) = self
+                .prepare_queued_images(std::mem::take(&mut variant.images))
+                .await?;
+            variant.images = images;
+            media.extend(variant_media);
+        }
+
+        self.storage
+            .queue_message(NewQueuedMessage {
+                bot_id: &self.bot_id,
+                targets: targets.chats.as_deref(),
+                excluded_chats: &targets.excluded,
+                message: &variants[0].message,
+                images: &[],
+                media: &media,
+                datetime: parse_datetime(&datetime)?,
+                options: &options,
+                awaiting_approval: false,
+                copy_from: None,
+                variants: &variants,
+                translations: &Translations::new(),
+                attachments: &Attachments::default(),
+                request_id: current_request_id().as_deref(),
+                duplicates_since: None,
+            })
+            .await
+    }
+
+    /// Empty for messages sent the same way to every chat.
+    pub async fn get_message_variants(
+        &self,
+        message_id: i32,
+    ) -> anyhow::Result<Vec<MessageVariant>> {
+        self.storage.get_message_variants(message_id).await
+    }
+
+    pub async fn assign_variant(
+        &self,
+        message_id: i32,
+        chat_id: i64,
+        variant: usize,
+    ) -> anyhow::Result<()> {
+        self.storage
+            .assign_variant(message_id, chat_id, variant as i32)
+            .await
+    }
+
+    /// `None` for unknown messages and those of other bots.
+    pub async fn get_variant_results(
+        &self,
+        message_id: i32,
+    ) -> anyhow::Result<Option<Vec<VariantResult>>> {
+        if self.get_queued_message(message_id).await?.is_none() {
+            return Ok(None);
+        }
+
+        Ok(Some(self.storage.get_variant_results(message_id).await?))
+    }
+}
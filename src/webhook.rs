@@ -0,0 +1,237 @@
+use std::{fmt, str::FromStr, time::Duration};
+
+use anyhow::anyhow;
+use axum::http::header::CONTENT_TYPE;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use tracing::{error, info, warn, Instrument, Span};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::state::{AppState, ChatCleaningStatus};
+
+/// Header carrying `sha256=` and the hex HMAC of the body, keyed with the
+/// secret of the webhook.
+pub const SIGNATURE_HEADER: &str = "x-webhook-signature";
+const EVENT_HEADER: &str = "x-webhook-event";
+
+const MAX_DELIVERY_ATTEMPTS: u32 = 5;
+// doubled after every failed attempt
+const FIRST_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEvent {
+    BroadcastCompleted,
+    /// The broadcast ran out of attempts and was set aside.
+    BroadcastFailed,
+    CleanupFinished,
+    ChatAdded,
+    ChatRemoved,
+    BotKicked,
+}
+
+impl fmt::Display for WebhookEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            WebhookEvent::BroadcastCompleted => "broadcast_completed",
+            WebhookEvent::BroadcastFailed => "broadcast_failed",
+            WebhookEvent::CleanupFinished => "cleanup_finished",
+            WebhookEvent::ChatAdded => "chat_added",
+            WebhookEvent::ChatRemoved => "chat_removed",
+            WebhookEvent::BotKicked => "bot_kicked",
+        })
+    }
+}
+
+impl FromStr for WebhookEvent {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "broadcast_completed" => Ok(WebhookEvent::BroadcastCompleted),
+            "broadcast_failed" => Ok(WebhookEvent::BroadcastFailed),
+            "cleanup_finished" => Ok(WebhookEvent::CleanupFinished),
+            "chat_added" => Ok(WebhookEvent::ChatAdded),
+            "chat_removed" => Ok(WebhookEvent::ChatRemoved),
+            "bot_kicked" => Ok(WebhookEvent::BotKicked),
+            other => Err(anyhow!("unknown webhook event: {other}")),
+        }
+    }
+}
+
+/// A URL events are posted to as JSON `{event, bot_id, created_at, data}`.
+#[derive(Clone, Serialize, ToSchema)]
+pub struct Webhook {
+    pub id: i32,
+    pub url: String,
+    /// Events the webhook receives, every event when empty.
+    pub events: Vec<WebhookEvent>,
+    #[serde(skip)]
+    pub secret: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Webhook {
+    fn receives(&self, event: WebhookEvent) -> bool {
+        self.events.is_empty() || self.events.contains(&event)
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct NewWebhook {
+    pub url: String,
+    #[serde(default)]
+    pub events: Vec<WebhookEvent>,
+}
+
+impl NewWebhook {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let url = reqwest::Url::parse(&self.url)?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(anyhow!("webhook URLs must use http or https"));
+        }
+
+        Ok(())
+    }
+}
+
+/// `sha256=` followed by the hex HMAC-SHA256 of the body.
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("any key length");
+    mac.update(body);
+    let hex: String = mac
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+
+    format!("sha256={hex}")
+}
+
+impl AppState {
+    /// Registers the webhook with a new random signing secret, which is only
+    /// ever returned here.
+    pub async fn add_webhook(&self, webhook: &NewWebhook) -> anyhow::Result<Webhook> {
+        info!("adding webhook {} for {:?}", webhook.url, webhook.events);
+        let secret = Uuid::new_v4().simple().to_string();
+
+        self.storage
+            .add_webhook(&self.bot_id, &webhook.url, &secret, &webhook.events)
+            .await
+    }
+
+    pub async fn get_webhooks(&self) -> anyhow::Result<Vec<Webhook>> {
+        self.storage.get_webhooks(&self.bot_id).await
+    }
+
+    /// Returns `false` when there is no such webhook.
+    pub async fn delete_webhook(&self, id: i32) -> anyhow::Result<bool> {
+        info!("deleting webhook {id}");
+
+        self.storage.delete_webhook(&self.bot_id, id).await
+    }
+
+    /// Posts the event to the webhooks receiving it in the background, so
+    /// slow or failing receivers never hold up the caller.
+    pub fn notify(&self, event: WebhookEvent, data: Value) {
+        let state = self.clone();
+        tokio::spawn(
+            async move {
+                if let Err(err) = state.deliver_event(event, data).await {
+                    error!("failed to deliver {event} webhooks: {err}");
+                }
+            }
+            .instrument(Span::current()),
+        );
+    }
+
+    /// Reports the counters of a cleanup that ran to the end, called while
+    /// the chat status still holds them.
+    pub fn notify_cleanup_finished(&self, chat_id: i64, reason: &str, initiator: &str) {
+        let progress = match self.chats_status.get(&chat_id).as_deref() {
+            Some(ChatCleaningStatus::InProgress(progress)) => Some(progress.clone()),
+            _ => None,
+        };
+
+        self.notify(
+            WebhookEvent::CleanupFinished,
+            json!({
+                "chat_id": chat_id,
+                "reason": reason,
+                "initiator": initiator,
+                "progress": progress,
+            }),
+        );
+    }
+
+    async fn deliver_event(&self, event: WebhookEvent, data: Value) -> anyhow::Result<()> {
+        let webhooks = self.get_webhooks().await?;
+        if !webhooks.iter().any(|webhook| webhook.receives(event)) {
+            return Ok(());
+        }
+
+        let body = serde_json::to_vec(&json!({
+            "event": event,
+            "bot_id": &*self.bot_id,
+            "created_at": Utc::now(),
+            "data": data,
+        }))?;
+        for webhook in webhooks {
+            if webhook.receives(event) {
+                let state = self.clone();
+                let body = body.clone();
+                tokio::spawn(
+                    async move { state.deliver(&webhook, event, body).await }
+                        .instrument(Span::current()),
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Retries with exponential backoff until the receiver answers with a
+    /// success status or the attempts run out.
+    async fn deliver(&self, webhook: &Webhook, event: WebhookEvent, body: Vec<u8>) {
+        if let Err(err) = self.check_outbound_url(&webhook.url) {
+            error!("not delivering {event} to webhook {}: {err}", webhook.id);
+            return;
+        }
+        let signature = sign(&webhook.secret, &body);
+        let mut delay = FIRST_RETRY_DELAY;
+
+        for attempt in 1..=MAX_DELIVERY_ATTEMPTS {
+            let result = self
+                .http
+                .post(&webhook.url)
+                .header(CONTENT_TYPE, "application/json")
+                .header(EVENT_HEADER, event.to_string())
+                .header(SIGNATURE_HEADER, &signature)
+                .body(body.clone())
+                .send()
+                .await
+                .and_then(|response| response.error_for_status());
+
+            match result {
+                Ok(_) => return,
+                Err(err) if attempt < MAX_DELIVERY_ATTEMPTS => {
+                    warn!(
+                        "webhook {} failed to receive {event}, retrying in {delay:?}: {err}",
+                        webhook.id
+                    );
+                    tokio::time::sleep(delay).await;
+                    delay *= 2;
+                }
+                Err(err) => error!(
+                    "giving up on delivering {event} to webhook {}: {err}",
+                    webhook.id
+                ),
+            }
+        }
+    }
+}
@@ -0,0 +1,147 @@
+use anyhow::bail;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use teloxide::{
+    requests::Requester,
+    types::{ChatId, MessageId, User},
+};
+use tracing::{error, info};
+use utoipa::ToSchema;
+
+use crate::{broadcast::Pacer, state::AppState, telegram_error::TelegramErrorClass};
+
+// bots can only delete messages for 48 hours after sending them
+const MAX_DELETE_AFTER_MINUTES: i32 = 48 * 60;
+const MAX_TEMPLATE_LENGTH: usize = 4096;
+/// Most greetings deleted in one round.
+const DELETION_BATCH_SIZE: i64 = 500;
+
+/// Greeting sent to members joining a chat. `{name}` and `{username}` in the
+/// template are replaced with the full name and the `@username` of the
+/// member, the latter falls back to the name.
+#[derive(Clone, Serialize, Deserialize, ToSchema, sqlx::FromRow)]
+pub struct Welcome {
+    pub template: String,
+    /// Deletes the greeting again after this many minutes.
+    #[serde(default)]
+    pub delete_after_minutes: Option<i32>,
+}
+
+/// Greeting sent with `delete_after_minutes`, stored until it is deleted.
+#[derive(sqlx::FromRow)]
+pub struct WelcomeDeletion {
+    pub chat_id: i64,
+    pub message_id: i32,
+}
+
+impl Welcome {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.template.trim().is_empty() {
+            bail!("welcome template is empty");
+        }
+        if self.template.chars().count() > MAX_TEMPLATE_LENGTH {
+            bail!("welcome template is longer than {MAX_TEMPLATE_LENGTH} characters");
+        }
+        if let Some(minutes) = self.delete_after_minutes {
+            if !(1..=MAX_DELETE_AFTER_MINUTES).contains(&minutes) {
+                bail!("delete_after_minutes must be between 1 and {MAX_DELETE_AFTER_MINUTES}");
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn render(&self, member: &User) -> String {
+        let name = member.full_name();
+        let username = match &member.username {
+            Some(username) => format!("@{username}"),
+            None => name.clone(),
+        };
+
+        self.template
+            .replace("{username}", &username)
+            .replace("{name}", &name)
+    }
+}
+
+impl AppState {
+    pub async fn get_welcome(&self, chat_id: i64) -> anyhow::Result<Option<Welcome>> {
+        self.storage.get_welcome(chat_id).await
+    }
+
+    pub async fn set_welcome(&self, chat_id: i64, welcome: &Welcome) -> anyhow::Result<()> {
+        welcome.validate()?;
+        info!("setting the welcome message of chat:{chat_id}");
+
+        self.storage.set_welcome(chat_id, welcome).await
+    }
+
+    /// Returns `false` when the chat had no welcome message.
+    pub async fn delete_welcome(&self, chat_id: i64) -> anyhow::Result<bool> {
+        info!("removing the welcome message of chat:{chat_id}");
+
+        self.storage.delete_welcome(chat_id).await
+    }
+
+    /// Greets every member that is not a bot with the welcome message of the
+    /// chat, if it has one.
+    pub async fn greet_members(&self, chat_id: i64, members: &[User]) -> anyhow::Result<()> {
+        let Some(welcome) = self.get_welcome(chat_id).await? else {
+            return Ok(());
+        };
+
+        for member in members.iter().filter(|member| !member.is_bot) {
+            let sent = self
+                .bot
+                .send_message(ChatId(chat_id), welcome.render(member))
+                .await?;
+            info!("greeted member:{} in chat:{chat_id}", member.id);
+
+            if let Some(minutes) = welcome.delete_after_minutes {
+                let deletion = WelcomeDeletion {
+                    chat_id,
+                    message_id: sent.id.0,
+                };
+                let delete_at = Utc::now() + Duration::minutes(minutes.into());
+                self.storage
+                    .add_welcome_deletion(&deletion, delete_at)
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+    /// Deletes the greetings whose `delete_after_minutes` ran out by `now`, returns
+    /// how many. Greetings telegram refuses to delete, like those removed by
+    /// an admin already, are given up on, rate limited ones are left for the
+    /// next round.
+    pub async fn delete_expired_greetings(&self, now: DateTime<Utc>) -> anyhow::Result<usize> {
+        let due = self
+            .storage
+            .get_due_welcome_deletions(&self.bot_id, now, DELETION_BATCH_SIZE)
+            .await?;
+
+        let pacer = Pacer::new(self.config.broadcast_rate);
+        let mut deleted = 0;
+        for deletion in due {
+            pacer.wait().await;
+            let chat_id = deletion.chat_id;
+            match self
+                .bot
+                .delete_message(ChatId(chat_id), MessageId(deletion.message_id))
+                .await
+            {
+                Ok(_) => deleted += 1,
+                Err(err) => {
+                    if let TelegramErrorClass::RateLimited(_) = TelegramErrorClass::of(&err) {
+                        break;
+                    }
+                    error!("giving up deleting the greeting in chat:{chat_id} {err}");
+                }
+            }
+            self.storage.remove_welcome_deletion(&deletion).await?;
+        }
+
+        Ok(deleted)
+    }
+}